@@ -1,10 +1,59 @@
+use arrow_array::{
+    Array, ArrayRef, Float32Array, RecordBatch, StructArray, UInt16Array, UInt32Array, UInt64Array,
+    UInt8Array,
+};
 use numpy::IntoPyArray;
 use pyo3::{
-    exceptions::PyValueError,
+    buffer::PyBuffer,
+    exceptions::{
+        PyImportError, PyIndexError, PyKeyboardInterrupt, PyRuntimeError, PyTypeError, PyValueError,
+    },
     prelude::*,
-    types::{PyString, PyTuple},
+    types::{
+        PyBytes, PyCFunction, PyCapsule, PyDict, PyFloat, PyInt, PyIterator, PyList, PyMapping,
+        PySet, PyString, PyTuple,
+    },
 };
-use symscan;
+use pyo3::{create_exception, IntoPyObjectExt};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use std::{slice, str};
+
+create_exception!(
+    symscan,
+    SymscanError,
+    PyValueError,
+    "Base class for errors raised by symscan. Subclasses ValueError."
+);
+create_exception!(
+    symscan,
+    NonAsciiInputError,
+    SymscanError,
+    "Raised when an input collection contains a non-ASCII string.\n\nAttributes\n----------\ninput_type : str\n    Either \"query\" or \"reference\".\nindex : int\n    The index of the first offending string.\nvalue : str\n    The offending string."
+);
+create_exception!(
+    symscan,
+    MaxDistanceError,
+    SymscanError,
+    "Raised when max_distance is too large, either in absolute terms or for the CachedRef being\nqueried.\n\nAttributes\n----------\ngot : int\n    The offending max_distance.\nlimit : int\n    The largest max_distance that is supported."
+);
+create_exception!(
+    symscan,
+    TooManyStringsError,
+    SymscanError,
+    "Raised when an input collection holds more strings than symscan supports.\n\nAttributes\n----------\ninput_type : str\n    Either \"query\" or \"reference\".\ngot : int\n    The number of strings in the offending collection.\nlimit : int\n    The maximum number of strings supported."
+);
+create_exception!(
+    symscan,
+    CacheFormatError,
+    SymscanError,
+    "Raised when loading a CachedRef from data that is malformed, or that was written by an\nincompatible version of symscan."
+);
 
 /// A class for memoizing the deletion variant calculations for a string collection.
 ///
@@ -23,27 +72,442 @@ use symscan;
 ///
 /// Parameters
 /// ----------
-/// reference : iterable of str
+/// reference : iterable of str or path-like
+///     Path-like references are read as in :py:func:`~symscan.get_neighbors_within`.
 /// max_distance : int, default=1
 ///     The maximum edit distance that this CachedRef instance will be able to support in future
 ///     queries.
-#[pyclass]
+/// n_threads : int, optional
+///     The number of threads to use when constructing the cache. See
+///     :py:func:`~symscan.get_neighbors_within`.
+/// metric : {"levenshtein", "hamming", "osa", "indel"} or tuple of int, default="levenshtein"
+///     The edit distance metric that the instance will be queried with. See
+///     :py:func:`~symscan.get_neighbors_within`. Queries use this metric by default, and passing
+///     a different one to a query raises a ValueError.
+///
+/// Attributes
+/// ----------
+/// max_distance : int
+///     The maximum edit distance that this CachedRef instance supports in queries.
+/// nbytes : int
+///     The approximate amount of memory (in bytes) held by the cache.
+/// metric : str or tuple of int
+///     The edit distance metric that this CachedRef instance is queried with.
+///
+/// Examples
+/// --------
+/// The reference strings held by the cache can be inspected with :py:func:`len` and indexing.
+///
+/// >>> import symscan
+/// >>> cached = symscan.CachedRef(["fooo", "barr", "bazz", "buzz"], max_distance=2)
+/// >>> len(cached)
+/// 4
+/// >>> cached[3]
+/// 'buzz'
+/// >>> cached[-1]
+/// 'buzz'
+/// >>> cached.max_distance
+/// 2
+///
+/// Notes
+/// -----
+/// CachedRef instances can be pickled, which makes it possible to construct a cache once and ship
+/// it to worker processes (e.g. via :py:mod:`multiprocessing` or joblib). The pickled data holds
+/// the memoized deletion variants, so unpickling does not recompute them. Pickles are tagged with
+/// a format version, and loading a pickle produced by an incompatible version of symscan raises a
+/// :py:class:`~symscan.CacheFormatError`.
+///
+/// >>> import pickle
+/// >>> import symscan
+/// >>> cached = symscan.CachedRef(["fooo", "barr", "bazz", "buzz"])
+/// >>> loaded = pickle.loads(pickle.dumps(cached))
+/// >>> (row, col, dists) = loaded.get_neighbors_across(["fizz", "fuzz", "buzz"])
+/// >>> row
+/// array([1, 2, 2], dtype=uint32)
+/// >>> col
+/// array([3, 2, 3], dtype=uint32)
+/// >>> dists
+/// array([1, 1, 0], dtype=uint8)
+#[pyclass(module = "symscan")]
 struct CachedRef {
     internal: symscan::CachedRef,
+    metric: Metric,
 }
 
 #[pymethods]
 impl CachedRef {
     #[new]
-    #[pyo3(signature = (reference, max_distance = 1))]
-    fn new(reference: &Bound<PyAny>, max_distance: u8) -> PyResult<Self> {
-        let ref_handles = get_pystring_handles(&reference)?;
-        let ref_views = get_str_refs(&ref_handles)?;
+    #[pyo3(signature = (
+        reference,
+        max_distance = 1,
+        n_threads = None,
+        metric = Metric::LEVENSHTEIN,
+    ))]
+    fn new(
+        reference: &Bound<PyAny>,
+        max_distance: u8,
+        n_threads: Option<i64>,
+        metric: Metric,
+    ) -> PyResult<Self> {
+        let (ref_strings, _) = get_input_strings(reference, Missing::Raise)?;
+        let ref_views = ref_strings.views(symscan::InputType::Reference)?;
+
+        let internal = run_interruptible(reference.py(), n_threads, metric, |options| {
+            symscan::CachedRef::new_with_options(&ref_views, max_distance, options)
+        })?;
+
+        Ok(CachedRef { internal, metric })
+    }
+
+    fn __getstate__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyTuple>> {
+        let mut state = Vec::new();
+        self.internal.write_to(&mut state).map_err(to_pyerr)?;
+        PyTuple::new(
+            py,
+            [
+                PyBytes::new(py, &state).into_any(),
+                self.metric.to_object(py)?,
+            ],
+        )
+    }
+
+    fn __setstate__(&mut self, state: &Bound<'_, PyAny>) -> PyResult<()> {
+        // Pickles written before the metric was recorded hold the serialized cache only.
+        let (data, metric) = match state.extract::<(Vec<u8>, Metric)>() {
+            Ok(state) => state,
+            Err(_) => (state.extract::<Vec<u8>>()?, Metric::LEVENSHTEIN),
+        };
+        self.internal = symscan::CachedRef::read_from(&data[..])
+            .map_err(|e| CacheFormatError::new_err(format!("failed to unpickle CachedRef: {e}")))?;
+        self.metric = metric;
+        Ok(())
+    }
+
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyTuple>> {
+        let py = slf.py();
+        let state = slf.borrow().__getstate__(py)?;
+        (slf.get_type(), (PyTuple::empty(py),), state).into_pyobject(py)
+    }
+
+    /// Append more strings to the cached reference.
+    ///
+    /// The new strings are assigned contiguous indices following those of the existing reference
+    /// strings, so ``len(cached)`` as of before the call gives the index of the first new string.
+    /// Subsequent queries against the instance take the new strings into account. Only the
+    /// deletion variants of the new strings are computed.
+    ///
+    /// Parameters
+    /// ----------
+    /// new : iterable of str
+    ///
+    /// Raises
+    /// ------
+    /// RuntimeError
+    ///     If the instance is concurrently being used by another thread.
+    ///
+    /// Examples
+    /// --------
+    /// >>> import symscan
+    /// >>> cached = symscan.CachedRef(["fooo", "barr"])
+    /// >>> (row, col, dists) = cached.get_neighbors_across(["fizz", "fuzz", "buzz"])
+    /// >>> row
+    /// array([], dtype=uint32)
+    /// >>> cached.add(["bazz", "buzz"])
+    /// >>> (row, col, dists) = cached.get_neighbors_across(["fizz", "fuzz", "buzz"])
+    /// >>> row
+    /// array([1, 2, 2], dtype=uint32)
+    /// >>> col
+    /// array([3, 2, 3], dtype=uint32)
+    /// >>> dists
+    /// array([1, 1, 0], dtype=uint8)
+    fn add(slf: &Bound<'_, Self>, new: &Bound<'_, PyAny>) -> PyResult<()> {
+        let new_handles = get_pystring_handles(new)?;
+        let new_views = get_str_refs(&new_handles)?;
+
+        let mut cached = slf.try_borrow_mut().map_err(|_| {
+            PyRuntimeError::new_err("CachedRef cannot be extended while it is in use")
+        })?;
+        cached.internal.extend(&new_views).map_err(to_pyerr)
+    }
+
+    fn __len__(&self) -> usize {
+        self.internal.len()
+    }
+
+    fn __getitem__(&self, idx: isize) -> PyResult<&str> {
+        let len = self.internal.len() as isize;
+        let normalized = if idx < 0 { idx + len } else { idx };
+        usize::try_from(normalized)
+            .ok()
+            .and_then(|i| self.internal.get(i))
+            .ok_or_else(|| PyIndexError::new_err("CachedRef index out of range"))
+    }
+
+    #[getter]
+    fn max_distance(&self) -> u8 {
+        self.internal.max_distance()
+    }
+
+    #[getter]
+    fn nbytes(&self) -> usize {
+        self.internal.nbytes()
+    }
+
+    #[getter]
+    fn metric<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.metric.to_object(py)
+    }
+
+    /// Save the CachedRef instance to a file.
+    ///
+    /// The saved file holds the memoized deletion variants along with the format version and
+    /// `max_distance` of the cache, and can be loaded back using :py:meth:`CachedRef.load`.
+    ///
+    /// Parameters
+    /// ----------
+    /// path : str or os.PathLike
+    ///     Path of the file to write. Any existing file at the path is overwritten.
+    ///
+    /// Raises
+    /// ------
+    /// OSError
+    ///     If the file cannot be written.
+    ///
+    /// Examples
+    /// --------
+    /// >>> import symscan
+    /// >>> cached = symscan.CachedRef(["fizz", "fuzz", "buzz"])
+    /// >>> cached.save("ref.idx")
+    /// >>> loaded = symscan.CachedRef.load("ref.idx")
+    /// >>> (row, col, dists) = loaded.get_neighbors_within()
+    /// >>> row
+    /// array([0, 1], dtype=uint32)
+    /// >>> col
+    /// array([1, 2], dtype=uint32)
+    /// >>> dists
+    /// array([1, 1], dtype=uint8)
+    fn save(&self, py: Python<'_>, path: PathBuf) -> PyResult<()> {
+        let file = File::create(&path).map_err(|e| io_error_with_path(py, e, &path))?;
+        self.internal.write_to(file).map_err(|e| match e {
+            symscan::Error::Io(e) => io_error_with_path(py, e, &path),
+            e => to_pyerr(e),
+        })
+    }
+
+    /// Load a CachedRef instance from a file written by :py:meth:`CachedRef.save`.
+    ///
+    /// Parameters
+    /// ----------
+    /// path : str or os.PathLike
+    /// metric : {"levenshtein", "hamming", "osa", "indel"} or tuple of int, default="levenshtein"
+    ///     The edit distance metric that the loaded instance is queried with (see
+    ///     :py:class:`~symscan.CachedRef`), which is not stored in the file.
+    ///
+    /// Returns
+    /// -------
+    /// CachedRef
+    ///
+    /// Raises
+    /// ------
+    /// OSError
+    ///     If the file cannot be read.
+    /// CacheFormatError
+    ///     If the file is not a valid CachedRef file, or was written by an incompatible version of
+    ///     symscan.
+    #[staticmethod]
+    #[pyo3(signature = (path, metric = Metric::LEVENSHTEIN))]
+    fn load(py: Python<'_>, path: PathBuf, metric: Metric) -> PyResult<Self> {
+        let file = File::open(&path).map_err(|e| io_error_with_path(py, e, &path))?;
+        let internal = symscan::CachedRef::read_from(file).map_err(|e| match e {
+            symscan::Error::Io(e) => io_error_with_path(py, e, &path),
+            e => CacheFormatError::new_err(format!("failed to load {}: {e}", path.display())),
+        })?;
+
+        Ok(CachedRef { internal, metric })
+    }
+
+    /// Construct a CachedRef from the lines of a bytes-like object.
+    ///
+    /// See :py:func:`~symscan.get_neighbors_within_buffer` for how `data` is split into lines. The
+    /// lines are copied into the cache, so `data` may be released or modified afterwards.
+    ///
+    /// Parameters
+    /// ----------
+    /// data : bytes-like
+    /// max_distance : int, default=1
+    ///     The maximum distance for which queries are supported.
+    /// delimiter : bytes, default=b"\n"
+    /// n_threads : int, optional
+    ///     The number of threads to use. See :py:func:`~symscan.get_neighbors_within`.
+    /// metric : {"levenshtein", "hamming", "osa", "indel"} or tuple of int, default="levenshtein"
+    ///     The edit distance metric that the instance will be queried with. See
+    ///     :py:class:`~symscan.CachedRef`.
+    ///
+    /// Returns
+    /// -------
+    /// CachedRef
+    ///
+    /// Examples
+    /// --------
+    /// >>> import symscan
+    /// >>> cached = symscan.CachedRef.from_buffer(b"fooo\nbarr\nbazz\nbuzz\n")
+    /// >>> len(cached)
+    /// 4
+    #[staticmethod]
+    #[pyo3(signature = (
+        data,
+        max_distance = 1,
+        delimiter = b"\n".as_slice(),
+        n_threads = None,
+        metric = Metric::LEVENSHTEIN,
+    ))]
+    fn from_buffer(
+        data: &Bound<'_, PyAny>,
+        max_distance: u8,
+        delimiter: &[u8],
+        n_threads: Option<i64>,
+        metric: Metric,
+    ) -> PyResult<Self> {
+        let buffer = get_contiguous_buffer(data)?;
+        // SAFETY: the lines are copied into the cache before returning.
+        let lines = split_buffer_lines(
+            unsafe { buffer_as_slice(&buffer) },
+            delimiter,
+            symscan::InputType::Reference,
+        )?;
+
+        let internal = run_interruptible(data.py(), n_threads, metric, |options| {
+            symscan::CachedRef::new_with_options(&lines, max_distance, options)
+        })?;
+
+        Ok(CachedRef { internal, metric })
+    }
+
+    /// Place a copy of the cache in a named shared memory segment.
+    ///
+    /// Other processes on the same machine (e.g. :py:mod:`multiprocessing` workers) can then use
+    /// the cache via :py:meth:`CachedRef.from_shared` without each holding their own copy. The
+    /// segment is compatible with :py:class:`multiprocessing.shared_memory.SharedMemory`, and must
+    /// not be written to once created.
+    ///
+    /// .. important::
+    ///
+    ///     The segment outlives the CachedRef instances using it, and must be released explicitly
+    ///     by calling :py:meth:`SharedCacheHandle.unlink` (from any process) once it is no longer
+    ///     needed. Instances already opened via :py:meth:`CachedRef.from_shared` remain usable
+    ///     after unlinking. If the creating process exits without unlinking the segment,
+    ///     Python's resource tracker releases it with a warning.
+    ///
+    /// Parameters
+    /// ----------
+    /// name : str, optional
+    ///     Name of the shared memory segment to create. By default, a unique name is generated.
+    ///
+    /// Returns
+    /// -------
+    /// SharedCacheHandle
+    ///     A lightweight, picklable handle to the segment.
+    ///
+    /// Raises
+    /// ------
+    /// FileExistsError
+    ///     If a segment called `name` already exists.
+    ///
+    /// Examples
+    /// --------
+    /// >>> from concurrent.futures import ProcessPoolExecutor
+    /// >>> import symscan
+    /// >>> def query(handle, strings):
+    /// ...     cached = symscan.CachedRef.from_shared(handle)
+    /// ...     return cached.get_neighbors_across(strings)
+    /// >>> cached = symscan.CachedRef(["fooo", "barr", "bazz", "buzz"])
+    /// >>> handle = cached.to_shared()
+    /// >>> with ProcessPoolExecutor() as executor:
+    /// ...     (row, col, dists) = executor.submit(query, handle, ["fizz", "fuzz", "buzz"]).result()
+    /// >>> handle.unlink()
+    /// >>> row
+    /// array([1, 2, 2], dtype=uint32)
+    /// >>> col
+    /// array([3, 2, 3], dtype=uint32)
+    #[pyo3(signature = (name = None))]
+    fn to_shared(&self, py: Python<'_>, name: Option<&str>) -> PyResult<SharedCacheHandle> {
+        let nbytes = self.internal.shared_size();
+
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("name", name)?;
+        kwargs.set_item("create", true)?;
+        kwargs.set_item("size", nbytes)?;
+        let shm = shared_memory_class(py)?.call((), Some(&kwargs))?;
+
+        let written = (|| {
+            let buffer = PyBuffer::<u8>::get(&shm.getattr("buf")?)?;
+            if buffer.readonly() {
+                return Err(PyRuntimeError::new_err(
+                    "shared memory segment is read-only",
+                ));
+            }
+            let bytes = unsafe {
+                slice::from_raw_parts_mut(buffer.buf_ptr().cast::<u8>(), buffer.len_bytes())
+            };
+            py.detach(|| self.internal.write_shared(bytes))
+                .map_err(to_pyerr)
+        })();
+        shm.call_method0("close")?;
+        if let Err(e) = written {
+            shm.call_method0("unlink")?;
+            return Err(e);
+        }
+
+        Ok(SharedCacheHandle {
+            name: shm.getattr("name")?.extract()?,
+            nbytes,
+            metric: self.metric,
+        })
+    }
+
+    /// Open a CachedRef placed in shared memory by :py:meth:`CachedRef.to_shared`.
+    ///
+    /// On POSIX systems, the returned instance maps the shared memory read-only and uses it in place
+    /// rather than copying it, keeping the mapping open for as long as it is alive. The contents
+    /// are validated once, when opened, so the segment must not be written to afterwards (which
+    /// symscan never does). Elsewhere, the contents are copied out of the segment. Calling
+    /// :py:meth:`CachedRef.add` on the returned instance first makes a private copy of the affected
+    /// data.
+    ///
+    /// Parameters
+    /// ----------
+    /// handle : SharedCacheHandle
+    ///
+    /// Returns
+    /// -------
+    /// CachedRef
+    ///
+    /// Raises
+    /// ------
+    /// FileNotFoundError
+    ///     If the segment does not exist (e.g. because it has already been unlinked).
+    /// CacheFormatError
+    ///     If the segment does not hold a valid CachedRef, or was written by an incompatible
+    ///     version of symscan.
+    #[staticmethod]
+    fn from_shared(py: Python<'_>, handle: PyRef<'_, SharedCacheHandle>) -> PyResult<Self> {
+        let segment = SharedSegment::open(py, &handle.name)?;
 
-        let internal = symscan::CachedRef::new(&ref_views, max_distance)
-            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        // SAFETY: the segment is mapped read-only by the CachedRef itself, and is never written to
+        // after having been populated by `to_shared`.
+        let internal = py
+            .detach(|| unsafe { symscan::CachedRef::from_shared(segment) })
+            .map_err(|e| {
+                CacheFormatError::new_err(format!(
+                    "failed to open shared CachedRef '{}': {e}",
+                    handle.name
+                ))
+            })?;
 
-        Ok(CachedRef { internal })
+        Ok(CachedRef {
+            internal,
+            metric: handle.metric,
+        })
     }
 
     /// The memoized equivalent of :py:func:`~symscan.get_neighbors_within`.
@@ -53,13 +517,20 @@ impl CachedRef {
     /// max_distance : int, default=1
     ///     The maximum edit distance at which strings are considered neighbours. This must not be
     ///     greater than the `max_distance` specified when constructing the caller instance.
+    /// n_threads : int, optional
+    ///     The number of threads to use. See :py:func:`~symscan.get_neighbors_within`.
+    /// return_similarity : bool, default=False
+    ///     Whether to additionally return the normalized similarity of each neighbor pair.
+    /// metric : {"levenshtein", "hamming", "osa", "indel"} or tuple of int, optional
+    ///     The edit distance metric to use, which must be the one the instance was constructed
+    ///     for. See :py:func:`~symscan.get_neighbors_within`.
     ///
     /// Returns
     /// -------
-    /// row : ndarray of shape (N,), dtype=uint32
+    /// row : ndarray of shape (N,), dtype=uint32 or uint64
     ///     Indices of strings in the cached reference that have neighbors.
     ///
-    /// col : ndarray of shape (N,), dtype=uint32
+    /// col : ndarray of shape (N,), dtype=uint32 or uint64
     ///     Indices of neighbor strings (i.e. ``reference[row[i]]`` and ``reference[col[i]]`` are
     ///     neighbors).
     ///
@@ -67,6 +538,10 @@ impl CachedRef {
     ///     Edit distances between neighbors (i.e. ``Levenshtein(reference[row[i]],
     ///     reference[col[i]]) = dists[i]``).
     ///
+    /// similarity : ndarray of shape (N,), dtype=float32
+    ///     Only returned if `return_similarity` is True. Normalized similarities between neighbors
+    ///     (see :py:func:`~symscan.get_neighbors_within`).
+    ///
     /// Examples
     /// --------
     /// Look for pairs of similar strings within a string collection.
@@ -80,24 +555,38 @@ impl CachedRef {
     /// array([1, 2], dtype=uint32)
     /// >>> dists
     /// array([1, 1], dtype=uint8)
-    #[pyo3(signature = (max_distance = 1))]
+    #[pyo3(signature = (
+        max_distance = 1,
+        n_threads = None,
+        return_similarity = false,
+        metric = None,
+    ))]
     fn get_neighbors_within<'py>(
         &self,
         py: Python<'py>,
         max_distance: u8,
-    ) -> PyResult<Bound<'py, PyTuple>> {
-        let symscan::NeighborPairs { row, col, dists } = self
-            .internal
-            .get_neighbors_within(max_distance)
-            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        n_threads: Option<i64>,
+        return_similarity: bool,
+        metric: Option<Metric>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let metric = self.metric.check_query(metric)?;
+        let pairs = run_interruptible(py, n_threads, metric, |options| {
+            self.internal
+                .get_neighbors_within_with_options(max_distance, options)
+        })?;
+        let similarities = return_similarity.then(|| {
+            let len = |i| self.internal.get(i).map_or(0, str::len);
+            get_similarities(&pairs, metric, len, len)
+        });
+        let input_lens = [self.internal.len()];
 
-        PyTuple::new(
+        cached_pairs_into_output(
             py,
-            &[
-                row.into_pyarray(py).as_any(),
-                col.into_pyarray(py).as_any(),
-                dists.into_pyarray(py).as_any(),
-            ],
+            pairs,
+            &input_lens,
+            similarities,
+            Output::Numpy,
+            (None, None),
         )
     }
 
@@ -105,16 +594,29 @@ impl CachedRef {
     ///
     /// Parameters
     /// ----------
-    /// query : iterable of str or CachedRef
+    /// query : iterable of str, path-like, mapping of str or CachedRef
+    ///     If a mapping is passed, `row` holds its keys instead of positional indices. See
+    ///     :py:func:`~symscan.get_neighbors_across`.
     /// max_distance : int, default=1
     ///     The maximum edit distance at which strings are considered neighbours.
+    /// n_threads : int, optional
+    ///     The number of threads to use. See :py:func:`~symscan.get_neighbors_within`.
+    /// return_similarity : bool, default=False
+    ///     Whether to additionally return the normalized similarity of each neighbor pair.
+    /// metric : {"levenshtein", "hamming", "osa", "indel"} or tuple of int, optional
+    ///     The edit distance metric to use, which must be the one the instance was constructed
+    ///     for. See :py:func:`~symscan.get_neighbors_within`.
+    /// output : {"numpy", "arrow"}, default="numpy"
+    ///     The format of the results. See :py:func:`~symscan.get_neighbors_across`.
+    /// missing : {"raise", "skip"}, default="raise"
+    ///     How to handle missing values in the query. See :py:func:`~symscan.get_neighbors_within`.
     ///
     /// Returns
     /// -------
-    /// row : ndarray of shape (N,), dtype=uint32
+    /// row : ndarray of shape (N,), dtype=uint32 or uint64
     ///     Indices of strings in the query that have neighbors.
     ///
-    /// col : ndarray of shape (N,), dtype=uint32
+    /// col : ndarray of shape (N,), dtype=uint32 or uint64
     ///     Indices of neighbor strings (i.e. ``query[row[i]]`` and ``reference[col[i]]`` are
     ///     neighbors).
     ///
@@ -122,6 +624,10 @@ impl CachedRef {
     ///     Edit distances between neighbors (i.e. ``Levenshtein(query[row[i]], reference[col[i]]) =
     ///     dists[i]``).
     ///
+    /// similarity : ndarray of shape (N,), dtype=float32
+    ///     Only returned if `return_similarity` is True. Normalized similarities between neighbors
+    ///     (see :py:func:`~symscan.get_neighbors_within`).
+    ///
     /// Examples
     /// --------
     /// Look for pairs of similar strings across the cached reference and a query collection.
@@ -146,77 +652,630 @@ impl CachedRef {
     /// array([3, 2, 3], dtype=uint32)
     /// >>> dists
     /// array([1, 1, 0], dtype=uint8)
-    #[pyo3(signature = (query, max_distance = 1))]
+    #[pyo3(signature = (
+        query,
+        max_distance = 1,
+        n_threads = None,
+        return_similarity = false,
+        metric = None,
+        output = "numpy",
+        missing = "raise",
+    ))]
+    #[allow(clippy::too_many_arguments)]
     fn get_neighbors_across<'py>(
         &self,
         py: Python<'py>,
         query: Bound<'py, PyAny>,
         max_distance: u8,
-    ) -> PyResult<Bound<'py, PyTuple>> {
-        let symscan::NeighborPairs { row, col, dists } = {
-            if let Ok(cached) = query.cast::<CachedRef>() {
-                self.internal
-                    .get_neighbors_across_cached(&cached.borrow().internal, max_distance)
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?
-            } else if let Ok(iterable) = query.try_iter() {
-                let query_handles = get_pystring_handles(&iterable)?;
-                let query_views = get_str_refs(&query_handles)?;
-                self.internal
-                    .get_neighbors_across(&query_views, max_distance)
-                    .map_err(|e| PyValueError::new_err(e.to_string()))?
-            } else {
-                let type_name = query
-                    .get_type()
-                    .name()
-                    .map(|pys| pys.to_string())
-                    .unwrap_or("UNKNOWN".to_string());
+        n_threads: Option<i64>,
+        return_similarity: bool,
+        metric: Option<Metric>,
+        output: &str,
+        missing: &str,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let metric = self.metric.check_query(metric)?;
+        let output = Output::parse(output)?;
+        let missing = Missing::parse(missing)?;
+        let query_mapping = split_mapping(&query)?;
+        let query = query_mapping.as_ref().map_or(&query, |(_, v)| v.as_any());
+
+        let (pairs, num_queries, similarities) = self.query_across(
+            query,
+            max_distance,
+            n_threads,
+            return_similarity,
+            metric,
+            missing,
+        )?;
+        let input_lens = [num_queries, self.internal.len()];
+        let keys = (query_mapping.as_ref().map(|(k, _)| k), None);
+
+        cached_pairs_into_output(py, pairs, &input_lens, similarities, output, keys)
+    }
+
+    /// Lazily find the neighbors of a query within the cached reference, one chunk at a time.
+    ///
+    /// This is equivalent to :py:meth:`CachedRef.get_neighbors_across`, except that the query is
+    /// consumed `chunk_rows` strings at a time, and the neighbor pairs found for each chunk are
+    /// yielded before moving on to the next. This bounds the memory held at once to the results
+    /// of one chunk, e.g. when each chunk is written to disk and discarded. Each query string
+    /// belongs to exactly one chunk, so the chunks together hold every neighbor pair exactly once.
+    ///
+    /// Parameters
+    /// ----------
+    /// query : iterable of str
+    /// max_distance : int, default=1
+    ///     The maximum edit distance at which strings are considered neighbours.
+    /// chunk_rows : int, default=1_000_000
+    ///     The number of query strings to search at a time.
+    /// n_threads : int, optional
+    ///     The number of threads to use. See :py:func:`~symscan.get_neighbors_within`.
+    ///
+    /// Yields
+    /// ------
+    /// row, col, dists : ndarray of shape (N,)
+    ///     The neighbor pairs involving the query strings of one chunk, as returned by
+    ///     :py:meth:`CachedRef.get_neighbors_across`. `row` holds positions in the whole query,
+    ///     not in the chunk. Chunks without any neighbor pairs yield empty arrays.
+    ///
+    /// Examples
+    /// --------
+    /// >>> import symscan
+    /// >>> cached = symscan.CachedRef(["fooo", "barr", "bazz", "buzz"])
+    /// >>> for (row, col, dists) in cached.iter_neighbors_across(["fizz", "fuzz", "buzz"], chunk_rows=2):
+    /// ...     print(row, col, dists)
+    /// [1] [3] [1]
+    /// [2 2] [2 3] [1 0]
+    #[pyo3(signature = (query, max_distance = 1, chunk_rows = 1_000_000, n_threads = None))]
+    fn iter_neighbors_across(
+        slf: &Bound<'_, Self>,
+        query: &Bound<'_, PyAny>,
+        max_distance: u8,
+        chunk_rows: usize,
+        n_threads: Option<i64>,
+    ) -> PyResult<NeighborChunks> {
+        if chunk_rows == 0 {
+            return Err(PyValueError::new_err(
+                "chunk_rows must be a positive integer",
+            ));
+        }
+        if query.cast::<PyString>().is_ok() {
+            return Err(PyValueError::new_err("expected iterable of str, got str"));
+        }
+
+        Ok(NeighborChunks {
+            cached: slf.clone().unbind(),
+            query: query.try_iter()?.unbind(),
+            max_distance,
+            chunk_rows,
+            n_threads,
+            num_queries: 0,
+        })
+    }
+
+    /// Find the neighbors of each query string within the cached reference.
+    ///
+    /// This is equivalent to :py:meth:`CachedRef.get_neighbors_across`, but with the results
+    /// grouped by query string in the style of scikit-learn's ``radius_neighbors``.
+    ///
+    /// Parameters
+    /// ----------
+    /// query : iterable of str or CachedRef
+    /// max_distance : int, default=1
+    ///     The maximum edit distance at which strings are considered neighbours.
+    /// n_threads : int, optional
+    ///     The number of threads to use. See :py:func:`~symscan.get_neighbors_within`.
+    /// metric : {"levenshtein", "hamming", "osa", "indel"} or tuple of int, optional
+    ///     The edit distance metric to use, which must be the one the instance was constructed
+    ///     for. See :py:func:`~symscan.get_neighbors_within`.
+    ///
+    /// Returns
+    /// -------
+    /// indices : list of ndarray, dtype=uint32 or uint64
+    ///     A list of length ``len(query)``, where ``indices[i]`` holds the indices of the reference
+    ///     strings that are neighbors of ``query[i]`` (empty if there are none). Neighbors are
+    ///     ordered by increasing edit distance, with ties broken by index.
+    ///
+    /// dists : list of ndarray, dtype=uint8
+    ///     A list of length ``len(query)``, where ``dists[i][j]`` is the edit distance between
+    ///     ``query[i]`` and ``reference[indices[i][j]]``.
+    ///
+    /// Examples
+    /// --------
+    /// >>> import symscan
+    /// >>> cached = symscan.CachedRef(["fooo", "barr", "bazz", "buzz"], max_distance=2)
+    /// >>> (indices, dists) = cached.radius_neighbors(["fizz", "fuzz", "buzz"], max_distance=2)
+    /// >>> indices
+    /// [array([2, 3], dtype=uint32), array([3, 2], dtype=uint32), array([3, 2], dtype=uint32)]
+    /// >>> dists
+    /// [array([2, 2], dtype=uint8), array([1, 2], dtype=uint8), array([0, 1], dtype=uint8)]
+    #[pyo3(signature = (query, max_distance = 1, n_threads = None, metric = None))]
+    fn radius_neighbors<'py>(
+        &self,
+        py: Python<'py>,
+        query: Bound<'py, PyAny>,
+        max_distance: u8,
+        n_threads: Option<i64>,
+        metric: Option<Metric>,
+    ) -> PyResult<(Bound<'py, PyList>, Bound<'py, PyList>)> {
+        let metric = self.metric.check_query(metric)?;
+        let (hits, num_queries, _) = self.query_across(
+            &query,
+            max_distance,
+            n_threads,
+            false,
+            metric,
+            Missing::Raise,
+        )?;
+        let (indices, dists) = group_hits_by_row(hits, num_queries);
+        let wide = needs_wide_indices(&[self.internal.len()]);
+
+        Ok((
+            PyList::new(py, indices.into_iter().map(|v| index_array(py, v, wide)))?,
+            PyList::new(py, dists.into_iter().map(|v| v.into_pyarray(py)))?,
+        ))
+    }
+
+    /// Find the neighbors of a single string within the cached reference.
+    ///
+    /// This is a low-latency alternative to :py:meth:`CachedRef.get_neighbors_across` for one-off
+    /// lookups (e.g. one per web request). It runs serially on the calling thread without
+    /// releasing the GIL, so it is not suited to long query strings or large `max_distance`.
+    ///
+    /// Parameters
+    /// ----------
+    /// query : str
+    /// max_distance : int, default=1
+    ///     The maximum edit distance at which strings are considered neighbours.
+    ///
+    /// Returns
+    /// -------
+    /// indices : ndarray of shape (N,), dtype=uint32 or uint64
+    ///     Indices of the reference strings that are neighbors of `query`, in increasing order.
+    ///
+    /// dists : ndarray of shape (N,), dtype=uint8
+    ///     Edit distances between `query` and the neighbors.
+    ///
+    /// Examples
+    /// --------
+    /// >>> import symscan
+    /// >>> cached = symscan.CachedRef(["fooo", "barr", "bazz", "buzz"], max_distance=2)
+    /// >>> (indices, dists) = cached.query_one("fuzz", max_distance=2)
+    /// >>> indices
+    /// array([2, 3], dtype=uint32)
+    /// >>> dists
+    /// array([2, 1], dtype=uint8)
+    #[pyo3(signature = (query, max_distance = 1))]
+    fn query_one<'py>(
+        &self,
+        py: Python<'py>,
+        query: &str,
+        max_distance: u8,
+    ) -> PyResult<(Bound<'py, PyAny>, Bound<'py, PyAny>)> {
+        let (indices, dists): (Vec<u32>, Vec<u8>) = if self.metric == Metric::LEVENSHTEIN {
+            let hits = self
+                .internal
+                .query_one(query, max_distance)
+                .map_err(to_pyerr)?;
+            hits.into_iter().unzip()
+        } else {
+            // The serial fast path only verifies with the Levenshtein distance.
+            let mut options = symscan::SearchOptions::default();
+            options.metric = self.metric.0;
+            let hits = self
+                .internal
+                .get_neighbors_across_with_options(&[query], max_distance, &options)
+                .map_err(to_pyerr)?;
+            (hits.col, hits.dists)
+        };
+        let wide = needs_wide_indices(&[self.internal.len()]);
+
+        Ok((
+            index_array(py, indices, wide),
+            dists.into_pyarray(py).into_any(),
+        ))
+    }
+}
+
+impl CachedRef {
+    /// Search across the cached reference and a query, which may either be an iterable of str or
+    /// another CachedRef. Returns the detected neighbor pairs along with the size of the query,
+    /// and the similarities of the pairs if requested. Missing values in the query are handled
+    /// according to `missing`, with the rows of the pairs referring to positions in the query.
+    fn query_across(
+        &self,
+        query: &Bound<'_, PyAny>,
+        max_distance: u8,
+        n_threads: Option<i64>,
+        return_similarity: bool,
+        metric: Metric,
+        missing: Missing,
+    ) -> PyResult<(symscan::NeighborPairs, usize, Option<Vec<f32>>)> {
+        let ref_len = |i| self.internal.get(i).map_or(0, str::len);
+
+        if let Ok(cached) = query.cast::<CachedRef>() {
+            let cached = cached.borrow();
+            if cached.metric != metric {
                 return Err(PyValueError::new_err(format!(
-                        "query must be either an iterable of str or CachedRef or None, got '{type_name}'",
-                    )));
+                    "cannot search across CachedRef instances constructed for different metrics, \
+                     got {} and {}",
+                    cached.metric.describe(),
+                    metric.describe()
+                )));
             }
+            let cached = &cached.internal;
+            let hits = run_interruptible(query.py(), n_threads, metric, |options| {
+                self.internal.get_neighbors_across_cached_with_options(
+                    cached,
+                    max_distance,
+                    options,
+                )
+            })?;
+            let similarities = return_similarity.then(|| {
+                get_similarities(
+                    &hits,
+                    metric,
+                    |i| cached.get(i).map_or(0, str::len),
+                    ref_len,
+                )
+            });
+            Ok((hits, cached.len(), similarities))
+        } else if is_path_like(query)? || query.try_iter().is_ok() {
+            let (query_strings, positions) = get_input_strings(query, missing)?;
+            let query_views = query_strings.views(symscan::InputType::Query)?;
+            let num_queries = input_len(query_views.len(), positions.as_deref());
+            let max_queries = u32::MAX as usize + 1;
+            if num_queries > max_queries {
+                // Searches against a CachedRef return u32 indices, which cannot hold the positions
+                // of a query this large.
+                return Err(to_pyerr(symscan::Error::TooManyStrings {
+                    input_type: symscan::InputType::Query,
+                    got: num_queries,
+                    limit: max_queries,
+                }));
+            }
+            let mut hits = run_interruptible(query.py(), n_threads, metric, |options| {
+                self.internal
+                    .get_neighbors_across_with_options(&query_views, max_distance, options)
+            })?;
+            let similarities = return_similarity
+                .then(|| get_similarities(&hits, metric, |i| query_views[i].len(), ref_len));
+            restore_positions(&mut hits.row, positions.as_deref());
+            Ok((hits, num_queries, similarities))
+        } else {
+            let type_name = query
+                .get_type()
+                .name()
+                .map(|pys| pys.to_string())
+                .unwrap_or("UNKNOWN".to_string());
+            Err(PyValueError::new_err(format!(
+                "query must be either an iterable of str, path-like or CachedRef or None, got \
+                 '{type_name}'",
+            )))
+        }
+    }
+}
+
+/// An iterator over the neighbor pairs of a query, one chunk at a time, as returned by
+/// :py:meth:`CachedRef.iter_neighbors_across`.
+#[pyclass(module = "symscan")]
+struct NeighborChunks {
+    cached: Py<CachedRef>,
+    query: Py<PyIterator>,
+    max_distance: u8,
+    chunk_rows: usize,
+    n_threads: Option<i64>,
+    num_queries: usize,
+}
+
+#[pymethods]
+impl NeighborChunks {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyTuple>>> {
+        let cached = self.cached.bind(py).borrow();
+        let Some((hits, chunk_len)) = search_query_chunk(
+            &cached.internal,
+            cached.metric,
+            self.query.bind(py),
+            self.num_queries,
+            self.chunk_rows,
+            self.max_distance,
+            self.n_threads,
+        )?
+        else {
+            return Ok(None);
         };
+        let mut pairs = ChunkedNeighborPairs::new(cached.internal.len());
+        pairs.extend(hits, self.num_queries, chunk_len);
+        self.num_queries += chunk_len;
 
-        PyTuple::new(
-            py,
-            &[
-                row.into_pyarray(py).as_any(),
-                col.into_pyarray(py).as_any(),
-                dists.into_pyarray(py).as_any(),
-            ],
-        )
+        pairs.into_pytuple(py).map(Some)
     }
 }
 
-/// Detect string pairs within an input collection that lie within a threshold edit distance.
+/// A handle to a CachedRef placed in shared memory by :py:meth:`CachedRef.to_shared`.
 ///
-/// The function considers all possible combinations of string pairs from `query`, and returns all
-/// those where the two strings are no more than `max_distance` Levenshtein edit distance units
-/// apart.
-///
-/// .. important::
-///
-///     This function **DOES NOT** double-count string pairs. As seen in the examples below, each
-///     pair is represented once where the `row` index is always less than the `col` index. In
-///     other words, if you were to interpret the output as a sparse matrix, only the lower
-///     triangle will be filled.
+/// Handles are lightweight and picklable, and can be passed to other processes which then open the
+/// cache via :py:meth:`CachedRef.from_shared`.
 ///
-/// Parameters
+/// Attributes
 /// ----------
-/// query : iterable of str
-/// max_distance : int, default=1
-///     The maximum edit distance at which strings are considered neighbours.
-///
-/// Returns
-/// -------
-/// row : ndarray of shape (N,), dtype=uint32
+/// name : str
+///     The name of the underlying :py:class:`multiprocessing.shared_memory.SharedMemory` segment.
+/// nbytes : int
+///     The size of the cache data held in the segment, in bytes.
+/// metric : str or tuple of int
+///     The edit distance metric of the cache (see :py:class:`~symscan.CachedRef`).
+#[pyclass(module = "symscan", frozen)]
+struct SharedCacheHandle {
+    #[pyo3(get)]
+    name: String,
+    #[pyo3(get)]
+    nbytes: usize,
+    metric: Metric,
+}
+
+#[pymethods]
+impl SharedCacheHandle {
+    #[new]
+    #[pyo3(signature = (name, nbytes, metric = Metric::LEVENSHTEIN))]
+    fn new(name: String, nbytes: usize, metric: Metric) -> Self {
+        SharedCacheHandle {
+            name,
+            nbytes,
+            metric,
+        }
+    }
+
+    fn __getnewargs__<'py>(&self, py: Python<'py>) -> PyResult<(&str, usize, Bound<'py, PyAny>)> {
+        Ok((&self.name, self.nbytes, self.metric.to_object(py)?))
+    }
+
+    #[getter]
+    fn metric<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.metric.to_object(py)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "SharedCacheHandle(name='{}', nbytes={})",
+            self.name, self.nbytes
+        )
+    }
+
+    /// Release the shared memory segment.
+    ///
+    /// This may be called from any process. CachedRef instances that have already been opened
+    /// from the segment remain usable, but no new ones can be opened.
+    ///
+    /// Raises
+    /// ------
+    /// FileNotFoundError
+    ///     If the segment has already been unlinked.
+    fn unlink(&self, py: Python<'_>) -> PyResult<()> {
+        // Attach with resource tracking so that unlinking also unregisters the segment.
+        let shm = shared_memory_class(py)?.call1((&self.name,))?;
+        shm.call_method0("close")?;
+        shm.call_method0("unlink")?;
+        Ok(())
+    }
+}
+
+/// A read-only mapping of a shared memory segment, backing a CachedRef opened via
+/// `CachedRef.from_shared` for as long as the CachedRef is alive.
+#[cfg(unix)]
+struct SharedSegment {
+    ptr: std::ptr::NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: the mapping is read-only, and owned by the segment.
+#[cfg(unix)]
+unsafe impl Send for SharedSegment {}
+#[cfg(unix)]
+unsafe impl Sync for SharedSegment {}
+
+#[cfg(unix)]
+impl SharedSegment {
+    fn open(_py: Python<'_>, name: &str) -> PyResult<Self> {
+        use std::ffi::CString;
+        use std::io;
+
+        let path = CString::new(format!("/{}", name.trim_start_matches('/')))
+            .map_err(|e| PyValueError::new_err(format!("invalid segment name: {e}")))?;
+
+        // SAFETY: `path` is a valid C string, and the descriptor is closed below once mapped.
+        let fd = unsafe { libc::shm_open(path.as_ptr(), libc::O_RDONLY, 0 as libc::c_uint) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let mapped = (|| {
+            // SAFETY: `fd` is an open descriptor, and `stat` is only read once filled in.
+            let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+            if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let len = stat.st_size as usize;
+            if len == 0 {
+                return Ok(Self {
+                    ptr: std::ptr::NonNull::dangling(),
+                    len,
+                });
+            }
+
+            // SAFETY: maps `len` bytes of an open descriptor read-only at an address of the
+            // kernel's choosing.
+            let ptr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    libc::PROT_READ,
+                    libc::MAP_SHARED,
+                    fd,
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self {
+                ptr: std::ptr::NonNull::new(ptr.cast()).expect("mmap returned null"),
+                len,
+            })
+        })();
+
+        // SAFETY: `fd` is open, and the mapping (if any) remains valid once it is closed.
+        unsafe { libc::close(fd) };
+        Ok(mapped?)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SharedSegment {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            // SAFETY: the mapping was created by `open`, and is not used past this point.
+            unsafe { libc::munmap(self.ptr.as_ptr().cast(), self.len) };
+        }
+    }
+}
+
+#[cfg(unix)]
+impl AsRef<[u8]> for SharedSegment {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: the mapping is `len` bytes long, and lives as long as `self`.
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+/// A private copy of a shared memory segment, backing a CachedRef opened via
+/// `CachedRef.from_shared` on platforms where the segment cannot be mapped read-only.
+#[cfg(not(unix))]
+struct SharedSegment {
+    words: Vec<u64>,
+    len: usize,
+}
+
+#[cfg(not(unix))]
+impl SharedSegment {
+    fn open(py: Python<'_>, name: &str) -> PyResult<Self> {
+        let shm = attach_shared_memory(py, name)?;
+        let buffer = PyBuffer::<u8>::get(&shm.getattr("buf")?)?;
+        let len = buffer.len_bytes();
+
+        // Copy into words so that the contents are as aligned as they were in the segment.
+        let mut words = vec![0u64; len.div_ceil(size_of::<u64>())];
+        let bytes = unsafe { std::slice::from_raw_parts_mut(words.as_mut_ptr().cast::<u8>(), len) };
+        bytes.copy_from_slice(unsafe { buffer_as_slice(&buffer) });
+
+        buffer.release(py);
+        shm.call_method0("close")?;
+        Ok(Self { words, len })
+    }
+}
+
+#[cfg(not(unix))]
+impl AsRef<[u8]> for SharedSegment {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: the words hold at least `len` initialised bytes.
+        unsafe { std::slice::from_raw_parts(self.words.as_ptr().cast(), self.len) }
+    }
+}
+
+fn shared_memory_class(py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+    py.import("multiprocessing.shared_memory")?
+        .getattr("SharedMemory")
+}
+
+/// Attach to an existing shared memory segment without registering it with the multiprocessing
+/// resource tracker, which would otherwise unlink the segment when the attaching process exits.
+#[cfg(not(unix))]
+fn attach_shared_memory<'py>(py: Python<'py>, name: &str) -> PyResult<Bound<'py, PyAny>> {
+    let shared_memory = shared_memory_class(py)?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("name", name)?;
+    kwargs.set_item("track", false)?;
+
+    match shared_memory.call((), Some(&kwargs)) {
+        Err(e) if e.is_instance_of::<PyTypeError>(py) => {
+            // Python < 3.13 does not support `track`, so unregister the segment manually.
+            let shm = shared_memory.call1((name,))?;
+            if py.import("os")?.getattr("name")?.extract::<String>()? == "posix" {
+                py.import("multiprocessing.resource_tracker")?
+                    .call_method1("unregister", (shm.getattr("_name")?, "shared_memory"))?;
+            }
+            Ok(shm)
+        }
+        result => result,
+    }
+}
+
+/// Detect string pairs within an input collection that lie within a threshold edit distance.
+///
+/// The function considers all possible combinations of string pairs from `query`, and returns all
+/// those where the two strings are no more than `max_distance` Levenshtein edit distance units
+/// apart.
+///
+/// .. important::
+///
+///     This function **DOES NOT** double-count string pairs. As seen in the examples below, each
+///     pair is represented once where the `row` index is always less than the `col` index. In
+///     other words, if you were to interpret the output as a sparse matrix, only the lower
+///     triangle will be filled.
+///
+/// Parameters
+/// ----------
+/// query : iterable of str or path-like
+///     If a str or :py:class:`os.PathLike` is passed, it is taken as the path to a text file
+///     holding one string per line, which is read without creating a Python object per line. Lines
+///     are split as in :py:func:`~symscan.get_neighbors_within_buffer`, and returned indices are
+///     (0-based) line numbers.
+/// max_distance : int, default=1
+///     The maximum edit distance at which strings are considered neighbours. Thresholds above 254
+///     are searched by comparing all pairs of strings of compatible lengths directly, which takes
+///     time quadratic in the size of the input.
+/// n_threads : int, optional
+///     The number of threads to use. By default, computations run on symscan's global thread pool,
+///     which has one thread per CPU core. Otherwise, each computation runs on a dedicated pool of
+///     `n_threads` threads, which is useful to avoid monopolizing the CPUs of a shared machine.
+/// return_similarity : bool, default=False
+///     Whether to additionally return the normalized similarity of each neighbor pair.
+/// metric : {"levenshtein", "hamming", "osa", "indel"} or tuple of int, default="levenshtein"
+///     The edit distance metric to use: the Levenshtein distance, the Hamming distance (under
+///     which strings of different lengths are never neighbors), the optimal string alignment
+///     distance (which also counts transpositions of adjacent characters as single edits), or the
+///     indel distance (which counts insertions and deletions only). A tuple of (insertion,
+///     deletion, substitution) costs, each at least 1, selects the Levenshtein distance with those
+///     costs, where insertions and deletions turn the `row` string of a pair into the `col`
+///     string.
+/// missing : {"raise", "skip"}, default="raise"
+///     How to handle missing values (None, NaN or ``pandas.NA``) in the input. If "raise", a
+///     ValueError naming the index of the first missing value is raised. If "skip", missing values
+///     are excluded from the search, while the returned indices still refer to positions in the
+///     original input.
+///
+/// Returns
+/// -------
+/// row : ndarray of shape (N,), dtype=uint32 or uint64
 ///     Indices of strings in the query that have neighbors.
 ///
-/// col : ndarray of shape (N,), dtype=uint32
+/// col : ndarray of shape (N,), dtype=uint32 or uint64
 ///     Indices of neighbor strings (i.e. ``query[row[i]]`` and ``query[col[i]]`` are neighbors).
 ///
-/// dists : ndarray of shape (N,), dtype=uint8
+/// dists : ndarray of shape (N,), dtype=uint8 or uint16
 ///     Edit distances between neighbors (i.e. ``Levenshtein(query[row[i]], query[col[i]]) =
-///     dists[i]``).
+///     dists[i]``). The dtype is uint16 if `max_distance` exceeds 254, and uint8 otherwise.
+///
+/// similarity : ndarray of shape (N,), dtype=float32
+///     Only returned if `return_similarity` is True. Normalized similarities between neighbors,
+///     computed as ``1 - dists[i] / max_dist``, where ``max_dist`` is the largest possible
+///     distance under `metric` between strings of the lengths of ``query[row[i]]`` and
+///     ``query[col[i]]`` (e.g. the length of the longer string for the Levenshtein distance), such
+///     that identical strings (including two empty strings) have a similarity of 1.
 ///
 /// Examples
 /// --------
@@ -242,28 +1301,117 @@ impl CachedRef {
 /// array([1, 2, 2], dtype=uint32)
 /// >>> dists
 /// array([1, 2, 1], dtype=uint8)
+///
+/// Distances are returned as uint16 once `max_distance` exceeds 254.
+///
+/// >>> (row, col, dists) = symscan.get_neighbors_within(["a" * 300, "b" * 300, "a"], max_distance=300)
+/// >>> dists
+/// array([300, 299, 300], dtype=uint16)
+///
+/// Set `return_similarity` to also get similarity scores normalized to the range [0, 1].
+///
+/// >>> (row, col, dists, similarity) = symscan.get_neighbors_within(["fizz", "fuzz", "fuz"], return_similarity=True)
+/// >>> similarity
+/// array([0.75, 0.75], dtype=float32)
+///
+/// The number of threads does not affect the results.
+///
+/// >>> (row, col, dists) = symscan.get_neighbors_within(["fizz", "fuzz", "buzz"], n_threads=1)
+/// >>> row
+/// array([0, 1], dtype=uint32)
+/// >>> col
+/// array([1, 2], dtype=uint32)
+/// >>> dists
+/// array([1, 1], dtype=uint8)
+///
+/// Missing values raise by default, but can be skipped instead.
+///
+/// >>> import numpy as np
+/// >>> symscan.get_neighbors_within(["fizz", None, "fuzz", np.nan, "buzz"])
+/// Traceback (most recent call last):
+///     ...
+/// ValueError: expected no missing values, got None at index 1 (pass missing='skip' to exclude missing values from the search)
+/// >>> (row, col, dists) = symscan.get_neighbors_within(["fizz", None, "fuzz", np.nan, "buzz"], missing="skip")
+/// >>> row
+/// array([0, 2], dtype=uint32)
+/// >>> col
+/// array([2, 4], dtype=uint32)
+/// >>> dists
+/// array([1, 1], dtype=uint8)
 #[pyfunction]
-#[pyo3(signature = (query, max_distance = 1))]
+#[pyo3(signature = (
+    query,
+    max_distance = 1,
+    n_threads = None,
+    return_similarity = false,
+    metric = Metric::LEVENSHTEIN,
+    missing = "raise",
+))]
 fn get_neighbors_within<'py>(
     py: Python<'py>,
     query: &Bound<'py, PyAny>,
-    max_distance: u8,
+    max_distance: u16,
+    n_threads: Option<i64>,
+    return_similarity: bool,
+    metric: Metric,
+    missing: &str,
 ) -> PyResult<Bound<'py, PyTuple>> {
-    let query_handles = get_pystring_handles(&query)?;
-    let query_views = get_str_refs(&query_handles)?;
+    let missing = Missing::parse(missing)?;
+    let (query_strings, positions) = get_input_strings(query, missing)?;
+    let query_views = query_strings.views(symscan::InputType::Query)?;
+
+    let search = WithinSearch {
+        query: &query_views,
+        positions: positions.as_deref(),
+        max_distance,
+        n_threads,
+        return_similarity,
+        metric,
+    };
+
+    search.run(py)
+}
+
+/// A search for neighbor pairs within an input collection, given as its strings along with their
+/// positions within the input if any missing values were skipped.
+struct WithinSearch<'a> {
+    query: &'a [&'a str],
+    positions: Option<&'a [usize]>,
+    max_distance: u16,
+    n_threads: Option<i64>,
+    return_similarity: bool,
+    metric: Metric,
+}
+
+impl WithinSearch<'_> {
+    /// Run the search, through the `_wide` entry points of the core if `max_distance` exceeds
+    /// [`MAX_NARROW_DISTANCE`], or through its `_large` entry points if the input exceeds the
+    /// limits of the regular ones, and convert the results into a tuple of arrays.
+    fn run<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyTuple>> {
+        if self.max_distance > MAX_NARROW_DISTANCE {
+            self.run_with::<Wide>(py)
+        } else if needs_wide_indices(&[input_len(self.query.len(), self.positions)]) {
+            self.run_with::<Large>(py)
+        } else {
+            self.run_with::<Regular>(py)
+        }
+    }
 
-    let symscan::NeighborPairs { row, col, dists } =
-        symscan::get_neighbors_within(&query_views, max_distance)
-            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    /// Implementation of [`WithinSearch::run`] through the entry points `E`.
+    fn run_with<'py, E: EntryPoints>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyTuple>> {
+        let mut pairs = run_interruptible(py, self.n_threads, self.metric, |options| {
+            E::within(self.query, self.max_distance, options)
+        })?;
+        let similarities = self.return_similarity.then(|| {
+            let len = |i: usize| self.query[i].len();
+            get_similarities(&pairs, self.metric, len, len)
+        });
+        let (row, col) = pairs.indices_mut();
+        restore_positions(row, self.positions);
+        restore_positions(col, self.positions);
 
-    PyTuple::new(
-        py,
-        &[
-            row.into_pyarray(py).as_any(),
-            col.into_pyarray(py).as_any(),
-            dists.into_pyarray(py).as_any(),
-        ],
-    )
+        neighbor_pairs_into_pytuple(py, pairs, similarities)
+    }
 }
 
 /// Detect string pairs across two input collections that lie within a threshold edit distance.
@@ -274,23 +1422,54 @@ fn get_neighbors_within<'py>(
 ///
 /// Parameters
 /// ----------
-/// query : iterable of str
-/// reference : iterable of str
+/// query : iterable of str, path-like, mapping of str or CachedRef
+/// reference : iterable of str, path-like, mapping of str or CachedRef
+///     Path-like inputs are read as in :py:func:`~symscan.get_neighbors_within`. If a mapping
+///     (e.g. a dict from database ids to strings) is passed as `query` or `reference`, the
+///     corresponding `row` or `col` holds its keys instead of positional indices. The keys are
+///     returned in an int64 array if they are all ints, and in an object array otherwise. If a :py:class:`~symscan.CachedRef` is passed, its cached deletion variants are
+///     used instead of being recomputed, as with :py:meth:`CachedRef.get_neighbors_across`. It
+///     must then have been constructed with a `max_distance` at least as large as the one given
+///     here. The meaning of `row` and `col` is the same either way.
 /// max_distance : int, default=1
-///     The maximum edit distance at which strings are considered neighbors.
+///     The maximum edit distance at which strings are considered neighbors. Thresholds above 254
+///     are searched as in :py:func:`~symscan.get_neighbors_within`, and are not supported with a
+///     CachedRef.
+/// n_threads : int, optional
+///     The number of threads to use. See :py:func:`~symscan.get_neighbors_within`.
+/// return_similarity : bool, default=False
+///     Whether to additionally return the normalized similarity of each neighbor pair.
+/// metric : {"levenshtein", "hamming", "osa", "indel"} or tuple of int, optional
+///     The edit distance metric to use. See :py:func:`~symscan.get_neighbors_within`. Defaults to
+///     the metric of the CachedRef if one is passed (which must otherwise match), and to
+///     "levenshtein" otherwise.
+/// output : {"numpy", "arrow"}, default="numpy"
+///     If "arrow", the results are instead returned as a single :py:class:`pyarrow.Table` with
+///     the columns ``query``, ``reference`` and ``dist`` (and ``similarity`` if requested),
+///     corresponding to `row`, `col`, `dists` (and `similarity`) below. The table is handed over
+///     via the Arrow C data interface without copying, which requires pyarrow to be installed.
+///     Not supported with mapping inputs.
+/// missing : {"raise", "skip"}, default="raise"
+///     How to handle missing values in `query` and `reference`. See
+///     :py:func:`~symscan.get_neighbors_within`.
 ///
 /// Returns
 /// -------
-/// row : ndarray of shape (N,), dtype=uint32
-///     Indices of strings in the query that have neighbors.
+/// row : ndarray of shape (N,), dtype=uint32 or uint64
+///     Indices (or keys) of strings in the query that have neighbors.
 ///
-/// col : ndarray of shape (N,), dtype=uint32
-///     Indices of neighbor strings (i.e. ``query[row[i]]`` and ``reference[col[i]]`` are
-///     neighbors).
+/// col : ndarray of shape (N,), dtype=uint32 or uint64
+///     Indices (or keys) of neighbor strings (i.e. ``query[row[i]]`` and ``reference[col[i]]``
+///     are neighbors).
 ///
-/// dists : ndarray of shape (N,), dtype=uint8
+/// dists : ndarray of shape (N,), dtype=uint8 or uint16
 ///     Edit distances between neighbors (i.e. ``Levenshtein(query[row[i]], reference[col[i]]) =
-///     dists[i]``).
+///     dists[i]``). The dtype is uint16 if `max_distance` exceeds 254, and uint8 otherwise.
+///
+/// similarity : ndarray of shape (N,), dtype=float32
+///     Only returned if `return_similarity` is True. Normalized similarities between neighbors,
+///     computed as ``1 - dists[i] / max(len(query[row[i]]), len(reference[col[i]]))``, such that
+///     identical strings (including two empty strings) have a similarity of 1.
 ///
 /// Examples
 /// --------
@@ -313,57 +1492,3864 @@ fn get_neighbors_within<'py>(
 /// array([2, 3, 2, 3, 2, 3], dtype=uint32)
 /// >>> dists
 /// array([2, 2, 2, 1, 1, 0], dtype=uint8)
+///
+/// The results can also be returned as a pyarrow Table.
+///
+/// >>> table = symscan.get_neighbors_across(["fizz", "fuzz", "buzz"], ["fooo", "barr", "bazz", "buzz"], output="arrow")
+/// >>> table.column_names
+/// ['query', 'reference', 'dist']
+/// >>> table.num_rows
+/// 3
+///
+/// A prebuilt CachedRef can be passed in place of either collection.
+///
+/// >>> cached = symscan.CachedRef(["fooo", "barr", "bazz", "buzz"])
+/// >>> (row, col, dists) = symscan.get_neighbors_across(["fizz", "fuzz", "buzz"], cached)
+/// >>> row
+/// array([1, 2, 2], dtype=uint32)
+/// >>> col
+/// array([3, 2, 3], dtype=uint32)
+/// >>> dists
+/// array([1, 1, 0], dtype=uint8)
+///
+/// Strings keyed by ids can be passed as mappings, in which case the ids are returned.
+///
+/// >>> query = {101: "fizz", 102: "fuzz", 103: "buzz"}
+/// >>> reference = {"a": "fooo", "b": "barr", "c": "bazz", "d": "buzz"}
+/// >>> (row, col, dists) = symscan.get_neighbors_across(query, reference)
+/// >>> row
+/// array([102, 103, 103])
+/// >>> col
+/// array(['d', 'c', 'd'], dtype=object)
 #[pyfunction]
-#[pyo3(signature = (query, reference, max_distance = 1))]
+#[pyo3(signature = (
+    query,
+    reference,
+    max_distance = 1,
+    n_threads = None,
+    return_similarity = false,
+    metric = None,
+    output = "numpy",
+    missing = "raise",
+))]
+#[allow(clippy::too_many_arguments)]
 fn get_neighbors_across<'py>(
     py: Python<'py>,
     query: &Bound<'py, PyAny>,
     reference: Bound<'py, PyAny>,
-    max_distance: u8,
-) -> PyResult<Bound<'py, PyTuple>> {
-    let query_handles = get_pystring_handles(&query)?;
-    let query_views = get_str_refs(&query_handles)?;
-    let ref_handles = get_pystring_handles(&reference)?;
-    let ref_views = get_str_refs(&ref_handles)?;
-
-    let symscan::NeighborPairs { row, col, dists } = {
-        symscan::get_neighbors_across(&query_views, &ref_views, max_distance)
-            .map_err(|e| PyValueError::new_err(e.to_string()))?
+    max_distance: u16,
+    n_threads: Option<i64>,
+    return_similarity: bool,
+    metric: Option<Metric>,
+    output: &str,
+    missing: &str,
+) -> PyResult<Bound<'py, PyAny>> {
+    if let Ok(cached) = reference.cast::<CachedRef>() {
+        return cached.borrow().get_neighbors_across(
+            py,
+            query.clone(),
+            cached_max_distance(max_distance)?,
+            n_threads,
+            return_similarity,
+            metric,
+            output,
+            missing,
+        );
+    }
+
+    let output = Output::parse(output)?;
+    let missing = Missing::parse(missing)?;
+    let ref_mapping = split_mapping(&reference)?;
+    let reference = ref_mapping.as_ref().map_or(&reference, |(_, v)| v.as_any());
+
+    if let Ok(cached) = query.cast::<CachedRef>() {
+        // Search the reference against the cached query, and swap the roles back afterwards.
+        let cached = cached.borrow();
+        let metric = cached.metric.check_query(metric)?;
+        let (pairs, num_refs, similarities) = cached.query_across(
+            reference,
+            cached_max_distance(max_distance)?,
+            n_threads,
+            return_similarity,
+            metric,
+            missing,
+        )?;
+        let (pairs, similarities) = transpose_neighbor_pairs(pairs, similarities);
+        let input_lens = [cached.internal.len(), num_refs];
+        let keys = (None, ref_mapping.as_ref().map(|(k, _)| k));
+
+        return cached_pairs_into_output(py, pairs, &input_lens, similarities, output, keys);
+    }
+
+    let query_mapping = split_mapping(query)?;
+    let query = query_mapping.as_ref().map_or(query, |(_, v)| v.as_any());
+
+    let (query_strings, query_positions) = get_input_strings(query, missing)?;
+    let query_views = query_strings.views(symscan::InputType::Query)?;
+    let (ref_strings, ref_positions) = get_input_strings(reference, missing)?;
+    let ref_views = ref_strings.views(symscan::InputType::Reference)?;
+
+    let search = AcrossSearch {
+        query: &query_views,
+        reference: &ref_views,
+        positions: (query_positions.as_deref(), ref_positions.as_deref()),
+        max_distance,
+        n_threads,
+        return_similarity,
+        metric: metric.unwrap_or(Metric::LEVENSHTEIN),
     };
+    let keys = (
+        query_mapping.as_ref().map(|(k, _)| k),
+        ref_mapping.as_ref().map(|(k, _)| k),
+    );
 
-    PyTuple::new(
-        py,
-        &[
-            row.into_pyarray(py).as_any(),
-            col.into_pyarray(py).as_any(),
-            dists.into_pyarray(py).as_any(),
-        ],
-    )
+    search.run(py, output, keys)
 }
 
-fn get_pystring_handles<'py>(input: &Bound<'py, PyAny>) -> PyResult<Vec<Bound<'py, PyString>>> {
-    if let Ok(_) = input.cast::<PyString>() {
-        Err(PyValueError::new_err("expected iterable of str, got str"))
-    } else {
-        input
-            .try_iter()?
-            .map(|v| v?.cast_into::<PyString>().map_err(PyErr::from))
-            .collect::<PyResult<Vec<_>>>()
+/// A search for neighbor pairs across two input collections, given as their strings along with
+/// their positions within the inputs if any missing values were skipped.
+struct AcrossSearch<'a> {
+    query: &'a [&'a str],
+    reference: &'a [&'a str],
+    positions: (Option<&'a [usize]>, Option<&'a [usize]>),
+    max_distance: u16,
+    n_threads: Option<i64>,
+    return_similarity: bool,
+    metric: Metric,
+}
+
+impl AcrossSearch<'_> {
+    /// Run the search, through the `_wide` entry points of the core if `max_distance` exceeds
+    /// [`MAX_NARROW_DISTANCE`], or through its `_large` entry points if either input exceeds the
+    /// limits of the regular ones, and convert the results into the requested output.
+    fn run<'py>(
+        &self,
+        py: Python<'py>,
+        output: Output,
+        keys: (Option<&Keys>, Option<&Keys>),
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let (query_positions, ref_positions) = self.positions;
+        if self.max_distance > MAX_NARROW_DISTANCE {
+            self.run_with::<Wide>(py, output, keys)
+        } else if needs_wide_indices(&[
+            input_len(self.query.len(), query_positions),
+            input_len(self.reference.len(), ref_positions),
+        ]) {
+            self.run_with::<Large>(py, output, keys)
+        } else {
+            self.run_with::<Regular>(py, output, keys)
+        }
+    }
+
+    /// Implementation of [`AcrossSearch::run`] through the entry points `E`.
+    fn run_with<'py, E: EntryPoints>(
+        &self,
+        py: Python<'py>,
+        output: Output,
+        keys: (Option<&Keys>, Option<&Keys>),
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let mut pairs = run_interruptible(py, self.n_threads, self.metric, |options| {
+            E::across(self.query, self.reference, self.max_distance, options)
+        })?;
+        let similarities = self.return_similarity.then(|| {
+            get_similarities(
+                &pairs,
+                self.metric,
+                |i| self.query[i].len(),
+                |i| self.reference[i].len(),
+            )
+        });
+        let (row, col) = pairs.indices_mut();
+        restore_positions(row, self.positions.0);
+        restore_positions(col, self.positions.1);
+
+        neighbor_pairs_into_output(py, pairs, similarities, output, keys)
     }
 }
 
-fn get_str_refs<'py>(input: &'py [Bound<'py, PyString>]) -> PyResult<Vec<&'py str>> {
-    input
-        .iter()
-        .map(|v| v.to_str())
-        .collect::<PyResult<Vec<_>>>()
+/// Detect string pairs within the lines of a bytes-like object that lie within a threshold edit
+/// distance.
+///
+/// This is equivalent to :py:func:`~symscan.get_neighbors_within`, but takes the raw contents of
+/// e.g. a file or :py:class:`mmap.mmap` and splits them into lines without creating a Python
+/// object per line, which can otherwise take longer than the search itself. A trailing delimiter
+/// does not start a further empty line, and when splitting on ``b"\n"``, a trailing ``b"\r"``
+/// is stripped from each line so that CRLF line endings are handled.
+///
+/// .. important::
+///
+///     `data` must not be modified while the call is running.
+///
+/// Parameters
+/// ----------
+/// data : bytes-like
+///     Any C-contiguous object supporting the buffer protocol, such as bytes or memoryview.
+/// max_distance : int, default=1
+///     The maximum edit distance at which strings are considered neighbors.
+/// delimiter : bytes, default=b"\n"
+///     A single byte separating the lines.
+/// n_threads : int, optional
+///     The number of threads to use. See :py:func:`~symscan.get_neighbors_within`.
+/// metric : {"levenshtein", "hamming", "osa", "indel"} or tuple of int, default="levenshtein"
+///     The edit distance metric to use. See :py:func:`~symscan.get_neighbors_within`.
+///
+/// Returns
+/// -------
+/// row, col, dists : ndarray of shape (N,)
+///     As returned by :py:func:`~symscan.get_neighbors_within`, where indices are (0-based) line
+///     numbers within `data`.
+///
+/// Examples
+/// --------
+/// >>> import symscan
+/// >>> (row, col, dists) = symscan.get_neighbors_within_buffer(b"fizz\r\nfuzz\r\nbuzz\r\n")
+/// >>> row
+/// array([0, 1], dtype=uint32)
+/// >>> col
+/// array([1, 2], dtype=uint32)
+/// >>> dists
+/// array([1, 1], dtype=uint8)
+#[pyfunction]
+#[pyo3(signature = (
+    data,
+    max_distance = 1,
+    delimiter = b"\n".as_slice(),
+    n_threads = None,
+    metric = Metric::LEVENSHTEIN,
+))]
+fn get_neighbors_within_buffer<'py>(
+    py: Python<'py>,
+    data: &Bound<'py, PyAny>,
+    max_distance: u16,
+    delimiter: &[u8],
+    n_threads: Option<i64>,
+    metric: Metric,
+) -> PyResult<Bound<'py, PyTuple>> {
+    let buffer = get_contiguous_buffer(data)?;
+    // SAFETY: the caller is documented to not modify `data` during the call.
+    let lines = split_buffer_lines(
+        unsafe { buffer_as_slice(&buffer) },
+        delimiter,
+        symscan::InputType::Query,
+    )?;
+
+    let search = WithinSearch {
+        query: &lines,
+        positions: None,
+        max_distance,
+        n_threads,
+        return_similarity: false,
+        metric,
+    };
+
+    search.run(py)
 }
 
-/// Fast discovery of similar strings in bulk
-#[pymodule(name = "symscan")]
-fn symscan_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(get_neighbors_within, m)?)?;
-    m.add_function(wrap_pyfunction!(get_neighbors_across, m)?)?;
-    m.add_class::<CachedRef>()?;
-    Ok(())
+/// Detect string pairs across the lines of two bytes-like objects that lie within a threshold edit
+/// distance.
+///
+/// This is equivalent to :py:func:`~symscan.get_neighbors_across`, with the inputs given as in
+/// :py:func:`~symscan.get_neighbors_within_buffer`.
+///
+/// .. important::
+///
+///     `query` and `reference` must not be modified while the call is running.
+///
+/// Parameters
+/// ----------
+/// query : bytes-like
+/// reference : bytes-like
+/// max_distance : int, default=1
+///     The maximum edit distance at which strings are considered neighbors.
+/// delimiter : bytes, default=b"\n"
+///     A single byte separating the lines.
+/// n_threads : int, optional
+///     The number of threads to use. See :py:func:`~symscan.get_neighbors_within`.
+/// metric : {"levenshtein", "hamming", "osa", "indel"} or tuple of int, default="levenshtein"
+///     The edit distance metric to use. See :py:func:`~symscan.get_neighbors_within`.
+///
+/// Returns
+/// -------
+/// row, col, dists : ndarray of shape (N,)
+///     As returned by :py:func:`~symscan.get_neighbors_across`, where indices are (0-based) line
+///     numbers within `query` and `reference` respectively.
+///
+/// Examples
+/// --------
+/// >>> import symscan
+/// >>> (row, col, dists) = symscan.get_neighbors_across_buffer(b"fizz\nfuzz\nbuzz", b"fooo\nbarr\nbazz\nbuzz")
+/// >>> row
+/// array([1, 2, 2], dtype=uint32)
+/// >>> col
+/// array([3, 2, 3], dtype=uint32)
+/// >>> dists
+/// array([1, 1, 0], dtype=uint8)
+#[pyfunction]
+#[pyo3(signature = (
+    query,
+    reference,
+    max_distance = 1,
+    delimiter = b"\n".as_slice(),
+    n_threads = None,
+    metric = Metric::LEVENSHTEIN,
+))]
+#[allow(clippy::too_many_arguments)]
+fn get_neighbors_across_buffer<'py>(
+    py: Python<'py>,
+    query: &Bound<'py, PyAny>,
+    reference: &Bound<'py, PyAny>,
+    max_distance: u16,
+    delimiter: &[u8],
+    n_threads: Option<i64>,
+    metric: Metric,
+) -> PyResult<Bound<'py, PyAny>> {
+    let query_buffer = get_contiguous_buffer(query)?;
+    let reference_buffer = get_contiguous_buffer(reference)?;
+    // SAFETY: the caller is documented to not modify the inputs during the call.
+    let query_lines = split_buffer_lines(
+        unsafe { buffer_as_slice(&query_buffer) },
+        delimiter,
+        symscan::InputType::Query,
+    )?;
+    let reference_lines = split_buffer_lines(
+        unsafe { buffer_as_slice(&reference_buffer) },
+        delimiter,
+        symscan::InputType::Reference,
+    )?;
+
+    let search = AcrossSearch {
+        query: &query_lines,
+        reference: &reference_lines,
+        positions: (None, None),
+        max_distance,
+        n_threads,
+        return_similarity: false,
+        metric,
+    };
+
+    search.run(py, Output::Numpy, (None, None))
+}
+
+/// Detect string pairs across a lazily consumed query and a reference that lie within a threshold
+/// edit distance.
+///
+/// This is equivalent to :py:func:`~symscan.get_neighbors_across`, except that `query` is consumed
+/// lazily, `chunk_size` strings at a time. Each chunk is searched against a
+/// :py:class:`~symscan.CachedRef` of the reference that is built once (unless one is passed as
+/// `reference`), so that memory usage stays bounded by the size of one chunk plus the cache and
+/// the results. This makes it possible to search a query that is too large to hold in memory as a
+/// Python list at once, e.g. one streamed from a file.
+///
+/// Parameters
+/// ----------
+/// query : iterable of str
+/// reference : iterable of str or CachedRef
+///     If a CachedRef is passed, it must have been constructed with a `max_distance` at least as
+///     large as the one given here, and the search uses its metric. Otherwise, the search uses
+///     the Levenshtein distance.
+/// max_distance : int, default=1
+///     The maximum edit distance at which strings are considered neighbors.
+/// chunk_size : int, default=1_000_000
+///     The number of query strings to materialize and search at a time.
+/// n_threads : int, optional
+///     The number of threads to use. See :py:func:`~symscan.get_neighbors_within`.
+///
+/// Returns
+/// -------
+/// row : ndarray of shape (N,), dtype=uint32 or uint64
+///     Indices of strings in the query that have neighbors.
+///
+/// col : ndarray of shape (N,), dtype=uint32 or uint64
+///     Indices of neighbor strings (i.e. ``query[row[i]]`` and ``reference[col[i]]`` are
+///     neighbors).
+///
+/// dists : ndarray of shape (N,), dtype=uint8
+///     Edit distances between neighbors (i.e. ``Levenshtein(query[row[i]], reference[col[i]]) =
+///     dists[i]``).
+///
+/// Examples
+/// --------
+/// The results are the same as those of :py:func:`~symscan.get_neighbors_across`, regardless of
+/// the chunk size.
+///
+/// >>> import symscan
+/// >>> query = (s for s in ["fizz", "fuzz", "buzz"])
+/// >>> (row, col, dists) = symscan.get_neighbors_across_chunked(query, ["fooo", "barr", "bazz", "buzz"], chunk_size=1)
+/// >>> row
+/// array([1, 2, 2], dtype=uint32)
+/// >>> col
+/// array([3, 2, 3], dtype=uint32)
+/// >>> dists
+/// array([1, 1, 0], dtype=uint8)
+#[pyfunction]
+#[pyo3(signature = (query, reference, max_distance = 1, chunk_size = 1_000_000, n_threads = None))]
+fn get_neighbors_across_chunked<'py>(
+    py: Python<'py>,
+    query: &Bound<'py, PyAny>,
+    reference: &Bound<'py, PyAny>,
+    max_distance: u8,
+    chunk_size: usize,
+    n_threads: Option<i64>,
+) -> PyResult<Bound<'py, PyTuple>> {
+    search_chunked(query, reference, max_distance, chunk_size, n_threads)?.into_pytuple(py)
+}
+
+/// Search a lazily consumed query against a reference one chunk at a time, as done by
+/// [`get_neighbors_across_chunked`].
+fn search_chunked(
+    query: &Bound<'_, PyAny>,
+    reference: &Bound<'_, PyAny>,
+    max_distance: u8,
+    chunk_size: usize,
+    n_threads: Option<i64>,
+) -> PyResult<ChunkedNeighborPairs> {
+    if chunk_size == 0 {
+        return Err(PyValueError::new_err(
+            "chunk_size must be a positive integer",
+        ));
+    }
+    if query.cast::<PyString>().is_ok() {
+        return Err(PyValueError::new_err("expected iterable of str, got str"));
+    }
+
+    let provided;
+    let built;
+    let cached = match reference.cast::<CachedRef>() {
+        Ok(cached) => {
+            provided = cached.borrow();
+            &*provided
+        }
+        Err(_) => {
+            built = CachedRef::new(reference, max_distance, n_threads, Metric::LEVENSHTEIN)?;
+            &built
+        }
+    };
+
+    let query_iter = query.try_iter()?;
+    let mut pairs = ChunkedNeighborPairs::new(cached.internal.len());
+    let mut num_queries = 0;
+
+    while let Some((chunk_hits, chunk_len)) = search_query_chunk(
+        &cached.internal,
+        cached.metric,
+        &query_iter,
+        num_queries,
+        chunk_size,
+        max_distance,
+        n_threads,
+    )? {
+        pairs.extend(chunk_hits, num_queries, chunk_len);
+        num_queries += chunk_len;
+    }
+
+    Ok(pairs)
+}
+
+/// Search the next chunk of up to `chunk_size` strings from `query_iter` against `cached`, where
+/// `num_queries` query strings have already been consumed. Returns the detected neighbor pairs,
+/// with rows referring to positions within the chunk, along with the size of the chunk, or None if
+/// the query is exhausted.
+fn search_query_chunk(
+    cached: &symscan::CachedRef,
+    metric: Metric,
+    query_iter: &Bound<'_, PyIterator>,
+    num_queries: usize,
+    chunk_size: usize,
+    max_distance: u8,
+    n_threads: Option<i64>,
+) -> PyResult<Option<(symscan::NeighborPairs, usize)>> {
+    let chunk_handles = query_iter
+        .clone()
+        .take(chunk_size)
+        .enumerate()
+        .map(|(idx, v)| get_pystring_handle(v?, num_queries + idx))
+        .collect::<PyResult<Vec<_>>>()?;
+    if chunk_handles.is_empty() {
+        return Ok(None);
+    }
+    let chunk_views = get_str_refs(&chunk_handles)?;
+
+    let hits = run_interruptible(query_iter.py(), n_threads, metric, |options| {
+        cached
+            .get_neighbors_across_with_options(&chunk_views, max_distance, options)
+            .map_err(|e| match e {
+                symscan::Error::NonAsciiInput {
+                    input_type,
+                    offending_idx,
+                    offending_string,
+                } => symscan::Error::NonAsciiInput {
+                    input_type,
+                    offending_idx: num_queries + offending_idx,
+                    offending_string,
+                },
+                e => e,
+            })
+    })?;
+
+    Ok(Some((hits, chunk_views.len())))
+}
+
+/// Detect neighboring string pairs, within a single collection or across two.
+///
+/// This is a single entry point covering all search modes, in the style of the ``symdel``
+/// function from the nearust package. It dispatches to :py:func:`~symscan.get_neighbors_within`
+/// if `reference` is None, to :py:meth:`CachedRef.get_neighbors_across` if `reference` is a
+/// CachedRef, and to :py:func:`~symscan.get_neighbors_across` otherwise. If `query` is a
+/// CachedRef and `reference` is None, :py:meth:`CachedRef.get_neighbors_within` is used.
+///
+/// Parameters
+/// ----------
+/// query : iterable of str or CachedRef
+/// reference : iterable of str or CachedRef, optional
+/// max_distance : int, default=1
+///     The maximum edit distance at which strings are considered neighbors.
+/// **kwargs
+///     Further keyword arguments (e.g. `n_threads`, `return_similarity`, `output`) are passed on
+///     to the function or method dispatched to.
+///
+/// Returns
+/// -------
+/// row, col, dists : ndarray of shape (N,)
+///     As returned by the function or method dispatched to.
+///
+/// Examples
+/// --------
+/// Without a reference, neighbors are searched for within the query.
+///
+/// >>> import symscan
+/// >>> (row, col, dists) = symscan.search(["fizz", "fuzz", "buzz"])
+/// >>> row
+/// array([0, 1], dtype=uint32)
+/// >>> col
+/// array([1, 2], dtype=uint32)
+/// >>> dists
+/// array([1, 1], dtype=uint8)
+///
+/// With a reference, neighbors are searched for across the query and reference.
+///
+/// >>> (row, col, dists) = symscan.search(["fizz", "fuzz", "buzz"], ["fooo", "barr", "bazz", "buzz"])
+/// >>> row
+/// array([1, 2, 2], dtype=uint32)
+/// >>> col
+/// array([3, 2, 3], dtype=uint32)
+/// >>> dists
+/// array([1, 1, 0], dtype=uint8)
+///
+/// The reference may also be a CachedRef, and further options are passed through.
+///
+/// >>> cached = symscan.CachedRef(["fooo", "barr", "bazz", "buzz"], max_distance=2)
+/// >>> (row, col, dists) = symscan.search(["fizz", "fuzz", "buzz"], cached, max_distance=2, n_threads=1)
+/// >>> row
+/// array([0, 0, 1, 1, 2, 2], dtype=uint32)
+/// >>> col
+/// array([2, 3, 2, 3, 2, 3], dtype=uint32)
+/// >>> dists
+/// array([2, 2, 2, 1, 1, 0], dtype=uint8)
+#[pyfunction]
+#[pyo3(signature = (query, reference = None, max_distance = 1, **kwargs))]
+fn search<'py>(
+    py: Python<'py>,
+    query: &Bound<'py, PyAny>,
+    reference: Option<&Bound<'py, PyAny>>,
+    max_distance: u16,
+    kwargs: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyAny>> {
+    match reference {
+        None if query.is_instance_of::<CachedRef>() => {
+            query.call_method("get_neighbors_within", (max_distance,), kwargs)
+        }
+        None => wrap_pyfunction!(get_neighbors_within, py)?.call((query, max_distance), kwargs),
+        Some(reference) if reference.is_instance_of::<CachedRef>() => {
+            reference.call_method("get_neighbors_across", (query, max_distance), kwargs)
+        }
+        Some(reference) => wrap_pyfunction!(get_neighbors_across, py)?
+            .call((query, reference, max_distance), kwargs),
+    }
+}
+
+/// Compute the Levenshtein distances between aligned pairs of strings.
+///
+/// Unlike :py:func:`~symscan.get_neighbors_across`, this does not search for neighbors, but
+/// simply computes the distance between ``a[i]`` and ``b[i]`` for each `i`. The computation runs
+/// in parallel with the GIL released.
+///
+/// Parameters
+/// ----------
+/// a : iterable of str
+/// b : iterable of str
+///     Must be of the same length as `a`.
+/// score_cutoff : int, optional
+///     If set, distances greater than `score_cutoff` are reported as ``score_cutoff + 1``, which
+///     allows computations on dissimilar pairs to terminate early.
+///
+/// Returns
+/// -------
+/// dists : ndarray of shape (N,), dtype=uint8, uint16, uint32 or uint64
+///     The distances between the string pairs. The dtype is the smallest that holds the largest
+///     distance possible, which is the length of the longest string given, or ``score_cutoff + 1``
+///     if lower. Distances are never clamped to fit the dtype.
+///
+/// Examples
+/// --------
+/// >>> import symscan
+/// >>> symscan.pairwise_distance(["fizz", "fuzz", "buzz"], ["fizz", "fooo", "fuzzy"])
+/// array([0, 3, 2], dtype=uint8)
+/// >>> symscan.pairwise_distance(["fizz", "fuzz", "buzz"], ["fizz", "fooo", "fuzzy"], score_cutoff=1)
+/// array([0, 2, 2], dtype=uint8)
+#[pyfunction]
+#[pyo3(signature = (a, b, score_cutoff = None))]
+fn pairwise_distance<'py>(
+    py: Python<'py>,
+    a: &Bound<'py, PyAny>,
+    b: &Bound<'py, PyAny>,
+    score_cutoff: Option<usize>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let a_handles = get_pystring_handles(a)?;
+    let a_views = get_str_refs(&a_handles)?;
+    let b_handles = get_pystring_handles(b)?;
+    let b_views = get_str_refs(&b_handles)?;
+
+    let dists = py
+        .detach(|| pairwise_dist_array(&a_views, &b_views, score_cutoff))
+        .map_err(to_pyerr)?;
+
+    Ok(dists.into_pyarray(py))
+}
+
+/// Compute the distances of [`pairwise_distance`], in the smallest dtype that holds the largest
+/// distance possible.
+fn pairwise_dist_array(
+    a: &[&str],
+    b: &[&str],
+    score_cutoff: Option<usize>,
+) -> Result<DistArray, symscan::Error> {
+    let dists = symscan::pairwise_distances(a, b, score_cutoff)?;
+
+    // The distance between two strings never exceeds the length of the longer one.
+    let max_len = a.iter().chain(b).map(|s| s.len()).max().unwrap_or(0);
+    let bound = match score_cutoff {
+        Some(cutoff) => max_len.min(cutoff.saturating_add(1)),
+        None => max_len,
+    };
+
+    Ok(DistArray::new(dists, bound))
+}
+
+/// Distances narrowed to the smallest unsigned integer type holding a given bound on them.
+#[derive(Debug, PartialEq)]
+enum DistArray {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+    U64(Vec<u64>),
+}
+
+impl DistArray {
+    fn new(dists: Vec<usize>, bound: usize) -> Self {
+        debug_assert!(dists.iter().all(|&d| d <= bound));
+        fn narrow<T: TryFrom<usize>>(dists: Vec<usize>) -> Vec<T> {
+            dists
+                .into_iter()
+                .map(|d| T::try_from(d).ok().expect("distance within bound"))
+                .collect()
+        }
+        if u8::try_from(bound).is_ok() {
+            DistArray::U8(narrow(dists))
+        } else if u16::try_from(bound).is_ok() {
+            DistArray::U16(narrow(dists))
+        } else if u32::try_from(bound).is_ok() {
+            DistArray::U32(narrow(dists))
+        } else {
+            DistArray::U64(narrow(dists))
+        }
+    }
+
+    fn into_pyarray(self, py: Python<'_>) -> Bound<'_, PyAny> {
+        match self {
+            DistArray::U8(dists) => dists.into_pyarray(py).into_any(),
+            DistArray::U16(dists) => dists.into_pyarray(py).into_any(),
+            DistArray::U32(dists) => dists.into_pyarray(py).into_any(),
+            DistArray::U64(dists) => dists.into_pyarray(py).into_any(),
+        }
+    }
+}
+
+/// The awaitable equivalent of :py:func:`symscan.get_neighbors_within`.
+///
+/// The search runs on a background thread, so that the event loop remains free to serve other
+/// tasks in the meantime. It must be called from within a running event loop. Invalid inputs
+/// raise immediately, rather than when awaited. Cancelling the returned future aborts the search.
+///
+/// Parameters
+/// ----------
+/// query : iterable of str
+/// max_distance : int, default=1
+///     The maximum edit distance at which strings are considered neighbors.
+/// n_threads : int, optional
+///     The number of threads to use. See :py:func:`symscan.get_neighbors_within`.
+///
+/// Returns
+/// -------
+/// asyncio.Future
+///     A future resolving to ``(row, col, dists)``, as returned by
+///     :py:func:`symscan.get_neighbors_within`.
+///
+/// Examples
+/// --------
+/// >>> import asyncio
+/// >>> import symscan.aio
+/// >>> (row, col, dists) = asyncio.run(symscan.aio.get_neighbors_within(["fizz", "fuzz", "buzz"]))
+/// >>> row
+/// array([0, 1], dtype=uint32)
+/// >>> col
+/// array([1, 2], dtype=uint32)
+/// >>> dists
+/// array([1, 1], dtype=uint8)
+#[pyfunction]
+#[pyo3(name = "get_neighbors_within", signature = (query, max_distance = 1, n_threads = None))]
+fn aio_get_neighbors_within<'py>(
+    py: Python<'py>,
+    query: &Bound<'py, PyAny>,
+    max_distance: u16,
+    n_threads: Option<i64>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let query = unbind_all(get_pystring_handles(query)?);
+    let pool = build_thread_pool(n_threads)?;
+
+    spawn_future(py, move |py, options| {
+        let query_handles = bind_all(py, &query);
+        let query_views = get_str_refs(&query_handles)?;
+        let pool = pool.as_ref();
+        let pairs = if max_distance > MAX_NARROW_DISTANCE {
+            detached_search::<Wide>(py, pool, &query_views, None, max_distance, options)?
+        } else if needs_wide_indices(&[query_views.len()]) {
+            detached_search::<Large>(py, pool, &query_views, None, max_distance, options)?
+        } else {
+            detached_search::<Regular>(py, pool, &query_views, None, max_distance, options)?
+        };
+        Ok(pairs.into_any().unbind())
+    })
+}
+
+/// The awaitable equivalent of :py:func:`symscan.get_neighbors_across`.
+///
+/// See :py:func:`symscan.aio.get_neighbors_within` for how the search is run.
+///
+/// Parameters
+/// ----------
+/// query : iterable of str
+/// reference : iterable of str or CachedRef
+///     If a :py:class:`~symscan.CachedRef` is passed, its cached deletion variants are used
+///     instead of being recomputed.
+/// max_distance : int, default=1
+///     The maximum edit distance at which strings are considered neighbors.
+/// n_threads : int, optional
+///     The number of threads to use. See :py:func:`symscan.get_neighbors_within`.
+///
+/// Returns
+/// -------
+/// asyncio.Future
+///     A future resolving to ``(row, col, dists)``, as returned by
+///     :py:func:`symscan.get_neighbors_across`.
+///
+/// Examples
+/// --------
+/// >>> import asyncio
+/// >>> import symscan.aio
+/// >>> cached = symscan.CachedRef(["fooo", "barr", "bazz", "buzz"])
+/// >>> (row, col, dists) = asyncio.run(symscan.aio.get_neighbors_across(["fizz", "fuzz", "buzz"], cached))
+/// >>> row
+/// array([1, 2, 2], dtype=uint32)
+/// >>> col
+/// array([3, 2, 3], dtype=uint32)
+/// >>> dists
+/// array([1, 1, 0], dtype=uint8)
+#[pyfunction]
+#[pyo3(
+    name = "get_neighbors_across",
+    signature = (query, reference, max_distance = 1, n_threads = None)
+)]
+fn aio_get_neighbors_across<'py>(
+    py: Python<'py>,
+    query: &Bound<'py, PyAny>,
+    reference: &Bound<'py, PyAny>,
+    max_distance: u16,
+    n_threads: Option<i64>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let query = unbind_all(get_pystring_handles(query)?);
+    let reference = match reference.cast::<CachedRef>() {
+        Ok(cached) => Err(cached.clone().unbind()),
+        Err(_) => Ok(unbind_all(get_pystring_handles(reference)?)),
+    };
+    let pool = build_thread_pool(n_threads)?;
+
+    spawn_future(py, move |py, options| {
+        let query_handles = bind_all(py, &query);
+        let query_views = get_str_refs(&query_handles)?;
+        let pool = pool.as_ref();
+        let pairs = match &reference {
+            Ok(reference) => {
+                let ref_handles = bind_all(py, reference);
+                let ref_views = get_str_refs(&ref_handles)?;
+                let reference = Some(&ref_views[..]);
+                if max_distance > MAX_NARROW_DISTANCE {
+                    detached_search::<Wide>(
+                        py,
+                        pool,
+                        &query_views,
+                        reference,
+                        max_distance,
+                        options,
+                    )?
+                } else if needs_wide_indices(&[query_views.len(), ref_views.len()]) {
+                    detached_search::<Large>(
+                        py,
+                        pool,
+                        &query_views,
+                        reference,
+                        max_distance,
+                        options,
+                    )?
+                } else {
+                    detached_search::<Regular>(
+                        py,
+                        pool,
+                        &query_views,
+                        reference,
+                        max_distance,
+                        options,
+                    )?
+                }
+            }
+            Err(cached) => {
+                let max_distance = cached_max_distance(max_distance)?;
+                let cached = cached.bind(py).borrow();
+                let internal = &cached.internal;
+                let pairs = py
+                    .detach(|| {
+                        install(pool, || {
+                            internal.get_neighbors_across_with_options(
+                                &query_views,
+                                max_distance,
+                                options,
+                            )
+                        })
+                    })
+                    .map_err(to_pyerr)?;
+                neighbor_pairs_into_pytuple(py, pairs, None)?
+            }
+        };
+        Ok(pairs.into_any().unbind())
+    })
+}
+
+/// The edit distance metric selected via the `metric` parameter, given either by name or as a
+/// tuple of (insertion, deletion, substitution) costs for the weighted Levenshtein distance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Metric(symscan::Metric);
+
+impl Metric {
+    const LEVENSHTEIN: Metric = Metric(symscan::Metric::Levenshtein);
+
+    const NAMED: [(&'static str, symscan::Metric); 4] = [
+        ("levenshtein", symscan::Metric::Levenshtein),
+        ("hamming", symscan::Metric::Hamming),
+        ("osa", symscan::Metric::Osa),
+        ("indel", symscan::Metric::Indel),
+    ];
+
+    fn parse(name: &str) -> PyResult<Self> {
+        Self::NAMED
+            .into_iter()
+            .find(|&(known, _)| known == name)
+            .map(|(_, metric)| Metric(metric))
+            .ok_or_else(|| {
+                let options = Self::NAMED
+                    .map(|(known, _)| format!("'{known}'"))
+                    .join(", ");
+                PyValueError::new_err(format!(
+                    "metric must be one of {options} or a tuple of (insertion, deletion, \
+                     substitution) costs, got '{name}'"
+                ))
+            })
+    }
+
+    /// The metric as it would be passed to the `metric` parameter.
+    fn to_object<'py>(self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        match self.0 {
+            symscan::Metric::WeightedLevenshtein(costs) => {
+                (costs.insertion(), costs.deletion(), costs.substitution()).into_bound_py_any(py)
+            }
+            metric => Self::NAMED
+                .into_iter()
+                .find(|&(_, known)| known == metric)
+                .map_or("unknown", |(name, _)| name)
+                .into_bound_py_any(py),
+        }
+    }
+
+    fn describe(self) -> String {
+        match self.0 {
+            symscan::Metric::WeightedLevenshtein(costs) => format!(
+                "({}, {}, {})",
+                costs.insertion(),
+                costs.deletion(),
+                costs.substitution()
+            ),
+            metric => Self::NAMED
+                .into_iter()
+                .find(|&(_, known)| known == metric)
+                .map_or("unknown".to_string(), |(name, _)| format!("'{name}'")),
+        }
+    }
+
+    /// The largest possible distance under this metric between a row string of length `row_len`
+    /// and a column string of length `col_len`, by which similarities are normalized.
+    fn max_dist(self, row_len: usize, col_len: usize) -> usize {
+        match self.0 {
+            symscan::Metric::Indel => row_len + col_len,
+            symscan::Metric::WeightedLevenshtein(costs) => {
+                let (ins, del, sub) = (
+                    costs.insertion() as usize,
+                    costs.deletion() as usize,
+                    costs.substitution() as usize,
+                );
+                // Either delete the whole row string and insert the whole column string, or
+                // substitute the common length and insert or delete the rest.
+                let via_indels = row_len * del + col_len * ins;
+                let via_substitutions = if row_len >= col_len {
+                    col_len * sub + (row_len - col_len) * del
+                } else {
+                    row_len * sub + (col_len - row_len) * ins
+                };
+                via_indels.min(via_substitutions)
+            }
+            _ => row_len.max(col_len),
+        }
+    }
+
+    /// Check that a CachedRef built for this metric is being queried with `requested` (if given),
+    /// returning the metric to query with.
+    fn check_query(self, requested: Option<Metric>) -> PyResult<Metric> {
+        match requested {
+            Some(requested) if requested != self => Err(PyValueError::new_err(format!(
+                "CachedRef instance was constructed for metric {}, got {}",
+                self.describe(),
+                requested.describe()
+            ))),
+            _ => Ok(self),
+        }
+    }
+}
+
+impl<'py> FromPyObject<'_, 'py> for Metric {
+    type Error = PyErr;
+
+    fn extract(obj: Borrowed<'_, 'py, PyAny>) -> PyResult<Self> {
+        if let Ok(name) = obj.cast::<PyString>() {
+            return Self::parse(name.to_str()?);
+        }
+        if let Ok((insertion, deletion, substitution)) = obj.extract::<(u8, u8, u8)>() {
+            let costs =
+                symscan::EditCosts::new(insertion, deletion, substitution).map_err(to_pyerr)?;
+            return Ok(Metric(symscan::Metric::WeightedLevenshtein(costs)));
+        }
+        Err(PyTypeError::new_err(
+            "metric must be a str or a tuple of (insertion, deletion, substitution) costs",
+        ))
+    }
+}
+
+/// Input collections larger than this exceed the limits of the regular entry points of the core
+/// (and would produce indices beyond the int32 range, which some downstream consumers such as
+/// scipy.sparse cannot handle in uint32 form), so they are searched through its `_large` entry
+/// points instead.
+const WIDE_INDEX_THRESHOLD: usize = (1 << 31) - 1;
+
+/// Whether input collections of the given sizes must be searched through the `_large` entry
+/// points of the core, which return uint64 rather than uint32 indices.
+fn needs_wide_indices(input_lens: &[usize]) -> bool {
+    input_lens.iter().any(|&n| n > WIDE_INDEX_THRESHOLD)
+}
+
+/// The index type of neighbor pairs: u32 for the pairs returned by the regular entry points of the
+/// core, and u64 for those returned by its `_large` entry points.
+trait PairIndex: numpy::Element + Copy + Send {
+    /// Convert a position within an input collection, which must fit within this type.
+    fn from_usize(i: usize) -> Self;
+
+    fn to_usize(self) -> usize;
+
+    /// Move the indices into an Arrow array, without copying.
+    fn into_arrow(indices: Vec<Self>) -> ArrayRef;
+}
+
+impl PairIndex for u32 {
+    fn from_usize(i: usize) -> Self {
+        i as u32
+    }
+
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+
+    fn into_arrow(indices: Vec<Self>) -> ArrayRef {
+        Arc::new(UInt32Array::from(indices))
+    }
+}
+
+impl PairIndex for u64 {
+    fn from_usize(i: usize) -> Self {
+        i as u64
+    }
+
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+
+    fn into_arrow(indices: Vec<Self>) -> ArrayRef {
+        Arc::new(UInt64Array::from(indices))
+    }
+}
+
+/// The distance type of neighbor pairs: u8 for the pairs returned by most entry points of the
+/// core, and u16 for those returned by its `_wide` entry points.
+trait PairDist: numpy::Element + Copy + Send + Into<f32> {
+    /// Move the distances into an Arrow array, without copying.
+    fn into_arrow(dists: Vec<Self>) -> ArrayRef;
+}
+
+impl PairDist for u8 {
+    fn into_arrow(dists: Vec<Self>) -> ArrayRef {
+        Arc::new(UInt8Array::from(dists))
+    }
+}
+
+impl PairDist for u16 {
+    fn into_arrow(dists: Vec<Self>) -> ArrayRef {
+        Arc::new(UInt16Array::from(dists))
+    }
+}
+
+/// Views of the row indices, column indices and distances of neighbor pairs.
+type PairSlices<'a, I, D> = (&'a [I], &'a [I], &'a [D]);
+
+/// The row indices, column indices and distances of neighbor pairs.
+type PairVecs<I, D> = (Vec<I>, Vec<I>, Vec<D>);
+
+/// Neighbor pairs as returned by the core, so that [`symscan::NeighborPairs`],
+/// [`symscan::LargeNeighborPairs`] and [`symscan::WideNeighborPairs`] share their conversions into
+/// outputs.
+trait Pairs: Send {
+    type Index: PairIndex;
+
+    type Dist: PairDist;
+
+    fn parts(&self) -> PairSlices<'_, Self::Index, Self::Dist>;
+
+    fn indices_mut(&mut self) -> (&mut [Self::Index], &mut [Self::Index]);
+
+    fn into_parts(self) -> PairVecs<Self::Index, Self::Dist>;
+}
+
+impl Pairs for symscan::NeighborPairs {
+    type Index = u32;
+
+    type Dist = u8;
+
+    fn parts(&self) -> (&[u32], &[u32], &[u8]) {
+        (&self.row, &self.col, &self.dists)
+    }
+
+    fn indices_mut(&mut self) -> (&mut [u32], &mut [u32]) {
+        (&mut self.row, &mut self.col)
+    }
+
+    fn into_parts(self) -> (Vec<u32>, Vec<u32>, Vec<u8>) {
+        (self.row, self.col, self.dists)
+    }
+}
+
+impl Pairs for symscan::WideNeighborPairs {
+    type Index = u32;
+
+    type Dist = u16;
+
+    fn parts(&self) -> (&[u32], &[u32], &[u16]) {
+        (&self.row, &self.col, &self.dists)
+    }
+
+    fn indices_mut(&mut self) -> (&mut [u32], &mut [u32]) {
+        (&mut self.row, &mut self.col)
+    }
+
+    fn into_parts(self) -> (Vec<u32>, Vec<u32>, Vec<u16>) {
+        (self.row, self.col, self.dists)
+    }
+}
+
+impl Pairs for symscan::LargeNeighborPairs {
+    type Index = u64;
+
+    type Dist = u8;
+
+    fn parts(&self) -> (&[u64], &[u64], &[u8]) {
+        (&self.row, &self.col, &self.dists)
+    }
+
+    fn indices_mut(&mut self) -> (&mut [u64], &mut [u64]) {
+        (&mut self.row, &mut self.col)
+    }
+
+    fn into_parts(self) -> (Vec<u64>, Vec<u64>, Vec<u8>) {
+        (self.row, self.col, self.dists)
+    }
+}
+
+/// The entry points of the core for inputs of up to [`WIDE_INDEX_THRESHOLD`] strings, which
+/// return u32 indices.
+struct Regular;
+
+/// The `_large` entry points of the core, which search inputs of any size and return u64 indices.
+struct Large;
+
+/// The `_wide` entry points of the core, which search thresholds beyond [`MAX_NARROW_DISTANCE`]
+/// and return u16 distances.
+struct Wide;
+
+/// The largest `max_distance` that the entry points of the core returning u8 distances accept.
+const MAX_NARROW_DISTANCE: u16 = (u8::MAX - 1) as u16;
+
+/// Narrow a `max_distance` for the entry points of the core returning u8 distances. Thresholds
+/// that do not fit are saturated, so that the core rejects them as it would any other threshold
+/// beyond its cap.
+fn narrow_max_distance(max_distance: u16) -> u8 {
+    u8::try_from(max_distance).unwrap_or(u8::MAX)
+}
+
+/// Narrow a `max_distance` for a search against a CachedRef, whose cached deletion variants cannot
+/// reach beyond [`MAX_NARROW_DISTANCE`].
+fn cached_max_distance(max_distance: u16) -> PyResult<u8> {
+    if max_distance <= MAX_NARROW_DISTANCE {
+        return Ok(narrow_max_distance(max_distance));
+    }
+    Python::attach(|py| {
+        let err = MaxDistanceError::new_err(format!(
+            "CachedRef instances support max_distance up to {MAX_NARROW_DISTANCE}, got \
+             {max_distance} (pass the strings instead for larger thresholds)"
+        ));
+        let value = err.value(py);
+        value.setattr("got", max_distance)?;
+        value.setattr("limit", MAX_NARROW_DISTANCE)?;
+        Err(err)
+    })
+}
+
+trait EntryPoints {
+    type Pairs: Pairs;
+
+    fn within(
+        query: &[&str],
+        max_distance: u16,
+        options: &symscan::SearchOptions,
+    ) -> Result<Self::Pairs, symscan::Error>;
+
+    fn across(
+        query: &[&str],
+        reference: &[&str],
+        max_distance: u16,
+        options: &symscan::SearchOptions,
+    ) -> Result<Self::Pairs, symscan::Error>;
+}
+
+impl EntryPoints for Regular {
+    type Pairs = symscan::NeighborPairs;
+
+    fn within(
+        query: &[&str],
+        max_distance: u16,
+        options: &symscan::SearchOptions,
+    ) -> Result<Self::Pairs, symscan::Error> {
+        let max_distance = narrow_max_distance(max_distance);
+        symscan::get_neighbors_within_with_options(query, max_distance, options)
+    }
+
+    fn across(
+        query: &[&str],
+        reference: &[&str],
+        max_distance: u16,
+        options: &symscan::SearchOptions,
+    ) -> Result<Self::Pairs, symscan::Error> {
+        let max_distance = narrow_max_distance(max_distance);
+        symscan::get_neighbors_across_with_options(query, reference, max_distance, options)
+    }
+}
+
+impl EntryPoints for Large {
+    type Pairs = symscan::LargeNeighborPairs;
+
+    fn within(
+        query: &[&str],
+        max_distance: u16,
+        options: &symscan::SearchOptions,
+    ) -> Result<Self::Pairs, symscan::Error> {
+        let max_distance = narrow_max_distance(max_distance);
+        symscan::get_neighbors_within_large_with_options(query, max_distance, options)
+    }
+
+    fn across(
+        query: &[&str],
+        reference: &[&str],
+        max_distance: u16,
+        options: &symscan::SearchOptions,
+    ) -> Result<Self::Pairs, symscan::Error> {
+        let max_distance = narrow_max_distance(max_distance);
+        symscan::get_neighbors_across_large_with_options(query, reference, max_distance, options)
+    }
+}
+
+impl EntryPoints for Wide {
+    type Pairs = symscan::WideNeighborPairs;
+
+    fn within(
+        query: &[&str],
+        max_distance: u16,
+        options: &symscan::SearchOptions,
+    ) -> Result<Self::Pairs, symscan::Error> {
+        symscan::get_neighbors_within_wide_with_options(query, max_distance, options)
+    }
+
+    fn across(
+        query: &[&str],
+        reference: &[&str],
+        max_distance: u16,
+        options: &symscan::SearchOptions,
+    ) -> Result<Self::Pairs, symscan::Error> {
+        symscan::get_neighbors_across_wide_with_options(query, reference, max_distance, options)
+    }
+}
+
+/// Widen the indices of neighbor pairs to u64.
+fn widen_neighbor_pairs(pairs: symscan::NeighborPairs) -> symscan::LargeNeighborPairs {
+    let (row, col, dists) = pairs.into_parts();
+    symscan::LargeNeighborPairs {
+        row: row.into_iter().map(u64::from).collect(),
+        col: col.into_iter().map(u64::from).collect(),
+        dists,
+    }
+}
+
+/// Convert indices from a search against a CachedRef into an array, widened to uint64 if `wide`.
+fn index_array(py: Python<'_>, indices: Vec<u32>, wide: bool) -> Bound<'_, PyAny> {
+    if wide {
+        let indices: Vec<u64> = indices.into_iter().map(u64::from).collect();
+        indices.into_pyarray(py).into_any()
+    } else {
+        indices.into_pyarray(py).into_any()
+    }
+}
+
+/// Convert neighbor pairs from a search against a CachedRef into the requested output. A CachedRef
+/// can hold more strings than [`WIDE_INDEX_THRESHOLD`] while the core still returns u32 indices,
+/// so these are widened to u64 if any of the inputs (of sizes `input_lens`) exceeds it, as they
+/// would be for any other search.
+fn cached_pairs_into_output<'py>(
+    py: Python<'py>,
+    pairs: symscan::NeighborPairs,
+    input_lens: &[usize],
+    similarities: Option<Vec<f32>>,
+    output: Output,
+    keys: (Option<&Keys>, Option<&Keys>),
+) -> PyResult<Bound<'py, PyAny>> {
+    if needs_wide_indices(input_lens) {
+        let pairs = widen_neighbor_pairs(pairs);
+        neighbor_pairs_into_output(py, pairs, similarities, output, keys)
+    } else {
+        neighbor_pairs_into_output(py, pairs, similarities, output, keys)
+    }
+}
+
+/// Neighbor pairs accumulated over the chunks of a query searched against a CachedRef, whose
+/// indices are widened to u64 once the query (or the CachedRef) holds more than
+/// [`WIDE_INDEX_THRESHOLD`] strings.
+enum ChunkedNeighborPairs {
+    Narrow(symscan::NeighborPairs),
+    Wide(symscan::LargeNeighborPairs),
+}
+
+impl ChunkedNeighborPairs {
+    /// Start accumulating the neighbor pairs of a query searched against a CachedRef of
+    /// `num_refs` strings.
+    fn new(num_refs: usize) -> Self {
+        let pairs = symscan::NeighborPairs::default();
+        if needs_wide_indices(&[num_refs]) {
+            ChunkedNeighborPairs::Wide(widen_neighbor_pairs(pairs))
+        } else {
+            ChunkedNeighborPairs::Narrow(pairs)
+        }
+    }
+
+    /// Append the neighbor pairs of a chunk of `chunk_len` query strings, offsetting their rows by
+    /// the `offset` query strings before the chunk.
+    fn extend(&mut self, hits: symscan::NeighborPairs, offset: usize, chunk_len: usize) {
+        if let ChunkedNeighborPairs::Narrow(pairs) = self {
+            if needs_wide_indices(&[offset + chunk_len]) {
+                *self = ChunkedNeighborPairs::Wide(widen_neighbor_pairs(std::mem::take(pairs)));
+            }
+        }
+
+        match self {
+            ChunkedNeighborPairs::Narrow(pairs) => {
+                let offset = offset as u32;
+                pairs
+                    .row
+                    .extend(hits.row.into_iter().map(|row| row + offset));
+                pairs.col.extend(hits.col);
+                pairs.dists.extend(hits.dists);
+            }
+            ChunkedNeighborPairs::Wide(pairs) => {
+                let offset = offset as u64;
+                pairs
+                    .row
+                    .extend(hits.row.into_iter().map(|row| u64::from(row) + offset));
+                pairs.col.extend(hits.col.into_iter().map(u64::from));
+                pairs.dists.extend(hits.dists);
+            }
+        }
+    }
+
+    fn into_pytuple(self, py: Python<'_>) -> PyResult<Bound<'_, PyTuple>> {
+        match self {
+            ChunkedNeighborPairs::Narrow(pairs) => neighbor_pairs_into_pytuple(py, pairs, None),
+            ChunkedNeighborPairs::Wide(pairs) => neighbor_pairs_into_pytuple(py, pairs, None),
+        }
+    }
+}
+
+fn neighbor_pairs_into_pytuple<P: Pairs>(
+    py: Python<'_>,
+    pairs: P,
+    similarities: Option<Vec<f32>>,
+) -> PyResult<Bound<'_, PyTuple>> {
+    let (row, col, dists) = pairs.into_parts();
+    let mut arrays = vec![
+        row.into_pyarray(py).into_any(),
+        col.into_pyarray(py).into_any(),
+        dists.into_pyarray(py).into_any(),
+    ];
+    if let Some(similarities) = similarities {
+        arrays.push(similarities.into_pyarray(py).into_any());
+    }
+    PyTuple::new(py, arrays)
+}
+
+/// Formats in which neighbor pairs across a query and reference can be returned, selected via the
+/// `output` parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Output {
+    Numpy,
+    Arrow,
+}
+
+impl Output {
+    const ALL: [Output; 2] = [Output::Numpy, Output::Arrow];
+
+    fn name(self) -> &'static str {
+        match self {
+            Output::Numpy => "numpy",
+            Output::Arrow => "arrow",
+        }
+    }
+
+    fn parse(name: &str) -> PyResult<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|output| output.name() == name)
+            .ok_or_else(|| {
+                let options = Self::ALL
+                    .map(|output| format!("'{}'", output.name()))
+                    .join(", ");
+                PyValueError::new_err(format!("output must be one of {options}, got '{name}'"))
+            })
+    }
+}
+
+/// Policies for missing values in input string collections, selected via the `missing` parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Missing {
+    Raise,
+    Skip,
+}
+
+impl Missing {
+    const ALL: [Missing; 2] = [Missing::Raise, Missing::Skip];
+
+    fn name(self) -> &'static str {
+        match self {
+            Missing::Raise => "raise",
+            Missing::Skip => "skip",
+        }
+    }
+
+    fn parse(name: &str) -> PyResult<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|missing| missing.name() == name)
+            .ok_or_else(|| {
+                let options = Self::ALL
+                    .map(|missing| format!("'{}'", missing.name()))
+                    .join(", ");
+                PyValueError::new_err(format!("missing must be one of {options}, got '{name}'"))
+            })
+    }
+}
+
+/// Convert neighbor pairs into the requested output format. If the query and / or reference were
+/// given as mappings, their `keys` replace the corresponding index arrays.
+fn neighbor_pairs_into_output<'py, P: Pairs>(
+    py: Python<'py>,
+    pairs: P,
+    similarities: Option<Vec<f32>>,
+    output: Output,
+    keys: (Option<&Keys>, Option<&Keys>),
+) -> PyResult<Bound<'py, PyAny>> {
+    match (output, keys) {
+        (Output::Numpy, (None, None)) => {
+            Ok(neighbor_pairs_into_pytuple(py, pairs, similarities)?.into_any())
+        }
+        (Output::Numpy, (row_keys, col_keys)) => {
+            let (row, col, dists) = pairs.into_parts();
+            let keys_or_indices = |indices: Vec<P::Index>, keys: Option<&Keys>| match keys {
+                Some(keys) => keys.take(py, &indices),
+                None => indices.into_pyarray(py).into_any(),
+            };
+            let mut arrays = vec![
+                keys_or_indices(row, row_keys),
+                keys_or_indices(col, col_keys),
+                dists.into_pyarray(py).into_any(),
+            ];
+            if let Some(similarities) = similarities {
+                arrays.push(similarities.into_pyarray(py).into_any());
+            }
+            Ok(PyTuple::new(py, arrays)?.into_any())
+        }
+        (Output::Arrow, (None, None)) => {
+            let pyarrow = py.import("pyarrow").map_err(|e| {
+                let err = PyImportError::new_err("output='arrow' requires pyarrow to be installed");
+                err.set_cause(py, Some(e));
+                err
+            })?;
+            let batch = ArrowBatch(neighbor_pairs_into_record_batch(pairs, similarities));
+            pyarrow.call_method1("table", (batch,))
+        }
+        (Output::Arrow, _) => Err(PyValueError::new_err(
+            "output='arrow' is not supported with mapping inputs",
+        )),
+    }
+}
+
+/// Swap the roles of the rows and columns of neighbor pairs (and their similarities), keeping the
+/// pairs sorted by row and then column.
+fn transpose_neighbor_pairs(
+    pairs: symscan::NeighborPairs,
+    similarities: Option<Vec<f32>>,
+) -> (symscan::NeighborPairs, Option<Vec<f32>>) {
+    let symscan::NeighborPairs { row, col, dists } = pairs;
+    let mut order: Vec<usize> = (0..row.len()).collect();
+    order.sort_unstable_by_key(|&i| (col[i], row[i]));
+
+    let transposed = symscan::NeighborPairs {
+        row: order.iter().map(|&i| col[i]).collect(),
+        col: order.iter().map(|&i| row[i]).collect(),
+        dists: order.iter().map(|&i| dists[i]).collect(),
+    };
+    let similarities = similarities.map(|s| order.iter().map(|&i| s[i]).collect());
+
+    (transposed, similarities)
+}
+
+/// The keys of a mapping passed in place of a string collection, in insertion order.
+enum Keys {
+    Int(Vec<i64>),
+    Object(Vec<Py<PyAny>>),
+}
+
+impl Keys {
+    /// Look up the keys at the given positional indices.
+    fn take<'py>(&self, py: Python<'py>, indices: &[impl PairIndex]) -> Bound<'py, PyAny> {
+        match self {
+            Keys::Int(keys) => {
+                let taken: Vec<i64> = indices.iter().map(|&i| keys[i.to_usize()]).collect();
+                taken.into_pyarray(py).into_any()
+            }
+            Keys::Object(keys) => {
+                let taken: Vec<Py<PyAny>> = indices
+                    .iter()
+                    .map(|&i| keys[i.to_usize()].clone_ref(py))
+                    .collect();
+                taken.into_pyarray(py).into_any()
+            }
+        }
+    }
+}
+
+/// If `input` is a mapping, split it into its keys and a list of its values, which then stand in
+/// for the input string collection.
+fn split_mapping<'py>(input: &Bound<'py, PyAny>) -> PyResult<Option<(Keys, Bound<'py, PyList>)>> {
+    let Ok(mapping) = input.cast::<PyMapping>() else {
+        return Ok(None);
+    };
+    let py = input.py();
+
+    let items = mapping.items()?;
+    let mut keys = Vec::with_capacity(items.len());
+    let values = PyList::empty(py);
+    let seen = PySet::empty(py)?;
+    for item in items.iter() {
+        let (key, value) = item.extract::<(Bound<'py, PyAny>, Bound<'py, PyAny>)>()?;
+        if seen.contains(&key)? {
+            return Err(PyValueError::new_err(format!(
+                "mapping keys must be unique, got duplicate key {}",
+                key.repr()?
+            )));
+        }
+        seen.add(&key)?;
+        keys.push(key);
+        values.append(value)?;
+    }
+
+    let int_keys = keys
+        .iter()
+        .map(|key| {
+            key.is_exact_instance_of::<PyInt>()
+                .then(|| key.extract::<i64>().ok())
+                .flatten()
+        })
+        .collect::<Option<Vec<_>>>();
+    let keys = match int_keys {
+        Some(int_keys) => Keys::Int(int_keys),
+        None => Keys::Object(keys.into_iter().map(Bound::unbind).collect()),
+    };
+
+    Ok(Some((keys, values)))
+}
+
+/// Convert neighbor pairs into an Arrow record batch with columns `query`, `reference`, `dist`
+/// (and `similarity`). The vectors are moved into the Arrow buffers without copying.
+fn neighbor_pairs_into_record_batch<P: Pairs>(
+    pairs: P,
+    similarities: Option<Vec<f32>>,
+) -> RecordBatch {
+    let (row, col, dists) = pairs.into_parts();
+    let mut columns = vec![
+        ("query", P::Index::into_arrow(row)),
+        ("reference", P::Index::into_arrow(col)),
+        ("dist", P::Dist::into_arrow(dists)),
+    ];
+    if let Some(similarities) = similarities {
+        columns.push(("similarity", Arc::new(Float32Array::from(similarities))));
+    }
+
+    RecordBatch::try_from_iter(columns).expect("columns are of equal length")
+}
+
+/// Exports a record batch through the Arrow PyCapsule interface, so that it can be imported by
+/// pyarrow (or any other Arrow implementation) without copying.
+#[pyclass(module = "symscan", frozen)]
+struct ArrowBatch(RecordBatch);
+
+#[pymethods]
+impl ArrowBatch {
+    #[pyo3(signature = (requested_schema = None))]
+    fn __arrow_c_array__<'py>(
+        &self,
+        py: Python<'py>,
+        requested_schema: Option<Bound<'py, PyAny>>,
+    ) -> PyResult<(Bound<'py, PyCapsule>, Bound<'py, PyCapsule>)> {
+        // Requesting a schema is only a hint, which we are free to ignore.
+        let _ = requested_schema;
+        let data = StructArray::from(self.0.clone()).into_data();
+        let (array, schema) = arrow_array::ffi::to_ffi(&data)
+            .map_err(|e| PyRuntimeError::new_err(format!("failed to export to Arrow: {e}")))?;
+
+        // Dropping the capsule contents releases them, unless they have been moved out of by the
+        // consumer.
+        Ok((
+            PyCapsule::new(py, schema, Some(c"arrow_schema".to_owned()))?,
+            PyCapsule::new(py, array, Some(c"arrow_array".to_owned()))?,
+        ))
+    }
+}
+
+/// Compute the normalized similarity `1 - dist / max_dist` of each neighbor pair, where `max_dist`
+/// is the largest distance under `metric` between strings of the pair's lengths, given functions
+/// returning the lengths of the strings at each row and col index. Pairs of empty strings have a
+/// similarity of 1.
+fn get_similarities(
+    pairs: &impl Pairs,
+    metric: Metric,
+    row_len: impl Fn(usize) -> usize,
+    col_len: impl Fn(usize) -> usize,
+) -> Vec<f32> {
+    let (row, col, dists) = pairs.parts();
+    row.iter()
+        .zip(col)
+        .zip(dists)
+        .map(|((&row, &col), &dist)| {
+            let max_dist = metric.max_dist(row_len(row.to_usize()), col_len(col.to_usize()));
+            if max_dist == 0 {
+                1.0
+            } else {
+                1.0 - dist.into() / max_dist as f32
+            }
+        })
+        .collect()
+}
+
+/// Group neighbor pairs by row index, ordering the neighbors of each row by distance then column
+/// index.
+fn group_hits_by_row(
+    hits: symscan::NeighborPairs,
+    num_rows: usize,
+) -> (Vec<Vec<u32>>, Vec<Vec<u8>>) {
+    let mut grouped = vec![Vec::new(); num_rows];
+    for ((row, col), dist) in hits.row.into_iter().zip(hits.col).zip(hits.dists) {
+        grouped[row as usize].push((dist, col));
+    }
+
+    grouped
+        .into_iter()
+        .map(|mut neighbors| {
+            neighbors.sort_unstable();
+            neighbors.into_iter().map(|(d, c)| (c, d)).unzip()
+        })
+        .unzip()
+}
+
+/// Collect handles to the Python strings in an iterable.
+///
+/// Besides str (and subclasses thereof, such as numpy's ``np.str_``), this accepts bytes objects
+/// (including ``np.bytes_``) holding ASCII data, which are decoded into new str objects. pandas
+/// and polars Series are converted in bulk (see [`series_to_sequence`]) before iterating.
+fn get_pystring_handles<'py>(input: &Bound<'py, PyAny>) -> PyResult<Vec<Bound<'py, PyString>>> {
+    let (handles, _) = get_pystring_handles_with_missing(input, Missing::Raise)?;
+    Ok(handles)
+}
+
+type StringHandles<'py> = Vec<Bound<'py, PyString>>;
+
+/// The strings of an input collection, either held by Python string objects or read from the
+/// lines of a file.
+enum InputStrings<'py> {
+    Handles(StringHandles<'py>),
+    File { path: PathBuf, contents: Vec<u8> },
+}
+
+impl InputStrings<'_> {
+    /// Get views of the strings. The lines of a file are validated to be ASCII, with any error
+    /// reporting the path and (1-based) line number of the offending line.
+    fn views(&self, input_type: symscan::InputType) -> PyResult<Vec<&str>> {
+        match self {
+            InputStrings::Handles(handles) => get_str_refs(handles),
+            InputStrings::File { path, contents } => {
+                split_buffer_lines(contents, b"\n", input_type).map_err(|err| {
+                    Python::attach(|py| {
+                        if !err.is_instance_of::<NonAsciiInputError>(py) {
+                            return err;
+                        }
+                        let value = err.value(py);
+                        let line = value.getattr("index").and_then(|v| v.extract::<usize>());
+                        let string = value.getattr("value").and_then(|v| v.extract::<String>());
+                        if let (Ok(line), Ok(string)) = (line, string) {
+                            let msg = format!(
+                                "non-ASCII input currently unsupported ('{string}' at line {} of \
+                                 '{}')",
+                                line + 1,
+                                path.display()
+                            );
+                            let _ = value.setattr("args", (msg,));
+                            let _ = value.setattr("path", path);
+                        }
+                        err
+                    })
+                })
+            }
+        }
+    }
+}
+
+/// Whether an input is to be taken as the path to a file of strings rather than as a collection.
+fn is_path_like(input: &Bound<'_, PyAny>) -> PyResult<bool> {
+    Ok(input.cast::<PyString>().is_ok() || input.hasattr("__fspath__")?)
+}
+
+/// Collect the strings of an input collection, which may either be an iterable of str or the
+/// path (a str or :py:class:`os.PathLike`) to a text file holding one string per line. Missing
+/// values in an iterable are handled as in [`get_pystring_handles_with_missing`].
+fn get_input_strings<'py>(
+    input: &Bound<'py, PyAny>,
+    missing: Missing,
+) -> PyResult<(InputStrings<'py>, Option<Vec<usize>>)> {
+    if is_path_like(input)? {
+        let path: PathBuf = input.extract()?;
+        let contents = input
+            .py()
+            .detach(|| std::fs::read(&path))
+            .map_err(|e| io_error_with_path(input.py(), e, &path))?;
+        return Ok((InputStrings::File { path, contents }, None));
+    }
+
+    let (handles, positions) = get_pystring_handles_with_missing(input, missing)?;
+    Ok((InputStrings::Handles(handles), positions))
+}
+
+/// Equivalent to [`get_pystring_handles`], with missing values (None, NaN and ``pandas.NA``)
+/// handled according to `missing`. If any were skipped, the positions of the collected strings
+/// within the input are also returned.
+fn get_pystring_handles_with_missing<'py>(
+    input: &Bound<'py, PyAny>,
+    missing: Missing,
+) -> PyResult<(StringHandles<'py>, Option<Vec<usize>>)> {
+    if input.cast::<PyString>().is_ok() {
+        return Err(PyValueError::new_err("expected iterable of str, got str"));
+    }
+
+    let sequence = series_to_sequence(input, missing)?;
+    let mut handles = Vec::new();
+    let mut positions = Vec::new();
+    let mut skipped = false;
+
+    for (idx, v) in sequence.as_ref().unwrap_or(input).try_iter()?.enumerate() {
+        let v = v?;
+        if is_missing(&v)? {
+            match missing {
+                Missing::Raise => {
+                    return Err(PyValueError::new_err(format!(
+                        "expected no missing values, got {} at index {idx} (pass missing='skip' \
+                         to exclude missing values from the search)",
+                        v.repr()?
+                    )))
+                }
+                Missing::Skip => {
+                    skipped = true;
+                    continue;
+                }
+            }
+        }
+        handles.push(get_pystring_handle(v, idx)?);
+        positions.push(idx);
+    }
+
+    Ok((handles, skipped.then_some(positions)))
+}
+
+/// Whether an element of an input collection represents a missing value.
+fn is_missing(v: &Bound<'_, PyAny>) -> PyResult<bool> {
+    if v.is_none() {
+        return Ok(true);
+    }
+    if let Ok(f) = v.cast::<PyFloat>() {
+        return Ok(f.value().is_nan());
+    }
+    let v_type = v.get_type();
+    Ok(v_type.name()?.to_str()? == "NAType" && v_type.module()?.to_str()?.starts_with("pandas"))
+}
+
+/// Map indices into a collection with skipped missing values back to positions within the
+/// original input.
+fn restore_positions<I: PairIndex>(indices: &mut [I], positions: Option<&[usize]>) {
+    if let Some(positions) = positions {
+        indices
+            .iter_mut()
+            .for_each(|i| *i = I::from_usize(positions[i.to_usize()]));
+    }
+}
+
+/// The length of an input collection, given the number of non-missing strings in it and their
+/// positions if any missing values were skipped. Trailing missing values are not counted, as they
+/// are irrelevant to the range of indices returned.
+fn input_len(num_strings: usize, positions: Option<&[usize]>) -> usize {
+    positions.map_or(num_strings, |p| p.last().map_or(0, |&i| i + 1))
+}
+
+/// If `input` is a pandas or polars Series, convert it in bulk into a sequence of Python objects.
+///
+/// Iterating over a Series directly boxes each element through the Series' own indexing machinery,
+/// which is much slower than letting the library materialize all elements at once (particularly
+/// for Arrow-backed or categorical data). Null values are rejected with their positional index,
+/// unless `missing` is set to skip them. Returns None for any other input, without importing
+/// either library.
+fn series_to_sequence<'py>(
+    input: &Bound<'py, PyAny>,
+    missing: Missing,
+) -> PyResult<Option<Bound<'py, PyAny>>> {
+    let input_type = input.get_type();
+    if input_type.name()?.to_str()? != "Series" {
+        return Ok(None);
+    }
+
+    let module = input_type.module()?;
+    let (null_mask, sequence) = match module.to_str()?.split('.').next() {
+        Some("pandas") => (
+            input.call_method0("isna")?,
+            input.call_method1("to_numpy", (input.py().get_type::<PyAny>(),))?,
+        ),
+        Some("polars") => (
+            input.call_method0("is_null")?,
+            input.call_method0("to_list")?,
+        ),
+        _ => return Ok(None),
+    };
+
+    if missing == Missing::Raise && null_mask.call_method0("any")?.is_truthy()? {
+        let mut idx = 0;
+        for is_null in null_mask.try_iter()? {
+            if is_null?.is_truthy()? {
+                break;
+            }
+            idx += 1;
+        }
+        return Err(PyValueError::new_err(format!(
+            "expected Series without null values, got null at index {idx}"
+        )));
+    }
+
+    Ok(Some(sequence))
+}
+
+/// Obtain a buffer over a C-contiguous bytes-like object.
+fn get_contiguous_buffer(data: &Bound<'_, PyAny>) -> PyResult<PyBuffer<u8>> {
+    let buffer = PyBuffer::<u8>::get(data)?;
+    if !buffer.is_c_contiguous() {
+        return Err(PyValueError::new_err("expected a C-contiguous buffer"));
+    }
+    Ok(buffer)
+}
+
+/// View the contents of a contiguous buffer as a byte slice.
+///
+/// # Safety
+///
+/// The underlying object must not be modified while the returned slice is in use.
+unsafe fn buffer_as_slice(buffer: &PyBuffer<u8>) -> &[u8] {
+    slice::from_raw_parts(buffer.buf_ptr().cast::<u8>(), buffer.len_bytes())
+}
+
+/// Split the contents of a buffer into lines (see [`get_neighbors_within_buffer`]), validating
+/// that each line is ASCII.
+fn split_buffer_lines<'a>(
+    data: &'a [u8],
+    delimiter: &[u8],
+    input_type: symscan::InputType,
+) -> PyResult<Vec<&'a str>> {
+    let &[delimiter] = delimiter else {
+        return Err(PyValueError::new_err(format!(
+            "delimiter must be a single byte, got {delimiter:?}"
+        )));
+    };
+    if data.is_empty() {
+        return Ok(Vec::new());
+    }
+    let data = data.strip_suffix(&[delimiter]).unwrap_or(data);
+
+    let lines = data
+        .split(|&b| b == delimiter)
+        .map(|line| match delimiter {
+            b'\n' => line.strip_suffix(b"\r").unwrap_or(line),
+            _ => line,
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(idx) = lines.iter().position(|line| !line.is_ascii()) {
+        return Err(to_pyerr(symscan::Error::NonAsciiInput {
+            input_type,
+            offending_idx: idx,
+            offending_string: String::from_utf8_lossy(lines[idx]).into_owned(),
+        }));
+    }
+
+    // SAFETY: ASCII is valid UTF-8.
+    Ok(lines
+        .into_iter()
+        .map(|line| unsafe { str::from_utf8_unchecked(line) })
+        .collect())
+}
+
+/// Convert the element at index `idx` of an input iterable to a string handle.
+fn get_pystring_handle(v: Bound<'_, PyAny>, idx: usize) -> PyResult<Bound<'_, PyString>> {
+    if let Ok(s) = v.cast::<PyString>() {
+        return Ok(s.clone());
+    }
+    if let Ok(b) = v.cast::<PyBytes>() {
+        return match str::from_utf8(b.as_bytes()) {
+            Ok(s) if s.is_ascii() => Ok(PyString::new(v.py(), s)),
+            _ => Err(PyValueError::new_err(format!(
+                "expected bytes elements to hold ASCII data, got {} at index {idx}",
+                v.repr()?
+            ))),
+        };
+    }
+    Err(PyTypeError::new_err(format!(
+        "expected iterable of str, got element of type '{}' at index {idx}",
+        v.get_type().name()?
+    )))
+}
+
+/// Detach string handles from the GIL, so that they can be moved onto another thread.
+fn unbind_all(handles: Vec<Bound<'_, PyString>>) -> Vec<Py<PyString>> {
+    handles.into_iter().map(Bound::unbind).collect()
+}
+
+/// Reattach string handles detached by [`unbind_all`].
+fn bind_all<'py>(py: Python<'py>, handles: &[Py<PyString>]) -> Vec<Bound<'py, PyString>> {
+    handles.iter().map(|v| v.bind(py).clone()).collect()
+}
+
+fn get_str_refs<'py>(input: &'py [Bound<'py, PyString>]) -> PyResult<Vec<&'py str>> {
+    input
+        .iter()
+        .map(|v| v.to_str())
+        .collect::<PyResult<Vec<_>>>()
+}
+
+/// Run a computation on a worker thread with the GIL released, while periodically checking for
+/// Python signals on the calling thread. If a signal handler raises (e.g. KeyboardInterrupt on
+/// Ctrl-C), the computation is cancelled and the handler's exception is propagated.
+///
+/// If `n_threads` is given, the computation runs on a dedicated rayon thread pool of that size
+/// instead of the global one. The search options passed to `f` select `metric`.
+fn run_interruptible<T, F>(
+    py: Python<'_>,
+    n_threads: Option<i64>,
+    metric: Metric,
+    f: F,
+) -> PyResult<T>
+where
+    T: Send,
+    F: FnOnce(&symscan::SearchOptions) -> Result<T, symscan::Error> + Send,
+{
+    const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+    let pool = build_thread_pool(n_threads)?;
+    let token = symscan::CancellationToken::new();
+    let mut options = symscan::SearchOptions::default();
+    options.metric = metric.0;
+    options.cancellation = Some(token.clone());
+
+    let caller = thread::current();
+    thread::scope(|s| {
+        let worker = s.spawn(|| {
+            let result = install(pool.as_ref(), || f(&options));
+            caller.unpark();
+            result
+        });
+
+        while !worker.is_finished() {
+            py.detach(|| thread::park_timeout(SIGNAL_CHECK_INTERVAL));
+            if let Err(e) = py.check_signals() {
+                token.cancel();
+                let _ = worker.join();
+                return Err(e);
+            }
+        }
+
+        match worker.join() {
+            Ok(result) => result.map_err(to_pyerr),
+            Err(payload) => std::panic::resume_unwind(payload),
+        }
+    })
+}
+
+/// Build a dedicated rayon thread pool of `n_threads` threads, if given.
+fn build_thread_pool(n_threads: Option<i64>) -> PyResult<Option<ThreadPool>> {
+    match n_threads {
+        None => Ok(None),
+        Some(n) if n <= 0 => Err(PyValueError::new_err(format!(
+            "n_threads must be a positive integer, got {n}"
+        ))),
+        Some(n) => ThreadPoolBuilder::new()
+            .num_threads(usize::try_from(n).unwrap_or(usize::MAX))
+            .build()
+            .map(Some)
+            .map_err(|e| PyRuntimeError::new_err(format!("failed to start threads: {e}"))),
+    }
+}
+
+/// Run `f` on `pool` if given, or on the global thread pool otherwise.
+fn install<T: Send>(pool: Option<&ThreadPool>, f: impl FnOnce() -> T + Send) -> T {
+    match pool {
+        Some(pool) => pool.install(f),
+        None => f(),
+    }
+}
+
+/// Search for neighbor pairs within `query`, or across it and `reference` if given, through the
+/// entry points `E` with the GIL released, and convert them into a tuple of arrays.
+fn detached_search<'py, E: EntryPoints>(
+    py: Python<'py>,
+    pool: Option<&ThreadPool>,
+    query: &[&str],
+    reference: Option<&[&str]>,
+    max_distance: u16,
+    options: &symscan::SearchOptions,
+) -> PyResult<Bound<'py, PyTuple>> {
+    let pairs = py
+        .detach(|| {
+            install(pool, || match reference {
+                Some(reference) => E::across(query, reference, max_distance, options),
+                None => E::within(query, max_distance, options),
+            })
+        })
+        .map_err(to_pyerr)?;
+    neighbor_pairs_into_pytuple(py, pairs, None)
+}
+
+/// Run `work` on a background thread, and return an :py:class:`asyncio.Future` of the running
+/// event loop that resolves to its result. `work` is expected to release the GIL for the duration
+/// of the computation, and to pass `options` on to it, so that cancelling the future cancels the
+/// computation.
+fn spawn_future<'py, F>(py: Python<'py>, work: F) -> PyResult<Bound<'py, PyAny>>
+where
+    F: FnOnce(Python<'_>, &symscan::SearchOptions) -> PyResult<Py<PyAny>> + Send + 'static,
+{
+    let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+    let future = event_loop.call_method0("create_future")?;
+
+    let token = symscan::CancellationToken::new();
+    let on_done = {
+        let token = token.clone();
+        PyCFunction::new_closure(py, None, None, move |args, _| -> PyResult<()> {
+            if args.get_item(0)?.call_method0("cancelled")?.is_truthy()? {
+                token.cancel();
+            }
+            Ok(())
+        })?
+    };
+    future.call_method1("add_done_callback", (on_done,))?;
+
+    let mut options = symscan::SearchOptions::default();
+    options.cancellation = Some(token);
+    let resolve = wrap_pyfunction!(resolve_future, py)?.unbind();
+    let (event_loop, future_handle) = (event_loop.unbind(), future.clone().unbind());
+
+    thread::Builder::new()
+        .name("symscan-aio".to_string())
+        .spawn(move || {
+            Python::attach(|py| {
+                let (value, error) = match work(py, &options) {
+                    Ok(value) => (Some(value), None),
+                    Err(e) => (None, Some(e.into_value(py).into_any())),
+                };
+                // This only fails if the event loop has been closed in the meantime, in which case
+                // there is nobody left to notify.
+                let _ = event_loop.bind(py).call_method1(
+                    "call_soon_threadsafe",
+                    (resolve, future_handle, value, error),
+                );
+            })
+        })
+        .map_err(|e| PyRuntimeError::new_err(format!("failed to start thread: {e}")))?;
+
+    Ok(future)
+}
+
+/// Set the result or exception of an asyncio future, unless it has already been cancelled.
+#[pyfunction]
+fn resolve_future(
+    future: &Bound<'_, PyAny>,
+    value: Option<Bound<'_, PyAny>>,
+    error: Option<Bound<'_, PyAny>>,
+) -> PyResult<()> {
+    if future.call_method0("done")?.is_truthy()? {
+        return Ok(());
+    }
+    match error {
+        Some(error) => future.call_method1("set_exception", (error,))?,
+        None => future.call_method1("set_result", (value,))?,
+    };
+    Ok(())
+}
+
+/// Convert a [`symscan::Error`] into the matching Python exception.
+fn to_pyerr(e: symscan::Error) -> PyErr {
+    let msg = e.to_string();
+    Python::attach(|py| {
+        let (err, attrs): (PyErr, Vec<(&str, Bound<PyAny>)>) = match e {
+            symscan::Error::NonAsciiInput {
+                input_type,
+                offending_idx,
+                offending_string,
+            } => (
+                NonAsciiInputError::new_err(msg),
+                vec![
+                    ("input_type", input_type.to_string().into_bound_py_any(py)?),
+                    ("index", offending_idx.into_bound_py_any(py)?),
+                    ("value", offending_string.into_bound_py_any(py)?),
+                ],
+            ),
+            symscan::Error::TooManyStrings {
+                input_type,
+                got,
+                limit,
+            } => (
+                TooManyStringsError::new_err(msg),
+                vec![
+                    ("input_type", input_type.to_string().into_bound_py_any(py)?),
+                    ("got", got.into_bound_py_any(py)?),
+                    ("limit", limit.into_bound_py_any(py)?),
+                ],
+            ),
+            symscan::Error::MaxDistCapped => (
+                MaxDistanceError::new_err(msg),
+                vec![
+                    ("got", u8::MAX.into_bound_py_any(py)?),
+                    ("limit", (u8::MAX - 1).into_bound_py_any(py)?),
+                ],
+            ),
+            symscan::Error::MaxDistTooLargeForCache { got, limit } => (
+                MaxDistanceError::new_err(msg),
+                vec![
+                    ("got", got.into_bound_py_any(py)?),
+                    ("limit", limit.into_bound_py_any(py)?),
+                ],
+            ),
+            symscan::Error::Io(e) => (PyErr::from(e), vec![]),
+            symscan::Error::Cancelled => (PyKeyboardInterrupt::new_err(msg), vec![]),
+            symscan::Error::SharedBufferTooSmall { .. }
+            | symscan::Error::InvalidOffsets { .. }
+            | symscan::Error::TooManyCandidates { .. }
+            | symscan::Error::InvalidRatio { .. }
+            | symscan::Error::RatioTooLow { .. }
+            | symscan::Error::MismatchedLengths { .. }
+            | symscan::Error::MismatchedLabels { .. }
+            | symscan::Error::MismatchedCaseSensitivity { .. }
+            | symscan::Error::MismatchedHashWidth { .. }
+            | symscan::Error::InvalidEditCosts { .. }
+            | symscan::Error::InvalidExcludedPair { .. }
+            | symscan::Error::PairOutOfRange { .. }
+            | symscan::Error::InputTooLong { .. } => (SymscanError::new_err(msg), vec![]),
+            symscan::Error::IncompatibleCacheVersion { .. } | symscan::Error::MalformedCache(_) => {
+                (CacheFormatError::new_err(msg), vec![])
+            }
+        };
+
+        let value = err.value(py);
+        for (name, attr) in attrs {
+            value.setattr(name, attr)?;
+        }
+        Ok(err)
+    })
+    .unwrap_or_else(|e| e)
+}
+
+/// Convert an I/O error into the matching Python OSError subclass, with the filename attached.
+fn io_error_with_path(py: Python<'_>, e: io::Error, path: &Path) -> PyErr {
+    let err_type = PyErr::from(io::Error::from(e.kind())).get_type(py);
+    match e.raw_os_error() {
+        Some(errno) => PyErr::from_type(err_type, (errno, e.to_string(), path.to_path_buf())),
+        None => PyErr::from_type(err_type, format!("{e}: '{}'", path.display())),
+    }
+}
+
+/// Fast discovery of similar strings in bulk
+///
+/// The module holds no global mutable state, and CachedRef guards its only mutation (``add`` and
+/// unpickling) behind pyo3's runtime borrow checking, so it is safe to use without the GIL on
+/// free-threaded builds of CPython.
+#[pymodule(name = "symscan", gil_used = false)]
+fn symscan_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(get_neighbors_within, m)?)?;
+    m.add_function(wrap_pyfunction!(get_neighbors_across, m)?)?;
+    m.add_function(wrap_pyfunction!(get_neighbors_across_chunked, m)?)?;
+    m.add_function(wrap_pyfunction!(get_neighbors_within_buffer, m)?)?;
+    m.add_function(wrap_pyfunction!(get_neighbors_across_buffer, m)?)?;
+    m.add_function(wrap_pyfunction!(search, m)?)?;
+    m.add_function(wrap_pyfunction!(pairwise_distance, m)?)?;
+    m.add_class::<CachedRef>()?;
+    m.add_class::<SharedCacheHandle>()?;
+    m.add_class::<NeighborChunks>()?;
+
+    let aio = PyModule::new(m.py(), "symscan.aio")?;
+    aio.setattr(
+        "__doc__",
+        "Awaitable variants of the symscan search functions",
+    )?;
+    aio.add_function(wrap_pyfunction!(aio_get_neighbors_within, &aio)?)?;
+    aio.add_function(wrap_pyfunction!(aio_get_neighbors_across, &aio)?)?;
+    m.add("aio", &aio)?;
+    // Make `import symscan.aio` work, which submodules of extension modules don't by default.
+    m.py()
+        .import("sys")?
+        .getattr("modules")?
+        .set_item("symscan.aio", aio)?;
+    m.add("SymscanError", m.py().get_type::<SymscanError>())?;
+    m.add(
+        "NonAsciiInputError",
+        m.py().get_type::<NonAsciiInputError>(),
+    )?;
+    m.add("MaxDistanceError", m.py().get_type::<MaxDistanceError>())?;
+    m.add(
+        "TooManyStringsError",
+        m.py().get_type::<TooManyStringsError>(),
+    )?;
+    m.add("CacheFormatError", m.py().get_type::<CacheFormatError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::IntoPyDict;
+    use std::ffi::CString;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use symscan::naive;
+
+    #[test]
+    fn test_get_similarities() {
+        let pairs = symscan::NeighborPairs {
+            row: vec![0, 0, 1, 3],
+            col: vec![1, 2, 2, 4],
+            dists: vec![1, 2, 1, 0],
+        };
+        let lens = [4, 4, 3, 0, 0];
+        let len = |i: usize| lens[i];
+
+        assert_eq!(
+            get_similarities(&pairs, Metric::LEVENSHTEIN, len, len),
+            vec![0.75, 0.5, 0.75, 1.0]
+        );
+
+        let pairs = symscan::WideNeighborPairs {
+            row: vec![0],
+            col: vec![1],
+            dists: vec![300],
+        };
+        let lens = [400, 100];
+        let len = |i: usize| lens[i];
+        assert_eq!(
+            get_similarities(&pairs, Metric::LEVENSHTEIN, len, len),
+            vec![0.25]
+        );
+    }
+
+    #[test]
+    fn test_parse_metric() {
+        assert_eq!(
+            Metric::parse("levenshtein").expect("known metric"),
+            Metric::LEVENSHTEIN
+        );
+        assert_eq!(
+            Metric::parse("osa").expect("known metric"),
+            Metric(symscan::Metric::Osa)
+        );
+        Python::initialize();
+        let err = Metric::parse("cosine").expect_err("unknown metric");
+        assert!(err
+            .to_string()
+            .contains("'levenshtein', 'hamming', 'osa', 'indel'"));
+
+        Python::attach(|py| {
+            let costs = (2, 1, 3).into_pyobject(py).unwrap();
+            let metric = costs.extract::<Metric>().expect("valid costs");
+            let expected = symscan::EditCosts::new(2, 1, 3).unwrap();
+            assert_eq!(metric.0, symscan::Metric::WeightedLevenshtein(expected));
+            assert_eq!(metric.describe(), "(2, 1, 3)");
+            let round_trip = metric.to_object(py).unwrap().extract::<Metric>().unwrap();
+            assert_eq!(round_trip, metric);
+
+            let err = (0, 1, 1)
+                .into_pyobject(py)
+                .unwrap()
+                .extract::<Metric>()
+                .expect_err("zero cost");
+            assert!(err.is_instance_of::<SymscanError>(py));
+            let err = 1_i64
+                .into_pyobject(py)
+                .unwrap()
+                .extract::<Metric>()
+                .expect_err("int");
+            assert!(err.is_instance_of::<PyTypeError>(py));
+        });
+    }
+
+    /// Generate `n` strings of up to 6 characters over a small alphabet, so that many pairs lie
+    /// within small distances of each other.
+    fn metric_test_strings(n: usize) -> Vec<String> {
+        let mut state = 0x2545_f491_u32;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+        (0..n)
+            .map(|_| {
+                let len = next() % 7;
+                (0..len)
+                    .map(|_| b"ACG"[(next() % 3) as usize] as char)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Check the hits of the search paths under `metric`, given as its Python object, against the
+    /// distances given by `reference_dist`, which returns None for pairs that are never neighbors.
+    fn check_metric(
+        metric: impl for<'py> IntoPyObject<'py>,
+        max_distance: u8,
+        reference_dist: impl Fn(&str, &str) -> Option<usize>,
+    ) {
+        let strings = metric_test_strings(80);
+        let (query, reference) = strings.split_at(30);
+        let expected_pairs = |rows: &[String], cols: &[String], within: bool| {
+            let mut expected = Vec::new();
+            for (i, a) in rows.iter().enumerate() {
+                for (j, b) in cols.iter().enumerate() {
+                    if within && j <= i {
+                        continue;
+                    }
+                    match reference_dist(a, b) {
+                        Some(dist) if dist <= max_distance as usize => expected.push((i, j, dist)),
+                        _ => {}
+                    }
+                }
+            }
+            expected
+        };
+        let triples = |pairs: symscan::NeighborPairs| {
+            let mut triples = (0..pairs.row.len())
+                .map(|i| {
+                    let (row, col) = (pairs.row[i] as usize, pairs.col[i] as usize);
+                    (row, col, pairs.dists[i] as usize)
+                })
+                .collect::<Vec<_>>();
+            triples.sort_unstable();
+            triples
+        };
+
+        Python::initialize();
+        Python::attach(|py| {
+            let metric = metric.into_bound_py_any(py).unwrap();
+            let metric = metric.extract::<Metric>().unwrap();
+            let query_views = query.iter().map(String::as_str).collect::<Vec<_>>();
+            let ref_views = reference.iter().map(String::as_str).collect::<Vec<_>>();
+
+            let within = run_interruptible(py, None, metric, |options| {
+                symscan::get_neighbors_within_with_options(&query_views, max_distance, options)
+            })
+            .unwrap();
+            assert_eq!(triples(within), expected_pairs(query, query, true));
+
+            let expected = expected_pairs(query, reference, false);
+            assert!(!expected.is_empty());
+            let across = run_interruptible(py, None, metric, |options| {
+                symscan::get_neighbors_across_with_options(
+                    &query_views,
+                    &ref_views,
+                    max_distance,
+                    options,
+                )
+            })
+            .unwrap();
+            assert_eq!(triples(across), expected);
+
+            // CachedRef instances search under the metric they were constructed for, both from
+            // the cache and from a cache of the query.
+            let query = PyList::new(py, query).unwrap();
+            let reference = PyList::new(py, reference).unwrap();
+            let cached = CachedRef::new(reference.as_any(), max_distance, None, metric).unwrap();
+            let metric = cached.metric.check_query(None).unwrap();
+            let (hits, _, _) = cached
+                .query_across(
+                    query.as_any(),
+                    max_distance,
+                    None,
+                    false,
+                    metric,
+                    Missing::Raise,
+                )
+                .unwrap();
+            assert_eq!(triples(hits), expected);
+
+            let cached_query = Bound::new(
+                py,
+                CachedRef::new(query.as_any(), max_distance, None, metric).unwrap(),
+            )
+            .unwrap();
+            let (hits, _, _) = cached
+                .query_across(
+                    cached_query.as_any(),
+                    max_distance,
+                    None,
+                    false,
+                    metric,
+                    Missing::Raise,
+                )
+                .unwrap();
+            assert_eq!(triples(hits), expected);
+        });
+    }
+
+    #[test]
+    fn test_metric_levenshtein() {
+        check_metric("levenshtein", 2, |a, b| Some(naive::levenshtein(a, b)));
+    }
+
+    #[test]
+    fn test_metric_hamming() {
+        check_metric("hamming", 2, naive::hamming);
+    }
+
+    #[test]
+    fn test_metric_osa() {
+        check_metric("osa", 2, |a, b| Some(naive::osa(a, b)));
+    }
+
+    #[test]
+    fn test_metric_indel() {
+        check_metric("indel", 2, |a, b| Some(naive::indel(a, b)));
+    }
+
+    #[test]
+    fn test_metric_weighted_levenshtein() {
+        let costs = symscan::EditCosts::new(2, 1, 3).unwrap();
+        check_metric((2, 1, 3), 3, |a, b| {
+            Some(naive::weighted_levenshtein(a, b, costs))
+        });
+    }
+
+    #[test]
+    fn test_pairwise_dist_array() {
+        let strings = metric_test_strings(600);
+        let (a, b) = strings.split_at(300);
+        let a = a.iter().map(String::as_str).collect::<Vec<_>>();
+        let b = b.iter().map(String::as_str).collect::<Vec<_>>();
+        let expected = a
+            .iter()
+            .zip(&b)
+            .map(|(a, b)| naive::levenshtein(a, b))
+            .collect::<Vec<_>>();
+        let narrow = |dists: &[usize]| dists.iter().map(|&d| d as u8).collect::<Vec<_>>();
+
+        // The dtype follows the longest string rather than the cutoff.
+        for score_cutoff in [None, Some(1000)] {
+            assert_eq!(
+                pairwise_dist_array(&a, &b, score_cutoff).unwrap(),
+                DistArray::U8(narrow(&expected))
+            );
+        }
+        let capped = expected.iter().map(|&d| d.min(2)).collect::<Vec<_>>();
+        assert_eq!(
+            pairwise_dist_array(&a, &b, Some(1)).unwrap(),
+            DistArray::U8(narrow(&capped))
+        );
+
+        // Distances beyond the range of uint8 widen the dtype rather than being clamped.
+        let long = "a".repeat(300);
+        assert_eq!(
+            pairwise_dist_array(&["", "a"], &[&long, &long], None).unwrap(),
+            DistArray::U16(vec![300, 299])
+        );
+        assert_eq!(
+            pairwise_dist_array(&["", "a"], &[&long, &long], Some(254)).unwrap(),
+            DistArray::U8(vec![255, 255])
+        );
+        assert_eq!(
+            pairwise_dist_array(&["", "a"], &[&long, &long], Some(255)).unwrap(),
+            DistArray::U16(vec![256, 256])
+        );
+        let longer = "a".repeat(70_000);
+        assert_eq!(
+            pairwise_dist_array(&[""], &[&longer], None).unwrap(),
+            DistArray::U32(vec![70_000])
+        );
+        assert_eq!(
+            pairwise_dist_array(&[], &[], None).unwrap(),
+            DistArray::U8(vec![])
+        );
+    }
+
+    #[test]
+    fn test_cached_metric_mismatch() {
+        Python::initialize();
+        Python::attach(|py| {
+            let reference = PyList::new(py, ["fooo", "barr", "bazz", "buzz"]).unwrap();
+            let query = PyList::new(py, ["fizz", "fuzz", "buzz", "bzuz"]).unwrap();
+            let hamming = Metric(symscan::Metric::Hamming);
+            let cached = CachedRef::new(reference.as_any(), 1, None, hamming).unwrap();
+
+            let err = cached
+                .get_neighbors_within(py, 1, None, false, Some(Metric::LEVENSHTEIN))
+                .expect_err("metric mismatch");
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(err
+                .to_string()
+                .contains("metric 'hamming', got 'levenshtein'"));
+
+            // Queries that give the metric of the cache, or none at all, are accepted.
+            for metric in [None, Some(hamming)] {
+                assert_eq!(cached.metric.check_query(metric).unwrap(), hamming);
+            }
+
+            // Two caches can only be searched against each other under the same metric.
+            let cached_query = Bound::new(
+                py,
+                CachedRef::new(query.as_any(), 1, None, Metric::LEVENSHTEIN).unwrap(),
+            )
+            .unwrap();
+            let err = cached
+                .query_across(
+                    cached_query.as_any(),
+                    1,
+                    None,
+                    false,
+                    hamming,
+                    Missing::Raise,
+                )
+                .expect_err("metric mismatch");
+            assert!(err.is_instance_of::<PyValueError>(py));
+
+            // Similarities are normalized by the largest distance under the metric.
+            let indel = Metric(symscan::Metric::Indel);
+            let pairs = symscan::NeighborPairs {
+                row: vec![0],
+                col: vec![1],
+                dists: vec![2],
+            };
+            assert_eq!(get_similarities(&pairs, indel, |_| 4, |_| 4), vec![0.75]);
+        });
+    }
+
+    #[test]
+    fn test_radius_neighbors_grouping() {
+        Python::initialize();
+        Python::attach(|py| {
+            let reference = PyList::new(py, ["fooo", "barr", "bazz", "buzz"]).unwrap();
+            let query = PyList::new(py, ["fizz", "fuzz", "buzz", "quux"]).unwrap();
+            let cached = CachedRef::new(reference.as_any(), 2, None, Metric::LEVENSHTEIN).unwrap();
+            let (hits, num_queries, _) = cached
+                .query_across(
+                    query.as_any(),
+                    2,
+                    None,
+                    false,
+                    Metric::LEVENSHTEIN,
+                    Missing::Raise,
+                )
+                .unwrap();
+            assert_eq!(num_queries, 4);
+
+            let mut flat = (0..hits.row.len())
+                .map(|i| (hits.row[i], hits.col[i], hits.dists[i]))
+                .collect::<Vec<_>>();
+
+            let (indices, dists) = group_hits_by_row(hits, num_queries);
+            // Neighbors are ordered by distance then index, with an empty group for "quux".
+            assert_eq!(indices, vec![vec![2, 3], vec![3, 2], vec![3, 2], vec![]]);
+            assert_eq!(dists, vec![vec![2, 2], vec![1, 2], vec![0, 1], vec![]]);
+
+            // The groups hold exactly the flat hits.
+            let mut regrouped = indices
+                .iter()
+                .zip(&dists)
+                .enumerate()
+                .flat_map(|(row, (cols, dists))| {
+                    cols.iter()
+                        .zip(dists)
+                        .map(move |(&col, &dist)| (row as u32, col, dist))
+                })
+                .collect::<Vec<_>>();
+            regrouped.sort_unstable();
+            flat.sort_unstable();
+            assert_eq!(regrouped, flat);
+        });
+    }
+
+    #[test]
+    fn test_exception_types() {
+        Python::initialize();
+        Python::attach(|py| {
+            let attr = |err: &PyErr, name: &str| err.value(py).getattr(name).unwrap();
+
+            let reference = PyList::new(py, ["fooo", "b\u{e4}rr", "bazz"]).unwrap();
+            let err = CachedRef::new(reference.as_any(), 1, None, Metric::LEVENSHTEIN)
+                .err()
+                .expect("non-ASCII reference");
+            assert!(err.is_instance_of::<NonAsciiInputError>(py));
+            assert_eq!(
+                attr(&err, "input_type").extract::<String>().unwrap(),
+                "reference"
+            );
+            assert_eq!(attr(&err, "index").extract::<usize>().unwrap(), 1);
+            assert_eq!(
+                attr(&err, "value").extract::<String>().unwrap(),
+                "b\u{e4}rr"
+            );
+
+            let reference = PyList::new(py, ["fooo", "barr", "bazz"]).unwrap();
+            let cached = CachedRef::new(reference.as_any(), 1, None, Metric::LEVENSHTEIN).unwrap();
+            let query = PyList::new(py, ["fizz"]).unwrap();
+            let err = cached
+                .query_across(
+                    query.as_any(),
+                    2,
+                    None,
+                    false,
+                    Metric::LEVENSHTEIN,
+                    Missing::Raise,
+                )
+                .expect_err("max_distance above that of the cache");
+            assert!(err.is_instance_of::<MaxDistanceError>(py));
+            assert_eq!(attr(&err, "got").extract::<u8>().unwrap(), 2);
+            assert_eq!(attr(&err, "limit").extract::<u8>().unwrap(), 1);
+
+            let err = to_pyerr(symscan::get_neighbors_within(&["fizz"], u8::MAX).unwrap_err());
+            assert!(err.is_instance_of::<MaxDistanceError>(py));
+            assert_eq!(attr(&err, "got").extract::<u8>().unwrap(), u8::MAX);
+            assert_eq!(attr(&err, "limit").extract::<u8>().unwrap(), u8::MAX - 1);
+
+            // CachedRef instances cannot be searched beyond that cap, even though other inputs
+            // can.
+            assert_eq!(
+                cached_max_distance(MAX_NARROW_DISTANCE).unwrap(),
+                u8::MAX - 1
+            );
+            let err = cached_max_distance(300).expect_err("max_distance above the cap");
+            assert!(err.is_instance_of::<MaxDistanceError>(py));
+            assert_eq!(attr(&err, "got").extract::<u16>().unwrap(), 300);
+            assert_eq!(attr(&err, "limit").extract::<u8>().unwrap(), u8::MAX - 1);
+
+            let err = to_pyerr(symscan::Error::TooManyStrings {
+                input_type: symscan::InputType::Query,
+                got: 5,
+                limit: 4,
+            });
+            assert!(err.is_instance_of::<TooManyStringsError>(py));
+            assert_eq!(
+                attr(&err, "input_type").extract::<String>().unwrap(),
+                "query"
+            );
+            assert_eq!(attr(&err, "got").extract::<usize>().unwrap(), 5);
+            assert_eq!(attr(&err, "limit").extract::<usize>().unwrap(), 4);
+
+            let err = to_pyerr(
+                symscan::CachedRef::read_from(&b"garbage"[..])
+                    .err()
+                    .unwrap(),
+            );
+            assert!(err.is_instance_of::<CacheFormatError>(py));
+
+            let err = to_pyerr(symscan::Error::Cancelled);
+            assert!(err.is_instance_of::<PyKeyboardInterrupt>(py));
+
+            // Every error raised by symscan can be caught as SymscanError, or as ValueError by
+            // code written before the hierarchy existed.
+            for err_type in [
+                py.get_type::<NonAsciiInputError>(),
+                py.get_type::<MaxDistanceError>(),
+                py.get_type::<TooManyStringsError>(),
+                py.get_type::<CacheFormatError>(),
+            ] {
+                assert!(err_type.is_subclass_of::<SymscanError>().unwrap());
+                assert!(err_type.is_subclass_of::<PyValueError>().unwrap());
+            }
+        });
+    }
+
+    #[test]
+    fn test_n_threads() {
+        let reference = include_str!("../../test_files/cdr3b_10k_b.txt")
+            .lines()
+            .collect::<Vec<_>>();
+        let query = include_str!("../../test_files/cdr3b_10k_a.txt")
+            .lines()
+            .collect::<Vec<_>>();
+
+        Python::initialize();
+        Python::attach(|py| {
+            // The computation runs on a dedicated pool of the requested size.
+            let num_threads = run_interruptible(py, Some(1), Metric::LEVENSHTEIN, |_| {
+                Ok(rayon::current_num_threads())
+            })
+            .unwrap();
+            assert_eq!(num_threads, 1);
+
+            let within = |n_threads| {
+                run_interruptible(py, n_threads, Metric::LEVENSHTEIN, |options| {
+                    symscan::get_neighbors_within_with_options(&query, 1, options)
+                })
+                .unwrap()
+            };
+            let across = |n_threads| {
+                run_interruptible(py, n_threads, Metric::LEVENSHTEIN, |options| {
+                    symscan::get_neighbors_across_with_options(&query, &reference, 1, options)
+                })
+                .unwrap()
+            };
+            assert_eq!(within(Some(1)), within(None));
+            assert_eq!(across(Some(1)), across(None));
+
+            let reference_list = PyList::new(py, &reference).unwrap();
+            let query_list = PyList::new(py, &query).unwrap();
+            let cached_across = |n_threads| {
+                let cached =
+                    CachedRef::new(reference_list.as_any(), 1, n_threads, Metric::LEVENSHTEIN)
+                        .unwrap();
+                let (hits, _, _) = cached
+                    .query_across(
+                        query_list.as_any(),
+                        1,
+                        n_threads,
+                        false,
+                        Metric::LEVENSHTEIN,
+                        Missing::Raise,
+                    )
+                    .unwrap();
+                hits
+            };
+            assert_eq!(cached_across(Some(1)), cached_across(None));
+
+            for n_threads in [0, -1] {
+                let err = run_interruptible(py, Some(n_threads), Metric::LEVENSHTEIN, |_| Ok(()))
+                    .expect_err("non-positive n_threads");
+                assert!(err.is_instance_of::<PyValueError>(py));
+                assert_eq!(
+                    err.value(py).to_string(),
+                    format!("n_threads must be a positive integer, got {n_threads}")
+                );
+
+                let err = CachedRef::new(
+                    reference_list.as_any(),
+                    1,
+                    Some(n_threads),
+                    Metric::LEVENSHTEIN,
+                )
+                .err()
+                .expect("non-positive n_threads");
+                assert!(err.is_instance_of::<PyValueError>(py));
+            }
+        });
+    }
+
+    #[test]
+    fn test_series_to_sequence() {
+        Python::initialize();
+        Python::attach(|py| {
+            // Minimal stand-ins for pandas and polars Series, which refuse element-wise iteration
+            // so as to check that the bulk conversion path is taken.
+            let namespace = PyDict::new(py);
+            py.run(
+                cr#"
+class Mask(list):
+    def any(self):
+        return any(self)
+
+class Series:
+    def __init__(self, data):
+        self.data = data
+    def __iter__(self):
+        raise AssertionError("Series iterated element-wise")
+    def isna(self):
+        return Mask(v is None for v in self.data)
+    def is_null(self):
+        return self.isna()
+    def to_numpy(self, dtype=None):
+        assert dtype is object
+        return tuple(self.data)
+    def to_list(self):
+        return list(self.data)
+
+class PandasSeries(Series):
+    pass
+
+class PolarsSeries(Series):
+    pass
+
+PandasSeries.__name__ = PolarsSeries.__name__ = "Series"
+PandasSeries.__module__ = "pandas.core.series"
+PolarsSeries.__module__ = "polars.series.series"
+"#,
+                Some(&namespace),
+                None,
+            )
+            .expect("valid script");
+
+            let strs = |handles: Vec<Bound<'_, PyString>>| {
+                handles.iter().map(|s| s.to_string()).collect::<Vec<_>>()
+            };
+            for class in ["PandasSeries", "PolarsSeries"] {
+                let class = namespace.get_item(class).unwrap().expect("defined");
+                let series = class.call1((vec!["fizz", "fuzz"],)).unwrap();
+                assert_eq!(
+                    strs(get_pystring_handles(&series).expect("legal")),
+                    vec!["fizz", "fuzz"]
+                );
+
+                let series = class
+                    .call1((vec![Some("fizz"), None, Some("buzz")],))
+                    .unwrap();
+                let err = get_pystring_handles(&series).expect_err("contains null");
+                assert!(err.to_string().contains("null at index 1"));
+            }
+
+            let list = PyList::new(py, ["fizz", "fuzz"]).unwrap();
+            assert!(series_to_sequence(list.as_any(), Missing::Raise)
+                .unwrap()
+                .is_none());
+        });
+    }
+
+    #[test]
+    fn test_numpy_string_scalars() {
+        Python::initialize();
+        Python::attach(|py| {
+            // Minimal stand-ins for np.str_, np.bytes_, np.ma.masked and an object dtype ndarray,
+            // which iterates over its elements as numpy scalars.
+            let namespace = PyDict::new(py);
+            py.run(
+                cr#"
+class str_(str):
+    pass
+
+class bytes_(bytes):
+    pass
+
+class MaskedConstant:
+    pass
+
+class ndarray:
+    def __init__(self, data):
+        self.data = list(data)
+    def __len__(self):
+        return len(self.data)
+    def __iter__(self):
+        return iter(self.data)
+
+for cls in (str_, bytes_, ndarray):
+    cls.__module__ = "numpy"
+MaskedConstant.__module__ = "numpy.ma.core"
+
+objects = ndarray([str_("fizz"), str_("fuzz"), bytes_(b"buzz"), "bizz"])
+non_ascii = ndarray([str_("fizz"), bytes_(b"f\xc3\xbczz")])
+mixed = ndarray([str_("fizz"), str_("fuzz"), MaskedConstant()])
+"#,
+                Some(&namespace),
+                None,
+            )
+            .expect("valid script");
+            let get = |name| namespace.get_item(name).unwrap().expect("defined");
+
+            let handles = get_pystring_handles(&get("objects")).expect("legal");
+            assert_eq!(
+                get_str_refs(&handles).unwrap(),
+                vec!["fizz", "fuzz", "buzz", "bizz"]
+            );
+
+            let err = get_pystring_handles(&get("non_ascii")).expect_err("non-ASCII bytes");
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(err
+                .to_string()
+                .contains("ASCII data, got b'f\\xc3\\xbczz' at index 1"));
+
+            let err = get_pystring_handles(&get("mixed")).expect_err("masked element");
+            assert!(err.is_instance_of::<PyTypeError>(py));
+            assert!(err
+                .to_string()
+                .contains("element of type 'MaskedConstant' at index 2"));
+        });
+    }
+
+    #[test]
+    fn test_search_dispatch() {
+        Python::initialize();
+        Python::attach(|py| {
+            let query = PyList::new(py, ["fizz", "fuzz", "buzz"]).unwrap();
+            let reference = PyList::new(py, ["fooo", "barr", "bazz", "buzz"]).unwrap();
+            let kwargs = |key: &str, value: &str| {
+                let kwargs = PyDict::new(py);
+                kwargs.set_item(key, value).unwrap();
+                kwargs
+            };
+
+            // Keyword arguments are passed through, whichever path is taken.
+            let err = search(py, &query, None, 1, Some(&kwargs("metric", "cosine")))
+                .expect_err("unknown metric");
+            assert!(err.is_instance_of::<PyValueError>(py));
+            let err = search(py, &query, Some(&reference), 1, Some(&kwargs("bogus", "")))
+                .expect_err("unknown kwarg");
+            assert!(err.is_instance_of::<PyTypeError>(py));
+
+            // A CachedRef reference is searched via its own method, which enforces its own limit.
+            let cached = Bound::new(
+                py,
+                CachedRef::new(reference.as_any(), 1, None, Metric::LEVENSHTEIN).unwrap(),
+            )
+            .unwrap();
+            let err = search(py, &query, Some(cached.as_any()), 2, None)
+                .expect_err("max_distance above that of cache");
+            assert!(err.is_instance_of::<MaxDistanceError>(py));
+            let err = search(py, cached.as_any(), None, 2, None)
+                .expect_err("max_distance above that of cache");
+            assert!(err.is_instance_of::<MaxDistanceError>(py));
+        });
+    }
+
+    #[test]
+    fn test_cached_concurrent_queries() {
+        const NUM_THREADS: usize = 8;
+
+        let reference = include_str!("../../test_files/cdr3b_10k_b.txt")
+            .lines()
+            .collect::<Vec<_>>();
+        let query = include_str!("../../test_files/cdr3b_10k_a.txt")
+            .lines()
+            .collect::<Vec<_>>();
+
+        Python::initialize();
+        let (cached, query, expected) = Python::attach(|py| {
+            let reference = PyList::new(py, &reference).unwrap();
+            let cached = Py::new(
+                py,
+                CachedRef::new(reference.as_any(), 1, None, Metric::LEVENSHTEIN).unwrap(),
+            )
+            .unwrap();
+            let query = PyList::new(py, &query).unwrap().unbind();
+            let (expected, _, _) = cached
+                .borrow(py)
+                .query_across(
+                    query.bind(py).as_any(),
+                    1,
+                    None,
+                    false,
+                    Metric::LEVENSHTEIN,
+                    Missing::Raise,
+                )
+                .unwrap();
+            (cached, query, expected)
+        });
+
+        thread::scope(|s| {
+            for _ in 0..NUM_THREADS {
+                s.spawn(|| {
+                    Python::attach(|py| {
+                        for _ in 0..2 {
+                            let (hits, _, _) = cached
+                                .borrow(py)
+                                .query_across(
+                                    query.bind(py).as_any(),
+                                    1,
+                                    None,
+                                    false,
+                                    Metric::LEVENSHTEIN,
+                                    Missing::Raise,
+                                )
+                                .unwrap();
+                            assert_eq!(hits, expected);
+                        }
+                    })
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_spawn_future() {
+        Python::initialize();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        Python::attach(|py| {
+            let start_slow = PyCFunction::new_closure(py, None, None, |args, _| {
+                let value = args.get_item(0)?.unbind();
+                spawn_future(args.py(), move |py, _| {
+                    py.detach(|| thread::sleep(Duration::from_millis(200)));
+                    Ok(value)
+                })
+                .map(Bound::unbind)
+            })
+            .unwrap();
+            let start_failing = PyCFunction::new_closure(py, None, None, |args, _| {
+                spawn_future(args.py(), |_, _| Err(PyValueError::new_err("oops")))
+                    .map(Bound::unbind)
+            })
+            .unwrap();
+            let start_endless = {
+                let cancelled = cancelled.clone();
+                PyCFunction::new_closure(py, None, None, move |args, _| {
+                    let cancelled = cancelled.clone();
+                    spawn_future(args.py(), move |py, options| {
+                        let token = options.cancellation.as_ref().unwrap();
+                        py.detach(|| {
+                            while !token.is_cancelled() {
+                                thread::sleep(Duration::from_millis(1));
+                            }
+                        });
+                        cancelled.store(true, Ordering::Relaxed);
+                        Ok(py.None())
+                    })
+                    .map(Bound::unbind)
+                })
+                .unwrap()
+            };
+            let globals = [
+                ("start_slow", start_slow),
+                ("start_failing", start_failing),
+                ("start_endless", start_endless),
+            ]
+            .into_py_dict(py)
+            .unwrap();
+            py.run(
+                c"
+import asyncio
+
+async def main():
+    ticks = 0
+
+    async def tick():
+        nonlocal ticks
+        while True:
+            await asyncio.sleep(0.01)
+            ticks += 1
+
+    ticker = asyncio.create_task(tick())
+    assert await asyncio.gather(start_slow(1), start_slow(2)) == [1, 2]
+    ticker.cancel()
+    assert ticks > 5, ticks
+
+    try:
+        await start_failing()
+        raise AssertionError('expected ValueError')
+    except ValueError as e:
+        assert str(e) == 'oops'
+
+    future = start_endless()
+    await asyncio.sleep(0.05)
+    future.cancel()
+    await asyncio.sleep(0)
+
+asyncio.run(main())
+",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+        });
+
+        let start = std::time::Instant::now();
+        while !cancelled.load(Ordering::Relaxed) {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "work was not cancelled"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_split_buffer_lines() {
+        let data = include_bytes!("../../test_files/cdr3b_10k_a.txt");
+        let expected = include_str!("../../test_files/cdr3b_10k_a.txt")
+            .lines()
+            .collect::<Vec<_>>();
+        let query = symscan::InputType::Query;
+
+        assert_eq!(split_buffer_lines(data, b"\n", query).unwrap(), expected);
+
+        let crlf = expected.join("\r\n");
+        assert_eq!(
+            split_buffer_lines(crlf.as_bytes(), b"\n", symscan::InputType::Query).unwrap(),
+            expected
+        );
+
+        let cases: [(&[u8], &[u8], Vec<&str>); 5] = [
+            (b"", b"\n", vec![]),
+            (b"\n", b"\n", vec![""]),
+            (b"fizz\n\nbuzz", b"\n", vec!["fizz", "", "buzz"]),
+            (b"fizz\r\nbuzz\r\n", b"\n", vec!["fizz", "buzz"]),
+            (b"fizz\r,buzz,", b",", vec!["fizz\r", "buzz"]),
+        ];
+        for (data, delimiter, expected) in cases {
+            assert_eq!(
+                split_buffer_lines(data, delimiter, symscan::InputType::Query).unwrap(),
+                expected
+            );
+        }
+
+        Python::initialize();
+        Python::attach(|py| {
+            let err = split_buffer_lines(b"fizz\nf\xc3\xbczz\n", b"\n", symscan::InputType::Query)
+                .expect_err("non-ASCII line");
+            assert!(err.is_instance_of::<NonAsciiInputError>(py));
+            assert_eq!(
+                err.value(py)
+                    .getattr("index")
+                    .unwrap()
+                    .extract::<usize>()
+                    .unwrap(),
+                1
+            );
+
+            let err = split_buffer_lines(b"fizz", b"\r\n", symscan::InputType::Query)
+                .expect_err("multi-byte delimiter");
+            assert!(err.is_instance_of::<PyValueError>(py));
+        });
+    }
+
+    #[test]
+    fn test_cached_from_buffer() {
+        Python::initialize();
+        Python::attach(|py| {
+            let reference_bytes = include_bytes!("../../test_files/cdr3b_10k_b.txt");
+            let reference = include_str!("../../test_files/cdr3b_10k_b.txt")
+                .lines()
+                .collect::<Vec<_>>();
+            let query = include_str!("../../test_files/cdr3b_10k_a.txt")
+                .lines()
+                .collect::<Vec<_>>();
+
+            let from_buffer = CachedRef::from_buffer(
+                PyBytes::new(py, reference_bytes).as_any(),
+                1,
+                b"\n",
+                None,
+                Metric::LEVENSHTEIN,
+            )
+            .unwrap();
+            let from_list = CachedRef::new(
+                PyList::new(py, reference).unwrap().as_any(),
+                1,
+                None,
+                Metric::LEVENSHTEIN,
+            )
+            .unwrap();
+
+            let query = PyList::new(py, query).unwrap();
+            let (expected, _, _) = from_list
+                .query_across(
+                    query.as_any(),
+                    1,
+                    None,
+                    false,
+                    Metric::LEVENSHTEIN,
+                    Missing::Raise,
+                )
+                .unwrap();
+            let (hits, _, _) = from_buffer
+                .query_across(
+                    query.as_any(),
+                    1,
+                    None,
+                    false,
+                    Metric::LEVENSHTEIN,
+                    Missing::Raise,
+                )
+                .unwrap();
+            assert_eq!(hits, expected);
+        });
+    }
+
+    /// A path in the system temporary directory that is removed when dropped.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            TempPath(std::env::temp_dir().join(format!("symscan_{name}_{}", std::process::id())))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_save_load() {
+        let reference = include_str!("../../test_files/cdr3b_10k_b.txt")
+            .lines()
+            .collect::<Vec<_>>();
+        let path = TempPath::new("test_save_load");
+
+        Python::initialize();
+        Python::attach(|py| {
+            let cached = CachedRef::new(
+                PyList::new(py, &reference).unwrap().as_any(),
+                2,
+                None,
+                Metric::LEVENSHTEIN,
+            )
+            .unwrap();
+            let expected = cached.internal.get_neighbors_within(2).unwrap();
+
+            // Paths are accepted both as str and as os.PathLike.
+            let path_class = py.import("pathlib").unwrap().getattr("Path").unwrap();
+            let pathlike = path_class.call1((&path.0,)).unwrap();
+            Bound::new(py, cached)
+                .unwrap()
+                .call_method1("save", (pathlike,))
+                .unwrap();
+            let loaded = py
+                .get_type::<CachedRef>()
+                .call_method1("load", (path.0.to_str().unwrap(), "hamming"))
+                .unwrap();
+            let loaded = loaded.cast::<CachedRef>().unwrap().borrow();
+
+            assert_eq!(loaded.internal.len(), reference.len());
+            assert_eq!(loaded.internal.max_distance(), 2);
+            assert_eq!(loaded.internal.get(0), Some(reference[0]));
+            assert_eq!(loaded.metric, Metric(symscan::Metric::Hamming));
+            assert_eq!(loaded.internal.get_neighbors_within(2).unwrap(), expected);
+
+            // A file written by an incompatible version is rejected.
+            let mut data = std::fs::read(&path.0).unwrap();
+            data[8] += 1;
+            std::fs::write(&path.0, &data).unwrap();
+            let err = CachedRef::load(py, path.0.clone(), Metric::LEVENSHTEIN)
+                .err()
+                .expect("incompatible version");
+            assert!(err.is_instance_of::<CacheFormatError>(py));
+            assert!(err.is_instance_of::<PyValueError>(py));
+
+            // As is one that is truncated.
+            data[8] -= 1;
+            std::fs::write(&path.0, &data[..data.len() / 2]).unwrap();
+            let err = CachedRef::load(py, path.0.clone(), Metric::LEVENSHTEIN)
+                .err()
+                .expect("truncated file");
+            assert!(err.is_instance_of::<CacheFormatError>(py));
+
+            // I/O errors surface as OSError carrying the path.
+            std::fs::remove_file(&path.0).unwrap();
+            let err = CachedRef::load(py, path.0.clone(), Metric::LEVENSHTEIN)
+                .err()
+                .expect("missing file");
+            assert!(err.is_instance_of::<pyo3::exceptions::PyFileNotFoundError>(py));
+            let filename = err.value(py).getattr("filename").unwrap();
+            assert_eq!(filename.extract::<PathBuf>().unwrap(), path.0);
+        });
+    }
+
+    #[test]
+    fn test_shared() {
+        let reference = include_str!("../../test_files/cdr3b_10k_b.txt")
+            .lines()
+            .collect::<Vec<_>>();
+
+        Python::initialize();
+        Python::attach(|py| {
+            let cached = CachedRef::new(
+                PyList::new(py, &reference).unwrap().as_any(),
+                1,
+                None,
+                Metric(symscan::Metric::Hamming),
+            )
+            .unwrap();
+            let expected = cached.internal.get_neighbors_within(1).unwrap();
+
+            let handle = Bound::new(py, cached.to_shared(py, None).unwrap()).unwrap();
+            let opened = CachedRef::from_shared(py, handle.borrow()).unwrap();
+            assert_eq!(opened.internal.len(), reference.len());
+            assert_eq!(opened.metric, Metric(symscan::Metric::Hamming));
+            assert_eq!(opened.internal.get_neighbors_within(1).unwrap(), expected);
+
+            // Instances already opened remain usable once the segment is unlinked, but it can no
+            // longer be opened.
+            handle.borrow().unlink(py).unwrap();
+            assert_eq!(opened.internal.get_neighbors_within(1).unwrap(), expected);
+            drop(opened);
+            let err = CachedRef::from_shared(py, handle.borrow())
+                .err()
+                .expect("unlinked segment");
+            assert!(err.is_instance_of::<pyo3::exceptions::PyFileNotFoundError>(py));
+
+            // A segment that does not hold a CachedRef is rejected.
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("create", true).unwrap();
+            kwargs.set_item("size", 64).unwrap();
+            let shm = shared_memory_class(py)
+                .unwrap()
+                .call((), Some(&kwargs))
+                .unwrap();
+            let name = shm.getattr("name").unwrap().extract::<String>().unwrap();
+            let handle =
+                Bound::new(py, SharedCacheHandle::new(name, 64, Metric::LEVENSHTEIN)).unwrap();
+            let err = CachedRef::from_shared(py, handle.borrow())
+                .err()
+                .expect("invalid segment");
+            assert!(err.is_instance_of::<CacheFormatError>(py));
+            shm.call_method0("close").unwrap();
+            shm.call_method0("unlink").unwrap();
+        });
+    }
+
+    #[test]
+    fn test_search_query_chunk() {
+        let reference = include_str!("../../test_files/cdr3b_10k_b.txt")
+            .lines()
+            .collect::<Vec<_>>();
+        let query = include_str!("../../test_files/cdr3b_10k_a.txt")
+            .lines()
+            .collect::<Vec<_>>();
+
+        Python::initialize();
+        Python::attach(|py| {
+            let cached = CachedRef::new(
+                PyList::new(py, &reference).unwrap().as_any(),
+                1,
+                None,
+                Metric::LEVENSHTEIN,
+            )
+            .unwrap();
+            let query = PyList::new(py, &query).unwrap();
+            let (expected, _, _) = cached
+                .query_across(
+                    query.as_any(),
+                    1,
+                    None,
+                    false,
+                    Metric::LEVENSHTEIN,
+                    Missing::Raise,
+                )
+                .unwrap();
+
+            for chunk_size in [1_000, 3_333, 10_000, 20_000] {
+                let query_iter = query.try_iter().unwrap();
+                let mut num_queries = 0;
+                let mut pairs = ChunkedNeighborPairs::new(cached.internal.len());
+                let mut num_chunks = 0;
+                while let Some((hits, chunk_len)) = search_query_chunk(
+                    &cached.internal,
+                    cached.metric,
+                    &query_iter,
+                    num_queries,
+                    chunk_size,
+                    1,
+                    None,
+                )
+                .unwrap()
+                {
+                    assert!(hits.row.iter().all(|&row| (row as usize) < chunk_len));
+                    pairs.extend(hits, num_queries, chunk_len);
+                    num_queries += chunk_len;
+                    num_chunks += 1;
+                }
+                assert_eq!(num_queries, query.len());
+                assert_eq!(num_chunks, query.len().div_ceil(chunk_size));
+
+                let ChunkedNeighborPairs::Narrow(concatenated) = pairs else {
+                    panic!("indices widened for a small query");
+                };
+                assert_eq!(concatenated, expected);
+            }
+        });
+    }
+
+    #[test]
+    fn test_search_chunked() {
+        Python::initialize();
+        Python::attach(|py| {
+            let reference = PyList::new(py, ["fooo", "barr", "bazz", "buzz"]).unwrap();
+            let eval = |code: &str| py.eval(&CString::new(code).unwrap(), None, None);
+            let expected = symscan::get_neighbors_across(
+                &["fizz", "fuzz", "buzz"],
+                &["fooo", "barr", "bazz", "buzz"],
+                1,
+            )
+            .unwrap();
+
+            // Splitting the query of the docstring example into chunks of one string each
+            // reproduces the unchunked result, whether or not a CachedRef is provided.
+            let cached = Bound::new(
+                py,
+                CachedRef::new(reference.as_any(), 1, None, Metric::LEVENSHTEIN).unwrap(),
+            )
+            .unwrap();
+            for reference in [reference.as_any(), cached.as_any()] {
+                for chunk_size in [1, 2, 3, 1_000_000] {
+                    let generator = eval("(s for s in ['fizz', 'fuzz', 'buzz'])").unwrap();
+                    let pairs = search_chunked(&generator, reference, 1, chunk_size, None).unwrap();
+                    let ChunkedNeighborPairs::Narrow(pairs) = pairs else {
+                        panic!("indices widened for a small query");
+                    };
+                    assert_eq!(pairs, expected);
+                }
+            }
+
+            // Errors refer to the position of the offending string within the whole query.
+            let generator = eval("(s for s in ['fizz', 'fuzz', 'f\u{fc}zz'])").unwrap();
+            let err = search_chunked(&generator, reference.as_any(), 1, 1, None)
+                .err()
+                .expect("non-ASCII query");
+            assert!(err.is_instance_of::<NonAsciiInputError>(py));
+            assert_eq!(
+                err.value(py)
+                    .getattr("index")
+                    .unwrap()
+                    .extract::<usize>()
+                    .unwrap(),
+                2
+            );
+
+            let generator = eval("(s for s in ['fizz'])").unwrap();
+            let err = search_chunked(&generator, reference.as_any(), 1, 0, None)
+                .err()
+                .expect("zero chunk_size");
+            assert!(err.is_instance_of::<PyValueError>(py));
+            let err = search_chunked(
+                PyString::new(py, "fizz").as_any(),
+                reference.as_any(),
+                1,
+                1,
+                None,
+            )
+            .err()
+            .expect("str query");
+            assert!(err.is_instance_of::<PyValueError>(py));
+        });
+    }
+
+    #[test]
+    fn test_chunked_neighbor_pairs_widening() {
+        let chunk = || symscan::NeighborPairs {
+            row: vec![0, 1],
+            col: vec![3, 2],
+            dists: vec![1, 0],
+        };
+
+        let mut pairs = ChunkedNeighborPairs::new(4);
+        pairs.extend(chunk(), 10, 2);
+        let ChunkedNeighborPairs::Narrow(narrow) = &pairs else {
+            panic!("indices widened for a small query");
+        };
+        assert_eq!(narrow.row, vec![10, 11]);
+
+        // Once the query grows beyond the threshold, earlier rows are carried over into u64.
+        let offset = WIDE_INDEX_THRESHOLD - 1;
+        pairs.extend(chunk(), offset, 2);
+        let ChunkedNeighborPairs::Wide(wide) = pairs else {
+            panic!("indices not widened beyond the threshold");
+        };
+        let offset = offset as u64;
+        assert_eq!(wide.row, vec![10, 11, offset, offset + 1]);
+        assert_eq!(wide.col, vec![3, 2, 3, 2]);
+        assert_eq!(wide.dists, vec![1, 0, 1, 0]);
+
+        // The same applies to a CachedRef beyond the threshold, from the start.
+        let mut pairs = ChunkedNeighborPairs::new(WIDE_INDEX_THRESHOLD + 1);
+        pairs.extend(chunk(), 0, 2);
+        assert!(matches!(pairs, ChunkedNeighborPairs::Wide(_)));
+    }
+
+    #[test]
+    fn test_entry_point_dispatch() {
+        let query = include_str!("../../test_files/cdr3b_10k_a.txt")
+            .lines()
+            .take(2_000)
+            .collect::<Vec<_>>();
+        let reference = include_str!("../../test_files/cdr3b_10k_b.txt")
+            .lines()
+            .take(2_000)
+            .collect::<Vec<_>>();
+        let options = symscan::SearchOptions::default();
+
+        // Both sets of entry points find the same pairs, with indices of their own width.
+        let regular = Regular::within(&query, 1, &options).unwrap();
+        let large = Large::within(&query, 1, &options).unwrap();
+        assert!(!regular.row.is_empty());
+        assert_eq!(large, widen_neighbor_pairs(regular));
+
+        let regular = Regular::across(&query, &reference, 1, &options).unwrap();
+        let large = Large::across(&query, &reference, 1, &options).unwrap();
+        assert!(!regular.row.is_empty());
+        assert_eq!(large, widen_neighbor_pairs(regular));
+
+        // Thresholds beyond the cap of the other entry points are searched with u16 distances,
+        // which agree with theirs below it.
+        assert_eq!(
+            Wide::within(&query, 1, &options).unwrap(),
+            Regular::within(&query, 1, &options).unwrap().into()
+        );
+        assert_eq!(
+            Wide::across(&query, &reference, 1, &options).unwrap(),
+            Regular::across(&query, &reference, 1, &options)
+                .unwrap()
+                .into()
+        );
+        let long = ["a".repeat(300), "b".repeat(300), "a".into()];
+        let long = long.iter().map(String::as_str).collect::<Vec<_>>();
+        let wide = Wide::within(&long, 300, &options).unwrap();
+        assert_eq!(wide.dists, vec![300, 299, 300]);
+        let wide = Wide::across(&long, &long, 299, &options).unwrap();
+        assert_eq!(wide.row, vec![0, 0, 1, 2, 2]);
+        assert_eq!(wide.col, vec![0, 2, 1, 0, 2]);
+        assert_eq!(wide.dists, vec![0, 299, 0, 299, 0]);
+        assert!(matches!(
+            Regular::within(&long, 300, &options),
+            Err(symscan::Error::MaxDistCapped)
+        ));
+
+        // Positions beyond the u32 range survive restoring them into u64 indices.
+        let mut indices = vec![0_u64, 1];
+        let positions = [5, u32::MAX as usize + 5];
+        restore_positions(&mut indices, Some(&positions));
+        assert_eq!(indices, vec![5, u32::MAX as u64 + 5]);
+        assert_eq!(input_len(2, Some(&positions)), u32::MAX as usize + 6);
+    }
+
+    #[test]
+    fn test_path_inputs() {
+        let test_files = Path::new(env!("CARGO_MANIFEST_DIR")).join("../test_files");
+        let reference_path = test_files.join("cdr3b_10k_b.txt");
+        let query_path = test_files.join("cdr3b_10k_a.txt");
+        let reference = include_str!("../../test_files/cdr3b_10k_b.txt")
+            .lines()
+            .collect::<Vec<_>>();
+        let query = include_str!("../../test_files/cdr3b_10k_a.txt")
+            .lines()
+            .collect::<Vec<_>>();
+
+        Python::initialize();
+        Python::attach(|py| {
+            let path_class = py.import("pathlib").unwrap().getattr("Path").unwrap();
+            let query_pathlike = path_class.call1((&query_path,)).unwrap();
+            let (strings, positions) = get_input_strings(&query_pathlike, Missing::Skip).unwrap();
+            assert_eq!(strings.views(symscan::InputType::Query).unwrap(), query);
+            assert_eq!(positions, None);
+
+            let from_path = CachedRef::new(
+                PyString::new(py, reference_path.to_str().unwrap()).as_any(),
+                1,
+                None,
+                Metric::LEVENSHTEIN,
+            )
+            .unwrap();
+            let from_list = CachedRef::new(
+                PyList::new(py, &reference).unwrap().as_any(),
+                1,
+                None,
+                Metric::LEVENSHTEIN,
+            )
+            .unwrap();
+            let query_list = PyList::new(py, &query).unwrap();
+            let (expected, _, _) = from_list
+                .query_across(
+                    query_list.as_any(),
+                    1,
+                    None,
+                    false,
+                    Metric::LEVENSHTEIN,
+                    Missing::Raise,
+                )
+                .unwrap();
+            let (hits, _, _) = from_path
+                .query_across(
+                    &query_pathlike,
+                    1,
+                    None,
+                    false,
+                    Metric::LEVENSHTEIN,
+                    Missing::Raise,
+                )
+                .unwrap();
+            assert_eq!(hits, expected);
+
+            let non_ascii_path = std::env::temp_dir().join(format!(
+                "symscan_test_path_inputs_{}.txt",
+                std::process::id()
+            ));
+            std::fs::write(&non_ascii_path, "fizz\nf\u{fc}zz\n").unwrap();
+            let (strings, _) = get_input_strings(
+                PyString::new(py, non_ascii_path.to_str().unwrap()).as_any(),
+                Missing::Raise,
+            )
+            .unwrap();
+            let err = strings
+                .views(symscan::InputType::Query)
+                .expect_err("non-ASCII line");
+            std::fs::remove_file(&non_ascii_path).unwrap();
+            assert!(err.is_instance_of::<NonAsciiInputError>(py));
+            assert_eq!(
+                err.value(py).to_string(),
+                format!(
+                    "non-ASCII input currently unsupported ('f\u{fc}zz' at line 2 of '{}')",
+                    non_ascii_path.display()
+                )
+            );
+            let index: usize = err.value(py).getattr("index").unwrap().extract().unwrap();
+            assert_eq!(index, 1);
+
+            let err = get_input_strings(
+                PyString::new(py, "no/such/file.txt").as_any(),
+                Missing::Raise,
+            )
+            .err()
+            .expect("missing file");
+            assert!(err.is_instance_of::<pyo3::exceptions::PyFileNotFoundError>(py));
+        });
+    }
+
+    #[test]
+    fn test_neighbor_pairs_into_record_batch() {
+        use arrow_array::cast::AsArray;
+        use arrow_array::types::{Float32Type, UInt16Type, UInt32Type, UInt64Type, UInt8Type};
+        use arrow_schema::DataType;
+
+        let pairs = || symscan::NeighborPairs {
+            row: vec![1, 2, 2],
+            col: vec![3, 2, 3],
+            dists: vec![1, 1, 0],
+        };
+
+        let batch = neighbor_pairs_into_record_batch(pairs(), None);
+        let schema = batch.schema();
+        let fields = schema
+            .fields()
+            .iter()
+            .map(|f| (f.name().as_str(), f.data_type().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            vec![
+                ("query", DataType::UInt32),
+                ("reference", DataType::UInt32),
+                ("dist", DataType::UInt8),
+            ]
+        );
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(
+            batch.column(0).as_primitive::<UInt32Type>().values(),
+            &[1, 2, 2]
+        );
+        assert_eq!(
+            batch.column(1).as_primitive::<UInt32Type>().values(),
+            &[3, 2, 3]
+        );
+        assert_eq!(
+            batch.column(2).as_primitive::<UInt8Type>().values(),
+            &[1, 1, 0]
+        );
+
+        // Pairs from the `_large` entry points of the core keep their u64 indices.
+        let large = widen_neighbor_pairs(pairs());
+        let batch = neighbor_pairs_into_record_batch(large, Some(vec![0.75, 0.75, 1.0]));
+        assert_eq!(
+            batch.column(0).as_primitive::<UInt64Type>().values(),
+            &[1, 2, 2]
+        );
+        assert_eq!(
+            batch.column(1).as_primitive::<UInt64Type>().values(),
+            &[3, 2, 3]
+        );
+        assert_eq!(
+            batch.column(3).as_primitive::<Float32Type>().values(),
+            &[0.75, 0.75, 1.0]
+        );
+        assert_eq!(batch.schema().field(3).name(), "similarity");
+
+        // Pairs from the `_wide` entry points of the core keep their u16 distances.
+        let batch =
+            neighbor_pairs_into_record_batch(symscan::WideNeighborPairs::from(pairs()), None);
+        assert_eq!(batch.schema().field(2).data_type(), &DataType::UInt16);
+        assert_eq!(
+            batch.column(2).as_primitive::<UInt16Type>().values(),
+            &[1, 1, 0]
+        );
+
+        // The data survives a round trip through the C data interface, as used by pyarrow.
+        let data = StructArray::from(batch.clone()).into_data();
+        let (array, schema) = arrow_array::ffi::to_ffi(&data).unwrap();
+        let imported = unsafe { arrow_array::ffi::from_ffi(array, &schema) }.unwrap();
+        assert_eq!(RecordBatch::from(StructArray::from(imported)), batch);
+    }
+
+    #[test]
+    fn test_parse_output() {
+        assert_eq!(Output::parse("numpy").expect("known"), Output::Numpy);
+        assert_eq!(Output::parse("arrow").expect("known"), Output::Arrow);
+        Python::initialize();
+        let err = Output::parse("pandas").expect_err("unknown output");
+        assert!(err.to_string().contains("'numpy', 'arrow'"));
+    }
+
+    #[test]
+    fn test_split_mapping() {
+        Python::initialize();
+        Python::attach(|py| {
+            let values_of = |values: Bound<'_, PyList>| values.extract::<Vec<String>>().unwrap();
+
+            let query = [(101, "fizz"), (102, "fuzz"), (103, "buzz")]
+                .into_py_dict(py)
+                .unwrap();
+            let Some((Keys::Int(keys), values)) = split_mapping(query.as_any()).unwrap() else {
+                panic!("expected int keys");
+            };
+            assert_eq!(keys, vec![101, 102, 103]);
+            assert_eq!(values_of(values), vec!["fizz", "fuzz", "buzz"]);
+
+            let reference = [("a", "fooo"), ("b", "barr"), ("c", "bazz"), ("d", "buzz")]
+                .into_py_dict(py)
+                .unwrap();
+            let Some((Keys::Object(keys), values)) = split_mapping(reference.as_any()).unwrap()
+            else {
+                panic!("expected object keys");
+            };
+            let keys = keys
+                .iter()
+                .map(|k| k.extract::<String>(py).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(keys, vec!["a", "b", "c", "d"]);
+            assert_eq!(values_of(values), vec!["fooo", "barr", "bazz", "buzz"]);
+
+            // The keys index the same strings as the positional API, in insertion order.
+            let pairs = symscan::get_neighbors_across(
+                &["fizz", "fuzz", "buzz"],
+                &["fooo", "barr", "bazz", "buzz"],
+                1,
+            )
+            .unwrap();
+            let row_keys = pairs.row.iter().map(|&i| [101, 102, 103][i as usize]);
+            let col_keys = pairs.col.iter().map(|&i| keys[i as usize].as_str());
+            assert_eq!(
+                row_keys.zip(col_keys).collect::<Vec<_>>(),
+                vec![(102, "d"), (103, "c"), (103, "d")]
+            );
+
+            // Keys that are not all (non-bool) ints fall back to objects.
+            for keys in ["[1, 'b']", "[True, 2]", "[1, 2**64]"] {
+                let mapping = py
+                    .eval(
+                        &CString::new(format!("dict(zip({keys}, ['fizz', 'fuzz']))")).unwrap(),
+                        None,
+                        None,
+                    )
+                    .unwrap();
+                assert!(matches!(
+                    split_mapping(&mapping).unwrap(),
+                    Some((Keys::Object(_), _))
+                ));
+            }
+
+            let namespace = PyDict::new(py);
+            py.run(
+                cr#"
+from collections.abc import Mapping
+
+class Duplicated(Mapping):
+    def __getitem__(self, key):
+        return "fizz"
+    def __iter__(self):
+        return iter(["a", "a"])
+    def __len__(self):
+        return 2
+"#,
+                Some(&namespace),
+                None,
+            )
+            .unwrap();
+            let duplicated = namespace
+                .get_item("Duplicated")
+                .unwrap()
+                .unwrap()
+                .call0()
+                .unwrap();
+            let err = split_mapping(&duplicated).err().expect("duplicate keys");
+            assert!(err.to_string().contains("duplicate key 'a'"));
+
+            let list = PyList::new(py, ["fizz", "fuzz"]).unwrap();
+            assert!(split_mapping(list.as_any()).unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn test_transpose_neighbor_pairs() {
+        let reference = include_str!("../../test_files/cdr3b_10k_b.txt")
+            .lines()
+            .collect::<Vec<_>>();
+        let query = include_str!("../../test_files/cdr3b_10k_a.txt")
+            .lines()
+            .collect::<Vec<_>>();
+        let expected = symscan::get_neighbors_across(&query, &reference, 1).unwrap();
+
+        // Searching the reference against a cache of the query gives the same pairs once
+        // transposed, as when get_neighbors_across is given a CachedRef query.
+        let cached_query = symscan::CachedRef::new(&query, 1).unwrap();
+        let swapped = cached_query.get_neighbors_across(&reference, 1).unwrap();
+        let swapped_pairs = swapped.row.iter().copied().zip(swapped.col.iter().copied());
+        let swapped_pairs = swapped_pairs.collect::<Vec<_>>();
+        // Tag each pair with its original position to check that similarities follow the pairs.
+        let tags = (0..swapped.len()).map(|i| i as f32).collect::<Vec<_>>();
+        let (transposed, tags) = transpose_neighbor_pairs(swapped, Some(tags));
+        assert_eq!(transposed, expected);
+
+        for (i, tag) in tags.unwrap().into_iter().enumerate() {
+            let (row, col) = swapped_pairs[tag as usize];
+            assert_eq!((col, row), (transposed.row[i], transposed.col[i]));
+        }
+    }
+
+    #[test]
+    fn test_missing_values() {
+        Python::initialize();
+        Python::attach(|py| {
+            let query = py
+                .eval(
+                    c"['fizz', None, 'fuzz', float('nan'), 'buzz', 'nan']",
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let err = get_pystring_handles_with_missing(&query, Missing::Raise)
+                .expect_err("contains missing values");
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(err.to_string().contains("got None at index 1"));
+
+            let (handles, positions) =
+                get_pystring_handles_with_missing(&query, Missing::Skip).unwrap();
+            let views = get_str_refs(&handles).unwrap();
+            assert_eq!(views, vec!["fizz", "fuzz", "buzz", "nan"]);
+            assert_eq!(positions.as_deref(), Some(&[0, 2, 4, 5][..]));
+            assert_eq!(input_len(views.len(), positions.as_deref()), 6);
+
+            // Skipping preserves the original positional indexing of the remaining strings.
+            let mut pairs = symscan::get_neighbors_within(&views, 1).unwrap();
+            restore_positions(&mut pairs.row, positions.as_deref());
+            restore_positions(&mut pairs.col, positions.as_deref());
+            assert_eq!((pairs.row, pairs.col), (vec![0, 2], vec![2, 4]));
+
+            // Without missing values, there is nothing to restore.
+            let complete = PyList::new(py, ["fizz", "fuzz"]).unwrap();
+            let (_, positions) =
+                get_pystring_handles_with_missing(complete.as_any(), Missing::Skip).unwrap();
+            assert!(positions.is_none());
+
+            // The same applies to queries against a CachedRef.
+            let reference = PyList::new(py, ["fooo", "barr", "bazz", "buzz"]).unwrap();
+            let cached = CachedRef::new(reference.as_any(), 1, None, Metric::LEVENSHTEIN).unwrap();
+            let (hits, num_queries, _) = cached
+                .query_across(&query, 1, None, false, Metric::LEVENSHTEIN, Missing::Skip)
+                .unwrap();
+            assert_eq!((hits.row, hits.col), (vec![2, 4, 4], vec![3, 2, 3]));
+            assert_eq!(num_queries, 6);
+        });
+    }
+
+    #[test]
+    fn test_parse_missing() {
+        assert_eq!(Missing::parse("raise").expect("known"), Missing::Raise);
+        assert_eq!(Missing::parse("skip").expect("known"), Missing::Skip);
+        Python::initialize();
+        let err = Missing::parse("drop").expect_err("unknown policy");
+        assert!(err.to_string().contains("'raise', 'skip'"));
+    }
+
+    #[test]
+    fn test_needs_wide_indices() {
+        assert!(!needs_wide_indices(&[]));
+        assert!(!needs_wide_indices(&[0, 3]));
+        assert!(!needs_wide_indices(&[WIDE_INDEX_THRESHOLD]));
+        assert!(!needs_wide_indices(&[WIDE_INDEX_THRESHOLD, 10]));
+        assert!(needs_wide_indices(&[WIDE_INDEX_THRESHOLD + 1]));
+        assert!(needs_wide_indices(&[10, u32::MAX as usize]));
+        // Inputs that fit the int32 range are those the regular entry points of the core accept.
+        assert_eq!(WIDE_INDEX_THRESHOLD, i32::MAX as usize);
+    }
 }
@@ -1,25 +1,182 @@
 import numpy as np
 from numpy.typing import NDArray
-from typing import Iterable
+from os import PathLike
+from typing import TYPE_CHECKING, Any, Iterable, Iterator, Literal, Mapping, TypeAlias
+
+if TYPE_CHECKING:
+    # pyarrow is an optional dependency, only needed for output="arrow".
+    import pyarrow as pa
+
+# uint32, unless an input collection holds 2**31 strings or more.
+IndexArray: TypeAlias = NDArray[np.uint32] | NDArray[np.uint64]
+# uint8, unless max_distance exceeds 254.
+DistArray: TypeAlias = NDArray[np.uint8] | NDArray[np.uint16]
+NeighborArrays: TypeAlias = tuple[IndexArray, IndexArray, DistArray]
+NeighborArraysWithSimilarity: TypeAlias = tuple[
+    IndexArray, IndexArray, DistArray, NDArray[np.float32]
+]
+# A tuple of (insertion, deletion, substitution) costs selects the weighted Levenshtein distance.
+Metric: TypeAlias = Literal["levenshtein", "hamming", "osa", "indel"] | tuple[int, int, int]
+Output: TypeAlias = Literal["numpy", "arrow"]
+Missing: TypeAlias = Literal["raise", "skip"]
+# Any C-contiguous object supporting the buffer protocol, e.g. also mmap.mmap.
+Buffer: TypeAlias = bytes | bytearray | memoryview
+# The path to a text file holding one string per line.
+StrPath: TypeAlias = str | PathLike[str]
+
+class SymscanError(ValueError): ...
+
+class NonAsciiInputError(SymscanError):
+    input_type: str
+    index: int
+    value: str
+
+class MaxDistanceError(SymscanError):
+    got: int
+    limit: int
+
+class TooManyStringsError(SymscanError):
+    input_type: str
+    got: int
+    limit: int
+
+class CacheFormatError(SymscanError): ...
 
 def get_neighbors_within(
-    query: Iterable[str],
+    query: Iterable[str] | StrPath,
     max_distance: int = 1,
-) -> tuple[NDArray[np.uint32], NDArray[np.uint32], NDArray[np.uint8]]: ...
+    n_threads: int | None = None,
+    return_similarity: bool = False,
+    metric: Metric = "levenshtein",
+    missing: Missing = "raise",
+) -> NeighborArrays | NeighborArraysWithSimilarity: ...
 def get_neighbors_across(
+    query: Iterable[str] | StrPath | Mapping[Any, str] | "CachedRef",
+    reference: Iterable[str] | StrPath | Mapping[Any, str] | "CachedRef",
+    max_distance: int = 1,
+    n_threads: int | None = None,
+    return_similarity: bool = False,
+    metric: Metric | None = None,
+    output: Output = "numpy",
+    missing: Missing = "raise",
+) -> NeighborArrays | NeighborArraysWithSimilarity | "pa.Table": ...
+def get_neighbors_across_chunked(
     query: Iterable[str],
-    reference: Iterable[str],
+    reference: Iterable[str] | "CachedRef",
+    max_distance: int = 1,
+    chunk_size: int = 1_000_000,
+    n_threads: int | None = None,
+) -> NeighborArrays: ...
+def get_neighbors_within_buffer(
+    data: Buffer,
+    max_distance: int = 1,
+    delimiter: bytes = b"\n",
+    n_threads: int | None = None,
+    metric: Metric = "levenshtein",
+) -> NeighborArrays: ...
+def get_neighbors_across_buffer(
+    query: Buffer,
+    reference: Buffer,
     max_distance: int = 1,
-) -> tuple[NDArray[np.uint32], NDArray[np.uint32], NDArray[np.uint8]]: ...
+    delimiter: bytes = b"\n",
+    n_threads: int | None = None,
+    metric: Metric = "levenshtein",
+) -> NeighborArrays: ...
+def search(
+    query: Iterable[str] | StrPath | "CachedRef",
+    reference: Iterable[str] | StrPath | "CachedRef" | None = None,
+    max_distance: int = 1,
+    *,
+    n_threads: int | None = None,
+    return_similarity: bool = False,
+    metric: Metric = "levenshtein",
+    output: Output = "numpy",
+    missing: Missing = "raise",
+) -> NeighborArrays | NeighborArraysWithSimilarity | "pa.Table": ...
+def pairwise_distance(
+    a: Iterable[str],
+    b: Iterable[str],
+    score_cutoff: int | None = None,
+) -> NDArray[np.uint8] | NDArray[np.uint16] | NDArray[np.uint32] | NDArray[np.uint64]: ...
 
 class CachedRef:
-    def __init__(self, reference: Iterable[str], max_distance: int = 1) -> None: ...
+    def __init__(
+        self,
+        reference: Iterable[str] | StrPath,
+        max_distance: int = 1,
+        n_threads: int | None = None,
+        metric: Metric = "levenshtein",
+    ) -> None: ...
+    def add(self, new: Iterable[str]) -> None: ...
+    def __len__(self) -> int: ...
+    def __getitem__(self, idx: int) -> str: ...
+    @property
+    def max_distance(self) -> int: ...
+    @property
+    def nbytes(self) -> int: ...
+    @property
+    def metric(self) -> Metric: ...
+    def save(self, path: str | PathLike[str]) -> None: ...
+    @staticmethod
+    def load(path: str | PathLike[str], metric: Metric = "levenshtein") -> "CachedRef": ...
+    @staticmethod
+    def from_buffer(
+        data: Buffer,
+        max_distance: int = 1,
+        delimiter: bytes = b"\n",
+        n_threads: int | None = None,
+        metric: Metric = "levenshtein",
+    ) -> "CachedRef": ...
+    def to_shared(self, name: str | None = None) -> "SharedCacheHandle": ...
+    @staticmethod
+    def from_shared(handle: "SharedCacheHandle") -> "CachedRef": ...
     def get_neighbors_within(
         self,
         max_distance: int = 1,
-    ) -> tuple[NDArray[np.uint32], NDArray[np.uint32], NDArray[np.uint8]]: ...
+        n_threads: int | None = None,
+        return_similarity: bool = False,
+        metric: Metric | None = None,
+    ) -> NeighborArrays | NeighborArraysWithSimilarity: ...
     def get_neighbors_across(
+        self,
+        query: Iterable[str] | StrPath | Mapping[Any, str] | "CachedRef",
+        max_distance: int = 1,
+        n_threads: int | None = None,
+        return_similarity: bool = False,
+        metric: Metric | None = None,
+        output: Output = "numpy",
+        missing: Missing = "raise",
+    ) -> NeighborArrays | NeighborArraysWithSimilarity | "pa.Table": ...
+    def iter_neighbors_across(
+        self,
+        query: Iterable[str],
+        max_distance: int = 1,
+        chunk_rows: int = 1_000_000,
+        n_threads: int | None = None,
+    ) -> "NeighborChunks": ...
+    def radius_neighbors(
         self,
         query: Iterable[str] | "CachedRef",
         max_distance: int = 1,
-    ) -> tuple[NDArray[np.uint32], NDArray[np.uint32], NDArray[np.uint8]]: ...
+        n_threads: int | None = None,
+        metric: Metric | None = None,
+    ) -> tuple[list[IndexArray], list[NDArray[np.uint8]]]: ...
+    def query_one(
+        self, query: str, max_distance: int = 1
+    ) -> tuple[IndexArray, NDArray[np.uint8]]: ...
+
+class NeighborChunks(Iterator[NeighborArrays]):
+    def __iter__(self) -> "NeighborChunks": ...
+    def __next__(self) -> NeighborArrays: ...
+
+class SharedCacheHandle:
+    def __init__(
+        self, name: str, nbytes: int, metric: Metric = "levenshtein"
+    ) -> None: ...
+    @property
+    def name(self) -> str: ...
+    @property
+    def nbytes(self) -> int: ...
+    @property
+    def metric(self) -> Metric: ...
+    def unlink(self) -> None: ...
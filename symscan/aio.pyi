@@ -0,0 +1,18 @@
+# Stubs for the symscan.aio submodule of the Python bindings, shipped in the wheel next to the
+# stubs of the symscan package itself (see pyproject.toml).
+import asyncio
+from typing import Iterable
+
+from symscan import CachedRef, NeighborArrays
+
+def get_neighbors_within(
+    query: Iterable[str],
+    max_distance: int = 1,
+    n_threads: int | None = None,
+) -> asyncio.Future[NeighborArrays]: ...
+def get_neighbors_across(
+    query: Iterable[str],
+    reference: Iterable[str] | CachedRef,
+    max_distance: int = 1,
+    n_threads: int | None = None,
+) -> asyncio.Future[NeighborArrays]: ...
@@ -1,6 +1,10 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use std::io::{self, BufRead, Cursor};
-use symscan::{get_neighbors_across, get_neighbors_within, CachedRef};
+use symscan::{
+    compute_distances, get_candidate_pairs_across, get_neighbors_across,
+    get_neighbors_across_with_options, get_neighbors_within, get_neighbors_within_with_options,
+    CachedRef, SearchOptions,
+};
 
 static QUERY_BYTES: &[u8] = include_bytes!("../../test_files/cdr3b_10k_a.txt");
 static REFERENCE_BYTES: &[u8] = include_bytes!("../../test_files/cdr3b_10k_b.txt");
@@ -30,6 +34,69 @@ fn setup_benchmarks(c: &mut Criterion) {
         })
     });
 
+    let mut merge_options = SearchOptions::default();
+    merge_options.merge_sorted_runs = true;
+    c.bench_function("get_candidates_cross (merged runs)", |b| {
+        b.iter(|| {
+            let _ = get_neighbors_across_with_options(&query, &reference, 1, &merge_options);
+        })
+    });
+
+    let mut fused_options = SearchOptions::default();
+    fused_options.fuse_verification = true;
+    c.bench_function("get_candidates_cross (fused verification)", |b| {
+        b.iter(|| {
+            let _ = get_neighbors_across_with_options(&query, &reference, 1, &fused_options);
+        })
+    });
+
+    // Strings that all derive from a handful of hubs, so that convergence groups are large and
+    // most candidates are rejected on verification.
+    let skewed = (0..2_000)
+        .map(|i| {
+            let mut s = format!("CASSL{:02}GQETQYF", i % 7).into_bytes();
+            let pos = (i / 7) % s.len();
+            s[pos] = b"ACDEFGHIKLMNPQRSTVWY"[(i / 7 / s.len()) % 20];
+            String::from_utf8(s).expect("ASCII")
+        })
+        .collect::<Vec<_>>();
+    c.bench_function("get_candidates_within (skewed)", |b| {
+        b.iter(|| {
+            let _ = get_neighbors_within(&skewed, 2);
+        })
+    });
+    c.bench_function("get_candidates_within (skewed, fused verification)", |b| {
+        b.iter(|| {
+            let _ = get_neighbors_within_with_options(&skewed, 2, &fused_options);
+        })
+    });
+
+    // Tiny inputs, for which all pairs are compared directly by default.
+    let (tiny_query, tiny_reference) = (&query[..200], &reference[..200]);
+    let mut symdel_options = SearchOptions::default();
+    symdel_options.brute_force_max_pairs = Some(0);
+    c.bench_function("get_candidates_within (200 strings)", |b| {
+        b.iter(|| {
+            let _ = get_neighbors_within(tiny_query, 2);
+        })
+    });
+    c.bench_function("get_candidates_within (200 strings, symdel)", |b| {
+        b.iter(|| {
+            let _ = get_neighbors_within_with_options(tiny_query, 2, &symdel_options);
+        })
+    });
+    c.bench_function("get_candidates_cross (200 strings)", |b| {
+        b.iter(|| {
+            let _ = get_neighbors_across(tiny_query, tiny_reference, 2);
+        })
+    });
+    c.bench_function("get_candidates_cross (200 strings, symdel)", |b| {
+        b.iter(|| {
+            let _ =
+                get_neighbors_across_with_options(tiny_query, tiny_reference, 2, &symdel_options);
+        })
+    });
+
     c.bench_function("get_candidates_within (cached)", |b| {
         b.iter(|| {
             let _ = cached_reference.get_neighbors_within(1);
@@ -42,6 +109,30 @@ fn setup_benchmarks(c: &mut Criterion) {
         })
     });
 
+    let batches = query.chunks(100).collect::<Vec<_>>();
+    c.bench_function(
+        "get_candidates_cross (partially cached, 100 queries)",
+        |b| {
+            b.iter(|| {
+                for batch in &batches {
+                    let _ = cached_reference.get_neighbors_across(batch, 1);
+                }
+            })
+        },
+    );
+    c.bench_function(
+        "get_candidates_cross (partially cached, 100 queries, reused scratch)",
+        |b| {
+            let mut scratch = cached_reference.query_scratch();
+            b.iter(|| {
+                for batch in &batches {
+                    let _ =
+                        cached_reference.get_neighbors_across_with_scratch(batch, 1, &mut scratch);
+                }
+            })
+        },
+    );
+
     c.bench_function("get_candidates_cross (fully cached)", |b| {
         b.iter(|| {
             let _ = cached_reference.get_neighbors_across_cached(&cached_query, 1);
@@ -53,6 +144,109 @@ fn setup_benchmarks(c: &mut Criterion) {
             let _ = CachedRef::new(&reference, 1);
         })
     });
+
+    // Dominated by the generation of single-deletion variants, which has its own code path.
+    c.bench_function("cached instantiation (query, d=1)", |b| {
+        b.iter(|| {
+            let _ = CachedRef::new(&query, 1);
+        })
+    });
+    c.bench_function("cached instantiation (query, d=2)", |b| {
+        b.iter(|| {
+            let _ = CachedRef::new(&query, 2);
+        })
+    });
+
+    // The candidate stage alone, whose output the verification benchmarks below start from.
+    c.bench_function("get_candidate_pairs_across (d=2)", |b| {
+        b.iter(|| {
+            let _ = get_candidate_pairs_across(&query, &reference, 2);
+        })
+    });
+
+    // The verification phase alone, over candidates sorted by query string, many of which have
+    // dozens of candidates each.
+    let candidates_d2 = get_candidate_pairs_across(&query, &reference, 2).expect("short input");
+    c.bench_function("compute_distances (cross candidates, d=2)", |b| {
+        b.iter(|| {
+            let _ = compute_distances(&candidates_d2, &query, &reference, 2);
+        })
+    });
+    // Bounds of 4 and above are where rapidfuzz consults the bit-parallel pattern of a query
+    // string, rather than enumerating the few possible edit sequences.
+    c.bench_function("compute_distances (cross candidates, d=2, bound 4)", |b| {
+        b.iter(|| {
+            let _ = compute_distances(&candidates_d2, &query, &reference, 4);
+        })
+    });
+
+    #[cfg(feature = "bench")]
+    setup_sort_benchmarks(c, &query);
+}
+
+/// Compare the radix sort of (hash, index) pairs against sorting them by comparison. Run with
+/// `cargo bench --features bench`.
+#[cfg(feature = "bench")]
+fn setup_sort_benchmarks(c: &mut Criterion, query: &[String]) {
+    use criterion::BatchSize;
+    use symscan::sorting::{comparison_sort, get_variant_index_pairs, radix_sort};
+
+    let bench_sorts = |c: &mut Criterion, name: &str, pairs: &[(u64, u32)], sample_size| {
+        let mut group = c.benchmark_group(format!("sort {name}"));
+        group.sample_size(sample_size);
+        group.bench_function("radix", |b| {
+            b.iter_batched(
+                || pairs.to_vec(),
+                |mut pairs| radix_sort(&mut pairs),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function("comparison", |b| {
+            b.iter_batched(
+                || pairs.to_vec(),
+                |mut pairs| comparison_sort(&mut pairs),
+                BatchSize::LargeInput,
+            )
+        });
+        group.finish();
+    };
+
+    bench_sorts(
+        c,
+        "variant index pairs (10k, d=1)",
+        &get_variant_index_pairs(query, 1).expect("short input"),
+        100,
+    );
+    bench_sorts(
+        c,
+        "variant index pairs (10k, d=2)",
+        &get_variant_index_pairs(query, 2).expect("short input"),
+        100,
+    );
+
+    // Ten million random strings of six residues, of which single deletions yield 70 million
+    // pairs. This takes a few GB of memory.
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let synthetic = (0..10_000_000)
+        .map(|_| {
+            (0..6)
+                .map(|_| {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    b"ACDEFGHIKLMNPQRSTVWY"[(state >> 33) as usize % 20] as char
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>();
+    let synthetic_pairs = get_variant_index_pairs(&synthetic, 1).expect("short input");
+    drop(synthetic);
+    bench_sorts(
+        c,
+        "variant index pairs (10M synthetic, d=1)",
+        &synthetic_pairs,
+        10,
+    );
 }
 
 criterion_group!(bench, setup_benchmarks);
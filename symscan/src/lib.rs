@@ -18,22 +18,38 @@
 //! the query (e.g. reference-side memoization when making repeated queries against a very large
 //! reference collection with relatively smaller query collections). For such cases, the library
 //! also provides the [`CachedRef`] struct.
+//!
+//! With the `tracing` feature enabled, searches and the construction of [`CachedRef`] instances
+//! are instrumented with [`tracing`](https://docs.rs/tracing) spans for each of their phases, and
+//! unusually large groups of strings sharing a deletion variant are reported as warning events.
+//! With the `testing` feature enabled, the `naive` module provides brute-force equivalents of the
+//! searches to test against.
 
 use foldhash::fast::FixedState;
-use hashbrown::HashMap;
-use itertools::Itertools;
-use rapidfuzz::distance::levenshtein;
+use hashbrown::{HashMap, HashSet};
+use itertools::{izip, Either, Itertools};
+use rapidfuzz::distance::{hamming, indel, levenshtein, osa};
 use rayon::prelude::*;
-use std::fmt::Display;
+use rayon::ThreadPool;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::fs::{self, File};
 use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
 use std::mem::MaybeUninit;
-use std::ops::Range;
-use std::{ptr, str, u8, usize};
-use thiserror;
-use utils::{CrossIndex, MaxDistance};
+use std::ops::{Deref, Range};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use std::{iter, ptr, slice, str};
+use utils::{CrossIndex, MaxDistance, VariantIndexPair};
 
 /// Used to specify the source of certain [`Error`] variants.
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum InputType {
     Query,
     Reference,
@@ -53,7 +69,10 @@ impl Display for InputType {
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     /// An input collection contained references to at least one non-ASCII string.
-    #[error("non-ASCII input currently unsupported ('{offending_string}' at {offending_idx})")]
+    #[error(
+        "non-ASCII input ('{offending_string}' at {offending_idx}) is only accepted with the \
+         `unicode` search option enabled (see `SearchOptions::unicode`)"
+    )]
     NonAsciiInput {
         input_type: InputType,
         offending_idx: usize,
@@ -66,7 +85,8 @@ pub enum Error {
     /// internal computations use [`u32`]s to encode string indices. The exception is when calling
     /// [`get_neighbors_across`], where the maximum is instead 2,147,483,647 ((2^31)-1) due to the
     /// fact that one of the 32 bits is reserved for distinguishing between indexes of the `query`
-    /// slice and the `reference` slice.
+    /// slice and the `reference` slice. [`get_neighbors_within_large`] and
+    /// [`get_neighbors_across_large`] lift these limits.
     #[error("{input_type} must not hold more than {limit} elements, got {got}")]
     TooManyStrings {
         input_type: InputType,
@@ -78,7 +98,17 @@ pub enum Error {
     ///
     /// This results in an error because that value is reserved for encoding when pairs exceed the
     /// threshold distance during internal computations.
-    #[error("max_distance is capped at {limit}, got {illegal}", limit = u8::MAX - 1, illegal = u8::MAX)]
+    ///
+    /// Each string of length `n` has `sum(C(n, k))` deletion variants for `k` up to `max_distance`,
+    /// so that symmetric deletion search becomes intractable long before distances approach 255.
+    /// Larger thresholds, of up to [`u16::MAX`], can be searched with [`get_neighbors_within_wide`]
+    /// and [`get_neighbors_across_wide`], which compare the strings directly instead.
+    #[error(
+        "max_distance is capped at {limit}, got {illegal} (see get_neighbors_within_wide for \
+         larger thresholds)",
+        limit = u8::MAX - 1,
+        illegal = u8::MAX
+    )]
     MaxDistCapped,
 
     /// The `max_distance` method parameter was set to a value greater than that given when
@@ -90,1495 +120,17412 @@ pub enum Error {
     /// queries with `max_distance` > X.
     #[error("CachedRef instance not compatible with max_distance above {limit}, got {got}")]
     MaxDistTooLargeForCache { got: u8, limit: u8 },
-}
-
-mod utils {
-    use super::Error;
-
-    #[derive(Clone, Copy, PartialEq, PartialOrd)]
-    pub struct MaxDistance(u8);
 
-    impl MaxDistance {
-        pub fn as_u8(&self) -> u8 {
-            self.0
-        }
+    /// An I/O error occurred while reading or writing a serialized [`CachedRef`], or the spill
+    /// files of [`SearchOptions::low_memory`].
+    #[error(transparent)]
+    Io(#[from] io::Error),
 
-        pub fn as_usize(&self) -> usize {
-            self.0 as usize
-        }
-    }
+    /// A serialized [`CachedRef`] was written using a different version of the serialization
+    /// format than the one supported by this version of the crate.
+    #[error("serialized CachedRef has format version {got}, expected {expected}")]
+    IncompatibleCacheVersion { got: u32, expected: u32 },
 
-    impl TryFrom<u8> for MaxDistance {
-        type Error = Error;
+    /// The data being deserialized is not a valid serialized [`CachedRef`].
+    #[error("malformed CachedRef data ({0})")]
+    MalformedCache(&'static str),
 
-        fn try_from(value: u8) -> Result<Self, Self::Error> {
-            if value == u8::MAX {
-                Err(Error::MaxDistCapped)
-            } else {
-                Ok(Self(value))
-            }
-        }
-    }
+    /// The computation was aborted via the [`CancellationToken`] in its [`SearchOptions`].
+    #[error("computation was cancelled")]
+    Cancelled,
 
-    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-    pub struct CrossIndex(u32);
+    /// The buffer passed to [`CachedRef::write_shared`] is smaller than
+    /// [`CachedRef::shared_size`].
+    #[error("buffer of {got} bytes is too small to hold the CachedRef, need {required}")]
+    SharedBufferTooSmall { got: usize, required: usize },
 
-    impl CrossIndex {
-        const TYPE_MASK: u32 = 1 << 31;
-        const VALUE_MASK: u32 = !Self::TYPE_MASK;
-        pub const MAX: usize = (1 << 31) - 1;
+    /// The offsets passed to [`get_neighbors_within_offsets`], [`get_neighbors_across_offsets`] or
+    /// [`CachedRef::from_offsets`] are not non-decreasing, or are negative or point past the end
+    /// of the buffer.
+    #[error(
+        "{input_type} offsets must be non-decreasing and within the buffer, got {got} at {idx}"
+    )]
+    InvalidOffsets {
+        input_type: InputType,
+        idx: usize,
+        got: i128,
+    },
 
-        pub fn from(value: u32, is_ref: bool) -> Self {
-            debug_assert_ne!(value & Self::TYPE_MASK, Self::TYPE_MASK);
+    /// The search would need to verify more candidate pairs than [`SearchOptions::max_candidates`]
+    /// (or than can be held in memory at all). This typically happens when many strings are
+    /// short enough to share a deletion variant, such as the empty string. `largest_group` holds
+    /// the numbers of query and reference strings sharing the variant that contributes the most
+    /// candidates (for searches within a collection, both are the same).
+    #[error(
+        "search would verify {got} candidate pairs, more than the limit of {limit} (largest \
+         group of {} query and {} reference strings)",
+        largest_group.0,
+        largest_group.1
+    )]
+    TooManyCandidates {
+        got: u128,
+        limit: usize,
+        largest_group: (usize, usize),
+    },
 
-            if is_ref {
-                Self(value | Self::TYPE_MASK)
-            } else {
-                Self(value)
-            }
-        }
+    /// The `min_ratio` passed to [`get_neighbors_within_ratio`] or
+    /// [`CachedRef::get_neighbors_within_ratio`] does not lie between 0 and 1.
+    #[error("min_ratio must lie between 0 and 1, got {got}")]
+    InvalidRatio { got: f32 },
 
-        pub fn is_ref(&self) -> bool {
-            self.0 & Self::TYPE_MASK == Self::TYPE_MASK
-        }
+    /// The `min_ratio` passed to [`get_neighbors_within_ratio`] or
+    /// [`CachedRef::get_neighbors_within_ratio`] is so low that the longest strings would have to
+    /// be searched beyond the largest supported `max_distance` (see [`Error::MaxDistCapped`]).
+    #[error(
+        "min_ratio {got} requires max_distance {required} for strings of length {max_len}, \
+         which is capped at {limit}",
+        limit = u8::MAX - 1
+    )]
+    RatioTooLow {
+        got: f32,
+        max_len: usize,
+        required: usize,
+    },
 
-        pub fn get_value(&self) -> u32 {
-            self.0 & Self::VALUE_MASK
-        }
-    }
-}
+    /// The two string collections passed to [`pairwise_distances`] differ in length.
+    #[error("query and reference must be of equal length, got {query} and {reference}")]
+    MismatchedLengths { query: usize, reference: usize },
 
-#[derive(Default)]
-struct IdentityHasher(u64);
+    /// The costs passed to [`EditCosts::new`] include a cost of 0.
+    #[error(
+        "edit costs must be at least 1, got insertion {insertion}, deletion {deletion} and \
+         substitution {substitution}"
+    )]
+    InvalidEditCosts {
+        insertion: u8,
+        deletion: u8,
+        substitution: u8,
+    },
 
-impl Hasher for IdentityHasher {
-    fn write(&mut self, bytes: &[u8]) {
-        unreachable!("hasher only designed for u64, got {bytes:?}");
-    }
+    /// The [`SearchOptions::excluded_pairs`] are not strictly increasing, or the pair at `idx`
+    /// refers to a string beyond the input (or, in searches within a single collection, does not
+    /// have a row index smaller than its column index).
+    #[error("excluded pair {pair:?} at {idx} is out of order or out of range")]
+    InvalidExcludedPair { idx: usize, pair: (u32, u32) },
 
-    fn write_u64(&mut self, i: u64) {
-        self.0 = i
-    }
+    /// The pair at `idx` of those passed to [`distances_for_pairs`] or
+    /// [`CachedRef::distances_for_pairs_cached`] refers to a string beyond the query or the
+    /// reference.
+    #[error(
+        "pair {pair:?} at {idx} is out of range for {query_len} query and {reference_len} \
+         reference strings"
+    )]
+    PairOutOfRange {
+        idx: usize,
+        pair: (u32, u32),
+        query_len: usize,
+        reference_len: usize,
+    },
 
-    fn finish(&self) -> u64 {
-        self.0
-    }
-}
+    /// The labels passed to [`get_neighbors_within_grouped`] are not one per string.
+    #[error("expected one label per string, got {labels} labels for {strings} strings")]
+    MismatchedLabels { strings: usize, labels: usize },
 
-#[derive(Default)]
-struct IdentityHasherBuilder;
+    /// A [`SearchConfig`] was run against a [`CachedRef`] that was constructed to compare
+    /// strings with a different case sensitivity (see [`SearchOptions::case_insensitive`]).
+    #[error("CachedRef instance has case_insensitive = {cache}, but the config has {config}")]
+    MismatchedCaseSensitivity { cache: bool, config: bool },
 
-impl BuildHasher for IdentityHasherBuilder {
-    type Hasher = IdentityHasher;
+    /// A [`CachedRef`] was queried with another [`CachedRef`], or run under a [`SearchConfig`],
+    /// that hashes deletion variants to a different width (see [`SearchOptions::narrow_hashes`]).
+    /// The widths are given in bits.
+    #[error(
+        "CachedRef instance keys deletion variants on {cache}-bit hashes, but got {got}-bit hashes"
+    )]
+    MismatchedHashWidth { cache: u32, got: u32 },
 
-    fn build_hasher(&self) -> Self::Hasher {
-        IdentityHasher::default()
-    }
+    /// An input collection contained a string with more deletion variants at `max_distance` than
+    /// can be generated (more than [4,294,967,295](u32::MAX)). The number of deletion variants of
+    /// a string of length `n` grows as `n^max_distance`, so this is only reached by very long
+    /// strings, which are better compared directly with [`pairwise_distances`].
+    #[error(
+        "{input_type} string at {offending_idx} of length {len} has too many deletion variants \
+         at max_distance {max_distance}"
+    )]
+    InputTooLong {
+        input_type: InputType,
+        offending_idx: usize,
+        len: usize,
+        max_distance: u8,
+    },
 }
 
-struct Span {
-    start: usize,
-    len: usize,
-}
+/// A handle that can be used to abort an ongoing computation from another thread.
+///
+/// Clones of a token share the same state, so that cancelling any one of them cancels all. See
+/// [`SearchOptions::cancellation`].
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
 
-impl Span {
-    fn new(start: usize, len: usize) -> Self {
-        Span { start, len }
+impl CancellationToken {
+    /// Construct a new, uncancelled token.
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    fn len(&self) -> usize {
-        self.len
+    /// Request cancellation of any computations using this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
     }
 
-    #[inline(always)]
-    fn as_range(&self) -> Range<usize> {
-        self.start..self.start + self.len
+    /// Whether cancellation has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
     }
 }
 
-/// Collection of string pairs that lie within the specified Levenshtein edit distance threshold.
+/// A phase of a search, as reported to a [`ProgressSink`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Phase {
+    /// Generating the deletion variants of the input strings, in units of strings.
+    VariantGeneration,
+    /// Sorting the deletion variants to find those that are shared, in units of variants.
+    Sorting,
+    /// Enumerating the candidate pairs of strings that share deletion variants, in units of
+    /// candidate pairs.
+    CandidateGeneration,
+    /// Computing the distances of candidate pairs, in units of candidate pairs. When candidates
+    /// are verified as they are enumerated (see [`SearchOptions::fuse_verification`]), or only
+    /// until each query string has a hit, the units are groups of strings sharing a deletion
+    /// variant instead, or query strings if the candidates of each are verified in turn (see
+    /// [`orphans_within`]).
+    DistanceComputation,
+}
+
+/// A receiver of progress reports from a search (see [`SearchOptions::progress`]).
 ///
-/// This is what is returned via the [`Ok`] variant from [`get_neighbors_within`],
-/// [`get_neighbors_across`], and related methods in [`CachedRef`]. [`row`](NeighborPairs::row) and
-/// [`col`](NeighborPairs::col) contain the indices of the neighbor string pairs, and
-/// [`dists`](NeighborPairs::dists) contains the Levenshtein distances between the corresponding
-/// pairs.
+/// At the start of each [`Phase`] of a search, [`ProgressSink::phase_started`] is called with the
+/// total number of units of work in the phase, after which [`ProgressSink::advance`] is called as
+/// units are completed. A phase may be run more than once per search (e.g. for the query and for
+/// the reference), and phases may be skipped (e.g. for tiny inputs, which are compared directly).
+/// Progress is reported in batches of thousands of units, possibly from several threads at once.
 ///
-/// # A note on double-counting pairs
+/// # Examples
 ///
-/// When returning the results of [`get_neighbors_within`] / [`CachedRef::get_neighbors_within`],
-/// string pairs _**ARE NOT**_ double-counted. As seen in the
-/// [examples](get_neighbors_within#examples), each pair is represented once where the
-/// [`row`](NeighborPairs::row) index is always less than the [`col`](NeighborPairs::col) index. In
-/// other words, if you were to interpret the [`NeighborPairs`] in these situations as a sparse
-/// matrix, only the lower triangle will be filled.
-#[derive(Debug, PartialEq)]
-pub struct NeighborPairs {
-    /// Indices of strings in the input `query` slice that have neighbors.
-    pub row: Vec<u32>,
-
-    /// Indices of neighbor strings. When computing neighbor pairs across separate `query` and
-    /// `reference` slices, then `query[row[i]]` and `reference[col[i]]` are neighbors. When
-    /// computing neighbor pairs within a single `query` slice, `query[row[i]]` and `query[col[i]]`
-    /// are neighbors.
-    pub col: Vec<u32>,
+/// ```
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::sync::Arc;
+/// use symscan::{get_neighbors_within_with_options, Phase, ProgressSink, SearchOptions};
+///
+/// #[derive(Default)]
+/// struct Counter(AtomicU64);
+///
+/// impl ProgressSink for Counter {
+///     fn phase_started(&self, phase: Phase, total_units: u64) {
+///         println!("{phase:?}: {total_units} units");
+///     }
+///
+///     fn advance(&self, units: u64) {
+///         self.0.fetch_add(units, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let counter = Arc::new(Counter::default());
+/// let mut options = SearchOptions::default();
+/// options.progress = Some(counter.clone());
+/// options.brute_force_max_pairs = Some(0);
+///
+/// get_neighbors_within_with_options(&["fizz", "fuzz", "buzz"], 1, &options).unwrap();
+///
+/// assert!(counter.0.load(Ordering::Relaxed) > 0);
+/// ```
+pub trait ProgressSink: Send + Sync {
+    /// Called at the start of a phase, with the total number of units of work in it.
+    fn phase_started(&self, phase: Phase, total_units: u64);
 
-    /// Edit distances between neighbor string pairs. When computing neighbor pairs across separate
-    /// `query` and `reference` slices, then `Levenshtein(query[row[i]], reference[col[i]]) ==
-    /// dists[i]`. When computing neighbor pairs within a single `query` slice,
-    /// `Levenshtein(query[row[i]], query[col[i]]) == dists[i]`.
-    pub dists: Vec<u8>,
+    /// Called as units of work of the current phase are completed.
+    fn advance(&self, units: u64);
 }
 
-impl NeighborPairs {
-    /// The number of neighboring string pairs detected.
-    pub fn len(&self) -> usize {
-        self.row.len()
+impl Debug for dyn ProgressSink + '_ {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressSink")
     }
 }
 
-/// A struct for memoizing the deletion variant calculations for a string collection.
-///
-/// When [constructed](CachedRef::new), [`CachedRef`] precomputes and stores the deletion variants
-/// for the supplied `reference` strings as a hashmap. This significantly speeds up subsequent
-/// queries against the reference, at the cost of spending extra time to construct the hashmap.
-/// This is useful for use-cases where you want to repeatedly query the same reference, especially
-/// if the reference is very large. However, for one-off computations, the pure functions
-/// [`get_neighbors_within`] and [`get_neighbors_across`] are faster.
-///
-/// **Note** that [`CachedRef`] instances constructed with `max_distance` set to X can only support
-/// queries with `max_distance` less than or equal to X.
+/// Statistics of a search, as returned by [`get_neighbors_within_with_stats`] and related
+/// functions, which show whether the time of a slow search goes into generating deletion
+/// variants, into large convergence groups, or into verifying candidates.
 ///
-/// **Note** when interpreting the index order of returned [`NeighborPairs`], the string collection
-/// specified at construction is considered the _reference_, and any string collections specified
-/// during subsequent query calls are considered the _query_.
+/// Counts are summed over all runs of a phase (e.g. for the query and for the reference), and
+/// are left at zero for skipped phases. In particular, tiny inputs are compared directly (see
+/// [`SearchOptions::brute_force_max_pairs`]), without generating any deletion variants.
 ///
 /// # Examples
 ///
 /// ```
-/// use symscan::{CachedRef, NeighborPairs};
-///
-/// let reference = ["fooo", "barr", "bazz", "buzz"];
-/// let cached = CachedRef::new(&reference, 2).unwrap();
+/// use symscan::{get_neighbors_within_with_stats, Phase, SearchOptions};
 ///
-/// let NeighborPairs { row, col, dists } = cached
-///     .get_neighbors_across(&["fizz", "fuzz", "buzz"], 1)
-///     .unwrap();
-///
-/// assert_eq!(row,   vec![1, 2, 2]);
-/// assert_eq!(col,   vec![3, 2, 3]);
-/// assert_eq!(dists, vec![1, 1, 0]);
+/// let mut options = SearchOptions::default();
+/// options.brute_force_max_pairs = Some(0);
 ///
-/// let NeighborPairs { row, col, dists } = cached
-///     .get_neighbors_across(&["fizz", "fuzz", "buzz"], 2)
+/// let (hits, stats) = get_neighbors_within_with_stats(&["fizz", "fuzz", "buzz"], 1, &options)
 ///     .unwrap();
 ///
-/// assert_eq!(row,   vec![0, 0, 1, 1, 2, 2]);
-/// assert_eq!(col,   vec![2, 3, 2, 3, 2, 3]);
-/// assert_eq!(dists, vec![2, 2, 2, 1, 1, 0]);
+/// // Deleting either "z" of each string yields the same variant, which is only counted once.
+/// assert_eq!(stats.num_variants, 12);
+/// assert_eq!(stats.num_candidates, 2);
+/// assert_eq!(stats.num_hits, hits.len() as u64);
+/// assert_eq!(stats.phase_times[0].0, Phase::VariantGeneration);
 /// ```
-pub struct CachedRef {
-    str_store: Vec<u8>,
-    str_spans: Vec<Span>,
-    index_store: Vec<u32>,
-    variant_map: HashMap<u64, Span, IdentityHasherBuilder>,
-    max_distance: MaxDistance,
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SearchStats {
+    /// The number of deletion variants generated, counting those that a string has more than once
+    /// (e.g. through runs of repeated symbols) once.
+    pub num_variants: u64,
+    /// The number of convergence groups, i.e. of groups of strings sharing a deletion variant.
+    pub num_convergence_groups: u64,
+    /// The number of strings in the largest convergence group.
+    pub largest_convergence_group: u64,
+    /// The number of candidate pairs enumerated from the convergence groups, before duplicates
+    /// are removed.
+    pub num_candidates: u64,
+    /// The number of candidate pairs whose distances are computed, after duplicates are removed.
+    /// Candidates verified as they are enumerated (see [`SearchOptions::fuse_verification`]) are
+    /// not counted.
+    pub num_verified: u64,
+    /// The number of pairs returned.
+    pub num_hits: u64,
+    /// The wall time spent in each phase, from its start to the start of the next phase or the end
+    /// of the search, in the order in which the phases first started.
+    pub phase_times: Vec<(Phase, Duration)>,
 }
 
-impl CachedRef {
-    /// Construct a new [`CachedRef`] instance.
-    pub fn new(reference: &[impl AsRef<str> + Sync], max_distance: u8) -> Result<Self, Error> {
-        if reference.len() > u32::MAX as usize {
-            return Err(Error::TooManyStrings {
-                input_type: InputType::Reference,
-                got: reference.len(),
-                limit: u32::MAX as usize,
-            });
+impl SearchStats {
+    /// The wall time spent in `phase`, which is zero if it was skipped.
+    pub fn phase_time(&self, phase: Phase) -> Duration {
+        self.phase_times
+            .iter()
+            .find(|(p, _)| *p == phase)
+            .map_or(Duration::ZERO, |&(_, time)| time)
+    }
+
+    /// Add the time since `start` to that of `phase`.
+    fn add_time(&mut self, phase: Phase, start: Instant) {
+        let elapsed = start.elapsed();
+        match self.phase_times.iter_mut().find(|(p, _)| *p == phase) {
+            Some((_, time)) => *time += elapsed,
+            None => self.phase_times.push((phase, elapsed)),
         }
-        let max_distance = MaxDistance::try_from(max_distance)?;
-        check_strings_ascii(reference, InputType::Reference)?;
+    }
+}
 
-        let (str_store, str_spans) = {
-            let strlens = reference.iter().map(|s| s.as_ref().len()).collect_vec();
+/// Accumulates [`SearchStats`] as a search runs through its phases, along with the phase that is
+/// currently running and when it started.
+#[derive(Debug, Default)]
+struct StatsRecorder(Mutex<(SearchStats, Option<(Phase, Instant)>)>);
 
-            let mut str_store_uninit = prealloc_maybeuninit_vec(strlens.iter().sum());
-            let str_spans = get_disjoint_spans(&strlens);
-            let str_store_chunks = get_disjoint_chunks_mut(&strlens, &mut str_store_uninit[..]);
+impl StatsRecorder {
+    fn phase_started(&self, phase: Phase, total_units: usize) {
+        let mut guard = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let (stats, current) = &mut *guard;
+        if let Some((prev, start)) = current.replace((phase, Instant::now())) {
+            stats.add_time(prev, start);
+        }
+        match phase {
+            Phase::Sorting => stats.num_variants += total_units as u64,
+            Phase::CandidateGeneration => stats.num_candidates += total_units as u64,
+            Phase::DistanceComputation => stats.num_verified += total_units as u64,
+            Phase::VariantGeneration => {}
+        }
+    }
 
-            reference
-                .par_iter()
-                .zip(str_store_chunks.into_par_iter())
-                .with_min_len(100000)
-                .for_each(|(s, chunk)| {
-                    debug_assert_eq!(s.as_ref().len(), chunk.len());
-                    unsafe {
-                        ptr::copy_nonoverlapping(
-                            s.as_ref().as_ptr(),
-                            chunk.as_mut_ptr() as *mut u8,
-                            s.as_ref().len(),
-                        )
-                    };
-                });
+    /// Record the sizes of convergence groups, ignoring those of single strings.
+    fn record_groups(&self, group_sizes: impl Iterator<Item = usize>) {
+        let mut guard = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let stats = &mut guard.0;
+        for size in group_sizes.filter(|&size| size > 1) {
+            stats.num_convergence_groups += 1;
+            stats.largest_convergence_group = stats.largest_convergence_group.max(size as u64);
+        }
+    }
 
-            let str_store = unsafe { cast_to_initialised_vec(str_store_uninit) };
+    /// End the current phase, returning the statistics of a search that found `num_hits` pairs.
+    fn finish(&self, num_hits: usize) -> SearchStats {
+        let mut guard = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let (stats, current) = &mut *guard;
+        if let Some((phase, start)) = current.take() {
+            stats.add_time(phase, start);
+        }
+        stats.num_hits = num_hits as u64;
+        std::mem::take(stats)
+    }
+}
 
-            (str_store, str_spans)
-        };
+/// Enter a [`tracing`] span named `$name` with the given fields for the rest of the enclosing
+/// block, under which the phases of the search are traced, if the `tracing` feature is enabled.
+macro_rules! search_span {
+    ($name:literal, $($fields:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _search_span = trace::SearchSpan::enter(tracing::info_span!($name, $($fields)*));
+    };
+}
 
-        let hash_builder = FixedState::default();
+/// Tracing of searches, enabled by the `tracing` feature.
+///
+/// A search enters a [`SearchSpan`] on the thread that runs it, and its phases are traced as
+/// consecutive spans under it, each of which is entered by [`Progress::start`] and exited when the
+/// next phase starts or the search ends.
+#[cfg(feature = "tracing")]
+mod trace {
+    use super::Phase;
+    use std::cell::RefCell;
+    use tracing::span::EnteredSpan;
+    use tracing::{info_span, Span};
 
-        let (index_store, convergence_groups) = {
-            let num_vars_per_string = get_num_del_vars_per_string(reference, max_distance);
+    /// The number of strings from which a convergence group is reported as unusually large, since
+    /// it alone yields about half a million candidates.
+    pub(crate) const LARGE_CONVERGENCE_GROUP: usize = 1000;
 
-            let mut variant_index_pairs_uninit =
-                prealloc_maybeuninit_vec::<(u64, u32)>(num_vars_per_string.iter().sum());
-            let vip_chunks =
-                get_disjoint_chunks_mut(&num_vars_per_string, &mut variant_index_pairs_uninit[..]);
+    thread_local! {
+        /// The span of the current phase, if a search is running on this thread.
+        static CURRENT_PHASE: RefCell<Option<Option<EnteredSpan>>> = const { RefCell::new(None) };
+    }
 
-            reference
-                .par_iter()
-                .zip(vip_chunks.into_par_iter())
-                .enumerate()
-                .with_min_len(100000)
-                .for_each(|(idx, (s, chunk))| {
-                    write_vi_pairs_rawidx(
-                        s.as_ref(),
-                        idx as u32,
-                        max_distance,
-                        chunk,
-                        &hash_builder,
-                    );
-                });
+    /// The span of a search, which is entered until dropped.
+    pub(crate) struct SearchSpan {
+        span: Option<EnteredSpan>,
+        outer_phase: Option<Option<EnteredSpan>>,
+    }
 
-            let mut variant_index_pairs =
-                unsafe { cast_to_initialised_vec(variant_index_pairs_uninit) };
+    impl SearchSpan {
+        pub(crate) fn enter(span: Span) -> Self {
+            // The phase of an enclosing search stays entered, so that this search nests under it.
+            let outer_phase = CURRENT_PHASE.with_borrow_mut(|current| current.replace(None));
+            Self {
+                span: Some(span.entered()),
+                outer_phase,
+            }
+        }
+    }
 
-            variant_index_pairs.par_sort_unstable();
-            variant_index_pairs.dedup();
+    impl Drop for SearchSpan {
+        fn drop(&mut self) {
+            let phase = CURRENT_PHASE.with_borrow_mut(|current| current.take());
+            drop(phase);
+            drop(self.span.take());
+            let outer_phase = self.outer_phase.take();
+            CURRENT_PHASE.with_borrow_mut(|current| *current = outer_phase);
+        }
+    }
 
-            let mut total_num_convergent_indices = 0;
-            let mut num_convergence_groups = 0;
+    /// End the current step of the search running on this thread, if any, and enter the span
+    /// made by `make_span` in its place.
+    fn enter_step(make_span: impl FnOnce() -> Span) {
+        CURRENT_PHASE.with_borrow_mut(|current| {
+            if let Some(step) = current {
+                // The previous step is exited first, so that the next one is not nested under it.
+                drop(step.take());
+                *step = Some(make_span().entered());
+            }
+        });
+    }
 
-            variant_index_pairs
-                .chunk_by(|(v1, _), (v2, _)| v1 == v2)
-                .for_each(|chunk| {
-                    total_num_convergent_indices += chunk.len();
-                    num_convergence_groups += 1;
-                });
+    pub(crate) fn enter_phase(phase: Phase, total_units: usize) {
+        enter_step(|| match phase {
+            Phase::VariantGeneration => info_span!("variant_generation", total_units),
+            Phase::Sorting => info_span!("sorting", total_units),
+            Phase::CandidateGeneration => info_span!("candidate_generation", total_units),
+            Phase::DistanceComputation => info_span!("distance_computation", total_units),
+        });
+    }
 
-            let mut convergent_indices = Vec::with_capacity(total_num_convergent_indices);
-            let mut convergence_groups = Vec::with_capacity(num_convergence_groups);
-            let mut cursor = 0;
+    pub(crate) fn enter_grouping(num_variants: usize) {
+        enter_step(|| info_span!("grouping", num_variants));
+    }
 
-            variant_index_pairs
-                .chunk_by(|(v1, _), (v2, _)| v1 == v2)
-                .for_each(|chunk| {
-                    convergent_indices.extend(chunk.iter().map(|&(_, i)| i));
-                    convergence_groups.push((chunk[0].0, Span::new(cursor, chunk.len())));
-                    cursor += chunk.len();
-                });
+    /// Emit a warning event if any of the convergence groups of a search is unusually large, which
+    /// suggests a pathological input (e.g. many copies of a low complexity string).
+    pub(crate) fn report_large_groups(group_sizes: impl Iterator<Item = usize>) {
+        let (num_large_groups, largest_group) = group_sizes
+            .filter(|&size| size >= LARGE_CONVERGENCE_GROUP)
+            .fold((0, 0), |(count, largest), size| {
+                (count + 1, largest.max(size))
+            });
+        if num_large_groups > 0 {
+            tracing::warn!(
+                num_large_groups,
+                largest_group,
+                "found unusually large convergence groups"
+            );
+        }
+    }
+}
 
-            debug_assert_eq!(cursor, convergent_indices.len());
+/// Where to report the progress of a search, and record its statistics, if anywhere.
+#[derive(Clone, Copy, Default)]
+struct Progress<'a> {
+    sink: Option<&'a dyn ProgressSink>,
+    stats: Option<&'a StatsRecorder>,
+}
 
-            (convergent_indices, convergence_groups)
-        };
+impl<'a> Progress<'a> {
+    fn start(self, phase: Phase, total_units: usize) {
+        #[cfg(feature = "tracing")]
+        trace::enter_phase(phase, total_units);
+        if let Some(sink) = self.sink {
+            sink.phase_started(phase, total_units as u64);
+        }
+        if let Some(stats) = self.stats {
+            stats.phase_started(phase, total_units);
+        }
+    }
 
-        let mut variant_map = HashMap::with_capacity_and_hasher(
-            convergence_groups.len(),
-            IdentityHasherBuilder::default(),
-        );
-
-        for (v_hash, index_range) in convergence_groups {
-            variant_map.entry(v_hash).insert(index_range);
+    /// Equivalent to [`Progress::start`], for a phase counted in units of convergence groups or
+    /// query strings (see [`Phase::DistanceComputation`]), which are not recorded in the
+    /// statistics.
+    fn start_in_groups(self, phase: Phase, total_groups: usize) {
+        #[cfg(feature = "tracing")]
+        trace::enter_phase(phase, total_groups);
+        if let Some(sink) = self.sink {
+            sink.phase_started(phase, total_groups as u64);
+        }
+        if let Some(stats) = self.stats {
+            stats.phase_started(phase, 0);
         }
-
-        Ok(CachedRef {
-            str_store,
-            str_spans,
-            index_store,
-            variant_map,
-            max_distance,
-        })
     }
 
-    /// The memoized equivalent of [`get_neighbors_within`].
-    pub fn get_neighbors_within(&self, max_distance: u8) -> Result<NeighborPairs, Error> {
-        let max_distance = MaxDistance::try_from(max_distance)?;
-        if max_distance > self.max_distance {
-            return Err(Error::MaxDistTooLargeForCache {
-                got: max_distance.as_u8(),
-                limit: self.max_distance.as_u8(),
-            });
+    fn advance(self, units: usize) {
+        if let Some(sink) = self.sink {
+            sink.advance(units as u64);
         }
+    }
 
-        let mut convergent_indices = Vec::with_capacity(self.variant_map.len());
-        self.variant_map.iter().for_each(|(_, span)| {
-            if span.len() == 1 {
-                return;
-            }
-            convergent_indices.push(self.get_convergent_indices_from_span(span));
-        });
-
-        let candidates = get_hit_candidates_within(&convergent_indices);
-        let dists = self.compute_dists_fully_cached(&candidates, self, max_distance);
+    /// Whether nothing is reported or recorded, so that searches may run concurrently.
+    fn is_silent(self) -> bool {
+        self.sink.is_none() && self.stats.is_none()
+    }
 
-        Ok(collect_true_hits(&candidates, &dists, max_distance))
+    /// Mark the start of grouping the sorted deletion variants into convergence groups, which is
+    /// reported as part of [`Phase::Sorting`], but traced as a step of its own.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn start_grouping(self, num_variants: usize) {
+        #[cfg(feature = "tracing")]
+        trace::enter_grouping(num_variants);
     }
 
-    /// The memoized equivalent of [`get_neighbors_across`].
-    pub fn get_neighbors_across(
-        &self,
-        query: &[impl AsRef<str> + Sync],
-        max_distance: u8,
-    ) -> Result<NeighborPairs, Error> {
-        let max_distance = MaxDistance::try_from(max_distance)?;
-        if max_distance > self.max_distance {
-            return Err(Error::MaxDistTooLargeForCache {
-                got: max_distance.as_u8(),
-                limit: self.max_distance.as_u8(),
-            });
+    /// Record the sizes of the convergence groups of a search in its statistics.
+    fn record_groups(self, group_sizes: impl Iterator<Item = usize> + Clone) {
+        #[cfg(feature = "tracing")]
+        trace::report_large_groups(group_sizes.clone());
+        if let Some(stats) = self.stats {
+            stats.record_groups(group_sizes);
         }
-        if query.len() > u32::MAX as usize {
-            return Err(Error::TooManyStrings {
-                input_type: InputType::Query,
-                got: query.len(),
-                limit: u32::MAX as usize,
-            });
+    }
+
+    /// A per-worker accumulator of progress, so that workers report in batches.
+    fn batch(self) -> ProgressBatch<'a> {
+        ProgressBatch {
+            progress: self,
+            pending: 0,
         }
-        check_strings_ascii(query, InputType::Query)?;
+    }
+}
 
-        let (q_idx_store, convergence_groups) = {
-            let num_vars_per_string = get_num_del_vars_per_string(query, max_distance);
+/// Progress accumulated by a worker, which is reported once it amounts to a batch, or when the
+/// worker is done.
+struct ProgressBatch<'a> {
+    progress: Progress<'a>,
+    pending: usize,
+}
 
-            let mut variant_index_pairs_uninit =
-                prealloc_maybeuninit_vec(num_vars_per_string.iter().sum());
-            let vip_chunks =
-                get_disjoint_chunks_mut(&num_vars_per_string, &mut variant_index_pairs_uninit[..]);
+impl ProgressBatch<'_> {
+    const LEN: usize = 1 << 14;
 
-            let hash_builder = FixedState::default();
+    #[inline(always)]
+    fn advance(&mut self, units: usize) {
+        if self.progress.sink.is_some() {
+            self.pending += units;
+            if self.pending >= Self::LEN {
+                self.progress.advance(self.pending);
+                self.pending = 0;
+            }
+        }
+    }
+}
 
-            query
-                .par_iter()
-                .zip(vip_chunks.into_par_iter())
-                .enumerate()
-                .with_min_len(100000)
-                .for_each(|(idx, (s, chunk))| {
-                    write_vi_pairs_rawidx(
-                        s.as_ref(),
-                        idx as u32,
-                        max_distance,
-                        chunk,
-                        &hash_builder,
-                    );
-                });
+impl Drop for ProgressBatch<'_> {
+    fn drop(&mut self) {
+        if self.pending > 0 {
+            self.progress.advance(self.pending);
+        }
+    }
+}
 
-            let mut variant_index_pairs =
-                unsafe { cast_to_initialised_vec(variant_index_pairs_uninit) };
+/// The order in which pairs are returned (see [`SearchOptions::order`]).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OutputOrder {
+    /// Sorted ascending by row index, and then by column index.
+    #[default]
+    ByRowThenCol,
+    /// Sorted ascending by distance, then by row index, and then by column index.
+    ByDistance,
+    /// In no particular order, which allows skipping any sorting steps that only serve to order
+    /// the output. The order may differ between runs.
+    Unordered,
+}
 
-            variant_index_pairs.par_sort_unstable();
-            variant_index_pairs.dedup();
+/// Which pairs of labeled strings [`get_neighbors_within_grouped`] compares.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupPolicy {
+    /// Only compare strings that share the same label.
+    WithinGroupsOnly,
+    /// Only compare strings that carry different labels.
+    AcrossGroupsOnly,
+}
 
-            let mut total_num_convergent_q_indices = 0;
-            let mut num_convergence_groups = 0;
+impl GroupPolicy {
+    /// Whether strings with the labels `a` and `b` are to be compared.
+    fn allows(self, a: u32, b: u32) -> bool {
+        match self {
+            GroupPolicy::WithinGroupsOnly => a == b,
+            GroupPolicy::AcrossGroupsOnly => a != b,
+        }
+    }
+}
 
-            variant_index_pairs
-                .chunk_by(|(v1, _), (v2, _)| v1 == v2)
-                .for_each(|chunk| {
-                    let variant = &chunk[0].0;
-                    match self.variant_map.get(variant) {
-                        None => return,
-                        Some(_) => {
-                            total_num_convergent_q_indices += chunk.len();
-                            num_convergence_groups += 1;
-                        }
-                    }
-                });
+/// Which pairs of strings [`get_neighbors_multi`] compares, given the collections they belong to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MultiPolicy {
+    /// Compare all pairs of strings, whether they belong to the same collection or not.
+    AllPairs,
+    /// Only compare strings belonging to different collections.
+    CrossOnly,
+    /// Only compare strings belonging to the same collection.
+    WithinOnly,
+}
 
-            let mut q_idx_store = Vec::with_capacity(total_num_convergent_q_indices);
-            let mut convergence_groups = Vec::with_capacity(num_convergence_groups);
-            let mut cursor = 0;
+/// Regions at the start and end of strings that must be identical for them to be neighbors (see
+/// [`SearchOptions::anchor`]), measured in symbols (bytes, or characters for non-ASCII text).
+///
+/// The anchored regions confine deletions, and the edits tolerated between neighbors, to the
+/// window `prefix..len - suffix` of each string of length `len`. Unlike a fixed range of
+/// positions, the window thus follows the conserved flanks of strings of varying lengths.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Anchor {
+    /// The length of the anchored region at the start of each string.
+    pub prefix: u8,
+    /// The length of the anchored region at the end of each string.
+    pub suffix: u8,
+}
 
-            variant_index_pairs
-                .chunk_by(|(v1, _), (v2, _)| v1 == v2)
-                .for_each(|chunk| {
-                    let variant = &chunk[0].0;
-                    match self.variant_map.get(variant) {
-                        None => return,
-                        Some(span) => {
-                            q_idx_store.extend(chunk.iter().map(|&(_, i)| i));
-                            convergence_groups.push((
-                                cursor..cursor + chunk.len(),
-                                self.get_convergent_indices_from_span(span),
-                            ));
-                            cursor += chunk.len();
-                        }
-                    }
-                });
+impl Anchor {
+    fn is_none(self) -> bool {
+        self.prefix == 0 && self.suffix == 0
+    }
+
+    /// Split `s` into its anchored prefix, its interior and its anchored suffix, or None if it is
+    /// shorter than the two anchored regions combined.
+    fn split(self, s: Symbols<'_>) -> Option<(&[u8], Symbols<'_>, &[u8])> {
+        let (prefix, suffix) = (self.prefix as usize, self.suffix as usize);
+        let (prefix_end, suffix_start) = match s {
+            Symbols::Text(t) if !t.is_ascii() => {
+                if get_num_chars(t) < prefix + suffix {
+                    return None;
+                }
+                let char_start = |(start, _)| start;
+                let prefix_end = t.char_indices().nth(prefix).map_or(t.len(), char_start);
+                let suffix_start = match suffix {
+                    0 => t.len(),
+                    _ => t.char_indices().nth_back(suffix - 1).map_or(0, char_start),
+                };
+                (prefix_end, suffix_start)
+            }
+            _ => {
+                let len = s.as_bytes().len();
+                if len < prefix + suffix {
+                    return None;
+                }
+                (prefix, len - suffix)
+            }
+        };
 
-            (q_idx_store, convergence_groups)
+        let bytes = s.as_bytes();
+        let interior = match s {
+            Symbols::Text(t) => Symbols::Text(&t[prefix_end..suffix_start]),
+            Symbols::Bytes(b) => Symbols::Bytes(&b[prefix_end..suffix_start]),
         };
+        Some((&bytes[..prefix_end], interior, &bytes[suffix_start..]))
+    }
+}
 
-        let convergence_groups = convergence_groups
-            .into_iter()
-            .map(|(r, s)| (&q_idx_store[r], s))
-            .collect_vec();
+/// How strings too short to hold the anchored regions of [`SearchOptions::anchor`] are treated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShortStrings {
+    /// Strings too short to hold the anchored regions have no neighbors at all, not even
+    /// identical ones.
+    #[default]
+    Skip,
+    /// Strings too short to hold the anchored regions are only neighbors of identical strings,
+    /// at distance 0, as if they were anchored in whole.
+    MatchExactly,
+}
 
-        let candidates = get_hit_candidates_from_cis_cross(&convergence_groups);
-        let dists = self.compute_dists_partially_cached(&candidates, query, max_distance);
+/// The anchored regions of strings, along with the treatment of strings too short to hold them
+/// (see [`SearchOptions::anchor`] and [`SearchOptions::short_strings`]).
+#[derive(Clone, Copy, Default)]
+struct Anchoring {
+    anchor: Anchor,
+    short_strings: ShortStrings,
+}
 
-        Ok(collect_true_hits(&candidates, &dists, max_distance))
+impl Anchoring {
+    fn is_none(self) -> bool {
+        self.anchor.is_none()
     }
 
-    /// Equivalent to [`CachedRef::get_neighbors_across`], where the query is also a [`CachedRef`]
-    /// instance.
-    pub fn get_neighbors_across_cached(
-        &self,
-        query: &Self,
-        max_distance: u8,
-    ) -> Result<NeighborPairs, Error> {
-        let max_distance = MaxDistance::try_from(max_distance)?;
-        if max_distance > self.max_distance {
-            return Err(Error::MaxDistTooLargeForCache {
-                got: max_distance.as_u8(),
-                limit: self.max_distance.as_u8(),
-            });
-        }
-        if max_distance > query.max_distance {
-            return Err(Error::MaxDistTooLargeForCache {
-                got: max_distance.as_u8(),
-                limit: query.max_distance.as_u8(),
-            });
+    /// Split `s` as [`Anchor::split`] does. Strings too short to hold the anchored regions are
+    /// only split if they are to be matched exactly, in which case the whole string makes up the
+    /// anchored prefix, around an empty interior.
+    fn split(self, s: Symbols<'_>) -> Option<(&[u8], Symbols<'_>, &[u8])> {
+        match (self.anchor.split(s), self.short_strings) {
+            (Some(split), _) => Some(split),
+            (None, ShortStrings::Skip) => None,
+            (None, ShortStrings::MatchExactly) => {
+                let interior = match s {
+                    Symbols::Text(_) => Symbols::Text(""),
+                    Symbols::Bytes(_) => Symbols::Bytes(&[]),
+                };
+                Some((s.as_bytes(), interior, &[]))
+            }
         }
+    }
 
-        let convergence_groups = if query.variant_map.len() < self.variant_map.len() {
-            let mut num_convergence_groups = 0;
-
-            query
-                .variant_map
-                .iter()
-                .for_each(|(variant, _)| match self.variant_map.get(variant) {
-                    None => return,
-                    Some(_) => {
-                        num_convergence_groups += 1;
-                    }
-                });
+    /// Whether `a` and `b` are both long enough to hold the anchored regions, and identical in
+    /// them, or are both too short to hold them, but identical and to be matched exactly.
+    fn matches(self, a: Symbols, b: Symbols) -> bool {
+        match (self.anchor.split(a), self.anchor.split(b)) {
+            (Some((prefix_a, _, suffix_a)), Some((prefix_b, _, suffix_b))) => {
+                prefix_a == prefix_b && suffix_a == suffix_b
+            }
+            (None, None) => {
+                self.short_strings == ShortStrings::MatchExactly && a.as_bytes() == b.as_bytes()
+            }
+            _ => false,
+        }
+    }
+}
 
-            let mut convergence_groups = Vec::with_capacity(num_convergence_groups);
+/// A [`BuildHasher`] for the deletion variants of the interior of an anchored string (see
+/// [`Anchor::split`]), whose hashers hash each variant as if it were preceded by `prefix` and
+/// followed by `suffix`.
+struct AnchoredHashBuilder<'a, B> {
+    inner: &'a B,
+    prefix: &'a [u8],
+    suffix: &'a [u8],
+}
 
-            query.variant_map.iter().for_each(|(variant, span_q)| {
-                match self.variant_map.get(variant) {
-                    None => return,
-                    Some(span_r) => {
-                        convergence_groups.push((
-                            query.get_convergent_indices_from_span(span_q),
-                            self.get_convergent_indices_from_span(span_r),
-                        ));
-                    }
-                }
-            });
+impl<'a, B: BuildHasher<Hasher: Clone>> BuildHasher for AnchoredHashBuilder<'a, B> {
+    type Hasher = AnchoredHasher<'a, B::Hasher>;
 
-            convergence_groups
-        } else {
-            let mut num_convergence_groups = 0;
+    fn build_hasher(&self) -> Self::Hasher {
+        let mut hasher = self.inner.build_hasher();
+        hasher.write(self.prefix);
+        AnchoredHasher {
+            inner: hasher,
+            suffix: self.suffix,
+        }
+    }
+}
 
-            self.variant_map
-                .iter()
-                .for_each(|(variant, _)| match query.variant_map.get(variant) {
-                    None => return,
-                    Some(_) => {
-                        num_convergence_groups += 1;
-                    }
-                });
+#[derive(Clone)]
+struct AnchoredHasher<'a, H> {
+    inner: H,
+    suffix: &'a [u8],
+}
 
-            let mut convergence_groups = Vec::with_capacity(num_convergence_groups);
+impl<H: Hasher + Clone> Hasher for AnchoredHasher<'_, H> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.inner.write(bytes);
+    }
 
-            self.variant_map.iter().for_each(|(variant, span_r)| {
-                match query.variant_map.get(variant) {
-                    None => return,
-                    Some(span_q) => {
-                        convergence_groups.push((
-                            query.get_convergent_indices_from_span(span_q),
-                            self.get_convergent_indices_from_span(span_r),
-                        ));
-                    }
-                }
-            });
+    fn finish(&self) -> u64 {
+        let mut hasher = self.inner.clone();
+        hasher.write(self.suffix);
+        hasher.finish()
+    }
+}
 
-            convergence_groups
-        };
+/// The width in bits of the deletion variant hashes of a [`CachedRef`], depending on whether they
+/// are truncated (see [`SearchOptions::narrow_hashes`]).
+fn get_cache_hash_width(narrow_hashes: bool) -> u32 {
+    if narrow_hashes {
+        32
+    } else {
+        64
+    }
+}
 
-        let candidates = get_hit_candidates_from_cis_cross(&convergence_groups);
-        let dists = self.compute_dists_fully_cached(&candidates, query, max_distance);
+/// The [`BuildHasher`] for the deletion variants of a [`CachedRef`], whose hashers truncate the
+/// hashes to their most significant 32 bits if `narrow` (see [`SearchOptions::narrow_hashes`]).
+///
+/// Truncated hashes repeat those 32 bits in their low half rather than zeroing it, since the
+/// variant table buckets keys by their low bits.
+struct CacheHashBuilder {
+    inner: FixedState,
+    narrow: bool,
+}
 
-        Ok(collect_true_hits(&candidates, &dists, max_distance))
+impl CacheHashBuilder {
+    fn new(seed: u64, narrow: bool) -> Self {
+        Self {
+            inner: FixedState::with_seed(seed),
+            narrow,
+        }
     }
+}
 
-    #[inline(always)]
-    fn get_convergent_indices_from_span(&self, span: &Span) -> &[u32] {
-        &self.index_store[span.as_range()]
-    }
+impl BuildHasher for CacheHashBuilder {
+    type Hasher = CacheHasher<<FixedState as BuildHasher>::Hasher>;
 
-    #[inline(always)]
-    fn get_str_at_index(&self, i: usize) -> &str {
-        unsafe { str::from_utf8_unchecked(&self.str_store[self.str_spans[i].as_range()]) }
+    fn build_hasher(&self) -> Self::Hasher {
+        CacheHasher {
+            inner: self.inner.build_hasher(),
+            narrow: self.narrow,
+        }
     }
+}
 
-    fn compute_dists_partially_cached(
-        &self,
-        hit_candidates: &[(u32, u32)],
-        query: &[impl AsRef<str> + Sync],
-        max_distance: MaxDistance,
-    ) -> Vec<u8> {
-        hit_candidates
-            .par_iter()
-            .with_min_len(100000)
-            .map(|&(idx_query, idx_reference)| {
-                let dist = {
-                    match levenshtein::distance_with_args(
-                        query[idx_query as usize].as_ref().bytes(),
-                        self.get_str_at_index(idx_reference as usize).bytes(),
-                        &levenshtein::Args::default().score_cutoff(max_distance.as_usize()),
-                    ) {
-                        None => u8::MAX,
-                        Some(dist) => dist as u8,
-                    }
-                };
+#[derive(Clone)]
+struct CacheHasher<H> {
+    inner: H,
+    narrow: bool,
+}
 
-                dist
-            })
-            .collect()
+impl<H: Hasher> Hasher for CacheHasher<H> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.inner.write(bytes);
     }
 
-    fn compute_dists_fully_cached(
-        &self,
-        hit_candidates: &[(u32, u32)],
-        query: &Self,
-        max_distance: MaxDistance,
-    ) -> Vec<u8> {
-        hit_candidates
-            .par_iter()
-            .with_min_len(100000)
-            .map(|&(idx_query, idx_reference)| {
-                let dist = {
-                    match levenshtein::distance_with_args(
-                        query.get_str_at_index(idx_query as usize).bytes(),
-                        self.get_str_at_index(idx_reference as usize).bytes(),
-                        &levenshtein::Args::default().score_cutoff(max_distance.as_usize()),
-                    ) {
-                        None => u8::MAX,
-                        Some(dist) => dist as u8,
-                    }
-                };
-
-                dist
-            })
-            .collect()
+    fn finish(&self) -> u64 {
+        let hash = self.inner.finish();
+        if self.narrow {
+            let top = hash >> 32;
+            top << 32 | top
+        } else {
+            hash
+        }
     }
 }
 
-/// Detect string pairs within an input collection that lie within a threshold edit distance.
-///
-/// The function considers all possible combinations (not permutations, [read
-/// more](NeighborPairs#a-note-on-double-counting-pairs)) of string pairs from `query`, and returns
-/// all those where the two strings are no more than `max_distance` Levenshtein edit distance units
-/// apart.
-///
-/// # Errors
-///
-/// Currently, the crate only supports ASCII input. The function will [`Err`] with
-/// [`Error::NonAsciiInput`] if `query` contains any non-ASCII data.
-///
-/// There are some hard limits on the sizes of the input arguments (see [`Error::TooManyStrings`],
-/// [`Error::MaxDistCapped`]). Note however that in practice, runtime or memory usage is almost
-/// certainly the limiting factor instead.
+/// A predicate deciding which candidate string pairs to verify (see
+/// [`SearchOptions::pair_filter`]).
 ///
 /// # Examples
 ///
 /// ```
-/// use symscan::{get_neighbors_within, NeighborPairs};
+/// use symscan::{get_neighbors_across_with_options, PairFilter, SearchOptions};
 ///
 /// let query = ["fizz", "fuzz", "buzz"];
-/// let NeighborPairs { row, col, dists } = get_neighbors_within(&query, 1).unwrap();
+/// let reference = ["fooo", "barr", "bazz", "buzz"];
+/// let donors_q = [0, 0, 1];
+/// let donors_r = [0, 0, 1, 1];
 ///
-/// assert_eq!(row,   vec![0, 1]);
-/// assert_eq!(col,   vec![1, 2]);
-/// assert_eq!(dists, vec![1, 1]);
+/// let mut options = SearchOptions::default();
+/// options.pair_filter = Some(PairFilter::new(move |qi, ri| {
+///     donors_q[qi as usize] != donors_r[ri as usize]
+/// }));
 ///
-/// let NeighborPairs { row, col, dists } = get_neighbors_within(&query, 2).unwrap();
+/// let hits = get_neighbors_across_with_options(&query, &reference, 2, &options).unwrap();
 ///
-/// assert_eq!(row,   vec![0, 0, 1]);
-/// assert_eq!(col,   vec![1, 2, 2]);
-/// assert_eq!(dists, vec![1, 2, 1]);
+/// assert_eq!(hits.row, vec![0, 0, 1, 1]);
+/// assert_eq!(hits.col, vec![2, 3, 2, 3]);
 /// ```
-pub fn get_neighbors_within(
-    query: &[impl AsRef<str> + Sync],
-    max_distance: u8,
-) -> Result<NeighborPairs, Error> {
-    if query.len() > u32::MAX as usize {
-        return Err(Error::TooManyStrings {
-            input_type: InputType::Query,
-            got: query.len(),
-            limit: u32::MAX as usize,
-        });
+#[derive(Clone)]
+pub struct PairFilter(Arc<dyn Fn(u32, u32) -> bool + Send + Sync>);
+
+impl PairFilter {
+    /// Construct a filter that keeps the candidate pairs of (query index, reference index) for
+    /// which `keep` returns `true`.
+    pub fn new(keep: impl Fn(u32, u32) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(keep))
     }
-    let max_distance = MaxDistance::try_from(max_distance)?;
-    check_strings_ascii(query, InputType::Query)?;
 
-    let (convergent_indices, group_sizes) = {
-        let num_vars_per_string = get_num_del_vars_per_string(query, max_distance);
+    /// Whether the candidate pair of (query index, reference index) is kept.
+    pub fn keeps(&self, query_index: u32, reference_index: u32) -> bool {
+        (self.0)(query_index, reference_index)
+    }
+}
 
-        let mut variant_index_pairs_uninit =
-            prealloc_maybeuninit_vec(num_vars_per_string.iter().sum());
-        let vip_chunks =
-            get_disjoint_chunks_mut(&num_vars_per_string, &mut variant_index_pairs_uninit[..]);
+impl Debug for PairFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PairFilter").finish_non_exhaustive()
+    }
+}
 
-        let hash_builder = FixedState::default();
+/// The distance metric with which candidate string pairs are verified (see
+/// [`SearchOptions::metric`]).
+///
+/// Candidates are always generated via deletion variants up to the maximum distance `d`, which
+/// finds all pairs of strings that can be made equal by deleting at most `d` characters from each.
+/// This covers all pairs within Levenshtein distance `d`, since each insertion, deletion or
+/// substitution can be undone by deleting at most one character from each string. Any metric
+/// whose edit operations can be undone in the same way can therefore be verified over the same
+/// candidates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Metric {
+    /// The Levenshtein distance, counting insertions, deletions and substitutions.
+    #[default]
+    Levenshtein,
+    /// The Hamming distance, counting substitutions only. Strings of different lengths are never
+    /// neighbors under this metric.
+    Hamming,
+    /// The optimal string alignment distance (a restricted form of the Damerau-Levenshtein
+    /// distance), which additionally counts transpositions of adjacent characters as single edits,
+    /// but never edits a substring more than once. A transposition is undone by deleting either
+    /// of the transposed characters from both strings, so no additional candidates are needed.
+    Osa,
+    /// The indel distance, counting insertions and deletions only, such that a substitution costs
+    /// two edits. This is never smaller than the Levenshtein distance.
+    Indel,
+    /// The Levenshtein distance with the given costs of each kind of edit, such as indels costing
+    /// twice as much as substitutions. Since every edit costs at least 1, this is never smaller
+    /// than the Levenshtein distance. Insertions and deletions are those that turn the query (or
+    /// row) string of a pair into the reference (or column) string.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{get_neighbors_within_with_options, EditCosts, Metric, SearchOptions};
+    ///
+    /// let mut options = SearchOptions::default();
+    /// options.metric = Metric::WeightedLevenshtein(EditCosts::new(2, 2, 1).unwrap());
+    ///
+    /// let hits = get_neighbors_within_with_options(&["fizz", "fuzz", "fizzy"], 2, &options);
+    ///
+    /// assert_eq!(hits.unwrap().dists, vec![1, 2]);
+    /// ```
+    WeightedLevenshtein(EditCosts),
+}
 
-        query
-            .par_iter()
-            .zip(vip_chunks.into_par_iter())
-            .enumerate()
-            .with_min_len(100000)
-            .for_each(|(idx, (s, chunk))| {
-                write_vi_pairs_rawidx(s.as_ref(), idx as u32, max_distance, chunk, &hash_builder);
+/// The costs of each kind of edit under [`Metric::WeightedLevenshtein`], each of which is at least
+/// 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EditCosts {
+    insertion: u8,
+    deletion: u8,
+    substitution: u8,
+}
+
+impl EditCosts {
+    /// Construct a new set of edit costs.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidEditCosts`] if any of the costs is 0. Candidates are generated
+    /// for pairs within `max_distance` unit-cost edits, so that cheaper edits could make pairs
+    /// neighbors that are never found.
+    pub fn new(insertion: u8, deletion: u8, substitution: u8) -> Result<Self, Error> {
+        if insertion == 0 || deletion == 0 || substitution == 0 {
+            return Err(Error::InvalidEditCosts {
+                insertion,
+                deletion,
+                substitution,
             });
+        }
+        Ok(Self {
+            insertion,
+            deletion,
+            substitution,
+        })
+    }
 
-        let mut variant_index_pairs =
-            unsafe { cast_to_initialised_vec(variant_index_pairs_uninit) };
+    /// The cost of inserting a symbol.
+    pub fn insertion(&self) -> u8 {
+        self.insertion
+    }
 
-        variant_index_pairs.par_sort_unstable();
-        variant_index_pairs.dedup();
+    /// The cost of deleting a symbol.
+    pub fn deletion(&self) -> u8 {
+        self.deletion
+    }
 
-        let mut total_num_convergent_indices = 0;
-        let mut num_convergence_groups = 0;
+    /// The cost of substituting a symbol.
+    pub fn substitution(&self) -> u8 {
+        self.substitution
+    }
 
-        variant_index_pairs
-            .chunk_by(|(v1, _), (v2, _)| v1 == v2)
-            .filter(|chunk| chunk.len() > 1)
-            .for_each(|chunk| {
-                total_num_convergent_indices += chunk.len();
-                num_convergence_groups += 1;
-            });
+    fn as_weight_table(self) -> levenshtein::WeightTable {
+        levenshtein::WeightTable {
+            insertion_cost: self.insertion as usize,
+            deletion_cost: self.deletion as usize,
+            substitution_cost: self.substitution as usize,
+        }
+    }
+}
 
-        let mut convergent_indices = Vec::with_capacity(total_num_convergent_indices);
-        let mut convergence_group_sizes = Vec::with_capacity(num_convergence_groups);
+impl Metric {
+    /// The distance between two strings under this metric, or u8::MAX if it exceeds
+    /// `max_distance`. If given, `cached` is the comparator of `a` (see [`QueryComparator`]).
+    #[inline(always)]
+    fn bounded_dist(
+        self,
+        a: Symbols,
+        b: Symbols,
+        max_distance: MaxDistance,
+        cached: Option<&levenshtein::BatchComparator<u8>>,
+    ) -> u8 {
+        if !(a.is_bytewise() && b.is_bytewise()) {
+            let (Symbols::Text(a), Symbols::Text(b)) = (a, b) else {
+                unreachable!("byte strings are only ever compared with each other");
+            };
+            return match self.bounded_dist_chars(a, b, max_distance.as_usize()) {
+                None => u8::MAX,
+                Some(dist) => dist as u8,
+            };
+        }
+        let (a, b) = (a.as_bytes(), b.as_bytes());
 
-        variant_index_pairs
-            .chunk_by(|(v1, _), (v2, _)| v1 == v2)
-            .filter(|chunk| chunk.len() > 1)
-            .for_each(|chunk| {
-                convergent_indices.extend(chunk.iter().map(|&(_, i)| i));
-                convergence_group_sizes.push(chunk.len());
-            });
+        // Every metric takes at least one edit per symbol of length difference, and any metric
+        // but the Hamming distance takes exactly that many if the shorter string can be obtained
+        // by deletions from the longer one, which is common among candidates.
+        let len_diff = a.len().abs_diff(b.len());
+        if len_diff > max_distance.as_usize() {
+            return u8::MAX;
+        }
+        if len_diff > 0 && self != Metric::Hamming {
+            let (shorter, longer) = if a.len() < b.len() { (a, b) } else { (b, a) };
+            if is_subsequence(shorter, longer) {
+                let dist = match self {
+                    Metric::WeightedLevenshtein(costs) if a.len() > b.len() => {
+                        len_diff * costs.deletion as usize
+                    }
+                    Metric::WeightedLevenshtein(costs) => len_diff * costs.insertion as usize,
+                    _ => len_diff,
+                };
+                return if dist <= max_distance.as_usize() {
+                    dist as u8
+                } else {
+                    u8::MAX
+                };
+            }
+        }
 
-        (convergent_indices, convergence_group_sizes)
-    };
+        let dist = match (self, cached) {
+            (Metric::Levenshtein, Some(cached)) => cached.distance_with_args(
+                b.iter().copied(),
+                &levenshtein::Args::default().score_cutoff(max_distance.as_usize()),
+            ),
+            (metric, _) => metric.bounded_dist_uncached(a, b, max_distance.as_usize()),
+        };
+        match dist {
+            None => u8::MAX,
+            Some(dist) => dist as u8,
+        }
+    }
 
-    let mut convergent_chunks = Vec::with_capacity(group_sizes.len());
-    let mut remaining = &convergent_indices[..];
-    for n in group_sizes {
-        let (chunk, rest) = remaining.split_at(n);
-        convergent_chunks.push(chunk);
-        remaining = rest;
+    /// The distance between two strings under this metric, or None if it exceeds `max_distance`,
+    /// comparing them byte by byte if both are bytewise and character by character otherwise.
+    /// Unlike [`Metric::bounded_dist`], `max_distance` is not capped (see [`Error::MaxDistCapped`]).
+    fn dist_up_to(self, a: Symbols, b: Symbols, max_distance: usize) -> Option<usize> {
+        if a.len().abs_diff(b.len()) > max_distance {
+            return None;
+        }
+        match (a, b) {
+            (Symbols::Text(a), Symbols::Text(b)) if !(a.is_ascii() && b.is_ascii()) => {
+                self.bounded_dist_chars(a, b, max_distance)
+            }
+            _ => self.bounded_dist_uncached(a.as_bytes(), b.as_bytes(), max_distance),
+        }
     }
 
-    debug_assert_eq!(remaining.len(), 0);
+    /// The distance between two byte strings under this metric, or None if it exceeds
+    /// `max_distance`, computed from scratch.
+    fn bounded_dist_uncached(self, a: &[u8], b: &[u8], max_distance: usize) -> Option<usize> {
+        match self {
+            Metric::Levenshtein => levenshtein::distance_with_args(
+                a.iter().copied(),
+                b.iter().copied(),
+                &levenshtein::Args::default().score_cutoff(max_distance),
+            ),
+            // Strings of different lengths are rejected with an error.
+            Metric::Hamming => hamming::distance_with_args(
+                a.iter().copied(),
+                b.iter().copied(),
+                &hamming::Args::default().score_cutoff(max_distance),
+            )
+            .ok()
+            .flatten(),
+            Metric::Osa => osa::distance_with_args(
+                a.iter().copied(),
+                b.iter().copied(),
+                &osa::Args::default().score_cutoff(max_distance),
+            ),
+            Metric::Indel => indel::distance_with_args(
+                a.iter().copied(),
+                b.iter().copied(),
+                &indel::Args::default().score_cutoff(max_distance),
+            ),
+            Metric::WeightedLevenshtein(costs) => levenshtein::distance_with_args(
+                a.iter().copied(),
+                b.iter().copied(),
+                &levenshtein::Args::default()
+                    .weights(&costs.as_weight_table())
+                    .score_cutoff(max_distance),
+            ),
+        }
+    }
+
+    /// Equivalent to [`Metric::bounded_dist_uncached`], but comparing the strings character by
+    /// character rather than byte by byte, for non-ASCII input.
+    #[cold]
+    fn bounded_dist_chars(self, a: &str, b: &str, max_distance: usize) -> Option<usize> {
+        match self {
+            Metric::Levenshtein => levenshtein::distance_with_args(
+                a.chars(),
+                b.chars(),
+                &levenshtein::Args::default().score_cutoff(max_distance),
+            ),
+            Metric::Hamming => hamming::distance_with_args(
+                a.chars(),
+                b.chars(),
+                &hamming::Args::default().score_cutoff(max_distance),
+            )
+            .ok()
+            .flatten(),
+            Metric::Osa => osa::distance_with_args(
+                a.chars(),
+                b.chars(),
+                &osa::Args::default().score_cutoff(max_distance),
+            ),
+            Metric::Indel => indel::distance_with_args(
+                a.chars(),
+                b.chars(),
+                &indel::Args::default().score_cutoff(max_distance),
+            ),
+            Metric::WeightedLevenshtein(costs) => levenshtein::distance_with_args(
+                a.chars(),
+                b.chars(),
+                &levenshtein::Args::default()
+                    .weights(&costs.as_weight_table())
+                    .score_cutoff(max_distance),
+            ),
+        }
+    }
+
+    /// The cheapest sequence of edits under this metric that turns `a` into `b`, comparing the
+    /// strings byte by byte if both are ASCII and character by character otherwise.
+    fn edit_ops(self, a: &str, b: &str) -> Vec<EditOp> {
+        if a.is_ascii() && b.is_ascii() {
+            self.edit_ops_of(a.as_bytes(), b.as_bytes())
+        } else {
+            self.edit_ops_of(&a.chars().collect_vec(), &b.chars().collect_vec())
+        }
+    }
+
+    fn edit_ops_of<T: PartialEq>(self, a: &[T], b: &[T]) -> Vec<EditOp> {
+        const FORBIDDEN: u32 = u32::MAX / 4;
+        let (insertion, deletion, substitution) = match self {
+            Metric::Levenshtein | Metric::Osa => (1, 1, 1),
+            Metric::Hamming => (FORBIDDEN, FORBIDDEN, 1),
+            Metric::Indel => (1, 1, FORBIDDEN),
+            Metric::WeightedLevenshtein(costs) => (
+                costs.insertion as u32,
+                costs.deletion as u32,
+                costs.substitution as u32,
+            ),
+        };
+        let transposes = |i: usize, j: usize| {
+            self == Metric::Osa && i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1]
+        };
+
+        // The cost of turning the first `i` symbols of `a` into the first `j` symbols of `b`.
+        let width = b.len() + 1;
+        let mut costs = vec![0; (a.len() + 1) * width];
+        for i in 0..=a.len() {
+            for j in 0..=b.len() {
+                costs[i * width + j] = if i == 0 {
+                    (j as u32).saturating_mul(insertion).min(FORBIDDEN)
+                } else if j == 0 {
+                    (i as u32).saturating_mul(deletion).min(FORBIDDEN)
+                } else {
+                    let diagonal = costs[(i - 1) * width + j - 1];
+                    let mut cost = if a[i - 1] == b[j - 1] {
+                        diagonal
+                    } else {
+                        diagonal + substitution
+                    };
+                    cost = cost.min(costs[(i - 1) * width + j] + deletion);
+                    cost = cost.min(costs[i * width + j - 1] + insertion);
+                    if transposes(i, j) {
+                        cost = cost.min(costs[(i - 2) * width + j - 2] + 1);
+                    }
+                    cost.min(FORBIDDEN)
+                };
+            }
+        }
 
-    let candidates = get_hit_candidates_within(&convergent_chunks);
-    let dists = compute_dists(&candidates, &query, &query, max_distance);
+        let mut ops = Vec::new();
+        let (mut i, mut j) = (a.len(), b.len());
+        while i > 0 || j > 0 {
+            let cost = costs[i * width + j];
+            let (src_pos, dest_pos) = (i as u32, j as u32);
+            if i > 0 && j > 0 {
+                let diagonal = costs[(i - 1) * width + j - 1];
+                if a[i - 1] == b[j - 1] && cost == diagonal {
+                    (i, j) = (i - 1, j - 1);
+                    continue;
+                }
+                if a[i - 1] != b[j - 1] && cost == diagonal + substitution {
+                    ops.push(EditOp::Substitute {
+                        src_pos: src_pos - 1,
+                        dest_pos: dest_pos - 1,
+                    });
+                    (i, j) = (i - 1, j - 1);
+                    continue;
+                }
+                if transposes(i, j) && cost == costs[(i - 2) * width + j - 2] + 1 {
+                    ops.push(EditOp::Transpose {
+                        src_pos: src_pos - 2,
+                        dest_pos: dest_pos - 2,
+                    });
+                    (i, j) = (i - 2, j - 2);
+                    continue;
+                }
+            }
+            if i > 0 && cost == costs[(i - 1) * width + j] + deletion {
+                ops.push(EditOp::Delete {
+                    src_pos: src_pos - 1,
+                    dest_pos,
+                });
+                i -= 1;
+            } else {
+                ops.push(EditOp::Insert {
+                    src_pos,
+                    dest_pos: dest_pos - 1,
+                });
+                j -= 1;
+            }
+        }
+        ops.reverse();
+        ops
+    }
+}
 
-    Ok(collect_true_hits(&candidates, &dists, max_distance))
+/// Whether `shorter` can be obtained by deleting symbols from `longer`.
+fn is_subsequence(shorter: &[u8], longer: &[u8]) -> bool {
+    let mut longer = longer.iter();
+    shorter.iter().all(|c| longer.any(|d| d == c))
 }
 
-/// Detect string pairs across two input collections that lie within a threshold edit distance.
-///
-/// The function considers all string pairs in the cartesian product of `query` and `reference`,
-/// and returns all those where the two strings are no more than `max_distance` Levenshtein edit
-/// distance units apart.
-///
-/// # Errors
-///
-/// Currently, the crate only supports ASCII input. The function will [`Err`] with
-/// [`Error::NonAsciiInput`] if `query` or `reference` contain any non-ASCII data.
+/// Where and within how much memory to sort deletion variants externally (see
+/// [`SearchOptions::low_memory`]).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LowMemory {
+    /// The directory in which the sorted runs are spilled to temporary files.
+    pub dir: PathBuf,
+    /// The number of bytes of memory in which deletion variants, and then candidate pairs, are
+    /// sorted at a time, including scratch space. Each run holds the variants of at least one
+    /// string, and a few thousand candidates, however small the budget.
+    pub budget_bytes: usize,
+}
+
+/// Additional options for the `*_with_options` family of functions and methods.
 ///
-/// There are some hard limits on the sizes of the input arguments (see [`Error::TooManyStrings`],
-/// [`Error::MaxDistCapped`]). Note however that in practice, runtime or memory usage is almost
-/// certainly the limiting factor instead.
+/// The options are constructed via [`Default`], after which the relevant fields can be set.
 ///
 /// # Examples
 ///
 /// ```
-/// use symscan::{get_neighbors_across, NeighborPairs};
-///
-/// let query = ["fizz", "fuzz", "buzz"];
-/// let reference = ["fooo", "barr", "bazz", "buzz"];
-/// let NeighborPairs { row, col, dists } = get_neighbors_across(&query, &reference, 1).unwrap();
+/// use symscan::{get_neighbors_within_with_options, CancellationToken, Error, SearchOptions};
 ///
-/// assert_eq!(row,   vec![1, 2, 2]);
-/// assert_eq!(col,   vec![3, 2, 3]);
-/// assert_eq!(dists, vec![1, 1, 0]);
+/// let token = CancellationToken::new();
+/// let mut options = SearchOptions::default();
+/// options.cancellation = Some(token.clone());
 ///
-/// let NeighborPairs { row, col, dists } = get_neighbors_across(&query, &reference, 2).unwrap();
+/// token.cancel();
+/// let result = get_neighbors_within_with_options(&["fizz", "fuzz", "buzz"], 1, &options);
 ///
-/// assert_eq!(row,   vec![0, 0, 1, 1, 2, 2]);
-/// assert_eq!(col,   vec![2, 3, 2, 3, 2, 3]);
-/// assert_eq!(dists, vec![2, 2, 2, 1, 1, 0]);
+/// assert!(matches!(result, Err(Error::Cancelled)));
 /// ```
-pub fn get_neighbors_across(
-    query: &[impl AsRef<str> + Sync],
-    reference: &[impl AsRef<str> + Sync],
-    max_distance: u8,
-) -> Result<NeighborPairs, Error> {
-    if query.len() > CrossIndex::MAX as usize {
-        return Err(Error::TooManyStrings {
-            input_type: InputType::Query,
-            got: query.len(),
-            limit: CrossIndex::MAX as usize,
-        });
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct SearchOptions {
+    /// A token through which the computation can be aborted, in which case [`Error::Cancelled`]
+    /// is returned. Cancellation is checked between the phases of the computation, as well as
+    /// periodically during distance computations.
+    pub cancellation: Option<CancellationToken>,
+    /// Experimental: in [`get_neighbors_across`], sort the deletion variants in runs of bounded
+    /// size and find convergence groups via a k-way merge of the sorted runs, rather than sorting
+    /// all deletion variants at once. The results are identical either way.
+    pub merge_sorted_runs: bool,
+    /// Experimental: in [`get_neighbors_within`] and [`get_neighbors_across`], verify the
+    /// candidate pairs of each convergence group as they are enumerated, rather than collecting,
+    /// sorting and deduplicating all candidates before verifying them. This avoids holding all
+    /// candidates in memory at once, so that peak memory use is bounded by the number of hits
+    /// rather than of candidates. Each worker skips candidates that it has recently verified, but
+    /// pairs that share multiple deletion variants may still be verified more than once. The
+    /// results are identical either way.
+    pub fuse_verification: bool,
+    /// In [`get_neighbors_within`] and [`get_neighbors_across`], sort the deletion variants
+    /// externally: generate and sort them in runs that fit within the given memory budget, spill
+    /// each sorted run to a temporary file in the given directory, and find the convergence
+    /// groups via a k-way merge of the spilled runs. The candidate pairs of each group are
+    /// generated as soon as the merge completes the group, and are themselves sorted and spilled
+    /// in runs, which are merged again to verify the candidates in blocks. This bounds the memory
+    /// taken up by the deletion variants, convergence groups and candidates, which otherwise
+    /// dominate peak memory use, at the expense of disk I/O. The spill files are removed once the
+    /// search completes or fails, and errors in writing or reading them result in [`Error::Io`].
+    /// Takes precedence over [`SearchOptions::fuse_verification`], but not over
+    /// [`SearchOptions::exact_grouping`]. Defaults to `None`, which sorts in memory. The results
+    /// are identical either way.
+    pub low_memory: Option<LowMemory>,
+    /// In [`get_neighbors_within`] and [`get_neighbors_across`], if there are at most this many
+    /// possible string pairs (`n * (n - 1) / 2` and `n_query * n_reference` respectively), skip
+    /// the symmetric deletion machinery and directly compute the distances of all pairs, which
+    /// has lower fixed overheads for tiny inputs. If `None` (the default), all pairs are compared
+    /// directly if there are no more of them than the deletion variants that the symmetric
+    /// deletion search would generate, which is roughly where the two approaches break even. Set
+    /// to `Some(0)` to always use symmetric deletion. The results are identical either way.
+    pub brute_force_max_pairs: Option<usize>,
+    /// The maximum number of candidate pairs (counted per shared deletion variant, i.e. before
+    /// deduplication) that a search may enumerate for verification. Exceeding it results in
+    /// [`Error::TooManyCandidates`], which is checked before any candidates are allocated. If
+    /// `None` (the default), only searches whose candidates could not be allocated at all are
+    /// rejected. Has no effect on [`SearchOptions::fuse_verification`], which does not hold the
+    /// candidates in memory.
+    pub max_candidates: Option<usize>,
+    /// The distance metric with which candidate pairs are verified, and in which the distances of
+    /// the returned pairs are expressed. Defaults to [`Metric::Levenshtein`].
+    pub metric: Metric,
+    /// Only return pairs that are at least this distance apart, such that only pairs within the
+    /// band `min_distance..=max_distance` are returned. Defaults to 0.
+    pub min_distance: u8,
+    /// Shorthand for a [`SearchOptions::min_distance`] of (at least) 1, which excludes pairs of
+    /// identical strings.
+    ///
+    /// With either option set, [`get_neighbors_within`] does not even consider identical strings
+    /// as candidate pairs, which saves enumerating the quadratic number of pairs between many
+    /// copies of the same string.
+    pub exclude_exact: bool,
+    /// Accept non-ASCII input, rather than rejecting it with [`Error::NonAsciiInput`]. Non-ASCII
+    /// strings are then treated as sequences of Unicode scalar values ([`char`]s), so that their
+    /// deletion variants are formed by deleting whole characters, and their distances count
+    /// edits to whole characters. ASCII strings are processed as usual, and can be matched
+    /// against non-ASCII ones. Has no effect on the `*_offsets` family of functions, which only
+    /// support ASCII input.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{get_neighbors_within_with_options, SearchOptions};
+    ///
+    /// let mut options = SearchOptions::default();
+    /// options.unicode = true;
+    ///
+    /// let hits = get_neighbors_within_with_options(&["café", "cafe", "東京都", "京都"], 1, &options);
+    ///
+    /// assert_eq!(hits.unwrap().dists, vec![1, 1]);
+    /// ```
+    pub unicode: bool,
+    /// A predicate applied to each candidate pair of (query index, reference index) before it is
+    /// verified, such that only the pairs it keeps can be returned. Rejected pairs are never
+    /// verified, so that excluding pairs a priori (e.g. pairs of strings from the same donor)
+    /// costs little beyond generating them as candidates. The indices are those of the input
+    /// strings, and in searches within a single collection, pairs are passed as (row, column)
+    /// with the row index smaller than the column index. The output remains sorted and
+    /// deduplicated. Defaults to `None`, which keeps all pairs.
+    pub pair_filter: Option<PairFilter>,
+    /// Pairs of (query index, reference index) that are never returned, given in strictly
+    /// increasing order. In searches within a single collection, pairs are given as (row, column)
+    /// with the row index smaller than the column index. The excluded pairs are subtracted from
+    /// the sorted candidates in a single merge pass before any distances are computed, which
+    /// suits skipping pairs that are already known from earlier runs. An unsorted list, or one
+    /// referring to strings beyond the input, results in [`Error::InvalidExcludedPair`]. Has no
+    /// effect on [`CachedRef`]. Defaults to `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{get_neighbors_within_with_options, NeighborPairs, SearchOptions};
+    ///
+    /// let mut options = SearchOptions::default();
+    /// options.excluded_pairs = Some(vec![(0, 1)].into());
+    ///
+    /// let NeighborPairs { row, col, dists } =
+    ///     get_neighbors_within_with_options(&["fizz", "fuzz", "buzz"], 1, &options).unwrap();
+    ///
+    /// assert_eq!(row,   vec![1]);
+    /// assert_eq!(col,   vec![2]);
+    /// assert_eq!(dists, vec![1]);
+    /// ```
+    pub excluded_pairs: Option<Arc<[(u32, u32)]>>,
+    /// In [`get_neighbors_within`], [`get_neighbors_across`] and [`CachedRef::new`], collapse
+    /// identical input strings onto a single copy before searching, and expand the results back
+    /// to the original indices afterwards, including the distance-0 pairs between the copies of
+    /// each string (unless exact pairs are excluded). This saves generating, sorting and
+    /// verifying the deletion variants of each copy separately, which pays off for inputs with
+    /// many duplicates. The results are identical either way.
+    pub deduplicate: bool,
+    /// Compare strings regardless of case, such that strings differing only in case are at
+    /// distance 0. All strings are lowercased before their deletion variants are generated and
+    /// their distances are computed, so that the returned distances are those between the
+    /// lowercased strings, while the returned indices still refer to the original strings. Text
+    /// is lowercased in full (see [`str::to_lowercase`]), but raw bytes only in the ASCII range.
+    ///
+    /// A [`CachedRef`] constructed with this option holds its strings lowercased, and lowercases
+    /// the strings it is later queried with or extended by accordingly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{get_neighbors_within_with_options, SearchOptions};
+    ///
+    /// let mut options = SearchOptions::default();
+    /// options.case_insensitive = true;
+    ///
+    /// let hits = get_neighbors_within_with_options(&["Apple", "APPLE", "apples"], 1, &options);
+    ///
+    /// assert_eq!(hits.unwrap().dists, vec![0, 1, 1]);
+    /// ```
+    pub case_insensitive: bool,
+    /// In searches for pairs within a single collection, return each pair in both orientations,
+    /// i.e. as both (row, column) and (column, row), rather than only once with the row index
+    /// smaller than the column index. The output is still sorted by (row, column), and holds
+    /// exactly twice as many pairs, which suits building adjacency structures directly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{get_neighbors_within_with_options, NeighborPairs, SearchOptions};
+    ///
+    /// let mut options = SearchOptions::default();
+    /// options.symmetric = true;
+    ///
+    /// let NeighborPairs { row, col, dists } =
+    ///     get_neighbors_within_with_options(&["fizz", "fuzz", "buzz"], 1, &options).unwrap();
+    ///
+    /// assert_eq!(row,   vec![0, 1, 1, 2]);
+    /// assert_eq!(col,   vec![1, 0, 2, 1]);
+    /// assert_eq!(dists, vec![1, 1, 1, 1]);
+    /// ```
+    pub symmetric: bool,
+    /// The order in which the pairs found by [`get_neighbors_within`], [`get_neighbors_across`]
+    /// and their variants (including those of [`CachedRef`]) are returned. Defaults to
+    /// [`OutputOrder::ByRowThenCol`]. The order is deterministic, regardless of the number of
+    /// threads used, unless [`OutputOrder::Unordered`] is requested. Searches that return at
+    /// most `k` hits per query document their own order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{get_neighbors_within_with_options, NeighborPairs, OutputOrder, SearchOptions};
+    ///
+    /// let mut options = SearchOptions::default();
+    /// options.order = OutputOrder::ByDistance;
+    ///
+    /// let NeighborPairs { row, col, dists } =
+    ///     get_neighbors_within_with_options(&["fizz", "fuzz", "buzz"], 2, &options).unwrap();
+    ///
+    /// assert_eq!(row,   vec![0, 1, 0]);
+    /// assert_eq!(col,   vec![1, 2, 2]);
+    /// assert_eq!(dists, vec![1, 1, 2]);
+    /// ```
+    pub order: OutputOrder,
+    /// The thread pool in which to run the parallel parts of the computation. If `None` (the
+    /// default), they run in the current rayon thread pool, which is the global pool unless
+    /// called from within another pool. This allows confining searches to a dedicated pool
+    /// without touching the global one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use symscan::{get_neighbors_within_with_options, SearchOptions};
+    ///
+    /// let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    /// let mut options = SearchOptions::default();
+    /// options.thread_pool = Some(Arc::new(pool));
+    ///
+    /// let hits = get_neighbors_within_with_options(&["fizz", "fuzz", "buzz"], 1, &options);
+    ///
+    /// assert_eq!(hits.unwrap().dists, vec![1, 1]);
+    /// ```
+    pub thread_pool: Option<Arc<ThreadPool>>,
+    /// The seed with which deletion variants are hashed. The results are the same for any seed,
+    /// except where the hashes of distinct deletion variants collide, which only costs verifying
+    /// some additional candidate pairs. Pinning a seed makes the hashes reproducible across
+    /// processes, while a randomly drawn seed keeps adversarially crafted input from forcing
+    /// collisions. Defaults to 0.
+    ///
+    /// A [`CachedRef`] hashes the deletion variants of its reference strings with the seed it was
+    /// constructed with, and keeps using that seed (see [`CachedRef::hash_seed`]) for the strings
+    /// it is extended by or queried with, regardless of the options of later calls.
+    pub hash_seed: u64,
+    /// In [`get_neighbors_within`] and [`get_neighbors_across`], hash deletion variants to 128
+    /// rather than 64 bits. With billions of deletion variants, 64-bit hashes of distinct
+    /// variants start to collide, which inflates convergence groups with spurious candidate pairs.
+    /// Wider hashes all but rule such collisions out, at the cost of a second hashing pass over
+    /// the deletion variants, and of 20 rather than 12 bytes per deletion variant while they are
+    /// sorted. The results are identical either way. Has no effect on [`CachedRef`], which keys
+    /// its deletion variants on 64-bit hashes. Defaults to `false`.
+    pub wide_hashes: bool,
+    /// Hash deletion variants to 32 rather than 64 bits, which brings the memory taken up by each
+    /// deletion variant while they are sorted down from 12 to 8 bytes, at the cost of spurious
+    /// candidate pairs between strings whose distinct deletion variants collide. Among `n`
+    /// distinct deletion variants, about `n * n / 2^33` pairs collide, each of which typically
+    /// adds a spurious candidate pair: some ten thousand for 10 million deletion variants (e.g.
+    /// a million strings at `max_distance` 1), a million for 100 million variants, and a hundred
+    /// million for a billion variants, beyond which the extra verification starts to outweigh
+    /// the memory saved. Spurious candidates are ruled out on verification, so that the results
+    /// are identical either way. [`SearchOptions::wide_hashes`] takes precedence.
+    ///
+    /// A [`CachedRef`] constructed with this option truncates the hashes of its deletion variants
+    /// to 32 bits, and keeps doing so (see [`CachedRef::narrow_hashes`]) for the strings it is
+    /// extended by or queried with, regardless of the options of later calls. Its table of
+    /// deletion variants then takes up 20 rather than 24 bytes per entry. Defaults to `false`.
+    pub narrow_hashes: bool,
+    /// In [`get_neighbors_within`] and [`get_neighbors_across`], only pair up strings as
+    /// candidates if they share an identical deletion variant, rather than just a deletion variant
+    /// hash. Each deletion variant is then recorded along with its position among the variants of
+    /// its string, from which the variants sharing a hash are reconstructed and compared byte by
+    /// byte before their strings are paired up. This guarantees that colliding hashes never give
+    /// rise to candidate pairs, at the cost of 4 more bytes per deletion variant while they are
+    /// sorted, and of reconstructing the variants of every convergence group. The results are
+    /// identical either way, as all candidates are verified. Takes precedence over
+    /// [`SearchOptions::wide_hashes`] and [`SearchOptions::merge_sorted_runs`], and has no effect
+    /// on [`CachedRef`]. Defaults to `false`.
+    pub exact_grouping: bool,
+    /// In [`get_neighbors_within`] and [`get_neighbors_across`], partition the strings into
+    /// blocks of consecutive lengths and search each block separately, since strings whose
+    /// lengths differ by more than `max_distance` can never be neighbors. Each block of query
+    /// strings is searched along with the strings up to `max_distance` longer (and, across
+    /// collections, shorter) than its own, so that every pair is still found exactly once, while
+    /// each search generates and sorts the deletion variants of fewer strings, and fewer strings
+    /// of very different lengths converge on short deletion variants.
+    ///
+    /// This pays off for inputs spanning a wide range of lengths, at the cost of generating the
+    /// deletion variants of strings near the boundaries between blocks more than once. The blocks
+    /// are searched concurrently unless progress is reported (see [`SearchOptions::progress`]).
+    /// The results are identical either way, except that [`SearchOptions::max_candidates`]
+    /// applies to each block separately. Only applies to searches returning all pairs, and not to
+    /// grouped searches or [`CachedRef`]. Defaults to `false`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{get_neighbors_within_with_options, SearchOptions};
+    ///
+    /// let mut options = SearchOptions::default();
+    /// options.partition_by_length = true;
+    ///
+    /// let query = ["fizz", "fuzz", "buzz", "fizzbuzz", "fuzzbuzz"];
+    /// let hits = get_neighbors_within_with_options(&query, 1, &options).unwrap();
+    ///
+    /// assert_eq!(hits.row,   vec![0, 1, 3]);
+    /// assert_eq!(hits.col,   vec![1, 2, 4]);
+    /// assert_eq!(hits.dists, vec![1, 1, 1]);
+    /// ```
+    pub partition_by_length: bool,
+    /// A receiver of progress reports as the search runs through its phases (see
+    /// [`ProgressSink`]). Defaults to `None`, in which case no progress is reported.
+    pub progress: Option<Arc<dyn ProgressSink>>,
+    /// Only consider strings neighbors if they share identical anchored regions of the given
+    /// lengths at their start and end, such as the conserved residues flanking CDR3 sequences.
+    /// Strings shorter than the two regions combined have no neighbors at all, unless
+    /// [`SearchOptions::short_strings`] says otherwise. Distances are computed over the whole
+    /// strings as usual, which for identical anchored regions is the distance between the
+    /// interiors. Defaults to no anchored regions.
+    ///
+    /// In [`get_neighbors_within`], [`get_neighbors_across`] and their variants, deletion
+    /// variants are only generated from the interior of each string between the anchored
+    /// regions, and hashed along with the anchored regions themselves. This shrinks the set of
+    /// candidates to verify considerably, besides saving the variants that would delete
+    /// anchored symbols. A [`CachedRef`] holds the deletion variants of whole strings, so
+    /// queries against it only enforce the anchored regions when verifying candidates.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{get_neighbors_within_with_options, Anchor, SearchOptions};
+    ///
+    /// let mut options = SearchOptions::default();
+    /// options.anchor = Anchor { prefix: 1, suffix: 0 };
+    ///
+    /// let hits = get_neighbors_within_with_options(&["fizz", "fuzz", "buzz"], 1, &options);
+    ///
+    /// assert_eq!(hits.unwrap().col, vec![1]);
+    /// ```
+    pub anchor: Anchor,
+    /// How strings too short to hold the anchored regions of [`SearchOptions::anchor`] are
+    /// treated. Defaults to [`ShortStrings::Skip`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{get_neighbors_within_with_options, Anchor, SearchOptions, ShortStrings};
+    ///
+    /// let mut options = SearchOptions::default();
+    /// options.anchor = Anchor { prefix: 2, suffix: 2 };
+    /// options.short_strings = ShortStrings::MatchExactly;
+    ///
+    /// let hits = get_neighbors_within_with_options(&["fizz", "fuzz", "biz", "biz"], 1, &options);
+    ///
+    /// assert_eq!(hits.unwrap().row, vec![2]);
+    /// ```
+    pub short_strings: ShortStrings,
+    /// Where searches record their statistics, as set by [`get_neighbors_within_with_stats`] and
+    /// related functions.
+    stats: Option<Arc<StatsRecorder>>,
+}
+
+impl SearchOptions {
+    /// Whether to compare all `num_pairs` string pairs directly, rather than searching for them
+    /// via `num_variants` deletion variants (see [`SearchOptions::brute_force_max_pairs`]).
+    fn use_brute_force(&self, num_pairs: u128, num_variants: impl FnOnce() -> usize) -> bool {
+        match self.brute_force_max_pairs {
+            Some(max_pairs) => num_pairs <= max_pairs as u128,
+            None => num_pairs <= num_variants() as u128,
+        }
     }
-    if reference.len() > CrossIndex::MAX as usize {
-        return Err(Error::TooManyStrings {
-            input_type: InputType::Reference,
-            got: reference.len(),
-            limit: CrossIndex::MAX as usize,
-        });
+
+    fn progress_reporter(&self) -> Progress<'_> {
+        Progress {
+            sink: self.progress.as_deref(),
+            stats: self.stats.as_deref(),
+        }
     }
-    let max_distance = MaxDistance::try_from(max_distance)?;
-    check_strings_ascii(query, InputType::Query)?;
-    check_strings_ascii(reference, InputType::Reference)?;
 
-    let (convergent_indices, group_sizes) = {
-        let num_del_variants_q = get_num_del_vars_per_string(query, max_distance);
-        let num_del_variants_r = get_num_del_vars_per_string(reference, max_distance);
+    /// A copy of the options under which searches record their statistics into the returned
+    /// recorder.
+    fn recording_stats(&self) -> (SearchOptions, Arc<StatsRecorder>) {
+        let recorder = Arc::new(StatsRecorder::default());
+        let options = SearchOptions {
+            stats: Some(recorder.clone()),
+            ..self.clone()
+        };
+        (options, recorder)
+    }
 
-        let total_capacity =
-            num_del_variants_q.iter().sum::<usize>() + num_del_variants_r.iter().sum::<usize>();
-        let mut variant_index_pairs_uninit = prealloc_maybeuninit_vec(total_capacity);
+    /// The thread pool in which to run the computation, if one is given and the current thread
+    /// is not already one of its workers.
+    fn outside_thread_pool(&self) -> Option<&ThreadPool> {
+        self.thread_pool
+            .as_deref()
+            .filter(|pool| pool.current_thread_index().is_none())
+    }
 
-        let mut vip_chunks_q = Vec::with_capacity(query.len());
-        let mut remaining = &mut variant_index_pairs_uninit[..];
-        for n in num_del_variants_q {
-            let (chunk, rest) = remaining.split_at_mut(n);
-            vip_chunks_q.push(chunk);
-            remaining = rest;
+    /// The options with which to search for pairs to be returned in the order of
+    /// [`SearchOptions::order`].
+    fn for_reordering(&self) -> Self {
+        Self {
+            order: OutputOrder::Unordered,
+            ..self.clone()
         }
+    }
 
-        let mut vip_chunks_r = Vec::with_capacity(reference.len());
-        for n in num_del_variants_r {
-            let (chunk, rest) = remaining.split_at_mut(n);
-            vip_chunks_r.push(chunk);
-            remaining = rest;
+    /// The options with which to search for the pairs to be returned in both orientations.
+    fn for_one_orientation(&self) -> Self {
+        Self {
+            symmetric: false,
+            ..self.clone()
         }
+    }
 
-        debug_assert_eq!(remaining.len(), 0);
-        debug_assert_eq!(vip_chunks_q.len(), query.len());
-        debug_assert_eq!(vip_chunks_r.len(), reference.len());
-
-        let hash_builder = FixedState::default();
+    /// The options with which to search strings whose case has already been folded.
+    fn for_folded_strings(&self) -> Self {
+        Self {
+            case_insensitive: false,
+            ..self.clone()
+        }
+    }
 
-        query
-            .par_iter()
-            .zip(vip_chunks_q.into_par_iter())
-            .enumerate()
-            .with_min_len(100000)
-            .for_each(|(idx, (s, chunk))| {
-                write_vi_pairs_ci(
-                    s.as_ref(),
-                    idx as u32,
-                    max_distance,
-                    false,
-                    chunk,
-                    &hash_builder,
-                );
-            });
-        reference
-            .par_iter()
-            .zip(vip_chunks_r.into_par_iter())
-            .enumerate()
-            .with_min_len(100000)
-            .for_each(|(idx, (s, chunk))| {
-                write_vi_pairs_ci(
-                    s.as_ref(),
-                    idx as u32,
-                    max_distance,
-                    true,
-                    chunk,
-                    &hash_builder,
-                );
-            });
+    /// The options with which to search the distinct strings of a deduplicated input. The pair
+    /// filter and excluded pairs refer to the original indices, so it is applied once the hits are expanded.
+    fn for_unique_strings(&self) -> Self {
+        Self {
+            deduplicate: false,
+            pair_filter: None,
+            excluded_pairs: None,
+            ..self.clone()
+        }
+    }
 
-        let mut variant_index_pairs =
-            unsafe { cast_to_initialised_vec(variant_index_pairs_uninit) };
+    /// The options with which to search each block of a partition by length. The pair filter and
+    /// excluded pairs refer to the original indices, so they are applied once the hits are mapped
+    /// back to them.
+    fn for_length_partitions(&self) -> Self {
+        Self {
+            partition_by_length: false,
+            pair_filter: None,
+            excluded_pairs: None,
+            ..self.clone()
+        }
+    }
 
-        variant_index_pairs.par_sort_unstable();
-        variant_index_pairs.dedup();
+    /// The options with which to search for the hits from which representatives are picked, which
+    /// must be returned once each, sorted by (row, column).
+    fn for_representatives(&self) -> Self {
+        Self {
+            symmetric: false,
+            order: OutputOrder::ByRowThenCol,
+            ..self.clone()
+        }
+    }
 
-        let mut total_num_convergent_indices = 0;
-        let mut num_convergence_groups = 0;
+    /// Whether the pair filter (if any) keeps the pair (`qi`, `ri`), and it is not excluded.
+    fn keeps(&self, qi: u32, ri: u32) -> bool {
+        self.pair_filter
+            .as_ref()
+            .is_none_or(|filter| filter.keeps(qi, ri))
+            && self
+                .excluded_pairs
+                .as_ref()
+                .is_none_or(|excluded| excluded.binary_search(&(qi, ri)).is_err())
+    }
 
-        variant_index_pairs
-            .chunk_by(|(v1, _), (v2, _)| v1 == v2)
-            .filter(|chunk| chunk.len() > 1)
-            .for_each(|chunk| {
-                total_num_convergent_indices += chunk.len();
-                num_convergence_groups += 1;
-            });
+    /// Check that the excluded pairs (if any) are strictly increasing and refer to strings of a
+    /// query of `query_len` and a reference of `reference_len` strings, or of a single collection
+    /// of `query_len` strings if `reference_len` is `None`.
+    fn check_excluded_pairs(
+        &self,
+        query_len: usize,
+        reference_len: Option<usize>,
+    ) -> Result<(), Error> {
+        let Some(excluded) = &self.excluded_pairs else {
+            return Ok(());
+        };
+        let in_range = |(qi, ri): (u32, u32)| match reference_len {
+            Some(reference_len) => (qi as usize) < query_len && (ri as usize) < reference_len,
+            None => qi < ri && (ri as usize) < query_len,
+        };
+        for (idx, &pair) in excluded.iter().enumerate() {
+            if !in_range(pair) || (idx > 0 && excluded[idx - 1] >= pair) {
+                return Err(Error::InvalidExcludedPair { idx, pair });
+            }
+        }
+        Ok(())
+    }
 
-        let mut convergent_indices = Vec::with_capacity(total_num_convergent_indices);
-        let mut convergence_group_sizes = Vec::with_capacity(num_convergence_groups);
+    /// Remove the excluded pairs (if any) from the sorted and deduplicated `candidates`, by
+    /// merging the two sorted lists.
+    fn remove_excluded(&self, candidates: &mut Vec<(u32, u32)>) {
+        let Some(excluded) = &self.excluded_pairs else {
+            return;
+        };
+        let mut excluded = excluded.iter().peekable();
+        candidates.retain(|candidate| {
+            while excluded.next_if(|&pair| pair < candidate).is_some() {}
+            excluded.peek() != Some(&candidate)
+        });
+    }
 
-        variant_index_pairs
-            .chunk_by(|(v1, _), (v2, _)| v1 == v2)
-            .filter(|chunk| chunk.len() > 1)
-            .map(|chunk| {
-                let len_q = chunk.iter().filter(|(_, ci)| !ci.is_ref()).count();
-                let len_r = chunk.iter().filter(|(_, ci)| ci.is_ref()).count();
-                (chunk, len_q, len_r)
-            })
-            .filter(|(_, len_q, len_r)| len_q * len_r > 0)
-            .for_each(|(chunk, len_q, len_r)| {
-                convergent_indices.extend(
-                    chunk
-                        .iter()
-                        .filter(|(_, ci)| !ci.is_ref())
-                        .map(|&(_, ci)| ci.get_value()),
-                );
-                convergent_indices.extend(
-                    chunk
-                        .iter()
-                        .filter(|(_, ci)| ci.is_ref())
-                        .map(|&(_, ci)| ci.get_value()),
-                );
+    /// The smallest distance of returned pairs, taking [`SearchOptions::exclude_exact`] into
+    /// account.
+    fn effective_min_distance(&self) -> u8 {
+        self.min_distance.max(self.exclude_exact as u8)
+    }
 
-                convergence_group_sizes.push((len_q, len_r));
-            });
+    /// Equivalent to [`SearchOptions::bounded_dist`], but for thresholds beyond the cap on
+    /// `max_distance` (see [`get_neighbors_within_wide`]), returning None for pairs that are not
+    /// neighbors, including those closer than [`SearchOptions::min_distance`].
+    fn wide_dist(
+        &self,
+        qi: u32,
+        ri: u32,
+        a: Symbols,
+        b: Symbols,
+        max_distance: u16,
+    ) -> Option<u16> {
+        if let Some(filter) = &self.pair_filter {
+            if !filter.keeps(qi, ri) {
+                return None;
+            }
+        }
+        if !self.anchor.is_none() && !self.anchoring().matches(a, b) {
+            return None;
+        }
+        let dist = self.metric.dist_up_to(a, b, max_distance as usize)?;
+        (dist >= self.effective_min_distance() as usize).then_some(dist as u16)
+    }
 
-        (convergent_indices, convergence_group_sizes)
-    };
+    /// The distance between the strings `a` and `b` of the candidate pair (`qi`, `ri`) under
+    /// [`SearchOptions::metric`], or u8::MAX if it exceeds `max_distance` or the pair is rejected
+    /// by [`SearchOptions::pair_filter`] or [`SearchOptions::anchor`], in which case no distance
+    /// is computed at all.
+    fn bounded_dist(
+        &self,
+        qi: u32,
+        ri: u32,
+        a: Symbols,
+        b: Symbols,
+        max_distance: MaxDistance,
+    ) -> u8 {
+        self.bounded_dist_with(qi, ri, a, b, max_distance, None)
+    }
 
-    let mut convergent_chunks = Vec::with_capacity(group_sizes.len());
-    let mut remaining = &convergent_indices[..];
-    for (n_q, n_r) in group_sizes {
-        let (chunk_q, rest) = remaining.split_at(n_q);
-        let (chunk_r, rest) = rest.split_at(n_r);
-        convergent_chunks.push((chunk_q, chunk_r));
-        remaining = rest;
+    /// Equivalent to [`SearchOptions::bounded_dist`], but comparing against `a` via `cached`, the
+    /// comparator of `a` if one was built (see [`QueryComparator`]).
+    #[inline(always)]
+    fn bounded_dist_with(
+        &self,
+        qi: u32,
+        ri: u32,
+        a: Symbols,
+        b: Symbols,
+        max_distance: MaxDistance,
+        cached: Option<&levenshtein::BatchComparator<u8>>,
+    ) -> u8 {
+        if let Some(filter) = &self.pair_filter {
+            if !filter.keeps(qi, ri) {
+                return u8::MAX;
+            }
+        }
+        if !self.anchor.is_none() && !self.anchoring().matches(a, b) {
+            return u8::MAX;
+        }
+        self.metric.bounded_dist(a, b, max_distance, cached)
     }
 
-    debug_assert_eq!(remaining.len(), 0);
+    /// The hasher for deletion variants, seeded with [`SearchOptions::hash_seed`].
+    fn hash_builder(&self) -> FixedState {
+        FixedState::with_seed(self.hash_seed)
+    }
 
-    let candidates = get_hit_candidates_from_cis_cross(&convergent_chunks);
-    let dists = compute_dists(&candidates, &query, &reference, max_distance);
+    /// See [`SearchOptions::anchor`] and [`SearchOptions::short_strings`].
+    fn anchoring(&self) -> Anchoring {
+        Anchoring {
+            anchor: self.anchor,
+            short_strings: self.short_strings,
+        }
+    }
 
-    Ok(collect_true_hits(&candidates, &dists, max_distance))
-}
+    /// Check that `strings` are ASCII, unless [`SearchOptions::unicode`] is set.
+    fn check_strings(
+        &self,
+        strings: &[impl AsRef<str>],
+        input_type: InputType,
+    ) -> Result<(), Error> {
+        if self.unicode {
+            Ok(())
+        } else {
+            check_strings_ascii(strings, input_type)
+        }
+    }
 
-fn check_strings_ascii(strings: &[impl AsRef<str>], input_type: InputType) -> Result<(), Error> {
-    for (idx, s) in strings.iter().enumerate() {
-        if !s.as_ref().is_ascii() {
-            return Err(Error::NonAsciiInput {
-                input_type,
-                offending_idx: idx,
-                offending_string: s.as_ref().to_string(),
-            });
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(|token| token.is_cancelled())
+    }
+
+    fn check_cancelled(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
         }
     }
-    Ok(())
 }
 
-fn get_num_del_vars_per_string(
-    strings: &[impl AsRef<str>],
-    max_distance: MaxDistance,
-) -> Vec<usize> {
-    strings
-        .iter()
-        .map(|s| {
-            let mut num_vars = 0;
-            for k in 0..=max_distance.as_u8() {
-                if k as usize > s.as_ref().len() {
-                    break;
-                }
-                num_vars += get_num_k_combs(s.as_ref().len(), k);
-            }
-            num_vars
-        })
-        .collect_vec()
+/// A complete description of a search: the maximum distance along with all [`SearchOptions`],
+/// assembled with chained builder methods.
+///
+/// This is an alternative to passing a `max_distance` and [`SearchOptions`] to the
+/// `*_with_options` functions and methods, which it runs under the hood. A [`CachedRef`] can be
+/// constructed from the same config via [`SearchConfig::build_cache`], and the config is checked
+/// for compatibility with the cache when run against it.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{Metric, NeighborPairs, SearchConfig};
+///
+/// let config = SearchConfig::new(1).metric(Metric::Osa).exclude_exact(true);
+///
+/// let NeighborPairs { row, col, dists } = config
+///     .run_within(&["fizz", "fizz", "fzz", "ifzz"])
+///     .unwrap();
+///
+/// assert_eq!(row,   vec![0, 0, 1, 1, 2]);
+/// assert_eq!(col,   vec![2, 3, 2, 3, 3]);
+/// assert_eq!(dists, vec![1, 1, 1, 1, 1]);
+///
+/// let cached = config.build_cache(&["fuzz", "buzz"]).unwrap();
+/// let hits = config.run_across_cached(&["fizz", "buzz"], &cached).unwrap();
+///
+/// assert_eq!(hits.row, vec![0, 1]);
+/// assert_eq!(hits.col, vec![0, 0]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct SearchConfig {
+    max_distance: u8,
+    options: SearchOptions,
 }
 
-fn get_num_k_combs(n: usize, k: u8) -> usize {
-    debug_assert!(n > 0);
-    debug_assert!(n >= k as usize);
-
-    if k == 0 {
-        return 1;
+impl SearchConfig {
+    /// Construct a new config for searches up to `max_distance`, with default options.
+    pub fn new(max_distance: u8) -> Self {
+        Self {
+            max_distance,
+            options: SearchOptions::default(),
+        }
     }
 
-    let num_subsamples: usize = (n - k as usize + 1..=n).product();
-    let subsample_perms: usize = (1..=k as usize).product();
-
-    return num_subsamples / subsample_perms;
-}
+    /// The maximum distance of the searches.
+    pub fn max_distance(&self) -> u8 {
+        self.max_distance
+    }
 
-/// Given an input string and its index in the original input vector, generate all possible strings
-/// after making at most max_deletions single-character deletions, compute their hash, and write
-/// them into the slots in the provided chunk, as 2-tuples (hash, input_idx).
-fn write_vi_pairs_rawidx(
-    input: &str,
-    input_idx: u32,
-    max_deletions: MaxDistance,
-    chunk: &mut [MaybeUninit<(u64, u32)>],
-    hash_builder: &impl BuildHasher,
-) {
-    let input_length = input.len();
+    /// The options of the searches.
+    pub fn search_options(&self) -> &SearchOptions {
+        &self.options
+    }
 
-    chunk[0].write((hash_string(input, hash_builder), input_idx));
+    /// Replace all options at once.
+    pub fn options(mut self, options: SearchOptions) -> Self {
+        self.options = options;
+        self
+    }
 
-    let mut variant_idx = 1;
-    let mut variant_buffer = Vec::with_capacity(input_length);
-    for num_deletions in 1..=max_deletions.as_u8() {
-        if num_deletions as usize > input_length {
-            break;
-        }
+    /// See [`SearchOptions::metric`].
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.options.metric = metric;
+        self
+    }
 
-        for deletion_indices in (0..input_length).combinations(num_deletions as usize) {
-            variant_buffer.clear();
-            let mut offset = 0;
+    /// See [`SearchOptions::min_distance`].
+    pub fn min_distance(mut self, min_distance: u8) -> Self {
+        self.options.min_distance = min_distance;
+        self
+    }
 
-            for idx in deletion_indices {
-                variant_buffer.extend_from_slice(&input.as_bytes()[offset..idx]);
-                offset = idx + 1;
-            }
-            variant_buffer.extend_from_slice(&input.as_bytes()[offset..input_length]);
+    /// See [`SearchOptions::exclude_exact`].
+    pub fn exclude_exact(mut self, exclude_exact: bool) -> Self {
+        self.options.exclude_exact = exclude_exact;
+        self
+    }
 
-            chunk[variant_idx].write((hash_string(&variant_buffer, hash_builder), input_idx));
-            variant_idx += 1;
-        }
+    /// See [`SearchOptions::unicode`].
+    pub fn unicode(mut self, unicode: bool) -> Self {
+        self.options.unicode = unicode;
+        self
     }
-}
 
-/// Similar to write_deletion_variants_rawidx but with the indices wrapped in CrossIndex.
-fn write_vi_pairs_ci(
-    input: &str,
-    input_idx: u32,
-    max_deletions: MaxDistance,
-    is_ref: bool,
-    chunk: &mut [MaybeUninit<(u64, CrossIndex)>],
-    hash_builder: &impl BuildHasher,
-) {
-    let input_length = input.len();
+    /// See [`SearchOptions::case_insensitive`].
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.options.case_insensitive = case_insensitive;
+        self
+    }
 
-    chunk[0].write((
-        hash_string(input, hash_builder),
-        CrossIndex::from(input_idx, is_ref),
-    ));
+    /// See [`SearchOptions::hash_seed`].
+    pub fn hash_seed(mut self, hash_seed: u64) -> Self {
+        self.options.hash_seed = hash_seed;
+        self
+    }
 
-    let mut variant_idx = 1;
-    let mut variant_buffer = Vec::with_capacity(input_length);
-    for num_deletions in 1..=max_deletions.as_u8() {
-        if num_deletions as usize > input_length {
-            break;
-        }
+    /// See [`SearchOptions::wide_hashes`].
+    pub fn wide_hashes(mut self, wide_hashes: bool) -> Self {
+        self.options.wide_hashes = wide_hashes;
+        self
+    }
 
-        for deletion_indices in (0..input_length).combinations(num_deletions as usize) {
-            variant_buffer.clear();
-            let mut offset = 0;
+    /// See [`SearchOptions::narrow_hashes`].
+    pub fn narrow_hashes(mut self, narrow_hashes: bool) -> Self {
+        self.options.narrow_hashes = narrow_hashes;
+        self
+    }
 
-            for idx in deletion_indices {
-                variant_buffer.extend_from_slice(&input.as_bytes()[offset..idx]);
-                offset = idx + 1;
-            }
-            variant_buffer.extend_from_slice(&input.as_bytes()[offset..input_length]);
+    /// See [`SearchOptions::exact_grouping`].
+    pub fn exact_grouping(mut self, exact_grouping: bool) -> Self {
+        self.options.exact_grouping = exact_grouping;
+        self
+    }
 
-            chunk[variant_idx].write((
-                hash_string(&variant_buffer, hash_builder),
-                CrossIndex::from(input_idx, is_ref),
-            ));
-            variant_idx += 1;
-        }
+    /// See [`SearchOptions::partition_by_length`].
+    pub fn partition_by_length(mut self, partition_by_length: bool) -> Self {
+        self.options.partition_by_length = partition_by_length;
+        self
     }
-}
 
-fn hash_string(s: impl AsRef<[u8]>, hash_builder: &impl BuildHasher) -> u64 {
-    let mut hasher = hash_builder.build_hasher();
-    hasher.write(s.as_ref());
-    hasher.finish()
-}
+    /// See [`SearchOptions::symmetric`].
+    pub fn symmetric(mut self, symmetric: bool) -> Self {
+        self.options.symmetric = symmetric;
+        self
+    }
 
-fn prealloc_maybeuninit_vec<T>(total_capacity: usize) -> Vec<MaybeUninit<T>> {
-    let mut v: Vec<MaybeUninit<T>> = Vec::with_capacity(total_capacity);
-    unsafe { v.set_len(total_capacity) };
-    v
-}
+    /// See [`SearchOptions::order`].
+    pub fn order(mut self, order: OutputOrder) -> Self {
+        self.options.order = order;
+        self
+    }
 
-fn get_disjoint_spans(span_lens: &[usize]) -> Vec<Span> {
-    let mut spans = Vec::with_capacity(span_lens.len());
-    let mut cursor = 0;
-    for &n in span_lens {
-        spans.push(Span::new(cursor, n));
-        cursor += n;
+    /// See [`SearchOptions::deduplicate`].
+    pub fn deduplicate(mut self, deduplicate: bool) -> Self {
+        self.options.deduplicate = deduplicate;
+        self
     }
-    spans
-}
 
-fn get_disjoint_chunks_mut<'a, T>(
-    chunk_lens: &[usize],
-    mut backing_memory: &'a mut [T],
-) -> Vec<&'a mut [T]> {
-    let mut chunks = Vec::with_capacity(chunk_lens.len());
-    for &n in chunk_lens {
-        let (chunk, rest) = backing_memory.split_at_mut(n);
-        chunks.push(chunk);
-        backing_memory = rest;
+    /// See [`SearchOptions::pair_filter`].
+    pub fn pair_filter(mut self, pair_filter: PairFilter) -> Self {
+        self.options.pair_filter = Some(pair_filter);
+        self
     }
 
-    debug_assert_eq!(backing_memory.len(), 0);
+    /// See [`SearchOptions::excluded_pairs`].
+    pub fn excluded_pairs(mut self, excluded_pairs: impl Into<Arc<[(u32, u32)]>>) -> Self {
+        self.options.excluded_pairs = Some(excluded_pairs.into());
+        self
+    }
 
-    chunks
-}
+    /// See [`SearchOptions::anchor`].
+    pub fn anchor(mut self, anchor: Anchor) -> Self {
+        self.options.anchor = anchor;
+        self
+    }
 
-unsafe fn cast_to_initialised_vec<T>(mut input: Vec<MaybeUninit<T>>) -> Vec<T> {
-    let ptr = input.as_mut_ptr() as *mut T;
-    let len = input.len();
-    let cap = input.capacity();
-    std::mem::forget(input);
-    Vec::from_raw_parts(ptr, len, cap)
-}
+    /// See [`SearchOptions::short_strings`].
+    pub fn short_strings(mut self, short_strings: ShortStrings) -> Self {
+        self.options.short_strings = short_strings;
+        self
+    }
 
-fn get_hit_candidates_within(convergent_indices: &[impl AsRef<[u32]> + Sync]) -> Vec<(u32, u32)> {
-    let num_hit_candidates = convergent_indices
-        .iter()
-        .map(|indices| get_num_k_combs(indices.as_ref().len(), 2))
-        .collect_vec();
-    let total_capacity = num_hit_candidates.iter().sum();
+    /// See [`SearchOptions::max_candidates`].
+    pub fn max_candidates(mut self, max_candidates: usize) -> Self {
+        self.options.max_candidates = Some(max_candidates);
+        self
+    }
 
-    let mut hit_candidates_uninit = prealloc_maybeuninit_vec(total_capacity);
-    let hc_chunks = get_disjoint_chunks_mut(&num_hit_candidates, &mut hit_candidates_uninit);
+    /// See [`SearchOptions::fuse_verification`].
+    pub fn fuse_verification(mut self, fuse_verification: bool) -> Self {
+        self.options.fuse_verification = fuse_verification;
+        self
+    }
 
-    convergent_indices
-        .par_iter()
-        .zip(hc_chunks.into_par_iter())
-        .with_min_len(100000)
-        .for_each(|(indices, chunk)| {
-            for (i, candidate) in indices
-                .as_ref()
-                .iter()
-                .map(|&v| v)
-                .tuple_combinations()
-                .enumerate()
-            {
-                chunk[i].write(candidate);
-            }
+    /// See [`SearchOptions::low_memory`].
+    pub fn low_memory(mut self, dir: impl Into<PathBuf>, budget_bytes: usize) -> Self {
+        self.options.low_memory = Some(LowMemory {
+            dir: dir.into(),
+            budget_bytes,
         });
+        self
+    }
 
-    let mut hit_candidates = unsafe { cast_to_initialised_vec(hit_candidates_uninit) };
-
-    hit_candidates.par_sort_unstable();
-    hit_candidates.dedup();
-
-    hit_candidates
-}
+    /// See [`SearchOptions::cancellation`].
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.options.cancellation = Some(token);
+        self
+    }
 
-fn get_hit_candidates_from_cis_cross<T, U>(convergent_indices: &[(T, U)]) -> Vec<(u32, u32)>
-where
-    T: AsRef<[u32]> + Sync,
-    U: AsRef<[u32]> + Sync,
-{
-    let num_hit_candidates = convergent_indices
-        .iter()
-        .map(|(qi, ri)| qi.as_ref().len() * ri.as_ref().len())
-        .collect_vec();
-    let total_capacity = num_hit_candidates.iter().sum();
+    /// See [`SearchOptions::thread_pool`].
+    pub fn thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.options.thread_pool = Some(pool);
+        self
+    }
 
-    let mut hit_candidates_uninit = prealloc_maybeuninit_vec(total_capacity);
-    let hc_chunks = get_disjoint_chunks_mut(&num_hit_candidates, &mut hit_candidates_uninit);
+    /// See [`SearchOptions::progress`].
+    pub fn progress(mut self, sink: Arc<dyn ProgressSink>) -> Self {
+        self.options.progress = Some(sink);
+        self
+    }
 
-    convergent_indices
-        .par_iter()
-        .zip(hc_chunks.into_par_iter())
-        .with_min_len(100000)
-        .for_each(|((indices_q, indices_r), chunk)| {
-            for (i, candidate) in indices_q
-                .as_ref()
-                .iter()
-                .map(|&v| v)
-                .cartesian_product(indices_r.as_ref().iter().map(|&v| v))
-                .enumerate()
-            {
-                chunk[i].write(candidate);
-            }
-        });
+    /// Equivalent to [`get_neighbors_within_with_options`] under this config.
+    pub fn run_within(&self, query: &[impl AsRef<str> + Sync]) -> Result<NeighborPairs, Error> {
+        get_neighbors_within_with_options(query, self.max_distance, &self.options)
+    }
 
-    let mut hit_candidates = unsafe { cast_to_initialised_vec(hit_candidates_uninit) };
+    /// Equivalent to [`get_neighbors_across_with_options`] under this config.
+    pub fn run_across(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        reference: &[impl AsRef<str> + Sync],
+    ) -> Result<NeighborPairs, Error> {
+        get_neighbors_across_with_options(query, reference, self.max_distance, &self.options)
+    }
 
-    hit_candidates.par_sort_unstable();
-    hit_candidates.dedup();
+    /// Equivalent to [`CachedRef::new_with_options`] under this config.
+    pub fn build_cache(&self, reference: &[impl AsRef<str> + Sync]) -> Result<CachedRef, Error> {
+        CachedRef::new_with_options(reference, self.max_distance, &self.options)
+    }
 
-    hit_candidates
-}
+    /// Equivalent to [`CachedRef::get_neighbors_within_with_options`] under this config.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors of [`CachedRef::get_neighbors_within_with_options`], returns
+    /// [`Error::MismatchedCaseSensitivity`] if `cached` was not constructed with the same
+    /// [`SearchOptions::case_insensitive`] as this config, and [`Error::MismatchedHashWidth`] if it
+    /// was not constructed with the same [`SearchOptions::narrow_hashes`].
+    pub fn run_within_cached(&self, cached: &CachedRef) -> Result<NeighborPairs, Error> {
+        self.check_cache(cached)?;
+        cached.get_neighbors_within_with_options(self.max_distance, &self.options)
+    }
 
-fn compute_dists(
-    hit_candidates: &[(u32, u32)],
-    query: &[impl AsRef<str> + Sync],
-    reference: &[impl AsRef<str> + Sync],
-    max_distance: MaxDistance,
-) -> Vec<u8> {
-    hit_candidates
-        .par_iter()
-        .with_min_len(100000)
-        .map(|&(idx_query, idx_reference)| {
-            let dist = {
-                match levenshtein::distance_with_args(
-                    query[idx_query as usize].as_ref().bytes(),
-                    reference[idx_reference as usize].as_ref().bytes(),
-                    &levenshtein::Args::default().score_cutoff(max_distance.as_usize()),
-                ) {
-                    None => u8::MAX,
-                    Some(dist) => dist as u8,
-                }
-            };
+    /// Equivalent to [`CachedRef::get_neighbors_across_with_options`] under this config.
+    ///
+    /// # Errors
+    ///
+    /// As for [`SearchConfig::run_within_cached`].
+    pub fn run_across_cached(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        cached: &CachedRef,
+    ) -> Result<NeighborPairs, Error> {
+        self.check_cache(cached)?;
+        cached.get_neighbors_across_with_options(query, self.max_distance, &self.options)
+    }
 
-            dist
-        })
-        .collect()
+    /// Check that `cached` holds what searches under this config need, beyond the maximum
+    /// distance that the cached searches check themselves.
+    fn check_cache(&self, cached: &CachedRef) -> Result<(), Error> {
+        if cached.case_insensitive() != self.options.case_insensitive {
+            return Err(Error::MismatchedCaseSensitivity {
+                cache: cached.case_insensitive(),
+                config: self.options.case_insensitive,
+            });
+        }
+        if cached.narrow_hashes() != self.options.narrow_hashes {
+            return Err(Error::MismatchedHashWidth {
+                cache: cached.hash_width(),
+                got: get_cache_hash_width(self.options.narrow_hashes),
+            });
+        }
+        Ok(())
+    }
 }
 
-/// Examine and double check hits to see if they are real
-fn collect_true_hits(
-    hit_candidates: &[(u32, u32)],
-    dists: &[u8],
-    max_distance: MaxDistance,
-) -> NeighborPairs {
-    let mut qi_filtered = Vec::with_capacity(dists.len());
-    let mut ri_filtered = Vec::with_capacity(dists.len());
-    let mut dists_filtered = Vec::with_capacity(dists.len());
+/// Brute-force equivalents of [`get_neighbors_within`] and [`get_neighbors_across`], for testing
+/// code built on this crate. Enabled by the `testing` feature.
+///
+/// These compare every pair of strings with a textbook dynamic programming implementation of the
+/// Levenshtein distance, which shares no code with the symmetric deletion search, and return the
+/// same pairs in the same order. They take time quadratic in the number of strings and in their
+/// lengths, so they are only suited to small inputs, e.g. as an oracle in property tests.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::get_neighbors_within;
+/// use symscan::naive::naive_within;
+///
+/// let query = ["fizz", "fuzz", "buzz"];
+///
+/// assert_eq!(naive_within(&query, 2), get_neighbors_within(&query, 2).unwrap());
+/// ```
+#[cfg(any(test, feature = "testing"))]
+pub mod naive {
+    use super::{EditCosts, NeighborPairs};
 
-    for (&(qi, ri), &d) in hit_candidates.iter().zip(dists.iter()) {
-        if d > max_distance.as_u8() {
-            continue;
+    /// The brute-force equivalent of [`get_neighbors_within`](super::get_neighbors_within).
+    /// Strings are compared character by character, so that non-ASCII text is also accepted.
+    pub fn naive_within(query: &[impl AsRef<str>], max_distance: u8) -> NeighborPairs {
+        let query = to_chars(query);
+        let mut hits = NeighborPairs::default();
+        for (i, a) in query.iter().enumerate() {
+            for (j, b) in query.iter().enumerate().skip(i + 1) {
+                push_if_within(&mut hits, i, j, a, b, max_distance);
+            }
         }
-        qi_filtered.push(qi);
-        ri_filtered.push(ri);
-        dists_filtered.push(d);
+        hits
     }
 
-    qi_filtered.shrink_to_fit();
-    ri_filtered.shrink_to_fit();
-    dists_filtered.shrink_to_fit();
+    /// The brute-force equivalent of [`get_neighbors_across`](super::get_neighbors_across).
+    /// Strings are compared character by character, so that non-ASCII text is also accepted.
+    pub fn naive_across(
+        query: &[impl AsRef<str>],
+        reference: &[impl AsRef<str>],
+        max_distance: u8,
+    ) -> NeighborPairs {
+        let (query, reference) = (to_chars(query), to_chars(reference));
+        let mut hits = NeighborPairs::default();
+        for (i, a) in query.iter().enumerate() {
+            for (j, b) in reference.iter().enumerate() {
+                push_if_within(&mut hits, i, j, a, b, max_distance);
+            }
+        }
+        hits
+    }
 
-    NeighborPairs {
-        row: qi_filtered,
-        col: ri_filtered,
-        dists: dists_filtered,
+    /// The Levenshtein distance between `a` and `b`, counted in characters.
+    pub fn levenshtein(a: &str, b: &str) -> usize {
+        levenshtein_chars(&chars(a), &chars(b))
+    }
+
+    /// The Levenshtein distance between `a` and `b` under the given costs of turning `a` into
+    /// `b`, counted in characters.
+    pub fn weighted_levenshtein(a: &str, b: &str, costs: EditCosts) -> usize {
+        let (a, b) = (chars(a), chars(b));
+        let (ins, del, sub) = (
+            costs.insertion() as usize,
+            costs.deletion() as usize,
+            costs.substitution() as usize,
+        );
+        let mut prev = (0..=b.len()).map(|j| j * ins).collect::<Vec<_>>();
+        for (i, ca) in a.iter().enumerate() {
+            let mut cur = vec![(i + 1) * del; b.len() + 1];
+            for (j, cb) in b.iter().enumerate() {
+                let replace = prev[j] + if ca == cb { 0 } else { sub };
+                cur[j + 1] = replace.min(prev[j + 1] + del).min(cur[j] + ins);
+            }
+            prev = cur;
+        }
+        prev[b.len()]
+    }
+
+    /// The Hamming distance between `a` and `b`, counted in characters, or [`None`] if they are of
+    /// different lengths.
+    pub fn hamming(a: &str, b: &str) -> Option<usize> {
+        let (a, b) = (chars(a), chars(b));
+        (a.len() == b.len()).then(|| a.iter().zip(&b).filter(|(x, y)| x != y).count())
+    }
+
+    /// The optimal string alignment distance between `a` and `b`, counted in characters.
+    pub fn osa(a: &str, b: &str) -> usize {
+        let (a, b) = (chars(a), chars(b));
+        let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+        d[0] = (0..=b.len()).collect();
+        for (i, row) in d.iter_mut().enumerate() {
+            row[0] = i;
+        }
+        for i in 1..=a.len() {
+            for j in 1..=b.len() {
+                let cost = usize::from(a[i - 1] != b[j - 1]);
+                d[i][j] = (d[i - 1][j] + 1)
+                    .min(d[i][j - 1] + 1)
+                    .min(d[i - 1][j - 1] + cost);
+                if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                    d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+                }
+            }
+        }
+        d[a.len()][b.len()]
+    }
+
+    /// The indel distance between `a` and `b`, counted in characters, via their longest common
+    /// subsequence.
+    pub fn indel(a: &str, b: &str) -> usize {
+        let (a, b) = (chars(a), chars(b));
+        let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in 1..=a.len() {
+            for j in 1..=b.len() {
+                lcs[i][j] = if a[i - 1] == b[j - 1] {
+                    lcs[i - 1][j - 1] + 1
+                } else {
+                    lcs[i - 1][j].max(lcs[i][j - 1])
+                };
+            }
+        }
+        a.len() + b.len() - 2 * lcs[a.len()][b.len()]
+    }
+
+    fn chars(s: &str) -> Vec<char> {
+        s.chars().collect()
+    }
+
+    fn to_chars(strings: &[impl AsRef<str>]) -> Vec<Vec<char>> {
+        strings.iter().map(|s| chars(s.as_ref())).collect()
+    }
+
+    fn push_if_within(
+        hits: &mut NeighborPairs,
+        i: usize,
+        j: usize,
+        a: &[char],
+        b: &[char],
+        max_distance: u8,
+    ) {
+        let dist = levenshtein_chars(a, b);
+        if dist <= max_distance as usize {
+            hits.row.push(i as u32);
+            hits.col.push(j as u32);
+            hits.dists.push(dist as u8);
+        }
+    }
+
+    fn levenshtein_chars(a: &[char], b: &[char]) -> usize {
+        // The distances from each prefix of `b` to the prefix of `a` processed so far.
+        let mut row = (0..=b.len()).collect::<Vec<_>>();
+        for (i, ca) in a.iter().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = i + 1;
+            for (j, cb) in b.iter().enumerate() {
+                let substitution = diagonal + usize::from(ca != cb);
+                diagonal = row[j + 1];
+                row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+            }
+        }
+        row[b.len()]
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::{self, BufRead, Cursor};
+/// The sorts of (hash, index) pairs that the search uses internally, for benchmarking them against
+/// each other. Enabled by the `bench` feature, and not part of the public API.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod sorting {
+    use super::{
+        for_each_variant_hash, par_radix_sort, MaxDistance, SearchOptions, VariantScratch,
+    };
+    use super::{AsSymbols, Error};
+    use rayon::prelude::*;
+
+    /// The (hash, index) pairs of the deletion variants of `strings` up to `max_distance`, in the
+    /// order in which they are generated, i.e. by string but unsorted by hash.
+    pub fn get_variant_index_pairs(
+        strings: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+    ) -> Result<Vec<(u64, u32)>, Error> {
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        let options = SearchOptions::default();
+        let hash_builder = options.hash_builder();
+        let mut scratch = VariantScratch::default();
+        let mut pairs = Vec::new();
+        for (idx, s) in strings.iter().enumerate() {
+            for_each_variant_hash(
+                s.as_symbols(),
+                max_distance,
+                options.anchoring(),
+                &hash_builder,
+                &mut scratch,
+                |hash| pairs.push((hash, idx as u32)),
+            );
+        }
+        Ok(pairs)
+    }
+
+    /// Sort pairs as the search does.
+    pub fn radix_sort(pairs: &mut [(u64, u32)]) {
+        par_radix_sort(pairs);
+    }
+
+    /// Sort pairs by comparison, as the search did before adopting [`radix_sort`].
+    pub fn comparison_sort(pairs: &mut [(u64, u32)]) {
+        pairs.par_sort_unstable();
+    }
+}
+
+mod utils {
+    use super::Error;
+
+    #[derive(Clone, Copy, PartialEq, PartialOrd)]
+    pub struct MaxDistance(u8);
+
+    impl MaxDistance {
+        pub fn as_u8(&self) -> u8 {
+            self.0
+        }
+
+        pub fn as_usize(&self) -> usize {
+            self.0 as usize
+        }
+
+        /// This distance, lowered to `value` if that is smaller.
+        pub fn min(self, value: u8) -> Self {
+            Self(self.0.min(value))
+        }
+    }
+
+    impl TryFrom<u8> for MaxDistance {
+        type Error = Error;
+
+        fn try_from(value: u8) -> Result<Self, Self::Error> {
+            if value == u8::MAX {
+                Err(Error::MaxDistCapped)
+            } else {
+                Ok(Self(value))
+            }
+        }
+    }
+
+    /// A deletion variant hash of `32 * W` bits paired with the index of the string it derives
+    /// from.
+    ///
+    /// The hash is stored as u32 words, most significant first, so that pairs of 64-bit hashes
+    /// with u32-sized indices take up 12 bytes rather than the 16 of a padded (u64, u32) tuple,
+    /// those of 32-bit hashes 8 bytes, and those of 128-bit hashes 20 bytes. The derived ordering
+    /// is the same as that of the tuple, i.e. by hash and then by index.
+    #[repr(C)]
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub struct VariantIndexPair<I, const W: usize = 2> {
+        hash: [u32; W],
+        index: I,
+    }
+
+    impl<I: Copy> VariantIndexPair<I> {
+        pub fn new(hash: u64, index: I) -> Self {
+            Self::from_halves(hash, 0, index)
+        }
+
+        pub fn hash(&self) -> u64 {
+            self.hash_prefix()
+        }
+    }
+
+    impl<I: Copy, const W: usize> VariantIndexPair<I, W> {
+        /// The pair of a hash whose most significant 64 bits are `hi`, followed by `lo` if the
+        /// hash is 128 bits wide, or truncated to the most significant 32 bits of `hi` if the hash
+        /// is 32 bits wide.
+        pub fn from_halves(hi: u64, lo: u64, index: I) -> Self {
+            let words = [(hi >> 32) as u32, hi as u32, (lo >> 32) as u32, lo as u32];
+            Self {
+                hash: std::array::from_fn(|i| words[i]),
+                index,
+            }
+        }
+
+        /// The most significant 64 bits of the hash, padded with zeros if the hash is only 32
+        /// bits wide.
+        pub fn hash_prefix(&self) -> u64 {
+            let lo = if W > 1 { self.hash[1] } else { 0 };
+            (self.hash[0] as u64) << 32 | lo as u64
+        }
+
+        pub fn same_hash(&self, other: &Self) -> bool {
+            self.hash == other.hash
+        }
+
+        pub fn index(&self) -> I {
+            self.index
+        }
+
+        /// The pair of the same hash with its index mapped by `f`.
+        pub fn map_index<J>(self, f: impl FnOnce(I) -> J) -> VariantIndexPair<J, W> {
+            VariantIndexPair {
+                hash: self.hash,
+                index: f(self.index),
+            }
+        }
+    }
+
+    #[repr(transparent)]
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub struct CrossIndex(u32);
+
+    impl CrossIndex {
+        const TYPE_MASK: u32 = 1 << 31;
+        const VALUE_MASK: u32 = !Self::TYPE_MASK;
+        pub const MAX: usize = (1 << 31) - 1;
+
+        pub fn from(value: u32, is_ref: bool) -> Self {
+            debug_assert_ne!(value & Self::TYPE_MASK, Self::TYPE_MASK);
+
+            if is_ref {
+                Self(value | Self::TYPE_MASK)
+            } else {
+                Self(value)
+            }
+        }
+
+        pub fn is_ref(&self) -> bool {
+            self.0 & Self::TYPE_MASK == Self::TYPE_MASK
+        }
+
+        pub fn get_value(&self) -> u32 {
+            self.0 & Self::VALUE_MASK
+        }
+    }
+}
+
+#[derive(Default)]
+struct IdentityHasher(u64);
+
+impl Hasher for IdentityHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        unreachable!("hasher only designed for u64 and u32, got {bytes:?}");
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.0 = i
+    }
+
+    /// Restores the narrow hash that the key was truncated from (see [`VariantTable::NarrowMap`]),
+    /// so that its buckets are spread as evenly as those of the full hash.
+    fn write_u32(&mut self, i: u32) {
+        self.0 = VariantTable::widen_key(i)
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Default)]
+struct IdentityHasherBuilder;
+
+impl BuildHasher for IdentityHasherBuilder {
+    type Hasher = IdentityHasher;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        IdentityHasher::default()
+    }
+}
+
+const CACHE_MAGIC: &[u8; 8] = b"SYMSCAN\0";
+const CACHE_FORMAT_VERSION: u32 = 4;
+
+const SHARED_MAGIC: &[u8; 8] = b"SYMSHM\0\0";
+const SHARED_FORMAT_VERSION: u32 = 4;
+/// Written in native byte order to detect buffers written on a machine with a different byte
+/// order or pointer width.
+const SHARED_LAYOUT_MARKER: u64 = 0x0102_0304_0506_0708 ^ (size_of::<usize>() as u64);
+const SHARED_HEADER_WORDS: usize = 11;
+
+/// A view of an input string, as either text or raw bytes.
+#[derive(Clone, Copy)]
+enum Symbols<'a> {
+    Text(&'a str),
+    Bytes(&'a [u8]),
+}
+
+impl<'a> Symbols<'a> {
+    fn as_bytes(self) -> &'a [u8] {
+        match self {
+            Symbols::Text(s) => s.as_bytes(),
+            Symbols::Bytes(b) => b,
+        }
+    }
+
+    /// Whether the string can be processed byte by byte, i.e. is raw bytes or ASCII text.
+    fn is_bytewise(self) -> bool {
+        match self {
+            Symbols::Text(s) => s.is_ascii(),
+            Symbols::Bytes(_) => true,
+        }
+    }
+
+    /// The number of symbols in the string, which are bytes if it is bytewise and characters
+    /// otherwise.
+    fn len(self) -> usize {
+        match self {
+            Symbols::Text(s) => get_num_chars(s),
+            Symbols::Bytes(b) => b.len(),
+        }
+    }
+}
+
+/// Input strings that can be searched: text via [`AsRef<str>`], or raw bytes via [`ByteStr`].
+trait AsSymbols: Sync {
+    fn as_symbols(&self) -> Symbols<'_>;
+}
+
+impl<T: AsRef<str> + Sync> AsSymbols for T {
+    fn as_symbols(&self) -> Symbols<'_> {
+        Symbols::Text(self.as_ref())
+    }
+}
+
+impl AsSymbols for Symbols<'_> {
+    fn as_symbols(&self) -> Symbols<'_> {
+        *self
+    }
+}
+
+/// Wrapper marking a byte string as raw bytes, to be searched without regard to any encoding.
+#[repr(transparent)]
+struct ByteStr<T>(T);
+
+impl<T: AsRef<[u8]> + Sync> AsSymbols for ByteStr<T> {
+    fn as_symbols(&self) -> Symbols<'_> {
+        Symbols::Bytes(self.0.as_ref())
+    }
+}
+
+impl<T> ByteStr<T> {
+    fn wrap_slice(strings: &[T]) -> &[Self] {
+        // SAFETY: ByteStr<T> is a transparent wrapper around T.
+        unsafe { slice::from_raw_parts(strings.as_ptr().cast::<Self>(), strings.len()) }
+    }
+}
+
+/// An input string with its case folded (see [`SearchOptions::case_insensitive`]), which borrows
+/// the original string if folding leaves it unchanged.
+enum FoldedStr<'a> {
+    Text(Cow<'a, str>),
+    Bytes(Cow<'a, [u8]>),
+}
+
+impl AsSymbols for FoldedStr<'_> {
+    fn as_symbols(&self) -> Symbols<'_> {
+        match self {
+            FoldedStr::Text(s) => Symbols::Text(s),
+            FoldedStr::Bytes(b) => Symbols::Bytes(b),
+        }
+    }
+}
+
+/// Fold the case of `strings`, lowercasing text in full but raw bytes only in the ASCII range.
+fn fold_case(strings: &[impl AsSymbols]) -> Vec<FoldedStr<'_>> {
+    strings
+        .par_iter()
+        .with_min_len(100000)
+        .map(|s| match s.as_symbols() {
+            Symbols::Text(s) if s.is_ascii() => {
+                FoldedStr::Text(if s.bytes().any(|b| b.is_ascii_uppercase()) {
+                    Cow::Owned(s.to_ascii_lowercase())
+                } else {
+                    Cow::Borrowed(s)
+                })
+            }
+            Symbols::Text(s) => {
+                let folded = s.to_lowercase();
+                FoldedStr::Text(if folded == s {
+                    Cow::Borrowed(s)
+                } else {
+                    Cow::Owned(folded)
+                })
+            }
+            Symbols::Bytes(b) => FoldedStr::Bytes(if b.iter().any(u8::is_ascii_uppercase) {
+                Cow::Owned(b.to_ascii_lowercase())
+            } else {
+                Cow::Borrowed(b)
+            }),
+        })
+        .collect()
+}
+
+/// A range of a buffer, laid out as two words so that it can be shared as is (see
+/// [`CachedRef::write_shared`]).
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Span {
+    start: usize,
+    len: usize,
+}
+
+const _: () = assert!(size_of::<Span>() == 2 * size_of::<usize>());
+
+impl Span {
+    fn new(start: usize, len: usize) -> Self {
+        Span { start, len }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    fn as_range(&self) -> Range<usize> {
+        self.start..self.start + self.len
+    }
+}
+
+/// A [`Span`] stored as u32 words, so that an entry keyed by a u32 takes up 20 bytes rather than
+/// the 24 of a (u32, Span) tuple padded to the alignment of usize.
+#[derive(Clone, Copy)]
+struct PackedSpan([u32; 4]);
+
+impl From<Span> for PackedSpan {
+    fn from(span: Span) -> Self {
+        let (start, len) = (span.start as u64, span.len as u64);
+        PackedSpan([
+            (start >> 32) as u32,
+            start as u32,
+            (len >> 32) as u32,
+            len as u32,
+        ])
+    }
+}
+
+impl From<PackedSpan> for Span {
+    fn from(packed: PackedSpan) -> Self {
+        let [start_hi, start_lo, len_hi, len_lo] = packed.0.map(u64::from);
+        Span::new(
+            (start_hi << 32 | start_lo) as usize,
+            (len_hi << 32 | len_lo) as usize,
+        )
+    }
+}
+
+/// A contiguous buffer that is either owned, or borrowed from memory backing a [`CachedRef`]
+/// constructed via [`CachedRef::from_shared`].
+enum Store<T> {
+    Owned(Vec<T>),
+    Shared {
+        ptr: *const T,
+        len: usize,
+        _backing: Arc<dyn AsRef<[u8]> + Send + Sync>,
+    },
+}
+
+// SAFETY: a shared store is a read-only view into memory kept alive by its (Send + Sync) backing.
+unsafe impl<T: Send + Sync> Send for Store<T> {}
+unsafe impl<T: Sync> Sync for Store<T> {}
+
+impl<T> Deref for Store<T> {
+    type Target = [T];
+
+    #[inline(always)]
+    fn deref(&self) -> &[T] {
+        match self {
+            Store::Owned(v) => v,
+            Store::Shared { ptr, len, .. } => unsafe { slice::from_raw_parts(*ptr, *len) },
+        }
+    }
+}
+
+impl<T: Clone> Store<T> {
+    /// Get mutable access to the contents, first copying them out of shared memory if necessary.
+    fn to_mut(&mut self) -> &mut Vec<T> {
+        if let Store::Shared { .. } = self {
+            *self = Store::Owned(self.to_vec());
+        }
+        match self {
+            Store::Owned(v) => v,
+            Store::Shared { .. } => unreachable!(),
+        }
+    }
+
+    fn nbytes(&self) -> usize {
+        match self {
+            Store::Owned(v) => v.capacity() * size_of::<T>(),
+            Store::Shared { len, .. } => len * size_of::<T>(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct VariantEntry {
+    hash: u64,
+    span: Span,
+}
+
+/// Reusable buffers for the intermediate results of [`CachedRef::get_neighbors_across_with_scratch`],
+/// created via [`CachedRef::query_scratch`]. The buffers grow as needed and are never shrunk.
+#[derive(Default)]
+pub struct QueryScratch {
+    variant_index_pairs: Vec<VariantIndexPair<u32>>,
+    q_idx_store: Vec<u32>,
+    convergence_groups: Vec<(Range<usize>, Span)>,
+    candidates: Vec<(u32, u32)>,
+    dists: Vec<u8>,
+    /// The buffers of the [`NeighborPairs`] passed to [`CachedRef::get_neighbors_across_into`],
+    /// to be reused for the hits.
+    hits: NeighborPairs,
+}
+
+/// Maps deletion variant hashes to the spans of the index store holding their convergent indices.
+enum VariantTable {
+    Map(HashMap<u64, Span, IdentityHasherBuilder>),
+    /// The map of a [`CachedRef`] with [narrow hashes](CachedRef::narrow_hashes), which is keyed
+    /// by the 32 bits that the hashes were truncated to and so takes up 20 rather than 24 bytes
+    /// per entry.
+    NarrowMap(HashMap<u32, PackedSpan, IdentityHasherBuilder>),
+    /// Entries sorted by hash, as laid out by [`CachedRef::write_shared`].
+    Sorted(Store<VariantEntry>),
+}
+
+impl VariantTable {
+    /// An empty map with room for `capacity` entries, keyed by u32 if `narrow_hashes`.
+    fn with_capacity(capacity: usize, narrow_hashes: bool) -> Self {
+        if narrow_hashes {
+            VariantTable::NarrowMap(HashMap::with_capacity_and_hasher(
+                capacity,
+                IdentityHasherBuilder,
+            ))
+        } else {
+            VariantTable::Map(HashMap::with_capacity_and_hasher(
+                capacity,
+                IdentityHasherBuilder,
+            ))
+        }
+    }
+
+    /// The key of a narrow hash, which repeats its 32 bits in both halves (see
+    /// [`CacheHashBuilder`]).
+    fn narrow_key(hash: u64) -> u32 {
+        (hash >> 32) as u32
+    }
+
+    fn widen_key(key: u32) -> u64 {
+        (key as u64) << 32 | key as u64
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            VariantTable::Map(map) => map.len(),
+            VariantTable::NarrowMap(map) => map.len(),
+            VariantTable::Sorted(entries) => entries.len(),
+        }
+    }
+
+    #[inline(always)]
+    fn get(&self, hash: &u64) -> Option<Span> {
+        match self {
+            VariantTable::Map(map) => map.get(hash).copied(),
+            VariantTable::NarrowMap(map) => map
+                .get(&Self::narrow_key(*hash))
+                .map(|&packed| packed.into()),
+            VariantTable::Sorted(entries) => entries
+                .binary_search_by_key(hash, |entry| entry.hash)
+                .ok()
+                .map(|i| entries[i].span),
+        }
+    }
+
+    fn contains_key(&self, hash: &u64) -> bool {
+        self.get(hash).is_some()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (u64, Span)> + '_ {
+        match self {
+            VariantTable::Map(map) => Either::Left(map.iter().map(|(&hash, &span)| (hash, span))),
+            VariantTable::NarrowMap(map) => Either::Right(Either::Left(
+                map.iter()
+                    .map(|(&key, &packed)| (Self::widen_key(key), packed.into())),
+            )),
+            VariantTable::Sorted(entries) => Either::Right(Either::Right(
+                entries.iter().map(|entry| (entry.hash, entry.span)),
+            )),
+        }
+    }
+
+    /// Insert an entry into a map.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table is [`VariantTable::Sorted`], which is read-only.
+    fn insert(&mut self, hash: u64, span: Span) {
+        match self {
+            VariantTable::Map(map) => {
+                map.insert(hash, span);
+            }
+            VariantTable::NarrowMap(map) => {
+                map.insert(Self::narrow_key(hash), span.into());
+            }
+            VariantTable::Sorted(_) => unreachable!("sorted variant tables are read-only"),
+        }
+    }
+
+    fn shrink_to_fit(&mut self) {
+        match self {
+            VariantTable::Map(map) => map.shrink_to_fit(),
+            VariantTable::NarrowMap(map) => map.shrink_to_fit(),
+            VariantTable::Sorted(_) => (),
+        }
+    }
+
+    fn nbytes(&self) -> usize {
+        match self {
+            VariantTable::Map(map) => map.allocation_size(),
+            VariantTable::NarrowMap(map) => map.allocation_size(),
+            VariantTable::Sorted(entries) => entries.nbytes(),
+        }
+    }
+}
+
+/// Byte offsets of the sections of a [`CachedRef`] laid out by [`CachedRef::write_shared`]. Each
+/// section starts on an 8-byte boundary.
+struct SharedLayout {
+    str_spans: Range<usize>,
+    str_store: Range<usize>,
+    index_store: Range<usize>,
+    variants: Range<usize>,
+}
+
+impl SharedLayout {
+    fn new(
+        num_strings: usize,
+        str_store_len: usize,
+        num_indices: usize,
+        num_variants: usize,
+    ) -> Option<Self> {
+        let mut cursor = SHARED_HEADER_WORDS * size_of::<u64>();
+        let mut section = |num_bytes: Option<usize>| -> Option<Range<usize>> {
+            let start = cursor.checked_next_multiple_of(8)?;
+            cursor = start.checked_add(num_bytes?)?;
+            Some(start..cursor)
+        };
+
+        Some(SharedLayout {
+            str_spans: section(num_strings.checked_mul(size_of::<Span>()))?,
+            str_store: section(Some(str_store_len))?,
+            index_store: section(num_indices.checked_mul(size_of::<u32>()))?,
+            variants: section(num_variants.checked_mul(size_of::<VariantEntry>()))?,
+        })
+    }
+
+    fn total_size(&self) -> usize {
+        self.variants.end
+    }
+}
+
+/// Collection of string pairs that lie within the specified Levenshtein edit distance threshold.
+///
+/// This is what is returned via the [`Ok`] variant from [`get_neighbors_within`],
+/// [`get_neighbors_across`], and related methods in [`CachedRef`]. [`row`](NeighborPairs::row) and
+/// [`col`](NeighborPairs::col) contain the indices of the neighbor string pairs, and
+/// [`dists`](NeighborPairs::dists) contains the Levenshtein distances between the corresponding
+/// pairs (or their distances under another [`SearchOptions::metric`], if one was selected).
+///
+/// # A note on double-counting pairs
+///
+/// When returning the results of [`get_neighbors_within`] / [`CachedRef::get_neighbors_within`],
+/// string pairs _**ARE NOT**_ double-counted. As seen in the
+/// [examples](get_neighbors_within#examples), each pair is represented once where the
+/// [`row`](NeighborPairs::row) index is always less than the [`col`](NeighborPairs::col) index. In
+/// other words, if you were to interpret the [`NeighborPairs`] in these situations as a sparse
+/// matrix, only the lower triangle will be filled.
+#[derive(Debug, Default, PartialEq)]
+pub struct NeighborPairs {
+    /// Indices of strings in the input `query` slice that have neighbors.
+    pub row: Vec<u32>,
+
+    /// Indices of neighbor strings. When computing neighbor pairs across separate `query` and
+    /// `reference` slices, then `query[row[i]]` and `reference[col[i]]` are neighbors. When
+    /// computing neighbor pairs within a single `query` slice, `query[row[i]]` and `query[col[i]]`
+    /// are neighbors.
+    pub col: Vec<u32>,
+
+    /// Edit distances between neighbor string pairs. When computing neighbor pairs across separate
+    /// `query` and `reference` slices, then `Levenshtein(query[row[i]], reference[col[i]]) ==
+    /// dists[i]`. When computing neighbor pairs within a single `query` slice,
+    /// `Levenshtein(query[row[i]], query[col[i]]) == dists[i]`.
+    pub dists: Vec<u8>,
+}
+
+impl NeighborPairs {
+    /// Collect sorted (row, column, distance) triplets.
+    fn from_triplets(triplets: Vec<(u32, u32, u8)>) -> Self {
+        let mut row = Vec::with_capacity(triplets.len());
+        let mut col = Vec::with_capacity(triplets.len());
+        let mut dists = Vec::with_capacity(triplets.len());
+        for (r, c, d) in triplets {
+            row.push(r);
+            col.push(c);
+            dists.push(d);
+        }
+        NeighborPairs { row, col, dists }
+    }
+
+    /// Sort the pairs as required by `order`, given that they are already sorted by (row, column)
+    /// if that is what it requires.
+    fn into_order(self, order: OutputOrder) -> Self {
+        match order {
+            OutputOrder::ByRowThenCol | OutputOrder::Unordered => self,
+            OutputOrder::ByDistance => {
+                let mut hits = itertools::izip!(self.dists, self.row, self.col).collect_vec();
+                hits.par_sort_unstable();
+
+                let mut pairs = NeighborPairs {
+                    row: Vec::with_capacity(hits.len()),
+                    col: Vec::with_capacity(hits.len()),
+                    dists: Vec::with_capacity(hits.len()),
+                };
+                for (dist, row, col) in hits {
+                    pairs.row.push(row);
+                    pairs.col.push(col);
+                    pairs.dists.push(dist);
+                }
+                pairs
+            }
+        }
+    }
+
+    /// Add the transpose of each pair of a search within `num_strings` strings, keeping the pairs
+    /// sorted by (row, column).
+    fn symmetrize(self, num_strings: usize) -> Self {
+        // Each row holds the transposed pairs (which have smaller column indices) followed by the
+        // original pairs, both of which arrive in order of column index.
+        let mut num_lower = vec![0; num_strings];
+        let mut num_upper = vec![0; num_strings];
+        for (&r, &c) in self.row.iter().zip(&self.col) {
+            num_upper[r as usize] += 1;
+            num_lower[c as usize] += 1;
+        }
+
+        let mut lower_cursors = Vec::with_capacity(num_strings);
+        let mut upper_cursors = Vec::with_capacity(num_strings);
+        let mut cursor = 0;
+        for (lower, upper) in num_lower.into_iter().zip(num_upper) {
+            lower_cursors.push(cursor);
+            upper_cursors.push(cursor + lower);
+            cursor += lower + upper;
+        }
+
+        let mut row = vec![0; 2 * self.len()];
+        let mut col = vec![0; 2 * self.len()];
+        let mut dists = vec![0; 2 * self.len()];
+        for ((&r, &c), &d) in self.row.iter().zip(&self.col).zip(&self.dists) {
+            let upper = &mut upper_cursors[r as usize];
+            (row[*upper], col[*upper], dists[*upper]) = (r, c, d);
+            *upper += 1;
+
+            let lower = &mut lower_cursors[c as usize];
+            (row[*lower], col[*lower], dists[*lower]) = (c, r, d);
+            *lower += 1;
+        }
+
+        NeighborPairs { row, col, dists }
+    }
+
+    /// The number of neighboring string pairs detected.
+    pub fn len(&self) -> usize {
+        self.row.len()
+    }
+
+    /// Whether no neighboring string pairs were detected.
+    pub fn is_empty(&self) -> bool {
+        self.row.is_empty()
+    }
+
+    /// Iterate over the pairs as (row string, column string, distance) triplets, resolving the
+    /// indices against the `query` and `reference` collections that were searched. For pairs
+    /// found within a single collection, pass it as both.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any index is out of range for its collection, which means that the pairs were
+    /// not found among the given collections.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::get_neighbors_across;
+    ///
+    /// let query = ["fizz", "fuzz", "buzz"];
+    /// let reference = ["fooo", "barr", "bazz", "buzz"];
+    /// let hits = get_neighbors_across(&query, &reference, 1).unwrap();
+    ///
+    /// assert_eq!(
+    ///     hits.resolve(&query, &reference).collect::<Vec<_>>(),
+    ///     vec![("fuzz", "buzz", 1), ("buzz", "bazz", 1), ("buzz", "buzz", 0)]
+    /// );
+    /// ```
+    pub fn resolve<'s, 'a: 's>(
+        &'s self,
+        query: &'a [impl AsRef<str>],
+        reference: &'a [impl AsRef<str>],
+    ) -> impl Iterator<Item = (&'a str, &'a str, u8)> + 's {
+        let check = |indices: &[u32], len: usize, input_type: InputType| {
+            if let Some(&idx) = indices.iter().max() {
+                assert!(
+                    (idx as usize) < len,
+                    "{input_type} index {idx} out of range for {len} strings"
+                );
+            }
+        };
+        check(&self.row, query.len(), InputType::Query);
+        check(&self.col, reference.len(), InputType::Reference);
+
+        izip!(&self.row, &self.col, &self.dists).map(|(&row, &col, &dist)| {
+            (
+                query[row as usize].as_ref(),
+                reference[col as usize].as_ref(),
+                dist,
+            )
+        })
+    }
+}
+
+/// Concatenates the pairs of several collections, such as the per-chunk results of
+/// [`CachedRef::get_neighbors_across_chunks`].
+impl FromIterator<NeighborPairs> for NeighborPairs {
+    fn from_iter<I: IntoIterator<Item = NeighborPairs>>(iter: I) -> Self {
+        let mut pairs = NeighborPairs::default();
+        for chunk in iter {
+            pairs.row.extend(chunk.row);
+            pairs.col.extend(chunk.col);
+            pairs.dists.extend(chunk.dists);
+        }
+        pairs
+    }
+}
+
+/// Collection of string pairs that lie above a threshold normalized similarity.
+///
+/// This is what is returned via the [`Ok`] variant from [`get_neighbors_within_ratio`] and
+/// [`CachedRef::get_neighbors_within_ratio`]. It is laid out like [`NeighborPairs`], but holds
+/// the normalized similarities `1 - dist / max(len_a, len_b)` of the pairs, where `dist` is their
+/// Levenshtein distance. Pairs of empty strings have a similarity of 1.
+#[derive(Debug, PartialEq)]
+pub struct SimilarPairs {
+    /// Indices of strings in the input `query` slice that have neighbors.
+    pub row: Vec<u32>,
+
+    /// Indices of neighbor strings, such that `query[row[i]]` and `query[col[i]]` are neighbors.
+    pub col: Vec<u32>,
+
+    /// Normalized similarities between neighbor string pairs.
+    pub similarities: Vec<f32>,
+}
+
+impl SimilarPairs {
+    /// Keep those of `hits` that are at least `min_ratio` similar, where `row_len` and `col_len`
+    /// return the lengths of the strings at each row and col index.
+    fn from_hits(
+        hits: NeighborPairs,
+        min_ratio: f32,
+        row_len: impl Fn(usize) -> usize,
+        col_len: impl Fn(usize) -> usize,
+    ) -> Self {
+        let mut pairs = Self {
+            row: Vec::new(),
+            col: Vec::new(),
+            similarities: Vec::new(),
+        };
+        for ((row, col), dist) in hits.row.into_iter().zip(hits.col).zip(hits.dists) {
+            let max_len = row_len(row as usize).max(col_len(col as usize));
+            let similarity = get_similarity(dist as usize, max_len);
+            if similarity < min_ratio {
+                continue;
+            }
+            pairs.row.push(row);
+            pairs.col.push(col);
+            pairs.similarities.push(similarity);
+        }
+        pairs
+    }
+
+    /// The number of similar string pairs detected.
+    pub fn len(&self) -> usize {
+        self.row.len()
+    }
+
+    /// Whether no similar string pairs were detected.
+    pub fn is_empty(&self) -> bool {
+        self.row.is_empty()
+    }
+}
+
+/// Collection of string pairs across and within several string collections that lie within a
+/// threshold edit distance.
+///
+/// This is what is returned via the [`Ok`] variant from [`get_neighbors_multi`]. It is laid out
+/// like [`NeighborPairs`], but identifies each string by the index of its collection in `sets`
+/// along with its index within that collection, such that `sets[row_set[i]][row[i]]` and
+/// `sets[col_set[i]][col[i]]` are neighbors. The pairs are sorted by (row_set, row, col_set, col)
+/// (unless another [`SearchOptions::order`] is requested), with each pair listed once, such that
+/// `row_set[i] <= col_set[i]`.
+#[derive(Debug, Default, PartialEq)]
+pub struct MultiNeighborPairs {
+    /// Indices of the collections holding the strings at [`row`](MultiNeighborPairs::row).
+    pub row_set: Vec<u32>,
+
+    /// Indices of strings that have neighbors, within their collections.
+    pub row: Vec<u32>,
+
+    /// Indices of the collections holding the strings at [`col`](MultiNeighborPairs::col).
+    pub col_set: Vec<u32>,
+
+    /// Indices of neighbor strings, within their collections.
+    pub col: Vec<u32>,
+
+    /// Edit distances between neighbor string pairs.
+    pub dists: Vec<u8>,
+}
+
+impl MultiNeighborPairs {
+    /// Split the indices of `hits`, which index into the concatenation of the collections
+    /// starting at `set_starts`, into collection and string indices.
+    fn from_hits(hits: NeighborPairs, set_starts: &[u32]) -> Self {
+        let split = |indices: Vec<u32>| -> (Vec<u32>, Vec<u32>) {
+            indices
+                .into_par_iter()
+                .map(|idx| {
+                    let set = set_starts.partition_point(|&start| start <= idx) - 1;
+                    (set as u32, idx - set_starts[set])
+                })
+                .unzip()
+        };
+        let (row_set, row) = split(hits.row);
+        let (col_set, col) = split(hits.col);
+        Self {
+            row_set,
+            row,
+            col_set,
+            col,
+            dists: hits.dists,
+        }
+    }
+
+    /// The number of neighbor string pairs detected.
+    pub fn len(&self) -> usize {
+        self.row.len()
+    }
+
+    /// Whether no neighbor string pairs were detected.
+    pub fn is_empty(&self) -> bool {
+        self.row.is_empty()
+    }
+}
+
+/// A single edit that, as part of a sequence of edits, turns a source string into a destination
+/// string.
+///
+/// Positions count symbols (bytes of ASCII strings, and characters of non-ASCII strings) from 0,
+/// and refer to the unedited source and destination strings: `src_pos` is the position in the
+/// source at which the edit applies, and `dest_pos` the corresponding position in the
+/// destination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditOp {
+    /// Replace the symbol at `src_pos` by the symbol at `dest_pos`.
+    Substitute { src_pos: u32, dest_pos: u32 },
+    /// Insert the symbol at `dest_pos` before the symbol at `src_pos`.
+    Insert { src_pos: u32, dest_pos: u32 },
+    /// Delete the symbol at `src_pos`.
+    Delete { src_pos: u32, dest_pos: u32 },
+    /// Swap the adjacent symbols at `src_pos` and `src_pos + 1`, which become those at `dest_pos`
+    /// and `dest_pos + 1`. Only produced under [`Metric::Osa`].
+    Transpose { src_pos: u32, dest_pos: u32 },
+}
+
+/// Collection of string pairs that lie within a threshold edit distance, along with the edits
+/// between them.
+///
+/// This is what is returned via the [`Ok`] variant from [`get_neighbors_within_aligned`] and
+/// [`get_neighbors_across_aligned`]. It is laid out like [`NeighborPairs`], with the additional
+/// [`edit_ops`](AlignedNeighborPairs::edit_ops) holding, for each pair, a cheapest sequence of
+/// edits under [`SearchOptions::metric`] that turns the row string into the column string.
+#[derive(Debug, Default, PartialEq)]
+pub struct AlignedNeighborPairs {
+    /// Indices of strings in the input `query` slice that have neighbors.
+    pub row: Vec<u32>,
+
+    /// Indices of neighbor strings.
+    pub col: Vec<u32>,
+
+    /// Edit distances between neighbor string pairs.
+    pub dists: Vec<u8>,
+
+    /// Edits that turn the string at [`row`](AlignedNeighborPairs::row) into the string at
+    /// [`col`](AlignedNeighborPairs::col), ordered by position. Where several sequences of edits
+    /// are equally cheap, one of them is chosen deterministically.
+    pub edit_ops: Vec<Vec<EditOp>>,
+}
+
+impl AlignedNeighborPairs {
+    /// Align each pair of `hits`, where `row_str` and `col_str` return the strings at each row and
+    /// col index.
+    fn from_hits<'a>(
+        hits: NeighborPairs,
+        row_str: impl Fn(usize) -> &'a str + Sync,
+        col_str: impl Fn(usize) -> &'a str + Sync,
+        options: &SearchOptions,
+    ) -> Self {
+        let edit_ops = hits
+            .row
+            .par_iter()
+            .zip(&hits.col)
+            .map(|(&row, &col)| {
+                let (a, b) = (row_str(row as usize), col_str(col as usize));
+                if options.case_insensitive {
+                    options
+                        .metric
+                        .edit_ops(&a.to_lowercase(), &b.to_lowercase())
+                } else {
+                    options.metric.edit_ops(a, b)
+                }
+            })
+            .collect();
+        Self {
+            row: hits.row,
+            col: hits.col,
+            dists: hits.dists,
+            edit_ops,
+        }
+    }
+
+    /// The number of neighbor string pairs detected.
+    pub fn len(&self) -> usize {
+        self.row.len()
+    }
+
+    /// Whether no neighbor string pairs were detected.
+    pub fn is_empty(&self) -> bool {
+        self.row.is_empty()
+    }
+}
+
+/// The neighbors of each query string, laid out in compressed sparse row (CSR) format.
+///
+/// This is what is returned via the [`Ok`] variant from [`get_neighbors_across_csr`]. The
+/// neighbors of query string `i` are `col[indptr[i]..indptr[i + 1]]`, at the distances
+/// `dists[indptr[i]..indptr[i + 1]]`, sorted by distance and then by reference index. Every query
+/// string has a (possibly empty) row, such that `indptr` holds `query.len() + 1` offsets. The
+/// three vectors can be passed to `scipy.sparse.csr_matrix((dists, col, indptr))` as they are.
+#[derive(Debug, Default, PartialEq)]
+pub struct GroupedNeighbors {
+    /// Offsets into [`col`](GroupedNeighbors::col) and [`dists`](GroupedNeighbors::dists) at which
+    /// the neighbors of each query string start, followed by the total number of neighbors.
+    pub indptr: Vec<u64>,
+
+    /// Indices of neighbor strings in the input `reference` slice.
+    pub col: Vec<u32>,
+
+    /// Edit distances between each query string and its neighbors.
+    pub dists: Vec<u8>,
+}
+
+impl GroupedNeighbors {
+    /// Group `hits` sorted by (row, column) into the rows of `num_rows` strings, sorting each row
+    /// by distance.
+    fn from_hits(hits: NeighborPairs, num_rows: usize) -> Self {
+        let mut indptr = vec![0; num_rows + 1];
+        for &row in &hits.row {
+            indptr[row as usize + 1] += 1;
+        }
+        for i in 0..num_rows {
+            indptr[i + 1] += indptr[i];
+        }
+
+        let mut neighbors = hits.dists.into_iter().zip(hits.col).collect_vec();
+        let mut rows = Vec::with_capacity(num_rows);
+        let mut remaining = &mut neighbors[..];
+        for bounds in indptr.windows(2) {
+            let (row, rest) = remaining.split_at_mut((bounds[1] - bounds[0]) as usize);
+            rows.push(row);
+            remaining = rest;
+        }
+        rows.into_par_iter()
+            .with_min_len(1024)
+            .for_each(|row| row.sort_unstable());
+
+        let (dists, col) = neighbors.into_iter().unzip();
+        Self { indptr, col, dists }
+    }
+
+    /// The number of query strings, i.e. of rows.
+    pub fn num_rows(&self) -> usize {
+        self.indptr.len().saturating_sub(1)
+    }
+
+    /// The number of neighbor string pairs detected.
+    pub fn len(&self) -> usize {
+        self.col.len()
+    }
+
+    /// Whether no neighbor string pairs were detected.
+    pub fn is_empty(&self) -> bool {
+        self.col.is_empty()
+    }
+
+    /// The reference indices of the neighbors of query string `i`, and their distances.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not the index of a row.
+    pub fn row(&self, i: usize) -> (&[u32], &[u8]) {
+        let bounds = self.indptr[i] as usize..self.indptr[i + 1] as usize;
+        (&self.col[bounds.clone()], &self.dists[bounds])
+    }
+}
+
+/// Collection of string pairs that lie within a threshold edit distance, indexed with [`u64`]s.
+///
+/// This is what is returned via the [`Ok`] variant from [`get_neighbors_within_large`] and
+/// [`get_neighbors_across_large`]. It is laid out like [`NeighborPairs`], but its indices can
+/// address inputs too large to be indexed with [`u32`]s.
+#[derive(Debug, Default, PartialEq)]
+pub struct LargeNeighborPairs {
+    /// Indices of strings in the input `query` slice that have neighbors.
+    pub row: Vec<u64>,
+
+    /// Indices of neighbor strings.
+    pub col: Vec<u64>,
+
+    /// Edit distances between neighbor string pairs.
+    pub dists: Vec<u8>,
+}
+
+impl LargeNeighborPairs {
+    /// Collect (row, column, distance) triplets.
+    fn from_triplets(triplets: Vec<(u64, u64, u8)>) -> Self {
+        let mut pairs = Self {
+            row: Vec::with_capacity(triplets.len()),
+            col: Vec::with_capacity(triplets.len()),
+            dists: Vec::with_capacity(triplets.len()),
+        };
+        for (row, col, dist) in triplets {
+            pairs.row.push(row);
+            pairs.col.push(col);
+            pairs.dists.push(dist);
+        }
+        pairs
+    }
+
+    /// The number of neighbor string pairs detected.
+    pub fn len(&self) -> usize {
+        self.row.len()
+    }
+
+    /// Whether no neighbor string pairs were detected.
+    pub fn is_empty(&self) -> bool {
+        self.row.is_empty()
+    }
+}
+
+impl From<NeighborPairs> for LargeNeighborPairs {
+    fn from(pairs: NeighborPairs) -> Self {
+        Self {
+            row: pairs.row.into_iter().map(u64::from).collect(),
+            col: pairs.col.into_iter().map(u64::from).collect(),
+            dists: pairs.dists,
+        }
+    }
+}
+
+/// Collection of string pairs that lie within a threshold edit distance, with [`u16`] distances.
+///
+/// This is what is returned via the [`Ok`] variant from [`get_neighbors_within_wide`] and
+/// [`get_neighbors_across_wide`]. It is laid out like [`NeighborPairs`], but its distances can
+/// exceed the cap on `max_distance` (see [`Error::MaxDistCapped`]).
+#[derive(Debug, Default, PartialEq)]
+pub struct WideNeighborPairs {
+    /// Indices of strings in the input `query` slice that have neighbors.
+    pub row: Vec<u32>,
+
+    /// Indices of neighbor strings.
+    pub col: Vec<u32>,
+
+    /// Edit distances between neighbor string pairs.
+    pub dists: Vec<u16>,
+}
+
+impl WideNeighborPairs {
+    /// The number of neighbor string pairs detected.
+    pub fn len(&self) -> usize {
+        self.row.len()
+    }
+
+    /// Whether no neighbor string pairs were detected.
+    pub fn is_empty(&self) -> bool {
+        self.row.is_empty()
+    }
+}
+
+impl From<NeighborPairs> for WideNeighborPairs {
+    fn from(pairs: NeighborPairs) -> Self {
+        Self {
+            row: pairs.row,
+            col: pairs.col,
+            dists: pairs.dists.into_iter().map(u16::from).collect(),
+        }
+    }
+}
+
+/// A struct for memoizing the deletion variant calculations for a string collection.
+///
+/// When [constructed](CachedRef::new), [`CachedRef`] precomputes and stores the deletion variants
+/// for the supplied `reference` strings as a hashmap. This significantly speeds up subsequent
+/// queries against the reference, at the cost of spending extra time to construct the hashmap.
+/// This is useful for use-cases where you want to repeatedly query the same reference, especially
+/// if the reference is very large. However, for one-off computations, the pure functions
+/// [`get_neighbors_within`] and [`get_neighbors_across`] are faster.
+///
+/// **Note** that [`CachedRef`] instances constructed with `max_distance` set to X can only support
+/// queries with `max_distance` less than or equal to X.
+///
+/// **Note** when interpreting the index order of returned [`NeighborPairs`], the string collection
+/// specified at construction is considered the _reference_, and any string collections specified
+/// during subsequent query calls are considered the _query_.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{CachedRef, NeighborPairs};
+///
+/// let reference = ["fooo", "barr", "bazz", "buzz"];
+/// let cached = CachedRef::new(&reference, 2).unwrap();
+///
+/// let NeighborPairs { row, col, dists } = cached
+///     .get_neighbors_across(&["fizz", "fuzz", "buzz"], 1)
+///     .unwrap();
+///
+/// assert_eq!(row,   vec![1, 2, 2]);
+/// assert_eq!(col,   vec![3, 2, 3]);
+/// assert_eq!(dists, vec![1, 1, 0]);
+///
+/// let NeighborPairs { row, col, dists } = cached
+///     .get_neighbors_across(&["fizz", "fuzz", "buzz"], 2)
+///     .unwrap();
+///
+/// assert_eq!(row,   vec![0, 0, 1, 1, 2, 2]);
+/// assert_eq!(col,   vec![2, 3, 2, 3, 2, 3]);
+/// assert_eq!(dists, vec![2, 2, 2, 1, 1, 0]);
+/// ```
+pub struct CachedRef {
+    str_store: Store<u8>,
+    str_spans: Store<Span>,
+    index_store: Store<u32>,
+    variant_map: VariantTable,
+    max_distance: MaxDistance,
+    case_insensitive: bool,
+    hash_seed: u64,
+    narrow_hashes: bool,
+}
+
+impl CachedRef {
+    /// Construct a new [`CachedRef`] instance.
+    pub fn new(reference: &[impl AsRef<str> + Sync], max_distance: u8) -> Result<Self, Error> {
+        Self::new_with_options(reference, max_distance, &SearchOptions::default())
+    }
+
+    /// Equivalent to [`CachedRef::new`], with additional [`SearchOptions`].
+    pub fn new_with_options(
+        reference: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+        options: &SearchOptions,
+    ) -> Result<Self, Error> {
+        if reference.len() > u32::MAX as usize {
+            return Err(Error::TooManyStrings {
+                input_type: InputType::Reference,
+                got: reference.len(),
+                limit: u32::MAX as usize,
+            });
+        }
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        options.check_strings(reference, InputType::Reference)?;
+
+        if options.case_insensitive {
+            Self::from_strings(&fold_case(reference), max_distance, options)
+        } else {
+            Self::from_strings(reference, max_distance, options)
+        }
+    }
+
+    /// Equivalent to [`CachedRef::new_with_options`], additionally returning the [`SearchStats`]
+    /// of the construction, which generates and groups the deletion variants of `reference`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{CachedRef, Phase, SearchOptions};
+    ///
+    /// let (cached, stats) =
+    ///     CachedRef::new_with_stats(&["fizz", "fuzz", "buzz"], 1, &SearchOptions::default())
+    ///         .unwrap();
+    ///
+    /// assert_eq!(stats.num_convergence_groups, 2);
+    /// assert_eq!(stats.largest_convergence_group, 2);
+    /// assert!(stats.phase_time(Phase::DistanceComputation).is_zero());
+    /// ```
+    pub fn new_with_stats(
+        reference: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+        options: &SearchOptions,
+    ) -> Result<(Self, SearchStats), Error> {
+        let (options, recorder) = options.recording_stats();
+        let cached = Self::new_with_options(reference, max_distance, &options)?;
+        Ok((cached, recorder.finish(0)))
+    }
+
+    /// Construct a [`CachedRef`] holding the validated `reference` strings, with their case
+    /// already folded if required.
+    fn from_strings(
+        reference: &[impl AsSymbols],
+        max_distance: MaxDistance,
+        options: &SearchOptions,
+    ) -> Result<Self, Error> {
+        if let Some(pool) = options.outside_thread_pool() {
+            return pool.install(|| Self::from_strings(reference, max_distance, options));
+        }
+
+        let (str_store, str_spans) = {
+            let strlens = reference
+                .iter()
+                .map(|s| s.as_symbols().as_bytes().len())
+                .collect_vec();
+
+            let mut str_store_uninit = prealloc_maybeuninit_vec(strlens.iter().sum());
+            let str_spans = get_disjoint_spans(&strlens);
+            let str_store_chunks = get_disjoint_chunks_mut(&strlens, &mut str_store_uninit[..]);
+
+            reference
+                .par_iter()
+                .zip(str_store_chunks.into_par_iter())
+                .with_min_len(100000)
+                .for_each(|(s, chunk)| {
+                    let s = s.as_symbols().as_bytes();
+                    debug_assert_eq!(s.len(), chunk.len());
+                    unsafe {
+                        ptr::copy_nonoverlapping(s.as_ptr(), chunk.as_mut_ptr() as *mut u8, s.len())
+                    };
+                });
+
+            let str_store = unsafe { cast_to_initialised_vec(str_store_uninit) };
+
+            (str_store, str_spans)
+        };
+
+        Self::from_store(reference, str_store, str_spans, max_distance, options)
+    }
+
+    /// Construct a new [`CachedRef`] instance from strings laid out contiguously in `buffer`, where
+    /// string `i` spans the bytes `offsets[i]..offsets[i + 1]`.
+    ///
+    /// This is equivalent to [`CachedRef::new`], but copies the strings into the cache in one go
+    /// rather than one by one, and validates the whole buffer as ASCII at once.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors of [`CachedRef::new`], returns [`Error::InvalidOffsets`] if
+    /// `offsets` is not non-decreasing or points past the end of `buffer`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{CachedRef, NeighborPairs};
+    ///
+    /// let cached = CachedRef::from_offsets(b"fooobarrbazzbuzz", &[0, 4, 8, 12, 16], 1).unwrap();
+    /// assert_eq!(cached.get(3), Some("buzz"));
+    ///
+    /// let NeighborPairs { row, col, dists } = cached
+    ///     .get_neighbors_across(&["fizz", "fuzz", "buzz"], 1)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(row,   vec![1, 2, 2]);
+    /// assert_eq!(col,   vec![3, 2, 3]);
+    /// assert_eq!(dists, vec![1, 1, 0]);
+    /// ```
+    pub fn from_offsets(
+        buffer: &[u8],
+        offsets: &[impl Offset],
+        max_distance: u8,
+    ) -> Result<Self, Error> {
+        Self::from_offsets_with_options(buffer, offsets, max_distance, &SearchOptions::default())
+    }
+
+    /// Equivalent to [`CachedRef::from_offsets`], with additional [`SearchOptions`].
+    pub fn from_offsets_with_options(
+        buffer: &[u8],
+        offsets: &[impl Offset],
+        max_distance: u8,
+        options: &SearchOptions,
+    ) -> Result<Self, Error> {
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        let folded_buffer;
+        let buffer = if options.case_insensitive {
+            folded_buffer = buffer.to_ascii_lowercase();
+            &folded_buffer[..]
+        } else {
+            buffer
+        };
+        let reference = get_str_views_from_offsets(buffer, offsets, InputType::Reference)?;
+
+        let offsets = offsets.iter().map(|&offset| offset.to_validated());
+        let (str_store, str_spans) = match (offsets.clone().next(), offsets.clone().last()) {
+            (Some(first), Some(last)) => (
+                buffer[first..last].to_vec(),
+                offsets
+                    .tuple_windows()
+                    .map(|(start, end)| Span::new(start - first, end - start))
+                    .collect_vec(),
+            ),
+            _ => (Vec::new(), Vec::new()),
+        };
+
+        Self::from_store(&reference, str_store, str_spans, max_distance, options)
+    }
+
+    /// Finish constructing a [`CachedRef`] holding the validated `reference` strings, which have
+    /// already been copied into `str_store` at `str_spans`.
+    fn from_store(
+        reference: &[impl AsSymbols],
+        str_store: Vec<u8>,
+        str_spans: Vec<Span>,
+        max_distance: MaxDistance,
+        options: &SearchOptions,
+    ) -> Result<Self, Error> {
+        if let Some(pool) = options.outside_thread_pool() {
+            return pool.install(|| {
+                Self::from_store(reference, str_store, str_spans, max_distance, options)
+            });
+        }
+        search_span!(
+            "cached_ref_new",
+            num_reference = reference.len(),
+            max_distance = max_distance.as_u8()
+        );
+        check_del_var_counts(
+            reference,
+            max_distance,
+            Anchoring::default(),
+            InputType::Reference,
+        )?;
+
+        let hash_builder = CacheHashBuilder::new(options.hash_seed, options.narrow_hashes);
+
+        let (index_store, convergence_groups) = {
+            let duplicates = options
+                .deduplicate
+                .then(|| Duplicates::find(reference))
+                .filter(|duplicates| duplicates.num_unique() < reference.len());
+            let variant_index_pairs = match duplicates {
+                Some(duplicates) => {
+                    duplicates.expand_variant_index_pairs(get_sorted_vi_pairs_rawidx(
+                        &duplicates.get_unique_strings(reference),
+                        0,
+                        max_distance,
+                        &hash_builder,
+                        options.progress_reporter(),
+                    ))
+                }
+                None => get_sorted_vi_pairs_rawidx(
+                    reference,
+                    0,
+                    max_distance,
+                    &hash_builder,
+                    options.progress_reporter(),
+                ),
+            };
+            options.check_cancelled()?;
+            options
+                .progress_reporter()
+                .start_grouping(variant_index_pairs.len());
+
+            let mut total_num_convergent_indices = 0;
+            let mut num_convergence_groups = 0;
+
+            variant_index_pairs
+                .chunk_by(|a, b| a.hash() == b.hash())
+                .for_each(|chunk| {
+                    total_num_convergent_indices += chunk.len();
+                    num_convergence_groups += 1;
+                });
+            options.progress_reporter().record_groups(
+                variant_index_pairs
+                    .chunk_by(|a, b| a.hash() == b.hash())
+                    .map(<[_]>::len),
+            );
+
+            let mut convergent_indices = Vec::with_capacity(total_num_convergent_indices);
+            let mut convergence_groups = Vec::with_capacity(num_convergence_groups);
+            let mut cursor = 0;
+
+            variant_index_pairs
+                .chunk_by(|a, b| a.hash() == b.hash())
+                .for_each(|chunk| {
+                    convergent_indices.extend(chunk.iter().map(VariantIndexPair::index));
+                    convergence_groups.push((chunk[0].hash(), Span::new(cursor, chunk.len())));
+                    cursor += chunk.len();
+                });
+
+            debug_assert_eq!(cursor, convergent_indices.len());
+
+            (convergent_indices, convergence_groups)
+        };
+
+        let mut variant_map =
+            VariantTable::with_capacity(convergence_groups.len(), options.narrow_hashes);
+
+        for (v_hash, index_range) in convergence_groups {
+            variant_map.insert(v_hash, index_range);
+        }
+
+        Ok(CachedRef {
+            str_store: Store::Owned(str_store),
+            str_spans: Store::Owned(str_spans),
+            index_store: Store::Owned(index_store),
+            variant_map,
+            max_distance,
+            case_insensitive: options.case_insensitive,
+            hash_seed: options.hash_seed,
+            narrow_hashes: options.narrow_hashes,
+        })
+    }
+
+    /// Append more strings to the cached reference.
+    ///
+    /// The new strings are assigned contiguous indices following those of the existing reference
+    /// strings (i.e. the first new string has index [`len`](CachedRef::len) as of before the
+    /// call), and subsequent queries against the instance take them into account. Only the
+    /// deletion variants of the new strings are computed; those of the existing reference strings
+    /// are reused.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NonAsciiInput`] if `new` contains any non-ASCII data, and
+    /// [`Error::TooManyStrings`] if the extended reference would hold more than [`u32::MAX`]
+    /// strings. The instance is left unmodified in either case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{CachedRef, NeighborPairs};
+    ///
+    /// let mut cached = CachedRef::new(&["fooo", "barr"], 1).unwrap();
+    /// cached.extend(&["bazz", "buzz"]).unwrap();
+    ///
+    /// let NeighborPairs { row, col, dists } = cached
+    ///     .get_neighbors_across(&["fizz", "fuzz", "buzz"], 1)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(row,   vec![1, 2, 2]);
+    /// assert_eq!(col,   vec![3, 2, 3]);
+    /// assert_eq!(dists, vec![1, 1, 0]);
+    /// ```
+    pub fn extend(&mut self, new: &[impl AsRef<str> + Sync]) -> Result<(), Error> {
+        let total_len = self.len() + new.len();
+        if total_len > u32::MAX as usize {
+            return Err(Error::TooManyStrings {
+                input_type: InputType::Reference,
+                got: total_len,
+                limit: u32::MAX as usize,
+            });
+        }
+        check_strings_ascii(new, InputType::Reference)?;
+
+        if self.case_insensitive {
+            self.extend_validated(&fold_case(new))
+        } else {
+            self.extend_validated(new)
+        }
+    }
+
+    /// Implementation of [`CachedRef::extend`] for validated strings, with their case already
+    /// folded if required.
+    fn extend_validated(&mut self, new: &[impl AsSymbols]) -> Result<(), Error> {
+        check_del_var_counts(
+            new,
+            self.max_distance,
+            Anchoring::default(),
+            InputType::Reference,
+        )?;
+        let new_pairs = get_sorted_vi_pairs_rawidx(
+            new,
+            self.len() as u32,
+            self.max_distance,
+            &self.hash_builder(),
+            Progress::default(),
+        );
+
+        let str_spans = self.str_spans.to_mut();
+        let str_store = self.str_store.to_mut();
+        for s in new.iter() {
+            let s = s.as_symbols().as_bytes();
+            str_spans.push(Span::new(str_store.len(), s.len()));
+            str_store.extend_from_slice(s);
+        }
+
+        let mut index_store = Vec::with_capacity(self.index_store.len() + new_pairs.len());
+        let mut variant_map = VariantTable::with_capacity(
+            self.variant_map.len() + new_pairs.len(),
+            self.narrow_hashes,
+        );
+
+        // Since new indices are all greater than existing ones, appending them after the existing
+        // indices of each convergence group keeps the groups sorted.
+        for (v_hash, span) in self.variant_map.iter() {
+            let start = index_store.len();
+            index_store.extend_from_slice(self.get_convergent_indices_from_span(&span));
+
+            let new_start = new_pairs.partition_point(|pair| pair.hash() < v_hash);
+            let new_end = new_pairs.partition_point(|pair| pair.hash() <= v_hash);
+            index_store.extend(
+                new_pairs[new_start..new_end]
+                    .iter()
+                    .map(VariantIndexPair::index),
+            );
+
+            variant_map.insert(v_hash, Span::new(start, index_store.len() - start));
+        }
+
+        for chunk in new_pairs.chunk_by(|a, b| a.hash() == b.hash()) {
+            if self.variant_map.contains_key(&chunk[0].hash()) {
+                continue;
+            }
+            variant_map.insert(chunk[0].hash(), Span::new(index_store.len(), chunk.len()));
+            index_store.extend(chunk.iter().map(VariantIndexPair::index));
+        }
+
+        variant_map.shrink_to_fit();
+        self.index_store = Store::Owned(index_store);
+        self.variant_map = variant_map;
+
+        Ok(())
+    }
+
+    /// The number of reference strings held by the cache.
+    pub fn len(&self) -> usize {
+        self.str_spans.len()
+    }
+
+    /// Whether the cache holds no reference strings.
+    pub fn is_empty(&self) -> bool {
+        self.str_spans.is_empty()
+    }
+
+    /// The maximum edit distance that this instance can support in queries, as specified at
+    /// construction.
+    pub fn max_distance(&self) -> u8 {
+        self.max_distance.as_u8()
+    }
+
+    /// Whether the instance compares strings regardless of case, as specified at construction
+    /// via [`SearchOptions::case_insensitive`].
+    pub fn case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+
+    /// The seed with which the instance hashes deletion variants, as specified at construction
+    /// via [`SearchOptions::hash_seed`].
+    pub fn hash_seed(&self) -> u64 {
+        self.hash_seed
+    }
+
+    /// Whether the instance truncates the hashes of deletion variants to 32 bits, as specified at
+    /// construction via [`SearchOptions::narrow_hashes`].
+    pub fn narrow_hashes(&self) -> bool {
+        self.narrow_hashes
+    }
+
+    /// The width of the hashes of deletion variants in bits.
+    fn hash_width(&self) -> u32 {
+        get_cache_hash_width(self.narrow_hashes)
+    }
+
+    /// The hasher for deletion variants, seeded with [`CachedRef::hash_seed`] and truncating the
+    /// hashes as per [`CachedRef::narrow_hashes`].
+    fn hash_builder(&self) -> CacheHashBuilder {
+        CacheHashBuilder::new(self.hash_seed, self.narrow_hashes)
+    }
+
+    /// The reference string at index `idx`, or [`None`] if out of bounds. If the instance is
+    /// [case-insensitive](CachedRef::case_insensitive), the string is returned lowercased.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::CachedRef;
+    ///
+    /// let cached = CachedRef::new(&["fizz", "fuzz", "buzz"], 1).unwrap();
+    ///
+    /// assert_eq!(cached.len(), 3);
+    /// assert_eq!(cached.get(1), Some("fuzz"));
+    /// assert_eq!(cached.get(3), None);
+    /// ```
+    pub fn get(&self, idx: usize) -> Option<&str> {
+        if idx < self.len() {
+            Some(self.get_str_at_index(idx))
+        } else {
+            None
+        }
+    }
+
+    /// The approximate number of bytes of memory held by the cache, including any memory borrowed
+    /// via [`CachedRef::from_shared`].
+    pub fn nbytes(&self) -> usize {
+        self.str_store.nbytes()
+            + self.str_spans.nbytes()
+            + self.index_store.nbytes()
+            + self.variant_map.nbytes()
+    }
+
+    /// The memoized equivalent of [`get_neighbors_within`].
+    pub fn get_neighbors_within(&self, max_distance: u8) -> Result<NeighborPairs, Error> {
+        self.get_neighbors_within_with_options(max_distance, &SearchOptions::default())
+    }
+
+    /// Equivalent to [`CachedRef::get_neighbors_within`], with additional [`SearchOptions`].
+    pub fn get_neighbors_within_with_options(
+        &self,
+        max_distance: u8,
+        options: &SearchOptions,
+    ) -> Result<NeighborPairs, Error> {
+        if let Some(pool) = options.outside_thread_pool() {
+            return pool.install(|| self.get_neighbors_within_with_options(max_distance, options));
+        }
+        search_span!(
+            "cached_ref_within",
+            num_reference = self.len(),
+            max_distance
+        );
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        if max_distance > self.max_distance {
+            return Err(Error::MaxDistTooLargeForCache {
+                got: max_distance.as_u8(),
+                limit: self.max_distance.as_u8(),
+            });
+        }
+
+        let mut convergent_indices = Vec::with_capacity(self.variant_map.len());
+        self.variant_map.iter().for_each(|(_, span)| {
+            if span.len() == 1 {
+                return;
+            }
+            convergent_indices.push(self.get_convergent_indices_from_span(&span));
+        });
+
+        let dist = |qi: u32, ri: u32| {
+            options.bounded_dist(
+                qi,
+                ri,
+                Symbols::Text(self.get_str_at_index(qi as usize)),
+                Symbols::Text(self.get_str_at_index(ri as usize)),
+                max_distance,
+            )
+        };
+        let (candidates, known) = get_hit_candidates_within(
+            &convergent_indices,
+            PairKeys::default(),
+            Some(&dist),
+            options,
+        )?;
+        options.check_cancelled()?;
+        let dists =
+            self.compute_dists_fully_cached(&candidates, &known, self, max_distance, options);
+        options.check_cancelled()?;
+
+        let hits = collect_true_hits(
+            &candidates,
+            &dists,
+            max_distance,
+            options.effective_min_distance(),
+        );
+        if options.symmetric {
+            Ok(hits.symmetrize(self.len()).into_order(options.order))
+        } else {
+            Ok(hits.into_order(options.order))
+        }
+    }
+
+    /// The memoized equivalent of [`get_neighbors_within_ratio`].
+    ///
+    /// The cache must have been constructed with a `max_distance` at least as large as the
+    /// distance that `min_ratio` requires for the longest cached strings, or
+    /// [`Error::MaxDistTooLargeForCache`] is returned.
+    pub fn get_neighbors_within_ratio(&self, min_ratio: f32) -> Result<SimilarPairs, Error> {
+        let len = |i: usize| get_num_chars(self.get_str_at_index(i));
+        let max_len = (0..self.len()).map(len).max().unwrap_or(0);
+        let max_distance = get_max_distance_for_ratio(min_ratio, max_len)?;
+
+        let hits = self.get_neighbors_within(max_distance.as_u8())?;
+        Ok(SimilarPairs::from_hits(hits, min_ratio, len, len))
+    }
+
+    /// The memoized equivalent of [`get_neighbors_across`].
+    pub fn get_neighbors_across(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+    ) -> Result<NeighborPairs, Error> {
+        self.get_neighbors_across_with_options(query, max_distance, &SearchOptions::default())
+    }
+
+    /// Equivalent to [`CachedRef::get_neighbors_across`], with additional [`SearchOptions`].
+    pub fn get_neighbors_across_with_options(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+        options: &SearchOptions,
+    ) -> Result<NeighborPairs, Error> {
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        if max_distance > self.max_distance {
+            return Err(Error::MaxDistTooLargeForCache {
+                got: max_distance.as_u8(),
+                limit: self.max_distance.as_u8(),
+            });
+        }
+        if query.len() > u32::MAX as usize {
+            return Err(Error::TooManyStrings {
+                input_type: InputType::Query,
+                got: query.len(),
+                limit: u32::MAX as usize,
+            });
+        }
+        options.check_strings(query, InputType::Query)?;
+
+        self.search_across(
+            query,
+            max_distance,
+            options,
+            Collect::Pairs,
+            &mut QueryScratch::default(),
+        )
+        .map(|hits| hits.into_pairs().into_order(options.order))
+    }
+
+    /// The memoized equivalent of [`get_neighbors_across_top_k`].
+    pub fn get_neighbors_across_top_k(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+        k: usize,
+    ) -> Result<NeighborPairs, Error> {
+        self.get_neighbors_across_top_k_with_options(
+            query,
+            max_distance,
+            k,
+            &SearchOptions::default(),
+        )
+    }
+
+    /// Equivalent to [`CachedRef::get_neighbors_across_top_k`], with additional
+    /// [`SearchOptions`].
+    pub fn get_neighbors_across_top_k_with_options(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+        k: usize,
+        options: &SearchOptions,
+    ) -> Result<NeighborPairs, Error> {
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        if max_distance > self.max_distance {
+            return Err(Error::MaxDistTooLargeForCache {
+                got: max_distance.as_u8(),
+                limit: self.max_distance.as_u8(),
+            });
+        }
+        if query.len() > u32::MAX as usize {
+            return Err(Error::TooManyStrings {
+                input_type: InputType::Query,
+                got: query.len(),
+                limit: u32::MAX as usize,
+            });
+        }
+        options.check_strings(query, InputType::Query)?;
+
+        self.search_across(
+            query,
+            max_distance,
+            options,
+            Collect::TopK(k),
+            &mut QueryScratch::default(),
+        )
+        .map(Hits::into_pairs)
+    }
+
+    /// Create scratch space for [`CachedRef::get_neighbors_across_with_scratch`].
+    ///
+    /// The scratch space starts out empty, and grows to fit the largest query it is used for.
+    pub fn query_scratch(&self) -> QueryScratch {
+        QueryScratch::default()
+    }
+
+    /// Equivalent to [`CachedRef::get_neighbors_across`], but holding intermediate results in
+    /// `scratch` rather than allocating new buffers for them on each call. This avoids repeatedly
+    /// allocating and faulting in large buffers when issuing many queries of similar size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{CachedRef, NeighborPairs};
+    ///
+    /// let cached = CachedRef::new(&["fooo", "barr", "bazz", "buzz"], 1).unwrap();
+    /// let mut scratch = cached.query_scratch();
+    ///
+    /// for query in [["fizz", "fuzz"], ["buzz", "bizz"]] {
+    ///     assert_eq!(
+    ///         cached.get_neighbors_across_with_scratch(&query, 1, &mut scratch).unwrap(),
+    ///         cached.get_neighbors_across(&query, 1).unwrap(),
+    ///     );
+    /// }
+    /// ```
+    pub fn get_neighbors_across_with_scratch(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+        scratch: &mut QueryScratch,
+    ) -> Result<NeighborPairs, Error> {
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        if max_distance > self.max_distance {
+            return Err(Error::MaxDistTooLargeForCache {
+                got: max_distance.as_u8(),
+                limit: self.max_distance.as_u8(),
+            });
+        }
+        if query.len() > u32::MAX as usize {
+            return Err(Error::TooManyStrings {
+                input_type: InputType::Query,
+                got: query.len(),
+                limit: u32::MAX as usize,
+            });
+        }
+        check_strings_ascii(query, InputType::Query)?;
+
+        self.search_across(
+            query,
+            max_distance,
+            &SearchOptions::default(),
+            Collect::Pairs,
+            scratch,
+        )
+        .map(Hits::into_pairs)
+    }
+
+    /// Equivalent to [`CachedRef::get_neighbors_across_with_scratch`], but writing the results
+    /// into `out`, whose previous contents are cleared and whose buffers are reused rather than
+    /// allocating new ones. On error, `out` is left empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{CachedRef, NeighborPairs};
+    ///
+    /// let cached = CachedRef::new(&["fooo", "barr", "bazz", "buzz"], 1).unwrap();
+    /// let mut scratch = cached.query_scratch();
+    /// let mut pairs = NeighborPairs::default();
+    ///
+    /// for query in [["fizz", "fuzz"], ["buzz", "bizz"]] {
+    ///     cached
+    ///         .get_neighbors_across_into(&query, 1, &mut scratch, &mut pairs)
+    ///         .unwrap();
+    ///     assert_eq!(pairs, cached.get_neighbors_across(&query, 1).unwrap());
+    /// }
+    /// ```
+    pub fn get_neighbors_across_into(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+        scratch: &mut QueryScratch,
+        out: &mut NeighborPairs,
+    ) -> Result<(), Error> {
+        scratch.hits = std::mem::take(out);
+        *out = self.get_neighbors_across_with_scratch(query, max_distance, scratch)?;
+        Ok(())
+    }
+
+    /// Equivalent to [`CachedRef::get_neighbors_across`], for queries that are pulled from an
+    /// iterator `chunk_size` strings at a time, such that only one chunk of queries is held in
+    /// memory at once.
+    ///
+    /// The returned iterator lazily yields the hits of each chunk, with row indices counting from
+    /// the start of `queries` rather than from the start of the chunk. Each chunk is searched with
+    /// the same reusable buffers (see [`CachedRef::get_neighbors_across_with_scratch`]), and the
+    /// iterator ends after the first error. The hits of all chunks can be concatenated by
+    /// collecting them into a single [`NeighborPairs`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::{CachedRef, NeighborPairs};
+    ///
+    /// let cached = CachedRef::new(&["fooo", "barr", "bazz", "buzz"], 1).unwrap();
+    /// let queries = ["fizz", "fuzz", "buzz"].map(String::from);
+    ///
+    /// let chunks = cached
+    ///     .get_neighbors_across_chunks(queries.clone(), 2, 1)
+    ///     .collect::<Result<Vec<_>, _>>()
+    ///     .unwrap();
+    /// assert_eq!(chunks.len(), 2);
+    ///
+    /// let hits = cached
+    ///     .get_neighbors_across_chunks(queries.clone(), 2, 1)
+    ///     .collect::<Result<NeighborPairs, _>>()
+    ///     .unwrap();
+    /// assert_eq!(hits, cached.get_neighbors_across(&queries, 1).unwrap());
+    /// ```
+    pub fn get_neighbors_across_chunks<'a, I>(
+        &'a self,
+        queries: I,
+        chunk_size: usize,
+        max_distance: u8,
+    ) -> impl Iterator<Item = Result<NeighborPairs, Error>> + 'a
+    where
+        I: IntoIterator<Item = String>,
+        I::IntoIter: 'a,
+    {
+        self.get_neighbors_across_chunks_with_options(
+            queries,
+            chunk_size,
+            max_distance,
+            &SearchOptions::default(),
+        )
+    }
+
+    /// Equivalent to [`CachedRef::get_neighbors_across_chunks`], with additional
+    /// [`SearchOptions`], which apply to each chunk separately (e.g. [`SearchOptions::order`]
+    /// orders the hits within each chunk).
+    pub fn get_neighbors_across_chunks_with_options<'a, I>(
+        &'a self,
+        queries: I,
+        chunk_size: usize,
+        max_distance: u8,
+        options: &SearchOptions,
+    ) -> impl Iterator<Item = Result<NeighborPairs, Error>> + 'a
+    where
+        I: IntoIterator<Item = String>,
+        I::IntoIter: 'a,
+    {
+        assert!(chunk_size > 0, "chunk_size must be positive");
+        let options = options.clone();
+        let mut queries = queries.into_iter();
+        let mut scratch = QueryScratch::default();
+        let mut chunk = Vec::with_capacity(chunk_size);
+        let mut chunk_start = 0;
+        let mut failed = false;
+
+        iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+            chunk.clear();
+            chunk.extend(queries.by_ref().take(chunk_size));
+            if chunk.is_empty() {
+                return None;
+            }
+
+            let result =
+                self.search_across_chunk(&chunk, chunk_start, max_distance, &options, &mut scratch);
+            chunk_start += chunk.len();
+            failed = result.is_err();
+            Some(result)
+        })
+    }
+
+    /// Search one chunk of the queries of [`CachedRef::get_neighbors_across_chunks_with_options`],
+    /// whose first string is at `chunk_start` among all queries.
+    fn search_across_chunk(
+        &self,
+        chunk: &[String],
+        chunk_start: usize,
+        max_distance: u8,
+        options: &SearchOptions,
+        scratch: &mut QueryScratch,
+    ) -> Result<NeighborPairs, Error> {
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        if max_distance > self.max_distance {
+            return Err(Error::MaxDistTooLargeForCache {
+                got: max_distance.as_u8(),
+                limit: self.max_distance.as_u8(),
+            });
+        }
+        if chunk_start + chunk.len() > u32::MAX as usize {
+            return Err(Error::TooManyStrings {
+                input_type: InputType::Query,
+                got: chunk_start + chunk.len(),
+                limit: u32::MAX as usize,
+            });
+        }
+        options
+            .check_strings(chunk, InputType::Query)
+            .map_err(|err| match err {
+                Error::NonAsciiInput {
+                    input_type,
+                    offending_idx,
+                    offending_string,
+                } => Error::NonAsciiInput {
+                    input_type,
+                    offending_idx: chunk_start + offending_idx,
+                    offending_string,
+                },
+                err => err,
+            })?;
+
+        let mut hits = self
+            .search_across(chunk, max_distance, options, Collect::Pairs, scratch)?
+            .into_pairs()
+            .into_order(options.order);
+        for row in &mut hits.row {
+            *row += chunk_start as u32;
+        }
+        Ok(hits)
+    }
+
+    /// The memoized equivalent of [`get_neighbor_counts_across`].
+    pub fn get_neighbor_counts_across(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+    ) -> Result<Vec<u32>, Error> {
+        self.get_neighbor_counts_across_with_options(query, max_distance, &SearchOptions::default())
+    }
+
+    /// Equivalent to [`CachedRef::get_neighbor_counts_across`], with additional
+    /// [`SearchOptions`].
+    pub fn get_neighbor_counts_across_with_options(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+        options: &SearchOptions,
+    ) -> Result<Vec<u32>, Error> {
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        if max_distance > self.max_distance {
+            return Err(Error::MaxDistTooLargeForCache {
+                got: max_distance.as_u8(),
+                limit: self.max_distance.as_u8(),
+            });
+        }
+        if query.len() > u32::MAX as usize {
+            return Err(Error::TooManyStrings {
+                input_type: InputType::Query,
+                got: query.len(),
+                limit: u32::MAX as usize,
+            });
+        }
+        options.check_strings(query, InputType::Query)?;
+
+        self.search_across(
+            query,
+            max_distance,
+            options,
+            Collect::Counts { symmetric: false },
+            &mut QueryScratch::default(),
+        )
+        .map(Hits::into_counts)
+    }
+
+    /// The memoized equivalent of [`for_each_neighbor_across`].
+    pub fn for_each_neighbor_across(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+        f: impl Fn(u32, u32, u8) + Sync,
+    ) -> Result<(), Error> {
+        self.for_each_neighbor_across_with_options(
+            query,
+            max_distance,
+            f,
+            &SearchOptions::default(),
+        )
+    }
+
+    /// Equivalent to [`CachedRef::for_each_neighbor_across`], with additional [`SearchOptions`].
+    pub fn for_each_neighbor_across_with_options(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+        f: impl Fn(u32, u32, u8) + Sync,
+        options: &SearchOptions,
+    ) -> Result<(), Error> {
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        if max_distance > self.max_distance {
+            return Err(Error::MaxDistTooLargeForCache {
+                got: max_distance.as_u8(),
+                limit: self.max_distance.as_u8(),
+            });
+        }
+        if query.len() > u32::MAX as usize {
+            return Err(Error::TooManyStrings {
+                input_type: InputType::Query,
+                got: query.len(),
+                limit: u32::MAX as usize,
+            });
+        }
+        options.check_strings(query, InputType::Query)?;
+
+        self.search_across(
+            query,
+            max_distance,
+            options,
+            Collect::ForEach(&f),
+            &mut QueryScratch::default(),
+        )?;
+        Ok(())
+    }
+
+    /// The memoized equivalent of [`has_neighbor_across`].
+    pub fn has_neighbor_across(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+    ) -> Result<Vec<bool>, Error> {
+        self.has_neighbor_across_with_options(query, max_distance, &SearchOptions::default())
+    }
+
+    /// Equivalent to [`CachedRef::has_neighbor_across`], with additional [`SearchOptions`].
+    pub fn has_neighbor_across_with_options(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+        options: &SearchOptions,
+    ) -> Result<Vec<bool>, Error> {
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        if max_distance > self.max_distance {
+            return Err(Error::MaxDistTooLargeForCache {
+                got: max_distance.as_u8(),
+                limit: self.max_distance.as_u8(),
+            });
+        }
+        if query.len() > u32::MAX as usize {
+            return Err(Error::TooManyStrings {
+                input_type: InputType::Query,
+                got: query.len(),
+                limit: u32::MAX as usize,
+            });
+        }
+        options.check_strings(query, InputType::Query)?;
+
+        self.search_across(
+            query,
+            max_distance,
+            options,
+            Collect::Flags,
+            &mut QueryScratch::default(),
+        )
+        .map(Hits::into_flags)
+    }
+
+    /// Flag the query strings that have no neighbors within `max_distance` in the cached
+    /// reference, i.e. the negation of [`CachedRef::has_neighbor_across`].
+    ///
+    /// Rather than enumerating the candidates of the whole query at once, the candidates of each
+    /// query string are looked up and verified in turn, stopping at its first hit. This suits
+    /// screens in which most query strings turn out to be orphans, all of whose candidates must be
+    /// verified to prove the absence of neighbors, without ever buffering the candidates of the
+    /// whole query.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::CachedRef;
+    ///
+    /// let cached = CachedRef::new(&["fooo", "barr", "bazz", "buzz"], 2).unwrap();
+    ///
+    /// assert_eq!(
+    ///     cached.orphan_mask(&["fizz", "fuzz", "xxxx"], 1).unwrap(),
+    ///     vec![true, false, true]
+    /// );
+    /// ```
+    pub fn orphan_mask(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+    ) -> Result<Vec<bool>, Error> {
+        self.orphan_mask_with_options(query, max_distance, &SearchOptions::default())
+    }
+
+    /// Equivalent to [`CachedRef::orphan_mask`], with additional [`SearchOptions`].
+    pub fn orphan_mask_with_options(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+        options: &SearchOptions,
+    ) -> Result<Vec<bool>, Error> {
+        if let Some(pool) = options.outside_thread_pool() {
+            return pool.install(|| self.orphan_mask_with_options(query, max_distance, options));
+        }
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        if max_distance > self.max_distance {
+            return Err(Error::MaxDistTooLargeForCache {
+                got: max_distance.as_u8(),
+                limit: self.max_distance.as_u8(),
+            });
+        }
+        if query.len() > u32::MAX as usize {
+            return Err(Error::TooManyStrings {
+                input_type: InputType::Query,
+                got: query.len(),
+                limit: u32::MAX as usize,
+            });
+        }
+        options.check_strings(query, InputType::Query)?;
+        search_span!(
+            "cached_ref_orphan_mask",
+            num_query = query.len(),
+            num_reference = self.len(),
+            max_distance = max_distance.as_u8()
+        );
+
+        if self.case_insensitive {
+            self.get_orphan_mask(&fold_case(query), max_distance, options)
+        } else {
+            self.get_orphan_mask(query, max_distance, options)
+        }
+    }
+
+    /// Implementation of [`CachedRef::orphan_mask_with_options`] for a validated query with its
+    /// case already folded if required.
+    fn get_orphan_mask(
+        &self,
+        query: &[impl AsSymbols],
+        max_distance: MaxDistance,
+        options: &SearchOptions,
+    ) -> Result<Vec<bool>, Error> {
+        check_del_var_counts(query, max_distance, Anchoring::default(), InputType::Query)?;
+        let mask = get_orphan_mask(
+            query,
+            max_distance,
+            Anchoring::default(),
+            &self.hash_builder(),
+            options,
+            |hash| {
+                self.variant_map
+                    .get(&hash)
+                    .map_or(&[][..], |span| self.get_convergent_indices_from_span(&span))
+                    .iter()
+                    .copied()
+            },
+            |qi, ri, bound| {
+                options.bounded_dist(
+                    qi,
+                    ri,
+                    query[qi as usize].as_symbols(),
+                    Symbols::Text(self.get_str_at_index(ri as usize)),
+                    bound,
+                )
+            },
+        );
+        options.check_cancelled()?;
+        Ok(mask)
+    }
+
+    /// The memoized equivalent of [`distances_for_pairs`], with the reference side of each pair
+    /// indexing into the cached strings.
+    ///
+    /// Unlike searches, this does not consult the cached deletion variants, so `max_distance` may
+    /// exceed that of the cache.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::CachedRef;
+    ///
+    /// let cached = CachedRef::new(&["fooo", "barr", "bazz", "buzz"], 1).unwrap();
+    ///
+    /// assert_eq!(
+    ///     cached.distances_for_pairs_cached(&[(0, 3), (1, 2), (0, 0)], &["fizz", "fuzz"], 2).unwrap(),
+    ///     vec![Some(2), Some(2), None]
+    /// );
+    /// ```
+    pub fn distances_for_pairs_cached(
+        &self,
+        pairs: &[(u32, u32)],
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+    ) -> Result<Vec<Option<u8>>, Error> {
+        self.distances_for_pairs_cached_with_options(
+            pairs,
+            query,
+            max_distance,
+            &SearchOptions::default(),
+        )
+    }
+
+    /// Equivalent to [`CachedRef::distances_for_pairs_cached`], with additional
+    /// [`SearchOptions`].
+    pub fn distances_for_pairs_cached_with_options(
+        &self,
+        pairs: &[(u32, u32)],
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+        options: &SearchOptions,
+    ) -> Result<Vec<Option<u8>>, Error> {
+        if let Some(pool) = options.outside_thread_pool() {
+            return pool.install(|| {
+                self.distances_for_pairs_cached_with_options(pairs, query, max_distance, options)
+            });
+        }
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        check_pairs(pairs, query.len(), self.len())?;
+        options.check_strings(query, InputType::Query)?;
+
+        let dists = if self.case_insensitive {
+            self.compute_dists_partially_cached(
+                pairs,
+                &KnownDists::default(),
+                &fold_case(query),
+                max_distance,
+                options,
+                Vec::new(),
+            )
+        } else {
+            self.compute_dists_partially_cached(
+                pairs,
+                &KnownDists::default(),
+                query,
+                max_distance,
+                options,
+                Vec::new(),
+            )
+        };
+        options.check_cancelled()?;
+
+        Ok(into_bounded_dists(dists))
+    }
+
+    /// The memoized equivalent of [`get_nearest_neighbors_across`].
+    pub fn get_nearest_neighbors_across(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+    ) -> Result<Vec<Option<(u32, u8)>>, Error> {
+        self.get_nearest_neighbors_across_with_options(
+            query,
+            max_distance,
+            &SearchOptions::default(),
+        )
+    }
+
+    /// Equivalent to [`CachedRef::get_nearest_neighbors_across`], with additional
+    /// [`SearchOptions`].
+    pub fn get_nearest_neighbors_across_with_options(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: u8,
+        options: &SearchOptions,
+    ) -> Result<Vec<Option<(u32, u8)>>, Error> {
+        let hits = self.get_neighbors_across_top_k_with_options(query, max_distance, 1, options)?;
+        Ok(get_nearest_from_top_hits(hits, query.len()))
+    }
+
+    /// Implementation of [`CachedRef::get_neighbors_across_with_scratch`] for a validated query.
+    /// The hits are collected as requested by `collect`.
+    fn search_across(
+        &self,
+        query: &[impl AsRef<str> + Sync],
+        max_distance: MaxDistance,
+        options: &SearchOptions,
+        collect: Collect<'_>,
+        scratch: &mut QueryScratch,
+    ) -> Result<Hits, Error> {
+        if let Some(pool) = options.outside_thread_pool() {
+            return pool
+                .install(|| self.search_across(query, max_distance, options, collect, scratch));
+        }
+        search_span!(
+            "cached_ref_across",
+            num_query = query.len(),
+            num_reference = self.len(),
+            max_distance = max_distance.as_u8()
+        );
+        if self.case_insensitive {
+            self.search_across_folded(&fold_case(query), max_distance, options, collect, scratch)
+        } else {
+            self.search_across_folded(query, max_distance, options, collect, scratch)
+        }
+    }
+
+    /// Implementation of [`CachedRef::search_across`] for a query with its case already folded if
+    /// required.
+    fn search_across_folded(
+        &self,
+        query: &[impl AsSymbols],
+        max_distance: MaxDistance,
+        options: &SearchOptions,
+        collect: Collect<'_>,
+        scratch: &mut QueryScratch,
+    ) -> Result<Hits, Error> {
+        check_del_var_counts(query, max_distance, Anchoring::default(), InputType::Query)?;
+        let variant_index_pairs = get_sorted_vi_pairs_rawidx_into(
+            query,
+            0,
+            max_distance,
+            Anchoring::default(),
+            &self.hash_builder(),
+            None,
+            options.progress_reporter(),
+            std::mem::take(&mut scratch.variant_index_pairs),
+        );
+        options.check_cancelled()?;
+
+        let QueryScratch {
+            q_idx_store,
+            convergence_groups,
+            ..
+        } = scratch;
+        q_idx_store.clear();
+        convergence_groups.clear();
+
+        variant_index_pairs
+            .chunk_by(|a, b| a.hash() == b.hash())
+            .for_each(|chunk| {
+                if let Some(span) = self.variant_map.get(&chunk[0].hash()) {
+                    let cursor = q_idx_store.len();
+                    q_idx_store.extend(chunk.iter().map(VariantIndexPair::index));
+                    convergence_groups.push((cursor..q_idx_store.len(), span));
+                }
+            });
+        scratch.variant_index_pairs = variant_index_pairs;
+
+        let q_idx_store = &scratch.q_idx_store;
+        if let Collect::Flags = collect {
+            let flags = get_hit_flags(
+                &scratch.convergence_groups,
+                |(q_range, span)| {
+                    q_idx_store[q_range.clone()]
+                        .iter()
+                        .copied()
+                        .cartesian_product(
+                            self.get_convergent_indices_from_span(span).iter().copied(),
+                        )
+                },
+                query.len(),
+                max_distance,
+                options,
+                |qi, ri, bound| {
+                    options.bounded_dist(
+                        qi,
+                        ri,
+                        query[qi as usize].as_symbols(),
+                        Symbols::Text(self.get_str_at_index(ri as usize)),
+                        bound,
+                    )
+                },
+            );
+            options.check_cancelled()?;
+            return Ok(Hits::Flags(flags));
+        }
+        let dist = |qi: u32, ri: u32| {
+            options.bounded_dist(
+                qi,
+                ri,
+                query[qi as usize].as_symbols(),
+                Symbols::Text(self.get_str_at_index(ri as usize)),
+                max_distance,
+            )
+        };
+        let (mut candidates, known) = get_filtered_hit_candidates(
+            &scratch.convergence_groups,
+            |(q_range, span)| q_range.len() as u128 * span.len() as u128,
+            |(q_range, span)| (q_range.len(), span.len()),
+            |(q_range, span)| {
+                q_idx_store[q_range.clone()]
+                    .iter()
+                    .copied()
+                    .cartesian_product(self.get_convergent_indices_from_span(span).iter().copied())
+            },
+            Some(&dist),
+            options,
+            std::mem::take(&mut scratch.candidates),
+        )?;
+        par_radix_sort(&mut candidates);
+        par_dedup(&mut candidates);
+        options.check_cancelled()?;
+
+        if let Collect::TopK(k) = collect {
+            let hits = get_top_k_hits(&candidates, max_distance, options, k, |qi, ri, bound| {
+                known.get_or((qi, ri), || {
+                    options.bounded_dist(
+                        qi,
+                        ri,
+                        query[qi as usize].as_symbols(),
+                        Symbols::Text(self.get_str_at_index(ri as usize)),
+                        bound,
+                    )
+                })
+            });
+            options.check_cancelled()?;
+            scratch.candidates = candidates;
+            return Ok(Hits::Pairs(hits));
+        }
+
+        let dists = self.compute_dists_partially_cached(
+            &candidates,
+            &known,
+            query,
+            max_distance,
+            options,
+            std::mem::take(&mut scratch.dists),
+        );
+        options.check_cancelled()?;
+
+        let hits = match collect {
+            Collect::Pairs => Hits::Pairs(collect_true_hits_into(
+                &candidates,
+                &dists,
+                max_distance,
+                options.effective_min_distance(),
+                std::mem::take(&mut scratch.hits),
+            )),
+            _ => collect.select(&candidates, &dists, query.len(), max_distance, options),
+        };
+        scratch.candidates = candidates;
+        scratch.dists = dists;
+        Ok(hits)
+    }
+
+    /// Find the neighbors of a single query string within the cached reference.
+    ///
+    /// This is a serial fast path for one-off lookups, which probes the cached deletion variants
+    /// directly and skips the setup costs of [`CachedRef::get_neighbors_across`]. Returns pairs of
+    /// (reference index, distance), sorted by reference index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::CachedRef;
+    ///
+    /// let cached = CachedRef::new(&["fooo", "barr", "bazz", "buzz"], 2).unwrap();
+    ///
+    /// assert_eq!(cached.query_one("fuzz", 2).unwrap(), vec![(2, 2), (3, 1)]);
+    /// assert_eq!(cached.query_one("fuzz", 1).unwrap(), vec![(3, 1)]);
+    /// ```
+    pub fn query_one(&self, query: &str, max_distance: u8) -> Result<Vec<(u32, u8)>, Error> {
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        if max_distance > self.max_distance {
+            return Err(Error::MaxDistTooLargeForCache {
+                got: max_distance.as_u8(),
+                limit: self.max_distance.as_u8(),
+            });
+        }
+        check_strings_ascii(&[query], InputType::Query)?;
+        let query = if self.case_insensitive {
+            Cow::Owned(query.to_ascii_lowercase())
+        } else {
+            Cow::Borrowed(query)
+        };
+        let query = &*query;
+        check_del_var_counts(
+            &[query],
+            max_distance,
+            Anchoring::default(),
+            InputType::Query,
+        )?;
+
+        let variants = {
+            let num_vars =
+                get_num_del_vars_per_string(&[query], max_distance, Anchoring::default())[0];
+            let mut variants_uninit = prealloc_maybeuninit_vec(num_vars);
+            let num_distinct = write_vi_pairs_rawidx(
+                Symbols::Text(query),
+                0,
+                max_distance,
+                Anchoring::default(),
+                &mut variants_uninit[..],
+                &self.hash_builder(),
+                0,
+                &mut VariantScratch::default(),
+            );
+            let mut variants = unsafe { cast_to_initialised_vec(variants_uninit) };
+            variants.truncate(num_distinct);
+            variants
+        };
+
+        let mut candidates = variants
+            .iter()
+            .filter_map(|pair| self.variant_map.get(&pair.hash()))
+            .flat_map(|span| self.get_convergent_indices_from_span(&span))
+            .copied()
+            .collect_vec();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        Ok(candidates
+            .into_iter()
+            .filter_map(|idx_reference| {
+                levenshtein::distance_with_args(
+                    query.bytes(),
+                    self.get_str_at_index(idx_reference as usize).bytes(),
+                    &levenshtein::Args::default().score_cutoff(max_distance.as_usize()),
+                )
+                .map(|dist| (idx_reference, dist as u8))
+            })
+            .collect())
+    }
+
+    /// Equivalent to [`CachedRef::get_neighbors_across`], where the query is also a [`CachedRef`]
+    /// instance.
+    ///
+    /// The strings of both instances are compared as held, so that the two should agree on
+    /// whether they are [case-insensitive](CachedRef::case_insensitive). If the two hash their
+    /// deletion variants with different [seeds](CachedRef::hash_seed), the deletion variants of
+    /// the query strings are regenerated with the seed of this instance instead.
+    pub fn get_neighbors_across_cached(
+        &self,
+        query: &Self,
+        max_distance: u8,
+    ) -> Result<NeighborPairs, Error> {
+        self.get_neighbors_across_cached_with_options(
+            query,
+            max_distance,
+            &SearchOptions::default(),
+        )
+    }
+
+    /// Equivalent to [`CachedRef::get_neighbors_across_cached`], with additional
+    /// [`SearchOptions`].
+    pub fn get_neighbors_across_cached_with_options(
+        &self,
+        query: &Self,
+        max_distance: u8,
+        options: &SearchOptions,
+    ) -> Result<NeighborPairs, Error> {
+        if let Some(pool) = options.outside_thread_pool() {
+            return pool.install(|| {
+                self.get_neighbors_across_cached_with_options(query, max_distance, options)
+            });
+        }
+        search_span!(
+            "cached_ref_across_cached",
+            num_query = query.len(),
+            num_reference = self.len(),
+            max_distance
+        );
+        let max_distance = MaxDistance::try_from(max_distance)?;
+        if max_distance > self.max_distance {
+            return Err(Error::MaxDistTooLargeForCache {
+                got: max_distance.as_u8(),
+                limit: self.max_distance.as_u8(),
+            });
+        }
+        if max_distance > query.max_distance {
+            return Err(Error::MaxDistTooLargeForCache {
+                got: max_distance.as_u8(),
+                limit: query.max_distance.as_u8(),
+            });
+        }
+        if query.narrow_hashes != self.narrow_hashes {
+            return Err(Error::MismatchedHashWidth {
+                cache: self.hash_width(),
+                got: query.hash_width(),
+            });
+        }
+        if query.hash_seed != self.hash_seed {
+            let strings = (0..query.len())
+                .map(|i| query.get_str_at_index(i))
+                .collect_vec();
+            return self
+                .search_across_folded(
+                    &strings,
+                    max_distance,
+                    options,
+                    Collect::Pairs,
+                    &mut QueryScratch::default(),
+                )
+                .map(|hits| hits.into_pairs().into_order(options.order));
+        }
+
+        let convergence_groups = if query.variant_map.len() < self.variant_map.len() {
+            let mut num_convergence_groups = 0;
+
+            query.variant_map.iter().for_each(|(variant, _)| {
+                match self.variant_map.get(&variant) {
+                    None => (),
+                    Some(_) => {
+                        num_convergence_groups += 1;
+                    }
+                }
+            });
+
+            let mut convergence_groups = Vec::with_capacity(num_convergence_groups);
+
+            query.variant_map.iter().for_each(|(variant, span_q)| {
+                match self.variant_map.get(&variant) {
+                    None => (),
+                    Some(span_r) => {
+                        convergence_groups.push((
+                            query.get_convergent_indices_from_span(&span_q),
+                            self.get_convergent_indices_from_span(&span_r),
+                        ));
+                    }
+                }
+            });
+
+            convergence_groups
+        } else {
+            let mut num_convergence_groups = 0;
+
+            self.variant_map.iter().for_each(|(variant, _)| {
+                match query.variant_map.get(&variant) {
+                    None => (),
+                    Some(_) => {
+                        num_convergence_groups += 1;
+                    }
+                }
+            });
+
+            let mut convergence_groups = Vec::with_capacity(num_convergence_groups);
+
+            self.variant_map.iter().for_each(|(variant, span_r)| {
+                match query.variant_map.get(&variant) {
+                    None => (),
+                    Some(span_q) => {
+                        convergence_groups.push((
+                            query.get_convergent_indices_from_span(&span_q),
+                            self.get_convergent_indices_from_span(&span_r),
+                        ));
+                    }
+                }
+            });
+
+            convergence_groups
+        };
+
+        let dist = |qi: u32, ri: u32| {
+            options.bounded_dist(
+                qi,
+                ri,
+                Symbols::Text(query.get_str_at_index(qi as usize)),
+                Symbols::Text(self.get_str_at_index(ri as usize)),
+                max_distance,
+            )
+        };
+        let (candidates, known) =
+            get_hit_candidates_from_cis_cross(&convergence_groups, Some(&dist), options)?;
+        options.check_cancelled()?;
+        let dists =
+            self.compute_dists_fully_cached(&candidates, &known, query, max_distance, options);
+        options.check_cancelled()?;
+
+        Ok(collect_true_hits(
+            &candidates,
+            &dists,
+            max_distance,
+            options.effective_min_distance(),
+        )
+        .into_order(options.order))
+    }
+
+    /// Serialize the [`CachedRef`] instance into `writer`.
+    ///
+    /// The serialized data embeds a format version number, and can be loaded back using
+    /// [`CachedRef::read_from`] without having to recompute any deletion variants. Since the
+    /// writes are internally buffered, there is no need to wrap `writer` in a [`BufWriter`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::CachedRef;
+    ///
+    /// let cached = CachedRef::new(&["fooo", "barr", "bazz", "buzz"], 1).unwrap();
+    ///
+    /// let mut buffer = Vec::new();
+    /// cached.write_to(&mut buffer).unwrap();
+    /// let loaded = CachedRef::read_from(&buffer[..]).unwrap();
+    ///
+    /// assert_eq!(
+    ///     loaded.get_neighbors_across(&["fizz", "fuzz", "buzz"], 1).unwrap(),
+    ///     cached.get_neighbors_across(&["fizz", "fuzz", "buzz"], 1).unwrap(),
+    /// );
+    /// ```
+    pub fn write_to(&self, writer: impl Write) -> Result<(), Error> {
+        let mut writer = BufWriter::new(writer);
+
+        writer.write_all(CACHE_MAGIC)?;
+        writer.write_all(&CACHE_FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&[
+            self.max_distance.as_u8(),
+            self.case_insensitive as u8,
+            self.narrow_hashes as u8,
+        ])?;
+        writer.write_all(&self.hash_seed.to_le_bytes())?;
+
+        writer.write_all(&(self.str_spans.len() as u64).to_le_bytes())?;
+        for span in self.str_spans.iter() {
+            writer.write_all(&(span.len() as u64).to_le_bytes())?;
+        }
+        writer.write_all(&self.str_store)?;
+
+        writer.write_all(&(self.index_store.len() as u64).to_le_bytes())?;
+        for idx in self.index_store.iter() {
+            writer.write_all(&idx.to_le_bytes())?;
+        }
+
+        writer.write_all(&(self.variant_map.len() as u64).to_le_bytes())?;
+        for (v_hash, span) in self.variant_map.iter() {
+            writer.write_all(&v_hash.to_le_bytes())?;
+            writer.write_all(&(span.start as u64).to_le_bytes())?;
+            writer.write_all(&(span.len() as u64).to_le_bytes())?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Deserialize a [`CachedRef`] instance previously serialized with [`CachedRef::write_to`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::IncompatibleCacheVersion`] if the data was serialized using a different
+    /// version of the serialization format, [`Error::MalformedCache`] if the data is not a valid
+    /// serialized [`CachedRef`], and [`Error::Io`] if reading from `reader` fails.
+    pub fn read_from(reader: impl Read) -> Result<Self, Error> {
+        let mut reader = BufReader::new(reader);
+
+        let mut magic = [0u8; CACHE_MAGIC.len()];
+        read_exact_or_malformed(&mut reader, &mut magic)?;
+        if &magic != CACHE_MAGIC {
+            return Err(Error::MalformedCache("missing header"));
+        }
+
+        let version = u32::from_le_bytes(read_array(&mut reader)?);
+        if version != CACHE_FORMAT_VERSION {
+            return Err(Error::IncompatibleCacheVersion {
+                got: version,
+                expected: CACHE_FORMAT_VERSION,
+            });
+        }
+
+        let [max_distance, case_insensitive, narrow_hashes] = read_array(&mut reader)?;
+        let max_distance = MaxDistance::try_from(max_distance)
+            .map_err(|_| Error::MalformedCache("illegal max_distance"))?;
+        let case_insensitive = match case_insensitive {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::MalformedCache("illegal case sensitivity flag")),
+        };
+        let narrow_hashes = match narrow_hashes {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::MalformedCache("illegal hash width flag")),
+        };
+        let hash_seed = u64::from_le_bytes(read_array(&mut reader)?);
+
+        let num_strings = read_len(&mut reader)?;
+        if num_strings > u32::MAX as usize {
+            return Err(Error::MalformedCache("too many strings"));
+        }
+        let strlens = read_u64_vec(&mut reader, num_strings)?
+            .into_iter()
+            .map(|n| n as usize)
+            .collect_vec();
+        let total_strlen = strlens
+            .iter()
+            .try_fold(0usize, |acc, &n| acc.checked_add(n))
+            .ok_or(Error::MalformedCache("string lengths overflow"))?;
+        let str_spans = get_disjoint_spans(&strlens);
+        let str_store = read_byte_vec(&mut reader, total_strlen)?;
+        if str_spans
+            .iter()
+            .any(|span| str::from_utf8(&str_store[span.as_range()]).is_err())
+        {
+            return Err(Error::MalformedCache("invalid UTF-8 string data"));
+        }
+
+        let num_indices = read_len(&mut reader)?;
+        let index_store = read_byte_vec(&mut reader, num_indices.saturating_mul(4))?
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes(b.try_into().expect("chunks are of size 4")))
+            .collect_vec();
+        if index_store.iter().any(|&i| i as usize >= num_strings) {
+            return Err(Error::MalformedCache("string index out of bounds"));
+        }
+
+        let num_variants = read_len(&mut reader)?;
+        let variant_entries = read_u64_vec(&mut reader, num_variants.saturating_mul(3))?;
+        let mut variant_map = VariantTable::with_capacity(num_variants, narrow_hashes);
+        for entry in variant_entries.chunks_exact(3) {
+            if narrow_hashes
+                && VariantTable::widen_key(VariantTable::narrow_key(entry[0])) != entry[0]
+            {
+                return Err(Error::MalformedCache("variant hash wider than 32 bits"));
+            }
+            let span = Span::new(entry[1] as usize, entry[2] as usize);
+            if span
+                .start
+                .checked_add(span.len())
+                .is_none_or(|end| end > num_indices)
+            {
+                return Err(Error::MalformedCache("variant span out of bounds"));
+            }
+            variant_map.insert(entry[0], span);
+        }
+
+        let mut trailing = [0u8; 1];
+        if reader.read(&mut trailing)? != 0 {
+            return Err(Error::MalformedCache("trailing data"));
+        }
+
+        Ok(CachedRef {
+            str_store: Store::Owned(str_store),
+            str_spans: Store::Owned(str_spans),
+            index_store: Store::Owned(index_store),
+            variant_map,
+            max_distance,
+            case_insensitive,
+            hash_seed,
+            narrow_hashes,
+        })
+    }
+
+    /// The number of bytes needed to lay out the [`CachedRef`] instance with
+    /// [`CachedRef::write_shared`].
+    pub fn shared_size(&self) -> usize {
+        self.shared_layout().total_size()
+    }
+
+    /// Lay out the [`CachedRef`] instance into `buffer`, such that other processes with access to
+    /// the same memory (e.g. a named shared memory segment) can use the cache without copying it
+    /// via [`CachedRef::from_shared`].
+    ///
+    /// Unlike [`CachedRef::write_to`], the layout uses the native byte order and pointer width,
+    /// and is only meant to be read back on the same machine by the same version of the crate.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::SharedBufferTooSmall`] if `buffer` is shorter than
+    /// [`CachedRef::shared_size`].
+    pub fn write_shared(&self, buffer: &mut [u8]) -> Result<(), Error> {
+        let layout = self.shared_layout();
+        if buffer.len() < layout.total_size() {
+            return Err(Error::SharedBufferTooSmall {
+                got: buffer.len(),
+                required: layout.total_size(),
+            });
+        }
+
+        let mut variants = self
+            .variant_map
+            .iter()
+            .map(|(hash, span)| VariantEntry { hash, span })
+            .collect_vec();
+        variants.par_sort_unstable_by_key(|entry| entry.hash);
+
+        let header: [u64; SHARED_HEADER_WORDS] = [
+            u64::from_ne_bytes(*SHARED_MAGIC),
+            SHARED_FORMAT_VERSION as u64,
+            SHARED_LAYOUT_MARKER,
+            self.max_distance.as_u8() as u64,
+            self.str_spans.len() as u64,
+            self.str_store.len() as u64,
+            self.index_store.len() as u64,
+            variants.len() as u64,
+            self.case_insensitive as u64,
+            self.hash_seed,
+            self.narrow_hashes as u64,
+        ];
+
+        buffer[..size_of_val(&header)].copy_from_slice(as_bytes(&header));
+        buffer[layout.str_spans].copy_from_slice(as_bytes(&self.str_spans));
+        buffer[layout.str_store].copy_from_slice(&self.str_store);
+        buffer[layout.index_store].copy_from_slice(as_bytes(&self.index_store));
+        buffer[layout.variants].copy_from_slice(as_bytes(&variants));
+
+        Ok(())
+    }
+
+    /// Construct a [`CachedRef`] that uses memory previously laid out by
+    /// [`CachedRef::write_shared`] in place, without copying it.
+    ///
+    /// `buffer` is kept alive for as long as the returned instance (or any of its internal
+    /// buffers) is in use, and must start on an 8-byte boundary. Calling [`CachedRef::extend`]
+    /// on the returned instance first copies the affected buffers into private memory.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must always return the same memory from [`AsRef::as_ref`], and that memory must
+    /// not be modified for as long as the returned instance is alive.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::IncompatibleCacheVersion`] if the buffer was laid out by a different
+    /// version of the crate, and [`Error::MalformedCache`] if the buffer does not hold a valid
+    /// [`CachedRef`] layout.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use symscan::CachedRef;
+    ///
+    /// // Stand-in for a memory-mapped shared memory segment, which is always suitably aligned.
+    /// struct Aligned(Vec<u64>);
+    ///
+    /// impl AsRef<[u8]> for Aligned {
+    ///     fn as_ref(&self) -> &[u8] {
+    ///         unsafe { std::slice::from_raw_parts(self.0.as_ptr().cast(), self.0.len() * 8) }
+    ///     }
+    /// }
+    ///
+    /// let cached = CachedRef::new(&["fooo", "barr", "bazz", "buzz"], 1).unwrap();
+    ///
+    /// let mut buffer = Aligned(vec![0; cached.shared_size().div_ceil(8)]);
+    /// let bytes =
+    ///     unsafe { std::slice::from_raw_parts_mut(buffer.0.as_mut_ptr().cast(), buffer.0.len() * 8) };
+    /// cached.write_shared(bytes).unwrap();
+    /// let shared = unsafe { CachedRef::from_shared(buffer) }.unwrap();
+    ///
+    /// assert_eq!(
+    ///     shared.get_neighbors_across(&["fizz", "fuzz", "buzz"], 1).unwrap(),
+    ///     cached.get_neighbors_across(&["fizz", "fuzz", "buzz"], 1).unwrap(),
+    /// );
+    /// ```
+    pub unsafe fn from_shared(
+        buffer: impl AsRef<[u8]> + Send + Sync + 'static,
+    ) -> Result<Self, Error> {
+        let backing: Arc<dyn AsRef<[u8]> + Send + Sync> = Arc::new(buffer);
+        let bytes = (*backing).as_ref();
+
+        if bytes.as_ptr().align_offset(8) != 0 {
+            return Err(Error::MalformedCache("buffer is not 8-byte aligned"));
+        }
+        if bytes.len() < SHARED_HEADER_WORDS * size_of::<u64>() {
+            return Err(Error::MalformedCache("missing header"));
+        }
+        let header: [u64; SHARED_HEADER_WORDS] = std::array::from_fn(|i| {
+            u64::from_ne_bytes(bytes[i * 8..(i + 1) * 8].try_into().expect("8 bytes"))
+        });
+
+        if header[0] != u64::from_ne_bytes(*SHARED_MAGIC) {
+            return Err(Error::MalformedCache("missing header"));
+        }
+        if header[1] != SHARED_FORMAT_VERSION as u64 {
+            return Err(Error::IncompatibleCacheVersion {
+                got: header[1] as u32,
+                expected: SHARED_FORMAT_VERSION,
+            });
+        }
+        if header[2] != SHARED_LAYOUT_MARKER {
+            return Err(Error::MalformedCache(
+                "laid out on an incompatible platform",
+            ));
+        }
+        let max_distance = u8::try_from(header[3])
+            .ok()
+            .and_then(|d| MaxDistance::try_from(d).ok())
+            .ok_or(Error::MalformedCache("illegal max_distance"))?;
+        let [num_strings, str_store_len, num_indices, num_variants] =
+            [header[4], header[5], header[6], header[7]]
+                .map(|n| usize::try_from(n).unwrap_or(usize::MAX));
+        if num_strings > u32::MAX as usize {
+            return Err(Error::MalformedCache("too many strings"));
+        }
+        let case_insensitive = match header[8] {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::MalformedCache("illegal case sensitivity flag")),
+        };
+        let narrow_hashes = match header[10] {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::MalformedCache("illegal hash width flag")),
+        };
+
+        let layout = SharedLayout::new(num_strings, str_store_len, num_indices, num_variants)
+            .filter(|layout| layout.total_size() <= bytes.len())
+            .ok_or(Error::MalformedCache("truncated data"))?;
+
+        let view = |range: &Range<usize>| bytes[range.clone()].as_ptr();
+        let str_spans = Store::Shared {
+            ptr: view(&layout.str_spans).cast::<Span>(),
+            len: num_strings,
+            _backing: backing.clone(),
+        };
+        let str_store = Store::Shared {
+            ptr: view(&layout.str_store),
+            len: str_store_len,
+            _backing: backing.clone(),
+        };
+        let index_store = Store::Shared {
+            ptr: view(&layout.index_store).cast::<u32>(),
+            len: num_indices,
+            _backing: backing.clone(),
+        };
+        let variants = Store::Shared {
+            ptr: view(&layout.variants).cast::<VariantEntry>(),
+            len: num_variants,
+            _backing: backing,
+        };
+
+        if str_spans.iter().any(|span| {
+            span.start
+                .checked_add(span.len)
+                .is_none_or(|end| end > str_store_len)
+        }) {
+            return Err(Error::MalformedCache("string span out of bounds"));
+        }
+        if str_spans
+            .iter()
+            .any(|span| str::from_utf8(&str_store[span.as_range()]).is_err())
+        {
+            return Err(Error::MalformedCache("invalid UTF-8 string data"));
+        }
+        if index_store.iter().any(|&i| i as usize >= num_strings) {
+            return Err(Error::MalformedCache("string index out of bounds"));
+        }
+        if variants.iter().any(|entry| {
+            entry
+                .span
+                .start
+                .checked_add(entry.span.len)
+                .is_none_or(|end| end > num_indices)
+        }) {
+            return Err(Error::MalformedCache("variant span out of bounds"));
+        }
+        if !variants.windows(2).all(|w| w[0].hash < w[1].hash) {
+            return Err(Error::MalformedCache("unsorted variant table"));
+        }
+
+        Ok(CachedRef {
+            str_store,
+            str_spans,
+            index_store,
+            variant_map: VariantTable::Sorted(variants),
+            max_distance,
+            case_insensitive,
+            hash_seed: header[9],
+            narrow_hashes,
+        })
+    }
+
+    fn shared_layout(&self) -> SharedLayout {
+        SharedLayout::new(
+            self.str_spans.len(),
+            self.str_store.len(),
+            self.index_store.len(),
+            self.variant_map.len(),
+        )
+        .expect("in-memory cache sizes fit in usize")
+    }
+
+    #[inline(always)]
+    fn get_convergent_indices_from_span(&self, span: &Span) -> &[u32] {
+        &self.index_store[span.as_range()]
+    }
+
+    #[inline(always)]
+    fn get_str_at_index(&self, i: usize) -> &str {
+        unsafe { str::from_utf8_unchecked(&self.str_store[self.str_spans[i].as_range()]) }
+    }
+
+    fn compute_dists_partially_cached(
+        &self,
+        hit_candidates: &[(u32, u32)],
+        known: &KnownDists,
+        query: &[impl AsSymbols],
+        max_distance: MaxDistance,
+        options: &SearchOptions,
+        mut dists: Vec<u8>,
+    ) -> Vec<u8> {
+        dists.clear();
+        dists.resize(hit_candidates.len(), u8::MAX);
+        compute_dists_into(
+            &mut dists,
+            hit_candidates,
+            known,
+            |i| query[i as usize].as_symbols(),
+            |i| Symbols::Text(self.get_str_at_index(i as usize)),
+            max_distance,
+            options,
+        );
+        dists
+    }
+
+    fn compute_dists_fully_cached(
+        &self,
+        hit_candidates: &[(u32, u32)],
+        known: &KnownDists,
+        query: &Self,
+        max_distance: MaxDistance,
+        options: &SearchOptions,
+    ) -> Vec<u8> {
+        let mut dists = vec![u8::MAX; hit_candidates.len()];
+        compute_dists_into(
+            &mut dists,
+            hit_candidates,
+            known,
+            |i| Symbols::Text(query.get_str_at_index(i as usize)),
+            |i| Symbols::Text(self.get_str_at_index(i as usize)),
+            max_distance,
+            options,
+        );
+        dists
+    }
+}
+
+/// Detect string pairs within an input collection that lie within a threshold edit distance.
+///
+/// The function considers all possible combinations (not permutations, [read
+/// more](NeighborPairs#a-note-on-double-counting-pairs)) of string pairs from `query`, and returns
+/// all those where the two strings are no more than `max_distance` Levenshtein edit distance units
+/// apart.
+///
+/// # Errors
+///
+/// By default, only ASCII input is supported (see [`SearchOptions::unicode`] to opt into Unicode
+/// support). The function will [`Err`] with [`Error::NonAsciiInput`] if `query` contains any
+/// non-ASCII data.
+///
+/// There are some hard limits on the sizes of the input arguments (see [`Error::TooManyStrings`],
+/// [`Error::MaxDistCapped`]). Note however that in practice, runtime or memory usage is almost
+/// certainly the limiting factor instead.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_within, NeighborPairs};
+///
+/// let query = ["fizz", "fuzz", "buzz"];
+/// let NeighborPairs { row, col, dists } = get_neighbors_within(&query, 1).unwrap();
+///
+/// assert_eq!(row,   vec![0, 1]);
+/// assert_eq!(col,   vec![1, 2]);
+/// assert_eq!(dists, vec![1, 1]);
+///
+/// let NeighborPairs { row, col, dists } = get_neighbors_within(&query, 2).unwrap();
+///
+/// assert_eq!(row,   vec![0, 0, 1]);
+/// assert_eq!(col,   vec![1, 2, 2]);
+/// assert_eq!(dists, vec![1, 2, 1]);
+/// ```
+pub fn get_neighbors_within(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<NeighborPairs, Error> {
+    get_neighbors_within_with_options(query, max_distance, &SearchOptions::default())
+}
+
+/// Equivalent to [`get_neighbors_within_with_options`], additionally returning the
+/// [`SearchStats`] of the search.
+pub fn get_neighbors_within_with_stats(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<(NeighborPairs, SearchStats), Error> {
+    let (options, recorder) = options.recording_stats();
+    let hits = get_neighbors_within_with_options(query, max_distance, &options)?;
+    let stats = recorder.finish(hits.len());
+    Ok((hits, stats))
+}
+
+/// Equivalent to [`get_neighbors_within`], with additional [`SearchOptions`].
+pub fn get_neighbors_within_with_options(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<NeighborPairs, Error> {
+    if query.len() > u32::MAX as usize {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: u32::MAX as usize,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+
+    get_neighbors_within_validated(query, max_distance, options, None, Collect::Pairs)
+        .map(Hits::into_pairs)
+}
+
+/// An integer type in which the offsets of strings laid out contiguously in a buffer can be
+/// given (see [`get_neighbors_within_offsets`]).
+///
+/// This trait is sealed, and implemented for [`u32`], [`u64`] and [`usize`], as well as for the
+/// [`i32`] and [`i64`] offsets of Arrow's `StringArray` and `LargeStringArray`, so that their
+/// buffers can be searched without copying.
+pub trait Offset: Copy + Sync + sealed::Sealed {}
+
+mod sealed {
+    pub trait Sealed {
+        /// The offset as an index into the buffer, or [`None`] if it does not fit one.
+        fn to_usize(self) -> Option<usize>;
+
+        /// The offset as reported by [`Error::InvalidOffsets`](crate::Error::InvalidOffsets).
+        fn to_i128(self) -> i128;
+
+        /// The offset as an index into the buffer, once all offsets have been validated.
+        fn to_validated(self) -> usize
+        where
+            Self: Sized,
+        {
+            self.to_usize().expect("offsets have been validated")
+        }
+    }
+}
+
+macro_rules! impl_offset {
+    ($($ty:ty),*) => {$(
+        impl sealed::Sealed for $ty {
+            fn to_usize(self) -> Option<usize> {
+                usize::try_from(self).ok()
+            }
+
+            fn to_i128(self) -> i128 {
+                self as i128
+            }
+        }
+
+        impl Offset for $ty {}
+    )*};
+}
+
+impl_offset!(u32, u64, usize, i32, i64);
+
+/// Detect string pairs within a collection of strings laid out contiguously in `buffer`, where
+/// string `i` spans the bytes `offsets[i]..offsets[i + 1]`.
+///
+/// This is equivalent to [`get_neighbors_within`], for strings that already live in one buffer
+/// (e.g. the contents of a file, or an Arrow string array). It avoids copying them into
+/// separately allocated strings, and validates the whole buffer as ASCII at once. The offsets may
+/// be of any [`Offset`] type.
+///
+/// # Errors
+///
+/// In addition to the errors of [`get_neighbors_within`], returns [`Error::InvalidOffsets`] if
+/// `offsets` is not non-decreasing or points past the end of `buffer`.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_within_offsets, NeighborPairs};
+///
+/// let buffer = b"fizzfuzzbuzz";
+/// let offsets = [0, 4, 8, 12];
+/// let NeighborPairs { row, col, dists } = get_neighbors_within_offsets(buffer, &offsets, 1).unwrap();
+///
+/// assert_eq!(row,   vec![0, 1]);
+/// assert_eq!(col,   vec![1, 2]);
+/// assert_eq!(dists, vec![1, 1]);
+/// ```
+pub fn get_neighbors_within_offsets(
+    buffer: &[u8],
+    offsets: &[impl Offset],
+    max_distance: u8,
+) -> Result<NeighborPairs, Error> {
+    get_neighbors_within_offsets_with_options(
+        buffer,
+        offsets,
+        max_distance,
+        &SearchOptions::default(),
+    )
+}
+
+/// Equivalent to [`get_neighbors_within_offsets`], with additional [`SearchOptions`].
+pub fn get_neighbors_within_offsets_with_options(
+    buffer: &[u8],
+    offsets: &[impl Offset],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<NeighborPairs, Error> {
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    let query = get_str_views_from_offsets(buffer, offsets, InputType::Query)?;
+
+    get_neighbors_within_validated(&query, max_distance, options, None, Collect::Pairs)
+        .map(Hits::into_pairs)
+}
+
+/// Detect string pairs across two collections of strings, each laid out contiguously in a buffer
+/// as for [`get_neighbors_within_offsets`].
+///
+/// This is equivalent to [`get_neighbors_across`], for strings that already live in one buffer
+/// per collection. The two collections may give their offsets in different [`Offset`] types.
+///
+/// # Errors
+///
+/// In addition to the errors of [`get_neighbors_across`], returns [`Error::InvalidOffsets`] if
+/// either set of offsets is not non-decreasing or points past the end of its buffer.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_across_offsets, NeighborPairs};
+///
+/// let query_offsets: [i32; 4] = [0, 4, 8, 12];
+/// let reference_offsets: [u64; 5] = [0, 4, 8, 12, 16];
+/// let NeighborPairs { row, col, dists } = get_neighbors_across_offsets(
+///     b"fizzfuzzbuzz",
+///     &query_offsets,
+///     b"fooobarrbazzbuzz",
+///     &reference_offsets,
+///     1,
+/// )
+/// .unwrap();
+///
+/// assert_eq!(row,   vec![1, 2, 2]);
+/// assert_eq!(col,   vec![3, 2, 3]);
+/// assert_eq!(dists, vec![1, 1, 0]);
+/// ```
+pub fn get_neighbors_across_offsets(
+    query_buffer: &[u8],
+    query_offsets: &[impl Offset],
+    reference_buffer: &[u8],
+    reference_offsets: &[impl Offset],
+    max_distance: u8,
+) -> Result<NeighborPairs, Error> {
+    get_neighbors_across_offsets_with_options(
+        query_buffer,
+        query_offsets,
+        reference_buffer,
+        reference_offsets,
+        max_distance,
+        &SearchOptions::default(),
+    )
+}
+
+/// Equivalent to [`get_neighbors_across_offsets`], with additional [`SearchOptions`].
+pub fn get_neighbors_across_offsets_with_options(
+    query_buffer: &[u8],
+    query_offsets: &[impl Offset],
+    reference_buffer: &[u8],
+    reference_offsets: &[impl Offset],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<NeighborPairs, Error> {
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    let query = get_str_views_from_offsets(query_buffer, query_offsets, InputType::Query)?;
+    let reference =
+        get_str_views_from_offsets(reference_buffer, reference_offsets, InputType::Reference)?;
+    for (strings, input_type) in [
+        (&query, InputType::Query),
+        (&reference, InputType::Reference),
+    ] {
+        if strings.len() > CrossIndex::MAX {
+            return Err(Error::TooManyStrings {
+                input_type,
+                got: strings.len(),
+                limit: CrossIndex::MAX,
+            });
+        }
+    }
+
+    get_neighbors_across_validated(&query, &reference, max_distance, options, Collect::Pairs)
+        .map(Hits::into_pairs)
+}
+
+/// Detect string pairs within a collection of raw byte strings that lie within a threshold edit
+/// distance.
+///
+/// This is equivalent to [`get_neighbors_within`], for strings that need not be valid UTF-8 (e.g.
+/// binary tokens). Each byte is treated as a separate symbol, so that deletion variants are formed
+/// by deleting bytes, and distances count byte edits.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_within_bytes, NeighborPairs};
+///
+/// let query: [&[u8]; 3] = [b"\xff\x00\x01", b"\xff\x00\x02", b"\x00\x01"];
+/// let NeighborPairs { row, col, dists } = get_neighbors_within_bytes(&query, 1).unwrap();
+///
+/// assert_eq!(row,   vec![0, 0]);
+/// assert_eq!(col,   vec![1, 2]);
+/// assert_eq!(dists, vec![1, 1]);
+/// ```
+pub fn get_neighbors_within_bytes<T: AsRef<[u8]> + Sync>(
+    query: &[T],
+    max_distance: u8,
+) -> Result<NeighborPairs, Error> {
+    get_neighbors_within_bytes_with_options(query, max_distance, &SearchOptions::default())
+}
+
+/// Equivalent to [`get_neighbors_within_bytes`], with additional [`SearchOptions`].
+pub fn get_neighbors_within_bytes_with_options<T: AsRef<[u8]> + Sync>(
+    query: &[T],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<NeighborPairs, Error> {
+    if query.len() > u32::MAX as usize {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: u32::MAX as usize,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+
+    get_neighbors_within_validated(
+        ByteStr::wrap_slice(query),
+        max_distance,
+        options,
+        None,
+        Collect::Pairs,
+    )
+    .map(Hits::into_pairs)
+}
+
+/// Detect string pairs within an input collection of labeled strings that lie within a threshold
+/// edit distance, comparing only the pairs that `policy` allows.
+///
+/// `labels` assigns a categorical label (e.g. a V gene) to each string in `query`. With
+/// [`GroupPolicy::WithinGroupsOnly`], only strings sharing a label are compared, and the labels are
+/// mixed into the hashes of the deletion variants so that strings of different labels never even
+/// become candidates. With [`GroupPolicy::AcrossGroupsOnly`], only strings of different labels are
+/// compared, and pairs of strings sharing a label are skipped over without being enumerated.
+/// Either way, this is much cheaper than filtering the output of [`get_neighbors_within`], whose
+/// return format and indices it otherwise shares.
+///
+/// # Errors
+///
+/// In addition to the errors of [`get_neighbors_within`], returns [`Error::MismatchedLabels`] if
+/// `labels` and `query` differ in length.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_within_grouped, GroupPolicy, NeighborPairs};
+///
+/// let query = ["fizz", "fuzz", "buzz"];
+/// let labels = [0, 1, 1];
+///
+/// let NeighborPairs { row, col, .. } =
+///     get_neighbors_within_grouped(&query, &labels, 2, GroupPolicy::WithinGroupsOnly).unwrap();
+/// assert_eq!((row, col), (vec![1], vec![2]));
+///
+/// let NeighborPairs { row, col, .. } =
+///     get_neighbors_within_grouped(&query, &labels, 2, GroupPolicy::AcrossGroupsOnly).unwrap();
+/// assert_eq!((row, col), (vec![0, 0], vec![1, 2]));
+/// ```
+pub fn get_neighbors_within_grouped(
+    query: &[impl AsRef<str> + Sync],
+    labels: &[u32],
+    max_distance: u8,
+    policy: GroupPolicy,
+) -> Result<NeighborPairs, Error> {
+    get_neighbors_within_grouped_with_options(
+        query,
+        labels,
+        max_distance,
+        policy,
+        &SearchOptions::default(),
+    )
+}
+
+/// Equivalent to [`get_neighbors_within_grouped`], with additional [`SearchOptions`].
+pub fn get_neighbors_within_grouped_with_options(
+    query: &[impl AsRef<str> + Sync],
+    labels: &[u32],
+    max_distance: u8,
+    policy: GroupPolicy,
+    options: &SearchOptions,
+) -> Result<NeighborPairs, Error> {
+    if query.len() > u32::MAX as usize {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: u32::MAX as usize,
+        });
+    }
+    if labels.len() != query.len() {
+        return Err(Error::MismatchedLabels {
+            strings: query.len(),
+            labels: labels.len(),
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+
+    get_neighbors_within_validated(
+        query,
+        max_distance,
+        options,
+        Some((labels, policy)),
+        Collect::Pairs,
+    )
+    .map(Hits::into_pairs)
+}
+
+/// Detect string pairs among several collections of strings that lie within a threshold edit
+/// distance, comparing only the pairs that `policy` allows.
+///
+/// This is equivalent to running [`get_neighbors_across`] on every pair of collections and/or
+/// [`get_neighbors_within`] on every collection, but generates the deletion variants of each
+/// string only once, in a single search over the union of all collections. The collections are
+/// told apart as with [`get_neighbors_within_grouped`], so that with
+/// [`MultiPolicy::WithinOnly`], strings of different collections never even become candidates.
+///
+/// # Errors
+///
+/// Returns the errors of [`get_neighbors_within`], where [`Error::TooManyStrings`] refers to the
+/// total number of strings in all collections, and the `offending_idx` of
+/// [`Error::NonAsciiInput`] to the index of the offending string within its collection.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_multi, MultiNeighborPairs, MultiPolicy};
+///
+/// let cohort_a = ["fizz", "fuzz"];
+/// let cohort_b = ["buzz"];
+/// let cohort_c = ["fizz", "bazz"];
+/// let sets = [&cohort_a[..], &cohort_b[..], &cohort_c[..]];
+///
+/// let MultiNeighborPairs { row_set, row, col_set, col, dists } =
+///     get_neighbors_multi(&sets, 1, MultiPolicy::CrossOnly).unwrap();
+///
+/// assert_eq!(row_set, vec![0, 0, 0, 1]);
+/// assert_eq!(row,     vec![0, 1, 1, 0]);
+/// assert_eq!(col_set, vec![2, 1, 2, 2]);
+/// assert_eq!(col,     vec![0, 0, 0, 1]);
+/// assert_eq!(dists,   vec![0, 1, 1, 1]);
+/// ```
+pub fn get_neighbors_multi<T: AsRef<str> + Sync>(
+    sets: &[&[T]],
+    max_distance: u8,
+    policy: MultiPolicy,
+) -> Result<MultiNeighborPairs, Error> {
+    get_neighbors_multi_with_options(sets, max_distance, policy, &SearchOptions::default())
+}
+
+/// Equivalent to [`get_neighbors_multi`], with additional [`SearchOptions`].
+///
+/// The indices passed to [`SearchOptions::pair_filter`] are those of the strings in the
+/// concatenation of all collections, in order.
+pub fn get_neighbors_multi_with_options<T: AsRef<str> + Sync>(
+    sets: &[&[T]],
+    max_distance: u8,
+    policy: MultiPolicy,
+    options: &SearchOptions,
+) -> Result<MultiNeighborPairs, Error> {
+    let total_len = sets.iter().map(|set| set.len()).sum::<usize>();
+    if total_len > u32::MAX as usize {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: total_len,
+            limit: u32::MAX as usize,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    for set in sets {
+        options.check_strings(set, InputType::Query)?;
+    }
+
+    let strings = sets.iter().flat_map(|set| set.iter()).collect_vec();
+    let mut set_starts = Vec::with_capacity(sets.len());
+    let mut labels = Vec::with_capacity(total_len);
+    for (set_idx, set) in sets.iter().enumerate() {
+        set_starts.push(labels.len() as u32);
+        labels.extend(iter::repeat_n(set_idx as u32, set.len()));
+    }
+    let groups = match policy {
+        MultiPolicy::AllPairs => None,
+        MultiPolicy::CrossOnly => Some((&labels[..], GroupPolicy::AcrossGroupsOnly)),
+        MultiPolicy::WithinOnly => Some((&labels[..], GroupPolicy::WithinGroupsOnly)),
+    };
+
+    let hits =
+        get_neighbors_within_validated(&strings, max_distance, options, groups, Collect::Pairs)?
+            .into_pairs();
+    Ok(MultiNeighborPairs::from_hits(hits, &set_starts))
+}
+
+/// Implementation of [`get_neighbors_within_with_options`] for a query that is already known to
+/// be of legal length and encoding.
+fn get_neighbors_within_validated(
+    query: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+    groups: Option<(&[u32], GroupPolicy)>,
+    collect: Collect<'_>,
+) -> Result<Hits, Error> {
+    if let Some(pool) = options.outside_thread_pool() {
+        return pool.install(|| {
+            get_neighbors_within_validated(query, max_distance, options, groups, collect)
+        });
+    }
+    search_span!(
+        "get_neighbors_within",
+        num_query = query.len(),
+        max_distance = max_distance.as_u8()
+    );
+    check_del_var_counts(query, max_distance, options.anchoring(), InputType::Query)?;
+    options.check_excluded_pairs(query.len(), None)?;
+    if options.order == OutputOrder::ByDistance && matches!(collect, Collect::Pairs) {
+        let hits = get_neighbors_within_validated(
+            query,
+            max_distance,
+            &options.for_reordering(),
+            groups,
+            collect,
+        )?
+        .into_pairs();
+        return Ok(Hits::Pairs(hits.into_order(options.order)));
+    }
+    if options.symmetric && matches!(collect, Collect::Pairs) {
+        let hits = get_neighbors_within_validated(
+            query,
+            max_distance,
+            &options.for_one_orientation(),
+            groups,
+            collect,
+        )?
+        .into_pairs();
+        return Ok(Hits::Pairs(hits.symmetrize(query.len())));
+    }
+    if options.case_insensitive {
+        return get_neighbors_within_validated(
+            &fold_case(query),
+            max_distance,
+            &options.for_folded_strings(),
+            groups,
+            collect,
+        );
+    }
+    if options.deduplicate && groups.is_none() && matches!(collect, Collect::Pairs) {
+        let duplicates = Duplicates::find(query);
+        if duplicates.num_unique() < query.len() {
+            let unique = duplicates.get_unique_strings(query);
+            let hits = get_neighbors_within_validated(
+                &unique,
+                max_distance,
+                &options.for_unique_strings(),
+                None,
+                Collect::Pairs,
+            )?
+            .into_pairs();
+            return Ok(Hits::Pairs(duplicates.expand_within(hits, options)));
+        }
+    }
+    if options.partition_by_length && groups.is_none() && matches!(collect, Collect::Pairs) {
+        let partition = LengthPartition::new(query, LengthPartition::target_num_blocks());
+        if partition.num_blocks() > 1 {
+            let hits = partition.search_within(query, max_distance, options)?;
+            return Ok(Hits::Pairs(hits));
+        }
+    }
+
+    let num_pairs = (query.len() as u128 * query.len().saturating_sub(1) as u128) / 2;
+    if options.use_brute_force(num_pairs, || {
+        count_del_vars(query, max_distance, options.anchoring())
+    }) {
+        let mut candidates = (0..query.len() as u32)
+            .tuple_combinations()
+            .filter(|&(a, b)| {
+                groups.is_none_or(|(labels, policy)| {
+                    policy.allows(labels[a as usize], labels[b as usize])
+                })
+            })
+            .collect_vec();
+        options.remove_excluded(&mut candidates);
+        return get_true_hits(
+            &candidates,
+            &KnownDists::default(),
+            query,
+            query,
+            max_distance,
+            options,
+            collect,
+        );
+    }
+
+    // Salting the deletion variants by label keeps strings of different labels from ever
+    // converging, which shrinks the convergence groups rather than just their candidates.
+    let salt_labels = match groups {
+        Some((labels, GroupPolicy::WithinGroupsOnly)) => Some(labels),
+        _ => None,
+    };
+
+    // Identical strings share all of their deletion variants, so that large numbers of duplicates
+    // would pair up quadratically within each convergence group. If their pairs are not wanted,
+    // group the duplicates within each convergence group so that they can be skipped over. Pairs
+    // ruled out by their labels are skipped over in the same way, but since strings of different
+    // labels may still be identical, duplicates are then left for verification to rule out.
+    let across_groups = matches!(groups, Some((_, GroupPolicy::AcrossGroupsOnly)));
+    let canonical_indices = (options.effective_min_distance() > 0 && !across_groups)
+        .then(|| get_canonical_indices(query));
+    let keys = match groups {
+        None => PairKeys {
+            same: None,
+            distinct: canonical_indices.as_deref(),
+        },
+        Some((labels, GroupPolicy::WithinGroupsOnly)) => PairKeys {
+            same: Some(labels),
+            distinct: canonical_indices.as_deref(),
+        },
+        Some((labels, GroupPolicy::AcrossGroupsOnly)) => PairKeys {
+            same: None,
+            distinct: Some(labels),
+        },
+    };
+
+    // Exact grouping compares the deletion variants themselves, which are not spilled.
+    if let Some(low_memory) = options
+        .low_memory
+        .as_ref()
+        .filter(|_| !options.exact_grouping)
+    {
+        return if options.wide_hashes {
+            get_neighbors_within_spilled::<4>(
+                query,
+                max_distance,
+                options,
+                salt_labels,
+                keys,
+                low_memory,
+                collect,
+            )
+        } else if options.narrow_hashes {
+            get_neighbors_within_spilled::<1>(
+                query,
+                max_distance,
+                options,
+                salt_labels,
+                keys,
+                low_memory,
+                collect,
+            )
+        } else {
+            get_neighbors_within_spilled::<2>(
+                query,
+                max_distance,
+                options,
+                salt_labels,
+                keys,
+                low_memory,
+                collect,
+            )
+        };
+    }
+
+    let (mut convergent_indices, group_sizes) = if options.exact_grouping {
+        let groups = get_exact_convergence_groups_within(query, max_distance, options, salt_labels);
+        options.check_cancelled()?;
+        groups
+    } else if options.wide_hashes {
+        get_convergence_groups_within::<4>(query, max_distance, options, salt_labels)?
+    } else if options.narrow_hashes {
+        get_convergence_groups_within::<1>(query, max_distance, options, salt_labels)?
+    } else {
+        get_convergence_groups_within::<2>(query, max_distance, options, salt_labels)?
+    };
+    options
+        .progress_reporter()
+        .record_groups(group_sizes.iter().copied());
+
+    if !keys.is_empty() {
+        let mut remaining = &mut convergent_indices[..];
+        for &n in &group_sizes {
+            let (chunk, rest) = remaining.split_at_mut(n);
+            chunk.sort_unstable_by_key(|&idx| (keys.get(idx), idx));
+            remaining = rest;
+        }
+    }
+
+    let mut convergent_chunks = Vec::with_capacity(group_sizes.len());
+    let mut remaining = &convergent_indices[..];
+    for n in group_sizes {
+        let (chunk, rest) = remaining.split_at(n);
+        convergent_chunks.push(chunk);
+        remaining = rest;
+    }
+
+    debug_assert_eq!(remaining.len(), 0);
+
+    if options.fuse_verification
+        && options.excluded_pairs.is_none()
+        && matches!(collect, Collect::Pairs)
+    {
+        let hits = get_true_hits_fused(
+            &convergent_chunks,
+            |indices| get_candidates_within(indices, keys),
+            query,
+            query,
+            max_distance,
+            options,
+        );
+        options.check_cancelled()?;
+        return Ok(Hits::Pairs(hits));
+    }
+
+    // The raw candidates are returned as they are, so must not be verified early.
+    let dist = |qi: u32, ri: u32| {
+        let (a, b) = (
+            query[qi as usize].as_symbols(),
+            query[ri as usize].as_symbols(),
+        );
+        options.bounded_dist(qi, ri, a, b, max_distance)
+    };
+    let bounded_dist = match collect {
+        Collect::Candidates => None,
+        _ => Some(&dist as &(dyn Fn(u32, u32) -> u8 + Sync)),
+    };
+    let (mut candidates, known) =
+        get_hit_candidates_within(&convergent_chunks, keys, bounded_dist, options)?;
+    options.check_cancelled()?;
+    options.remove_excluded(&mut candidates);
+    if let Collect::Candidates = collect {
+        return Ok(Hits::Candidates(candidates));
+    }
+
+    get_true_hits(
+        &candidates,
+        &known,
+        query,
+        query,
+        max_distance,
+        options,
+        collect,
+    )
+}
+
+/// Count the neighbors of each string in an input collection that lie within a threshold edit
+/// distance.
+///
+/// Returns a vector aligned with `query`, holding the number of other strings within `max_distance`
+/// of each string. The counts are the same as those of the rows and columns of
+/// [`get_neighbors_within`] combined, but the hits are tallied as they are verified, without ever
+/// being stored as pairs.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::get_neighbor_counts_within;
+///
+/// let query = ["fizz", "fuzz", "buzz", "xxxx"];
+///
+/// assert_eq!(get_neighbor_counts_within(&query, 1).unwrap(), vec![1, 2, 1, 0]);
+/// assert_eq!(get_neighbor_counts_within(&query, 2).unwrap(), vec![2, 2, 2, 0]);
+/// ```
+pub fn get_neighbor_counts_within(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<Vec<u32>, Error> {
+    get_neighbor_counts_within_with_options(query, max_distance, &SearchOptions::default())
+}
+
+/// Equivalent to [`get_neighbor_counts_within`], with additional [`SearchOptions`].
+///
+/// Since the hits are tallied once all candidates are deduplicated,
+/// [`SearchOptions::fuse_verification`] has no effect here.
+pub fn get_neighbor_counts_within_with_options(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<Vec<u32>, Error> {
+    if query.len() > u32::MAX as usize {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: u32::MAX as usize,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+
+    get_neighbors_within_validated(
+        query,
+        max_distance,
+        options,
+        None,
+        Collect::Counts { symmetric: true },
+    )
+    .map(Hits::into_counts)
+}
+
+/// Greedily deduplicate an input collection of strings up to a threshold edit distance, returning
+/// the indices of the strings kept as representatives, in ascending order.
+///
+/// The strings are visited in the order of their indices, and each is kept unless it lies within
+/// `max_distance` of a string that has already been kept, such that the lowest index always wins.
+/// A string that has been dropped never causes another to be dropped, so that this is not the
+/// transitive closure of the neighbor relation: if `a` and `b`, and `b` and `c` are neighbors, but
+/// `a` and `c` are not, then `a` and `c` are both kept. Every string that is not kept lies within
+/// `max_distance` of a representative (see [`get_representatives_within`]), and no two
+/// representatives do.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::deduplicate_within;
+///
+/// let query = ["fizz", "fuzz", "buzz", "fizz"];
+///
+/// assert_eq!(deduplicate_within(&query, 0).unwrap(), vec![0, 1, 2]);
+/// assert_eq!(deduplicate_within(&query, 1).unwrap(), vec![0, 2]);
+/// assert_eq!(deduplicate_within(&query, 2).unwrap(), vec![0]);
+/// ```
+pub fn deduplicate_within(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<Vec<u32>, Error> {
+    deduplicate_within_with_options(query, max_distance, &SearchOptions::default())
+}
+
+/// Equivalent to [`deduplicate_within`], with additional [`SearchOptions`].
+pub fn deduplicate_within_with_options(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<Vec<u32>, Error> {
+    let representatives = get_representatives_within_with_options(query, max_distance, options)?;
+    Ok((0..representatives.len() as u32)
+        .filter(|&idx| representatives[idx as usize] == idx)
+        .collect())
+}
+
+/// Greedily deduplicate an input collection of strings up to a threshold edit distance as
+/// [`deduplicate_within`] does, returning the index of the representative of each string.
+///
+/// Returns a vector aligned with `query`, holding the index of each string kept as a
+/// representative itself, and for every other string the lowest index of a representative within
+/// `max_distance` of it.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::get_representatives_within;
+///
+/// // "fizz" and "buzz" are too far apart to be merged through "fuzz".
+/// let query = ["fizz", "fuzz", "buzz", "fizz"];
+///
+/// assert_eq!(get_representatives_within(&query, 1).unwrap(), vec![0, 0, 2, 0]);
+/// ```
+pub fn get_representatives_within(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<Vec<u32>, Error> {
+    get_representatives_within_with_options(query, max_distance, &SearchOptions::default())
+}
+
+/// Equivalent to [`get_representatives_within`], with additional [`SearchOptions`].
+///
+/// Representatives are picked among the hits that the options let through, so that e.g. with
+/// [`SearchOptions::min_distance`] above 0, identical strings are never merged.
+pub fn get_representatives_within_with_options(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<Vec<u32>, Error> {
+    if query.len() > u32::MAX as usize {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: u32::MAX as usize,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+
+    let hits = get_neighbors_within_validated(
+        query,
+        max_distance,
+        &options.for_representatives(),
+        None,
+        Collect::Pairs,
+    )?
+    .into_pairs();
+    Ok(get_representatives(&hits, query.len()))
+}
+
+/// Greedily pick the representatives of `num_strings` strings given the `hits` between them,
+/// which are sorted by (row, column) with each row smaller than its column (see
+/// [`get_representatives_within`]).
+fn get_representatives(hits: &NeighborPairs, num_strings: usize) -> Vec<u32> {
+    let mut representatives = vec![u32::MAX; num_strings];
+    let mut pairs = hits.row.iter().zip(&hits.col).peekable();
+    for idx in 0..num_strings as u32 {
+        // All neighbors with lower indices have been visited, so a string not claimed by any of
+        // them is kept.
+        if representatives[idx as usize] == u32::MAX {
+            representatives[idx as usize] = idx;
+        }
+        let is_kept = representatives[idx as usize] == idx;
+        for (_, &col) in pairs.peeking_take_while(|&(&row, _)| row == idx) {
+            if is_kept && representatives[col as usize] == u32::MAX {
+                representatives[col as usize] = idx;
+            }
+        }
+    }
+    representatives
+}
+
+/// Detect string pairs within an input collection that lie within a threshold edit distance, and
+/// pass each of them to a callback rather than collecting them.
+///
+/// The callback `f` receives the same (row, column, distance) triplets that
+/// [`get_neighbors_within`] would return, but without them ever being gathered into a
+/// [`NeighborPairs`], so that hits can be streamed to a writer or a channel as they are found. The
+/// callback is invoked from multiple threads at once, so it must be thread-safe, and the order in
+/// which hits are passed to it is unspecified.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Mutex;
+/// use symscan::for_each_neighbor_within;
+///
+/// let hits = Mutex::new(Vec::new());
+/// for_each_neighbor_within(&["fizz", "fuzz", "buzz"], 1, |row, col, dist| {
+///     hits.lock().unwrap().push((row, col, dist));
+/// })
+/// .unwrap();
+///
+/// let mut hits = hits.into_inner().unwrap();
+/// hits.sort();
+/// assert_eq!(hits, vec![(0, 1, 1), (1, 2, 1)]);
+/// ```
+pub fn for_each_neighbor_within(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    f: impl Fn(u32, u32, u8) + Sync,
+) -> Result<(), Error> {
+    for_each_neighbor_within_with_options(query, max_distance, f, &SearchOptions::default())
+}
+
+/// Equivalent to [`for_each_neighbor_within`], with additional [`SearchOptions`].
+///
+/// Since hits are only passed on once all candidates are deduplicated,
+/// [`SearchOptions::fuse_verification`] has no effect here.
+pub fn for_each_neighbor_within_with_options(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    f: impl Fn(u32, u32, u8) + Sync,
+    options: &SearchOptions,
+) -> Result<(), Error> {
+    if query.len() > u32::MAX as usize {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: u32::MAX as usize,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+
+    get_neighbors_within_validated(query, max_distance, options, None, Collect::ForEach(&f))?;
+    Ok(())
+}
+
+/// Detect string pairs within an input collection whose normalized similarity is at least
+/// `min_ratio`.
+///
+/// The normalized similarity of two strings is `1 - dist / max(len_a, len_b)`, where `dist` is
+/// their Levenshtein distance (see [`SimilarPairs`]). Since it tolerates more edits between longer
+/// strings, this is better suited than an absolute `max_distance` to collections of strings that
+/// vary a lot in length. Internally, the collection is searched as in [`get_neighbors_within`] at
+/// the largest distance that the longest strings could be apart while still being similar
+/// enough, after which the pairs are filtered by their similarity. A `min_ratio` of 1 therefore
+/// finds identical strings only.
+///
+/// # Errors
+///
+/// In addition to the errors of [`get_neighbors_within`], returns [`Error::InvalidRatio`] if
+/// `min_ratio` does not lie between 0 and 1, and [`Error::RatioTooLow`] if it would require
+/// searching at a `max_distance` beyond the supported maximum.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_within_ratio, SimilarPairs};
+///
+/// let query = ["fizz", "fuzz", "fizzbuzz", "fuzzbuzz"];
+/// let SimilarPairs { row, col, similarities } = get_neighbors_within_ratio(&query, 0.8).unwrap();
+///
+/// assert_eq!(row,          vec![2]);
+/// assert_eq!(col,          vec![3]);
+/// assert_eq!(similarities, vec![0.875]);
+/// ```
+pub fn get_neighbors_within_ratio(
+    query: &[impl AsRef<str> + Sync],
+    min_ratio: f32,
+) -> Result<SimilarPairs, Error> {
+    if query.len() > u32::MAX as usize {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: u32::MAX as usize,
+        });
+    }
+    let max_len = query.iter().map(|s| s.as_ref().len()).max().unwrap_or(0);
+    let max_distance = get_max_distance_for_ratio(min_ratio, max_len)?;
+    check_strings_ascii(query, InputType::Query)?;
+
+    let hits = get_neighbors_within_validated(
+        query,
+        max_distance,
+        &SearchOptions::default(),
+        None,
+        Collect::Pairs,
+    )?
+    .into_pairs();
+    let len = |i: usize| query[i].as_ref().len();
+    Ok(SimilarPairs::from_hits(hits, min_ratio, len, len))
+}
+
+/// The largest distance at which two strings, the longer of which is `max_len` long, can be at
+/// least `min_ratio` similar.
+fn get_max_distance_for_ratio(min_ratio: f32, max_len: usize) -> Result<MaxDistance, Error> {
+    if !(0.0..=1.0).contains(&min_ratio) {
+        return Err(Error::InvalidRatio { got: min_ratio });
+    }
+    // Estimate the distance without rounding errors in the similarity, then correct for them.
+    let mut required = (max_len as f64 * (1.0 - min_ratio as f64)).floor() as usize;
+    while required < max_len && get_similarity(required + 1, max_len) >= min_ratio {
+        required += 1;
+    }
+    while required > 0 && get_similarity(required, max_len) < min_ratio {
+        required -= 1;
+    }
+
+    u8::try_from(required)
+        .ok()
+        .and_then(|required| MaxDistance::try_from(required).ok())
+        .ok_or(Error::RatioTooLow {
+            got: min_ratio,
+            max_len,
+            required,
+        })
+}
+
+/// The normalized similarity of two strings `dist` apart, the longer of which is `max_len` long.
+fn get_similarity(dist: usize, max_len: usize) -> f32 {
+    if max_len == 0 {
+        1.0
+    } else {
+        1.0 - dist as f32 / max_len as f32
+    }
+}
+
+/// Detect string pairs within a collection of strings that lie within a threshold edit distance,
+/// along with the edits between them.
+///
+/// This is equivalent to [`get_neighbors_within`], except that each pair is additionally aligned
+/// to find a cheapest sequence of edits that turns the row string into the column string (see
+/// [`AlignedNeighborPairs`]). Only the pairs that are returned are aligned, but aligning is
+/// considerably more expensive than computing a bounded distance, so this is best reserved for
+/// searches that return few pairs per string.
+///
+/// Under [`SearchOptions::case_insensitive`], the edits are those between the lowercased strings.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_within_aligned, AlignedNeighborPairs, EditOp};
+///
+/// let AlignedNeighborPairs { row, col, edit_ops, .. } =
+///     get_neighbors_within_aligned(&["fizz", "fuzz", "fuzzy"], 1).unwrap();
+///
+/// assert_eq!(row, vec![0, 1]);
+/// assert_eq!(col, vec![1, 2]);
+/// assert_eq!(
+///     edit_ops,
+///     vec![
+///         vec![EditOp::Substitute { src_pos: 1, dest_pos: 1 }],
+///         vec![EditOp::Insert { src_pos: 4, dest_pos: 4 }],
+///     ]
+/// );
+/// ```
+pub fn get_neighbors_within_aligned(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<AlignedNeighborPairs, Error> {
+    get_neighbors_within_aligned_with_options(query, max_distance, &SearchOptions::default())
+}
+
+/// Equivalent to [`get_neighbors_within_aligned`], with additional [`SearchOptions`].
+pub fn get_neighbors_within_aligned_with_options(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<AlignedNeighborPairs, Error> {
+    let hits = get_neighbors_within_with_options(query, max_distance, options)?;
+    let string = |i: usize| query[i].as_ref();
+    Ok(AlignedNeighborPairs::from_hits(
+        hits, string, string, options,
+    ))
+}
+
+/// Detect string pairs across two collections of strings that lie within a threshold edit
+/// distance, along with the edits between them.
+///
+/// This is equivalent to [`get_neighbors_across`], except that each pair is additionally aligned
+/// as in [`get_neighbors_within_aligned`], such that the edits turn each query string into the
+/// reference string it is paired with.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_across_aligned, AlignedNeighborPairs, EditOp};
+///
+/// let AlignedNeighborPairs { row, col, edit_ops, .. } =
+///     get_neighbors_across_aligned(&["fizz", "buzz"], &["fuzz", "uzz"], 1).unwrap();
+///
+/// assert_eq!(row, vec![0, 1, 1]);
+/// assert_eq!(col, vec![0, 0, 1]);
+/// assert_eq!(
+///     edit_ops,
+///     vec![
+///         vec![EditOp::Substitute { src_pos: 1, dest_pos: 1 }],
+///         vec![EditOp::Substitute { src_pos: 0, dest_pos: 0 }],
+///         vec![EditOp::Delete { src_pos: 0, dest_pos: 0 }],
+///     ]
+/// );
+/// ```
+pub fn get_neighbors_across_aligned(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<AlignedNeighborPairs, Error> {
+    get_neighbors_across_aligned_with_options(
+        query,
+        reference,
+        max_distance,
+        &SearchOptions::default(),
+    )
+}
+
+/// Equivalent to [`get_neighbors_across_aligned`], with additional [`SearchOptions`].
+pub fn get_neighbors_across_aligned_with_options(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<AlignedNeighborPairs, Error> {
+    let hits = get_neighbors_across_with_options(query, reference, max_distance, options)?;
+    Ok(AlignedNeighborPairs::from_hits(
+        hits,
+        |i| query[i].as_ref(),
+        |i| reference[i].as_ref(),
+        options,
+    ))
+}
+
+/// Detect string pairs within a collection of strings that lie within a threshold edit distance,
+/// where the collection may hold more strings than [`get_neighbors_within`] supports.
+///
+/// Inputs of up to 2,147,483,647 ((2^31)-1) strings are searched exactly as
+/// [`get_neighbors_within`] would, and only have their indices widened to [`u64`]s. Larger inputs
+/// are split into chunks of that many strings, each of which is searched within itself as well
+/// as across every later chunk (as in [`get_neighbors_across_large`]). The pairs are returned in
+/// the requested [`SearchOptions::order`] either way, including both orientations of each pair
+/// under [`SearchOptions::symmetric`].
+///
+/// # Errors
+///
+/// Returns the errors of [`get_neighbors_within`], except that [`Error::TooManyStrings`] is only
+/// returned if the input needs to be split into chunks while [`SearchOptions::pair_filter`] or
+/// [`SearchOptions::excluded_pairs`] is set, as these cannot address strings beyond the
+/// [`u32`] range.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_within_large, LargeNeighborPairs};
+///
+/// let LargeNeighborPairs { row, col, dists } =
+///     get_neighbors_within_large(&["fizz", "fuzz", "buzz"], 2).unwrap();
+///
+/// assert_eq!(row,   vec![0, 0, 1]);
+/// assert_eq!(col,   vec![1, 2, 2]);
+/// assert_eq!(dists, vec![1, 2, 1]);
+/// ```
+pub fn get_neighbors_within_large(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<LargeNeighborPairs, Error> {
+    get_neighbors_within_large_with_options(query, max_distance, &SearchOptions::default())
+}
+
+/// Equivalent to [`get_neighbors_within_large`], with additional [`SearchOptions`].
+pub fn get_neighbors_within_large_with_options(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<LargeNeighborPairs, Error> {
+    get_neighbors_within_chunked(query, max_distance, options, CrossIndex::MAX)
+}
+
+/// Implementation of [`get_neighbors_within_large_with_options`], splitting inputs of more than
+/// `chunk_len` strings into chunks of (at most) `chunk_len` strings.
+fn get_neighbors_within_chunked(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+    chunk_len: usize,
+) -> Result<LargeNeighborPairs, Error> {
+    if query.len() <= chunk_len {
+        return get_neighbors_within_with_options(query, max_distance, options)
+            .map(LargeNeighborPairs::from);
+    }
+    if options.pair_filter.is_some() || options.excluded_pairs.is_some() {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: chunk_len,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+    check_del_var_counts(query, max_distance, options.anchoring(), InputType::Query)?;
+
+    // The chunks are searched for each pair once, in (row, column) order, and the hits are
+    // mirrored and reordered as requested once they have all been collected.
+    let chunk_options = SearchOptions {
+        order: match options.order {
+            OutputOrder::Unordered => OutputOrder::Unordered,
+            _ => OutputOrder::ByRowThenCol,
+        },
+        symmetric: false,
+        ..options.clone()
+    };
+    let chunks = query.chunks(chunk_len).collect_vec();
+    let mut triplets = Vec::new();
+    for (i, &chunk) in chunks.iter().enumerate() {
+        let offset = (i * chunk_len) as u64;
+        let hits = get_neighbors_within_validated(
+            chunk,
+            max_distance,
+            &chunk_options,
+            None,
+            Collect::Pairs,
+        )?
+        .into_pairs();
+        triplets.extend(
+            itertools::izip!(hits.row, hits.col, hits.dists)
+                .map(|(row, col, dist)| (row as u64 + offset, col as u64 + offset, dist)),
+        );
+        for (j, &later_chunk) in chunks.iter().enumerate().skip(i + 1) {
+            let later_offset = (j * chunk_len) as u64;
+            let hits = get_neighbors_across_validated(
+                chunk,
+                later_chunk,
+                max_distance,
+                &chunk_options,
+                Collect::Pairs,
+            )?
+            .into_pairs();
+            triplets
+                .extend(itertools::izip!(hits.row, hits.col, hits.dists).map(
+                    |(row, col, dist)| (row as u64 + offset, col as u64 + later_offset, dist),
+                ));
+        }
+    }
+    if options.symmetric {
+        let num_pairs = triplets.len();
+        triplets.reserve(num_pairs);
+        for idx in 0..num_pairs {
+            let (row, col, dist) = triplets[idx];
+            triplets.push((col, row, dist));
+        }
+    }
+    match options.order {
+        OutputOrder::ByRowThenCol => triplets.par_sort_unstable(),
+        OutputOrder::ByDistance => {
+            triplets.par_sort_unstable_by_key(|&(row, col, dist)| (dist, row, col))
+        }
+        OutputOrder::Unordered => (),
+    }
+
+    Ok(LargeNeighborPairs::from_triplets(triplets))
+}
+
+/// Enumerate the candidate pairs that [`get_neighbors_within`] would verify, without computing
+/// any of their distances.
+///
+/// These are the pairs of strings `(i, j)`, with `i < j`, that share a deletion variant of up to
+/// `max_distance` deletions, sorted and deduplicated. They are a superset of the true hits: pairs
+/// whose distance exceeds `max_distance` can share a deletion variant, and pairs may also converge
+/// through a collision of variant hashes. Inputs small enough to be compared directly (see
+/// [`SearchOptions::brute_force_max_pairs`]) yield all of their pairs as candidates.
+///
+/// This makes the candidates suitable for scoring with a function other than edit distance, as
+/// long as it does not admit pairs that edit distance would rule out. The default pipeline can be
+/// reassembled by passing them to [`compute_distances`] and keeping the pairs within
+/// `max_distance`.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{compute_distances, get_candidate_pairs_within};
+///
+/// let query = ["fizz", "fuzz", "buzz"];
+/// let candidates = get_candidate_pairs_within(&query, 1).unwrap();
+/// let dists = compute_distances(&candidates, &query, &query, 1).unwrap();
+/// let hits = candidates
+///     .into_iter()
+///     .zip(dists)
+///     .filter(|&(_, dist)| dist <= 1)
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(hits, vec![((0, 1), 1), ((1, 2), 1)]);
+/// ```
+pub fn get_candidate_pairs_within(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<Vec<(u32, u32)>, Error> {
+    get_candidate_pairs_within_with_options(query, max_distance, &SearchOptions::default())
+}
+
+/// Equivalent to [`get_candidate_pairs_within`], with additional [`SearchOptions`].
+///
+/// Options that only affect verification, such as [`SearchOptions::metric`] or
+/// [`SearchOptions::pair_filter`], have no effect here.
+pub fn get_candidate_pairs_within_with_options(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<Vec<(u32, u32)>, Error> {
+    if query.len() > u32::MAX as usize {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: u32::MAX as usize,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+
+    get_neighbors_within_validated(query, max_distance, options, None, Collect::Candidates)
+        .map(Hits::into_candidates)
+}
+
+/// Enumerate the candidate pairs that [`get_neighbors_across`] would verify, without computing
+/// any of their distances.
+///
+/// These are the pairs of query and reference indices that share a deletion variant of up to
+/// `max_distance` deletions, sorted and deduplicated. As with [`get_candidate_pairs_within`], they
+/// are a superset of the true hits, which may contain pairs beyond `max_distance` as well as
+/// pairs converging through hash collisions.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{compute_distances, get_candidate_pairs_across, NeighborPairs};
+///
+/// let query = ["fizz", "fuzz", "buzz"];
+/// let reference = ["fooo", "barr", "bazz", "buzz"];
+/// let candidates = get_candidate_pairs_across(&query, &reference, 1).unwrap();
+/// let dists = compute_distances(&candidates, &query, &reference, 1).unwrap();
+///
+/// let mut hits = NeighborPairs::default();
+/// for ((row, col), dist) in candidates.into_iter().zip(dists) {
+///     if dist <= 1 {
+///         hits.row.push(row);
+///         hits.col.push(col);
+///         hits.dists.push(dist);
+///     }
+/// }
+///
+/// assert_eq!(hits.row,   vec![1, 2, 2]);
+/// assert_eq!(hits.col,   vec![3, 2, 3]);
+/// assert_eq!(hits.dists, vec![1, 1, 0]);
+/// ```
+pub fn get_candidate_pairs_across(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<Vec<(u32, u32)>, Error> {
+    get_candidate_pairs_across_with_options(
+        query,
+        reference,
+        max_distance,
+        &SearchOptions::default(),
+    )
+}
+
+/// Equivalent to [`get_candidate_pairs_across`], with additional [`SearchOptions`].
+///
+/// Options that only affect verification, such as [`SearchOptions::metric`] or
+/// [`SearchOptions::pair_filter`], have no effect here.
+pub fn get_candidate_pairs_across_with_options(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<Vec<(u32, u32)>, Error> {
+    if query.len() > CrossIndex::MAX {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: CrossIndex::MAX,
+        });
+    }
+    if reference.len() > CrossIndex::MAX {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Reference,
+            got: reference.len(),
+            limit: CrossIndex::MAX,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+    options.check_strings(reference, InputType::Reference)?;
+
+    get_neighbors_across_validated(query, reference, max_distance, options, Collect::Candidates)
+        .map(Hits::into_candidates)
+}
+
+/// Compute the edit distances of the given pairs of query and reference indices, such as the
+/// candidate pairs returned by [`get_candidate_pairs_within`] or [`get_candidate_pairs_across`].
+///
+/// Pairs are compared in parallel, with computations on pairs further than `max_distance` apart
+/// terminating early and reporting a distance of [255](u8::MAX). For candidate pairs within a
+/// single collection, pass the collection as both `query` and `reference`.
+///
+/// # Errors
+///
+/// Returns [`Error::MaxDistCapped`] if `max_distance` is 255, and [`Error::NonAsciiInput`] if
+/// either collection contains non-ASCII data.
+///
+/// # Panics
+///
+/// Panics if any pair holds an index out of bounds of `query` or `reference`.
+pub fn compute_distances(
+    pairs: &[(u32, u32)],
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<Vec<u8>, Error> {
+    compute_distances_with_options(
+        pairs,
+        query,
+        reference,
+        max_distance,
+        &SearchOptions::default(),
+    )
+}
+
+/// Equivalent to [`compute_distances`], with additional [`SearchOptions`].
+///
+/// Pairs rejected by [`SearchOptions::pair_filter`] are reported as [255](u8::MAX) without being
+/// compared. Options that only affect which pairs are reported, such as
+/// [`SearchOptions::min_distance`], have no effect here.
+pub fn compute_distances_with_options(
+    pairs: &[(u32, u32)],
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<Vec<u8>, Error> {
+    if let Some(pool) = options.outside_thread_pool() {
+        return pool.install(|| {
+            compute_distances_with_options(pairs, query, reference, max_distance, options)
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+    options.check_strings(reference, InputType::Reference)?;
+
+    let dists = if options.case_insensitive {
+        compute_dists(
+            pairs,
+            &KnownDists::default(),
+            &fold_case(query),
+            &fold_case(reference),
+            max_distance,
+            options,
+        )
+    } else {
+        compute_dists(
+            pairs,
+            &KnownDists::default(),
+            query,
+            reference,
+            max_distance,
+            options,
+        )
+    };
+    options.check_cancelled()?;
+
+    Ok(dists)
+}
+
+/// Compute the edit distances of the given pairs of query and reference indices, reporting
+/// [`None`] for pairs further than `max_distance` apart.
+///
+/// This is a bounds-checked counterpart to [`compute_distances`], for when the pairs of interest
+/// are already known (e.g. from a join on metadata) and need only be verified.
+///
+/// # Errors
+///
+/// Returns [`Error::PairOutOfRange`] if any pair holds an index out of bounds of `query` or
+/// `reference`, [`Error::MaxDistCapped`] if `max_distance` is 255, and [`Error::NonAsciiInput`]
+/// if either collection contains non-ASCII data.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{distances_for_pairs, Error};
+///
+/// let query = ["fizz", "fuzz", "buzz"];
+/// let reference = ["fooo", "fizz"];
+///
+/// assert_eq!(
+///     distances_for_pairs(&[(0, 1), (1, 1), (2, 0)], &query, &reference, 1).unwrap(),
+///     vec![Some(0), Some(1), None]
+/// );
+/// assert!(matches!(
+///     distances_for_pairs(&[(0, 2)], &query, &reference, 1),
+///     Err(Error::PairOutOfRange { idx: 0, .. })
+/// ));
+/// ```
+pub fn distances_for_pairs(
+    pairs: &[(u32, u32)],
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<Vec<Option<u8>>, Error> {
+    distances_for_pairs_with_options(
+        pairs,
+        query,
+        reference,
+        max_distance,
+        &SearchOptions::default(),
+    )
+}
+
+/// Equivalent to [`distances_for_pairs`], with additional [`SearchOptions`].
+///
+/// As with [`compute_distances_with_options`], pairs rejected by [`SearchOptions::pair_filter`]
+/// are reported as [`None`] without being compared.
+pub fn distances_for_pairs_with_options(
+    pairs: &[(u32, u32)],
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<Vec<Option<u8>>, Error> {
+    check_pairs(pairs, query.len(), reference.len())?;
+    compute_distances_with_options(pairs, query, reference, max_distance, options)
+        .map(into_bounded_dists)
+}
+
+/// Check that every pair indexes into query and reference collections of the given lengths.
+fn check_pairs(pairs: &[(u32, u32)], query_len: usize, reference_len: usize) -> Result<(), Error> {
+    match pairs
+        .iter()
+        .position(|&(qi, ri)| qi as usize >= query_len || ri as usize >= reference_len)
+    {
+        Some(idx) => Err(Error::PairOutOfRange {
+            idx,
+            pair: pairs[idx],
+            query_len,
+            reference_len,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Map the distances of pairs beyond the threshold, reported as [255](u8::MAX), to [`None`].
+fn into_bounded_dists(dists: Vec<u8>) -> Vec<Option<u8>> {
+    dists
+        .into_iter()
+        .map(|d| (d != u8::MAX).then_some(d))
+        .collect()
+}
+
+/// Compute the Levenshtein distances between the aligned string pairs `query[i]` and
+/// `reference[i]`.
+///
+/// Unlike [`get_neighbors_across`], this does not search for neighbors but simply computes the
+/// distance of each given pair, in parallel. If `score_cutoff` is set, distances greater than it
+/// are reported as `score_cutoff + 1`, which allows computations on dissimilar pairs to terminate
+/// early.
+///
+/// # Errors
+///
+/// Returns [`Error::MismatchedLengths`] if `query` and `reference` are of different lengths, and
+/// [`Error::NonAsciiInput`] if either contains non-ASCII data.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::pairwise_distances;
+///
+/// let query = ["fizz", "fuzz", "buzz"];
+/// let reference = ["fizz", "fooo", "fuzzy"];
+///
+/// assert_eq!(pairwise_distances(&query, &reference, None).unwrap(), vec![0, 3, 2]);
+/// assert_eq!(pairwise_distances(&query, &reference, Some(1)).unwrap(), vec![0, 2, 2]);
+/// ```
+pub fn pairwise_distances(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    score_cutoff: Option<usize>,
+) -> Result<Vec<usize>, Error> {
+    if query.len() != reference.len() {
+        return Err(Error::MismatchedLengths {
+            query: query.len(),
+            reference: reference.len(),
+        });
+    }
+    check_strings_ascii(query, InputType::Query)?;
+    check_strings_ascii(reference, InputType::Reference)?;
+
+    Ok(query
+        .par_iter()
+        .zip(reference.par_iter())
+        .with_min_len(100000)
+        .map(|(q, r)| {
+            let (q, r) = (q.as_ref(), r.as_ref());
+            // The distance between two strings never exceeds the length of the longer one.
+            let cutoff = score_cutoff.unwrap_or(q.len().max(r.len()));
+            levenshtein::distance_with_args(
+                q.bytes(),
+                r.bytes(),
+                &levenshtein::Args::default().score_cutoff(cutoff),
+            )
+            .unwrap_or(cutoff.saturating_add(1))
+        })
+        .collect())
+}
+
+/// Generate the distinct strings obtainable by deleting at most `max_deletions` characters from
+/// `input`, starting with `input` itself.
+///
+/// These are the deletion variants that symscan compares to discover neighbors. Each distinct
+/// variant is yielded exactly once, even where it can be obtained by deleting different
+/// characters (such as either `s` in `"grass"`). ASCII input is treated as bytes, and any other
+/// input as Unicode characters (as with [`SearchOptions::unicode`]), with the variants yielded as
+/// their UTF-8 encodings.
+///
+/// # Errors
+///
+/// Returns [`Error::MaxDistCapped`] if `max_deletions` is 255.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::deletion_variants;
+///
+/// let variants = deletion_variants("abb", 1).unwrap().collect::<Vec<_>>();
+///
+/// assert_eq!(variants, [&b"abb"[..], b"bb", b"ab"]);
+/// ```
+pub fn deletion_variants(input: &str, max_deletions: u8) -> Result<DeletionVariants<'_>, Error> {
+    let max_deletions = MaxDistance::try_from(max_deletions)?;
+    let char_starts = input
+        .char_indices()
+        .map(|(start, _)| start)
+        .chain([input.len()])
+        .collect_vec();
+    let num_chars = char_starts.len() - 1;
+
+    Ok(DeletionVariants {
+        input,
+        char_starts,
+        max_deletions: max_deletions.as_usize().min(num_chars),
+        deletion_indices: None,
+    })
+}
+
+/// Hash each of the distinct deletion variants of `input` generated by [`deletion_variants`], by
+/// writing its bytes to a fresh [`Hasher`] built by `hash_builder`.
+///
+/// # Errors
+///
+/// Returns [`Error::MaxDistCapped`] if `max_deletions` is 255.
+///
+/// # Examples
+///
+/// ```
+/// use std::hash::{BuildHasher, Hasher};
+/// use std::collections::hash_map::RandomState;
+/// use symscan::deletion_variant_hashes;
+///
+/// let hash_builder = RandomState::new();
+/// let hashes = deletion_variant_hashes("abb", 1, &hash_builder).unwrap().collect::<Vec<_>>();
+///
+/// let mut hasher = hash_builder.build_hasher();
+/// hasher.write(b"bb");
+/// assert_eq!(hashes[1], hasher.finish());
+/// assert_eq!(hashes.len(), 3);
+/// ```
+pub fn deletion_variant_hashes<'a>(
+    input: &'a str,
+    max_deletions: u8,
+    hash_builder: &'a impl BuildHasher,
+) -> Result<impl Iterator<Item = u64> + 'a, Error> {
+    Ok(deletion_variants(input, max_deletions)?.map(|variant| {
+        let mut hasher = hash_builder.build_hasher();
+        hasher.write(&variant);
+        hasher.finish()
+    }))
+}
+
+/// An iterator over the distinct deletion variants of a string, created by
+/// [`deletion_variants`].
+///
+/// Variants are generated in order of the number of characters deleted, and then in
+/// lexicographic order of the positions deleted. Of the sets of positions whose deletion gives
+/// the same variant, only the one that keeps the leftmost possible characters is used: that is,
+/// sets where a deleted character is followed by an equal kept one are skipped.
+#[derive(Clone, Debug)]
+pub struct DeletionVariants<'a> {
+    input: &'a str,
+    /// The byte offsets at which each character of the input starts, followed by its length.
+    char_starts: Vec<usize>,
+    max_deletions: usize,
+    /// The positions of the characters to delete for the next candidate variant, or None if the
+    /// input itself has yet to be yielded.
+    deletion_indices: Option<Vec<usize>>,
+}
+
+impl DeletionVariants<'_> {
+    fn char_at(&self, idx: usize) -> &[u8] {
+        &self.input.as_bytes()[self.char_starts[idx]..self.char_starts[idx + 1]]
+    }
+
+    /// Whether deleting the characters at `deletion_indices` keeps the leftmost possible
+    /// characters for the resulting variant, i.e. no deleted character is followed by an equal
+    /// kept one.
+    fn keeps_leftmost(&self, deletion_indices: &[usize]) -> bool {
+        let num_chars = self.char_starts.len() - 1;
+        deletion_indices.iter().enumerate().all(|(i, &idx)| {
+            // The next kept position follows the run of consecutive deleted positions from idx.
+            let run_length = deletion_indices[i..]
+                .iter()
+                .zip(idx..)
+                .take_while(|&(&deleted, position)| deleted == position)
+                .count();
+            let kept = idx + run_length;
+            kept == num_chars || self.char_at(idx) != self.char_at(kept)
+        })
+    }
+}
+
+impl<'a> Iterator for DeletionVariants<'a> {
+    type Item = Cow<'a, [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let num_chars = self.char_starts.len() - 1;
+        let Some(mut deletion_indices) = self.deletion_indices.take() else {
+            self.deletion_indices = Some(vec![0]);
+            return Some(Cow::Borrowed(self.input.as_bytes()));
+        };
+
+        while deletion_indices.len() <= self.max_deletions {
+            let keeps_leftmost = self.keeps_leftmost(&deletion_indices);
+            let variant = keeps_leftmost.then(|| {
+                let bytes = self.input.as_bytes();
+                let mut variant = Vec::with_capacity(bytes.len());
+                let mut offset = 0;
+                for &idx in &deletion_indices {
+                    variant
+                        .extend_from_slice(&bytes[self.char_starts[offset]..self.char_starts[idx]]);
+                    offset = idx + 1;
+                }
+                variant.extend_from_slice(&bytes[self.char_starts[offset]..]);
+                variant
+            });
+
+            if next_combination(&mut deletion_indices, num_chars).is_none() {
+                let num_deletions = deletion_indices.len() + 1;
+                deletion_indices.clear();
+                deletion_indices.extend(0..num_deletions);
+            }
+            if let Some(variant) = variant {
+                self.deletion_indices = Some(deletion_indices);
+                return Some(Cow::Owned(variant));
+            }
+        }
+
+        self.deletion_indices = Some(deletion_indices);
+        None
+    }
+}
+
+/// Detect string pairs across two input collections that lie within a threshold edit distance.
+///
+/// The function considers all string pairs in the cartesian product of `query` and `reference`,
+/// and returns all those where the two strings are no more than `max_distance` Levenshtein edit
+/// distance units apart.
+///
+/// # Errors
+///
+/// By default, only ASCII input is supported (see [`SearchOptions::unicode`] to opt into Unicode
+/// support). The function will [`Err`] with [`Error::NonAsciiInput`] if `query` or `reference` contain any
+/// non-ASCII data.
+///
+/// There are some hard limits on the sizes of the input arguments (see [`Error::TooManyStrings`],
+/// [`Error::MaxDistCapped`]). Note however that in practice, runtime or memory usage is almost
+/// certainly the limiting factor instead.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_across, NeighborPairs};
+///
+/// let query = ["fizz", "fuzz", "buzz"];
+/// let reference = ["fooo", "barr", "bazz", "buzz"];
+/// let NeighborPairs { row, col, dists } = get_neighbors_across(&query, &reference, 1).unwrap();
+///
+/// assert_eq!(row,   vec![1, 2, 2]);
+/// assert_eq!(col,   vec![3, 2, 3]);
+/// assert_eq!(dists, vec![1, 1, 0]);
+///
+/// let NeighborPairs { row, col, dists } = get_neighbors_across(&query, &reference, 2).unwrap();
+///
+/// assert_eq!(row,   vec![0, 0, 1, 1, 2, 2]);
+/// assert_eq!(col,   vec![2, 3, 2, 3, 2, 3]);
+/// assert_eq!(dists, vec![2, 2, 2, 1, 1, 0]);
+/// ```
+pub fn get_neighbors_across(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<NeighborPairs, Error> {
+    get_neighbors_across_with_options(query, reference, max_distance, &SearchOptions::default())
+}
+
+/// Equivalent to [`get_neighbors_across_with_options`], additionally returning the
+/// [`SearchStats`] of the search.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_across_with_stats, SearchOptions};
+///
+/// let query = ["fizz", "fuzz", "buzz"];
+/// let reference = ["fizz", "fuzz", "buzz"];
+/// let (hits, stats) =
+///     get_neighbors_across_with_stats(&query, &reference, 1, &SearchOptions::default()).unwrap();
+///
+/// assert_eq!(stats.num_hits, hits.len() as u64);
+/// assert!(stats.num_verified >= stats.num_hits);
+/// ```
+pub fn get_neighbors_across_with_stats(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<(NeighborPairs, SearchStats), Error> {
+    let (options, recorder) = options.recording_stats();
+    let hits = get_neighbors_across_with_options(query, reference, max_distance, &options)?;
+    let stats = recorder.finish(hits.len());
+    Ok((hits, stats))
+}
+
+/// Detect string pairs across two collections that lie within a threshold edit distance, and
+/// return the strings themselves rather than their indices.
+///
+/// Returns (query string, reference string, distance) triplets in the same order as the pairs
+/// of [`get_neighbors_across`], borrowing the strings from the inputs (see
+/// [`NeighborPairs::resolve`]).
+///
+/// # Examples
+///
+/// ```
+/// use symscan::join_across;
+///
+/// let query = ["fizz", "fuzz", "buzz"];
+/// let reference = ["fooo", "barr", "bazz", "buzz"];
+///
+/// assert_eq!(
+///     join_across(&query, &reference, 1).unwrap(),
+///     vec![("fuzz", "buzz", 1), ("buzz", "bazz", 1), ("buzz", "buzz", 0)]
+/// );
+/// ```
+pub fn join_across<'a>(
+    query: &'a [impl AsRef<str> + Sync],
+    reference: &'a [impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<Vec<(&'a str, &'a str, u8)>, Error> {
+    join_across_with_options(query, reference, max_distance, &SearchOptions::default())
+}
+
+/// Equivalent to [`join_across`], with additional [`SearchOptions`].
+pub fn join_across_with_options<'a>(
+    query: &'a [impl AsRef<str> + Sync],
+    reference: &'a [impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<Vec<(&'a str, &'a str, u8)>, Error> {
+    let hits = get_neighbors_across_with_options(query, reference, max_distance, options)?;
+    Ok(hits.resolve(query, reference).collect())
+}
+
+/// Equivalent to [`get_neighbors_across`], with additional [`SearchOptions`].
+pub fn get_neighbors_across_with_options(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<NeighborPairs, Error> {
+    if query.len() > CrossIndex::MAX {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: CrossIndex::MAX,
+        });
+    }
+    if reference.len() > CrossIndex::MAX {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Reference,
+            got: reference.len(),
+            limit: CrossIndex::MAX,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+    options.check_strings(reference, InputType::Reference)?;
+
+    get_neighbors_across_validated(query, reference, max_distance, options, Collect::Pairs)
+        .map(Hits::into_pairs)
+}
+
+/// Detect string pairs within an input collection that lie within a threshold edit distance of
+/// up to [`u16::MAX`].
+///
+/// This is equivalent to [`get_neighbors_within`], but returns the distances as [`u16`], so that
+/// thresholds beyond the cap on `max_distance` (see [`Error::MaxDistCapped`]) can be searched.
+/// Thresholds below the cap are searched via symmetric deletion as usual. Beyond it, symmetric
+/// deletion is intractable, so all pairs of strings whose lengths differ by no more than
+/// `max_distance` are compared directly instead, which takes time quadratic in the size of the
+/// input.
+///
+/// # Errors
+///
+/// As for [`get_neighbors_within`], except that [`Error::MaxDistCapped`] is never returned.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::get_neighbors_within_wide;
+///
+/// let query = ["a".repeat(300), "b".repeat(300), "a".to_string()];
+/// let hits = get_neighbors_within_wide(&query, 300).unwrap();
+///
+/// assert_eq!(hits.row, vec![0, 0, 1]);
+/// assert_eq!(hits.col, vec![1, 2, 2]);
+/// assert_eq!(hits.dists, vec![300, 299, 300]);
+/// ```
+pub fn get_neighbors_within_wide(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u16,
+) -> Result<WideNeighborPairs, Error> {
+    get_neighbors_within_wide_with_options(query, max_distance, &SearchOptions::default())
+}
+
+/// Equivalent to [`get_neighbors_within_wide`], with additional [`SearchOptions`].
+///
+/// Beyond the cap on `max_distance`, where all pairs are compared directly, only
+/// [`SearchOptions::metric`], [`SearchOptions::min_distance`], [`SearchOptions::exclude_exact`],
+/// [`SearchOptions::case_insensitive`], [`SearchOptions::unicode`], [`SearchOptions::anchor`],
+/// [`SearchOptions::short_strings`], [`SearchOptions::pair_filter`] and
+/// [`SearchOptions::thread_pool`] apply.
+pub fn get_neighbors_within_wide_with_options(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u16,
+    options: &SearchOptions,
+) -> Result<WideNeighborPairs, Error> {
+    if let Some(max_distance) = u8::try_from(max_distance).ok().filter(|&d| d < u8::MAX) {
+        return get_neighbors_within_with_options(query, max_distance, options)
+            .map(WideNeighborPairs::from);
+    }
+    if query.len() > u32::MAX as usize {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: u32::MAX as usize,
+        });
+    }
+    options.check_strings(query, InputType::Query)?;
+
+    get_neighbors_wide_direct(query, None::<&[Symbols]>, max_distance, options)
+}
+
+/// Detect string pairs across two input collections that lie within a threshold edit distance of
+/// up to [`u16::MAX`].
+///
+/// This is equivalent to [`get_neighbors_across`], but returns the distances as [`u16`], so that
+/// thresholds beyond the cap on `max_distance` (see [`Error::MaxDistCapped`]) can be searched, as
+/// described for [`get_neighbors_within_wide`].
+///
+/// # Errors
+///
+/// As for [`get_neighbors_across`], except that [`Error::MaxDistCapped`] is never returned.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::get_neighbors_across_wide;
+///
+/// let query = ["a".repeat(300)];
+/// let reference = ["b".repeat(300), "a".to_string(), "c".to_string()];
+/// let hits = get_neighbors_across_wide(&query, &reference, 299).unwrap();
+///
+/// assert_eq!(hits.col, vec![1]);
+/// assert_eq!(hits.dists, vec![299]);
+/// ```
+pub fn get_neighbors_across_wide(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u16,
+) -> Result<WideNeighborPairs, Error> {
+    get_neighbors_across_wide_with_options(
+        query,
+        reference,
+        max_distance,
+        &SearchOptions::default(),
+    )
+}
+
+/// Equivalent to [`get_neighbors_across_wide`], with additional [`SearchOptions`], which apply as
+/// described for [`get_neighbors_within_wide_with_options`].
+pub fn get_neighbors_across_wide_with_options(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u16,
+    options: &SearchOptions,
+) -> Result<WideNeighborPairs, Error> {
+    if let Some(max_distance) = u8::try_from(max_distance).ok().filter(|&d| d < u8::MAX) {
+        return get_neighbors_across_with_options(query, reference, max_distance, options)
+            .map(WideNeighborPairs::from);
+    }
+    for (got, input_type) in [
+        (query.len(), InputType::Query),
+        (reference.len(), InputType::Reference),
+    ] {
+        if got > u32::MAX as usize {
+            return Err(Error::TooManyStrings {
+                input_type,
+                got,
+                limit: u32::MAX as usize,
+            });
+        }
+    }
+    options.check_strings(query, InputType::Query)?;
+    options.check_strings(reference, InputType::Reference)?;
+
+    get_neighbors_wide_direct(query, Some(reference), max_distance, options)
+}
+
+/// Compare all pairs of strings of `query` (with those of `reference` if given, and with each other
+/// otherwise) whose lengths differ by no more than `max_distance` directly, for thresholds beyond
+/// the cap on `max_distance` (see [`get_neighbors_within_wide`]).
+fn get_neighbors_wide_direct(
+    query: &[impl AsSymbols],
+    reference: Option<&[impl AsSymbols]>,
+    max_distance: u16,
+    options: &SearchOptions,
+) -> Result<WideNeighborPairs, Error> {
+    if let Some(pool) = options.outside_thread_pool() {
+        return pool.install(|| get_neighbors_wide_direct(query, reference, max_distance, options));
+    }
+    if options.case_insensitive {
+        let reference = reference.map(fold_case);
+        return get_neighbors_wide_direct(
+            &fold_case(query),
+            reference.as_deref(),
+            max_distance,
+            &options.for_folded_strings(),
+        );
+    }
+
+    let query = query.iter().map(AsSymbols::as_symbols).collect_vec();
+    let reference = reference.map(|r| r.iter().map(AsSymbols::as_symbols).collect_vec());
+    let within = reference.is_none();
+    let reference = reference.as_deref().unwrap_or(&query);
+
+    // Reference strings ordered by length, so that those of each query string's length range can
+    // be found by binary search.
+    let mut by_len = (0..reference.len() as u32)
+        .map(|ri| (reference[ri as usize].len(), ri))
+        .collect_vec();
+    by_len.par_sort_unstable();
+
+    let max_distance_usize = max_distance as usize;
+    let rows = (0..query.len() as u32)
+        .into_par_iter()
+        .map(|qi| {
+            if options.is_cancelled() {
+                return Vec::new();
+            }
+            let a = query[qi as usize];
+            let len = a.len();
+            let start = by_len.partition_point(|&(l, _)| l + max_distance_usize < len);
+            let end = by_len.partition_point(|&(l, _)| l <= len + max_distance_usize);
+            let mut hits = by_len[start..end]
+                .iter()
+                .filter(|&&(_, ri)| !within || ri > qi)
+                .filter_map(|&(_, ri)| {
+                    let dist =
+                        options.wide_dist(qi, ri, a, reference[ri as usize], max_distance)?;
+                    Some((ri, dist))
+                })
+                .collect_vec();
+            hits.sort_unstable();
+            hits
+        })
+        .collect::<Vec<_>>();
+    options.check_cancelled()?;
+
+    let mut pairs = WideNeighborPairs::default();
+    for (qi, hits) in rows.into_iter().enumerate() {
+        for (ri, dist) in hits {
+            pairs.row.push(qi as u32);
+            pairs.col.push(ri);
+            pairs.dists.push(dist);
+        }
+    }
+    Ok(pairs)
+}
+
+/// Detect, for each query string, the `k` closest reference strings that lie within a threshold
+/// edit distance.
+///
+/// This is equivalent to [`get_neighbors_across`], except that at most `k` hits are kept per query
+/// string, preferring smaller distances and breaking ties by reference index. The hits are sorted
+/// by (row, distance), and memory use is proportional to `k` times the length of the query rather
+/// than to the total number of hits.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_across_top_k, NeighborPairs};
+///
+/// let query = ["fizz", "fuzz", "buzz"];
+/// let reference = ["fooo", "barr", "bazz", "buzz"];
+///
+/// let NeighborPairs { row, col, dists } =
+///     get_neighbors_across_top_k(&query, &reference, 2, 1).unwrap();
+///
+/// assert_eq!(row,   vec![0, 1, 2]);
+/// assert_eq!(col,   vec![2, 3, 3]);
+/// assert_eq!(dists, vec![2, 1, 0]);
+/// ```
+pub fn get_neighbors_across_top_k(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    k: usize,
+) -> Result<NeighborPairs, Error> {
+    get_neighbors_across_top_k_with_options(
+        query,
+        reference,
+        max_distance,
+        k,
+        &SearchOptions::default(),
+    )
+}
+
+/// Equivalent to [`get_neighbors_across_top_k`], with additional [`SearchOptions`].
+///
+/// Since candidates are always verified as the hits of each query string are selected,
+/// [`SearchOptions::fuse_verification`] has no effect here.
+pub fn get_neighbors_across_top_k_with_options(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    k: usize,
+    options: &SearchOptions,
+) -> Result<NeighborPairs, Error> {
+    if query.len() > CrossIndex::MAX {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: CrossIndex::MAX,
+        });
+    }
+    if reference.len() > CrossIndex::MAX {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Reference,
+            got: reference.len(),
+            limit: CrossIndex::MAX,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+    options.check_strings(reference, InputType::Reference)?;
+
+    get_neighbors_across_validated(query, reference, max_distance, options, Collect::TopK(k))
+        .map(Hits::into_pairs)
+}
+
+/// Detect, for each query string, the reference strings that lie within a threshold edit
+/// distance, grouped by query string.
+///
+/// This is equivalent to [`get_neighbors_across`], except that the hits are returned in
+/// compressed sparse row format (see [`GroupedNeighbors`]), with the neighbors of each query
+/// string sorted by distance and then by reference index. [`SearchOptions::order`] has no effect
+/// here.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_across_csr, GroupedNeighbors};
+///
+/// let query = ["fizz", "fooo", "buzz"];
+/// let reference = ["fuzz", "bazz", "buzz"];
+///
+/// let GroupedNeighbors { indptr, col, dists } =
+///     get_neighbors_across_csr(&query, &reference, 2).unwrap();
+///
+/// assert_eq!(indptr, vec![0, 3, 3, 6]);
+/// assert_eq!(col,    vec![0, 1, 2, 2, 0, 1]);
+/// assert_eq!(dists,  vec![1, 2, 2, 0, 1, 1]);
+/// ```
+pub fn get_neighbors_across_csr(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<GroupedNeighbors, Error> {
+    get_neighbors_across_csr_with_options(query, reference, max_distance, &SearchOptions::default())
+}
+
+/// Equivalent to [`get_neighbors_across_csr`], with additional [`SearchOptions`].
+pub fn get_neighbors_across_csr_with_options(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<GroupedNeighbors, Error> {
+    let options = SearchOptions {
+        order: OutputOrder::ByRowThenCol,
+        ..options.clone()
+    };
+    let hits = get_neighbors_across_with_options(query, reference, max_distance, &options)?;
+    Ok(GroupedNeighbors::from_hits(hits, query.len()))
+}
+
+/// Detect string pairs across two collections of strings that lie within a threshold edit
+/// distance, where either collection may hold more strings than [`get_neighbors_across`] supports.
+///
+/// Inputs within the limits of [`get_neighbors_across`] are searched exactly as it would, and
+/// only have their indices widened to [`u64`]s. Larger inputs are split into chunks that are each
+/// within those limits, and every query chunk is searched against every reference chunk, which
+/// repeats the deletion variant generation of each chunk once per chunk of the other collection.
+/// The pairs are returned in the requested [`SearchOptions::order`] either way.
+///
+/// # Errors
+///
+/// Returns the errors of [`get_neighbors_across`], except that [`Error::TooManyStrings`] is only
+/// returned if the inputs need to be split into chunks while [`SearchOptions::pair_filter`] or
+/// [`SearchOptions::excluded_pairs`] is set, as these cannot address strings beyond the
+/// [`u32`] range.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::{get_neighbors_across_large, LargeNeighborPairs};
+///
+/// let query = ["fizz", "fuzz", "buzz"];
+/// let reference = ["fooo", "barr", "bazz", "buzz"];
+///
+/// let LargeNeighborPairs { row, col, dists } =
+///     get_neighbors_across_large(&query, &reference, 1).unwrap();
+///
+/// assert_eq!(row,   vec![1, 2, 2]);
+/// assert_eq!(col,   vec![3, 2, 3]);
+/// assert_eq!(dists, vec![1, 1, 0]);
+/// ```
+pub fn get_neighbors_across_large(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<LargeNeighborPairs, Error> {
+    get_neighbors_across_large_with_options(
+        query,
+        reference,
+        max_distance,
+        &SearchOptions::default(),
+    )
+}
+
+/// Equivalent to [`get_neighbors_across_large`], with additional [`SearchOptions`].
+pub fn get_neighbors_across_large_with_options(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<LargeNeighborPairs, Error> {
+    get_neighbors_across_chunked(query, reference, max_distance, options, CrossIndex::MAX)
+}
+
+/// Implementation of [`get_neighbors_across_large_with_options`], splitting inputs of more than
+/// `chunk_len` strings into chunks of (at most) `chunk_len` strings.
+fn get_neighbors_across_chunked(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+    chunk_len: usize,
+) -> Result<LargeNeighborPairs, Error> {
+    if query.len() <= chunk_len && reference.len() <= chunk_len {
+        return get_neighbors_across_with_options(query, reference, max_distance, options)
+            .map(LargeNeighborPairs::from);
+    }
+    if options.pair_filter.is_some() || options.excluded_pairs.is_some() {
+        let (input_type, got) = if query.len() > chunk_len {
+            (InputType::Query, query.len())
+        } else {
+            (InputType::Reference, reference.len())
+        };
+        return Err(Error::TooManyStrings {
+            input_type,
+            got,
+            limit: chunk_len,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+    options.check_strings(reference, InputType::Reference)?;
+    check_del_var_counts(query, max_distance, options.anchoring(), InputType::Query)?;
+    check_del_var_counts(
+        reference,
+        max_distance,
+        options.anchoring(),
+        InputType::Reference,
+    )?;
+
+    // Each chunk is searched in (row, column) order, so that the hits of a query chunk only need
+    // to be interleaved across reference chunks.
+    let chunk_options = SearchOptions {
+        order: match options.order {
+            OutputOrder::Unordered => OutputOrder::Unordered,
+            _ => OutputOrder::ByRowThenCol,
+        },
+        ..options.clone()
+    };
+    let mut triplets = Vec::new();
+    for (q_chunk_idx, query_chunk) in query.chunks(chunk_len).enumerate() {
+        let q_offset = (q_chunk_idx * chunk_len) as u64;
+        let chunk_start = triplets.len();
+        for (r_chunk_idx, reference_chunk) in reference.chunks(chunk_len).enumerate() {
+            let r_offset = (r_chunk_idx * chunk_len) as u64;
+            let hits = get_neighbors_across_validated(
+                query_chunk,
+                reference_chunk,
+                max_distance,
+                &chunk_options,
+                Collect::Pairs,
+            )?
+            .into_pairs();
+            triplets.extend(
+                itertools::izip!(hits.row, hits.col, hits.dists)
+                    .map(|(row, col, dist)| (row as u64 + q_offset, col as u64 + r_offset, dist)),
+            );
+        }
+        if options.order == OutputOrder::ByRowThenCol {
+            triplets[chunk_start..].par_sort_unstable();
+        }
+    }
+    if options.order == OutputOrder::ByDistance {
+        triplets.par_sort_unstable_by_key(|&(row, col, dist)| (dist, row, col));
+    }
+
+    Ok(LargeNeighborPairs::from_triplets(triplets))
+}
+
+/// Count the neighbors of each query string among the reference strings that lie within a
+/// threshold edit distance.
+///
+/// Returns a vector aligned with `query`, holding the number of reference strings within
+/// `max_distance` of each query string. The counts are the same as those of the rows of
+/// [`get_neighbors_across`], but the hits are tallied as they are verified, without ever being
+/// stored as pairs.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::get_neighbor_counts_across;
+///
+/// let query = ["fizz", "fuzz", "buzz"];
+/// let reference = ["fooo", "barr", "bazz", "buzz"];
+///
+/// assert_eq!(get_neighbor_counts_across(&query, &reference, 2).unwrap(), vec![2, 2, 2]);
+/// ```
+pub fn get_neighbor_counts_across(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<Vec<u32>, Error> {
+    get_neighbor_counts_across_with_options(
+        query,
+        reference,
+        max_distance,
+        &SearchOptions::default(),
+    )
+}
+
+/// Equivalent to [`get_neighbor_counts_across`], with additional [`SearchOptions`].
+///
+/// Since the hits are tallied once all candidates are deduplicated,
+/// [`SearchOptions::fuse_verification`] has no effect here.
+pub fn get_neighbor_counts_across_with_options(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<Vec<u32>, Error> {
+    if query.len() > CrossIndex::MAX {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: CrossIndex::MAX,
+        });
+    }
+    if reference.len() > CrossIndex::MAX {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Reference,
+            got: reference.len(),
+            limit: CrossIndex::MAX,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+    options.check_strings(reference, InputType::Reference)?;
+
+    get_neighbors_across_validated(
+        query,
+        reference,
+        max_distance,
+        options,
+        Collect::Counts { symmetric: false },
+    )
+    .map(Hits::into_counts)
+}
+
+/// Detect string pairs across two collections that lie within a threshold edit distance, and pass
+/// each of them to a callback rather than collecting them.
+///
+/// This is equivalent to [`get_neighbors_across`], with hits passed to `f` as in
+/// [`for_each_neighbor_within`]. The callback must be thread-safe, and the order in which hits are
+/// passed to it is unspecified.
+pub fn for_each_neighbor_across(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    f: impl Fn(u32, u32, u8) + Sync,
+) -> Result<(), Error> {
+    for_each_neighbor_across_with_options(
+        query,
+        reference,
+        max_distance,
+        f,
+        &SearchOptions::default(),
+    )
+}
+
+/// Equivalent to [`for_each_neighbor_across`], with additional [`SearchOptions`].
+///
+/// Since hits are only passed on once all candidates are deduplicated,
+/// [`SearchOptions::fuse_verification`] has no effect here.
+pub fn for_each_neighbor_across_with_options(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    f: impl Fn(u32, u32, u8) + Sync,
+    options: &SearchOptions,
+) -> Result<(), Error> {
+    if query.len() > CrossIndex::MAX {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: CrossIndex::MAX,
+        });
+    }
+    if reference.len() > CrossIndex::MAX {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Reference,
+            got: reference.len(),
+            limit: CrossIndex::MAX,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+    options.check_strings(reference, InputType::Reference)?;
+
+    get_neighbors_across_validated(
+        query,
+        reference,
+        max_distance,
+        options,
+        Collect::ForEach(&f),
+    )?;
+    Ok(())
+}
+
+/// Screen each query string for whether it has any neighbor among the reference strings within a
+/// threshold edit distance.
+///
+/// Returns a vector aligned with `query`, which is `true` for the query strings that appear as a
+/// row of [`get_neighbors_across`]. Once a query string is found to have a neighbor, its remaining
+/// candidates are skipped over without being verified, which makes this cheaper than enumerating
+/// all of the hits.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::has_neighbor_across;
+///
+/// let query = ["fizz", "fuzz", "xxxx"];
+/// let reference = ["fooo", "barr", "bazz", "buzz"];
+///
+/// assert_eq!(has_neighbor_across(&query, &reference, 1).unwrap(), vec![false, true, false]);
+/// assert_eq!(has_neighbor_across(&query, &reference, 2).unwrap(), vec![true, true, false]);
+/// ```
+pub fn has_neighbor_across(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<Vec<bool>, Error> {
+    has_neighbor_across_with_options(query, reference, max_distance, &SearchOptions::default())
+}
+
+/// Equivalent to [`has_neighbor_across`], with additional [`SearchOptions`].
+///
+/// Since candidates are always verified as they are enumerated,
+/// [`SearchOptions::fuse_verification`] has no effect here.
+pub fn has_neighbor_across_with_options(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<Vec<bool>, Error> {
+    if query.len() > CrossIndex::MAX {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: CrossIndex::MAX,
+        });
+    }
+    if reference.len() > CrossIndex::MAX {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Reference,
+            got: reference.len(),
+            limit: CrossIndex::MAX,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+    options.check_strings(reference, InputType::Reference)?;
+
+    get_neighbors_across_validated(query, reference, max_distance, options, Collect::Flags)
+        .map(Hits::into_flags)
+}
+
+/// Flag the strings of an input collection that have no neighbors within a threshold edit
+/// distance among the other strings of the collection.
+///
+/// Returns a vector aligned with `query`, which is `true` for the strings that appear in neither
+/// the rows nor the columns of [`get_neighbors_within`]. The deletion variants of the whole
+/// collection are sorted once, after which the candidates of each string are looked up and
+/// verified in turn, stopping at its first hit (see [`CachedRef::orphan_mask`]).
+///
+/// # Examples
+///
+/// ```
+/// use symscan::orphans_within;
+///
+/// let query = ["fizz", "fuzz", "buzz", "xxxx"];
+///
+/// assert_eq!(orphans_within(&query, 1).unwrap(), vec![false, false, false, true]);
+/// assert_eq!(orphans_within(&["fizz", "buzz"], 1).unwrap(), vec![true, true]);
+/// ```
+pub fn orphans_within(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<Vec<bool>, Error> {
+    orphans_within_with_options(query, max_distance, &SearchOptions::default())
+}
+
+/// Equivalent to [`orphans_within`], with additional [`SearchOptions`].
+pub fn orphans_within_with_options(
+    query: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<Vec<bool>, Error> {
+    if query.len() > u32::MAX as usize {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: u32::MAX as usize,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+    options.check_strings(query, InputType::Query)?;
+
+    get_orphans_within_validated(query, max_distance, options)
+}
+
+fn get_orphans_within_validated(
+    query: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+) -> Result<Vec<bool>, Error> {
+    if let Some(pool) = options.outside_thread_pool() {
+        return pool.install(|| get_orphans_within_validated(query, max_distance, options));
+    }
+    search_span!(
+        "orphans_within",
+        num_query = query.len(),
+        max_distance = max_distance.as_u8()
+    );
+    check_del_var_counts(query, max_distance, options.anchoring(), InputType::Query)?;
+    options.check_excluded_pairs(query.len(), None)?;
+    if options.case_insensitive {
+        return get_orphans_within_validated(
+            &fold_case(query),
+            max_distance,
+            &options.for_folded_strings(),
+        );
+    }
+
+    let hash_builder = options.hash_builder();
+    let variant_index_pairs: Vec<VariantIndexPair<u32>> = get_sorted_vi_pairs_rawidx_into(
+        query,
+        0,
+        max_distance,
+        options.anchoring(),
+        &hash_builder,
+        None,
+        options.progress_reporter(),
+        Vec::new(),
+    );
+    options.check_cancelled()?;
+
+    let mask = get_orphan_mask(
+        query,
+        max_distance,
+        options.anchoring(),
+        &hash_builder,
+        options,
+        |hash| {
+            let start = variant_index_pairs.partition_point(|vip| vip.hash() < hash);
+            variant_index_pairs[start..]
+                .iter()
+                .take_while(move |vip| vip.hash() == hash)
+                .map(VariantIndexPair::index)
+        },
+        |qi, ri, bound| {
+            // Pairs are oriented as in [`get_neighbors_within`], with the smaller index first.
+            let (a, b) = (qi.min(ri), qi.max(ri));
+            if a == b || !options.keeps(a, b) {
+                return u8::MAX;
+            }
+            options.bounded_dist(
+                a,
+                b,
+                query[a as usize].as_symbols(),
+                query[b as usize].as_symbols(),
+                bound,
+            )
+        },
+    );
+    options.check_cancelled()?;
+    Ok(mask)
+}
+
+/// Find, for each query string, the closest reference string that lies within a threshold edit
+/// distance.
+///
+/// Returns a vector aligned with `query`, holding the (reference index, distance) of the nearest
+/// neighbor of each query string, or [`None`] if it has no neighbors within `max_distance`. Ties are
+/// broken by the lowest reference index. This is equivalent to [`get_neighbors_across_top_k`] with
+/// `k` = 1, and stops verifying the candidates of a query string as soon as an exact match is found.
+///
+/// # Examples
+///
+/// ```
+/// use symscan::get_nearest_neighbors_across;
+///
+/// let query = ["fizz", "fuzz", "buzz", "xxxx"];
+/// let reference = ["fooo", "barr", "bazz", "buzz"];
+///
+/// assert_eq!(
+///     get_nearest_neighbors_across(&query, &reference, 2).unwrap(),
+///     vec![Some((2, 2)), Some((3, 1)), Some((3, 0)), None],
+/// );
+/// ```
+pub fn get_nearest_neighbors_across(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+) -> Result<Vec<Option<(u32, u8)>>, Error> {
+    get_nearest_neighbors_across_with_options(
+        query,
+        reference,
+        max_distance,
+        &SearchOptions::default(),
+    )
+}
+
+/// Equivalent to [`get_nearest_neighbors_across`], with additional [`SearchOptions`].
+pub fn get_nearest_neighbors_across_with_options(
+    query: &[impl AsRef<str> + Sync],
+    reference: &[impl AsRef<str> + Sync],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<Vec<Option<(u32, u8)>>, Error> {
+    let hits = get_neighbors_across_top_k_with_options(query, reference, max_distance, 1, options)?;
+    Ok(get_nearest_from_top_hits(hits, query.len()))
+}
+
+/// Spread the hits of a top-1 search out into a vector with one entry per query string.
+fn get_nearest_from_top_hits(hits: NeighborPairs, query_len: usize) -> Vec<Option<(u32, u8)>> {
+    let mut nearest = vec![None; query_len];
+    for ((qi, ri), d) in hits.row.into_iter().zip(hits.col).zip(hits.dists) {
+        nearest[qi as usize] = Some((ri, d));
+    }
+    nearest
+}
+
+/// Implementation of [`get_neighbors_across_with_options`] for a query and reference that are
+/// already known to be of legal length and encoding, collecting the hits as requested by `collect`.
+fn get_neighbors_across_validated(
+    query: &[impl AsSymbols],
+    reference: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+    collect: Collect<'_>,
+) -> Result<Hits, Error> {
+    if let Some(pool) = options.outside_thread_pool() {
+        return pool.install(|| {
+            get_neighbors_across_validated(query, reference, max_distance, options, collect)
+        });
+    }
+    search_span!(
+        "get_neighbors_across",
+        num_query = query.len(),
+        num_reference = reference.len(),
+        max_distance = max_distance.as_u8()
+    );
+    check_del_var_counts(query, max_distance, options.anchoring(), InputType::Query)?;
+    check_del_var_counts(
+        reference,
+        max_distance,
+        options.anchoring(),
+        InputType::Reference,
+    )?;
+    options.check_excluded_pairs(query.len(), Some(reference.len()))?;
+    if options.order == OutputOrder::ByDistance && matches!(collect, Collect::Pairs) {
+        let hits = get_neighbors_across_validated(
+            query,
+            reference,
+            max_distance,
+            &options.for_reordering(),
+            collect,
+        )?
+        .into_pairs();
+        return Ok(Hits::Pairs(hits.into_order(options.order)));
+    }
+    if options.case_insensitive {
+        return get_neighbors_across_validated(
+            &fold_case(query),
+            &fold_case(reference),
+            max_distance,
+            &options.for_folded_strings(),
+            collect,
+        );
+    }
+    if options.deduplicate && matches!(collect, Collect::Pairs) {
+        let duplicates_q = Duplicates::find(query);
+        let duplicates_r = Duplicates::find(reference);
+        if duplicates_q.num_unique() < query.len() || duplicates_r.num_unique() < reference.len() {
+            let hits = get_neighbors_across_validated(
+                &duplicates_q.get_unique_strings(query),
+                &duplicates_r.get_unique_strings(reference),
+                max_distance,
+                &options.for_unique_strings(),
+                Collect::Pairs,
+            )?
+            .into_pairs();
+            return Ok(Hits::Pairs(Duplicates::expand_across(
+                hits,
+                &duplicates_q,
+                &duplicates_r,
+                options,
+            )));
+        }
+    }
+    if options.partition_by_length && matches!(collect, Collect::Pairs) {
+        let partition = LengthPartition::new(query, LengthPartition::target_num_blocks());
+        if partition.num_blocks() > 1 {
+            let hits = partition.search_across(query, reference, max_distance, options)?;
+            return Ok(Hits::Pairs(hits));
+        }
+    }
+
+    let num_pairs = query.len() as u128 * reference.len() as u128;
+    let num_variants = || {
+        count_del_vars(query, max_distance, options.anchoring())
+            + count_del_vars(reference, max_distance, options.anchoring())
+    };
+    if options.use_brute_force(num_pairs, num_variants) {
+        let mut candidates = (0..query.len() as u32)
+            .cartesian_product(0..reference.len() as u32)
+            .collect_vec();
+        options.remove_excluded(&mut candidates);
+        return get_true_hits(
+            &candidates,
+            &KnownDists::default(),
+            query,
+            reference,
+            max_distance,
+            options,
+            collect,
+        );
+    }
+
+    // Exact grouping compares the deletion variants themselves, which are not spilled.
+    if let Some(low_memory) = options
+        .low_memory
+        .as_ref()
+        .filter(|_| !options.exact_grouping)
+    {
+        return if options.wide_hashes {
+            get_neighbors_across_spilled::<4>(
+                query,
+                reference,
+                max_distance,
+                options,
+                low_memory,
+                collect,
+            )
+        } else if options.narrow_hashes {
+            get_neighbors_across_spilled::<1>(
+                query,
+                reference,
+                max_distance,
+                options,
+                low_memory,
+                collect,
+            )
+        } else {
+            get_neighbors_across_spilled::<2>(
+                query,
+                reference,
+                max_distance,
+                options,
+                low_memory,
+                collect,
+            )
+        };
+    }
+
+    let (convergent_indices, group_sizes) = if options.exact_grouping {
+        get_exact_convergence_groups_cross(query, reference, max_distance, options)?
+    } else if options.wide_hashes {
+        get_convergence_groups_cross::<4>(query, reference, max_distance, options)?
+    } else if options.narrow_hashes {
+        get_convergence_groups_cross::<1>(query, reference, max_distance, options)?
+    } else {
+        get_convergence_groups_cross::<2>(query, reference, max_distance, options)?
+    };
+    options
+        .progress_reporter()
+        .record_groups(group_sizes.iter().map(|(n_q, n_r)| n_q + n_r));
+
+    let mut convergent_chunks = Vec::with_capacity(group_sizes.len());
+    let mut remaining = &convergent_indices[..];
+    for (n_q, n_r) in group_sizes {
+        let (chunk_q, rest) = remaining.split_at(n_q);
+        let (chunk_r, rest) = rest.split_at(n_r);
+        convergent_chunks.push((chunk_q, chunk_r));
+        remaining = rest;
+    }
+
+    debug_assert_eq!(remaining.len(), 0);
+
+    if options.excluded_pairs.is_none() && matches!(collect, Collect::Flags) {
+        let flags = get_hit_flags(
+            &convergent_chunks,
+            |(indices_q, indices_r)| {
+                indices_q
+                    .iter()
+                    .copied()
+                    .cartesian_product(indices_r.iter().copied())
+            },
+            query.len(),
+            max_distance,
+            options,
+            |qi, ri, bound| {
+                options.bounded_dist(
+                    qi,
+                    ri,
+                    query[qi as usize].as_symbols(),
+                    reference[ri as usize].as_symbols(),
+                    bound,
+                )
+            },
+        );
+        options.check_cancelled()?;
+        return Ok(Hits::Flags(flags));
+    }
+    if options.fuse_verification
+        && options.excluded_pairs.is_none()
+        && matches!(collect, Collect::Pairs)
+    {
+        let hits = get_true_hits_fused(
+            &convergent_chunks,
+            |(indices_q, indices_r)| {
+                indices_q
+                    .iter()
+                    .copied()
+                    .cartesian_product(indices_r.iter().copied())
+            },
+            query,
+            reference,
+            max_distance,
+            options,
+        );
+        options.check_cancelled()?;
+        return Ok(Hits::Pairs(hits));
+    }
+
+    // The raw candidates are returned as they are, so must not be verified early.
+    let dist = |qi: u32, ri: u32| {
+        let (a, b) = (
+            query[qi as usize].as_symbols(),
+            reference[ri as usize].as_symbols(),
+        );
+        options.bounded_dist(qi, ri, a, b, max_distance)
+    };
+    let bounded_dist = match collect {
+        Collect::Candidates => None,
+        _ => Some(&dist as &(dyn Fn(u32, u32) -> u8 + Sync)),
+    };
+    let (mut candidates, known) =
+        get_hit_candidates_from_cis_cross(&convergent_chunks, bounded_dist, options)?;
+    options.check_cancelled()?;
+    options.remove_excluded(&mut candidates);
+    if let Collect::Candidates = collect {
+        return Ok(Hits::Candidates(candidates));
+    }
+
+    get_true_hits(
+        &candidates,
+        &known,
+        query,
+        reference,
+        max_distance,
+        options,
+        collect,
+    )
+}
+
+/// Detect string pairs across two collections of raw byte strings that lie within a threshold edit
+/// distance.
+///
+/// This is equivalent to [`get_neighbors_across`], for strings that need not be valid UTF-8 (see
+/// [`get_neighbors_within_bytes`]).
+pub fn get_neighbors_across_bytes<T: AsRef<[u8]> + Sync, U: AsRef<[u8]> + Sync>(
+    query: &[T],
+    reference: &[U],
+    max_distance: u8,
+) -> Result<NeighborPairs, Error> {
+    get_neighbors_across_bytes_with_options(
+        query,
+        reference,
+        max_distance,
+        &SearchOptions::default(),
+    )
+}
+
+/// Equivalent to [`get_neighbors_across_bytes`], with additional [`SearchOptions`].
+pub fn get_neighbors_across_bytes_with_options<T: AsRef<[u8]> + Sync, U: AsRef<[u8]> + Sync>(
+    query: &[T],
+    reference: &[U],
+    max_distance: u8,
+    options: &SearchOptions,
+) -> Result<NeighborPairs, Error> {
+    if query.len() > CrossIndex::MAX {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Query,
+            got: query.len(),
+            limit: CrossIndex::MAX,
+        });
+    }
+    if reference.len() > CrossIndex::MAX {
+        return Err(Error::TooManyStrings {
+            input_type: InputType::Reference,
+            got: reference.len(),
+            limit: CrossIndex::MAX,
+        });
+    }
+    let max_distance = MaxDistance::try_from(max_distance)?;
+
+    get_neighbors_across_validated(
+        ByteStr::wrap_slice(query),
+        ByteStr::wrap_slice(reference),
+        max_distance,
+        options,
+        Collect::Pairs,
+    )
+    .map(Hits::into_pairs)
+}
+
+/// Find the convergence groups among sorted and deduplicated (hash, index) pairs, i.e. the groups
+/// of more than one pair sharing the same hash, returning their indices back to back along with
+/// the size of each group.
+fn get_convergence_groups<const W: usize>(
+    variant_index_pairs: &[VariantIndexPair<u32, W>],
+) -> (Vec<u32>, Vec<usize>) {
+    let mut total_num_convergent_indices = 0;
+    let mut num_convergence_groups = 0;
+
+    variant_index_pairs
+        .chunk_by(|a, b| a.same_hash(b))
+        .filter(|chunk| chunk.len() > 1)
+        .for_each(|chunk| {
+            total_num_convergent_indices += chunk.len();
+            num_convergence_groups += 1;
+        });
+
+    let mut convergent_indices = Vec::with_capacity(total_num_convergent_indices);
+    let mut convergence_group_sizes = Vec::with_capacity(num_convergence_groups);
+
+    variant_index_pairs
+        .chunk_by(|a, b| a.same_hash(b))
+        .filter(|chunk| chunk.len() > 1)
+        .for_each(|chunk| {
+            convergent_indices.extend(chunk.iter().map(VariantIndexPair::index));
+            convergence_group_sizes.push(chunk.len());
+        });
+
+    (convergent_indices, convergence_group_sizes)
+}
+
+/// The convergent indices of all convergence groups across two collections back to back, along
+/// with the number of query and reference indices in each group.
+type CrossConvergenceGroups = (Vec<u32>, Vec<(usize, usize)>);
+
+/// Generate the deletion variants of the `query` and `reference` strings, hashed to `32 * W` bits,
+/// and find their convergence groups (see [`push_convergence_group_cross`]).
+fn get_convergence_groups_cross<const W: usize>(
+    query: &[impl AsSymbols],
+    reference: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+) -> Result<CrossConvergenceGroups, Error> {
+    let num_del_variants_q = get_num_del_vars_per_string(query, max_distance, options.anchoring());
+    let num_del_variants_r =
+        get_num_del_vars_per_string(reference, max_distance, options.anchoring());
+
+    let total_capacity =
+        num_del_variants_q.iter().sum::<usize>() + num_del_variants_r.iter().sum::<usize>();
+    let mut variant_index_pairs_uninit =
+        prealloc_maybeuninit_vec::<VariantIndexPair<CrossIndex, W>>(total_capacity);
+
+    let mut vip_chunks_q = Vec::with_capacity(query.len());
+    let mut remaining = &mut variant_index_pairs_uninit[..];
+    for &n in &num_del_variants_q {
+        let (chunk, rest) = remaining.split_at_mut(n);
+        vip_chunks_q.push(chunk);
+        remaining = rest;
+    }
+
+    let mut vip_chunks_r = Vec::with_capacity(reference.len());
+    for &n in &num_del_variants_r {
+        let (chunk, rest) = remaining.split_at_mut(n);
+        vip_chunks_r.push(chunk);
+        remaining = rest;
+    }
+
+    debug_assert_eq!(remaining.len(), 0);
+    debug_assert_eq!(vip_chunks_q.len(), query.len());
+    debug_assert_eq!(vip_chunks_r.len(), reference.len());
+
+    let hash_builder = options.hash_builder();
+    let progress = options.progress_reporter();
+    progress.start(Phase::VariantGeneration, query.len() + reference.len());
+
+    let num_distinct_variants_q = query
+        .par_iter()
+        .zip(vip_chunks_q.into_par_iter())
+        .enumerate()
+        .with_min_len(100000)
+        .map_init(
+            || (VariantScratch::default(), progress.batch()),
+            |(scratch, batch), (idx, (s, chunk))| {
+                let num_distinct = write_vi_pairs_ci(
+                    s.as_symbols(),
+                    CrossIndex::from(idx as u32, false),
+                    max_distance,
+                    options.anchoring(),
+                    chunk,
+                    &hash_builder,
+                    scratch,
+                );
+                batch.advance(1);
+                num_distinct
+            },
+        )
+        .collect::<Vec<_>>();
+    let num_distinct_variants_r = reference
+        .par_iter()
+        .zip(vip_chunks_r.into_par_iter())
+        .enumerate()
+        .with_min_len(100000)
+        .map_init(
+            || (VariantScratch::default(), progress.batch()),
+            |(scratch, batch), (idx, (s, chunk))| {
+                let num_distinct = write_vi_pairs_ci(
+                    s.as_symbols(),
+                    CrossIndex::from(idx as u32, true),
+                    max_distance,
+                    options.anchoring(),
+                    chunk,
+                    &hash_builder,
+                    scratch,
+                );
+                batch.advance(1);
+                num_distinct
+            },
+        )
+        .collect::<Vec<_>>();
+
+    let mut variant_index_pairs = unsafe { cast_to_initialised_vec(variant_index_pairs_uninit) };
+    compact_chunks(
+        &mut variant_index_pairs,
+        &[num_del_variants_q, num_del_variants_r].concat(),
+        &[num_distinct_variants_q, num_distinct_variants_r].concat(),
+    );
+    progress.start(Phase::Sorting, variant_index_pairs.len());
+
+    if options.merge_sorted_runs {
+        let groups = get_convergence_groups_cross_merged(
+            &mut variant_index_pairs,
+            MERGE_RUN_LEN,
+            rayon::current_num_threads() * 4,
+        );
+        progress.advance(variant_index_pairs.len());
+        options.check_cancelled()?;
+        Ok(groups)
+    } else {
+        par_radix_sort(&mut variant_index_pairs);
+        progress.advance(variant_index_pairs.len());
+        par_dedup(&mut variant_index_pairs);
+        options.check_cancelled()?;
+        progress.start_grouping(variant_index_pairs.len());
+
+        let mut total_num_convergent_indices = 0;
+        let mut num_convergence_groups = 0;
+
+        variant_index_pairs
+            .chunk_by(|a, b| a.same_hash(b))
+            .filter(|chunk| chunk.len() > 1)
+            .for_each(|chunk| {
+                total_num_convergent_indices += chunk.len();
+                num_convergence_groups += 1;
+            });
+
+        let mut convergent_indices = Vec::with_capacity(total_num_convergent_indices);
+        let mut convergence_group_sizes = Vec::with_capacity(num_convergence_groups);
+
+        variant_index_pairs
+            .chunk_by(|a, b| a.same_hash(b))
+            .for_each(|chunk| {
+                push_convergence_group_cross(
+                    chunk,
+                    &mut convergent_indices,
+                    &mut convergence_group_sizes,
+                )
+            });
+
+        Ok((convergent_indices, convergence_group_sizes))
+    }
+}
+
+/// Find the convergence groups of `query` as [`get_convergence_groups`] does, but only grouping
+/// strings that share an identical deletion variant (see [`SearchOptions::exact_grouping`]). The
+/// deletion variants are salted by `labels` as in [`get_sorted_vi_pairs_rawidx_into`].
+fn get_exact_convergence_groups_within(
+    query: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+    labels: Option<&[u32]>,
+) -> (Vec<u32>, Vec<usize>) {
+    let progress = options.progress_reporter();
+    progress.start(Phase::VariantGeneration, query.len());
+    let mut variant_ref_pairs = Vec::new();
+    extend_variant_ref_pairs(
+        &mut variant_ref_pairs,
+        query,
+        |idx| idx,
+        max_distance,
+        options.anchoring(),
+        &options.hash_builder(),
+        labels,
+        progress,
+    );
+    progress.start(Phase::Sorting, variant_ref_pairs.len());
+    variant_ref_pairs.par_sort_unstable();
+    progress.advance(variant_ref_pairs.len());
+    progress.start_grouping(variant_ref_pairs.len());
+
+    let mut convergent_indices = Vec::new();
+    let mut convergence_group_sizes = Vec::new();
+    for_each_exact_convergence_group(
+        &variant_ref_pairs,
+        |idx| query[idx as usize].as_symbols(),
+        max_distance,
+        options.anchoring(),
+        |group| {
+            convergent_indices.extend(group.iter().map(VariantIndexPair::index));
+            convergence_group_sizes.push(group.len());
+        },
+    );
+
+    (convergent_indices, convergence_group_sizes)
+}
+
+/// Find the convergence groups across `query` and `reference` as [`get_convergence_groups_cross`]
+/// does, but only grouping strings that share an identical deletion variant (see
+/// [`SearchOptions::exact_grouping`]).
+fn get_exact_convergence_groups_cross(
+    query: &[impl AsSymbols],
+    reference: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+) -> Result<CrossConvergenceGroups, Error> {
+    let hash_builder = options.hash_builder();
+    let progress = options.progress_reporter();
+    progress.start(Phase::VariantGeneration, query.len() + reference.len());
+    let mut variant_ref_pairs = Vec::new();
+    extend_variant_ref_pairs(
+        &mut variant_ref_pairs,
+        query,
+        |idx| CrossIndex::from(idx, false),
+        max_distance,
+        options.anchoring(),
+        &hash_builder,
+        None,
+        progress,
+    );
+    extend_variant_ref_pairs(
+        &mut variant_ref_pairs,
+        reference,
+        |idx| CrossIndex::from(idx, true),
+        max_distance,
+        options.anchoring(),
+        &hash_builder,
+        None,
+        progress,
+    );
+    progress.start(Phase::Sorting, variant_ref_pairs.len());
+    variant_ref_pairs.par_sort_unstable();
+    progress.advance(variant_ref_pairs.len());
+    options.check_cancelled()?;
+    progress.start_grouping(variant_ref_pairs.len());
+
+    let mut convergent_indices = Vec::new();
+    let mut convergence_group_sizes = Vec::new();
+    for_each_exact_convergence_group(
+        &variant_ref_pairs,
+        |idx| match idx.is_ref() {
+            false => query[idx.get_value() as usize].as_symbols(),
+            true => reference[idx.get_value() as usize].as_symbols(),
+        },
+        max_distance,
+        options.anchoring(),
+        |group| {
+            push_convergence_group_cross(
+                group,
+                &mut convergent_indices,
+                &mut convergence_group_sizes,
+            )
+        },
+    );
+
+    Ok((convergent_indices, convergence_group_sizes))
+}
+
+/// Split each group of sorted (hash, variant) pairs sharing the same hash into groups of pairs
+/// whose deletion variants are identical, reconstructing each variant from the string given by
+/// `symbols_of` its index. `f` is called on each such group that involves more than one string,
+/// as sorted and deduplicated (hash, index) pairs.
+fn for_each_exact_convergence_group<'a, I: Copy + Ord>(
+    variant_ref_pairs: &[VariantIndexPair<VariantRef<I>>],
+    symbols_of: impl Fn(I) -> Symbols<'a>,
+    max_distance: MaxDistance,
+    anchor: Anchoring,
+    mut f: impl FnMut(&[VariantIndexPair<I>]),
+) {
+    let mut variants = Vec::new();
+    let mut group = Vec::new();
+    for chunk in variant_ref_pairs.chunk_by(|a, b| a.same_hash(b)) {
+        if chunk.len() < 2 {
+            continue;
+        }
+        variants.clear();
+        for pair in chunk {
+            let VariantRef { index, ordinal } = pair.index();
+            let mut variant = Vec::new();
+            write_nth_variant(
+                symbols_of(index),
+                max_distance,
+                anchor,
+                ordinal as usize,
+                &mut variant,
+            );
+            variants.push((variant, index));
+        }
+        variants.sort_unstable();
+        variants.dedup();
+
+        for same_variant in variants.chunk_by(|a, b| a.0 == b.0) {
+            if same_variant.len() < 2 {
+                continue;
+            }
+            group.clear();
+            group.extend(
+                same_variant
+                    .iter()
+                    .map(|&(_, index)| VariantIndexPair::new(chunk[0].hash(), index)),
+            );
+            f(&group);
+        }
+    }
+}
+
+/// If a deduplicated group of (hash, index) pairs sharing the same hash involves strings from both
+/// the query and the reference, append its query indices followed by its reference indices to
+/// `convergent_indices`, and the number of each to `group_sizes`.
+fn push_convergence_group_cross<const W: usize>(
+    group: &[VariantIndexPair<CrossIndex, W>],
+    convergent_indices: &mut Vec<u32>,
+    group_sizes: &mut Vec<(usize, usize)>,
+) {
+    if group.len() < 2 {
+        return;
+    }
+    let len_r = group.iter().filter(|pair| pair.index().is_ref()).count();
+    let len_q = group.len() - len_r;
+    if len_q * len_r == 0 {
+        return;
+    }
+
+    convergent_indices.extend(
+        group
+            .iter()
+            .filter(|pair| !pair.index().is_ref())
+            .map(|pair| pair.index().get_value()),
+    );
+    convergent_indices.extend(
+        group
+            .iter()
+            .filter(|pair| pair.index().is_ref())
+            .map(|pair| pair.index().get_value()),
+    );
+    group_sizes.push((len_q, len_r));
+}
+
+/// The number of (hash, index) pairs sorted together in the merge pipeline of
+/// [`get_convergence_groups_cross_merged`].
+const MERGE_RUN_LEN: usize = 1 << 20;
+
+/// Find the convergence groups among (hash, index) pairs, as [`push_convergence_group_cross`] does
+/// over the sorted and deduplicated pairs, but without sorting them globally. Instead, each run of
+/// `run_len` pairs is sorted separately, and the sorted runs are merged in parallel over
+/// `num_ranges` disjoint ranges of hash values. The groups are returned in order of their hashes.
+fn get_convergence_groups_cross_merged<const W: usize>(
+    variant_index_pairs: &mut [VariantIndexPair<CrossIndex, W>],
+    run_len: usize,
+    num_ranges: usize,
+) -> (Vec<u32>, Vec<(usize, usize)>) {
+    variant_index_pairs
+        .par_chunks_mut(run_len)
+        .for_each(|run| run.sort_unstable());
+    let runs = variant_index_pairs.chunks(run_len).collect_vec();
+
+    // Hashes are uniformly distributed, so equal divisions of the hash space balance the work.
+    let num_ranges = num_ranges.max(1);
+    let range_starts = |run: &[VariantIndexPair<CrossIndex, W>], range_idx: usize| {
+        let lower = ((range_idx as u128) << 64) / num_ranges as u128;
+        u64::try_from(lower).map_or(run.len(), |lower| {
+            run.partition_point(|pair| pair.hash_prefix() < lower)
+        })
+    };
+
+    let per_range = (0..num_ranges)
+        .into_par_iter()
+        .map(|range_idx| {
+            let mut cursors = runs
+                .iter()
+                .map(|run| {
+                    let start = range_starts(run, range_idx);
+                    let end = range_starts(run, range_idx + 1);
+                    &run[start..end]
+                })
+                .collect_vec();
+
+            let mut heap = BinaryHeap::with_capacity(cursors.len());
+            for (run_idx, cursor) in cursors.iter().enumerate() {
+                if let Some(&first) = cursor.first() {
+                    heap.push(Reverse((first, run_idx)));
+                }
+            }
+
+            let mut convergent_indices = Vec::new();
+            let mut group_sizes = Vec::new();
+            let mut group: Vec<VariantIndexPair<CrossIndex, W>> = Vec::new();
+            while let Some(Reverse((pair, run_idx))) = heap.pop() {
+                cursors[run_idx] = &cursors[run_idx][1..];
+                if let Some(&next) = cursors[run_idx].first() {
+                    heap.push(Reverse((next, run_idx)));
+                }
+
+                match group.last() {
+                    Some(&last) if last == pair => continue,
+                    Some(last) if !last.same_hash(&pair) => {
+                        push_convergence_group_cross(
+                            &group,
+                            &mut convergent_indices,
+                            &mut group_sizes,
+                        );
+                        group.clear();
+                    }
+                    _ => {}
+                }
+                group.push(pair);
+            }
+            push_convergence_group_cross(&group, &mut convergent_indices, &mut group_sizes);
+
+            (convergent_indices, group_sizes)
+        })
+        .collect::<Vec<_>>();
+
+    let mut convergent_indices = Vec::with_capacity(per_range.iter().map(|(v, _)| v.len()).sum());
+    let mut group_sizes = Vec::with_capacity(per_range.iter().map(|(_, v)| v.len()).sum());
+    for (range_indices, range_sizes) in per_range {
+        convergent_indices.extend(range_indices);
+        group_sizes.extend(range_sizes);
+    }
+
+    (convergent_indices, group_sizes)
+}
+
+/// Generate the deletion variants of the `query` strings, hashed to `32 * W` bits and salted by
+/// `labels` (see [`get_sorted_vi_pairs_rawidx_into`]), and find their convergence groups (see
+/// [`get_convergence_groups`]).
+fn get_convergence_groups_within<const W: usize>(
+    query: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+    labels: Option<&[u32]>,
+) -> Result<(Vec<u32>, Vec<usize>), Error> {
+    let variant_index_pairs: Vec<VariantIndexPair<u32, W>> = get_sorted_vi_pairs_rawidx_into(
+        query,
+        0,
+        max_distance,
+        options.anchoring(),
+        &options.hash_builder(),
+        labels,
+        options.progress_reporter(),
+        Vec::new(),
+    );
+    options.check_cancelled()?;
+    options
+        .progress_reporter()
+        .start_grouping(variant_index_pairs.len());
+    Ok(get_convergence_groups(&variant_index_pairs))
+}
+
+/// Search within the `query` strings as [`get_neighbors_within_validated`] does, but sorting their
+/// deletion variants externally as configured by `low_memory` (see [`SearchOptions::low_memory`]).
+/// Each convergence group is passed on from the merge of the spilled runs as soon as it is
+/// complete, and its candidates, restricted by `keys` as in [`get_candidates_within`], are in turn
+/// spilled and verified in blocks (see [`SpilledCandidates`]). The hashes are salted by `labels` as
+/// in [`get_sorted_vi_pairs_rawidx_into`].
+fn get_neighbors_within_spilled<const W: usize>(
+    query: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+    labels: Option<&[u32]>,
+    keys: PairKeys,
+    low_memory: &LowMemory,
+    collect: Collect<'_>,
+) -> Result<Hits, Error> {
+    let progress = options.progress_reporter();
+    progress.start(Phase::VariantGeneration, query.len());
+    let mut runs = SpilledRuns::<VariantIndexPair<u32, W>>::new(low_memory);
+    let num_variants =
+        runs.spill_sorted_vi_pairs(query, |idx| idx, max_distance, options, labels)?;
+
+    progress.start(Phase::Sorting, num_variants);
+    progress.start_grouping(num_variants);
+    let mut candidates = SpilledCandidates::new(low_memory, options);
+    let mut indices = Vec::new();
+    runs.for_each_group(VariantIndexPair::same_hash, |group| {
+        if group.len() < 2 {
+            return Ok(());
+        }
+        progress.record_groups(iter::once(group.len()));
+        indices.clear();
+        indices.extend(group.iter().map(VariantIndexPair::index));
+        if !keys.is_empty() {
+            indices.sort_unstable_by_key(|&idx| (keys.get(idx), idx));
+        }
+        candidates.push_group(
+            count_candidates_within(&indices, keys),
+            (indices.len(), indices.len()),
+            get_candidates_within(&indices, keys),
+        )?;
+        options.check_cancelled()
+    })?;
+    progress.advance(num_variants);
+
+    candidates.verify(query, query, max_distance, collect)
+}
+
+/// Search the `query` strings against the `reference` strings as
+/// [`get_neighbors_across_validated`] does, but sorting their deletion variants externally and
+/// spilling the candidates of their convergence groups as [`get_neighbors_within_spilled`] does.
+fn get_neighbors_across_spilled<const W: usize>(
+    query: &[impl AsSymbols],
+    reference: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+    low_memory: &LowMemory,
+    collect: Collect<'_>,
+) -> Result<Hits, Error> {
+    let progress = options.progress_reporter();
+    progress.start(Phase::VariantGeneration, query.len() + reference.len());
+    let mut runs = SpilledRuns::<VariantIndexPair<CrossIndex, W>>::new(low_memory);
+    let num_variants_q = runs.spill_sorted_vi_pairs(
+        query,
+        |idx| CrossIndex::from(idx, false),
+        max_distance,
+        options,
+        None,
+    )?;
+    let num_variants_r = runs.spill_sorted_vi_pairs(
+        reference,
+        |idx| CrossIndex::from(idx, true),
+        max_distance,
+        options,
+        None,
+    )?;
+    let num_variants = num_variants_q + num_variants_r;
+
+    progress.start(Phase::Sorting, num_variants);
+    progress.start_grouping(num_variants);
+    let mut candidates = SpilledCandidates::new(low_memory, options);
+    let mut indices = Vec::new();
+    let mut group_sizes = Vec::new();
+    runs.for_each_group(VariantIndexPair::same_hash, |group| {
+        indices.clear();
+        group_sizes.clear();
+        push_convergence_group_cross(group, &mut indices, &mut group_sizes);
+        let Some(&(len_q, len_r)) = group_sizes.first() else {
+            return Ok(());
+        };
+        progress.record_groups(iter::once(len_q + len_r));
+        let (indices_q, indices_r) = indices.split_at(len_q);
+        candidates.push_group(
+            len_q as u128 * len_r as u128,
+            (len_q, len_r),
+            indices_q
+                .iter()
+                .copied()
+                .cartesian_product(indices_r.iter().copied()),
+        )?;
+        options.check_cancelled()
+    })?;
+    progress.advance(num_variants);
+
+    candidates.verify(query, reference, max_distance, collect)
+}
+
+/// The least number of candidate pairs spilled per run, however small the memory budget, which
+/// keeps the number of spill files in check (see [`SpilledCandidates`]).
+const SPILL_RUN_MIN_CANDIDATES: usize = 1 << 12;
+
+/// The candidate pairs of the convergence groups of a search whose deletion variants are sorted
+/// externally (see [`SearchOptions::low_memory`]). The candidates are buffered within the memory
+/// budget, and each full buffer is sorted, deduplicated and spilled as a run of its own. The runs
+/// are then merged into blocks of whole query rows, which are verified one at a time.
+struct SpilledCandidates<'a> {
+    runs: SpilledRuns<(u32, u32)>,
+    buffer: Vec<(u32, u32)>,
+    capacity: usize,
+    options: &'a SearchOptions,
+    limit: usize,
+    total_num_candidates: u128,
+    /// The number of candidates of the largest group so far, along with its size.
+    largest_group: (u128, (usize, usize)),
+}
+
+impl<'a> SpilledCandidates<'a> {
+    fn new(low_memory: &LowMemory, options: &'a SearchOptions) -> Self {
+        // Each run is sorted via a scratch buffer as large as the run itself.
+        let capacity =
+            (low_memory.budget_bytes / (2 * size_of::<(u32, u32)>())).max(SPILL_RUN_MIN_CANDIDATES);
+        Self {
+            runs: SpilledRuns::new(low_memory),
+            buffer: Vec::new(),
+            capacity,
+            options,
+            limit: options
+                .max_candidates
+                .unwrap_or(isize::MAX as usize / size_of::<(u32, u32)>()),
+            total_num_candidates: 0,
+            largest_group: (0, (0, 0)),
+        }
+    }
+
+    /// Add the `num_candidates` candidates of a convergence group of `group_size` strings. Once
+    /// there are more candidates than [`SearchOptions::max_candidates`] in all, those of the
+    /// remaining groups are only counted, so that [`SpilledCandidates::verify`] can report them.
+    fn push_group(
+        &mut self,
+        num_candidates: u128,
+        group_size: (usize, usize),
+        candidates: impl Iterator<Item = (u32, u32)>,
+    ) -> io::Result<()> {
+        // Ties go to the later group, as with Iterator::max_by_key.
+        if num_candidates >= self.largest_group.0 {
+            self.largest_group = (num_candidates, group_size);
+        }
+        self.total_num_candidates += num_candidates;
+        if self.total_num_candidates > self.limit as u128 {
+            return Ok(());
+        }
+
+        for candidate in candidates {
+            if self.buffer.len() == self.capacity {
+                self.spill_buffer()?;
+            }
+            self.buffer.push(candidate);
+        }
+        Ok(())
+    }
+
+    fn spill_buffer(&mut self) -> io::Result<()> {
+        par_radix_sort(&mut self.buffer);
+        par_dedup(&mut self.buffer);
+        self.runs.spill(&self.buffer)?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Verify the candidates of all groups, collecting the true hits as requested by `collect`
+    /// (see [`get_true_hits`]), or fail with [`Error::TooManyCandidates`] if there are too many
+    /// of them in all.
+    fn verify(
+        mut self,
+        query: &[impl AsSymbols],
+        reference: &[impl AsSymbols],
+        max_distance: MaxDistance,
+        collect: Collect<'_>,
+    ) -> Result<Hits, Error> {
+        let options = self.options;
+        if self.total_num_candidates > self.limit as u128 {
+            return Err(Error::TooManyCandidates {
+                got: self.total_num_candidates,
+                limit: self.limit,
+                largest_group: self.largest_group.1,
+            });
+        }
+        if !self.buffer.is_empty() {
+            self.spill_buffer()?;
+        }
+        // The candidates were generated as the groups were merged, so are accounted for at once.
+        let progress = options.progress_reporter();
+        progress.start(
+            Phase::CandidateGeneration,
+            self.total_num_candidates as usize,
+        );
+        progress.advance(self.total_num_candidates as usize);
+
+        let mut block = std::mem::take(&mut self.buffer);
+        let mut hits = None;
+        let verify_block = |block: &mut Vec<(u32, u32)>, hits: &mut Option<Hits>| {
+            options.remove_excluded(block);
+            let block_hits = get_true_hits(
+                block,
+                &KnownDists::default(),
+                query,
+                reference,
+                max_distance,
+                options,
+                collect,
+            )?;
+            *hits = Some(match hits.take() {
+                Some(hits) => hits.merge(block_hits),
+                None => block_hits,
+            });
+            block.clear();
+            Ok::<_, Error>(())
+        };
+        let capacity = self.capacity;
+        self.runs.for_each_group(
+            |a, b| a.0 == b.0,
+            |row| {
+                block.extend_from_slice(row);
+                if block.len() >= capacity {
+                    verify_block(&mut block, &mut hits)?;
+                }
+                Ok::<_, Error>(())
+            },
+        )?;
+        if !block.is_empty() || hits.is_none() {
+            verify_block(&mut block, &mut hits)?;
+        }
+
+        Ok(hits.expect("at least one block is verified"))
+    }
+}
+
+/// Distinguishes the spill files of the searches within this process (see [`SpilledRuns`]).
+static SPILL_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The bounds on the buffer through which each spilled run is read back during the merge.
+const SPILL_READ_BUFFER_MIN: usize = 1 << 12;
+const SPILL_READ_BUFFER_MAX: usize = 1 << 20;
+
+/// The maximum number of spilled runs merged at once, which bounds the number of open files.
+const SPILL_MERGE_FAN_IN: usize = 64;
+
+/// Sorted runs of plain data (see [`as_bytes`]) spilled to temporary files in the directory of a
+/// [`LowMemory`] configuration, which are removed when the runs are dropped.
+struct SpilledRuns<T> {
+    dir: PathBuf,
+    budget_bytes: usize,
+    paths: Vec<PathBuf>,
+    lens: Vec<usize>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: PlainData + Ord> SpilledRuns<T> {
+    fn new(low_memory: &LowMemory) -> Self {
+        Self {
+            dir: low_memory.dir.clone(),
+            budget_bytes: low_memory.budget_bytes,
+            paths: Vec::new(),
+            lens: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Create the file of a new run, which is kept track of before anything is written to it, so
+    /// that it is removed even if writing fails.
+    fn create_run(&mut self) -> io::Result<BufWriter<File>> {
+        let id = SPILL_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = self
+            .dir
+            .join(format!("symscan-{}-{id}.run", std::process::id()));
+        let file = File::create_new(&path)?;
+        self.paths.push(path);
+        Ok(BufWriter::new(file))
+    }
+
+    /// Write a sorted run to a new temporary file.
+    fn spill(&mut self, run: &[T]) -> io::Result<()> {
+        let mut writer = self.create_run()?;
+        writer.write_all(as_bytes(run))?;
+        writer.flush()?;
+        self.lens.push(run.len());
+        Ok(())
+    }
+
+    /// Start a k-way merge of the runs in `runs`.
+    fn open_runs(&self, runs: Range<usize>) -> io::Result<RunMerge<T>> {
+        let buffer_len = (self.budget_bytes / runs.len().max(1))
+            .clamp(SPILL_READ_BUFFER_MIN, SPILL_READ_BUFFER_MAX);
+        let readers = self.paths[runs.clone()]
+            .iter()
+            .zip(&self.lens[runs])
+            .map(|(path, &len)| {
+                Ok(RunReader {
+                    reader: BufReader::with_capacity(buffer_len, File::open(path)?),
+                    remaining: len,
+                    _marker: PhantomData,
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        RunMerge::new(readers)
+    }
+
+    /// Merge the oldest runs into one until at most [`SPILL_MERGE_FAN_IN`] of them remain.
+    fn reduce_fan_in(&mut self) -> io::Result<()> {
+        while self.paths.len() > SPILL_MERGE_FAN_IN {
+            let mut merge = self.open_runs(0..SPILL_MERGE_FAN_IN)?;
+            let mut writer = self.create_run()?;
+            let mut len = 0;
+            while let Some(item) = merge.next_item()? {
+                writer.write_all(as_bytes(&[item]))?;
+                len += 1;
+            }
+            writer.flush()?;
+            self.lens.push(len);
+            drop(merge);
+
+            for path in self.paths.drain(..SPILL_MERGE_FAN_IN) {
+                let _ = fs::remove_file(path);
+            }
+            self.lens.drain(..SPILL_MERGE_FAN_IN);
+        }
+        Ok(())
+    }
+
+    /// Merge the runs, calling `f` on each maximal group of consecutive distinct items for which
+    /// `same_group` holds pairwise, in sorted order, until `f` fails.
+    fn for_each_group<E: From<io::Error>>(
+        &mut self,
+        same_group: impl Fn(&T, &T) -> bool,
+        mut f: impl FnMut(&[T]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        self.reduce_fan_in()?;
+        let mut merge = self.open_runs(0..self.paths.len())?;
+
+        let mut group: Vec<T> = Vec::new();
+        while let Some(item) = merge.next_item()? {
+            if group.last().is_some_and(|last| !same_group(last, &item)) {
+                f(&group)?;
+                group.clear();
+            }
+            group.push(item);
+        }
+        if !group.is_empty() {
+            f(&group)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<I: Copy + Ord, const W: usize> SpilledRuns<VariantIndexPair<I, W>>
+where
+    VariantIndexPair<I, W>: PlainData,
+{
+    /// Generate the deletion variants of `strings` in batches that fit within the memory budget,
+    /// and spill each batch as a sorted and deduplicated run, with
+    /// the index of each string mapped by `index_of`. The hashes are salted by `labels` as in
+    /// [`get_sorted_vi_pairs_rawidx_into`]. Returns the total number of spilled pairs.
+    fn spill_sorted_vi_pairs(
+        &mut self,
+        strings: &[impl AsSymbols],
+        index_of: impl Fn(u32) -> I,
+        max_distance: MaxDistance,
+        options: &SearchOptions,
+        labels: Option<&[u32]>,
+    ) -> Result<usize, Error> {
+        let num_vars_per_string =
+            get_num_del_vars_per_string(strings, max_distance, options.anchoring());
+        let hash_builder = options.hash_builder();
+        let progress = options.progress_reporter();
+
+        let mut num_spilled = 0;
+        let mut buffer: Vec<VariantIndexPair<u32, W>> = Vec::new();
+        let mut run = Vec::new();
+        // Each run is sorted via a scratch buffer as large as the run itself.
+        for batch in get_spill_batches(
+            &num_vars_per_string,
+            2 * size_of::<VariantIndexPair<u32, W>>(),
+            self.budget_bytes,
+        ) {
+            buffer = get_sorted_vi_pairs_rawidx_into(
+                &strings[batch.clone()],
+                batch.start as u32,
+                max_distance,
+                options.anchoring(),
+                &hash_builder,
+                labels.map(|labels| &labels[batch.clone()]),
+                Progress::default(),
+                buffer,
+            );
+            progress.advance(batch.len());
+            options.check_cancelled()?;
+
+            // Mapping the indices preserves their order, so that the run remains sorted.
+            run.clear();
+            run.extend(buffer.iter().map(|pair| pair.map_index(&index_of)));
+            self.spill(&run)?;
+            num_spilled += run.len();
+        }
+
+        Ok(num_spilled)
+    }
+}
+
+impl<T> Drop for SpilledRuns<T> {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            // Nothing more can be done about a spill file that cannot be removed.
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Reads back the items of a run written by [`SpilledRuns::spill`].
+struct RunReader<T> {
+    reader: BufReader<File>,
+    remaining: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: PlainData> RunReader<T> {
+    fn next_item(&mut self) -> io::Result<Option<T>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        let mut item = MaybeUninit::<T>::zeroed();
+        // SAFETY: the bytes of the item have been zeroed, and T is plain data (see PlainData), so
+        // that any bytes read back from the run make up a valid T.
+        let bytes =
+            unsafe { slice::from_raw_parts_mut(item.as_mut_ptr().cast::<u8>(), size_of::<T>()) };
+        self.reader.read_exact(bytes)?;
+        self.remaining -= 1;
+        Ok(Some(unsafe { item.assume_init() }))
+    }
+}
+
+/// A k-way merge of sorted runs, yielding their distinct items in order.
+struct RunMerge<T> {
+    readers: Vec<RunReader<T>>,
+    heap: BinaryHeap<Reverse<(T, usize)>>,
+    last: Option<T>,
+}
+
+impl<T: PlainData + Ord> RunMerge<T> {
+    fn new(mut readers: Vec<RunReader<T>>) -> io::Result<Self> {
+        let mut heap = BinaryHeap::with_capacity(readers.len());
+        for (run_idx, reader) in readers.iter_mut().enumerate() {
+            if let Some(first) = reader.next_item()? {
+                heap.push(Reverse((first, run_idx)));
+            }
+        }
+        Ok(Self {
+            readers,
+            heap,
+            last: None,
+        })
+    }
+
+    fn next_item(&mut self) -> io::Result<Option<T>> {
+        while let Some(Reverse((item, run_idx))) = self.heap.pop() {
+            if let Some(next) = self.readers[run_idx].next_item()? {
+                self.heap.push(Reverse((next, run_idx)));
+            }
+            if self.last != Some(item) {
+                self.last = Some(item);
+                return Ok(Some(item));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Divide strings with `num_vars_per_string` deletion variants into consecutive batches whose
+/// variants, of `item_size` bytes each, take up at most `budget_bytes`, save for batches of a
+/// single string that exceeds the budget on its own.
+fn get_spill_batches(
+    num_vars_per_string: &[usize],
+    item_size: usize,
+    budget_bytes: usize,
+) -> Vec<Range<usize>> {
+    let max_batch_vars = (budget_bytes / item_size).max(1);
+    let mut batches = Vec::new();
+    let mut start = 0;
+    let mut batch_vars = 0usize;
+    for (idx, &n) in num_vars_per_string.iter().enumerate() {
+        if idx > start && batch_vars.saturating_add(n) > max_batch_vars {
+            batches.push(start..idx);
+            start = idx;
+            batch_vars = 0;
+        }
+        batch_vars = batch_vars.saturating_add(n);
+    }
+    if start < num_vars_per_string.len() {
+        batches.push(start..num_vars_per_string.len());
+    }
+    batches
+}
+
+/// View the strings laid out in `buffer` at `offsets` (see [`get_neighbors_within_offsets`]),
+/// validating the offsets, and that the strings are ASCII in a single pass over the buffer.
+fn get_str_views_from_offsets<'a>(
+    buffer: &'a [u8],
+    offsets: &[impl Offset],
+    input_type: InputType,
+) -> Result<Vec<&'a str>, Error> {
+    let num_strings = offsets.len().saturating_sub(1);
+    if num_strings > u32::MAX as usize {
+        return Err(Error::TooManyStrings {
+            input_type,
+            got: num_strings,
+            limit: u32::MAX as usize,
+        });
+    }
+
+    let mut prev = 0;
+    for (idx, &offset) in offsets.iter().enumerate() {
+        match offset.to_usize() {
+            Some(offset) if offset >= prev && offset <= buffer.len() => prev = offset,
+            _ => {
+                return Err(Error::InvalidOffsets {
+                    input_type,
+                    idx,
+                    got: offset.to_i128(),
+                });
+            }
+        }
+    }
+
+    let (Some(&first), Some(&last)) = (offsets.first(), offsets.last()) else {
+        return Ok(Vec::new());
+    };
+    let first = first.to_validated();
+    let strings = &buffer[first..last.to_validated()];
+    if let Some(pos) = strings.iter().position(|b| !b.is_ascii()) {
+        let pos = first + pos;
+        let idx = offsets.partition_point(|&offset| offset.to_validated() <= pos) - 1;
+        let offending = &buffer[offsets[idx].to_validated()..offsets[idx + 1].to_validated()];
+        return Err(Error::NonAsciiInput {
+            input_type,
+            offending_idx: idx,
+            offending_string: String::from_utf8_lossy(offending).into_owned(),
+        });
+    }
+
+    // SAFETY: the strings have been validated as ASCII, which is valid UTF-8.
+    Ok(offsets
+        .iter()
+        .map(|&offset| offset.to_validated())
+        .tuple_windows()
+        .map(|(start, end)| unsafe { str::from_utf8_unchecked(&buffer[start..end]) })
+        .collect())
+}
+
+fn check_strings_ascii(strings: &[impl AsRef<str>], input_type: InputType) -> Result<(), Error> {
+    for (idx, s) in strings.iter().enumerate() {
+        if !s.as_ref().is_ascii() {
+            return Err(Error::NonAsciiInput {
+                input_type,
+                offending_idx: idx,
+                offending_string: s.as_ref().to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The number of characters in `s`, which is its length in bytes if it is ASCII.
+fn get_num_chars(s: &str) -> usize {
+    if s.is_ascii() {
+        s.len()
+    } else {
+        s.chars().count()
+    }
+}
+
+/// The largest number of deletion variants generated for any one string. Capping this at
+/// [`u32::MAX`] keeps the total number of variants across up to [`u32::MAX`] strings within a
+/// [`u64`].
+const MAX_DEL_VARS_PER_STRING: usize = u32::MAX as usize;
+
+/// Check that none of `strings` has more than [`MAX_DEL_VARS_PER_STRING`] deletion variants at
+/// `max_distance`, which must hold before their variants are counted or generated.
+fn check_del_var_counts(
+    strings: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    anchor: Anchoring,
+    input_type: InputType,
+) -> Result<(), Error> {
+    for (idx, s) in strings.iter().enumerate() {
+        let len = s.as_symbols().len();
+        if get_num_anchored_del_vars(s.as_symbols(), max_distance, anchor).is_none() {
+            return Err(Error::InputTooLong {
+                input_type,
+                offending_idx: idx,
+                len,
+                max_distance: max_distance.as_u8(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The number of deletion variants of a string of length len (including the string itself), or
+/// None if there are more than [`MAX_DEL_VARS_PER_STRING`].
+fn get_num_del_vars(len: usize, max_distance: MaxDistance) -> Option<usize> {
+    // An empty string has a single deletion variant: itself.
+    let max_deletions = max_distance.as_usize().min(len) as u8;
+    (0..=max_deletions)
+        .try_fold(0usize, |num_vars, k| {
+            num_vars.checked_add(get_num_k_combs(len, k)?)
+        })
+        .filter(|&num_vars| num_vars <= MAX_DEL_VARS_PER_STRING)
+}
+
+/// The number of deletion variants of `s` generated under `anchor` (see [`for_each_variant_hash`]),
+/// or None if there are more than [`MAX_DEL_VARS_PER_STRING`].
+fn get_num_anchored_del_vars(
+    s: Symbols,
+    max_distance: MaxDistance,
+    anchor: Anchoring,
+) -> Option<usize> {
+    if anchor.is_none() {
+        return get_num_del_vars(s.len(), max_distance);
+    }
+    match anchor.split(s) {
+        Some((_, interior, _)) => get_num_del_vars(interior.len(), max_distance),
+        None => Some(0),
+    }
+}
+
+/// The number of deletion variants of each of `strings`, which must have passed
+/// [`check_del_var_counts`].
+fn get_num_del_vars_per_string(
+    strings: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    anchor: Anchoring,
+) -> Vec<usize> {
+    strings
+        .iter()
+        .map(|s| {
+            get_num_anchored_del_vars(s.as_symbols(), max_distance, anchor).unwrap_or(usize::MAX)
+        })
+        .collect_vec()
+}
+
+fn count_del_vars(
+    strings: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    anchor: Anchoring,
+) -> usize {
+    get_num_del_vars_per_string(strings, max_distance, anchor)
+        .into_iter()
+        .fold(0, usize::saturating_add)
+}
+
+/// The number of ways of choosing k out of n items, which is 1 for k = 0 (including when n = 0),
+/// or None if it overflows.
+fn get_num_k_combs(n: usize, k: u8) -> Option<usize> {
+    debug_assert!(n >= k as usize);
+
+    // After step i, num_combs is the number of ways of choosing i + 1 out of n items, so that the
+    // division is always exact.
+    (0..k as usize).try_fold(1usize, |num_combs, i| {
+        Some(num_combs.checked_mul(n - i)? / (i + 1))
+    })
+}
+
+/// Generate the deletion variant hashes of all input strings, paired with the index of the string
+/// they originate from (offset by idx_offset), sorted and deduplicated.
+fn get_sorted_vi_pairs_rawidx(
+    strings: &[impl AsSymbols],
+    idx_offset: u32,
+    max_distance: MaxDistance,
+    hash_builder: &(impl BuildHasher<Hasher: Clone> + Sync),
+    progress: Progress,
+) -> Vec<VariantIndexPair<u32>> {
+    get_sorted_vi_pairs_rawidx_into(
+        strings,
+        idx_offset,
+        max_distance,
+        Anchoring::default(),
+        hash_builder,
+        None,
+        progress,
+        Vec::new(),
+    )
+}
+
+/// Equivalent to [`get_sorted_vi_pairs_rawidx`], generating the deletion variants under `anchor`
+/// and reusing the allocation of `buffer` for the result. If `labels` are given, the hashes of the
+/// deletion variants of each string are salted by its label, so that variants of strings with
+/// different labels (almost) never converge.
+#[allow(clippy::too_many_arguments)]
+fn get_sorted_vi_pairs_rawidx_into<const W: usize>(
+    strings: &[impl AsSymbols],
+    idx_offset: u32,
+    max_distance: MaxDistance,
+    anchor: Anchoring,
+    hash_builder: &(impl BuildHasher<Hasher: Clone> + Sync),
+    labels: Option<&[u32]>,
+    progress: Progress,
+    buffer: Vec<VariantIndexPair<u32, W>>,
+) -> Vec<VariantIndexPair<u32, W>> {
+    let num_vars_per_string = get_num_del_vars_per_string(strings, max_distance, anchor);
+    progress.start(Phase::VariantGeneration, strings.len());
+
+    let mut variant_index_pairs_uninit =
+        reuse_as_maybeuninit_vec(buffer, num_vars_per_string.iter().sum());
+    let vip_chunks =
+        get_disjoint_chunks_mut(&num_vars_per_string, &mut variant_index_pairs_uninit[..]);
+
+    let num_distinct_vars_per_string = strings
+        .par_iter()
+        .zip(vip_chunks.into_par_iter())
+        .enumerate()
+        .with_min_len(100000)
+        .map_init(
+            || (VariantScratch::default(), progress.batch()),
+            |(scratch, batch), (idx, (s, chunk))| {
+                let hash_salt = labels.map_or(0, |labels| get_label_salt(labels[idx]));
+                let num_distinct = write_vi_pairs_rawidx(
+                    s.as_symbols(),
+                    idx_offset + idx as u32,
+                    max_distance,
+                    anchor,
+                    chunk,
+                    hash_builder,
+                    hash_salt,
+                    scratch,
+                );
+                batch.advance(1);
+                num_distinct
+            },
+        )
+        .collect::<Vec<_>>();
+
+    let mut variant_index_pairs = unsafe { cast_to_initialised_vec(variant_index_pairs_uninit) };
+    compact_chunks(
+        &mut variant_index_pairs,
+        &num_vars_per_string,
+        &num_distinct_vars_per_string,
+    );
+
+    progress.start(Phase::Sorting, variant_index_pairs.len());
+    par_radix_sort(&mut variant_index_pairs);
+    progress.advance(variant_index_pairs.len());
+    par_dedup(&mut variant_index_pairs);
+
+    variant_index_pairs
+}
+
+/// The salt with which the deletion variant hashes of strings labelled `label` are XORed (see
+/// [`get_sorted_vi_pairs_rawidx_into`]).
+fn get_label_salt(label: u32) -> u64 {
+    (label as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+/// A deletion variant of the string at `index`, identified by its position `ordinal` among the
+/// deletion variants of the string, in the order in which [`for_each_variant_hash`] generates
+/// them (see [`write_nth_variant`]).
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct VariantRef<I> {
+    index: I,
+    ordinal: u32,
+}
+
+/// Append the deletion variant hashes of all `strings` to `variant_ref_pairs`, paired with
+/// references to the variants they derive from, with the index of each string mapped by
+/// `index_of`. The hashes are salted by `labels` as in [`get_sorted_vi_pairs_rawidx_into`].
+#[allow(clippy::too_many_arguments)]
+fn extend_variant_ref_pairs<I: Copy + Send>(
+    variant_ref_pairs: &mut Vec<VariantIndexPair<VariantRef<I>>>,
+    strings: &[impl AsSymbols],
+    index_of: impl Fn(u32) -> I + Sync,
+    max_distance: MaxDistance,
+    anchor: Anchoring,
+    hash_builder: &(impl BuildHasher<Hasher: Clone> + Sync),
+    labels: Option<&[u32]>,
+    progress: Progress,
+) {
+    let num_vars_per_string = get_num_del_vars_per_string(strings, max_distance, anchor);
+    let mut variant_ref_pairs_uninit = prealloc_maybeuninit_vec(num_vars_per_string.iter().sum());
+    let vrp_chunks =
+        get_disjoint_chunks_mut(&num_vars_per_string, &mut variant_ref_pairs_uninit[..]);
+
+    strings
+        .par_iter()
+        .zip(vrp_chunks.into_par_iter())
+        .enumerate()
+        .with_min_len(100000)
+        .for_each_init(
+            || (VariantScratch::default(), progress.batch()),
+            |(scratch, batch), (idx, (s, chunk))| {
+                let hash_salt = labels.map_or(0, |labels| get_label_salt(labels[idx]));
+                let index = index_of(idx as u32);
+                let mut ordinal = 0;
+                for_each_variant_hash(
+                    s.as_symbols(),
+                    max_distance,
+                    anchor,
+                    hash_builder,
+                    scratch,
+                    |hash| {
+                        let variant_ref = VariantRef {
+                            index,
+                            ordinal: ordinal as u32,
+                        };
+                        chunk[ordinal].write(VariantIndexPair::new(hash ^ hash_salt, variant_ref));
+                        ordinal += 1;
+                    },
+                );
+                batch.advance(1);
+            },
+        );
+
+    variant_ref_pairs.extend(unsafe { cast_to_initialised_vec(variant_ref_pairs_uninit) });
+}
+
+/// Write the deletion variant of `input` at position `ordinal` in the order in which
+/// [`for_each_variant_hash`] generates them into `out`, along with the anchored regions around
+/// it, which `input` must be long enough to hold. The variants are generated in order of the
+/// number of symbols deleted, and then in lexicographic order of the positions deleted, so that
+/// the positions of the variant follow from unranking its combination of positions.
+fn write_nth_variant(
+    input: Symbols,
+    max_deletions: MaxDistance,
+    anchor: Anchoring,
+    ordinal: usize,
+    out: &mut Vec<u8>,
+) {
+    let (prefix, interior, suffix) = anchor
+        .split(input)
+        .expect("strings too short for the anchor have no variants");
+    let bytes = interior.as_bytes();
+    let starts = match interior {
+        Symbols::Text(t) if !interior.is_bytewise() => t
+            .char_indices()
+            .map(|(start, _)| start)
+            .chain([t.len()])
+            .collect_vec(),
+        _ => (0..=bytes.len()).collect_vec(),
+    };
+    let num_symbols = starts.len() - 1;
+
+    let mut rank = ordinal;
+    let mut num_deletions = 0;
+    loop {
+        let num_combs = get_num_k_combs(num_symbols, num_deletions).expect("ordinal in range");
+        if rank < num_combs {
+            break;
+        }
+        rank -= num_combs;
+        num_deletions += 1;
+    }
+    debug_assert!(num_deletions as usize <= max_deletions.as_usize());
+
+    out.clear();
+    out.extend_from_slice(prefix);
+    let mut offset = 0;
+    let mut next = 0;
+    for i in 0..num_deletions {
+        // Skip past the combinations deleting `next` as their i-th position, which precede those
+        // deleting a later one.
+        loop {
+            let remaining = num_deletions - i - 1;
+            let num_combs =
+                get_num_k_combs(num_symbols - next - 1, remaining).expect("ordinal in range");
+            if rank < num_combs {
+                break;
+            }
+            rank -= num_combs;
+            next += 1;
+        }
+        out.extend_from_slice(&bytes[starts[offset]..starts[next]]);
+        offset = next + 1;
+        next += 1;
+    }
+    out.extend_from_slice(&bytes[starts[offset]..]);
+    out.extend_from_slice(suffix);
+}
+
+/// Given an input string and its index in the original input vector, generate all possible strings
+/// after making at most max_deletions single-character deletions (outside of the regions fixed by
+/// `anchor`), compute their hash, and write them into the slots in the provided chunk, as 2-tuples
+/// (hash, input_idx). The hashes are XORed with `hash_salt`, so that variants only converge with
+/// those of strings salted alike.
+///
+/// The pairs are then deduplicated within the chunk (see [`dedup_chunk`]), and the number of
+/// distinct pairs, which are left sorted at the front of the chunk, is returned.
+#[allow(clippy::too_many_arguments)]
+fn write_vi_pairs_rawidx<const W: usize>(
+    input: Symbols,
+    input_idx: u32,
+    max_deletions: MaxDistance,
+    anchor: Anchoring,
+    chunk: &mut [MaybeUninit<VariantIndexPair<u32, W>>],
+    hash_builder: &impl BuildHasher<Hasher: Clone>,
+    hash_salt: u64,
+    scratch: &mut VariantScratch,
+) -> usize {
+    let mut variant_idx = 0;
+    for_each_variant_hash(
+        input,
+        max_deletions,
+        anchor,
+        hash_builder,
+        scratch,
+        |hash| {
+            chunk[variant_idx].write(VariantIndexPair::from_halves(
+                hash ^ hash_salt,
+                0,
+                input_idx,
+            ));
+            variant_idx += 1;
+        },
+    );
+    if W > 2 {
+        write_wide_hash_halves(input, max_deletions, anchor, chunk, hash_builder, scratch);
+    }
+    dedup_chunk(chunk)
+}
+
+/// Similar to [`write_vi_pairs_rawidx`] but with the indices wrapped in CrossIndex, and unsalted.
+fn write_vi_pairs_ci<const W: usize>(
+    input: Symbols,
+    index: CrossIndex,
+    max_deletions: MaxDistance,
+    anchor: Anchoring,
+    chunk: &mut [MaybeUninit<VariantIndexPair<CrossIndex, W>>],
+    hash_builder: &impl BuildHasher<Hasher: Clone>,
+    scratch: &mut VariantScratch,
+) -> usize {
+    let mut variant_idx = 0;
+    for_each_variant_hash(
+        input,
+        max_deletions,
+        anchor,
+        hash_builder,
+        scratch,
+        |hash| {
+            chunk[variant_idx].write(VariantIndexPair::from_halves(hash, 0, index));
+            variant_idx += 1;
+        },
+    );
+    if W > 2 {
+        write_wide_hash_halves(input, max_deletions, anchor, chunk, hash_builder, scratch);
+    }
+    dedup_chunk(chunk)
+}
+
+/// Sort the pairs in `chunk`, every slot of which must have been initialised, and move the
+/// distinct ones to its front, returning their number.
+///
+/// All pairs of a chunk share the index of the string they derive from, so this drops the
+/// repeated deletion variants of the string, of which strings with runs of repeated symbols have
+/// many (e.g. all single deletions of "AAAA" are the same), before they reach the global sort.
+fn dedup_chunk<T: Copy + Ord>(chunk: &mut [MaybeUninit<T>]) -> usize {
+    // SAFETY: every slot of the chunk has been initialised, and MaybeUninit<T> has the same layout
+    // as T.
+    let pairs = unsafe { slice::from_raw_parts_mut(chunk.as_mut_ptr().cast::<T>(), chunk.len()) };
+    pairs.sort_unstable();
+
+    let mut len = 0;
+    for i in 0..pairs.len() {
+        if len == 0 || pairs[i] != pairs[len - 1] {
+            pairs[len] = pairs[i];
+            len += 1;
+        }
+    }
+    len
+}
+
+/// Move the first `retained_lens[i]` elements of each of the consecutive chunks of `v`, of lengths
+/// `chunk_lens`, down to follow those retained from the previous chunk, and truncate `v` to the
+/// retained elements.
+fn compact_chunks<T: Copy>(v: &mut Vec<T>, chunk_lens: &[usize], retained_lens: &[usize]) {
+    debug_assert_eq!(chunk_lens.iter().sum::<usize>(), v.len());
+
+    let mut start = 0;
+    let mut total_len = 0;
+    for (&chunk_len, &len) in chunk_lens.iter().zip(retained_lens) {
+        v.copy_within(start..start + len, total_len);
+        start += chunk_len;
+        total_len += len;
+    }
+    v.truncate(total_len);
+}
+
+/// The bytes that deletion variants are prefixed with to derive the less significant halves of
+/// their 128-bit hashes (see [`SearchOptions::wide_hashes`]) from the same hasher as their more
+/// significant halves.
+const WIDE_HASH_DOMAIN: &[u8] = b"symscan:wide-hash";
+
+/// Complete the 128-bit hashes of the deletion variants of `input`, whose more significant halves
+/// have already been written into `chunk`, by hashing each variant again with
+/// [`WIDE_HASH_DOMAIN`] in front of it. The variants are generated in the same order as in the
+/// first pass, so that each hash half lands in the slot of its variant.
+fn write_wide_hash_halves<I: Copy, const W: usize>(
+    input: Symbols,
+    max_deletions: MaxDistance,
+    anchor: Anchoring,
+    chunk: &mut [MaybeUninit<VariantIndexPair<I, W>>],
+    hash_builder: &impl BuildHasher<Hasher: Clone>,
+    scratch: &mut VariantScratch,
+) {
+    let hash_builder = AnchoredHashBuilder {
+        inner: hash_builder,
+        prefix: WIDE_HASH_DOMAIN,
+        suffix: &[],
+    };
+    let mut variant_idx = 0;
+    for_each_variant_hash(
+        input,
+        max_deletions,
+        anchor,
+        &hash_builder,
+        scratch,
+        |hash| {
+            // SAFETY: the first pass over the same variants initialised every slot of the chunk.
+            let pair = unsafe { chunk[variant_idx].assume_init_mut() };
+            *pair = VariantIndexPair::from_halves(pair.hash_prefix(), hash, pair.index());
+            variant_idx += 1;
+        },
+    );
+}
+
+/// Scratch space for generating and hashing deletion variants, which is meant to be created once
+/// per worker (e.g. via rayon's `for_each_init`) and reused across all the strings it processes,
+/// so that no per-string allocation or initialisation is needed.
+struct VariantScratch {
+    deletion_indices: [usize; u8::MAX as usize],
+    batch: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+impl VariantScratch {
+    const BATCH_BYTES: usize = 4096;
+    const BATCH_LEN: usize = 256;
+}
+
+impl Default for VariantScratch {
+    fn default() -> Self {
+        Self {
+            deletion_indices: [0; u8::MAX as usize],
+            batch: vec![0; Self::BATCH_BYTES],
+            ends: vec![0; Self::BATCH_LEN],
+        }
+    }
+}
+
+/// Generator of the strings obtainable by deleting between 1 and max_deletions characters from
+/// input, in lexicographic order of the deleted positions, writing each into a caller-provided
+/// buffer. Variants obtainable by deleting different positions are generated once per set of
+/// positions.
+struct DeletionVariantWriter<'a> {
+    input: &'a [u8],
+    max_deletions: usize,
+    num_deletions: usize,
+    deletion_indices: &'a mut [usize; u8::MAX as usize],
+    /// Whether the last advance only moved the final deletion index along by one, in which case
+    /// the next variant differs from the previous one by a single byte.
+    tail_advanced: bool,
+}
+
+impl<'a> DeletionVariantWriter<'a> {
+    fn new(
+        input: &'a [u8],
+        max_deletions: MaxDistance,
+        deletion_indices: &'a mut [usize; u8::MAX as usize],
+    ) -> Self {
+        deletion_indices[0] = 0;
+        Self {
+            input,
+            max_deletions: max_deletions.as_usize().min(input.len()),
+            num_deletions: 1,
+            deletion_indices,
+            tail_advanced: false,
+        }
+    }
+
+    /// Write the next variant into the start of out, which must be at least as long as the input,
+    /// returning its length, or None if all variants have been generated.
+    fn write_next(&mut self, out: &mut [u8]) -> Option<usize> {
+        let num_deletions = self.num_deletions;
+        if num_deletions > self.max_deletions {
+            return None;
+        }
+
+        let mut variant_length = 0;
+        let mut offset = 0;
+        for &idx in self.deletion_indices[..num_deletions]
+            .iter()
+            .chain([&self.input.len()])
+        {
+            let kept = &self.input[offset..idx];
+            out[variant_length..variant_length + kept.len()].copy_from_slice(kept);
+            variant_length += kept.len();
+            offset = idx + 1;
+        }
+
+        self.advance();
+        Some(variant_length)
+    }
+
+    /// Like [`DeletionVariantWriter::write_next`], but given the previous variant generated by this
+    /// instance, which is reused where possible rather than generating the next one from scratch.
+    fn write_next_after(&mut self, prev: &[u8], out: &mut [u8]) -> Option<usize> {
+        if !self.tail_advanced {
+            return self.write_next(out);
+        }
+
+        // The final deletion index moved from idx - 1 to idx, so the byte at idx - 1 is now kept,
+        // in place of the byte at idx.
+        let num_deletions = self.num_deletions;
+        let idx = self.deletion_indices[num_deletions - 1];
+        out[..prev.len()].copy_from_slice(prev);
+        out[idx - num_deletions] = self.input[idx - 1];
+
+        self.advance();
+        Some(prev.len())
+    }
+
+    fn advance(&mut self) {
+        // Once all combinations are exhausted, move on to the first combination of one more
+        // deletion.
+        let num_deletions = self.num_deletions;
+        let deletion_indices = &mut self.deletion_indices[..num_deletions];
+        match next_combination(deletion_indices, self.input.len()) {
+            Some(i) => self.tail_advanced = i == num_deletions - 1,
+            None => {
+                self.tail_advanced = false;
+                self.num_deletions += 1;
+                for (i, idx) in self.deletion_indices[..self.num_deletions.min(u8::MAX as usize)]
+                    .iter_mut()
+                    .enumerate()
+                {
+                    *idx = i;
+                }
+            }
+        }
+    }
+}
+
+/// Advance `indices`, a strictly increasing combination of positions below n, to the next one in
+/// lexicographic order: increment the rightmost index that has not yet reached its final position,
+/// and reset the ones after it to follow on directly. Returns the position in `indices` of the
+/// incremented index, or None if `indices` already held the last combination, leaving it as is.
+#[inline]
+fn next_combination(indices: &mut [usize], n: usize) -> Option<usize> {
+    let max_first = n - indices.len();
+    let i = (0..indices.len()).rfind(|&i| indices[i] < max_first + i)?;
+    indices[i] += 1;
+    for j in i + 1..indices.len() {
+        indices[j] = indices[j - 1] + 1;
+    }
+    Some(i)
+}
+
+/// Call `f` on the hash of input, followed by the hashes of each of its deletion variants. Raw bytes
+/// and ASCII text are handled by [`for_each_byte_variant_hash`], and any other text by
+/// [`for_each_char_variant_hash`], which hash the same variants the same way.
+///
+/// Under an `anchor`, only the interior of input is subject to deletions, and its variants are
+/// hashed with the anchored regions around them. Input too short to hold the anchored regions has
+/// no variants at all, not even itself.
+fn for_each_variant_hash(
+    input: Symbols,
+    max_deletions: MaxDistance,
+    anchor: Anchoring,
+    hash_builder: &impl BuildHasher<Hasher: Clone>,
+    scratch: &mut VariantScratch,
+    f: impl FnMut(u64),
+) {
+    if anchor.is_none() {
+        return for_each_unanchored_variant_hash(input, max_deletions, hash_builder, scratch, f);
+    }
+    let Some((prefix, interior, suffix)) = anchor.split(input) else {
+        return;
+    };
+    let hash_builder = AnchoredHashBuilder {
+        inner: hash_builder,
+        prefix,
+        suffix,
+    };
+    for_each_unanchored_variant_hash(interior, max_deletions, &hash_builder, scratch, f);
+}
+
+fn for_each_unanchored_variant_hash(
+    input: Symbols,
+    max_deletions: MaxDistance,
+    hash_builder: &impl BuildHasher<Hasher: Clone>,
+    scratch: &mut VariantScratch,
+    f: impl FnMut(u64),
+) {
+    match input {
+        Symbols::Text(input) if !input.is_ascii() => {
+            for_each_char_variant_hash(input, max_deletions, hash_builder, scratch, f);
+        }
+        _ => {
+            for_each_byte_variant_hash(input.as_bytes(), max_deletions, hash_builder, scratch, f);
+        }
+    }
+}
+
+/// Call `f` on the hash of input, followed by the hashes of each of its deletion variants in the
+/// order produced by [`DeletionVariantWriter`], deleting individual bytes.
+///
+/// Rather than hashing each variant as it is generated, variants are generated back to back into
+/// the scratch batch buffer and hashed in batches, each from a clone of a single hasher template.
+/// This keeps the generation and hashing loops tight, while producing exactly the same hashes as
+/// hashing each variant on its own would.
+fn for_each_byte_variant_hash(
+    input: &[u8],
+    max_deletions: MaxDistance,
+    hash_builder: &impl BuildHasher<Hasher: Clone>,
+    scratch: &mut VariantScratch,
+    mut f: impl FnMut(u64),
+) {
+    let template = hash_builder.build_hasher();
+    let mut hash = |bytes: &[u8]| {
+        let mut hasher = template.clone();
+        hasher.write(bytes);
+        f(hasher.finish());
+    };
+
+    hash(input);
+
+    let VariantScratch {
+        deletion_indices,
+        batch,
+        ends,
+    } = scratch;
+    if batch.len() < input.len() {
+        // Only happens for unusually long inputs, so it is not worth making room for more than
+        // one variant at a time.
+        batch.resize(input.len(), 0);
+    }
+    if max_deletions.as_usize() == 1 {
+        return for_each_single_deletion_variant(input, &mut batch[..input.len()], hash);
+    }
+
+    let mut variants = DeletionVariantWriter::new(input, max_deletions, deletion_indices);
+    let mut exhausted = false;
+    while !exhausted {
+        let mut num_in_batch = 0;
+        let mut start = 0;
+        let mut prev_start = 0;
+        while num_in_batch < ends.len() && start + input.len() <= batch.len() {
+            let (generated, out) = batch.split_at_mut(start);
+            let written = match num_in_batch {
+                0 => variants.write_next(out),
+                _ => variants.write_next_after(&generated[prev_start..], out),
+            };
+            let Some(variant_length) = written else {
+                exhausted = true;
+                break;
+            };
+            prev_start = start;
+            start += variant_length;
+            ends[num_in_batch] = start;
+            num_in_batch += 1;
+        }
+
+        let mut start = 0;
+        for &end in &ends[..num_in_batch] {
+            hash(&batch[start..end]);
+            start = end;
+        }
+    }
+}
+
+/// Call `f` on each of the variants of input with a single byte deleted, in the order produced by
+/// [`DeletionVariantWriter`], using `buf` (as long as input) to hold them.
+///
+/// This is the common case of searches at a `max_distance` of 1, for which the variants can be
+/// rolled along in place: the variant deleting position `i + 1` follows from that deleting `i`
+/// by restoring the byte at `i`, so that each costs a single byte write.
+#[inline]
+fn for_each_single_deletion_variant(input: &[u8], buf: &mut [u8], mut f: impl FnMut(&[u8])) {
+    let Some((_, rest)) = input.split_first() else {
+        return;
+    };
+    let variant = &mut buf[..rest.len()];
+    variant.copy_from_slice(rest);
+    f(variant);
+    for i in 0..rest.len() {
+        variant[i] = input[i];
+        f(variant);
+    }
+}
+
+/// Equivalent to [`for_each_byte_variant_hash`], but deleting whole characters rather than bytes,
+/// for non-ASCII input. Each variant is hashed as its UTF-8 encoding.
+#[cold]
+fn for_each_char_variant_hash(
+    input: &str,
+    max_deletions: MaxDistance,
+    hash_builder: &impl BuildHasher<Hasher: Clone>,
+    scratch: &mut VariantScratch,
+    mut f: impl FnMut(u64),
+) {
+    let template = hash_builder.build_hasher();
+    let mut hash = |bytes: &[u8]| {
+        let mut hasher = template.clone();
+        hasher.write(bytes);
+        f(hasher.finish());
+    };
+
+    hash(input.as_bytes());
+
+    let bytes = input.as_bytes();
+    let char_starts = input
+        .char_indices()
+        .map(|(start, _)| start)
+        .chain([input.len()])
+        .collect_vec();
+    let num_chars = char_starts.len() - 1;
+    let mut variant = Vec::with_capacity(input.len());
+
+    for num_deletions in 1..=max_deletions.as_usize().min(num_chars) {
+        let deletion_indices = &mut scratch.deletion_indices[..num_deletions];
+        for (i, idx) in deletion_indices.iter_mut().enumerate() {
+            *idx = i;
+        }
+
+        loop {
+            variant.clear();
+            let mut offset = 0;
+            for &idx in deletion_indices.iter() {
+                variant.extend_from_slice(&bytes[char_starts[offset]..char_starts[idx]]);
+                offset = idx + 1;
+            }
+            variant.extend_from_slice(&bytes[char_starts[offset]..]);
+            hash(&variant);
+
+            if next_combination(deletion_indices, num_chars).is_none() {
+                break;
+            }
+        }
+    }
+}
+
+fn read_exact_or_malformed(reader: &mut impl Read, buf: &mut [u8]) -> Result<(), Error> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => Error::MalformedCache("unexpected end of data"),
+        _ => Error::Io(e),
+    })
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> Result<[u8; N], Error> {
+    let mut buf = [0u8; N];
+    read_exact_or_malformed(reader, &mut buf)?;
+    Ok(buf)
+}
+
+fn read_len(reader: &mut impl Read) -> Result<usize, Error> {
+    usize::try_from(u64::from_le_bytes(read_array(reader)?))
+        .map_err(|_| Error::MalformedCache("length does not fit in usize"))
+}
+
+/// Read exactly `len` bytes from the reader. The buffer is grown as data comes in rather than
+/// preallocated, so that corrupted length fields cannot trigger huge allocations.
+fn read_byte_vec(reader: &mut impl Read, len: usize) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    reader.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(Error::MalformedCache("unexpected end of data"));
+    }
+    Ok(buf)
+}
+
+fn read_u64_vec(reader: &mut impl Read, len: usize) -> Result<Vec<u64>, Error> {
+    Ok(read_byte_vec(reader, len.saturating_mul(8))?
+        .chunks_exact(8)
+        .map(|b| u64::from_le_bytes(b.try_into().expect("chunks are of size 8")))
+        .collect_vec())
+}
+
+fn prealloc_maybeuninit_vec<T>(total_capacity: usize) -> Vec<MaybeUninit<T>> {
+    let mut v: Vec<MaybeUninit<T>> = Vec::with_capacity(total_capacity);
+    unsafe { v.set_len(total_capacity) };
+    v
+}
+
+/// Reuse the allocation of `v` for an uninitialised vector of `len` elements, growing it if needed.
+fn reuse_as_maybeuninit_vec<T>(mut v: Vec<T>, len: usize) -> Vec<MaybeUninit<T>> {
+    v.clear();
+    v.reserve_exact(len);
+    let mut v = std::mem::ManuallyDrop::new(v);
+    let mut v = unsafe { Vec::from_raw_parts(v.as_mut_ptr().cast(), 0, v.capacity()) };
+    unsafe { v.set_len(len) };
+    v
+}
+
+/// Plain data that can be viewed as bytes (see [`as_bytes`]), and read back from them.
+///
+/// # Safety
+///
+/// Implementors must have a fixed layout without padding bytes, so that all of their bytes are
+/// initialised, and every bit pattern of their size must be a valid value.
+unsafe trait PlainData: Copy {}
+
+unsafe impl PlainData for u32 {}
+unsafe impl PlainData for u64 {}
+unsafe impl PlainData for (u32, u32) {}
+unsafe impl PlainData for Span {}
+unsafe impl PlainData for VariantEntry {}
+unsafe impl PlainData for CrossIndex {}
+unsafe impl<const W: usize> PlainData for VariantIndexPair<u32, W> {}
+unsafe impl<const W: usize> PlainData for VariantIndexPair<CrossIndex, W> {}
+
+/// View a slice of plain data as bytes.
+fn as_bytes<T: PlainData>(data: &[T]) -> &[u8] {
+    // SAFETY: T has no padding (see PlainData), so that all bytes of the slice are initialised.
+    unsafe { slice::from_raw_parts(data.as_ptr().cast(), size_of_val(data)) }
+}
+
+fn get_disjoint_spans(span_lens: &[usize]) -> Vec<Span> {
+    let mut spans = Vec::with_capacity(span_lens.len());
+    let mut cursor = 0;
+    for &n in span_lens {
+        spans.push(Span::new(cursor, n));
+        cursor += n;
+    }
+    spans
+}
+
+fn get_disjoint_chunks_mut<'a, T>(
+    chunk_lens: &[usize],
+    mut backing_memory: &'a mut [T],
+) -> Vec<&'a mut [T]> {
+    let mut chunks = Vec::with_capacity(chunk_lens.len());
+    for &n in chunk_lens {
+        let (chunk, rest) = backing_memory.split_at_mut(n);
+        chunks.push(chunk);
+        backing_memory = rest;
+    }
+
+    debug_assert_eq!(backing_memory.len(), 0);
+
+    chunks
+}
+
+unsafe fn cast_to_initialised_vec<T>(mut input: Vec<MaybeUninit<T>>) -> Vec<T> {
+    let ptr = input.as_mut_ptr() as *mut T;
+    let len = input.len();
+    let cap = input.capacity();
+    std::mem::forget(input);
+    Vec::from_raw_parts(ptr, len, cap)
+}
+
+/// Remove consecutive repeated elements from a sorted vector in parallel, with the same result as
+/// [`Vec::dedup`].
+fn par_dedup<T: Copy + PartialEq + Send + Sync>(v: &mut Vec<T>) {
+    const MIN_CHUNK_SIZE: usize = 1 << 16;
+
+    let chunk_size = v
+        .len()
+        .div_ceil(rayon::current_num_threads() * 4)
+        .max(MIN_CHUNK_SIZE);
+    par_dedup_by_chunks(v, chunk_size);
+}
+
+/// Implementation of [`par_dedup`], where each chunk of `chunk_size` elements is deduplicated in
+/// place in parallel, after which the retained elements of each chunk are moved down to follow
+/// those of the previous one.
+fn par_dedup_by_chunks<T: Copy + PartialEq + Send + Sync>(v: &mut Vec<T>, chunk_size: usize) {
+    if v.len() <= chunk_size {
+        v.dedup();
+        return;
+    }
+
+    // Whether the first element of each chunk repeats the last element of the previous chunk,
+    // which must be determined before the chunks are modified.
+    let continues_previous = (0..v.len())
+        .step_by(chunk_size)
+        .map(|start| start > 0 && v[start] == v[start - 1])
+        .collect_vec();
+
+    let retained_lens = v
+        .par_chunks_mut(chunk_size)
+        .zip(continues_previous)
+        .map(|(chunk, continues_previous)| {
+            let mut last = continues_previous.then_some(chunk[0]);
+            let mut len = 0;
+            for i in 0..chunk.len() {
+                let x = chunk[i];
+                if last != Some(x) {
+                    chunk[len] = x;
+                    len += 1;
+                    last = Some(x);
+                }
+            }
+            len
+        })
+        .collect::<Vec<_>>();
+
+    let mut total_len = 0;
+    for (i, len) in retained_lens.into_iter().enumerate() {
+        let start = i * chunk_size;
+        v.copy_within(start..start + len, total_len);
+        total_len += len;
+    }
+    v.truncate(total_len);
+}
+
+/// Items sortable by [`par_radix_sort`].
+trait RadixKey: Copy + Ord + Send + Sync {
+    /// A prefix of the sort key, such that items with smaller prefixes sort before those with
+    /// larger ones.
+    fn key_prefix(&self) -> u64;
+}
+
+impl<I: Copy + Ord + Send + Sync, const W: usize> RadixKey for VariantIndexPair<I, W> {
+    #[inline(always)]
+    fn key_prefix(&self) -> u64 {
+        self.hash_prefix()
+    }
+}
+
+impl RadixKey for (u32, u32) {
+    #[inline(always)]
+    fn key_prefix(&self) -> u64 {
+        (self.0 as u64) << 32 | self.1 as u64
+    }
+}
+
+impl RadixKey for (u64, u32) {
+    #[inline(always)]
+    fn key_prefix(&self) -> u64 {
+        self.0
+    }
+}
+
+/// The number of leading key prefix bits by which [`par_radix_sort`] partitions items in its first
+/// pass.
+const RADIX_BITS: u32 = 8;
+
+/// Vectors shorter than this are sorted by comparison directly in [`par_radix_sort`], as the fixed
+/// costs of partitioning them would outweigh its gains.
+const RADIX_SORT_MIN_LEN: usize = 1 << 16;
+
+/// Buckets shorter than this are sorted by comparison directly after the first pass of
+/// [`par_radix_sort`], rather than being partitioned further.
+const RADIX_SUBPARTITION_MIN_LEN: usize = 1 << 10;
+
+/// The maximum number of key prefix bits by which [`par_radix_sort`] partitions each bucket in its
+/// second pass.
+const RADIX_SUBPARTITION_MAX_BITS: u32 = 12;
+
+/// A pointer to a buffer that workers write to at disjoint positions.
+#[derive(Clone, Copy)]
+struct DisjointWritePtr<T>(*mut T);
+
+// SAFETY: workers only ever write to disjoint positions through the pointer.
+unsafe impl<T: Send> Send for DisjointWritePtr<T> {}
+unsafe impl<T: Send> Sync for DisjointWritePtr<T> {}
+
+impl<T> DisjointWritePtr<T> {
+    /// The pointer itself, accessed through a method so that closures capture the whole wrapper.
+    fn get(self) -> *mut T {
+        self.0
+    }
+}
+
+/// Sort a slice in parallel, with the same result as [`slice::par_sort_unstable`].
+///
+/// The items are partitioned into buckets by the leading [`RADIX_BITS`] of the range of their key
+/// prefixes in a parallel scatter pass into a scratch buffer, after which each bucket is
+/// partitioned by the following bits back into the slice, and the resulting partitions are sorted
+/// by comparison. For evenly spread prefixes, such as hashes, the partitions hold a handful of
+/// items each, which makes this faster than sorting the whole slice by comparison, at the expense
+/// of a scratch buffer the size of the slice.
+fn par_radix_sort<T: RadixKey>(v: &mut [T]) {
+    if v.len() < RADIX_SORT_MIN_LEN {
+        v.par_sort_unstable();
+        return;
+    }
+
+    // Partitioning by the leading bits of the prefixes that actually occur keeps the buckets
+    // balanced even for prefixes far narrower than 64 bits, such as pairs of string indices.
+    let max_prefix = v.par_iter().map(RadixKey::key_prefix).max().unwrap_or(0);
+    let shift = (u64::BITS - max_prefix.leading_zeros()).saturating_sub(RADIX_BITS);
+    let bucket_of = |item: &T| (item.key_prefix() >> shift) as usize;
+    let num_buckets = 1 << RADIX_BITS;
+
+    let chunk_len = v.len().div_ceil(rayon::current_num_threads());
+    let mut write_positions = v
+        .par_chunks(chunk_len)
+        .map(|chunk| {
+            let mut counts = vec![0; num_buckets];
+            for item in chunk {
+                counts[bucket_of(item)] += 1;
+            }
+            counts
+        })
+        .collect::<Vec<_>>();
+
+    // Lay out the buckets back to back, with the items of each bucket ordered by chunk, so that
+    // each chunk writes to a range of its own within each bucket.
+    let mut bucket_lens = vec![0; num_buckets];
+    let mut cursor = 0;
+    for (bucket, bucket_len) in bucket_lens.iter_mut().enumerate() {
+        for positions in &mut write_positions {
+            let count = positions[bucket];
+            positions[bucket] = cursor;
+            cursor += count;
+            *bucket_len += count;
+        }
+    }
+
+    let mut scratch = prealloc_maybeuninit_vec::<T>(v.len());
+    let scratch_ptr = DisjointWritePtr(scratch.as_mut_ptr());
+    v.par_chunks(chunk_len)
+        .zip(write_positions.into_par_iter())
+        .for_each(|(chunk, mut positions)| {
+            for &item in chunk {
+                let position = &mut positions[bucket_of(&item)];
+                // SAFETY: each chunk writes as many items to each bucket as it counted, within
+                // a range of the scratch buffer of its own.
+                unsafe {
+                    scratch_ptr
+                        .get()
+                        .add(*position)
+                        .write(MaybeUninit::new(item))
+                };
+                *position += 1;
+            }
+        });
+
+    // SAFETY: every item has been written to a distinct position of the scratch buffer, which is
+    // as long as the slice.
+    let mut scratch = unsafe { cast_to_initialised_vec(scratch) };
+    get_disjoint_chunks_mut(&bucket_lens, &mut scratch[..])
+        .into_par_iter()
+        .zip(get_disjoint_chunks_mut(&bucket_lens, v))
+        .for_each(|(src, dst)| {
+            if shift == 0 || src.len() < RADIX_SUBPARTITION_MIN_LEN {
+                src.sort_unstable();
+                dst.copy_from_slice(src);
+            } else {
+                // Aim for a handful of items per partition, which are then sorted by insertion.
+                let bits = (src.len() / 4)
+                    .ilog2()
+                    .min(RADIX_SUBPARTITION_MAX_BITS)
+                    .min(shift);
+                partition_and_sort(src, dst, shift - bits, bits);
+            }
+        });
+}
+
+/// Partition the items of `src` into `dst` by the `bits` of their key prefixes above the lowest
+/// `shift`, and sort each resulting partition by comparison.
+fn partition_and_sort<T: RadixKey>(src: &[T], dst: &mut [T], shift: u32, bits: u32) {
+    let mask = (1 << bits) - 1;
+    let partition_of = |item: &T| ((item.key_prefix() >> shift) & mask) as usize;
+
+    let mut partition_lens = vec![0; 1 << bits];
+    for item in src {
+        partition_lens[partition_of(item)] += 1;
+    }
+    let mut write_positions = Vec::with_capacity(partition_lens.len());
+    let mut cursor = 0;
+    for &len in &partition_lens {
+        write_positions.push(cursor);
+        cursor += len;
+    }
+    for &item in src {
+        let position = &mut write_positions[partition_of(&item)];
+        dst[*position] = item;
+        *position += 1;
+    }
+
+    for partition in get_disjoint_chunks_mut(&partition_lens, dst) {
+        partition.sort_unstable();
+    }
+}
+
+/// Enumerate the candidate pairs of all convergence groups, sorted and deduplicated. If
+/// `canonical_indices` is given, pairs of identical strings are skipped (see
+/// [`get_candidates_within`]). Oversized groups are verified via `bounded_dist` as they are
+/// enumerated if it is given, and only contribute their hits, whose distances are returned as well
+/// (see [`get_filtered_hit_candidates`]).
+fn get_hit_candidates_within(
+    convergent_indices: &[impl AsRef<[u32]> + Sync],
+    keys: PairKeys,
+    bounded_dist: Option<&(dyn Fn(u32, u32) -> u8 + Sync)>,
+    options: &SearchOptions,
+) -> Result<(Vec<(u32, u32)>, KnownDists), Error> {
+    let (mut hit_candidates, known) = get_filtered_hit_candidates(
+        convergent_indices,
+        |indices| count_candidates_within(indices.as_ref(), keys),
+        |indices| (indices.as_ref().len(), indices.as_ref().len()),
+        |indices| get_candidates_within(indices.as_ref(), keys),
+        bounded_dist,
+        options,
+        Vec::new(),
+    )?;
+
+    par_radix_sort(&mut hit_candidates);
+    par_dedup(&mut hit_candidates);
+
+    Ok((hit_candidates, known))
+}
+
+/// Map each string to the index of its first identical copy in `strings`.
+fn get_canonical_indices(strings: &[impl AsSymbols]) -> Vec<u32> {
+    let mut order = (0..strings.len() as u32).collect_vec();
+    order.par_sort_by(|&a, &b| {
+        strings[a as usize]
+            .as_symbols()
+            .as_bytes()
+            .cmp(strings[b as usize].as_symbols().as_bytes())
+    });
+
+    let mut canonical_indices = vec![0; strings.len()];
+    for run in order.chunk_by(|&a, &b| {
+        strings[a as usize].as_symbols().as_bytes() == strings[b as usize].as_symbols().as_bytes()
+    }) {
+        // The sort is stable, so that each run starts with its smallest index.
+        for &idx in run {
+            canonical_indices[idx as usize] = run[0];
+        }
+    }
+    canonical_indices
+}
+
+/// The distinct strings of a collection, along with the indices of all copies of each.
+struct Duplicates {
+    /// The index of the first copy of each distinct string, in ascending order.
+    unique: Vec<u32>,
+    /// The indices of the copies of each distinct string in ascending order, concatenated in the
+    /// order of `unique`.
+    members: Vec<u32>,
+    /// The offsets at which the copies of each distinct string start in `members`, followed by
+    /// the length of `members`.
+    offsets: Vec<usize>,
+}
+
+impl Duplicates {
+    fn find(strings: &[impl AsSymbols]) -> Self {
+        let canonical_indices = get_canonical_indices(strings);
+
+        let mut ranks = vec![0; strings.len()];
+        let mut unique = Vec::new();
+        for (idx, &canonical_idx) in canonical_indices.iter().enumerate() {
+            if canonical_idx as usize == idx {
+                ranks[idx] = unique.len() as u32;
+                unique.push(idx as u32);
+            }
+        }
+
+        let mut offsets = vec![0; unique.len() + 1];
+        for &canonical_idx in &canonical_indices {
+            offsets[ranks[canonical_idx as usize] as usize + 1] += 1;
+        }
+        for i in 1..offsets.len() {
+            offsets[i] += offsets[i - 1];
+        }
+
+        let mut cursors = offsets.clone();
+        let mut members = vec![0; strings.len()];
+        for (idx, &canonical_idx) in canonical_indices.iter().enumerate() {
+            let cursor = &mut cursors[ranks[canonical_idx as usize] as usize];
+            members[*cursor] = idx as u32;
+            *cursor += 1;
+        }
+
+        Duplicates {
+            unique,
+            members,
+            offsets,
+        }
+    }
+
+    fn num_unique(&self) -> usize {
+        self.unique.len()
+    }
+
+    fn get_unique_strings<'a>(&self, strings: &'a [impl AsSymbols]) -> Vec<Symbols<'a>> {
+        self.unique
+            .iter()
+            .map(|&idx| strings[idx as usize].as_symbols())
+            .collect()
+    }
+
+    /// The indices of the copies of the distinct string of the given rank.
+    fn get_members(&self, rank: u32) -> &[u32] {
+        &self.members[self.offsets[rank as usize]..self.offsets[rank as usize + 1]]
+    }
+
+    /// Expand the hits of a search within the distinct strings to the hits between all of their
+    /// copies, adding the distance-0 pairs between the copies of each string where wanted.
+    fn expand_within(&self, hits: NeighborPairs, options: &SearchOptions) -> NeighborPairs {
+        let mut expanded = (0..hits.len())
+            .into_par_iter()
+            .flat_map_iter(|i| {
+                let dist = hits.dists[i];
+                let members_a = self.get_members(hits.row[i]);
+                let members_b = self.get_members(hits.col[i]);
+                members_a
+                    .iter()
+                    .cartesian_product(members_b)
+                    .map(move |(&a, &b)| (a.min(b), a.max(b), dist))
+            })
+            .filter(|&(a, b, _)| options.keeps(a, b))
+            .collect::<Vec<_>>();
+
+        if options.effective_min_distance() == 0 {
+            expanded.par_extend(
+                (0..self.num_unique() as u32)
+                    .into_par_iter()
+                    .flat_map_iter(|rank| self.get_members(rank).iter().tuple_combinations())
+                    .filter(|&(&a, &b)| options.keeps(a, b))
+                    .map(|(&a, &b)| (a, b, 0)),
+            );
+        }
+
+        if options.order != OutputOrder::Unordered {
+            expanded.par_sort_unstable();
+        }
+        NeighborPairs::from_triplets(expanded)
+    }
+
+    /// Expand the hits of a search across the distinct strings of `query` and `reference` to the
+    /// hits between all of their copies.
+    fn expand_across(
+        hits: NeighborPairs,
+        query: &Self,
+        reference: &Self,
+        options: &SearchOptions,
+    ) -> NeighborPairs {
+        let mut expanded = (0..hits.len())
+            .into_par_iter()
+            .flat_map_iter(|i| {
+                let dist = hits.dists[i];
+                let members_q = query.get_members(hits.row[i]);
+                let members_r = reference.get_members(hits.col[i]);
+                members_q
+                    .iter()
+                    .cartesian_product(members_r)
+                    .map(move |(&qi, &ri)| (qi, ri, dist))
+            })
+            .filter(|&(qi, ri, _)| options.keeps(qi, ri))
+            .collect::<Vec<_>>();
+
+        if options.order != OutputOrder::Unordered {
+            expanded.par_sort_unstable();
+        }
+        NeighborPairs::from_triplets(expanded)
+    }
+
+    /// Expand sorted deletion variants of the distinct strings to those of all of their copies,
+    /// keeping them sorted.
+    fn expand_variant_index_pairs(
+        &self,
+        variant_index_pairs: Vec<VariantIndexPair<u32>>,
+    ) -> Vec<VariantIndexPair<u32>> {
+        let mut expanded = variant_index_pairs
+            .par_iter()
+            .flat_map_iter(|vip| {
+                self.get_members(vip.index())
+                    .iter()
+                    .map(|&idx| VariantIndexPair::new(vip.hash(), idx))
+            })
+            .collect::<Vec<_>>();
+
+        expanded
+            .par_chunk_by_mut(|a, b| a.hash() == b.hash())
+            .for_each(|chunk| chunk.sort_unstable());
+
+        expanded
+    }
+}
+
+/// A partition of the query strings of a search into blocks of consecutive lengths (see
+/// [`SearchOptions::partition_by_length`]).
+struct LengthPartition {
+    /// The number of symbols in each query string.
+    lens: Vec<usize>,
+    /// The smallest length of each block, followed by one past the largest length of the last
+    /// block.
+    bounds: Vec<usize>,
+}
+
+impl LengthPartition {
+    /// Partition `strings` into blocks of consecutive lengths, aiming for `target_num_blocks`
+    /// blocks of about equal numbers of strings. Strings of the same length always end up in the
+    /// same block, so that there may be fewer blocks.
+    fn new(strings: &[impl AsSymbols], target_num_blocks: usize) -> Self {
+        let lens = strings
+            .par_iter()
+            .map(|s| s.as_symbols().len())
+            .collect::<Vec<_>>();
+        let mut sorted_lens = lens.clone();
+        sorted_lens.par_sort_unstable();
+
+        let target_block_len = sorted_lens.len().div_ceil(target_num_blocks.max(1));
+        let mut bounds = Vec::new();
+        let mut block_len = 0;
+        let mut num_seen = 0;
+        for run in sorted_lens.chunk_by(|a, b| a == b) {
+            if bounds.is_empty() {
+                bounds.push(run[0]);
+            }
+            block_len += run.len();
+            num_seen += run.len();
+            if block_len >= target_block_len || num_seen == sorted_lens.len() {
+                bounds.push(run[0] + 1);
+                block_len = 0;
+            }
+        }
+
+        Self { lens, bounds }
+    }
+
+    /// The number of blocks to aim for, several per thread so that the concurrent searches of
+    /// blocks of uneven sizes balance out.
+    fn target_num_blocks() -> usize {
+        rayon::current_num_threads() * 4
+    }
+
+    fn num_blocks(&self) -> usize {
+        self.bounds.len().saturating_sub(1)
+    }
+
+    /// The range of lengths of the given block.
+    fn get_block(&self, block: usize) -> Range<usize> {
+        self.bounds[block]..self.bounds[block + 1]
+    }
+
+    /// Apply `f` to each block, concurrently if the search reports nothing (see
+    /// [`Progress::is_silent`]).
+    fn map_blocks<T: Send>(
+        &self,
+        options: &SearchOptions,
+        f: impl Fn(usize) -> Result<T, Error> + Sync,
+    ) -> Result<Vec<T>, Error> {
+        if options.progress_reporter().is_silent() {
+            (0..self.num_blocks()).into_par_iter().map(&f).collect()
+        } else {
+            (0..self.num_blocks()).map(f).collect()
+        }
+    }
+
+    /// Search each block of `query` along with the strings up to `max_distance` longer, keeping
+    /// the pairs of strings at least one of which is in the block.
+    fn search_within(
+        &self,
+        query: &[impl AsSymbols],
+        max_distance: MaxDistance,
+        options: &SearchOptions,
+    ) -> Result<NeighborPairs, Error> {
+        let block_options = options.for_length_partitions();
+        let per_block = self.map_blocks(options, |block| {
+            let lens = self.get_block(block);
+            let window = lens.start..lens.end + max_distance.as_u8() as usize;
+            // The strings stay in the order of their indices, so that each pair keeps its
+            // orientation.
+            let indices = get_indices_with_lens(&self.lens, window);
+            let strings = indices
+                .iter()
+                .map(|&idx| query[idx as usize].as_symbols())
+                .collect_vec();
+            let hits = get_neighbors_within_validated(
+                &strings,
+                max_distance,
+                &block_options,
+                None,
+                Collect::Pairs,
+            )?
+            .into_pairs();
+            let in_block = |i: u32| lens.contains(&self.lens[indices[i as usize] as usize]);
+            Ok(izip!(hits.row, hits.col, hits.dists)
+                .filter(|&(a, b, _)| in_block(a) || in_block(b))
+                .map(|(a, b, dist)| (indices[a as usize], indices[b as usize], dist))
+                .filter(|&(a, b, _)| options.keeps(a, b))
+                .collect_vec())
+        })?;
+
+        let mut triplets = per_block.concat();
+        if options.order != OutputOrder::Unordered {
+            triplets.par_sort_unstable();
+        }
+        Ok(NeighborPairs::from_triplets(triplets))
+    }
+
+    /// Search each block of `query` against the strings of `reference` with lengths up to
+    /// `max_distance` away from those of the block.
+    fn search_across(
+        &self,
+        query: &[impl AsSymbols],
+        reference: &[impl AsSymbols],
+        max_distance: MaxDistance,
+        options: &SearchOptions,
+    ) -> Result<NeighborPairs, Error> {
+        let reference_lens = reference
+            .par_iter()
+            .map(|s| s.as_symbols().len())
+            .collect::<Vec<_>>();
+        let block_options = options.for_length_partitions();
+        let per_block = self.map_blocks(options, |block| {
+            let lens = self.get_block(block);
+            let window = lens.start.saturating_sub(max_distance.as_u8() as usize)
+                ..lens.end + max_distance.as_u8() as usize;
+            let indices_q = get_indices_with_lens(&self.lens, lens);
+            let indices_r = get_indices_with_lens(&reference_lens, window);
+            if indices_r.is_empty() {
+                return Ok(Vec::new());
+            }
+            let strings_q = indices_q
+                .iter()
+                .map(|&idx| query[idx as usize].as_symbols())
+                .collect_vec();
+            let strings_r = indices_r
+                .iter()
+                .map(|&idx| reference[idx as usize].as_symbols())
+                .collect_vec();
+            let hits = get_neighbors_across_validated(
+                &strings_q,
+                &strings_r,
+                max_distance,
+                &block_options,
+                Collect::Pairs,
+            )?
+            .into_pairs();
+            Ok(izip!(hits.row, hits.col, hits.dists)
+                .map(|(qi, ri, dist)| (indices_q[qi as usize], indices_r[ri as usize], dist))
+                .filter(|&(qi, ri, _)| options.keeps(qi, ri))
+                .collect_vec())
+        })?;
+
+        let mut triplets = per_block.concat();
+        if options.order != OutputOrder::Unordered {
+            triplets.par_sort_unstable();
+        }
+        Ok(NeighborPairs::from_triplets(triplets))
+    }
+}
+
+/// The indices of the strings with `lens` within `range`, in ascending order.
+fn get_indices_with_lens(lens: &[usize], range: Range<usize>) -> Vec<u32> {
+    lens.iter()
+        .enumerate()
+        .filter(|(_, len)| range.contains(len))
+        .map(|(idx, _)| idx as u32)
+        .collect()
+}
+
+/// Per-string keys that restrict which strings sharing a deletion variant are paired up as
+/// candidates. Strings are only paired if their `same` keys are equal and their `distinct` keys
+/// differ, where either restriction is lifted if its keys are not given.
+#[derive(Clone, Copy, Default)]
+struct PairKeys<'a> {
+    same: Option<&'a [u32]>,
+    distinct: Option<&'a [u32]>,
+}
+
+impl PairKeys<'_> {
+    fn is_empty(&self) -> bool {
+        self.same.is_none() && self.distinct.is_none()
+    }
+
+    /// The (`same`, `distinct`) keys of a string, which default to 0 if not given. The indices of
+    /// each convergence group must be sorted by their keys, and then by index.
+    fn get(&self, idx: u32) -> (u32, u32) {
+        let key = |keys: Option<&[u32]>| keys.map_or(0, |keys| keys[idx as usize]);
+        (key(self.same), key(self.distinct))
+    }
+}
+
+/// The candidate pairs among the strings sharing a deletion variant, in ascending index order
+/// within each pair.
+///
+/// If any `keys` are given, `indices` must be sorted as described in [`PairKeys::get`], and pairs that the
+/// keys rule out are skipped without being enumerated. Each string is then only paired with the
+/// strings following its run of equal `distinct` keys, up to the end of its run of equal `same`
+/// keys.
+fn get_candidates_within<'a>(
+    indices: &'a [u32],
+    keys: PairKeys<'a>,
+) -> impl Iterator<Item = (u32, u32)> + 'a {
+    if keys.is_empty() {
+        return Either::Left(indices.iter().copied().tuple_combinations());
+    }
+
+    Either::Right(indices.iter().enumerate().flat_map(move |(pos, &idx)| {
+        let key = keys.get(idx);
+        let same_end = pos + indices[pos..].partition_point(|&other| keys.get(other).0 == key.0);
+        let distinct_end = match keys.distinct {
+            None => pos + 1,
+            Some(_) => {
+                pos + indices[pos..same_end].partition_point(|&other| keys.get(other) == key)
+            }
+        };
+        indices[distinct_end..same_end]
+            .iter()
+            .map(move |&other| (idx.min(other), idx.max(other)))
+    }))
+}
+
+/// The number of candidate pairs that [`get_candidates_within`] enumerates.
+fn count_candidates_within(indices: &[u32], keys: PairKeys) -> u128 {
+    let num_pairs = |n: usize| {
+        let n = n as u128;
+        n * n.saturating_sub(1) / 2
+    };
+    if keys.is_empty() {
+        return num_pairs(indices.len());
+    }
+
+    let num_same_pairs = indices
+        .chunk_by(|&a, &b| keys.get(a).0 == keys.get(b).0)
+        .map(|run| num_pairs(run.len()))
+        .sum::<u128>();
+    let num_ruled_out_pairs = match keys.distinct {
+        None => 0,
+        Some(_) => indices
+            .chunk_by(|&a, &b| keys.get(a) == keys.get(b))
+            .map(|run| num_pairs(run.len()))
+            .sum::<u128>(),
+    };
+    num_same_pairs - num_ruled_out_pairs
+}
+
+/// Equivalent to [`get_hit_candidates_within`], for convergence groups of query and reference
+/// indices.
+fn get_hit_candidates_from_cis_cross<T, U>(
+    convergent_indices: &[(T, U)],
+    bounded_dist: Option<&(dyn Fn(u32, u32) -> u8 + Sync)>,
+    options: &SearchOptions,
+) -> Result<(Vec<(u32, u32)>, KnownDists), Error>
+where
+    T: AsRef<[u32]> + Sync,
+    U: AsRef<[u32]> + Sync,
+{
+    let (mut hit_candidates, known) = get_filtered_hit_candidates(
+        convergent_indices,
+        |(qi, ri)| qi.as_ref().len() as u128 * ri.as_ref().len() as u128,
+        |(qi, ri)| (qi.as_ref().len(), ri.as_ref().len()),
+        |(qi, ri)| {
+            qi.as_ref()
+                .iter()
+                .copied()
+                .cartesian_product(ri.as_ref().iter().copied())
+        },
+        bounded_dist,
+        options,
+        Vec::new(),
+    )?;
+
+    par_radix_sort(&mut hit_candidates);
+    par_dedup(&mut hit_candidates);
+
+    Ok((hit_candidates, known))
+}
+
+/// Enumerate the candidate pairs of each convergence group via `candidates`, unsorted and with most
+/// (but not necessarily all) duplicates removed. The allocation of `buffer` is reused for the
+/// result.
+///
+/// Before anything is allocated, the total `num_candidates` of all groups is checked against
+/// [`SearchOptions::max_candidates`], so that a few huge groups (e.g. of all strings short enough
+/// to be reduced to the empty string) error out rather than exhaust memory. `group_size` gives the
+/// numbers of query and reference strings in a group, for reporting the largest one.
+///
+/// Strings that share several deletion variants are emitted as a candidate pair by each group they
+/// share, so a large fraction of all candidates can be duplicates. To keep these from inflating the
+/// global sort, groups are processed in batches of around BATCH_LEN candidates, and each worker
+/// drops candidates that its [`CandidateFilter`] has recently seen. The retained candidates of each
+/// batch are then moved down to follow those of the previous one.
+///
+/// The candidates of groups with more than [`STREAMED_GROUP_MIN_CANDIDATES`] of them are not held
+/// in memory all at once. Instead, they are enumerated in blocks of BATCH_LEN candidates. If
+/// `bounded_dist` is given, each block is verified via `bounded_dist` in parallel and then
+/// discarded, so that only the hits among them are retained. Their distances are returned along
+/// with the candidates, so that they need not be computed again. Otherwise, the candidates of the
+/// blocks are sorted and deduplicated as they accumulate, so that the memory they take is bounded
+/// by the number of distinct candidates among them (which are all returned) rather than by their
+/// total number across groups.
+fn get_filtered_hit_candidates<'a, G, I>(
+    groups: &'a [G],
+    num_candidates: impl Fn(&G) -> u128,
+    group_size: impl Fn(&G) -> (usize, usize),
+    candidates: impl Fn(&'a G) -> I + Sync,
+    bounded_dist: Option<&(dyn Fn(u32, u32) -> u8 + Sync)>,
+    options: &SearchOptions,
+    buffer: Vec<(u32, u32)>,
+) -> Result<(Vec<(u32, u32)>, KnownDists), Error>
+where
+    G: Sync,
+    I: Iterator<Item = (u32, u32)>,
+{
+    const BATCH_LEN: usize = 1 << 16;
+
+    let limit = options
+        .max_candidates
+        .unwrap_or(isize::MAX as usize / size_of::<(u32, u32)>());
+    let total_num_candidates = groups.iter().map(&num_candidates).sum::<u128>();
+    if total_num_candidates > limit as u128 {
+        let largest_group = groups
+            .iter()
+            .max_by_key(|group| num_candidates(group))
+            .map_or((0, 0), group_size);
+        return Err(Error::TooManyCandidates {
+            got: total_num_candidates,
+            limit,
+            largest_group,
+        });
+    }
+
+    let is_streamed = |group: &G| num_candidates(group) > STREAMED_GROUP_MIN_CANDIDATES as u128;
+
+    let mut group_batches = Vec::new();
+    let mut batch_lens = Vec::new();
+    let mut streamed_groups = Vec::new();
+    let mut batch_start = 0;
+    let mut batch_len = 0;
+    for (i, group) in groups.iter().enumerate() {
+        if is_streamed(group) {
+            // Close the batch before the group, leaving the group itself out of all batches.
+            if batch_start < i {
+                group_batches.push(&groups[batch_start..i]);
+                batch_lens.push(batch_len);
+            }
+            streamed_groups.push(group);
+            batch_start = i + 1;
+            batch_len = 0;
+            continue;
+        }
+        batch_len += num_candidates(group) as usize;
+        if batch_len >= BATCH_LEN || i == groups.len() - 1 {
+            group_batches.push(&groups[batch_start..=i]);
+            batch_lens.push(batch_len);
+            batch_start = i + 1;
+            batch_len = 0;
+        }
+    }
+
+    let mut hit_candidates_uninit = reuse_as_maybeuninit_vec(buffer, batch_lens.iter().sum());
+    let hc_chunks = get_disjoint_chunks_mut(&batch_lens, &mut hit_candidates_uninit);
+    let progress = options.progress_reporter();
+    progress.start(Phase::CandidateGeneration, total_num_candidates as usize);
+
+    let retained_lens = group_batches
+        .into_par_iter()
+        .zip(hc_chunks.into_par_iter())
+        .map_init(CandidateFilter::default, |filter, (groups, chunk)| {
+            let mut len = 0;
+            for candidate in groups.iter().flat_map(&candidates) {
+                if filter.insert(candidate) {
+                    chunk[len].write(candidate);
+                    len += 1;
+                }
+            }
+            progress.advance(chunk.len());
+            len
+        })
+        .collect::<Vec<_>>();
+
+    let mut total_len = 0;
+    let mut start = 0;
+    for (batch_len, len) in batch_lens.into_iter().zip(retained_lens) {
+        hit_candidates_uninit.copy_within(start..start + len, total_len);
+        total_len += len;
+        start += batch_len;
+    }
+    hit_candidates_uninit.truncate(total_len);
+    let mut hit_candidates = unsafe { cast_to_initialised_vec(hit_candidates_uninit) };
+
+    let mut known = Vec::new();
+    let mut unverified = Vec::new();
+    let mut num_deduped = 0;
+    let mut block = Vec::new();
+    for group in streamed_groups {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            num_candidates = num_candidates(group) as u64,
+            "streaming the candidates of an oversized convergence group"
+        );
+        let mut group_candidates = candidates(group);
+        loop {
+            block.clear();
+            block.extend(group_candidates.by_ref().take(BATCH_LEN));
+            if block.is_empty() || options.is_cancelled() {
+                break;
+            }
+            match bounded_dist {
+                Some(bounded_dist) => {
+                    known.par_extend(block.par_iter().filter_map(|&(idx_query, idx_reference)| {
+                        let dist = bounded_dist(idx_query, idx_reference);
+                        (dist != u8::MAX).then_some(((idx_query, idx_reference), dist))
+                    }));
+                }
+                None => {
+                    unverified.extend_from_slice(&block);
+                    // Deduplicate whenever the candidates have doubled since, so that duplicates
+                    // never make up more than half of them.
+                    if unverified.len() >= 2 * num_deduped + BATCH_LEN {
+                        par_radix_sort(&mut unverified);
+                        par_dedup(&mut unverified);
+                        num_deduped = unverified.len();
+                    }
+                }
+            }
+            progress.advance(block.len());
+        }
+    }
+    if unverified.len() > num_deduped {
+        par_radix_sort(&mut unverified);
+        par_dedup(&mut unverified);
+    }
+    hit_candidates.extend(known.iter().map(|&(candidate, _)| candidate));
+    hit_candidates.append(&mut unverified);
+
+    Ok((hit_candidates, KnownDists::new(known)))
+}
+
+/// The number of candidates above which the candidates of a convergence group are verified (or
+/// deduplicated, if they are not to be verified) in blocks as they are enumerated, rather than
+/// collected along with those of all other groups (see [`get_filtered_hit_candidates`]). Such groups arise when many strings reduce
+/// to the same deletion variant, e.g. when all strings no longer than `max_distance` share the
+/// empty string, and would otherwise take memory quadratic in their size up front.
+const STREAMED_GROUP_MIN_CANDIDATES: usize = 1 << 22;
+
+/// The distances of the candidate pairs that were verified as they were enumerated (see
+/// [`get_filtered_hit_candidates`]), which verification looks up rather than computing again.
+#[derive(Default)]
+struct KnownDists(Vec<((u32, u32), u8)>);
+
+impl KnownDists {
+    fn new(mut dists: Vec<((u32, u32), u8)>) -> Self {
+        dists.par_sort_unstable();
+        dists.dedup();
+        Self(dists)
+    }
+
+    /// The known distance of the `candidate` pair, or that given by `dist` otherwise.
+    #[inline(always)]
+    fn get_or(&self, candidate: (u32, u32), dist: impl FnOnce() -> u8) -> u8 {
+        if self.0.is_empty() {
+            return dist();
+        }
+        match self.0.binary_search_by_key(&candidate, |&(pair, _)| pair) {
+            Ok(i) => self.0[i].1,
+            Err(_) => dist(),
+        }
+    }
+}
+
+/// A bounded set of recently seen candidate pairs, used to drop duplicate candidates early.
+///
+/// Pairs are held in a direct-mapped table, so a pair is forgotten once another pair maps to the
+/// same slot, but a pair is never reported as seen if it has not been.
+struct CandidateFilter {
+    slots: Vec<(u32, u32)>,
+}
+
+impl CandidateFilter {
+    const NUM_SLOTS_LOG2: u32 = 14;
+
+    /// Record `candidate` as seen, returning whether it was not already.
+    #[inline(always)]
+    fn insert(&mut self, candidate: (u32, u32)) -> bool {
+        let packed = ((candidate.0 as u64) << 32) | candidate.1 as u64;
+        let slot = packed.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (64 - Self::NUM_SLOTS_LOG2);
+        let slot = &mut self.slots[slot as usize];
+        if *slot == candidate {
+            return false;
+        }
+        *slot = candidate;
+        true
+    }
+}
+
+impl Default for CandidateFilter {
+    fn default() -> Self {
+        // No string index reaches u32::MAX, so no candidate pair matches the empty slots.
+        Self {
+            slots: vec![(u32::MAX, u32::MAX); 1 << Self::NUM_SLOTS_LOG2],
+        }
+    }
+}
+
+/// A query string prepared for comparison against many reference strings, which for the
+/// Levenshtein distance between byte strings holds the bit-parallel pattern of the query, so that
+/// it is built once rather than for every candidate pair.
+struct QueryComparator<'a> {
+    symbols: Symbols<'a>,
+    cached: Option<levenshtein::BatchComparator<u8>>,
+}
+
+impl<'a> QueryComparator<'a> {
+    /// The number of candidates of a query string from which building its pattern pays off.
+    const MIN_CANDIDATES: usize = 4;
+
+    fn new(symbols: Symbols<'a>, metric: Metric, num_candidates: usize) -> Self {
+        let cached = (metric == Metric::Levenshtein
+            && num_candidates >= Self::MIN_CANDIDATES
+            && symbols.is_bytewise())
+        .then(|| levenshtein::BatchComparator::new(symbols.as_bytes().iter().copied()));
+        Self { symbols, cached }
+    }
+}
+
+/// Compute the bounded distances of the candidate pairs, which are verified in blocks. Within a
+/// block, the runs of candidates sharing a query string, into which sorted candidates fall, are
+/// compared against a single [`QueryComparator`] of that string. The distances of the `known` pairs
+/// are looked up instead.
+fn compute_dists(
+    hit_candidates: &[(u32, u32)],
+    known: &KnownDists,
+    query: &[impl AsSymbols],
+    reference: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+) -> Vec<u8> {
+    let mut dists = vec![u8::MAX; hit_candidates.len()];
+    compute_dists_into(
+        &mut dists,
+        hit_candidates,
+        known,
+        |i| query[i as usize].as_symbols(),
+        |i| reference[i as usize].as_symbols(),
+        max_distance,
+        options,
+    );
+    dists
+}
+
+/// Compute the bounded distances of the candidate pairs into `dists` as in [`compute_dists`], given
+/// the strings at each query and reference index.
+fn compute_dists_into<'q, 'r>(
+    dists: &mut [u8],
+    hit_candidates: &[(u32, u32)],
+    known: &KnownDists,
+    query: impl Fn(u32) -> Symbols<'q> + Sync,
+    reference: impl Fn(u32) -> Symbols<'r> + Sync,
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+) {
+    const BLOCK_LEN: usize = 1 << 12;
+
+    let progress = options.progress_reporter();
+    progress.start(Phase::DistanceComputation, hit_candidates.len());
+
+    hit_candidates
+        .par_chunks(BLOCK_LEN)
+        .zip(dists.par_chunks_mut(BLOCK_LEN))
+        .for_each_init(
+            || progress.batch(),
+            |batch, (block, block_dists)| {
+                if options.is_cancelled() {
+                    return;
+                }
+                batch.advance(block.len());
+
+                let mut block_dists = block_dists.iter_mut();
+                for row in block.chunk_by(|a, b| a.0 == b.0) {
+                    let idx_query = row[0].0;
+                    let comparator =
+                        QueryComparator::new(query(idx_query), options.metric, row.len());
+                    for (&(_, idx_reference), dist) in row.iter().zip(block_dists.by_ref()) {
+                        *dist = known.get_or((idx_query, idx_reference), || {
+                            options.bounded_dist_with(
+                                idx_query,
+                                idx_reference,
+                                comparator.symbols,
+                                reference(idx_reference),
+                                max_distance,
+                                comparator.cached.as_ref(),
+                            )
+                        });
+                    }
+                }
+            },
+        );
+}
+
+/// The form in which the true hits among the candidate pairs of a search are collected.
+#[derive(Clone, Copy)]
+enum Collect<'a> {
+    /// All of the hits.
+    Pairs,
+    /// The closest `k` hits of each query string (see [`get_top_k_hits`]).
+    TopK(usize),
+    /// The number of hits of each query string. For searches within a collection, where the query
+    /// and the reference are one and the same, hits are counted towards both of their strings.
+    Counts { symmetric: bool },
+    /// Whether each query string has any hits at all (see [`get_hit_flags`]).
+    Flags,
+    /// Pass each hit to a callback as (query index, reference index, distance), in no particular
+    /// order, rather than collecting it.
+    ForEach(&'a (dyn Fn(u32, u32, u8) + Sync)),
+    /// The candidate pairs themselves, without verifying them at all.
+    Candidates,
+}
+
+impl Collect<'_> {
+    /// Collect the true hits among the candidate pairs, which are sorted and unique, given their
+    /// distances.
+    fn select(
+        self,
+        hit_candidates: &[(u32, u32)],
+        dists: &[u8],
+        query_len: usize,
+        max_distance: MaxDistance,
+        options: &SearchOptions,
+    ) -> Hits {
+        let min_distance = options.effective_min_distance();
+        match self {
+            Collect::Pairs => Hits::Pairs(collect_true_hits(
+                hit_candidates,
+                dists,
+                max_distance,
+                min_distance,
+            )),
+            Collect::TopK(_) => unreachable!("top-k hits are selected as they are verified"),
+            Collect::Flags => unreachable!("hit flags are set as candidates are verified"),
+            Collect::Candidates => unreachable!("candidates are returned before verification"),
+            Collect::Counts { symmetric } => Hits::Counts(count_true_hits(
+                hit_candidates,
+                dists,
+                query_len,
+                max_distance,
+                min_distance,
+                symmetric,
+            )),
+            Collect::ForEach(f) => {
+                let is_hit = |d: u8| (min_distance..=max_distance.as_u8()).contains(&d);
+                hit_candidates
+                    .par_iter()
+                    .zip(dists.par_iter())
+                    .with_min_len(100000)
+                    .for_each(|(&(qi, ri), &d)| {
+                        if is_hit(d) {
+                            f(qi, ri, d);
+                        }
+                    });
+                Hits::Streamed
+            }
+        }
+    }
+}
+
+/// The true hits of a search, in the form requested by a [`Collect`].
+enum Hits {
+    Pairs(NeighborPairs),
+    Counts(Vec<u32>),
+    Flags(Vec<bool>),
+    /// The hits were passed to a callback.
+    Streamed,
+    /// The unverified candidate pairs, sorted and unique.
+    Candidates(Vec<(u32, u32)>),
+}
+
+impl Hits {
+    fn into_pairs(self) -> NeighborPairs {
+        match self {
+            Hits::Pairs(pairs) => pairs,
+            _ => unreachable!("hits were collected as pairs"),
+        }
+    }
+
+    fn into_counts(self) -> Vec<u32> {
+        match self {
+            Hits::Counts(counts) => counts,
+            _ => unreachable!("hits were collected as counts"),
+        }
+    }
+
+    fn into_flags(self) -> Vec<bool> {
+        match self {
+            Hits::Flags(flags) => flags,
+            _ => unreachable!("hits were collected as flags"),
+        }
+    }
+
+    fn into_candidates(self) -> Vec<(u32, u32)> {
+        match self {
+            Hits::Candidates(candidates) => candidates,
+            _ => unreachable!("candidates were collected without verification"),
+        }
+    }
+
+    /// Combine the hits among two blocks of candidate pairs, where those of `other` all follow
+    /// those of `self` in sorted order, and no query string has candidates in both.
+    fn merge(self, other: Hits) -> Hits {
+        match (self, other) {
+            (Hits::Pairs(mut pairs), Hits::Pairs(other)) => {
+                pairs.row.extend(other.row);
+                pairs.col.extend(other.col);
+                pairs.dists.extend(other.dists);
+                Hits::Pairs(pairs)
+            }
+            (Hits::Counts(mut counts), Hits::Counts(other)) => {
+                for (count, other) in counts.iter_mut().zip(other) {
+                    *count += other;
+                }
+                Hits::Counts(counts)
+            }
+            (Hits::Flags(mut flags), Hits::Flags(other)) => {
+                for (flag, other) in flags.iter_mut().zip(other) {
+                    *flag |= other;
+                }
+                Hits::Flags(flags)
+            }
+            (Hits::Streamed, Hits::Streamed) => Hits::Streamed,
+            (Hits::Candidates(mut candidates), Hits::Candidates(other)) => {
+                candidates.extend(other);
+                Hits::Candidates(candidates)
+            }
+            _ => unreachable!("hits were collected in the same form"),
+        }
+    }
+}
+
+/// Verify all of the given candidate pairs, which are sorted and unique, and collect the true hits
+/// as requested by `collect`. The distances of the `known` pairs are not computed again.
+fn get_true_hits(
+    candidates: &[(u32, u32)],
+    known: &KnownDists,
+    query: &[impl AsSymbols],
+    reference: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+    collect: Collect<'_>,
+) -> Result<Hits, Error> {
+    let dist = |qi: u32, ri: u32, bound| {
+        known.get_or((qi, ri), || {
+            options.bounded_dist(
+                qi,
+                ri,
+                query[qi as usize].as_symbols(),
+                reference[ri as usize].as_symbols(),
+                bound,
+            )
+        })
+    };
+    match collect {
+        Collect::TopK(k) => {
+            let hits = get_top_k_hits(candidates, max_distance, options, k, dist);
+            options.check_cancelled()?;
+            return Ok(Hits::Pairs(hits));
+        }
+        Collect::Flags => {
+            let flags = get_hit_flags(
+                candidates,
+                |&candidate| iter::once(candidate),
+                query.len(),
+                max_distance,
+                options,
+                dist,
+            );
+            options.check_cancelled()?;
+            return Ok(Hits::Flags(flags));
+        }
+        Collect::Candidates => return Ok(Hits::Candidates(candidates.to_vec())),
+        Collect::Pairs | Collect::Counts { .. } | Collect::ForEach(_) => (),
+    }
+
+    let dists = compute_dists(candidates, known, query, reference, max_distance, options);
+    options.check_cancelled()?;
+
+    Ok(collect.select(candidates, &dists, query.len(), max_distance, options))
+}
+
+/// Enumerate the candidate pairs of each convergence group via `candidates`, verify them on the
+/// fly, and collect the true hits, sorted and deduplicated as [`collect_true_hits`] would return
+/// them. Only the true hits are buffered, in per-thread vectors that are concatenated at the end.
+///
+/// Candidates that a worker's [`CandidateFilter`] has recently seen are not verified again, which
+/// spares most of the repeated verifications of pairs sharing several deletion variants. Any
+/// remaining duplicate hits are removed from the much smaller set of hits at the end.
+fn get_true_hits_fused<G, I>(
+    convergence_groups: &[G],
+    candidates: impl Fn(&G) -> I + Sync,
+    query: &[impl AsSymbols],
+    reference: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+) -> NeighborPairs
+where
+    G: Sync,
+    I: Iterator<Item = (u32, u32)>,
+{
+    let distance_range = options.effective_min_distance()..=max_distance.as_u8();
+    let progress = options.progress_reporter();
+    progress.start_in_groups(Phase::DistanceComputation, convergence_groups.len());
+
+    let mut hits = convergence_groups
+        .par_iter()
+        .with_min_len(1000)
+        .fold(
+            || (Vec::new(), progress.batch(), CandidateFilter::default()),
+            |(mut hits, mut batch, mut filter), group| {
+                if options.is_cancelled() {
+                    return (hits, batch, filter);
+                }
+                batch.advance(1);
+                for (qi, ri) in candidates(group) {
+                    if !filter.insert((qi, ri)) {
+                        continue;
+                    }
+                    let dist = options.bounded_dist(
+                        qi,
+                        ri,
+                        query[qi as usize].as_symbols(),
+                        reference[ri as usize].as_symbols(),
+                        max_distance,
+                    );
+                    if distance_range.contains(&dist) {
+                        hits.push((qi, ri, dist));
+                    }
+                }
+                (hits, batch, filter)
+            },
+        )
+        .map(|(hits, ..)| hits)
+        .reduce(Vec::new, |mut a, mut b| {
+            a.append(&mut b);
+            a
+        });
+
+    hits.par_sort_unstable();
+    par_dedup(&mut hits);
+
+    let mut pairs = NeighborPairs {
+        row: Vec::with_capacity(hits.len()),
+        col: Vec::with_capacity(hits.len()),
+        dists: Vec::with_capacity(hits.len()),
+    };
+    for (qi, ri, dist) in hits {
+        pairs.row.push(qi);
+        pairs.col.push(ri);
+        pairs.dists.push(dist);
+    }
+    pairs
+}
+
+/// Verify the candidate pairs, which are sorted and unique, and keep at most `k` true hits per query
+/// index, preferring smaller distances and breaking ties by reference index. The result is sorted
+/// by (row, distance, column).
+///
+/// The candidates of each query index are verified in order by a single thread, which selects the
+/// hits with a bounded heap. Once the heap is full, `dist` is only asked to resolve distances that
+/// would displace its worst hit, and the remaining candidates are skipped altogether once it can no
+/// longer be improved upon.
+fn get_top_k_hits(
+    hit_candidates: &[(u32, u32)],
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+    k: usize,
+    dist: impl Fn(u32, u32, MaxDistance) -> u8 + Sync,
+) -> NeighborPairs {
+    let min_distance = options.effective_min_distance();
+
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for run in hit_candidates.chunk_by(|a, b| a.0 == b.0) {
+        runs.push(start..start + run.len());
+        start += run.len();
+    }
+
+    let progress = options.progress_reporter();
+    progress.start(Phase::DistanceComputation, hit_candidates.len());
+
+    let top_hits_per_run = runs
+        .into_par_iter()
+        .with_min_len(1000)
+        .map_init(
+            || progress.batch(),
+            |batch, run| {
+                batch.advance(run.len());
+                let qi = hit_candidates[run.start].0;
+                let mut heap = BinaryHeap::with_capacity(k.min(run.len()));
+                if k == 0 || options.is_cancelled() {
+                    return (qi, Vec::new());
+                }
+
+                let mut bound = max_distance;
+                for &(_, ri) in &hit_candidates[run] {
+                    let d = dist(qi, ri, bound);
+                    if d < min_distance || d > bound.as_u8() {
+                        continue;
+                    }
+                    // Candidates come in order of reference index, so ties never displace a hit.
+                    if heap.len() < k {
+                        heap.push((d, ri));
+                    } else if let Some(mut worst) = heap.peek_mut() {
+                        if d < worst.0 {
+                            *worst = (d, ri);
+                        }
+                    }
+                    if heap.len() == k {
+                        let worst = heap.peek().map_or(0, |&(d, _)| d);
+                        if worst <= min_distance {
+                            break;
+                        }
+                        bound = bound.min(worst - 1);
+                    }
+                }
+                (qi, heap.into_sorted_vec())
+            },
+        )
+        .collect::<Vec<_>>();
+
+    let total_num_hits = top_hits_per_run.iter().map(|(_, hits)| hits.len()).sum();
+    let mut pairs = NeighborPairs {
+        row: Vec::with_capacity(total_num_hits),
+        col: Vec::with_capacity(total_num_hits),
+        dists: Vec::with_capacity(total_num_hits),
+    };
+    for (qi, hits) in top_hits_per_run {
+        for (d, ri) in hits {
+            pairs.row.push(qi);
+            pairs.col.push(ri);
+            pairs.dists.push(d);
+        }
+    }
+    pairs
+}
+
+/// Flag the query indices that have at least one true hit among the candidate pairs of the given
+/// convergence groups, as enumerated by `candidates` and verified on the fly by `dist`. Since a
+/// flag only ever needs setting once, the remaining candidates of a query index are skipped over
+/// without being verified as soon as it is flagged, and candidates need not be deduplicated.
+fn get_hit_flags<G, I>(
+    convergence_groups: &[G],
+    candidates: impl Fn(&G) -> I + Sync,
+    query_len: usize,
+    max_distance: MaxDistance,
+    options: &SearchOptions,
+    dist: impl Fn(u32, u32, MaxDistance) -> u8 + Sync,
+) -> Vec<bool>
+where
+    G: Sync,
+    I: Iterator<Item = (u32, u32)>,
+{
+    let distance_range = options.effective_min_distance()..=max_distance.as_u8();
+    let flags = (0..query_len).map(|_| AtomicBool::new(false)).collect_vec();
+    let progress = options.progress_reporter();
+    progress.start_in_groups(Phase::DistanceComputation, convergence_groups.len());
+
+    convergence_groups
+        .par_iter()
+        .with_min_len(1000)
+        .for_each_init(
+            || progress.batch(),
+            |batch, group| {
+                if options.is_cancelled() {
+                    return;
+                }
+                batch.advance(1);
+                for (qi, ri) in candidates(group) {
+                    let flag = &flags[qi as usize];
+                    if flag.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                    if distance_range.contains(&dist(qi, ri, max_distance)) {
+                        flag.store(true, Ordering::Relaxed);
+                    }
+                }
+            },
+        );
+
+    flags.into_iter().map(AtomicBool::into_inner).collect()
+}
+
+/// Flag the strings of `query` that have no hits among the candidates that `candidates_of`
+/// yields for each of their deletion variant hashes. Unlike [`get_hit_flags`], the candidates of
+/// each query string are looked up and verified in turn, each only once, until its first hit.
+fn get_orphan_mask<I>(
+    query: &[impl AsSymbols],
+    max_distance: MaxDistance,
+    anchor: Anchoring,
+    hash_builder: &(impl BuildHasher<Hasher: Clone> + Sync),
+    options: &SearchOptions,
+    candidates_of: impl Fn(u64) -> I + Sync,
+    dist: impl Fn(u32, u32, MaxDistance) -> u8 + Sync,
+) -> Vec<bool>
+where
+    I: Iterator<Item = u32>,
+{
+    let distance_range = options.effective_min_distance()..=max_distance.as_u8();
+    let progress = options.progress_reporter();
+    progress.start_in_groups(Phase::DistanceComputation, query.len());
+
+    query
+        .par_iter()
+        .enumerate()
+        .with_min_len(100)
+        .map_init(
+            || {
+                let scratch = VariantScratch::default();
+                (scratch, Vec::new(), HashSet::new(), progress.batch())
+            },
+            |(scratch, hashes, seen, batch), (qi, s)| {
+                if options.is_cancelled() {
+                    return false;
+                }
+                batch.advance(1);
+                hashes.clear();
+                seen.clear();
+                for_each_variant_hash(
+                    s.as_symbols(),
+                    max_distance,
+                    anchor,
+                    hash_builder,
+                    scratch,
+                    |hash| hashes.push(hash),
+                );
+                !hashes
+                    .iter()
+                    .flat_map(|&hash| candidates_of(hash))
+                    .filter(|&ri| seen.insert(ri))
+                    .any(|ri| distance_range.contains(&dist(qi as u32, ri, max_distance)))
+            },
+        )
+        .collect()
+}
+
+/// Count the true hits of each query index, i.e. the candidate pairs that are between
+/// `min_distance` and `max_distance` apart. If `symmetric`, each hit is also counted towards its
+/// reference index.
+fn count_true_hits(
+    hit_candidates: &[(u32, u32)],
+    dists: &[u8],
+    query_len: usize,
+    max_distance: MaxDistance,
+    min_distance: u8,
+    symmetric: bool,
+) -> Vec<u32> {
+    let is_hit = |d: u8| (min_distance..=max_distance.as_u8()).contains(&d);
+    let counts = (0..query_len).map(|_| AtomicU32::new(0)).collect_vec();
+
+    hit_candidates
+        .par_iter()
+        .zip(dists.par_iter())
+        .with_min_len(100000)
+        .for_each(|(&(qi, ri), &d)| {
+            if !is_hit(d) {
+                return;
+            }
+            counts[qi as usize].fetch_add(1, Ordering::Relaxed);
+            if symmetric {
+                counts[ri as usize].fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+    counts.into_iter().map(AtomicU32::into_inner).collect()
+}
+
+/// Examine and double check hits to see if they are real, i.e. between `min_distance` and
+/// `max_distance` apart.
+fn collect_true_hits(
+    hit_candidates: &[(u32, u32)],
+    dists: &[u8],
+    max_distance: MaxDistance,
+    min_distance: u8,
+) -> NeighborPairs {
+    collect_true_hits_into(
+        hit_candidates,
+        dists,
+        max_distance,
+        min_distance,
+        NeighborPairs::default(),
+    )
+}
+
+/// Equivalent to [`collect_true_hits`], reusing the allocations of `buffer` for the result.
+fn collect_true_hits_into(
+    hit_candidates: &[(u32, u32)],
+    dists: &[u8],
+    max_distance: MaxDistance,
+    min_distance: u8,
+    buffer: NeighborPairs,
+) -> NeighborPairs {
+    const CHUNK_SIZE: usize = 1 << 16;
+    let is_hit = |d: u8| (min_distance..=max_distance.as_u8()).contains(&d);
+
+    let num_hits_per_chunk = dists
+        .par_chunks(CHUNK_SIZE)
+        .map(|chunk| chunk.iter().filter(|&&d| is_hit(d)).count())
+        .collect::<Vec<_>>();
+    let total_num_hits = num_hits_per_chunk.iter().sum();
+
+    let mut qi_filtered_uninit = reuse_as_maybeuninit_vec(buffer.row, total_num_hits);
+    let mut ri_filtered_uninit = reuse_as_maybeuninit_vec(buffer.col, total_num_hits);
+    let mut dists_filtered_uninit = reuse_as_maybeuninit_vec(buffer.dists, total_num_hits);
+    let qi_chunks = get_disjoint_chunks_mut(&num_hits_per_chunk, &mut qi_filtered_uninit);
+    let ri_chunks = get_disjoint_chunks_mut(&num_hits_per_chunk, &mut ri_filtered_uninit);
+    let dists_chunks = get_disjoint_chunks_mut(&num_hits_per_chunk, &mut dists_filtered_uninit);
+
+    hit_candidates
+        .par_chunks(CHUNK_SIZE)
+        .zip(dists.par_chunks(CHUNK_SIZE))
+        .zip(qi_chunks.into_par_iter())
+        .zip(ri_chunks.into_par_iter())
+        .zip(dists_chunks.into_par_iter())
+        .for_each(
+            |((((candidates, dists), qi_chunk), ri_chunk), dists_chunk)| {
+                let mut i = 0;
+                for (&(qi, ri), &d) in candidates.iter().zip(dists.iter()) {
+                    if !is_hit(d) {
+                        continue;
+                    }
+                    qi_chunk[i].write(qi);
+                    ri_chunk[i].write(ri);
+                    dists_chunk[i].write(d);
+                    i += 1;
+                }
+                debug_assert_eq!(i, qi_chunk.len());
+            },
+        );
+
+    NeighborPairs {
+        row: unsafe { cast_to_initialised_vec(qi_filtered_uninit) },
+        col: unsafe { cast_to_initialised_vec(ri_filtered_uninit) },
+        dists: unsafe { cast_to_initialised_vec(dists_filtered_uninit) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::io::{self, BufRead, Cursor};
+    use std::sync::Mutex;
+
+    /// The system allocator, counting the allocations made by each thread for benchmarks of
+    /// buffer reuse.
+    struct CountingAllocator;
+
+    thread_local! {
+        static NUM_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    impl CountingAllocator {
+        fn num_allocations() -> usize {
+            NUM_ALLOCATIONS.with(Cell::get)
+        }
+
+        fn count() {
+            let _ = NUM_ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        }
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            Self::count();
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            Self::count();
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    // component tests
+
+    #[test]
+    fn test_nck() {
+        let cases = [(5, 2, 10), (5, 5, 1), (5, 0, 1)];
+        for (n, k, expected) in cases {
+            let result = get_num_k_combs(n, k);
+            assert_eq!(result, Some(expected));
+        }
+        assert_eq!(get_num_k_combs(0, 0), Some(1));
+        assert_eq!(get_num_k_combs(100, 50), None);
+    }
+
+    #[test]
+    fn test_variant_index_pair() {
+        assert_eq!(size_of::<VariantIndexPair<u32>>(), 12);
+        assert_eq!(size_of::<VariantIndexPair<CrossIndex>>(), 12);
+        assert_eq!(size_of::<VariantIndexPair<u32, 4>>(), 20);
+
+        let tuples = [
+            (0, 0),
+            (0, 1),
+            (1, u32::MAX),
+            (u32::MAX as u64, 0),
+            (1 << 32, 0),
+            (u64::MAX, 2),
+        ];
+        for (hash, index) in tuples {
+            let pair = VariantIndexPair::new(hash, index);
+            assert_eq!((pair.hash(), pair.index()), (hash, index));
+        }
+        for a in tuples {
+            for b in tuples {
+                assert_eq!(
+                    VariantIndexPair::new(a.0, a.1).cmp(&VariantIndexPair::new(b.0, b.1)),
+                    a.cmp(&b)
+                );
+            }
+        }
+    }
+
+    fn for_each_deletion_variant(
+        input: &[u8],
+        max_deletions: MaxDistance,
+        mut f: impl FnMut(&[u8]),
+    ) {
+        let mut deletion_indices = [0; u8::MAX as usize];
+        let mut buffer = vec![0; input.len()];
+        let mut variants = DeletionVariantWriter::new(input, max_deletions, &mut deletion_indices);
+        while let Some(variant_length) = variants.write_next(&mut buffer) {
+            f(&buffer[..variant_length]);
+        }
+    }
+
+    fn naive_deletion_variants(input: &[u8], max_deletions: u8) -> Vec<Vec<u8>> {
+        let mut variants = Vec::new();
+        for k in 1..=(max_deletions as usize).min(input.len()) {
+            for deletion_indices in (0..input.len()).combinations(k) {
+                variants.push(
+                    (0..input.len())
+                        .filter(|i| !deletion_indices.contains(i))
+                        .map(|i| input[i])
+                        .collect_vec(),
+                );
+            }
+        }
+        variants
+    }
+
+    #[test]
+    fn test_for_each_deletion_variant() {
+        let long = "CASSLAPGATNEKLFFCASSLAPGATNEKLFFCASSLAPGATNEKLFFCASSLAPGATNEKLFFCASS";
+        assert!(long.len() > 64);
+
+        for input in ["", "a", "ab", "fizz", "CASSLAPGATNEKLFF", long] {
+            for max_deletions in [1, 2, 3] {
+                let mut variants = Vec::new();
+                for_each_deletion_variant(
+                    input.as_bytes(),
+                    MaxDistance::try_from(max_deletions).unwrap(),
+                    |variant| variants.push(variant.to_vec()),
+                );
+                assert_eq!(
+                    variants,
+                    naive_deletion_variants(input.as_bytes(), max_deletions),
+                    "input={input} max_deletions={max_deletions}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_per_string_variant_dedup() {
+        let hash_builder = FixedState::default();
+        let max_distance = MaxDistance::try_from(2).unwrap();
+        let mut scratch = VariantScratch::default();
+
+        // "AAAA" only has one distinct variant per number of deletions.
+        let num_vars = get_num_del_vars_per_string(&["AAAA"], max_distance, Anchoring::default());
+        let mut chunk = prealloc_maybeuninit_vec::<VariantIndexPair<u32>>(num_vars[0]);
+        let num_distinct = write_vi_pairs_rawidx(
+            Symbols::Text("AAAA"),
+            0,
+            max_distance,
+            Anchoring::default(),
+            &mut chunk,
+            &hash_builder,
+            0,
+            &mut scratch,
+        );
+        assert_eq!((num_vars[0], num_distinct), (11, 3));
+
+        // Compacting the chunks leaves the same pairs as deduplicating the sorted whole.
+        let strings = ["AAAA", "CASSLAAF", "", "ABAB", "A"];
+        let num_vars = get_num_del_vars_per_string(&strings, max_distance, Anchoring::default());
+        let mut pairs = prealloc_maybeuninit_vec(num_vars.iter().sum());
+        let chunks = get_disjoint_chunks_mut(&num_vars, &mut pairs);
+        let num_distinct = strings
+            .iter()
+            .zip(chunks)
+            .enumerate()
+            .map(|(idx, (s, chunk))| {
+                write_vi_pairs_rawidx(
+                    Symbols::Text(s),
+                    idx as u32,
+                    max_distance,
+                    Anchoring::default(),
+                    chunk,
+                    &hash_builder,
+                    0,
+                    &mut scratch,
+                )
+            })
+            .collect_vec();
+        let mut pairs: Vec<VariantIndexPair<u32>> = unsafe { cast_to_initialised_vec(pairs) };
+        let mut expected = pairs.clone();
+        expected.sort_unstable();
+        expected.dedup();
+        compact_chunks(&mut pairs, &num_vars, &num_distinct);
+        assert!(pairs.len() < num_vars.iter().sum());
+        pairs.sort_unstable();
+        assert!(pairs == expected);
+        let sorted = get_sorted_vi_pairs_rawidx(
+            &strings,
+            0,
+            max_distance,
+            &hash_builder,
+            Progress::default(),
+        );
+        assert!(sorted == expected);
+    }
+
+    #[test]
+    fn test_single_deletion_variants() {
+        for input in ["", "a", "ab", "fizz", "CASSLAPGATNEKLFF"] {
+            let mut variants = Vec::new();
+            let mut buf = vec![0; input.len()];
+            for_each_single_deletion_variant(input.as_bytes(), &mut buf, |variant| {
+                variants.push(variant.to_vec())
+            });
+            assert_eq!(
+                variants,
+                naive_deletion_variants(input.as_bytes(), 1),
+                "input={input}"
+            );
+        }
+
+        // The specialised path hashes the input and its variants like the general one does.
+        let max_deletions = MaxDistance::try_from(1).unwrap();
+        let hash_builder = foldhash::fast::FixedState::default();
+        let input = b"CASSLAPGATNEKLFF";
+        let mut hashes = Vec::new();
+        for_each_byte_variant_hash(
+            input,
+            max_deletions,
+            &hash_builder,
+            &mut VariantScratch::default(),
+            |hash| hashes.push(hash),
+        );
+        let expected = [input.to_vec()]
+            .into_iter()
+            .chain(naive_deletion_variants(input, 1))
+            .map(|variant| {
+                let mut hasher = hash_builder.build_hasher();
+                hasher.write(&variant);
+                hasher.finish()
+            })
+            .collect_vec();
+        assert_eq!(hashes, expected);
+    }
+
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_for_each_deletion_variant() {
+        use std::time::Instant;
+
+        let strings = bytes_as_ascii_lines(include_bytes!("../../test_files/cdr3b_10k_a.txt"));
+        let max_deletions = MaxDistance::try_from(2).unwrap();
+
+        let start = Instant::now();
+        for s in &strings {
+            for_each_deletion_variant(s.as_bytes(), max_deletions, |v| {
+                std::hint::black_box(v);
+            });
+        }
+        let elapsed = start.elapsed();
+
+        // The itertools-based approach previously used, which allocates per combination.
+        let start = Instant::now();
+        for s in &strings {
+            let mut buffer = Vec::with_capacity(s.len());
+            for k in 1..=2 {
+                for deletion_indices in (0..s.len()).combinations(k) {
+                    buffer.clear();
+                    let mut offset = 0;
+                    for idx in deletion_indices {
+                        buffer.extend_from_slice(&s.as_bytes()[offset..idx]);
+                        offset = idx + 1;
+                    }
+                    buffer.extend_from_slice(&s.as_bytes()[offset..]);
+                    std::hint::black_box(&buffer);
+                }
+            }
+        }
+        let itertools_elapsed = start.elapsed();
+
+        assert!(
+            elapsed < itertools_elapsed,
+            "took {elapsed:?}, itertools took {itertools_elapsed:?}"
+        );
+    }
+
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_get_neighbors_across_into() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached = CachedRef::new(&reference, 2).unwrap();
+        let batches = query.chunks(100).cycle().take(1000).collect_vec();
+
+        // Allocations are counted per thread, so the queries are run on a single worker thread.
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let (num_allocations, num_allocations_allocating) = pool.install(|| {
+            let mut scratch = cached.query_scratch();
+            let mut hits = NeighborPairs::default();
+            let start = CountingAllocator::num_allocations();
+            for batch in &batches {
+                cached
+                    .get_neighbors_across_into(batch, 2, &mut scratch, &mut hits)
+                    .unwrap();
+                std::hint::black_box(&hits);
+            }
+            let num_allocations = CountingAllocator::num_allocations() - start;
+
+            let start = CountingAllocator::num_allocations();
+            for batch in &batches {
+                std::hint::black_box(cached.get_neighbors_across(batch, 2).unwrap());
+            }
+            (
+                num_allocations,
+                CountingAllocator::num_allocations() - start,
+            )
+        });
+
+        assert!(
+            num_allocations < num_allocations_allocating,
+            "made {num_allocations} allocations, allocating made {num_allocations_allocating}"
+        );
+    }
+
+    #[test]
+    fn test_deletion_variants() {
+        let mut state = 0x5851_f42d_4c95_7f2d_u64;
+        let mut next = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as usize
+        };
+        let hash_builder = FixedState::default();
+
+        for _ in 0..2000 {
+            let len = next() % 9;
+            let input = (0..len).map(|_| ["a", "b", "c"][next() % 3]).join("");
+            for max_deletions in 0..=3 {
+                let variants = deletion_variants(&input, max_deletions)
+                    .unwrap()
+                    .collect_vec();
+                assert_eq!(variants[0], input.as_bytes());
+                assert!(variants.iter().all_unique(), "input={input}");
+                let expected = iter::once(input.as_bytes().to_vec())
+                    .chain(naive_deletion_variants(input.as_bytes(), max_deletions))
+                    .sorted()
+                    .dedup()
+                    .collect_vec();
+                assert_eq!(
+                    variants.iter().map(|v| v.to_vec()).sorted().collect_vec(),
+                    expected,
+                    "input={input} max_deletions={max_deletions}"
+                );
+
+                // The hashes agree with those used internally, minus the duplicates.
+                let hashes = deletion_variant_hashes(&input, max_deletions, &hash_builder)
+                    .unwrap()
+                    .sorted()
+                    .collect_vec();
+                let mut internal = Vec::new();
+                for_each_variant_hash(
+                    Symbols::Text(&input),
+                    MaxDistance::try_from(max_deletions).unwrap(),
+                    Anchoring::default(),
+                    &hash_builder,
+                    &mut VariantScratch::default(),
+                    |hash| internal.push(hash),
+                );
+                internal.sort_unstable();
+                internal.dedup();
+                assert_eq!(hashes, internal);
+            }
+        }
+
+        let variants = deletion_variants("日本本", 2).unwrap().collect_vec();
+        let expected = ["日本本", "本本", "日本", "本", "日"].map(str::as_bytes);
+        assert_eq!(variants, expected);
+        assert!(matches!(
+            deletion_variants("fizz", 255),
+            Err(Error::MaxDistCapped)
+        ));
+    }
+
+    #[test]
+    fn test_for_each_variant_hash() {
+        let hash_builder = FixedState::default();
+        let long = "CASSLAPGATNEKLFF".repeat(300);
+        assert!(long.len() > 4096);
+
+        for input in ["", "a", "fizz", "CASSLAPGATNEKLFF", &long[..70], &long] {
+            for max_deletions in [1, 2, 3] {
+                if input.len() > 1000 && max_deletions > 1 {
+                    continue;
+                }
+                let mut hashes = Vec::new();
+                for_each_variant_hash(
+                    Symbols::Text(input),
+                    MaxDistance::try_from(max_deletions).unwrap(),
+                    Anchoring::default(),
+                    &hash_builder,
+                    &mut VariantScratch::default(),
+                    |hash| hashes.push(hash),
+                );
+
+                let expected = std::iter::once(input.as_bytes().to_vec())
+                    .chain(naive_deletion_variants(input.as_bytes(), max_deletions))
+                    .map(|variant| {
+                        let mut hasher = hash_builder.build_hasher();
+                        hasher.write(&variant);
+                        hasher.finish()
+                    })
+                    .collect_vec();
+                assert_eq!(
+                    hashes, expected,
+                    "input={input} max_deletions={max_deletions}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_write_vi_pairs_rawidx() {
+        use std::time::{Duration, Instant};
+        type Pairs = [MaybeUninit<VariantIndexPair<u32>>];
+
+        let strings = bytes_as_ascii_lines(include_bytes!("../../test_files/cdr3b_10k_a.txt"));
+        let max_deletions = MaxDistance::try_from(2).unwrap();
+        let hash_builder = FixedState::default();
+        let num_vars_per_string =
+            get_num_del_vars_per_string(&strings, max_deletions, Anchoring::default());
+        let mut pairs = prealloc_maybeuninit_vec(num_vars_per_string.iter().sum());
+
+        let batched = |pairs: &mut Pairs| {
+            let mut scratch = VariantScratch::default();
+            let chunks = get_disjoint_chunks_mut(&num_vars_per_string, pairs);
+            for (idx, (s, chunk)) in strings.iter().zip(chunks).enumerate() {
+                write_vi_pairs_rawidx(
+                    s.as_symbols(),
+                    idx as u32,
+                    max_deletions,
+                    Anchoring::default(),
+                    chunk,
+                    &hash_builder,
+                    0,
+                    &mut scratch,
+                );
+            }
+        };
+        let fresh_scratch = |pairs: &mut Pairs| {
+            let chunks = get_disjoint_chunks_mut(&num_vars_per_string, pairs);
+            for (idx, (s, chunk)) in strings.iter().zip(chunks).enumerate() {
+                write_vi_pairs_rawidx(
+                    s.as_symbols(),
+                    idx as u32,
+                    max_deletions,
+                    Anchoring::default(),
+                    chunk,
+                    &hash_builder,
+                    0,
+                    &mut VariantScratch::default(),
+                );
+            }
+        };
+        // Building a fresh hasher for each variant as it is generated.
+        let hash_string = |s: &[u8]| {
+            let mut hasher = hash_builder.build_hasher();
+            hasher.write(s);
+            hasher.finish()
+        };
+        let unbatched = |pairs: &mut Pairs| {
+            let mut deletion_indices = [0; u8::MAX as usize];
+            let mut buffer = [0; 64];
+            let chunks = get_disjoint_chunks_mut(&num_vars_per_string, pairs);
+            for (idx, (s, chunk)) in strings.iter().zip(chunks).enumerate() {
+                chunk[0].write(VariantIndexPair::new(hash_string(s.as_bytes()), idx as u32));
+                let mut variants =
+                    DeletionVariantWriter::new(s.as_bytes(), max_deletions, &mut deletion_indices);
+                let mut variant_idx = 1;
+                while let Some(variant_length) = variants.write_next(&mut buffer) {
+                    let hash = hash_string(&buffer[..variant_length]);
+                    chunk[variant_idx].write(VariantIndexPair::new(hash, idx as u32));
+                    variant_idx += 1;
+                }
+            }
+        };
+
+        let mut time = |f: &dyn Fn(&mut Pairs)| {
+            f(&mut pairs);
+            (0..10)
+                .map(|_| {
+                    let start = Instant::now();
+                    f(&mut pairs);
+                    start.elapsed()
+                })
+                .min()
+                .unwrap_or(Duration::ZERO)
+        };
+        let elapsed = time(&batched);
+        let fresh_scratch_elapsed = time(&fresh_scratch);
+        let unbatched_elapsed = time(&unbatched);
+
+        assert!(
+            elapsed < fresh_scratch_elapsed && elapsed < unbatched_elapsed,
+            "took {elapsed:?}, with fresh scratch space per string took {fresh_scratch_elapsed:?}, \
+             unbatched took {unbatched_elapsed:?}"
+        );
+    }
+
+    #[test]
+    fn test_get_filtered_hit_candidates() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let max_distance = MaxDistance::try_from(2).unwrap();
+        let variant_index_pairs = get_sorted_vi_pairs_rawidx(
+            &query,
+            0,
+            max_distance,
+            &FixedState::default(),
+            Progress::default(),
+        );
+        let groups = variant_index_pairs
+            .chunk_by(|a, b| a.hash() == b.hash())
+            .filter(|group| group.len() > 1)
+            .map(|group| group.iter().map(VariantIndexPair::index).collect_vec())
+            .collect_vec();
+
+        let unfiltered = groups
+            .iter()
+            .flat_map(|group| group.iter().copied().tuple_combinations())
+            .collect_vec();
+        let filtered = get_filtered_hit_candidates(
+            &groups,
+            |group| get_num_k_combs(group.len(), 2).unwrap_or(usize::MAX) as u128,
+            |group| (group.len(), group.len()),
+            |group| group.iter().copied().tuple_combinations(),
+            None,
+            &SearchOptions::default(),
+            Vec::new(),
+        )
+        .expect("few candidates")
+        .0;
+
+        let mut expected = unfiltered.clone();
+        expected.sort_unstable();
+        expected.dedup();
+        let mut deduped = filtered.clone();
+        deduped.sort_unstable();
+        deduped.dedup();
+        assert_eq!(deduped, expected);
+
+        // Most of the duplicates should already be gone before the global sort.
+        let num_duplicates = unfiltered.len() - expected.len();
+        let num_remaining_duplicates = filtered.len() - expected.len();
+        assert!(
+            num_remaining_duplicates * 4 < num_duplicates,
+            "{num_remaining_duplicates} of {num_duplicates} duplicates remain"
+        );
+    }
+
+    #[test]
+    fn test_streamed_groups() {
+        // A group too large to collect the candidates of, amid groups that are collected whole.
+        let oversized = (0..3000).collect_vec();
+        assert!(get_num_k_combs(oversized.len(), 2).unwrap() > STREAMED_GROUP_MIN_CANDIDATES);
+        let groups = vec![vec![0, 1, 2], oversized, vec![5, 3001], vec![3002, 3003]];
+        let dist = |a: u32, b: u32| {
+            if (a + b).is_multiple_of(7) {
+                ((b - a) % 5) as u8
+            } else {
+                u8::MAX
+            }
+        };
+
+        let mut expected = [(0, 1), (0, 2), (1, 2), (5, 3001), (3002, 3003)]
+            .into_iter()
+            .chain(
+                (0..3000)
+                    .tuple_combinations()
+                    .filter(|&(a, b)| dist(a, b) != u8::MAX),
+            )
+            .collect_vec();
+        expected.sort_unstable();
+        expected.dedup();
+
+        for streamed in [false, true] {
+            let (mut candidates, known) = get_filtered_hit_candidates(
+                &groups,
+                |group| get_num_k_combs(group.len(), 2).unwrap_or(usize::MAX) as u128,
+                |group| (group.len(), group.len()),
+                |group| group.iter().copied().tuple_combinations(),
+                streamed.then_some(&dist as &(dyn Fn(u32, u32) -> u8 + Sync)),
+                &SearchOptions::default(),
+                Vec::new(),
+            )
+            .expect("few candidates");
+            candidates.sort_unstable();
+            candidates.dedup();
+            if streamed {
+                assert_eq!(candidates, expected);
+                // The distances of the streamed hits are known, and only theirs.
+                for &(a, b) in &candidates {
+                    let got = known.get_or((a, b), || u8::MAX - 1);
+                    if b < 3000 && dist(a, b) != u8::MAX {
+                        assert_eq!(got, dist(a, b));
+                    } else {
+                        assert_eq!(got, u8::MAX - 1);
+                    }
+                }
+            } else {
+                assert!(known.0.is_empty());
+                // The pairs of the first group are also candidates of the oversized one.
+                assert_eq!(candidates.len(), 4_498_500 + 2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_streamed_groups_unverified() {
+        // Oversized groups sharing most of their strings, whose candidates are thus largely
+        // duplicates of each other's.
+        let groups = (0..4).map(|i| (i..3000 + i).collect_vec()).collect_vec();
+        let num_candidates = get_num_k_combs(3000, 2).unwrap();
+        assert!(num_candidates > STREAMED_GROUP_MIN_CANDIDATES);
+
+        let (candidates, known) = get_filtered_hit_candidates(
+            &groups,
+            |group| get_num_k_combs(group.len(), 2).unwrap_or(usize::MAX) as u128,
+            |group| (group.len(), group.len()),
+            |group| group.iter().copied().tuple_combinations(),
+            None,
+            &SearchOptions::default(),
+            Vec::new(),
+        )
+        .expect("few candidates");
+        assert!(known.0.is_empty());
+
+        // The candidates are returned once rather than once per group, in memory bounded by their
+        // number. They are all pairs of 0..3003, but those more than 2999 apart.
+        let num_expected = get_num_k_combs(3003, 2).unwrap() - 6;
+        assert_eq!(candidates.len(), num_expected);
+        assert!(candidates
+            .iter()
+            .all(|&(a, b)| a < b && b - a < 3000 && b < 3003));
+        assert!(candidates.is_sorted_by(|a, b| a < b));
+        assert!(
+            candidates.capacity() < 2 * num_expected,
+            "{} for {num_expected} candidates",
+            candidates.capacity(),
+        );
+    }
+
+    #[test]
+    fn test_candidate_guard() {
+        // All length-1 strings share the empty deletion variant at d=1.
+        let letters = (b'a'..=b'z').map(|c| (c as char).to_string()).collect_vec();
+        let query = letters.iter().cycle().take(3000).collect_vec();
+        let reference = letters.iter().cycle().take(2000).collect_vec();
+        let options = SearchOptions {
+            max_candidates: Some(1_000_000),
+            ..Default::default()
+        };
+
+        let result = get_neighbors_within_with_options(&query, 1, &options);
+        assert!(matches!(
+            result,
+            Err(Error::TooManyCandidates {
+                got,
+                limit: 1_000_000,
+                largest_group: (3000, 3000),
+            }) if got > 3000 * 2999 / 2
+        ));
+
+        let result = get_neighbors_across_with_options(&query, &reference, 1, &options);
+        assert!(matches!(
+            result,
+            Err(Error::TooManyCandidates {
+                largest_group: (3000, 2000),
+                ..
+            })
+        ));
+
+        let cached = CachedRef::new(&reference, 1).expect("short input");
+        let result = cached.get_neighbors_across_with_options(&query, 1, &options);
+        assert!(matches!(
+            result,
+            Err(Error::TooManyCandidates {
+                largest_group: (3000, 2000),
+                ..
+            })
+        ));
+
+        let hits = get_neighbors_within_with_options(&query[..1000], 1, &options)
+            .expect("under the limit");
+        assert_eq!(hits.row.len(), 1000 * 999 / 2);
+    }
+
+    #[test]
+    fn test_par_dedup() {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            state >> 33
+        };
+
+        for len in [0, 1, 2, 3, 10, 100, 1000] {
+            for num_distinct in [1, 2, 5, 50, 1000] {
+                let mut sorted = (0..len)
+                    .map(|_| (next() % num_distinct, (next() % 3) as u32))
+                    .collect_vec();
+                sorted.sort_unstable();
+                let mut expected = sorted.clone();
+                expected.dedup();
+
+                for chunk_size in [1, 2, 3, 7, 64, 1 << 16] {
+                    let mut deduped = sorted.clone();
+                    par_dedup_by_chunks(&mut deduped, chunk_size);
+                    assert_eq!(deduped, expected, "len={len} chunk_size={chunk_size}");
+                }
+
+                let mut deduped = sorted.clone();
+                par_dedup(&mut deduped);
+                assert_eq!(deduped, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_par_radix_sort() {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            state
+        };
+
+        for len in [
+            0,
+            1,
+            1000,
+            RADIX_SORT_MIN_LEN - 1,
+            RADIX_SORT_MIN_LEN,
+            300_000,
+        ] {
+            // Full-width hashes, with repeats.
+            let mut pairs = (0..len)
+                .map(|_| (next() | 0xff, (next() % 3) as u32))
+                .collect_vec();
+            let mut expected = pairs.clone();
+            expected.sort_unstable();
+            par_radix_sort(&mut pairs);
+            assert_eq!(pairs, expected, "len={len}");
+
+            // Narrow and skewed prefixes, such as those of candidate pairs.
+            let mut candidates = (0..len)
+                .map(|_| {
+                    let row = (next() >> 40) as u32 % 5000;
+                    (row, row + 1 + (next() >> 40) as u32 % 50)
+                })
+                .collect_vec();
+            let mut expected = candidates.clone();
+            expected.sort_unstable();
+            par_radix_sort(&mut candidates);
+            assert_eq!(candidates, expected, "len={len}");
+
+            // Prefixes of few bits, fewer than either pass would partition by.
+            let mut pairs = (0..len)
+                .map(|_| (next() % 1000, (next() % 3) as u32))
+                .collect_vec();
+            let mut expected = pairs.clone();
+            expected.sort_unstable();
+            par_radix_sort(&mut pairs);
+            assert_eq!(pairs, expected, "len={len}");
+
+            // A single prefix, which leaves everything to the comparison sort of one bucket.
+            let mut pairs = (0..len)
+                .map(|_| VariantIndexPair::<u32, 4>::from_halves(7, next(), next() as u32))
+                .collect_vec();
+            let mut expected = pairs.clone();
+            expected.sort_unstable();
+            par_radix_sort(&mut pairs);
+            assert!(pairs == expected, "len={len}");
+        }
+    }
+
+    #[test]
+    fn test_get_convergence_groups_cross_merged() {
+        let max_distance = MaxDistance::try_from(2).unwrap();
+        let hash_builder = FixedState::default();
+        let mut scratch = VariantScratch::default();
+        let mut variant_index_pairs = Vec::new();
+        for (strings, is_ref) in [(&TEST_QUERY[..], false), (&TEST_REF[..], true)] {
+            for (idx, s) in strings.iter().enumerate() {
+                let num_vars =
+                    get_num_del_vars_per_string(&[s], max_distance, Anchoring::default())[0];
+                let mut chunk = prealloc_maybeuninit_vec(num_vars);
+                let num_distinct = write_vi_pairs_ci(
+                    s.as_symbols(),
+                    CrossIndex::from(idx as u32, is_ref),
+                    max_distance,
+                    Anchoring::default(),
+                    &mut chunk,
+                    &hash_builder,
+                    &mut scratch,
+                );
+                chunk.truncate(num_distinct);
+                variant_index_pairs.extend(unsafe { cast_to_initialised_vec(chunk) });
+            }
+        }
+
+        let mut sorted = variant_index_pairs.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        let mut expected = (Vec::new(), Vec::new());
+        for group in sorted.chunk_by(|a, b| a.hash() == b.hash()) {
+            push_convergence_group_cross(group, &mut expected.0, &mut expected.1);
+        }
+        assert!(!expected.1.is_empty());
+
+        for run_len in [1, 3, 16, variant_index_pairs.len()] {
+            for num_ranges in [1, 2, 7] {
+                let mut pairs = variant_index_pairs.clone();
+                let groups = get_convergence_groups_cross_merged(&mut pairs, run_len, num_ranges);
+                assert_eq!(
+                    groups, expected,
+                    "run_len={run_len} num_ranges={num_ranges}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_num_del_vars_per_string() {
+        let strings = ["foo".to_string(), "bar".to_string(), "baz".to_string()];
+        let result = get_num_del_vars_per_string(
+            &strings,
+            MaxDistance::try_from(1).expect("legal"),
+            Anchoring::default(),
+        );
+        assert_eq!(result, vec![4, 4, 4]);
+    }
+
+    const TEST_QUERY: [&str; 5] = ["fizz", "fuzz", "buzz", "izzy", "lofi"];
+    const TEST_REF: [&str; 3] = ["file", "tofu", "fizz"];
+
+    #[test]
+    fn test_compute_dists() {
+        let cases = [
+            (
+                (0..5).tuple_combinations().collect_vec(),
+                &TEST_QUERY[..],
+                MaxDistance::try_from(1).expect("legal"),
+                vec![1, 255, 255, 255, 1, 255, 255, 255, 255, 255],
+            ),
+            (
+                (0..5).tuple_combinations().collect_vec(),
+                &TEST_QUERY[..],
+                MaxDistance::try_from(2).expect("legal"),
+                vec![1, 2, 2, 255, 1, 255, 255, 255, 255, 255],
+            ),
+            (
+                (0..5).cartesian_product(0..3).collect_vec(),
+                &TEST_REF[..],
+                MaxDistance::try_from(1).expect("legal"),
+                vec![
+                    255, 255, 0, 255, 255, 1, 255, 255, 255, 255, 255, 255, 255, 255, 255,
+                ],
+            ),
+            (
+                (0..5).cartesian_product(0..3).collect_vec(),
+                &TEST_REF[..],
+                MaxDistance::try_from(2).expect("legal"),
+                vec![
+                    2, 255, 0, 255, 255, 1, 255, 255, 2, 255, 255, 2, 255, 2, 255,
+                ],
+            ),
+        ];
+
+        for (candidates, reference, mdist, expected) in cases {
+            let results = compute_dists(
+                &candidates,
+                &KnownDists::default(),
+                &TEST_QUERY,
+                reference,
+                mdist,
+                &SearchOptions::default(),
+            );
+            assert_eq!(results, expected);
+        }
+    }
+
+    #[test]
+    fn test_compute_dists_batched() {
+        // Rows long enough to be compared via a query comparator, spanning several blocks.
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let candidates = (0..3).cartesian_product(0..5000).collect_vec();
+        let cached_query = CachedRef::new(&query[..3], 1).expect("legal");
+        let cached_reference = CachedRef::new(&reference[..5000], 1).expect("legal");
+        let metrics = [
+            Metric::Levenshtein,
+            Metric::WeightedLevenshtein(EditCosts::new(2, 1, 1).expect("legal")),
+            Metric::Osa,
+        ];
+        for (metric, mdist) in metrics.into_iter().cartesian_product([1, 2, 4, 6]) {
+            let mdist = MaxDistance::try_from(mdist).expect("legal");
+            let options = SearchOptions {
+                metric,
+                ..Default::default()
+            };
+            let expected = candidates
+                .iter()
+                .map(|&(qi, ri)| {
+                    options.bounded_dist(
+                        qi,
+                        ri,
+                        query[qi as usize].as_symbols(),
+                        reference[ri as usize].as_symbols(),
+                        mdist,
+                    )
+                })
+                .collect_vec();
+            let known = KnownDists::default();
+            assert_eq!(
+                compute_dists(&candidates, &known, &query, &reference, mdist, &options),
+                expected
+            );
+
+            // As are those of cached references.
+            assert_eq!(
+                cached_reference.compute_dists_partially_cached(
+                    &candidates,
+                    &known,
+                    &query,
+                    mdist,
+                    &options,
+                    Vec::new()
+                ),
+                expected
+            );
+            assert_eq!(
+                cached_reference.compute_dists_fully_cached(
+                    &candidates,
+                    &known,
+                    &cached_query,
+                    mdist,
+                    &options
+                ),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_compute_dists_known() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let candidates = (0..3).cartesian_product(0..5000).collect_vec();
+        let mdist = MaxDistance::try_from(2).expect("legal");
+        let options = SearchOptions::default();
+        let expected = compute_dists(
+            &candidates,
+            &KnownDists::default(),
+            &query,
+            &reference,
+            mdist,
+            &options,
+        );
+
+        // Known distances are taken as they are.
+        let known = KnownDists::new(
+            candidates
+                .iter()
+                .step_by(7)
+                .map(|&candidate| (candidate, 3))
+                .collect(),
+        );
+        let expected = expected
+            .iter()
+            .enumerate()
+            .map(|(i, &dist)| if i % 7 == 0 { 3 } else { dist })
+            .collect_vec();
+        assert_eq!(
+            compute_dists(&candidates, &known, &query, &reference, mdist, &options),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_get_true_hits() {
+        let cases = [
+            (
+                (0..5).tuple_combinations().collect_vec(),
+                vec![1, 255, 255, 255, 1, 255, 255, 255, 255, 255],
+                MaxDistance::try_from(1).expect("legal"),
+                NeighborPairs {
+                    row: vec![0, 1],
+                    col: vec![1, 2],
+                    dists: vec![1, 1],
+                },
+            ),
+            (
+                (0..5).tuple_combinations().collect_vec(),
+                vec![1, 2, 2, 255, 1, 255, 255, 255, 255, 255],
+                MaxDistance::try_from(2).expect("legal"),
+                NeighborPairs {
+                    row: vec![0, 0, 0, 1],
+                    col: vec![1, 2, 3, 2],
+                    dists: vec![1, 2, 2, 1],
+                },
+            ),
+        ];
+
+        for (candidates, dists, mdist, expected) in cases {
+            let result = collect_true_hits(&candidates, &dists, mdist, 0);
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_get_true_hits_randomized() {
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        let mut next = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as u32
+        };
+
+        for num_candidates in [0, 1, 1000, (1 << 16) + 1, 300_000] {
+            let candidates = (0..num_candidates).map(|_| (next(), next())).collect_vec();
+            let dists = (0..num_candidates)
+                .map(|_| match next() % 4 {
+                    0 => u8::MAX,
+                    n => n as u8,
+                })
+                .collect_vec();
+            let max_distance = MaxDistance::try_from(2).expect("legal");
+
+            let mut expected = NeighborPairs {
+                row: Vec::new(),
+                col: Vec::new(),
+                dists: Vec::new(),
+            };
+            for (&(qi, ri), &d) in candidates.iter().zip(&dists) {
+                if d <= 2 {
+                    expected.row.push(qi);
+                    expected.col.push(ri);
+                    expected.dists.push(d);
+                }
+            }
+
+            let result = collect_true_hits(&candidates, &dists, max_distance, 0);
+            assert_eq!(result, expected);
+            assert_eq!(result.row.capacity(), result.row.len());
+        }
+    }
+
+    #[test]
+    fn test_symdel_within() {
+        let cases = [
+            (
+                1,
+                NeighborPairs {
+                    row: vec![0, 1],
+                    col: vec![1, 2],
+                    dists: vec![1, 1],
+                },
+            ),
+            (
+                2,
+                NeighborPairs {
+                    row: vec![0, 0, 0, 1],
+                    col: vec![1, 2, 3, 2],
+                    dists: vec![1, 2, 2, 1],
+                },
+            ),
+        ];
+        for (mdist, expected) in cases {
+            let result = get_neighbors_within(&TEST_QUERY, mdist).expect("short input");
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_symdel_within_cached() {
+        let cached = CachedRef::new(&TEST_QUERY, 2).expect("short input");
+        let cases = [
+            (
+                1,
+                NeighborPairs {
+                    row: vec![0, 1],
+                    col: vec![1, 2],
+                    dists: vec![1, 1],
+                },
+            ),
+            (
+                2,
+                NeighborPairs {
+                    row: vec![0, 0, 0, 1],
+                    col: vec![1, 2, 3, 2],
+                    dists: vec![1, 2, 2, 1],
+                },
+            ),
+        ];
+        for (mdist, expected) in cases {
+            let result = cached.get_neighbors_within(mdist).expect("legal max dist");
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_symdel_cross() {
+        let cases = [
+            (
+                1,
+                NeighborPairs {
+                    row: vec![0, 1],
+                    col: vec![2, 2],
+                    dists: vec![0, 1],
+                },
+            ),
+            (
+                2,
+                NeighborPairs {
+                    row: vec![0, 0, 1, 2, 3, 4],
+                    col: vec![0, 2, 2, 2, 2, 1],
+                    dists: vec![2, 0, 1, 2, 2, 2],
+                },
+            ),
+        ];
+        for (mdist, expected) in cases {
+            let result = get_neighbors_across(&TEST_QUERY, &TEST_REF, mdist).expect("valid input");
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_get_candidates_cross_partially_cached() {
+        let cached = CachedRef::new(&TEST_REF, 2).expect("short input");
+        let cases = [
+            (
+                1,
+                NeighborPairs {
+                    row: vec![0, 1],
+                    col: vec![2, 2],
+                    dists: vec![0, 1],
+                },
+            ),
+            (
+                2,
+                NeighborPairs {
+                    row: vec![0, 0, 1, 2, 3, 4],
+                    col: vec![0, 2, 2, 2, 2, 1],
+                    dists: vec![2, 0, 1, 2, 2, 2],
+                },
+            ),
+        ];
+        for (mdist, expected) in cases {
+            let result = cached
+                .get_neighbors_across(&TEST_QUERY, mdist)
+                .expect("legal max dist");
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_get_candidates_cross_fully_cached() {
+        let cached_q = CachedRef::new(&TEST_QUERY, 2).expect("short input");
+        let cached_r = CachedRef::new(&TEST_REF, 2).expect("short input");
+        let cases = [
+            (
+                1,
+                NeighborPairs {
+                    row: vec![0, 1],
+                    col: vec![2, 2],
+                    dists: vec![0, 1],
+                },
+            ),
+            (
+                2,
+                NeighborPairs {
+                    row: vec![0, 0, 1, 2, 3, 4],
+                    col: vec![0, 2, 2, 2, 2, 1],
+                    dists: vec![2, 0, 1, 2, 2, 2],
+                },
+            ),
+        ];
+        for (mdist, expected) in cases {
+            let result = cached_r
+                .get_neighbors_across_cached(&cached_q, mdist)
+                .expect("legal max dist");
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_hash_seed() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let expected_within = bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2);
+        let expected_cross = bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2);
+        let default_cached = CachedRef::new(&query, 2).expect("short input");
+        assert_eq!(default_cached.hash_seed(), 0);
+
+        let hashes = |seed| {
+            deletion_variant_hashes("fizz", 1, &FixedState::with_seed(seed))
+                .expect("short input")
+                .collect_vec()
+        };
+        assert_eq!(hashes(0), hashes(0));
+        assert_ne!(hashes(0), hashes(42));
+
+        for hash_seed in [0, 42, u64::MAX] {
+            let options = SearchOptions {
+                hash_seed,
+                brute_force_max_pairs: Some(0),
+                ..Default::default()
+            };
+            assert_eq!(
+                get_neighbors_within_with_options(&query, 2, &options).expect("valid"),
+                expected_within
+            );
+            assert_eq!(
+                get_neighbors_across_with_options(&query, &reference, 2, &options).expect("valid"),
+                expected_cross
+            );
+
+            let mut cached_r =
+                CachedRef::new_with_options(&reference[..5000], 2, &options).expect("short input");
+            assert_eq!(cached_r.hash_seed(), hash_seed);
+            cached_r.extend(&reference[5000..]).expect("short input");
+
+            let mut buffer = Vec::new();
+            cached_r.write_to(&mut buffer).expect("writing to vec");
+            let loaded = CachedRef::read_from(&buffer[..]).expect("valid");
+            assert_eq!(loaded.hash_seed(), hash_seed);
+
+            // Queries are hashed with the seed of the cache, whatever the options say.
+            for cached in [&cached_r, &loaded] {
+                assert_eq!(
+                    cached
+                        .get_neighbors_across_with_options(&query, 2, &SearchOptions::default())
+                        .expect("valid"),
+                    expected_cross
+                );
+                assert_eq!(
+                    cached
+                        .get_neighbors_across_cached(&default_cached, 2)
+                        .expect("valid"),
+                    expected_cross
+                );
+            }
+            let cached_q = CachedRef::new_with_options(&query, 2, &options).expect("short input");
+            assert_eq!(
+                cached_q.get_neighbors_within(2).expect("valid"),
+                expected_within
+            );
+            assert_eq!(
+                cached_r
+                    .get_neighbors_across_cached(&cached_q, 2)
+                    .expect("valid"),
+                expected_cross
+            );
+        }
+    }
+
+    /// A [`BuildHasher`] keeping only the lowest `bits` bits of the hashes of `inner`, so as to
+    /// force collisions between the hashes of distinct deletion variants.
+    struct TruncatedState {
+        inner: FixedState,
+        bits: u32,
+    }
+
+    #[derive(Clone)]
+    struct TruncatedHasher<H> {
+        inner: H,
+        bits: u32,
+    }
+
+    impl BuildHasher for TruncatedState {
+        type Hasher = TruncatedHasher<<FixedState as BuildHasher>::Hasher>;
+
+        fn build_hasher(&self) -> Self::Hasher {
+            TruncatedHasher {
+                inner: self.inner.build_hasher(),
+                bits: self.bits,
+            }
+        }
+    }
+
+    impl<H: Hasher> Hasher for TruncatedHasher<H> {
+        fn write(&mut self, bytes: &[u8]) {
+            self.inner.write(bytes);
+        }
+
+        fn finish(&self) -> u64 {
+            self.inner.finish() & ((1 << self.bits) - 1)
+        }
+    }
+
+    #[test]
+    fn test_wide_hashes() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let max_distance = MaxDistance::try_from(1).unwrap();
+        let truncated = TruncatedState {
+            inner: FixedState::default(),
+            bits: 16,
+        };
+        let num_candidates = |group_sizes: Vec<usize>| -> usize {
+            group_sizes.iter().map(|&n| n * (n - 1) / 2).sum()
+        };
+
+        let narrow: Vec<VariantIndexPair<u32>> = get_sorted_vi_pairs_rawidx_into(
+            &query,
+            0,
+            max_distance,
+            Anchoring::default(),
+            &truncated,
+            None,
+            Progress::default(),
+            Vec::new(),
+        );
+        let wide: Vec<VariantIndexPair<u32, 4>> = get_sorted_vi_pairs_rawidx_into(
+            &query,
+            0,
+            max_distance,
+            Anchoring::default(),
+            &truncated,
+            None,
+            Progress::default(),
+            Vec::new(),
+        );
+        let untruncated = get_sorted_vi_pairs_rawidx(
+            &query,
+            0,
+            max_distance,
+            &FixedState::default(),
+            Progress::default(),
+        );
+
+        let narrow_candidates = num_candidates(get_convergence_groups(&narrow).1);
+        let wide_candidates = num_candidates(get_convergence_groups(&wide).1);
+        let untruncated_candidates = num_candidates(get_convergence_groups(&untruncated).1);
+        assert!(
+            wide_candidates < narrow_candidates / 10,
+            "{wide_candidates} candidates with wide hashes, {narrow_candidates} without"
+        );
+        assert!(wide_candidates >= untruncated_candidates);
+
+        let expected_within = bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_1);
+        let expected_cross = bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_1);
+        for merge_sorted_runs in [false, true] {
+            let options = SearchOptions {
+                wide_hashes: true,
+                merge_sorted_runs,
+                brute_force_max_pairs: Some(0),
+                ..Default::default()
+            };
+            assert_eq!(
+                get_neighbors_within_with_options(&query, 1, &options).expect("valid"),
+                expected_within
+            );
+            assert_eq!(
+                get_neighbors_across_with_options(&query, &reference, 1, &options).expect("valid"),
+                expected_cross
+            );
+        }
+    }
+
+    #[test]
+    fn test_narrow_hashes() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        assert_eq!(size_of::<VariantIndexPair<u32, 1>>(), 8);
+
+        let expected_within = bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2);
+        let expected_cross = bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2);
+        for merge_sorted_runs in [false, true] {
+            let options = SearchOptions {
+                narrow_hashes: true,
+                merge_sorted_runs,
+                brute_force_max_pairs: Some(0),
+                ..Default::default()
+            };
+            assert_eq!(
+                get_neighbors_within_with_options(&query, 2, &options).expect("valid"),
+                expected_within
+            );
+            assert_eq!(
+                get_neighbors_across_with_options(&query, &reference, 2, &options).expect("valid"),
+                expected_cross
+            );
+        }
+
+        let config = SearchConfig::new(2).narrow_hashes(true);
+        let cached_r = config.build_cache(&reference).expect("short input");
+        let cached_q = config.build_cache(&query).expect("short input");
+        assert!(cached_r.narrow_hashes());
+        assert_eq!(
+            config.run_within_cached(&cached_q).expect("valid"),
+            expected_within
+        );
+        assert_eq!(
+            config.run_across_cached(&query, &cached_r).expect("valid"),
+            expected_cross
+        );
+        assert_eq!(
+            cached_r
+                .get_neighbors_across_cached(&cached_q, 2)
+                .expect("valid"),
+            expected_cross
+        );
+
+        let mut buffer = Vec::new();
+        cached_r.write_to(&mut buffer).expect("writing to vec");
+        let loaded = CachedRef::read_from(&buffer[..]).expect("valid data");
+        assert!(loaded.narrow_hashes());
+        assert_eq!(
+            loaded.get_neighbors_across(&query, 2).expect("valid"),
+            expected_cross
+        );
+
+        // The variant table of a narrow cache is keyed by u32, and so takes up less memory.
+        assert_eq!(size_of::<(u32, PackedSpan)>(), 20);
+        assert_eq!(size_of::<(u64, Span)>(), 24);
+        let wide_r = CachedRef::new(&reference, 2).expect("short input");
+        assert!(matches!(cached_r.variant_map, VariantTable::NarrowMap(_)));
+        assert!(matches!(loaded.variant_map, VariantTable::NarrowMap(_)));
+        assert!(cached_r.variant_map.nbytes() < wide_r.variant_map.nbytes());
+        assert!(cached_r.nbytes() < wide_r.nbytes());
+        for (hash, span) in cached_r.variant_map.iter() {
+            assert_eq!(hash >> 32, hash & u32::MAX as u64);
+            assert_eq!(
+                cached_r.variant_map.get(&hash).map(|span| span.as_range()),
+                Some(span.as_range())
+            );
+        }
+        let mut corrupted = buffer.clone();
+        let first_hash = corrupted.len() - 3 * size_of::<u64>();
+        corrupted[first_hash] ^= 1;
+        assert!(matches!(
+            CachedRef::read_from(&corrupted[..]),
+            Err(Error::MalformedCache("variant hash wider than 32 bits"))
+        ));
+
+        // Caches hashing to different widths cannot be used together.
+        let wide_q = CachedRef::new(&query, 2).expect("short input");
+        assert!(matches!(
+            cached_r.get_neighbors_across_cached(&wide_q, 2),
+            Err(Error::MismatchedHashWidth { cache: 32, got: 64 })
+        ));
+        assert!(matches!(
+            SearchConfig::new(2).run_across_cached(&query, &cached_r),
+            Err(Error::MismatchedHashWidth { cache: 32, got: 64 })
+        ));
+    }
+
+    #[test]
+    fn test_exact_grouping() {
+        let max_distance = MaxDistance::try_from(2).unwrap();
+        for (input, anchor) in [
+            ("fizzbuzz", Anchor::default()),
+            ("東京都庁", Anchor::default()),
+            (
+                "fizzbuzz",
+                Anchor {
+                    prefix: 2,
+                    suffix: 1,
+                },
+            ),
+            (
+                "東京都庁舎",
+                Anchor {
+                    prefix: 1,
+                    suffix: 1,
+                },
+            ),
+        ] {
+            let anchor = Anchoring {
+                anchor,
+                short_strings: ShortStrings::Skip,
+            };
+            let mut scratch = VariantScratch::default();
+            let mut hashes = Vec::new();
+            let hash_builder = FixedState::default();
+            let symbols = input.as_symbols();
+            for_each_variant_hash(
+                symbols,
+                max_distance,
+                anchor,
+                &hash_builder,
+                &mut scratch,
+                |h| hashes.push(h),
+            );
+            let (prefix, _, suffix) = anchor.split(symbols).unwrap();
+
+            let mut variant = Vec::new();
+            for (ordinal, &hash) in hashes.iter().enumerate() {
+                write_nth_variant(symbols, max_distance, anchor, ordinal, &mut variant);
+                assert!(variant.starts_with(prefix) && variant.ends_with(suffix));
+                if anchor.is_none() {
+                    let mut hasher = hash_builder.build_hasher();
+                    hasher.write(&variant);
+                    assert_eq!(hasher.finish(), hash);
+                }
+            }
+        }
+
+        // With hashes truncated to nothing, all deletion variants collide.
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let (query, reference) = (&query[..300], &reference[..300]);
+        let max_distance = MaxDistance::try_from(1).unwrap();
+        let colliding = TruncatedState {
+            inner: FixedState::default(),
+            bits: 0,
+        };
+        let sorted_groups = |groups: (Vec<u32>, Vec<usize>)| {
+            let mut remaining = &groups.0[..];
+            let mut sorted = groups
+                .1
+                .iter()
+                .map(|&n| {
+                    let (group, rest) = remaining.split_at(n);
+                    remaining = rest;
+                    group.to_vec()
+                })
+                .collect_vec();
+            sorted.sort_unstable();
+            sorted
+        };
+
+        let mut variant_ref_pairs = Vec::new();
+        extend_variant_ref_pairs(
+            &mut variant_ref_pairs,
+            query,
+            |idx| idx,
+            max_distance,
+            Anchoring::default(),
+            &colliding,
+            None,
+            Progress::default(),
+        );
+        variant_ref_pairs.sort_unstable();
+        let mut exact_groups = (Vec::new(), Vec::new());
+        for_each_exact_convergence_group(
+            &variant_ref_pairs,
+            |idx| query[idx as usize].as_symbols(),
+            max_distance,
+            Anchoring::default(),
+            |group| {
+                exact_groups
+                    .0
+                    .extend(group.iter().map(VariantIndexPair::index));
+                exact_groups.1.push(group.len());
+            },
+        );
+        let colliding_pairs =
+            get_sorted_vi_pairs_rawidx(query, 0, max_distance, &colliding, Progress::default());
+        let hashed_pairs = get_sorted_vi_pairs_rawidx(
+            query,
+            0,
+            max_distance,
+            &FixedState::default(),
+            Progress::default(),
+        );
+        assert_eq!(
+            get_convergence_groups(&colliding_pairs).1,
+            vec![query.len()]
+        );
+        assert_eq!(
+            sorted_groups(exact_groups),
+            sorted_groups(get_convergence_groups(&hashed_pairs))
+        );
+
+        let options = SearchOptions {
+            exact_grouping: true,
+            brute_force_max_pairs: Some(0),
+            ..Default::default()
+        };
+        let default_options = SearchOptions {
+            brute_force_max_pairs: Some(0),
+            ..Default::default()
+        };
+        for max_distance in [1, 2] {
+            assert_eq!(
+                get_candidate_pairs_within_with_options(query, max_distance, &options)
+                    .expect("valid"),
+                get_candidate_pairs_within_with_options(query, max_distance, &default_options)
+                    .expect("valid")
+            );
+            assert_eq!(
+                get_candidate_pairs_across_with_options(query, reference, max_distance, &options)
+                    .expect("valid"),
+                get_candidate_pairs_across_with_options(
+                    query,
+                    reference,
+                    max_distance,
+                    &default_options
+                )
+                .expect("valid")
+            );
+        }
+
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        assert_eq!(
+            get_neighbors_within_with_options(&query, 1, &options).expect("valid"),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_1)
+        );
+        assert_eq!(
+            get_neighbors_across_with_options(&query, &reference, 1, &options).expect("valid"),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_1)
+        );
+
+        let anchored = |exact_grouping| SearchOptions {
+            exact_grouping,
+            anchor: Anchor {
+                prefix: 3,
+                suffix: 2,
+            },
+            unicode: true,
+            brute_force_max_pairs: Some(0),
+            ..Default::default()
+        };
+        let strings = ["CASSLGF", "CASSQGF", "CASLGFF", "東京都", "京都府", "東都"];
+        assert_eq!(
+            get_neighbors_within_with_options(&strings, 2, &anchored(true)).expect("valid"),
+            get_neighbors_within_with_options(&strings, 2, &anchored(false)).expect("valid")
+        );
+    }
+
+    #[test]
+    fn test_cached_shared_roundtrip() {
+        struct Aligned(Vec<u64>);
+
+        impl AsRef<[u8]> for Aligned {
+            fn as_ref(&self) -> &[u8] {
+                as_bytes(&self.0)
+            }
+        }
+
+        let cached = CachedRef::new(&TEST_REF, 2).expect("legal");
+        let mut buffer = vec![0u64; cached.shared_size().div_ceil(8)];
+        let bytes = unsafe {
+            slice::from_raw_parts_mut(buffer.as_mut_ptr().cast::<u8>(), buffer.len() * 8)
+        };
+
+        assert!(matches!(
+            cached.write_shared(&mut bytes[..cached.shared_size() - 1]),
+            Err(Error::SharedBufferTooSmall { .. })
+        ));
+        cached.write_shared(bytes).expect("large enough");
+
+        let mut shared = unsafe { CachedRef::from_shared(Aligned(buffer.clone())) }.expect("valid");
+        assert_eq!(shared.len(), TEST_REF.len());
+        assert_eq!(shared.max_distance(), 2);
+        assert_eq!(shared.get(2), Some("fizz"));
+        for mdist in 0..=2 {
+            assert_eq!(
+                shared.get_neighbors_within(mdist).expect("legal"),
+                cached.get_neighbors_within(mdist).expect("legal")
+            );
+            assert_eq!(
+                shared
+                    .get_neighbors_across(&TEST_QUERY, mdist)
+                    .expect("legal"),
+                cached
+                    .get_neighbors_across(&TEST_QUERY, mdist)
+                    .expect("legal")
+            );
+            assert_eq!(
+                shared
+                    .get_neighbors_across_cached(&cached, mdist)
+                    .expect("legal"),
+                cached
+                    .get_neighbors_across_cached(&cached, mdist)
+                    .expect("legal")
+            );
+        }
+
+        shared.extend(&TEST_QUERY).expect("legal");
+        let mut extended = CachedRef::new(&TEST_REF, 2).expect("legal");
+        extended.extend(&TEST_QUERY).expect("legal");
+        assert_eq!(
+            shared.get_neighbors_within(2).expect("legal"),
+            extended.get_neighbors_within(2).expect("legal")
+        );
+
+        let mut truncated = buffer.clone();
+        truncated.truncate(truncated.len() - 1);
+        assert!(matches!(
+            unsafe { CachedRef::from_shared(Aligned(truncated)) },
+            Err(Error::MalformedCache(_))
+        ));
+
+        let mut bad_index = buffer.clone();
+        let index_start = SharedLayout::new(3, 12, 1, 0)
+            .expect("small")
+            .index_store
+            .start;
+        bad_index[index_start / 8] = u64::MAX;
+        assert!(matches!(
+            unsafe { CachedRef::from_shared(Aligned(bad_index)) },
+            Err(Error::MalformedCache(_))
+        ));
+
+        let mut bad_version = buffer;
+        bad_version[1] += 1;
+        assert!(matches!(
+            unsafe { CachedRef::from_shared(Aligned(bad_version)) },
+            Err(Error::IncompatibleCacheVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let token = CancellationToken::new();
+        let options = SearchOptions {
+            cancellation: Some(token.clone()),
+            ..Default::default()
+        };
+
+        let cached = CachedRef::new_with_options(&TEST_REF, 2, &options).expect("not cancelled");
+        assert!(get_neighbors_within_with_options(&TEST_QUERY, 2, &options).is_ok());
+
+        token.cancel();
+        assert!(matches!(
+            get_neighbors_within_with_options(&TEST_QUERY, 2, &options),
+            Err(Error::Cancelled)
+        ));
+        assert!(matches!(
+            get_neighbors_across_with_options(&TEST_QUERY, &TEST_REF, 2, &options),
+            Err(Error::Cancelled)
+        ));
+        assert!(matches!(
+            CachedRef::new_with_options(&TEST_REF, 2, &options),
+            Err(Error::Cancelled)
+        ));
+        assert!(matches!(
+            cached.get_neighbors_within_with_options(2, &options),
+            Err(Error::Cancelled)
+        ));
+        assert!(matches!(
+            cached.get_neighbors_across_with_options(&TEST_QUERY, 2, &options),
+            Err(Error::Cancelled)
+        ));
+        assert!(matches!(
+            cached.get_neighbors_across_cached_with_options(&cached, 2, &options),
+            Err(Error::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn test_wide() {
+        // Deterministic pseudo-random strings long and dissimilar enough for distances beyond
+        // 255, over a small alphabet so that closer pairs occur as well.
+        let mut state = 11u64;
+        let mut random_string = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+            let len = 150 + (state >> 56) as usize;
+            let alphabet = [b"AC", b"GT", b"Aa"][(state % 3) as usize];
+            (0..len)
+                .map(|i| alphabet[((state >> (i % 48 + 8)) ^ i as u64) as usize % 2] as char)
+                .collect::<String>()
+        };
+        let query = (0..24).map(|_| random_string()).collect_vec();
+        let reference = (0..16).map(|_| random_string()).collect_vec();
+
+        let expected_within = |max_distance: usize, dist: &dyn Fn(&str, &str) -> Option<usize>| {
+            let mut expected = Vec::new();
+            for (i, j) in (0..query.len()).tuple_combinations() {
+                match dist(&query[i], &query[j]) {
+                    Some(d) if d <= max_distance => expected.push((i as u32, j as u32, d as u16)),
+                    _ => {}
+                }
+            }
+            expected
+        };
+        let triplets =
+            |pairs: WideNeighborPairs| izip!(pairs.row, pairs.col, pairs.dists).collect_vec();
+
+        for max_distance in [255, 300, 1000] {
+            let expected = expected_within(max_distance as usize, &|a, b| {
+                Some(naive::levenshtein(a, b))
+            });
+            assert!(expected.iter().any(|&(_, _, d)| d >= 255));
+            assert!(expected.len() < query.len() * (query.len() - 1) / 2 || max_distance == 1000);
+            assert_eq!(
+                triplets(get_neighbors_within_wide(&query, max_distance).expect("valid")),
+                expected
+            );
+
+            let mut expected = Vec::new();
+            for (i, j) in (0..query.len()).cartesian_product(0..reference.len()) {
+                let d = naive::levenshtein(&query[i], &reference[j]);
+                if d <= max_distance as usize {
+                    expected.push((i as u32, j as u32, d as u16));
+                }
+            }
+            assert_eq!(
+                triplets(
+                    get_neighbors_across_wide(&query, &reference, max_distance).expect("valid")
+                ),
+                expected
+            );
+        }
+
+        // Options apply to the direct comparisons as well.
+        for (metric, dist) in [
+            (
+                Metric::Indel,
+                &(|a: &str, b: &str| Some(naive::indel(a, b)))
+                    as &dyn Fn(&str, &str) -> Option<usize>,
+            ),
+            (Metric::Hamming, &|a: &str, b: &str| naive::hamming(a, b)),
+        ] {
+            let options = SearchOptions {
+                metric,
+                min_distance: 3,
+                ..Default::default()
+            };
+            let expected = expected_within(400, &|a, b| dist(a, b).filter(|&d| d >= 3));
+            assert_eq!(
+                triplets(
+                    get_neighbors_within_wide_with_options(&query, 400, &options).expect("valid")
+                ),
+                expected
+            );
+        }
+        let options = SearchOptions {
+            case_insensitive: true,
+            ..Default::default()
+        };
+        let expected = expected_within(300, &|a, b| {
+            Some(naive::levenshtein(&a.to_lowercase(), &b.to_lowercase()))
+        });
+        assert_eq!(
+            triplets(get_neighbors_within_wide_with_options(&query, 300, &options).expect("valid")),
+            expected
+        );
+
+        // Thresholds below the cap are searched via symmetric deletion.
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        assert_eq!(
+            get_neighbors_within_wide(&query, 2).expect("valid"),
+            WideNeighborPairs::from(get_neighbors_within(&query, 2).expect("valid"))
+        );
+        assert!(matches!(
+            get_neighbors_within_wide(&["f\u{fc}zz"], 300),
+            Err(Error::NonAsciiInput { .. })
+        ));
+    }
+
+    #[test]
+    fn test_pairwise_distances() {
+        // Deterministic pseudo-random strings over a small alphabet, so that close pairs occur.
+        let mut state = 42u64;
+        let mut random_string = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+            let len = (state >> 60) as usize;
+            (0..len)
+                .map(|i| b"abc"[((state >> (2 * i + 8)) % 3) as usize] as char)
+                .collect::<String>()
+        };
+        let query = (0..300).map(|_| random_string()).collect_vec();
+        let reference = (0..300).map(|_| random_string()).collect_vec();
+
+        let expected = query
+            .iter()
+            .zip(&reference)
+            .map(|(q, r)| naive::levenshtein(q, r))
+            .collect_vec();
+        assert_eq!(
+            pairwise_distances(&query, &reference, None).expect("legal"),
+            expected
+        );
+        assert_eq!(
+            pairwise_distances(&query, &reference, Some(2)).expect("legal"),
+            expected.iter().map(|&d| d.min(3)).collect_vec()
+        );
+
+        assert!(matches!(
+            pairwise_distances(&query, &reference[1..], None),
+            Err(Error::MismatchedLengths {
+                query: 300,
+                reference: 299
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cached_query_one() {
+        let reference = bytes_as_ascii_lines(include_bytes!("../../test_files/cdr3b_10k_b.txt"));
+        let query = bytes_as_ascii_lines(include_bytes!("../../test_files/cdr3b_10k_a.txt"));
+        let cached = CachedRef::new(&reference, 2).expect("legal");
+
+        for (qi, q) in query.iter().enumerate().step_by(97) {
+            let batch = cached.get_neighbors_across(&[q], 2).expect("legal");
+            let mut expected = batch.col.into_iter().zip(batch.dists).collect_vec();
+            expected.sort_unstable();
+            assert_eq!(
+                cached.query_one(q, 2).expect("legal"),
+                expected,
+                "query {qi}"
+            );
+        }
+
+        assert!(matches!(
+            cached.query_one("CASSLGQ", 3),
+            Err(Error::MaxDistTooLargeForCache { got: 3, limit: 2 })
+        ));
+        assert!(matches!(
+            cached.query_one("CASSLGQ\u{e9}", 1),
+            Err(Error::NonAsciiInput { .. })
+        ));
+    }
+
+    /// Micro-benchmark documenting the latency win of [`CachedRef::query_one`] over a batch call
+    /// with a single query string. The gap is wider from Python, where the batch call additionally
+    /// pays for a worker thread and numpy array construction. Ignored by default as timings are
+    /// unreliable under a loaded test runner; run with `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore]
+    fn bench_cached_query_one() {
+        use std::time::Instant;
+
+        let reference = bytes_as_ascii_lines(include_bytes!("../../test_files/cdr3b_10k_b.txt"));
+        let query = bytes_as_ascii_lines(include_bytes!("../../test_files/cdr3b_10k_a.txt"));
+        let cached = CachedRef::new(&reference, 1).expect("legal");
+        let query = &query[..1000];
+
+        let start = Instant::now();
+        for q in query {
+            std::hint::black_box(cached.get_neighbors_across(&[q], 1).expect("legal"));
+        }
+        let batch_elapsed = start.elapsed();
+
+        let start = Instant::now();
+        for q in query {
+            std::hint::black_box(cached.query_one(q, 1).expect("legal"));
+        }
+        let single_elapsed = start.elapsed();
+
+        assert!(
+            single_elapsed < batch_elapsed,
+            "query_one took {single_elapsed:?}, batch took {batch_elapsed:?}"
+        );
+    }
+
+    #[test]
+    fn test_cached_accessors() {
+        let cached = CachedRef::new(&TEST_REF, 2).expect("short input");
+        assert_eq!(cached.len(), 3);
+        assert!(!cached.is_empty());
+        assert_eq!(cached.max_distance(), 2);
+        assert_eq!(
+            (0..4).map(|i| cached.get(i)).collect_vec(),
+            vec![Some("file"), Some("tofu"), Some("fizz"), None]
+        );
+        assert!(cached.nbytes() >= 12 + 3 * size_of::<Span>());
+
+        let empty = CachedRef::new(&[] as &[&str], 1).expect("short input");
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_cached_extend() {
+        let mut cached = CachedRef::new(&TEST_REF[..1], 2).expect("short input");
+        cached.extend(&TEST_REF[1..]).expect("short input");
+        assert_eq!(cached.len(), TEST_REF.len());
+
+        let expected = CachedRef::new(&TEST_REF, 2).expect("short input");
+        for mdist in [1, 2] {
+            assert_eq!(
+                cached
+                    .get_neighbors_across(&TEST_QUERY, mdist)
+                    .expect("legal max dist"),
+                expected
+                    .get_neighbors_across(&TEST_QUERY, mdist)
+                    .expect("legal max dist"),
+            );
+        }
+
+        let mut cached = CachedRef::new(&TEST_QUERY[..2], 2).expect("short input");
+        cached.extend(&TEST_QUERY[2..]).expect("short input");
+        let expected = CachedRef::new(&TEST_QUERY, 2).expect("short input");
+        assert_eq!(
+            cached.get_neighbors_within(2).expect("legal max dist"),
+            expected.get_neighbors_within(2).expect("legal max dist"),
+        );
+    }
+
+    #[test]
+    fn test_cached_serialization_roundtrip() {
+        let cached = CachedRef::new(&TEST_REF, 2).expect("short input");
+        let mut buffer = Vec::new();
+        cached.write_to(&mut buffer).expect("writing to vec");
+        let loaded = CachedRef::read_from(&buffer[..]).expect("valid data");
+
+        for mdist in [1, 2] {
+            assert_eq!(
+                loaded
+                    .get_neighbors_across(&TEST_QUERY, mdist)
+                    .expect("legal max dist"),
+                cached
+                    .get_neighbors_across(&TEST_QUERY, mdist)
+                    .expect("legal max dist"),
+            );
+        }
+        assert!(matches!(
+            loaded.get_neighbors_within(3),
+            Err(Error::MaxDistTooLargeForCache { got: 3, limit: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_cached_deserialization_rejects_bad_data() {
+        let cached = CachedRef::new(&TEST_REF, 1).expect("short input");
+        let mut buffer = Vec::new();
+        cached.write_to(&mut buffer).expect("writing to vec");
+
+        let mut wrong_version = buffer.clone();
+        wrong_version[CACHE_MAGIC.len()] += 1;
+        assert!(matches!(
+            CachedRef::read_from(&wrong_version[..]),
+            Err(Error::IncompatibleCacheVersion { .. })
+        ));
+
+        let truncated = &buffer[..buffer.len() - 1];
+        assert!(matches!(
+            CachedRef::read_from(truncated),
+            Err(Error::MalformedCache(_))
+        ));
+
+        assert!(matches!(
+            CachedRef::read_from(&b"not a cache"[..]),
+            Err(Error::MalformedCache(_))
+        ));
+    }
+
+    // testing on real world data
+
+    static CDR3_Q_BYTES: &[u8] = include_bytes!("../../test_files/cdr3b_10k_a.txt");
+    static CDR3_R_BYTES: &[u8] = include_bytes!("../../test_files/cdr3b_10k_b.txt");
+    static EXPECTED_BYTES_WITHIN_1: &[u8] = include_bytes!("../../test_files/results_10k_a.txt");
+    static EXPECTED_BYTES_WITHIN_2: &[u8] = include_bytes!("../../test_files/results_10k_a_d2.txt");
+    static EXPECTED_BYTES_CROSS_1: &[u8] = include_bytes!("../../test_files/results_10k_cross.txt");
+    static EXPECTED_BYTES_CROSS_2: &[u8] =
+        include_bytes!("../../test_files/results_10k_cross_d2.txt");
+
+    fn bytes_as_ascii_lines(bytes: &[u8]) -> Vec<String> {
+        Cursor::new(bytes)
+            .lines()
+            .collect::<io::Result<Vec<String>>>()
+            .expect("test files have valid lines")
+    }
+
+    fn bytes_as_neighbour_pairs(bytes: &[u8]) -> NeighborPairs {
+        let mut i = Vec::new();
+        let mut j = Vec::new();
+        let mut dists = Vec::new();
+
+        Cursor::new(bytes).lines().for_each(|v| {
+            let line = v.expect("test files have valid lines");
+            let triplet = line.split(",").collect_vec();
+            i.push(
+                triplet[0]
+                    .parse::<u32>()
+                    .expect("test files have int triplets")
+                    - 1,
+            );
+            j.push(
+                triplet[1]
+                    .parse::<u32>()
+                    .expect("test files have int triplets")
+                    - 1,
+            );
+            dists.push(
+                triplet[2]
+                    .parse::<u8>()
+                    .expect("test files have int triplets"),
+            );
+        });
+
+        NeighborPairs {
+            row: i,
+            col: j,
+            dists,
+        }
+    }
+
+    #[test]
+    fn test_within() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+
+        let hits = get_neighbors_within(&query, 1).expect("short input");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_1));
+
+        let hits = get_neighbors_within(&query, 2).expect("short input");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2));
+    }
+
+    #[test]
+    fn test_cross() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+
+        let hits = get_neighbors_across(&query, &reference, 1).expect("valid inputs");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_1));
+
+        let hits = get_neighbors_across(&query, &reference, 2).expect("valid inputs");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2));
+    }
+
+    #[test]
+    fn test_join() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let expected = bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_1);
+
+        let joined = join_across(&query, &reference, 1).expect("valid");
+        assert_eq!(joined.len(), expected.len());
+        for (i, &(q, r, dist)) in joined.iter().enumerate() {
+            assert_eq!(q, query[expected.row[i] as usize]);
+            assert_eq!(r, reference[expected.col[i] as usize]);
+            assert_eq!(dist, expected.dists[i]);
+        }
+
+        let hits = get_neighbors_within(&query, 1).expect("valid");
+        assert!(hits
+            .resolve(&query, &query)
+            .all(|(a, b, dist)| levenshtein::distance(a.bytes(), b.bytes()) == dist as usize));
+    }
+
+    #[test]
+    #[should_panic(expected = "reference index 3 out of range for 3 strings")]
+    fn test_resolve_out_of_range() {
+        let query = ["fizz", "fuzz", "buzz"];
+        let reference = ["fooo", "barr", "bazz", "buzz"];
+        let hits = get_neighbors_across(&query, &reference, 1).expect("valid");
+        hits.resolve(&query, &reference[..3]).for_each(drop);
+    }
+
+    #[test]
+    fn test_cross_merged() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let options = SearchOptions {
+            merge_sorted_runs: true,
+            ..Default::default()
+        };
+
+        let hits =
+            get_neighbors_across_with_options(&query, &reference, 1, &options).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_1));
+
+        let hits =
+            get_neighbors_across_with_options(&query, &reference, 2, &options).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2));
+    }
+
+    #[test]
+    fn test_fused_verification() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let options = SearchOptions {
+            fuse_verification: true,
+            ..Default::default()
+        };
+
+        let hits = get_neighbors_within_with_options(&query, 1, &options).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_1));
+        let hits = get_neighbors_within_with_options(&query, 2, &options).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2));
+
+        let hits =
+            get_neighbors_across_with_options(&query, &reference, 1, &options).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_1));
+        let hits =
+            get_neighbors_across_with_options(&query, &reference, 2, &options).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2));
+
+        let config = SearchConfig::new(2).fuse_verification(true);
+        assert_eq!(
+            config.run_within(&query).expect("valid"),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2)
+        );
+        assert_eq!(
+            config.run_across(&query, &reference).expect("valid"),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2)
+        );
+    }
+
+    /// A temporary directory, which is removed along with its contents when dropped, even if a test
+    /// fails.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("symscan-{name}-{}", std::process::id()));
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_low_memory() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let temp_dir = TempDir::new("test-low-memory");
+        let dir = &temp_dir.0;
+
+        // A budget of a few thousand strings' worth of deletion variants per run.
+        let config = SearchConfig::new(2).low_memory(dir, 1 << 20);
+        assert_eq!(
+            config.run_within(&query).expect("valid"),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2)
+        );
+        assert_eq!(
+            config.run_across(&query, &reference).expect("valid"),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2)
+        );
+        let config = SearchConfig::new(1).low_memory(dir, 0).wide_hashes(true);
+        assert_eq!(
+            config.run_within(&query).expect("valid"),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_1)
+        );
+        assert_eq!(
+            config.run_across(&query, &reference).expect("valid"),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_1)
+        );
+
+        // Labels are sliced along with the strings of each run.
+        let labels = (0..query.len() as u32).map(|idx| idx % 3).collect_vec();
+        let policy = GroupPolicy::WithinGroupsOnly;
+        assert_eq!(
+            get_neighbors_within_grouped_with_options(
+                &query,
+                &labels,
+                1,
+                policy,
+                config.search_options()
+            )
+            .expect("valid"),
+            get_neighbors_within_grouped(&query, &labels, 1, policy).expect("valid")
+        );
+
+        // The candidates are spilled in as many runs as they take up blocks of the smallest size,
+        // and verified in blocks of whole query rows, whatever form the hits are collected in.
+        let in_memory = SearchOptions::default();
+        let spilled = config.search_options();
+        assert_eq!(
+            get_neighbor_counts_within_with_options(&query, 1, spilled).expect("valid"),
+            get_neighbor_counts_within_with_options(&query, 1, &in_memory).expect("valid")
+        );
+        assert_eq!(
+            get_neighbor_counts_across_with_options(&query, &reference, 1, spilled).expect("valid"),
+            get_neighbor_counts_across_with_options(&query, &reference, 1, &in_memory)
+                .expect("valid")
+        );
+        assert_eq!(
+            get_neighbors_across_top_k_with_options(&query, &reference, 1, 2, spilled)
+                .expect("valid"),
+            get_neighbors_across_top_k_with_options(&query, &reference, 1, 2, &in_memory)
+                .expect("valid")
+        );
+        assert_eq!(
+            has_neighbor_across_with_options(&query, &reference, 1, spilled).expect("valid"),
+            has_neighbor_across_with_options(&query, &reference, 1, &in_memory).expect("valid")
+        );
+        assert_eq!(
+            get_candidate_pairs_within_with_options(&query, 1, spilled).expect("valid"),
+            get_candidate_pairs_within_with_options(&query, 1, &in_memory).expect("valid")
+        );
+        assert_eq!(
+            get_candidate_pairs_across_with_options(&query, &reference, 1, spilled).expect("valid"),
+            get_candidate_pairs_across_with_options(&query, &reference, 1, &in_memory)
+                .expect("valid")
+        );
+
+        // The candidates of all groups are counted against the limit, as they are in memory.
+        let limit = |options: &SearchOptions| SearchOptions {
+            max_candidates: Some(1000),
+            ..options.clone()
+        };
+        let (Err(spilled), Err(in_memory)) = (
+            get_neighbors_within_with_options(&query, 2, &limit(spilled)),
+            get_neighbors_within_with_options(&query, 2, &limit(&in_memory)),
+        ) else {
+            panic!("too many candidates");
+        };
+        assert_eq!(spilled.to_string(), in_memory.to_string());
+
+        assert_eq!(std::fs::read_dir(dir).unwrap().count(), 0);
+
+        let config = SearchConfig::new(1).low_memory(dir.join("missing"), 1 << 20);
+        assert!(matches!(config.run_within(&query), Err(Error::Io(_))));
+        assert!(matches!(
+            config.run_across(&query, &reference),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_get_spill_batches() {
+        assert_eq!(
+            get_spill_batches(&[2, 2, 5, 1, 1], 4, 16),
+            vec![0..2, 2..3, 3..5]
+        );
+        assert_eq!(get_spill_batches(&[3, 3], 4, 0), vec![0..1, 1..2]);
+        assert!(get_spill_batches(&[], 4, 16).is_empty());
+    }
+
+    fn lines_as_buffer_and_offsets(lines: &[String]) -> (Vec<u8>, Vec<u32>) {
+        let mut offsets = vec![0];
+        for line in lines {
+            offsets.push(offsets.last().unwrap() + line.len() as u32);
+        }
+        (lines.concat().into_bytes(), offsets)
+    }
+
+    #[test]
+    fn test_offsets() {
+        let (q_buffer, q_offsets) =
+            lines_as_buffer_and_offsets(&bytes_as_ascii_lines(CDR3_Q_BYTES));
+        let (r_buffer, r_offsets) =
+            lines_as_buffer_and_offsets(&bytes_as_ascii_lines(CDR3_R_BYTES));
+
+        let hits = get_neighbors_within_offsets(&q_buffer, &q_offsets, 1).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_1));
+
+        let cached = CachedRef::from_offsets(&r_buffer, &r_offsets, 2).expect("valid");
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let hits = cached.get_neighbors_across(&query, 2).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2));
+
+        let err = get_neighbors_within_offsets(b"fizzfuzz", &[0, 4, 3], 1).unwrap_err();
+        assert!(matches!(err, Error::InvalidOffsets { idx: 2, got: 3, .. }));
+        let res = CachedRef::from_offsets(b"fizzfuzz", &[0, 4, 9], 1);
+        assert!(matches!(
+            res,
+            Err(Error::InvalidOffsets { idx: 2, got: 9, .. })
+        ));
+        let err =
+            get_neighbors_within_offsets("fizzfüzzbuzz".as_bytes(), &[0, 4, 9, 13], 1).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::NonAsciiInput {
+                offending_idx: 1,
+                ..
+            }
+        ));
+
+        let hits = get_neighbors_within_offsets(b"", &[] as &[u32], 1).expect("valid");
+        assert!(hits.row.is_empty());
+
+        // Arrow-style signed and wide offsets, of different types for query and reference.
+        let q_offsets_i32 = q_offsets.iter().map(|&o| o as i32).collect_vec();
+        let r_offsets_u64 = r_offsets.iter().map(|&o| o as u64).collect_vec();
+        let hits =
+            get_neighbors_across_offsets(&q_buffer, &q_offsets_i32, &r_buffer, &r_offsets_u64, 2)
+                .expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2));
+        let hits = get_neighbors_within_offsets(&q_buffer, &q_offsets_i32, 1).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_1));
+        let cached = CachedRef::from_offsets(&r_buffer, &r_offsets_u64, 2).expect("valid");
+        assert_eq!(
+            cached.get_neighbors_across(&query, 2).expect("valid"),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2)
+        );
+
+        // Slices of a larger buffer need not start at offset 0.
+        let hits = get_neighbors_across_offsets(b"xxfizz", &[2i64, 6], b"fuzz", &[0usize, 4], 1)
+            .expect("valid");
+        assert_eq!(hits.dists, vec![1]);
+
+        let err = get_neighbors_within_offsets(b"fizzfuzz", &[-1i32, 4, 8], 1).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidOffsets {
+                idx: 0,
+                got: -1,
+                ..
+            }
+        ));
+        let err = get_neighbors_across_offsets(b"fizz", &[0u32, 4], b"fuzz", &[0i32, 4, 2], 1)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidOffsets {
+                input_type: InputType::Reference,
+                idx: 2,
+                got: 2,
+            }
+        ));
+    }
+
+    #[test]
+    fn test_brute_force() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let (query, reference) = (&query[..300], &reference[..50]);
+        let symdel = SearchOptions {
+            brute_force_max_pairs: Some(0),
+            ..Default::default()
+        };
+        let brute_force = SearchOptions {
+            brute_force_max_pairs: Some(usize::MAX),
+            ..Default::default()
+        };
+
+        for max_distance in [1, 2, 3] {
+            assert_eq!(
+                get_neighbors_within_with_options(query, max_distance, &brute_force)
+                    .expect("valid"),
+                get_neighbors_within_with_options(query, max_distance, &symdel).expect("valid"),
+            );
+            assert_eq!(
+                get_neighbors_across_with_options(query, reference, max_distance, &brute_force)
+                    .expect("valid"),
+                get_neighbors_across_with_options(query, reference, max_distance, &symdel)
+                    .expect("valid"),
+            );
+        }
+    }
+
+    #[test]
+    fn test_hamming() {
+        let hamming = SearchOptions {
+            metric: Metric::Hamming,
+            brute_force_max_pairs: Some(0),
+            ..Default::default()
+        };
+
+        // "abc" is within Levenshtein but not Hamming distance 1 of "abcd", and "abc" and "bca"
+        // are 2 Levenshtein but 3 Hamming distance units apart.
+        let query = ["abcd", "abce", "abc", "bca"];
+        let hits = get_neighbors_within_with_options(&query, 1, &hamming).expect("valid");
+        assert_eq!(
+            hits,
+            NeighborPairs {
+                row: vec![0],
+                col: vec![1],
+                dists: vec![1],
+            }
+        );
+        let hits = get_neighbors_within_with_options(&query, 3, &hamming).expect("valid");
+        assert_eq!(
+            hits,
+            NeighborPairs {
+                row: vec![0, 2],
+                col: vec![1, 3],
+                dists: vec![1, 3],
+            }
+        );
+        let hits = get_neighbors_within(&query, 3).expect("valid");
+        assert_eq!(hits.dists, vec![1, 1, 2, 1, 2, 2]);
+
+        // Hamming neighbors are the equal-length Levenshtein neighbors within the same distance.
+        let expected_hamming = |hits: NeighborPairs, q: &[String], r: &[String]| {
+            let mut expected = NeighborPairs {
+                row: Vec::new(),
+                col: Vec::new(),
+                dists: Vec::new(),
+            };
+            for (&qi, &ri) in hits.row.iter().zip(&hits.col) {
+                let (a, b) = (&q[qi as usize], &r[ri as usize]);
+                let Some(dist) = naive::hamming(a, b).filter(|&dist| dist <= 2) else {
+                    continue;
+                };
+                expected.row.push(qi);
+                expected.col.push(ri);
+                expected.dists.push(dist as u8);
+            }
+            expected
+        };
+
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached = CachedRef::new(&reference, 2).expect("short input");
+        let expected_within = expected_hamming(
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2),
+            &query,
+            &query,
+        );
+        let expected_cross = expected_hamming(
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2),
+            &query,
+            &reference,
+        );
+        assert!(!expected_within.is_empty() && !expected_cross.is_empty());
+
+        let fused = SearchOptions {
+            fuse_verification: true,
+            ..hamming.clone()
+        };
+        for options in [&hamming, &fused] {
+            assert_eq!(
+                get_neighbors_within_with_options(&query, 2, options).expect("valid"),
+                expected_within,
+            );
+            assert_eq!(
+                get_neighbors_across_with_options(&query, &reference, 2, options).expect("valid"),
+                expected_cross,
+            );
+        }
+        assert_eq!(
+            cached
+                .get_neighbors_across_with_options(&query, 2, &hamming)
+                .expect("valid"),
+            expected_cross,
+        );
+    }
+
+    #[test]
+    fn test_osa() {
+        let osa = SearchOptions {
+            metric: Metric::Osa,
+            brute_force_max_pairs: Some(0),
+            ..Default::default()
+        };
+
+        let hits = get_neighbors_within_with_options(&["abcd", "abdc"], 1, &osa).expect("valid");
+        assert_eq!(
+            hits,
+            NeighborPairs {
+                row: vec![0],
+                col: vec![1],
+                dists: vec![1],
+            }
+        );
+        assert!(get_neighbors_within(&["abcd", "abdc"], 1)
+            .expect("valid")
+            .is_empty());
+
+        // The symmetric deletion candidates must cover all pairs within OSA distance, which is
+        // checked against directly verifying all pairs.
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let (query, reference) = (&query[..300], &reference[..50]);
+        let transposed = query
+            .iter()
+            .enumerate()
+            .map(|(idx, s)| {
+                let mut bytes = s.clone().into_bytes();
+                bytes.swap(1, 2);
+                if idx % 2 == 1 {
+                    bytes.swap(4, 5);
+                }
+                String::from_utf8(bytes).expect("ascii")
+            })
+            .collect_vec();
+        let brute_force = SearchOptions {
+            brute_force_max_pairs: Some(usize::MAX),
+            ..osa.clone()
+        };
+        let cached = CachedRef::new(&transposed, 2).expect("short input");
+
+        for max_distance in [1, 2] {
+            let expected =
+                get_neighbors_across_with_options(query, &transposed, max_distance, &brute_force)
+                    .expect("valid");
+            assert!(expected.len() >= query.len() / 2);
+            assert_eq!(
+                get_neighbors_across_with_options(query, &transposed, max_distance, &osa)
+                    .expect("valid"),
+                expected,
+            );
+            assert_eq!(
+                cached
+                    .get_neighbors_across_with_options(query, max_distance, &osa)
+                    .expect("valid"),
+                expected,
+            );
+            assert_eq!(
+                get_neighbors_within_with_options(query, max_distance, &osa).expect("valid"),
+                get_neighbors_within_with_options(query, max_distance, &brute_force)
+                    .expect("valid"),
+            );
+            assert_eq!(
+                get_neighbors_across_with_options(query, reference, max_distance, &osa)
+                    .expect("valid"),
+                get_neighbors_across_with_options(query, reference, max_distance, &brute_force)
+                    .expect("valid"),
+            );
+        }
+    }
+
+    #[test]
+    fn test_indel() {
+        let indel = SearchOptions {
+            metric: Metric::Indel,
+            brute_force_max_pairs: Some(0),
+            ..Default::default()
+        };
+
+        let query = ["abcd", "abce", "abd"];
+        let hits = get_neighbors_within_with_options(&query, 1, &indel).expect("valid");
+        assert_eq!(
+            hits,
+            NeighborPairs {
+                row: vec![0],
+                col: vec![2],
+                dists: vec![1],
+            }
+        );
+        let hits = get_neighbors_within_with_options(&query, 2, &indel).expect("valid");
+        assert_eq!(
+            hits,
+            NeighborPairs {
+                row: vec![0, 0],
+                col: vec![1, 2],
+                dists: vec![2, 1],
+            }
+        );
+
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let (query, reference) = (&query[..300], &reference[..50]);
+        let brute_force = SearchOptions {
+            brute_force_max_pairs: Some(usize::MAX),
+            ..indel.clone()
+        };
+        let cached = CachedRef::new(reference, 2).expect("short input");
+
+        for max_distance in [1, 2] {
+            let expected =
+                get_neighbors_across_with_options(query, reference, max_distance, &brute_force)
+                    .expect("valid");
+            assert_eq!(
+                get_neighbors_across_with_options(query, reference, max_distance, &indel)
+                    .expect("valid"),
+                expected,
+            );
+            assert_eq!(
+                cached
+                    .get_neighbors_across_with_options(query, max_distance, &indel)
+                    .expect("valid"),
+                expected,
+            );
+            assert_eq!(
+                get_neighbors_within_with_options(query, max_distance, &indel).expect("valid"),
+                get_neighbors_within_with_options(query, max_distance, &brute_force)
+                    .expect("valid"),
+            );
+        }
+    }
+
+    #[test]
+    fn test_csr() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let hits = bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2);
+        let mut rows = vec![Vec::new(); query.len()];
+        for ((row, col), dist) in hits.row.into_iter().zip(hits.col).zip(hits.dists) {
+            rows[row as usize].push((dist, col));
+        }
+
+        let options = SearchOptions {
+            order: OutputOrder::ByDistance,
+            ..Default::default()
+        };
+        let grouped =
+            get_neighbors_across_csr_with_options(&query, &reference, 2, &options).expect("valid");
+        assert_eq!(grouped.num_rows(), query.len());
+        assert_eq!(grouped.indptr[0], 0);
+        assert_eq!(grouped.indptr[query.len()] as usize, grouped.len());
+        assert!(rows.iter().any(Vec::is_empty));
+        for (i, mut expected) in rows.into_iter().enumerate() {
+            expected.sort_unstable();
+            let (col, dists) = grouped.row(i);
+            assert_eq!(
+                dists.iter().copied().zip(col.iter().copied()).collect_vec(),
+                expected
+            );
+        }
+
+        let grouped = get_neighbors_across_csr(&query, &[] as &[&str], 2).expect("valid");
+        assert_eq!(grouped.indptr, vec![0; query.len() + 1]);
+        assert!(grouped.is_empty());
+    }
+
+    #[test]
+    fn test_large_within() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let query = &query[..2000];
+
+        for order in [OutputOrder::ByRowThenCol, OutputOrder::ByDistance] {
+            for symmetric in [false, true] {
+                let options = SearchOptions {
+                    order,
+                    symmetric,
+                    deduplicate: true,
+                    ..Default::default()
+                };
+                let expected = LargeNeighborPairs::from(
+                    get_neighbors_within_with_options(query, 2, &options).expect("valid"),
+                );
+                assert!(!expected.is_empty());
+                assert_eq!(
+                    get_neighbors_within_large_with_options(query, 2, &options).expect("valid"),
+                    expected
+                );
+                assert_eq!(
+                    get_neighbors_within_chunked(query, 2, &options, 700).expect("valid"),
+                    expected
+                );
+            }
+        }
+
+        let options = SearchOptions {
+            order: OutputOrder::Unordered,
+            ..Default::default()
+        };
+        let mut chunked = get_neighbors_within_chunked(query, 2, &options, 700).expect("valid");
+        chunked = LargeNeighborPairs::from_triplets(
+            itertools::izip!(chunked.row, chunked.col, chunked.dists)
+                .sorted_unstable()
+                .collect_vec(),
+        );
+        assert_eq!(
+            chunked,
+            LargeNeighborPairs::from(get_neighbors_within(query, 2).expect("valid"))
+        );
+
+        let options = SearchOptions {
+            excluded_pairs: Some(vec![(0, 1)].into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            get_neighbors_within_chunked(query, 2, &options, 700),
+            Err(Error::TooManyStrings { limit: 700, .. })
+        ));
+    }
+
+    #[test]
+    fn test_large_across() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let (query, reference) = (&query[..2000], &reference[..1000]);
+
+        for order in [
+            OutputOrder::ByRowThenCol,
+            OutputOrder::ByDistance,
+            OutputOrder::Unordered,
+        ] {
+            for deduplicate in [false, true] {
+                let options = SearchOptions {
+                    order,
+                    deduplicate,
+                    ..Default::default()
+                };
+                let expected = LargeNeighborPairs::from(
+                    get_neighbors_across_with_options(query, reference, 2, &options)
+                        .expect("valid"),
+                );
+                assert_eq!(
+                    get_neighbors_across_large_with_options(query, reference, 2, &options)
+                        .expect("valid"),
+                    expected
+                );
+
+                let mut chunked = get_neighbors_across_chunked(query, reference, 2, &options, 700)
+                    .expect("valid");
+                let mut expected = expected;
+                if order == OutputOrder::Unordered {
+                    let sort = |pairs: &mut LargeNeighborPairs| {
+                        let mut triplets = itertools::izip!(
+                            pairs.row.iter().copied(),
+                            pairs.col.iter().copied(),
+                            pairs.dists.iter().copied()
+                        )
+                        .collect_vec();
+                        triplets.sort_unstable();
+                        *pairs = LargeNeighborPairs::from_triplets(triplets);
+                    };
+                    sort(&mut chunked);
+                    sort(&mut expected);
+                }
+                assert_eq!(chunked, expected);
+            }
+        }
+
+        let options = SearchOptions {
+            pair_filter: Some(PairFilter::new(|_, _| true)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            get_neighbors_across_chunked(query, &reference[..10], 2, &options, 700),
+            Err(Error::TooManyStrings {
+                input_type: InputType::Query,
+                limit: 700,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_aligned() {
+        fn apply_edit_ops(a: &[char], b: &[char], ops: &[EditOp]) -> Vec<char> {
+            let mut edited = Vec::new();
+            let mut next = 0;
+            for &op in ops {
+                let (EditOp::Substitute { src_pos, .. }
+                | EditOp::Insert { src_pos, .. }
+                | EditOp::Delete { src_pos, .. }
+                | EditOp::Transpose { src_pos, .. }) = op;
+                edited.extend_from_slice(&a[next..src_pos as usize]);
+                next = src_pos as usize;
+                match op {
+                    EditOp::Substitute { dest_pos, .. } => {
+                        edited.push(b[dest_pos as usize]);
+                        next += 1;
+                    }
+                    EditOp::Insert { dest_pos, .. } => edited.push(b[dest_pos as usize]),
+                    EditOp::Delete { .. } => next += 1,
+                    EditOp::Transpose { .. } => {
+                        edited.extend([a[next + 1], a[next]]);
+                        next += 2;
+                    }
+                }
+            }
+            edited.extend_from_slice(&a[next..]);
+            edited
+        }
+
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let (query, reference) = (&query[..2000], &reference[..500]);
+
+        for metric in [
+            Metric::Levenshtein,
+            Metric::Hamming,
+            Metric::Osa,
+            Metric::Indel,
+            Metric::WeightedLevenshtein(EditCosts::new(2, 3, 1).expect("valid")),
+        ] {
+            let options = SearchOptions {
+                metric,
+                ..Default::default()
+            };
+            let hits =
+                get_neighbors_across_with_options(query, reference, 3, &options).expect("valid");
+            let aligned = get_neighbors_across_aligned_with_options(query, reference, 3, &options)
+                .expect("valid");
+            assert!(!aligned.is_empty());
+            assert_eq!(aligned.row, hits.row);
+            assert_eq!(aligned.col, hits.col);
+            assert_eq!(aligned.dists, hits.dists);
+
+            for i in 0..aligned.len() {
+                let a = query[aligned.row[i] as usize].chars().collect_vec();
+                let b = reference[aligned.col[i] as usize].chars().collect_vec();
+                let ops = &aligned.edit_ops[i];
+                assert_eq!(apply_edit_ops(&a, &b, ops), b);
+                let cost = ops
+                    .iter()
+                    .map(|op| match (metric, op) {
+                        (Metric::WeightedLevenshtein(costs), EditOp::Insert { .. }) => {
+                            costs.insertion()
+                        }
+                        (Metric::WeightedLevenshtein(costs), EditOp::Delete { .. }) => {
+                            costs.deletion()
+                        }
+                        (Metric::WeightedLevenshtein(costs), _) => costs.substitution(),
+                        (Metric::Indel, EditOp::Substitute { .. }) => panic!("no substitutions"),
+                        (Metric::Hamming, EditOp::Substitute { .. }) => 1,
+                        (Metric::Hamming, _) => panic!("substitutions only"),
+                        _ => 1,
+                    } as usize)
+                    .sum::<usize>();
+                assert_eq!(cost, aligned.dists[i] as usize);
+            }
+        }
+
+        let options = SearchOptions {
+            metric: Metric::Osa,
+            unicode: true,
+            case_insensitive: true,
+            ..Default::default()
+        };
+        let aligned =
+            get_neighbors_within_aligned_with_options(&["東京都", "京東都", "Tokyo"], 1, &options)
+                .expect("valid");
+        assert_eq!(
+            aligned.edit_ops,
+            vec![vec![EditOp::Transpose {
+                src_pos: 0,
+                dest_pos: 0
+            }]]
+        );
+        let aligned =
+            get_neighbors_across_aligned_with_options(&["TOKYO"], &["okyoo"], 2, &options)
+                .expect("valid");
+        assert_eq!(
+            aligned.edit_ops,
+            vec![vec![
+                EditOp::Delete {
+                    src_pos: 0,
+                    dest_pos: 0
+                },
+                EditOp::Insert {
+                    src_pos: 4,
+                    dest_pos: 3
+                },
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_weighted_levenshtein() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let (query, reference) = (&query[..300], &reference[..50]);
+        let cached = CachedRef::new(reference, 3).expect("short input");
+
+        for (ins, del, sub) in [(1, 1, 1), (2, 2, 1), (1, 2, 3), (3, 1, 1)] {
+            let costs = EditCosts::new(ins, del, sub).expect("valid");
+            let options = SearchOptions {
+                metric: Metric::WeightedLevenshtein(costs),
+                brute_force_max_pairs: Some(0),
+                ..Default::default()
+            };
+
+            for max_distance in [1, 2, 3] {
+                let mut expected = Vec::new();
+                for (qi, q) in query.iter().enumerate() {
+                    for (ri, r) in reference.iter().enumerate() {
+                        let dist = naive::weighted_levenshtein(q, r, costs);
+                        if dist <= max_distance as usize {
+                            expected.push((qi as u32, ri as u32, dist as u8));
+                        }
+                    }
+                }
+                let expected = NeighborPairs::from_triplets(expected);
+
+                assert_eq!(
+                    get_neighbors_across_with_options(query, reference, max_distance, &options)
+                        .expect("valid"),
+                    expected
+                );
+                assert_eq!(
+                    cached
+                        .get_neighbors_across_with_options(query, max_distance, &options)
+                        .expect("valid"),
+                    expected
+                );
+            }
+        }
+
+        // Insertions and deletions turn the row string into the column string, also when
+        // comparing characters.
+        let costs = EditCosts::new(1, 3, 1).expect("valid");
+        let options = SearchOptions {
+            metric: Metric::WeightedLevenshtein(costs),
+            unicode: true,
+            ..Default::default()
+        };
+        for (query, reference, dists) in [
+            (["abc"], ["abcd"], vec![1]),
+            (["abcd"], ["abc"], vec![3]),
+            (["日本"], ["日本語"], vec![1]),
+            (["日本語"], ["日本"], vec![3]),
+            (["日本語"], ["日本x"], vec![1]),
+        ] {
+            let hits = get_neighbors_across_with_options(&query, &reference, 3, &options);
+            assert_eq!(hits.expect("valid").dists, dists);
+        }
+        let hits = get_neighbors_across_with_options(&["abcd"], &["abc"], 2, &options);
+        assert!(hits.expect("valid").is_empty());
+
+        assert!(matches!(
+            EditCosts::new(1, 0, 1),
+            Err(Error::InvalidEditCosts { deletion: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_within_ratio() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let query = &query[..300];
+        let cached = CachedRef::new(query, 4).expect("short input");
+
+        for min_ratio in [0.8, 0.85, 0.9, 1.0] {
+            let mut expected = SimilarPairs {
+                row: Vec::new(),
+                col: Vec::new(),
+                similarities: Vec::new(),
+            };
+            for (i, j) in (0..query.len()).tuple_combinations() {
+                let (a, b) = (&query[i], &query[j]);
+                let dist = levenshtein::distance(a.bytes(), b.bytes());
+                let max_len = a.len().max(b.len());
+                let similarity = 1.0 - dist as f32 / max_len as f32;
+                if similarity < min_ratio {
+                    continue;
+                }
+                expected.row.push(i as u32);
+                expected.col.push(j as u32);
+                expected.similarities.push(similarity);
+            }
+            assert!(min_ratio == 1.0 || !expected.is_empty());
+
+            assert_eq!(
+                get_neighbors_within_ratio(query, min_ratio).expect("valid"),
+                expected
+            );
+            assert_eq!(
+                cached.get_neighbors_within_ratio(min_ratio).expect("valid"),
+                expected
+            );
+        }
+
+        // Pairs of empty strings are identical, and an empty string is dissimilar to any other.
+        let hits = NeighborPairs {
+            row: vec![0, 0, 2],
+            col: vec![1, 2, 3],
+            dists: vec![0, 1, 1],
+        };
+        let lens = [0, 0, 1, 2];
+        assert_eq!(
+            SimilarPairs::from_hits(hits, 0.5, |i| lens[i], |i| lens[i]),
+            SimilarPairs {
+                row: vec![0, 2],
+                col: vec![1, 3],
+                similarities: vec![1.0, 0.5],
+            }
+        );
+        assert_eq!(
+            get_max_distance_for_ratio(0.0, 0).expect("valid").as_u8(),
+            0
+        );
+
+        assert!(matches!(
+            get_neighbors_within_ratio(&["fizz"], 1.5),
+            Err(Error::InvalidRatio { .. })
+        ));
+        assert!(matches!(
+            get_neighbors_within_ratio(&["fizz"], f32::NAN),
+            Err(Error::InvalidRatio { .. })
+        ));
+        assert!(matches!(
+            get_neighbors_within_ratio(&["a".repeat(600)], 0.5),
+            Err(Error::RatioTooLow { required: 300, .. })
+        ));
+        assert!(matches!(
+            cached.get_neighbors_within_ratio(0.5),
+            Err(Error::MaxDistTooLargeForCache { .. })
+        ));
+    }
+
+    #[test]
+    fn test_min_distance() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached_q = CachedRef::new(&query, 2).expect("short input");
+        let cached_r = CachedRef::new(&reference, 2).expect("short input");
+        let filtered = |bytes: &[u8], min_distance: u8| {
+            let hits = bytes_as_neighbour_pairs(bytes);
+            let mut expected = NeighborPairs {
+                row: Vec::new(),
+                col: Vec::new(),
+                dists: Vec::new(),
+            };
+            for ((row, col), dist) in hits.row.into_iter().zip(hits.col).zip(hits.dists) {
+                if dist >= min_distance {
+                    expected.row.push(row);
+                    expected.col.push(col);
+                    expected.dists.push(dist);
+                }
+            }
+            expected
+        };
+
+        let exclude_exact = SearchOptions {
+            exclude_exact: true,
+            ..Default::default()
+        };
+        let min_distance_2 = SearchOptions {
+            min_distance: 2,
+            ..Default::default()
+        };
+        let fused = SearchOptions {
+            fuse_verification: true,
+            ..min_distance_2.clone()
+        };
+        for (options, min_distance) in [(&exclude_exact, 1), (&min_distance_2, 2), (&fused, 2)] {
+            let expected_within = filtered(EXPECTED_BYTES_WITHIN_2, min_distance);
+            let expected_cross = filtered(EXPECTED_BYTES_CROSS_2, min_distance);
+            assert!(expected_cross.len() < bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2).len());
+
+            assert_eq!(
+                get_neighbors_within_with_options(&query, 2, options).expect("valid"),
+                expected_within,
+            );
+            assert_eq!(
+                get_neighbors_across_with_options(&query, &reference, 2, options).expect("valid"),
+                expected_cross,
+            );
+            assert_eq!(
+                cached_q
+                    .get_neighbors_within_with_options(2, options)
+                    .expect("valid"),
+                expected_within,
+            );
+            assert_eq!(
+                cached_r
+                    .get_neighbors_across_with_options(&query, 2, options)
+                    .expect("valid"),
+                expected_cross,
+            );
+            assert_eq!(
+                cached_r
+                    .get_neighbors_across_cached_with_options(&cached_q, 2, options)
+                    .expect("valid"),
+                expected_cross,
+            );
+        }
+    }
+
+    #[test]
+    fn test_exclude_exact_duplicates() {
+        let neighbors = [
+            "CASSLGQAYEQYF",
+            "CASSLGQAYEQY",
+            "CASRLGQAYEQYF",
+            "CASSPGQGYEQYF",
+        ];
+        let with_copies = |num_copies: usize| {
+            let mut query = vec!["CASSLGQAYEQYF"; num_copies];
+            query.extend(neighbors);
+            query.push("CASSLGQAYEQYF");
+            query
+        };
+        let exclude_exact = SearchOptions {
+            exclude_exact: true,
+            brute_force_max_pairs: Some(0),
+            max_candidates: Some(1_000_000),
+            ..Default::default()
+        };
+        let fused = SearchOptions {
+            fuse_verification: true,
+            ..exclude_exact.clone()
+        };
+
+        let query = with_copies(100);
+        let expected = {
+            let hits = get_neighbors_within(&query, 2).expect("valid");
+            let mut expected = NeighborPairs {
+                row: Vec::new(),
+                col: Vec::new(),
+                dists: Vec::new(),
+            };
+            for ((row, col), dist) in hits.row.into_iter().zip(hits.col).zip(hits.dists) {
+                if dist > 0 {
+                    expected.row.push(row);
+                    expected.col.push(col);
+                    expected.dists.push(dist);
+                }
+            }
+            expected
+        };
+        assert_eq!(expected.len(), 102 * 3 + 1);
+        for options in [&exclude_exact, &fused] {
+            assert_eq!(
+                get_neighbors_within_with_options(&query, 2, options).expect("valid"),
+                expected
+            );
+        }
+
+        // The copies would pair up into far more candidates than the limit.
+        let query = with_copies(10_000);
+        let hits = get_neighbors_within_with_options(&query, 2, &exclude_exact).expect("valid");
+        assert_eq!(hits.len(), 10_002 * 3 + 1);
+        assert!(hits.dists.iter().all(|&d| d > 0));
+        let with_exact = SearchOptions {
+            exclude_exact: false,
+            ..exclude_exact.clone()
+        };
+        assert!(matches!(
+            get_neighbors_within_with_options(&query, 2, &with_exact),
+            Err(Error::TooManyCandidates { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unicode() {
+        let hash_builder = FixedState::default();
+        let mut hashes = Vec::new();
+        for_each_variant_hash(
+            Symbols::Text("日本語"),
+            MaxDistance::try_from(1).unwrap(),
+            Anchoring::default(),
+            &hash_builder,
+            &mut VariantScratch::default(),
+            |hash| hashes.push(hash),
+        );
+        let expected = ["日本語", "本語", "日語", "日本"]
+            .map(|variant| {
+                let mut hasher = hash_builder.build_hasher();
+                hasher.write(variant.as_bytes());
+                hasher.finish()
+            })
+            .to_vec();
+        assert_eq!(hashes, expected);
+        assert_eq!(
+            get_num_del_vars_per_string(
+                &["日本語", "café"],
+                MaxDistance::try_from(2).unwrap(),
+                Anchoring::default()
+            ),
+            vec![7, 11]
+        );
+
+        // Each of these is a single character edit, but more than one byte edit, away from its
+        // neighbor.
+        let query = [
+            "café",
+            "cafe",
+            "caffè",
+            "東京都",
+            "京都",
+            "京都府",
+            "日本語",
+            "日本人",
+        ];
+        let reference = ["cafè", "東京", "日本人", "naïve"];
+        let unicode = SearchOptions {
+            unicode: true,
+            brute_force_max_pairs: Some(0),
+            ..Default::default()
+        };
+
+        let hits = get_neighbors_within_with_options(&query, 1, &unicode).expect("valid");
+        assert_eq!(
+            hits,
+            NeighborPairs {
+                row: vec![0, 3, 4, 6],
+                col: vec![1, 4, 5, 7],
+                dists: vec![1, 1, 1, 1],
+            }
+        );
+        assert!(matches!(
+            get_neighbors_within(&query, 1),
+            Err(Error::NonAsciiInput {
+                offending_idx: 0,
+                ..
+            })
+        ));
+        assert_eq!(
+            pairwise_distances(&["cafe", "京都"], &["café", "東京都"], None).ok(),
+            None
+        );
+
+        let expected_cross = NeighborPairs {
+            row: vec![0, 1, 2, 3, 6, 7],
+            col: vec![0, 0, 0, 1, 2, 2],
+            dists: vec![1, 1, 1, 1, 1, 0],
+        };
+        let fused = SearchOptions {
+            fuse_verification: true,
+            ..unicode.clone()
+        };
+        let brute_force = SearchOptions {
+            brute_force_max_pairs: Some(usize::MAX),
+            ..unicode.clone()
+        };
+        for options in [&unicode, &fused, &brute_force] {
+            assert_eq!(
+                get_neighbors_across_with_options(&query, &reference, 1, options).expect("valid"),
+                expected_cross,
+            );
+        }
+
+        let cached = CachedRef::new_with_options(&reference, 1, &unicode).expect("valid");
+        assert_eq!(
+            cached
+                .get_neighbors_across_with_options(&query, 1, &unicode)
+                .expect("valid"),
+            expected_cross,
+        );
+        let cached_query = CachedRef::new_with_options(&query, 1, &unicode).expect("valid");
+        assert_eq!(
+            cached
+                .get_neighbors_across_cached(&cached_query, 1)
+                .expect("valid"),
+            expected_cross,
+        );
+        assert_eq!(cached_query.get_neighbors_within(1).expect("valid"), hits);
+
+        let mut buffer = Vec::new();
+        cached.write_to(&mut buffer).expect("in-memory write");
+        let loaded = CachedRef::read_from(&buffer[..]).expect("valid");
+        assert_eq!(loaded.get(3), Some("naïve"));
+        assert_eq!(
+            loaded
+                .get_neighbors_across_with_options(&query, 1, &unicode)
+                .expect("valid"),
+            expected_cross,
+        );
+    }
+
+    #[test]
+    fn test_bytes() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let query_bytes = query.iter().map(|s| s.as_bytes()).collect_vec();
+        let reference_bytes = reference
+            .iter()
+            .map(|s| s.as_bytes().to_vec())
+            .collect_vec();
+
+        assert_eq!(
+            get_neighbors_within_bytes(&query_bytes, 1).expect("valid"),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_1)
+        );
+        assert_eq!(
+            get_neighbors_across_bytes(&query_bytes, &reference_bytes, 1).expect("valid"),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_1)
+        );
+
+        // Non-UTF-8 bytes are symbols like any other, and multi-byte characters are not special.
+        let query: [&[u8]; 4] = [b"\xff\xfe\x00", b"\xfe\x00", b"caf\xc3\xa9", b"cafe"];
+        let brute_force = SearchOptions {
+            brute_force_max_pairs: Some(usize::MAX),
+            ..Default::default()
+        };
+        let symdel = SearchOptions {
+            brute_force_max_pairs: Some(0),
+            ..Default::default()
+        };
+        for options in [&brute_force, &symdel] {
+            assert_eq!(
+                get_neighbors_within_bytes_with_options(&query, 2, options).expect("valid"),
+                NeighborPairs {
+                    row: vec![0, 2],
+                    col: vec![1, 3],
+                    dists: vec![1, 2],
+                }
+            );
+            assert_eq!(
+                get_neighbors_across_bytes_with_options(&query, &query[..2], 1, options)
+                    .expect("valid"),
+                NeighborPairs {
+                    row: vec![0, 0, 1, 1],
+                    col: vec![0, 1, 0, 1],
+                    dists: vec![0, 1, 1, 0],
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_top_k() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached_r = CachedRef::new(&reference, 2).expect("short input");
+        let top_k = |k: usize| {
+            let hits = bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2);
+            let mut hits = hits
+                .row
+                .into_iter()
+                .zip(hits.dists)
+                .zip(hits.col)
+                .map(|((row, dist), col)| (row, dist, col))
+                .collect_vec();
+            hits.sort_unstable();
+            let mut expected = NeighborPairs {
+                row: Vec::new(),
+                col: Vec::new(),
+                dists: Vec::new(),
+            };
+            for (_, group) in &hits.into_iter().chunk_by(|&(row, _, _)| row) {
+                for (row, dist, col) in group.take(k) {
+                    expected.row.push(row);
+                    expected.col.push(col);
+                    expected.dists.push(dist);
+                }
+            }
+            expected
+        };
+
+        let fused = SearchOptions {
+            fuse_verification: true,
+            ..Default::default()
+        };
+        let brute_force = SearchOptions {
+            brute_force_max_pairs: Some(usize::MAX),
+            ..Default::default()
+        };
+        for k in [0, 1, 2, 5] {
+            let expected = top_k(k);
+            if k > 0 {
+                assert!(!expected.is_empty());
+            }
+
+            assert_eq!(
+                get_neighbors_across_top_k(&query, &reference, 2, k).expect("valid"),
+                expected
+            );
+            assert_eq!(
+                get_neighbors_across_top_k_with_options(&query, &reference, 2, k, &fused)
+                    .expect("valid"),
+                expected
+            );
+            assert_eq!(
+                get_neighbors_across_top_k_with_options(
+                    &query[..300],
+                    &reference[..50],
+                    2,
+                    k,
+                    &brute_force
+                )
+                .expect("valid"),
+                get_neighbors_across_top_k(&query[..300], &reference[..50], 2, k).expect("valid"),
+            );
+            assert_eq!(
+                cached_r
+                    .get_neighbors_across_top_k(&query, 2, k)
+                    .expect("valid"),
+                expected
+            );
+        }
+        assert!(top_k(1).len() < top_k(5).len());
+    }
+
+    #[test]
+    fn test_nearest() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached_r = CachedRef::new(&reference, 2).expect("short input");
+        let nearest = |min_distance: u8| {
+            let hits = bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2);
+            let mut expected: Vec<Option<(u32, u8)>> = vec![None; query.len()];
+            for ((row, col), dist) in hits.row.into_iter().zip(hits.col).zip(hits.dists) {
+                let entry = &mut expected[row as usize];
+                if dist >= min_distance && entry.is_none_or(|(c, d)| (dist, col) < (d, c)) {
+                    *entry = Some((col, dist));
+                }
+            }
+            expected
+        };
+
+        let expected = nearest(0);
+        assert!(expected.iter().any(|hit| matches!(hit, Some((_, 0)))));
+        assert!(expected.iter().any(|hit| matches!(hit, Some((_, 2)))));
+        assert!(expected.iter().any(Option::is_none));
+        assert_eq!(
+            get_nearest_neighbors_across(&query, &reference, 2).expect("valid"),
+            expected
+        );
+        assert_eq!(
+            cached_r
+                .get_nearest_neighbors_across(&query, 2)
+                .expect("valid"),
+            expected
+        );
+
+        let exclude_exact = SearchOptions {
+            exclude_exact: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_nearest_neighbors_across_with_options(&query, &reference, 2, &exclude_exact)
+                .expect("valid"),
+            nearest(1)
+        );
+    }
+
+    #[test]
+    fn test_representatives() {
+        // "fizz" ~ "fuzz" ~ "fuzzy", but "fizz" !~ "fuzzy": greedily, "fuzz" is dropped in favor
+        // of "fizz", which leaves "fuzzy" to be kept, whereas transitively all three would merge.
+        let chain = ["fizz", "fuzz", "fuzzy", "fizz", "fuzzy"];
+        assert_eq!(
+            get_representatives_within(&chain, 1).expect("valid"),
+            vec![0, 0, 2, 0, 2]
+        );
+        assert_eq!(deduplicate_within(&chain, 1).expect("valid"), vec![0, 2]);
+
+        let options = SearchOptions {
+            min_distance: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_representatives_within_with_options(&chain, 1, &options).expect("valid"),
+            vec![0, 0, 2, 3, 4]
+        );
+
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES)[..1000].to_vec();
+        for max_distance in [1, 2] {
+            let mut expected = vec![u32::MAX; query.len()];
+            for i in 0..query.len() {
+                expected[i] = (0..i)
+                    .find(|&k| {
+                        expected[k] == k as u32
+                            && levenshtein::distance(query[k].bytes(), query[i].bytes())
+                                <= max_distance as usize
+                    })
+                    .unwrap_or(i) as u32;
+            }
+            assert_eq!(
+                get_representatives_within(&query, max_distance).expect("valid"),
+                expected
+            );
+            assert_eq!(
+                deduplicate_within(&query, max_distance).expect("valid"),
+                (0..query.len() as u32)
+                    .filter(|&i| expected[i as usize] == i)
+                    .collect_vec()
+            );
+        }
+    }
+
+    #[test]
+    fn test_neighbor_counts() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached_r = CachedRef::new(&reference, 2).expect("short input");
+
+        let hits = bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2);
+        let mut expected = vec![0; query.len()];
+        for (row, col) in hits.row.into_iter().zip(hits.col) {
+            expected[row as usize] += 1;
+            expected[col as usize] += 1;
+        }
+        assert_eq!(
+            get_neighbor_counts_within(&query, 2).expect("valid"),
+            expected
+        );
+
+        let hits = bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2);
+        let mut expected = vec![0; query.len()];
+        for row in hits.row {
+            expected[row as usize] += 1;
+        }
+        assert_eq!(
+            get_neighbor_counts_across(&query, &reference, 2).expect("valid"),
+            expected
+        );
+        assert_eq!(
+            cached_r
+                .get_neighbor_counts_across(&query, 2)
+                .expect("valid"),
+            expected
+        );
+
+        let exclude_exact = SearchOptions {
+            exclude_exact: true,
+            ..Default::default()
+        };
+        let hits = get_neighbors_within_with_options(&query, 2, &exclude_exact).expect("valid");
+        let mut expected = vec![0; query.len()];
+        for (row, col) in hits.row.into_iter().zip(hits.col) {
+            expected[row as usize] += 1;
+            expected[col as usize] += 1;
+        }
+        assert_eq!(
+            get_neighbor_counts_within_with_options(&query, 2, &exclude_exact).expect("valid"),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_naive_oracle() {
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        let mut next = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as usize
+        };
+        let mut random_strings = |n: usize, alphabet: &[char]| {
+            (0..n)
+                .map(|_| {
+                    let len = next() % 9;
+                    (0..len)
+                        .map(|_| alphabet[next() % alphabet.len()])
+                        .collect::<String>()
+                })
+                .collect_vec()
+        };
+
+        assert_eq!(naive::levenshtein("kitten", "sitting"), 3);
+        assert_eq!(naive::levenshtein("", "日本"), 2);
+
+        let unicode = SearchOptions {
+            unicode: true,
+            ..Default::default()
+        };
+        for round in 0..20 {
+            let alphabet: &[char] = if round % 4 == 3 {
+                &['a', 'é', '日']
+            } else {
+                &['A', 'C', 'G', 'T']
+            };
+            let query = random_strings(100 + round * 10, alphabet);
+            let reference = random_strings(80, alphabet);
+            let cached = CachedRef::new_with_options(&reference, 3, &unicode).expect("valid");
+            for max_distance in [0, 1, 2, 3] {
+                let expected = naive::naive_within(&query, max_distance);
+                assert_eq!(
+                    get_neighbors_within_with_options(&query, max_distance, &unicode)
+                        .expect("valid"),
+                    expected
+                );
+
+                let expected = naive::naive_across(&query, &reference, max_distance);
+                assert_eq!(
+                    get_neighbors_across_with_options(&query, &reference, max_distance, &unicode)
+                        .expect("valid"),
+                    expected
+                );
+                assert_eq!(
+                    cached
+                        .get_neighbors_across_with_options(&query, max_distance, &unicode)
+                        .expect("valid"),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_has_neighbor() {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as usize
+        };
+        let mut random_strings = |n: usize| {
+            (0..n)
+                .map(|_| {
+                    let len = 2 + next() % 5;
+                    (0..len)
+                        .map(|_| b"ACG"[next() % 3] as char)
+                        .collect::<String>()
+                })
+                .collect_vec()
+        };
+
+        for (num_query, num_reference) in [(0, 10), (10, 0), (50, 50), (2000, 500)] {
+            let query = random_strings(num_query);
+            let reference = random_strings(num_reference);
+            let cached_r = CachedRef::new(&reference, 2).expect("short input");
+            let brute_force = SearchOptions {
+                brute_force_max_pairs: Some(usize::MAX),
+                ..Default::default()
+            };
+            let exclude_exact = SearchOptions {
+                exclude_exact: true,
+                ..Default::default()
+            };
+
+            for max_distance in [0, 1, 2] {
+                for options in [&SearchOptions::default(), &brute_force, &exclude_exact] {
+                    let hits = get_neighbors_across_with_options(
+                        &query,
+                        &reference,
+                        max_distance,
+                        options,
+                    )
+                    .expect("valid");
+                    let mut expected = vec![false; query.len()];
+                    for row in hits.row {
+                        expected[row as usize] = true;
+                    }
+
+                    assert_eq!(
+                        has_neighbor_across_with_options(&query, &reference, max_distance, options)
+                            .expect("valid"),
+                        expected
+                    );
+                    assert_eq!(
+                        cached_r
+                            .has_neighbor_across_with_options(&query, max_distance, options)
+                            .expect("valid"),
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_orphans() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached_r = CachedRef::new(&reference, 2).expect("valid");
+
+        let keep = |qi: u32, ri: u32| !(qi + ri).is_multiple_of(3);
+        let options_within = [
+            SearchOptions::default(),
+            SearchOptions {
+                exclude_exact: true,
+                ..Default::default()
+            },
+            SearchOptions {
+                pair_filter: Some(PairFilter::new(keep)),
+                excluded_pairs: Some(vec![(0, 1), (0, 2), (4, 9)].into()),
+                ..Default::default()
+            },
+            SearchOptions {
+                case_insensitive: true,
+                ..Default::default()
+            },
+        ];
+        for max_distance in [0, 1, 2] {
+            for options in &options_within {
+                let hits = get_neighbors_within_with_options(&query, max_distance, options)
+                    .expect("valid");
+                let mut expected = vec![true; query.len()];
+                for idx in hits.row.into_iter().chain(hits.col) {
+                    expected[idx as usize] = false;
+                }
+                assert_eq!(
+                    orphans_within_with_options(&query, max_distance, options).expect("valid"),
+                    expected
+                );
+            }
+
+            let expected = has_neighbor_across(&query, &reference, max_distance)
+                .expect("valid")
+                .into_iter()
+                .map(|has_neighbor| !has_neighbor)
+                .collect_vec();
+            assert_eq!(
+                cached_r.orphan_mask(&query, max_distance).expect("valid"),
+                expected
+            );
+        }
+
+        assert!(matches!(
+            cached_r.orphan_mask(&query, 3),
+            Err(Error::MaxDistTooLargeForCache { got: 3, limit: 2 })
+        ));
+        assert_eq!(
+            orphans_within(&["fizz", "FIZZ"], 0).expect("valid"),
+            vec![true, true]
+        );
+    }
+
+    #[test]
+    fn test_candidate_pairs() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let reassemble = |candidates: Vec<(u32, u32)>, dists: Vec<u8>| {
+            let (mut row, mut col, mut kept_dists) = (Vec::new(), Vec::new(), Vec::new());
+            for ((qi, ri), d) in candidates.into_iter().zip(dists) {
+                if d <= 2 {
+                    row.push(qi);
+                    col.push(ri);
+                    kept_dists.push(d);
+                }
+            }
+            NeighborPairs {
+                row,
+                col,
+                dists: kept_dists,
+            }
+        };
+
+        let candidates = get_candidate_pairs_within(&query, 2).expect("valid");
+        assert!(candidates.is_sorted() && candidates.iter().all_unique());
+        assert!(candidates.iter().all(|&(a, b)| a < b));
+        let dists = compute_distances(&candidates, &query, &query, 2).expect("valid");
+        assert_eq!(
+            reassemble(candidates, dists),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2)
+        );
+
+        let candidates = get_candidate_pairs_across(&query, &reference, 2).expect("valid");
+        assert!(candidates.is_sorted() && candidates.iter().all_unique());
+        let dists = compute_distances(&candidates, &query, &reference, 2).expect("valid");
+        assert_eq!(
+            reassemble(candidates, dists),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2)
+        );
+
+        // Brute force comparison makes candidates of all pairs.
+        let brute_force = SearchOptions {
+            brute_force_max_pairs: Some(usize::MAX),
+            ..Default::default()
+        };
+        let strings = ["fizz", "fuzz", "buzz"];
+        assert_eq!(
+            get_candidate_pairs_within_with_options(&strings, 1, &brute_force).expect("valid"),
+            vec![(0, 1), (0, 2), (1, 2)]
+        );
+
+        let case_insensitive = SearchOptions {
+            case_insensitive: true,
+            brute_force_max_pairs: Some(0),
+            ..Default::default()
+        };
+        let upper = ["FIZZ", "Fuzz"];
+        assert_eq!(
+            get_candidate_pairs_across_with_options(&strings, &upper, 1, &case_insensitive)
+                .expect("valid"),
+            vec![(0, 0), (0, 1), (1, 0), (1, 1), (2, 1)]
+        );
+        assert_eq!(
+            compute_distances_with_options(
+                &[(0, 0), (2, 1)],
+                &strings,
+                &upper,
+                1,
+                &case_insensitive
+            )
+            .expect("valid"),
+            vec![0, 1]
+        );
+        assert!(matches!(
+            compute_distances(&[(0, 0)], &strings, &upper, 255),
+            Err(Error::MaxDistCapped)
+        ));
+    }
+
+    #[test]
+    fn test_distances_for_pairs() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached_r = CachedRef::new(&reference, 1).expect("short input");
+
+        let pairs: Vec<_> = (0..query.len() as u32)
+            .step_by(7)
+            .flat_map(|qi| {
+                (0..reference.len() as u32)
+                    .step_by(11)
+                    .map(move |ri| (qi, ri))
+            })
+            .collect();
+        let expected: Vec<_> = pairs
+            .iter()
+            .map(|&(qi, ri)| {
+                let d = naive::levenshtein(&query[qi as usize], &reference[ri as usize]);
+                (d <= 3).then_some(d as u8)
+            })
+            .collect();
+        assert!(expected.iter().any(Option::is_some) && expected.iter().any(Option::is_none));
+        assert_eq!(
+            distances_for_pairs(&pairs, &query, &reference, 3).expect("valid"),
+            expected
+        );
+        // The reference need not be searchable at max_distance.
+        assert_eq!(
+            cached_r
+                .distances_for_pairs_cached(&pairs, &query, 3)
+                .expect("valid"),
+            expected
+        );
+
+        let case_insensitive = SearchOptions {
+            case_insensitive: true,
+            ..Default::default()
+        };
+        let strings = ["fizz", "fuzz", "buzz"];
+        let upper = ["FIZZ", "Fuzz"];
+        let cached_upper =
+            CachedRef::new_with_options(&upper, 1, &case_insensitive).expect("valid");
+        let pairs = [(0, 0), (2, 1), (2, 0)];
+        assert_eq!(
+            distances_for_pairs_with_options(&pairs, &strings, &upper, 1, &case_insensitive)
+                .expect("valid"),
+            vec![Some(0), Some(1), None]
+        );
+        assert_eq!(
+            cached_upper
+                .distances_for_pairs_cached(&pairs, &strings, 1)
+                .expect("valid"),
+            vec![Some(0), Some(1), None]
+        );
+
+        assert!(matches!(
+            distances_for_pairs(&[(0, 0), (3, 0)], &strings, &upper, 1),
+            Err(Error::PairOutOfRange {
+                idx: 1,
+                pair: (3, 0),
+                query_len: 3,
+                reference_len: 2,
+            })
+        ));
+        assert!(matches!(
+            cached_upper.distances_for_pairs_cached(&[(0, 2)], &strings, 1),
+            Err(Error::PairOutOfRange { idx: 0, .. })
+        ));
+        assert!(matches!(
+            distances_for_pairs(&[(0, 0)], &strings, &upper, 255),
+            Err(Error::MaxDistCapped)
+        ));
+    }
+
+    #[test]
+    fn test_for_each_neighbor() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached_r = CachedRef::new(&reference, 2).expect("short input");
+        let into_pairs = |hits: Mutex<Vec<(u32, u32, u8)>>| {
+            let mut hits = hits.into_inner().expect("not poisoned");
+            hits.sort_unstable();
+            let mut pairs = NeighborPairs {
+                row: Vec::new(),
+                col: Vec::new(),
+                dists: Vec::new(),
+            };
+            for (row, col, dist) in hits {
+                pairs.row.push(row);
+                pairs.col.push(col);
+                pairs.dists.push(dist);
+            }
+            pairs
+        };
+
+        let hits = Mutex::new(Vec::new());
+        for_each_neighbor_within(&query, 2, |row, col, dist| {
+            hits.lock().expect("not poisoned").push((row, col, dist))
+        })
+        .expect("valid");
+        assert_eq!(
+            into_pairs(hits),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2)
+        );
+
+        let hits = Mutex::new(Vec::new());
+        for_each_neighbor_across(&query, &reference, 2, |row, col, dist| {
+            hits.lock().expect("not poisoned").push((row, col, dist))
+        })
+        .expect("valid");
+        assert_eq!(
+            into_pairs(hits),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2)
+        );
+
+        let hits = Mutex::new(Vec::new());
+        cached_r
+            .for_each_neighbor_across(&query, 2, |row, col, dist| {
+                hits.lock().expect("not poisoned").push((row, col, dist))
+            })
+            .expect("valid");
+        assert_eq!(
+            into_pairs(hits),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2)
+        );
+    }
+
+    #[test]
+    fn test_pair_filter() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached_q = CachedRef::new(&query, 2).expect("short input");
+        let cached_r = CachedRef::new(&reference, 2).expect("short input");
+        let keep = |qi: u32, ri: u32| !(qi + ri).is_multiple_of(3);
+        let filtered = |bytes: &[u8]| {
+            let hits = bytes_as_neighbour_pairs(bytes);
+            let mut expected = NeighborPairs {
+                row: Vec::new(),
+                col: Vec::new(),
+                dists: Vec::new(),
+            };
+            for ((row, col), dist) in hits.row.into_iter().zip(hits.col).zip(hits.dists) {
+                if keep(row, col) {
+                    expected.row.push(row);
+                    expected.col.push(col);
+                    expected.dists.push(dist);
+                }
+            }
+            expected
+        };
+        let expected_within = filtered(EXPECTED_BYTES_WITHIN_2);
+        let expected_cross = filtered(EXPECTED_BYTES_CROSS_2);
+        assert!(expected_cross.len() < bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2).len());
+
+        let options = SearchOptions {
+            pair_filter: Some(PairFilter::new(keep)),
+            ..Default::default()
+        };
+        let fused = SearchOptions {
+            fuse_verification: true,
+            ..options.clone()
+        };
+        for options in [&options, &fused] {
+            assert_eq!(
+                get_neighbors_within_with_options(&query, 2, options).expect("valid"),
+                expected_within
+            );
+            assert_eq!(
+                get_neighbors_across_with_options(&query, &reference, 2, options).expect("valid"),
+                expected_cross
+            );
+        }
+        assert_eq!(
+            cached_q
+                .get_neighbors_within_with_options(2, &options)
+                .expect("valid"),
+            expected_within
+        );
+        assert_eq!(
+            cached_r
+                .get_neighbors_across_with_options(&query, 2, &options)
+                .expect("valid"),
+            expected_cross
+        );
+        assert_eq!(
+            cached_r
+                .get_neighbors_across_cached_with_options(&cached_q, 2, &options)
+                .expect("valid"),
+            expected_cross
+        );
+    }
+
+    #[test]
+    fn test_excluded_pairs() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let split = |bytes: &[u8]| {
+            let hits = bytes_as_neighbour_pairs(bytes);
+            let mut excluded = Vec::new();
+            let mut expected = NeighborPairs {
+                row: Vec::new(),
+                col: Vec::new(),
+                dists: Vec::new(),
+            };
+            for (i, ((row, col), dist)) in hits
+                .row
+                .into_iter()
+                .zip(hits.col)
+                .zip(hits.dists)
+                .enumerate()
+            {
+                if i % 3 == 0 {
+                    excluded.push((row, col));
+                } else {
+                    expected.row.push(row);
+                    expected.col.push(col);
+                    expected.dists.push(dist);
+                }
+            }
+            // Pairs that are not hits to begin with can be excluded all the same.
+            excluded.push((0, query.len() as u32 - 1));
+            excluded.sort_unstable();
+            excluded.dedup();
+            (excluded, expected)
+        };
+        let (excluded_within, expected_within) = split(EXPECTED_BYTES_WITHIN_2);
+        let (excluded_cross, expected_cross) = split(EXPECTED_BYTES_CROSS_2);
+
+        for options in [
+            SearchOptions::default(),
+            SearchOptions {
+                fuse_verification: true,
+                ..Default::default()
+            },
+            SearchOptions {
+                deduplicate: true,
+                ..Default::default()
+            },
+        ] {
+            let within = SearchOptions {
+                excluded_pairs: Some(excluded_within.clone().into()),
+                ..options.clone()
+            };
+            assert_eq!(
+                get_neighbors_within_with_options(&query, 2, &within).expect("valid"),
+                expected_within
+            );
+            let cross = SearchOptions {
+                excluded_pairs: Some(excluded_cross.clone().into()),
+                ..options
+            };
+            assert_eq!(
+                get_neighbors_across_with_options(&query, &reference, 2, &cross).expect("valid"),
+                expected_cross
+            );
+        }
+
+        let options = SearchOptions {
+            excluded_pairs: Some(excluded_within.clone().into()),
+            ..Default::default()
+        };
+        let counts = get_neighbor_counts_within_with_options(&query, 2, &options).expect("valid");
+        assert_eq!(
+            counts.iter().sum::<u32>() as usize,
+            2 * expected_within.len()
+        );
+
+        let invalid = |excluded: Vec<(u32, u32)>| SearchOptions {
+            excluded_pairs: Some(excluded.into()),
+            ..Default::default()
+        };
+        let n = query.len() as u32;
+        assert!(matches!(
+            get_neighbors_within_with_options(&query, 2, &invalid(vec![(0, 2), (0, 1)])),
+            Err(Error::InvalidExcludedPair {
+                idx: 1,
+                pair: (0, 1)
+            })
+        ));
+        assert!(matches!(
+            get_neighbors_within_with_options(&query, 2, &invalid(vec![(0, 1), (0, 1)])),
+            Err(Error::InvalidExcludedPair { idx: 1, .. })
+        ));
+        assert!(matches!(
+            get_neighbors_within_with_options(&query, 2, &invalid(vec![(1, 0)])),
+            Err(Error::InvalidExcludedPair { idx: 0, .. })
+        ));
+        assert!(matches!(
+            get_neighbors_within_with_options(&query, 2, &invalid(vec![(0, n)])),
+            Err(Error::InvalidExcludedPair { idx: 0, .. })
+        ));
+        assert!(matches!(
+            get_neighbors_across_with_options(&query, &reference, 2, &invalid(vec![(n, 0)])),
+            Err(Error::InvalidExcludedPair { idx: 0, .. })
+        ));
+        assert!(
+            get_neighbors_across_with_options(&query, &reference, 2, &invalid(vec![(1, 0)]))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_grouped() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let labels = (0..query.len() as u32).map(|i| i % 7 % 3).collect_vec();
+        let filtered = |hits: NeighborPairs, policy: GroupPolicy| {
+            let mut expected = NeighborPairs {
+                row: Vec::new(),
+                col: Vec::new(),
+                dists: Vec::new(),
+            };
+            for ((row, col), dist) in hits.row.into_iter().zip(hits.col).zip(hits.dists) {
+                if policy.allows(labels[row as usize], labels[col as usize]) {
+                    expected.row.push(row);
+                    expected.col.push(col);
+                    expected.dists.push(dist);
+                }
+            }
+            expected
+        };
+
+        let exclude_exact = SearchOptions {
+            exclude_exact: true,
+            ..Default::default()
+        };
+        let fused = SearchOptions {
+            fuse_verification: true,
+            ..Default::default()
+        };
+        let brute_force = SearchOptions {
+            brute_force_max_pairs: Some(usize::MAX),
+            ..Default::default()
+        };
+        for policy in [GroupPolicy::WithinGroupsOnly, GroupPolicy::AcrossGroupsOnly] {
+            let expected = filtered(bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2), policy);
+            assert!(!expected.is_empty());
+            assert!(expected.len() < bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2).len());
+            assert_eq!(
+                get_neighbors_within_grouped(&query, &labels, 2, policy).expect("valid"),
+                expected
+            );
+            assert_eq!(
+                get_neighbors_within_grouped_with_options(&query, &labels, 2, policy, &fused)
+                    .expect("valid"),
+                expected
+            );
+
+            let all_hits = get_neighbors_within_with_options(&query, 2, &exclude_exact);
+            assert_eq!(
+                get_neighbors_within_grouped_with_options(
+                    &query,
+                    &labels,
+                    2,
+                    policy,
+                    &exclude_exact
+                )
+                .expect("valid"),
+                filtered(all_hits.expect("valid"), policy)
+            );
+
+            let all_hits = get_neighbors_within(&query[..300], 2).expect("valid");
+            assert_eq!(
+                get_neighbors_within_grouped_with_options(
+                    &query[..300],
+                    &labels[..300],
+                    2,
+                    policy,
+                    &brute_force
+                )
+                .expect("valid"),
+                filtered(all_hits, policy)
+            );
+        }
+
+        assert!(matches!(
+            get_neighbors_within_grouped(&query, &labels[1..], 2, GroupPolicy::WithinGroupsOnly),
+            Err(Error::MismatchedLabels { .. })
+        ));
+    }
+
+    #[test]
+    fn test_multi() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let sets = [&query[..3000], &reference[..], &[], &query[3000..]];
+
+        let mut expected_within = Vec::new();
+        let mut expected_cross = Vec::new();
+        for (set_a, strings_a) in sets.iter().enumerate() {
+            let hits = get_neighbors_within(strings_a, 2).expect("valid");
+            for ((row, col), dist) in hits.row.into_iter().zip(hits.col).zip(hits.dists) {
+                expected_within.push((set_a as u32, row, set_a as u32, col, dist));
+            }
+            for (set_b, strings_b) in sets.iter().enumerate().skip(set_a + 1) {
+                let hits = get_neighbors_across(strings_a, strings_b, 2).expect("valid");
+                for ((row, col), dist) in hits.row.into_iter().zip(hits.col).zip(hits.dists) {
+                    expected_cross.push((set_a as u32, row, set_b as u32, col, dist));
+                }
+            }
+        }
+        let as_tuples = |hits: MultiNeighborPairs| {
+            itertools::izip!(hits.row_set, hits.row, hits.col_set, hits.col, hits.dists)
+                .collect_vec()
+        };
+        let sorted = |hits: Vec<_>| hits.into_iter().sorted().collect_vec();
+
+        let fused = SearchOptions {
+            fuse_verification: true,
+            ..Default::default()
+        };
+        for options in [&SearchOptions::default(), &fused] {
+            let hits = get_neighbors_multi_with_options(&sets, 2, MultiPolicy::WithinOnly, options)
+                .expect("valid");
+            assert_eq!(as_tuples(hits), sorted(expected_within.clone()));
+
+            let hits = get_neighbors_multi_with_options(&sets, 2, MultiPolicy::CrossOnly, options)
+                .expect("valid");
+            assert_eq!(as_tuples(hits), sorted(expected_cross.clone()));
+
+            let hits = get_neighbors_multi_with_options(&sets, 2, MultiPolicy::AllPairs, options)
+                .expect("valid");
+            assert_eq!(
+                as_tuples(hits),
+                sorted([expected_within.clone(), expected_cross.clone()].concat())
+            );
+        }
 
-    // component tests
+        let no_sets: [&[&str]; 0] = [];
+        assert!(get_neighbors_multi(&no_sets, 2, MultiPolicy::AllPairs)
+            .expect("valid")
+            .is_empty());
+        assert!(matches!(
+            get_neighbors_multi(&[&["fizz"][..], &["füzz"]], 1, MultiPolicy::AllPairs),
+            Err(Error::NonAsciiInput {
+                offending_idx: 0,
+                ..
+            })
+        ));
+    }
 
     #[test]
-    fn test_nck() {
-        let cases = [(5, 2, 10), (5, 5, 1), (5, 0, 1)];
-        for (n, k, expected) in cases {
-            let result = get_num_k_combs(n, k);
-            assert_eq!(result, expected);
+    fn test_anchor() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached_r = CachedRef::new(&reference, 2).expect("short input");
+        let filtered = |hits: NeighborPairs, reference: &[String], anchor: Anchor| {
+            let mut expected = NeighborPairs::default();
+            for ((row, col), dist) in hits.row.into_iter().zip(hits.col).zip(hits.dists) {
+                let (a, b) = (&query[row as usize], &reference[col as usize]);
+                let (prefix, suffix) = (anchor.prefix as usize, anchor.suffix as usize);
+                if a.len().min(b.len()) >= prefix + suffix
+                    && a[..prefix] == b[..prefix]
+                    && a[a.len() - suffix..] == b[b.len() - suffix..]
+                {
+                    expected.row.push(row);
+                    expected.col.push(col);
+                    expected.dists.push(dist);
+                }
+            }
+            expected
+        };
+
+        for anchor in [
+            Anchor {
+                prefix: 3,
+                suffix: 0,
+            },
+            Anchor {
+                prefix: 0,
+                suffix: 2,
+            },
+            Anchor {
+                prefix: 4,
+                suffix: 3,
+            },
+            Anchor {
+                prefix: 200,
+                suffix: 0,
+            },
+        ] {
+            let options = SearchOptions {
+                anchor,
+                ..Default::default()
+            };
+            let fused = SearchOptions {
+                anchor,
+                fuse_verification: true,
+                ..Default::default()
+            };
+            let brute_force = SearchOptions {
+                anchor,
+                brute_force_max_pairs: Some(usize::MAX),
+                ..Default::default()
+            };
+
+            let expected = filtered(
+                bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2),
+                &query,
+                anchor,
+            );
+            assert!(expected.len() < bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2).len());
+            for options in [&options, &fused] {
+                assert_eq!(
+                    get_neighbors_within_with_options(&query, 2, options).expect("valid"),
+                    expected
+                );
+            }
+
+            let expected = filtered(
+                bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2),
+                &reference,
+                anchor,
+            );
+            for options in [&options, &fused] {
+                assert_eq!(
+                    get_neighbors_across_with_options(&query, &reference, 2, options)
+                        .expect("valid"),
+                    expected
+                );
+            }
+            assert_eq!(
+                cached_r
+                    .get_neighbors_across_with_options(&query, 2, &options)
+                    .expect("valid"),
+                expected
+            );
+
+            let all_hits = get_neighbors_within(&query[..300], 2).expect("valid");
+            assert_eq!(
+                get_neighbors_within_with_options(&query[..300], 2, &brute_force).expect("valid"),
+                filtered(all_hits, &query, anchor)
+            );
+        }
+
+        // Anchored regions are measured in characters, and strings too short to hold them have
+        // no neighbors, not even identical ones.
+        let options = SearchOptions {
+            anchor: Anchor {
+                prefix: 1,
+                suffix: 1,
+            },
+            unicode: true,
+            brute_force_max_pairs: Some(0),
+            ..Default::default()
+        };
+        let strings = ["日本本語", "日本語", "月本語", "日本", "日本", "x", "x"];
+        assert_eq!(
+            get_neighbors_within_with_options(&strings, 1, &options).expect("valid"),
+            NeighborPairs::from_triplets(vec![(0, 1, 1), (3, 4, 0)])
+        );
+        assert_eq!(
+            get_candidate_pairs_within_with_options(&strings, 1, &options).expect("valid"),
+            vec![(0, 1), (3, 4)]
+        );
+
+        // Unless they are to be matched exactly, in which case they only neighbor their copies.
+        let strings = [
+            "日本本語",
+            "日本語",
+            "月本語",
+            "日本",
+            "日本",
+            "x",
+            "x",
+            "y",
+        ];
+        let expected = NeighborPairs::from_triplets(vec![(0, 1, 1), (3, 4, 0), (5, 6, 0)]);
+        let cached = CachedRef::new_with_options(&strings, 1, &options).expect("short input");
+        for brute_force_max_pairs in [Some(0), Some(usize::MAX)] {
+            for exact_grouping in [false, true] {
+                let options = SearchOptions {
+                    short_strings: ShortStrings::MatchExactly,
+                    brute_force_max_pairs,
+                    exact_grouping,
+                    ..options.clone()
+                };
+                assert_eq!(
+                    get_neighbors_within_with_options(&strings, 1, &options).expect("valid"),
+                    expected
+                );
+                assert_eq!(
+                    get_neighbors_across_with_options(&strings[..5], &strings[3..], 1, &options)
+                        .expect("valid"),
+                    NeighborPairs::from_triplets(vec![(3, 0, 0), (3, 1, 0), (4, 0, 0), (4, 1, 0)])
+                );
+                assert_eq!(
+                    cached
+                        .get_neighbors_across_with_options(&strings[5..], 1, &options)
+                        .expect("valid"),
+                    NeighborPairs::from_triplets(vec![
+                        (0, 5, 0),
+                        (0, 6, 0),
+                        (1, 5, 0),
+                        (1, 6, 0),
+                        (2, 7, 0)
+                    ])
+                );
+            }
         }
     }
 
     #[test]
-    fn test_get_num_del_vars_per_string() {
-        let strings = ["foo".to_string(), "bar".to_string(), "baz".to_string()];
-        let result =
-            get_num_del_vars_per_string(&strings, MaxDistance::try_from(1).expect("legal"));
-        assert_eq!(result, vec![4, 4, 4]);
+    fn test_anchor_naive() {
+        // Deterministic pseudo-random strings over a small alphabet, some shorter than the
+        // anchored regions.
+        let mut state = 7u64;
+        let mut random_string = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+            let len = (state >> 61) as usize + 1;
+            (0..len)
+                .map(|i| b"AC"[((state >> (i + 8)) % 2) as usize] as char)
+                .collect::<String>()
+        };
+        let query = (0..150).map(|_| random_string()).collect_vec();
+        let reference = (0..100).map(|_| random_string()).collect_vec();
+
+        let naive = |anchor: Anchor, short_strings, a: &str, b: &str, max_distance| {
+            let (prefix, suffix) = (anchor.prefix as usize, anchor.suffix as usize);
+            let fits = |s: &str| s.len() >= prefix + suffix;
+            let dist = match (fits(a), fits(b)) {
+                (true, true)
+                    if a[..prefix] == b[..prefix]
+                        && a[a.len() - suffix..] == b[b.len() - suffix..] =>
+                {
+                    naive::levenshtein(a, b)
+                }
+                (false, false) if short_strings == ShortStrings::MatchExactly && a == b => 0,
+                _ => return None,
+            };
+            (dist <= max_distance as usize).then_some(dist as u8)
+        };
+
+        for ((anchor, short_strings), max_distance) in [(1, 0), (0, 2), (2, 1)]
+            .into_iter()
+            .cartesian_product([ShortStrings::Skip, ShortStrings::MatchExactly])
+            .cartesian_product([1, 2])
+        {
+            let anchor = Anchor {
+                prefix: anchor.0,
+                suffix: anchor.1,
+            };
+            let options = SearchOptions {
+                anchor,
+                short_strings,
+                ..Default::default()
+            };
+
+            let mut expected = Vec::new();
+            for (i, j) in (0..query.len()).tuple_combinations() {
+                if let Some(dist) = naive(anchor, short_strings, &query[i], &query[j], max_distance)
+                {
+                    expected.push((i as u32, j as u32, dist));
+                }
+            }
+            assert_eq!(
+                get_neighbors_within_with_options(&query, max_distance, &options).expect("valid"),
+                NeighborPairs::from_triplets(expected)
+            );
+
+            let mut expected = Vec::new();
+            for (i, j) in (0..query.len()).cartesian_product(0..reference.len()) {
+                if let Some(dist) = naive(
+                    anchor,
+                    short_strings,
+                    &query[i],
+                    &reference[j],
+                    max_distance,
+                ) {
+                    expected.push((i as u32, j as u32, dist));
+                }
+            }
+            assert!(!expected.is_empty());
+            let expected = NeighborPairs::from_triplets(expected);
+            assert_eq!(
+                get_neighbors_across_with_options(&query, &reference, max_distance, &options)
+                    .expect("valid"),
+                expected
+            );
+            let cached = CachedRef::new(&reference, max_distance).expect("short input");
+            assert_eq!(
+                cached
+                    .get_neighbors_across_with_options(&query, max_distance, &options)
+                    .expect("valid"),
+                expected
+            );
+        }
     }
 
-    const TEST_QUERY: [&str; 5] = ["fizz", "fuzz", "buzz", "izzy", "lofi"];
-    const TEST_REF: [&str; 3] = ["file", "tofu", "fizz"];
+    #[test]
+    fn test_deduplicate() {
+        let mut query = bytes_as_ascii_lines(CDR3_Q_BYTES)[..2000].to_vec();
+        let mut reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        query.extend_from_within(..1000);
+        query.extend_from_within(..10);
+        reference.extend_from_within(500..1500);
+
+        let keep = |qi: u32, ri: u32| !(qi + ri).is_multiple_of(3);
+        for exclude_exact in [false, true] {
+            for pair_filter in [None, Some(PairFilter::new(keep))] {
+                let options = SearchOptions {
+                    exclude_exact,
+                    pair_filter,
+                    ..Default::default()
+                };
+                let deduplicated = SearchOptions {
+                    deduplicate: true,
+                    ..options.clone()
+                };
+                assert_eq!(
+                    get_neighbors_within_with_options(&query, 2, &deduplicated).expect("valid"),
+                    get_neighbors_within_with_options(&query, 2, &options).expect("valid"),
+                );
+                assert_eq!(
+                    get_neighbors_across_with_options(&query, &reference, 2, &deduplicated)
+                        .expect("valid"),
+                    get_neighbors_across_with_options(&query, &reference, 2, &options)
+                        .expect("valid"),
+                );
+            }
+        }
+
+        let deduplicated = SearchOptions {
+            deduplicate: true,
+            ..Default::default()
+        };
+        let cached = CachedRef::new_with_options(&reference, 2, &deduplicated).expect("valid");
+        let expected = CachedRef::new(&reference, 2).expect("valid");
+        assert_eq!(
+            cached.get_neighbors_within(2).expect("valid"),
+            expected.get_neighbors_within(2).expect("valid")
+        );
+        assert_eq!(
+            cached.get_neighbors_across(&query, 2).expect("valid"),
+            expected.get_neighbors_across(&query, 2).expect("valid")
+        );
+    }
 
     #[test]
-    fn test_compute_dists() {
-        let cases = [
-            (
-                (0..5).tuple_combinations().collect_vec(),
-                &TEST_QUERY[..],
-                MaxDistance::try_from(1).expect("legal"),
-                vec![1, 255, 255, 255, 1, 255, 255, 255, 255, 255],
-            ),
-            (
-                (0..5).tuple_combinations().collect_vec(),
-                &TEST_QUERY[..],
-                MaxDistance::try_from(2).expect("legal"),
-                vec![1, 2, 2, 255, 1, 255, 255, 255, 255, 255],
-            ),
+    fn test_partition_by_length() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let partition = LengthPartition::new(&query, 4);
+        assert!(partition.num_blocks() > 1);
+        assert!(query.iter().all(|s| {
+            (0..partition.num_blocks()).any(|block| partition.get_block(block).contains(&s.len()))
+        }));
+
+        let partitioned = SearchOptions {
+            partition_by_length: true,
+            ..Default::default()
+        };
+        let hits = get_neighbors_within_with_options(&query, 1, &partitioned).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_1));
+        let hits = get_neighbors_within_with_options(&query, 2, &partitioned).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2));
+        let hits =
+            get_neighbors_across_with_options(&query, &reference, 1, &partitioned).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_1));
+        let hits =
+            get_neighbors_across_with_options(&query, &reference, 2, &partitioned).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2));
+
+        // Truncating the strings spreads them over a wide range of lengths, and an asymmetric
+        // metric checks that pairs keep their orientation.
+        let query = query[..2000]
+            .iter()
+            .enumerate()
+            .map(|(i, s)| &s[..s.len() - i % 8])
+            .collect_vec();
+        let reference = reference[..2000]
+            .iter()
+            .enumerate()
+            .map(|(i, s)| &s[..s.len() - i % 5])
+            .collect_vec();
+        let keep = |qi: u32, ri: u32| !(qi + ri).is_multiple_of(3);
+        for (metric, pair_filter, order) in [
+            (Metric::Levenshtein, None, OutputOrder::ByRowThenCol),
             (
-                (0..5).cartesian_product(0..3).collect_vec(),
-                &TEST_REF[..],
-                MaxDistance::try_from(1).expect("legal"),
-                vec![
-                    255, 255, 0, 255, 255, 1, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-                ],
+                Metric::WeightedLevenshtein(EditCosts::new(1, 2, 1).expect("valid")),
+                None,
+                OutputOrder::ByRowThenCol,
             ),
             (
-                (0..5).cartesian_product(0..3).collect_vec(),
-                &TEST_REF[..],
-                MaxDistance::try_from(2).expect("legal"),
-                vec![
-                    2, 255, 0, 255, 255, 1, 255, 255, 2, 255, 255, 2, 255, 2, 255,
-                ],
+                Metric::Indel,
+                Some(PairFilter::new(keep)),
+                OutputOrder::ByRowThenCol,
             ),
-        ];
+            (Metric::Levenshtein, None, OutputOrder::ByDistance),
+        ] {
+            let options = SearchOptions {
+                metric,
+                pair_filter,
+                order,
+                brute_force_max_pairs: Some(0),
+                ..Default::default()
+            };
+            let partitioned = SearchOptions {
+                partition_by_length: true,
+                ..options.clone()
+            };
+            assert_eq!(
+                get_neighbors_within_with_options(&query, 2, &partitioned).expect("valid"),
+                get_neighbors_within_with_options(&query, 2, &options).expect("valid"),
+            );
+            assert_eq!(
+                get_neighbors_across_with_options(&query, &reference, 2, &partitioned)
+                    .expect("valid"),
+                get_neighbors_across_with_options(&query, &reference, 2, &options).expect("valid"),
+            );
+        }
+    }
 
-        for (candidates, reference, mdist, expected) in cases {
-            let results = compute_dists(&candidates, &TEST_QUERY, reference, mdist);
-            assert_eq!(results, expected);
+    #[test]
+    fn test_case_insensitive() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let mix_case = |strings: &[String]| {
+            strings
+                .iter()
+                .enumerate()
+                .map(|(i, s)| {
+                    s.chars()
+                        .enumerate()
+                        .map(|(j, c)| {
+                            if (i + j) % 3 == 0 {
+                                c.to_ascii_lowercase()
+                            } else {
+                                c
+                            }
+                        })
+                        .collect::<String>()
+                })
+                .collect_vec()
+        };
+        let mixed_q = mix_case(&query);
+        let mixed_r = mix_case(&reference);
+        let expected_within = bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2);
+        let expected_cross = bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2);
+        assert_ne!(
+            get_neighbors_within(&mixed_q, 2).expect("valid"),
+            expected_within
+        );
+
+        let options = SearchOptions {
+            case_insensitive: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_neighbors_within_with_options(&mixed_q, 2, &options).expect("valid"),
+            expected_within
+        );
+        assert_eq!(
+            get_neighbors_across_with_options(&mixed_q, &mixed_r, 2, &options).expect("valid"),
+            expected_cross
+        );
+
+        let cached = CachedRef::new_with_options(&mixed_r, 2, &options).expect("valid");
+        assert!(cached.case_insensitive());
+        assert_eq!(cached.get(0), Some(&*reference[0].to_ascii_lowercase()));
+        let mut buffer = Vec::new();
+        cached.write_to(&mut buffer).expect("writing to vec");
+        let loaded = CachedRef::read_from(&buffer[..]).expect("valid");
+        for cached in [&cached, &loaded] {
+            assert_eq!(
+                cached.get_neighbors_across(&mixed_q, 2).expect("valid"),
+                expected_cross
+            );
+            assert_eq!(
+                cached.query_one(&mixed_q[0], 2).expect("valid"),
+                CachedRef::new(&reference, 2)
+                    .expect("valid")
+                    .query_one(&query[0], 2)
+                    .expect("valid")
+            );
         }
     }
 
     #[test]
-    fn test_get_true_hits() {
-        let cases = [
-            (
-                (0..5).tuple_combinations().collect_vec(),
-                vec![1, 255, 255, 255, 1, 255, 255, 255, 255, 255],
-                MaxDistance::try_from(1).expect("legal"),
-                NeighborPairs {
-                    row: vec![0, 1],
-                    col: vec![1, 2],
-                    dists: vec![1, 1],
-                },
-            ),
-            (
-                (0..5).tuple_combinations().collect_vec(),
-                vec![1, 2, 2, 255, 1, 255, 255, 255, 255, 255],
-                MaxDistance::try_from(2).expect("legal"),
-                NeighborPairs {
-                    row: vec![0, 0, 0, 1],
-                    col: vec![1, 2, 3, 2],
-                    dists: vec![1, 2, 2, 1],
-                },
-            ),
-        ];
+    fn test_symmetric() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let expected = bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2);
+        let options = SearchOptions {
+            symmetric: true,
+            ..Default::default()
+        };
+        let cached = CachedRef::new(&query, 2).expect("short input");
 
-        for (candidates, dists, mdist, expected) in cases {
-            let result = collect_true_hits(&candidates, &dists, mdist);
-            assert_eq!(result, expected);
+        for hits in [
+            get_neighbors_within_with_options(&query, 2, &options).expect("valid"),
+            cached
+                .get_neighbors_within_with_options(2, &options)
+                .expect("valid"),
+        ] {
+            assert_eq!(hits.len(), 2 * expected.len());
+            let pairs = hits.row.iter().zip(&hits.col).collect_vec();
+            assert!(pairs.windows(2).all(|w| w[0] < w[1]));
+
+            let mut upper = NeighborPairs {
+                row: Vec::new(),
+                col: Vec::new(),
+                dists: Vec::new(),
+            };
+            let mut lower = Vec::new();
+            for ((&row, &col), &dist) in hits.row.iter().zip(&hits.col).zip(&hits.dists) {
+                if row < col {
+                    upper.row.push(row);
+                    upper.col.push(col);
+                    upper.dists.push(dist);
+                } else {
+                    lower.push((col, row, dist));
+                }
+            }
+            lower.sort_unstable();
+            assert_eq!(upper, expected);
+            assert_eq!(
+                lower,
+                itertools::izip!(
+                    expected.row.clone(),
+                    expected.col.clone(),
+                    expected.dists.clone()
+                )
+                .collect_vec()
+            );
         }
     }
 
     #[test]
-    fn test_symdel_within() {
-        let cases = [
-            (
-                1,
-                NeighborPairs {
-                    row: vec![0, 1],
-                    col: vec![1, 2],
-                    dists: vec![1, 1],
-                },
-            ),
-            (
-                2,
-                NeighborPairs {
-                    row: vec![0, 0, 0, 1],
-                    col: vec![1, 2, 3, 2],
-                    dists: vec![1, 2, 2, 1],
-                },
-            ),
-        ];
-        for (mdist, expected) in cases {
-            let result = get_neighbors_within(&TEST_QUERY, mdist).expect("short input");
-            assert_eq!(result, expected);
+    fn test_output_order() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached = CachedRef::new(&reference, 2).expect("short input");
+        let cached_q = CachedRef::new(&query, 2).expect("short input");
+        let expected_cross = bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2);
+        let sorted_by_distance = |hits: &NeighborPairs| {
+            let mut triplets = itertools::izip!(&hits.dists, &hits.row, &hits.col).collect_vec();
+            triplets.sort_unstable();
+            triplets
+                .into_iter()
+                .map(|(&d, &r, &c)| (r, c, d))
+                .collect_vec()
+        };
+        let as_triplets = |hits: &NeighborPairs| {
+            itertools::izip!(hits.row.clone(), hits.col.clone(), hits.dists.clone()).collect_vec()
+        };
+
+        let by_distance = SearchOptions {
+            order: OutputOrder::ByDistance,
+            ..Default::default()
+        };
+        let symmetric = SearchOptions {
+            symmetric: true,
+            ..by_distance.clone()
+        };
+        let mut runs = Vec::new();
+        for num_threads in [1, 4, 4] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(num_threads)
+                .build()
+                .expect("thread pool");
+            runs.push(pool.install(|| {
+                [
+                    get_neighbors_across_with_options(&query, &reference, 2, &by_distance)
+                        .expect("valid"),
+                    cached
+                        .get_neighbors_across_with_options(&query, 2, &by_distance)
+                        .expect("valid"),
+                    get_neighbors_within_with_options(&query, 2, &symmetric).expect("valid"),
+                    cached_q
+                        .get_neighbors_within_with_options(2, &symmetric)
+                        .expect("valid"),
+                ]
+            }));
         }
+        assert!(runs.windows(2).all(|w| w[0] == w[1]));
+
+        let [cross, cached_cross, within, cached_within] = &runs[0];
+        assert_eq!(as_triplets(cross), sorted_by_distance(&expected_cross));
+        assert_eq!(cached_cross, cross);
+        assert_eq!(as_triplets(within), sorted_by_distance(within));
+        assert_eq!(cached_within, within);
+
+        let unordered = SearchOptions {
+            order: OutputOrder::Unordered,
+            deduplicate: true,
+            ..Default::default()
+        };
+        let hits =
+            get_neighbors_across_with_options(&query, &reference, 2, &unordered).expect("valid");
+        let mut triplets = as_triplets(&hits);
+        triplets.sort_unstable();
+        assert_eq!(triplets, as_triplets(&expected_cross));
     }
 
     #[test]
-    fn test_symdel_within_cached() {
-        let cached = CachedRef::new(&TEST_QUERY, 2).expect("short input");
-        let cases = [
-            (
-                1,
-                NeighborPairs {
-                    row: vec![0, 1],
-                    col: vec![1, 2],
-                    dists: vec![1, 1],
-                },
-            ),
-            (
-                2,
-                NeighborPairs {
-                    row: vec![0, 0, 0, 1],
-                    col: vec![1, 2, 3, 2],
-                    dists: vec![1, 2, 2, 1],
-                },
-            ),
-        ];
-        for (mdist, expected) in cases {
-            let result = cached.get_neighbors_within(mdist).expect("legal max dist");
-            assert_eq!(result, expected);
+    fn test_thread_pool() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(3)
+            .thread_name(|i| format!("symscan-test-{i}"))
+            .build()
+            .expect("thread pool");
+        let in_pool = Arc::new(AtomicBool::new(true));
+        let keep = {
+            let in_pool = in_pool.clone();
+            move |_, _| {
+                let name = std::thread::current().name().map(str::to_owned);
+                if !name.is_some_and(|name| name.starts_with("symscan-test-")) {
+                    in_pool.store(false, Ordering::Relaxed);
+                }
+                true
+            }
+        };
+        let options = SearchOptions {
+            thread_pool: Some(Arc::new(pool)),
+            pair_filter: Some(PairFilter::new(keep)),
+            brute_force_max_pairs: Some(0),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            get_neighbors_within_with_options(&query, 2, &options).expect("valid"),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2)
+        );
+        assert_eq!(
+            get_neighbors_across_with_options(&query, &reference, 2, &options).expect("valid"),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2)
+        );
+        let cached = CachedRef::new_with_options(&reference, 2, &options).expect("valid");
+        assert_eq!(
+            cached
+                .get_neighbors_across_with_options(&query, 2, &options)
+                .expect("valid"),
+            bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2)
+        );
+        assert!(in_pool.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_progress() {
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<(Phase, u64, u64)>>);
+
+        impl ProgressSink for Recorder {
+            fn phase_started(&self, phase: Phase, total_units: u64) {
+                self.0.lock().expect("lock").push((phase, total_units, 0));
+            }
+
+            fn advance(&self, units: u64) {
+                let mut phases = self.0.lock().expect("lock");
+                phases.last_mut().expect("phase started").2 += units;
+            }
+        }
+
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        for cross in [false, true] {
+            let recorder = Arc::new(Recorder::default());
+            let options = SearchOptions {
+                progress: Some(recorder.clone()),
+                ..Default::default()
+            };
+            if cross {
+                get_neighbors_across_with_options(&query, &reference, 2, &options).expect("valid");
+            } else {
+                get_neighbors_within_with_options(&query, 2, &options).expect("valid");
+            }
+
+            let phases = recorder.0.lock().expect("lock").clone();
+            assert_eq!(
+                phases.iter().map(|&(phase, _, _)| phase).collect_vec(),
+                vec![
+                    Phase::VariantGeneration,
+                    Phase::Sorting,
+                    Phase::CandidateGeneration,
+                    Phase::DistanceComputation
+                ]
+            );
+            assert!(phases
+                .iter()
+                .all(|&(_, total, done)| total > 0 && done == total));
+            assert_eq!(
+                phases[0].1,
+                (query.len() + cross as usize * reference.len()) as u64
+            );
         }
     }
 
     #[test]
-    fn test_symdel_cross() {
-        let cases = [
-            (
-                1,
-                NeighborPairs {
-                    row: vec![0, 1],
-                    col: vec![2, 2],
-                    dists: vec![0, 1],
-                },
-            ),
-            (
-                2,
-                NeighborPairs {
-                    row: vec![0, 0, 1, 2, 3, 4],
-                    col: vec![0, 2, 2, 2, 2, 1],
-                    dists: vec![2, 0, 1, 2, 2, 2],
-                },
-            ),
-        ];
-        for (mdist, expected) in cases {
-            let result = get_neighbors_across(&TEST_QUERY, &TEST_REF, mdist).expect("valid input");
-            assert_eq!(result, expected);
-        }
+    fn test_stats() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let options = SearchOptions::default();
+
+        let (hits, stats) = get_neighbors_within_with_stats(&query, 1, &options).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_1));
+        assert_eq!(stats.num_hits, hits.len() as u64);
+        assert!(stats.num_variants > query.len() as u64);
+        assert!(stats.num_convergence_groups > 0);
+        assert!(stats.largest_convergence_group >= 2);
+        assert!(stats.num_candidates >= stats.num_verified);
+        assert!(stats.num_verified >= stats.num_hits);
+        assert_eq!(
+            stats
+                .phase_times
+                .iter()
+                .map(|&(phase, _)| phase)
+                .collect_vec(),
+            vec![
+                Phase::VariantGeneration,
+                Phase::Sorting,
+                Phase::CandidateGeneration,
+                Phase::DistanceComputation
+            ]
+        );
+
+        let (hits, stats) =
+            get_neighbors_across_with_stats(&query, &reference, 1, &options).expect("valid");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_1));
+        assert_eq!(stats.num_hits, hits.len() as u64);
+        assert!(stats.num_candidates >= stats.num_verified);
+        assert!(stats.num_verified >= stats.num_hits);
+
+        // Fused verification counts its work in convergence groups, not verified candidates.
+        let fused = SearchOptions {
+            fuse_verification: true,
+            ..Default::default()
+        };
+        let (_, stats) = get_neighbors_within_with_stats(&query, 1, &fused).expect("valid");
+        assert_eq!(stats.num_verified, 0);
+        assert!(stats.phase_time(Phase::DistanceComputation) > Duration::ZERO);
+
+        let (cached, stats) = CachedRef::new_with_stats(&reference, 1, &options).expect("valid");
+        assert_eq!(cached.len(), reference.len());
+        assert!(stats.num_variants > reference.len() as u64);
+        assert!(stats.num_convergence_groups > 0);
+        assert_eq!(stats.num_hits, 0);
+        assert!(stats.phase_time(Phase::DistanceComputation).is_zero());
     }
 
+    #[cfg(feature = "tracing")]
     #[test]
-    fn test_get_candidates_cross_partially_cached() {
-        let cached = CachedRef::new(&TEST_REF, 2).expect("short input");
-        let cases = [
-            (
-                1,
-                NeighborPairs {
-                    row: vec![0, 1],
-                    col: vec![2, 2],
-                    dists: vec![0, 1],
-                },
-            ),
-            (
-                2,
-                NeighborPairs {
-                    row: vec![0, 0, 1, 2, 3, 4],
-                    col: vec![0, 2, 2, 2, 2, 1],
-                    dists: vec![2, 0, 1, 2, 2, 2],
-                },
-            ),
-        ];
-        for (mdist, expected) in cases {
-            let result = cached
-                .get_neighbors_across(&TEST_QUERY, mdist)
-                .expect("legal max dist");
-            assert_eq!(result, expected);
+    fn test_tracing() {
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        /// Records the spans entered on the calling thread, each with the span it was entered
+        /// under, along with the number of events.
+        #[derive(Default)]
+        struct Recorder {
+            names: Mutex<Vec<&'static str>>,
+            stack: Mutex<Vec<&'static str>>,
+            entered: Mutex<Vec<(&'static str, Option<&'static str>)>>,
+            num_events: Mutex<usize>,
         }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+                let mut names = self.names.lock().expect("lock");
+                names.push(attrs.metadata().name());
+                Id::from_u64(names.len() as u64)
+            }
+
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, _: &Event<'_>) {
+                *self.num_events.lock().expect("lock") += 1;
+            }
+
+            fn enter(&self, id: &Id) {
+                let name = self.names.lock().expect("lock")[id.into_u64() as usize - 1];
+                let mut stack = self.stack.lock().expect("lock");
+                let parent = stack.last().copied();
+                self.entered.lock().expect("lock").push((name, parent));
+                stack.push(name);
+            }
+
+            fn exit(&self, _: &Id) {
+                self.stack.lock().expect("lock").pop();
+            }
+        }
+
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let recorder = Arc::new(Recorder::default());
+        tracing::subscriber::with_default(recorder.clone(), || {
+            get_neighbors_within(&query, 1).expect("valid");
+        });
+        let root = "get_neighbors_within";
+        assert_eq!(
+            *recorder.entered.lock().expect("lock"),
+            vec![
+                (root, None),
+                ("variant_generation", Some(root)),
+                ("sorting", Some(root)),
+                ("grouping", Some(root)),
+                ("candidate_generation", Some(root)),
+                ("distance_computation", Some(root)),
+            ]
+        );
+        assert!(recorder.stack.lock().expect("lock").is_empty());
+        assert_eq!(*recorder.num_events.lock().expect("lock"), 0);
+
+        let recorder = Arc::new(Recorder::default());
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let cached = CachedRef::new(&query, 1).expect("valid");
+            cached
+                .get_neighbors_across(&query[..100], 1)
+                .expect("valid");
+        });
+        let entered = recorder.entered.lock().expect("lock").clone();
+        assert_eq!(entered[0], ("cached_ref_new", None));
+        assert!(entered.contains(&("cached_ref_across", None)));
+        assert!(entered
+            .iter()
+            .all(|&(_, parent)| parent.is_none_or(|parent| parent.starts_with("cached_ref"))));
+
+        // Many copies of a string all converge on each of its deletion variants.
+        let pathological = vec!["fizz"; 2 * trace::LARGE_CONVERGENCE_GROUP];
+        let options = SearchOptions {
+            brute_force_max_pairs: Some(0),
+            ..Default::default()
+        };
+        let recorder = Arc::new(Recorder::default());
+        tracing::subscriber::with_default(recorder.clone(), || {
+            get_neighbors_within_with_options(&pathological, 1, &options).expect("valid");
+        });
+        assert_eq!(*recorder.num_events.lock().expect("lock"), 1);
     }
 
     #[test]
-    fn test_get_candidates_cross_fully_cached() {
-        let cached_q = CachedRef::new(&TEST_QUERY, 2).expect("short input");
-        let cached_r = CachedRef::new(&TEST_REF, 2).expect("short input");
-        let cases = [
-            (
-                1,
-                NeighborPairs {
-                    row: vec![0, 1],
-                    col: vec![2, 2],
-                    dists: vec![0, 1],
-                },
-            ),
-            (
-                2,
-                NeighborPairs {
-                    row: vec![0, 0, 1, 2, 3, 4],
-                    col: vec![0, 2, 2, 2, 2, 1],
-                    dists: vec![2, 0, 1, 2, 2, 2],
-                },
-            ),
-        ];
-        for (mdist, expected) in cases {
-            let result = cached_r
-                .get_neighbors_across_cached(&cached_q, mdist)
-                .expect("legal max dist");
-            assert_eq!(result, expected);
+    fn test_bounded_dist_shortcuts() {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as usize
+        };
+        let mut random_string = || {
+            let len = next() % 8;
+            (0..len).map(|_| b"ACG"[next() % 3]).collect_vec()
+        };
+
+        for _ in 0..20000 {
+            let (a, b) = (random_string(), random_string());
+            for d in 0..=4 {
+                let cutoff = &levenshtein::Args::default().score_cutoff(d);
+                let expected = [
+                    (
+                        Metric::Levenshtein,
+                        levenshtein::distance_with_args(
+                            a.iter().copied(),
+                            b.iter().copied(),
+                            cutoff,
+                        ),
+                    ),
+                    (
+                        Metric::Hamming,
+                        hamming::distance_with_args(
+                            a.iter().copied(),
+                            b.iter().copied(),
+                            &hamming::Args::default().score_cutoff(d),
+                        )
+                        .ok()
+                        .flatten(),
+                    ),
+                    (
+                        Metric::Osa,
+                        osa::distance_with_args(
+                            a.iter().copied(),
+                            b.iter().copied(),
+                            &osa::Args::default().score_cutoff(d),
+                        ),
+                    ),
+                    (
+                        Metric::Indel,
+                        indel::distance_with_args(
+                            a.iter().copied(),
+                            b.iter().copied(),
+                            &indel::Args::default().score_cutoff(d),
+                        ),
+                    ),
+                ];
+                let mdist = MaxDistance::try_from(d as u8).expect("legal");
+                for (metric, dist) in expected {
+                    let comparator = QueryComparator::new(Symbols::Bytes(&a), metric, usize::MAX);
+                    for cached in [None, comparator.cached.as_ref()] {
+                        assert_eq!(
+                            metric.bounded_dist(
+                                Symbols::Bytes(&a),
+                                Symbols::Bytes(&b),
+                                mdist,
+                                cached,
+                            ),
+                            dist.map_or(u8::MAX, |dist| dist as u8),
+                            "{metric:?} between {a:?} and {b:?} within {d}"
+                        );
+                    }
+                }
+            }
         }
     }
 
-    // testing on real world data
+    #[test]
+    fn test_search_config() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let config = SearchConfig::new(2)
+            .metric(Metric::Osa)
+            .exclude_exact(true)
+            .symmetric(true);
+        let options = SearchOptions {
+            metric: Metric::Osa,
+            exclude_exact: true,
+            symmetric: true,
+            ..Default::default()
+        };
 
-    static CDR3_Q_BYTES: &[u8] = include_bytes!("../../test_files/cdr3b_10k_a.txt");
-    static CDR3_R_BYTES: &[u8] = include_bytes!("../../test_files/cdr3b_10k_b.txt");
-    static EXPECTED_BYTES_WITHIN_1: &[u8] = include_bytes!("../../test_files/results_10k_a.txt");
-    static EXPECTED_BYTES_WITHIN_2: &[u8] = include_bytes!("../../test_files/results_10k_a_d2.txt");
-    static EXPECTED_BYTES_CROSS_1: &[u8] = include_bytes!("../../test_files/results_10k_cross.txt");
-    static EXPECTED_BYTES_CROSS_2: &[u8] =
-        include_bytes!("../../test_files/results_10k_cross_d2.txt");
+        assert_eq!(
+            config.run_within(&query).expect("valid"),
+            get_neighbors_within_with_options(&query, 2, &options).expect("valid")
+        );
+        assert_eq!(
+            config.run_across(&query, &reference).expect("valid"),
+            get_neighbors_across_with_options(&query, &reference, 2, &options).expect("valid")
+        );
 
-    fn bytes_as_ascii_lines(bytes: &[u8]) -> Vec<String> {
-        Cursor::new(bytes)
-            .lines()
-            .collect::<io::Result<Vec<String>>>()
-            .expect("test files have valid lines")
+        let cached = config.build_cache(&reference).expect("valid");
+        assert_eq!(cached.max_distance(), 2);
+        assert_eq!(
+            config.run_across_cached(&query, &cached).expect("valid"),
+            config.run_across(&query, &reference).expect("valid")
+        );
+        assert!(matches!(
+            SearchConfig::new(3).run_within_cached(&cached),
+            Err(Error::MaxDistTooLargeForCache { got: 3, limit: 2 })
+        ));
+        assert!(matches!(
+            config
+                .clone()
+                .case_insensitive(true)
+                .run_within_cached(&cached),
+            Err(Error::MismatchedCaseSensitivity {
+                cache: false,
+                config: true
+            })
+        ));
     }
 
-    fn bytes_as_neighbour_pairs(bytes: &[u8]) -> NeighborPairs {
-        let mut i = Vec::new();
-        let mut j = Vec::new();
-        let mut dists = Vec::new();
-
-        Cursor::new(bytes).lines().for_each(|v| {
-            let line = v.expect("test files have valid lines");
-            let triplet = line.split(",").collect_vec();
-            i.push(
-                triplet[0]
-                    .parse::<u32>()
-                    .expect("test files have int triplets")
-                    - 1,
-            );
-            j.push(
-                triplet[1]
-                    .parse::<u32>()
-                    .expect("test files have int triplets")
-                    - 1,
-            );
-            dists.push(
-                triplet[2]
-                    .parse::<u8>()
-                    .expect("test files have int triplets"),
-            );
-        });
+    #[test]
+    fn test_non_ascii_rejected() {
+        let ascii = ["fizz", "fuzz", "buzz"];
+        let non_ascii = ["fizz", "füzz", "buzz"];
+        let cached = CachedRef::new(&ascii, 1).expect("valid");
+        let mut extended = CachedRef::new(&ascii, 1).expect("valid");
+        let (buffer, offsets) = lines_as_buffer_and_offsets(&non_ascii.map(String::from));
+        let rejected = |result: Result<(), Error>| {
+            matches!(
+                result,
+                Err(Error::NonAsciiInput {
+                    offending_idx: 1,
+                    ..
+                })
+            )
+        };
 
-        NeighborPairs {
-            row: i,
-            col: j,
-            dists,
+        assert!(rejected(get_neighbors_within(&non_ascii, 1).map(drop)));
+        let err = get_neighbors_within(&non_ascii, 1).expect_err("non-ASCII");
+        assert!(err.to_string().contains("SearchOptions::unicode"));
+        assert!(rejected(
+            get_neighbors_within_offsets(&buffer, &offsets, 1).map(drop)
+        ));
+        assert!(rejected(
+            get_neighbors_within_grouped(&non_ascii, &[0; 3], 1, GroupPolicy::WithinGroupsOnly)
+                .map(drop)
+        ));
+        assert!(rejected(
+            get_neighbor_counts_within(&non_ascii, 1).map(drop)
+        ));
+        assert!(rejected(for_each_neighbor_within(
+            &non_ascii,
+            1,
+            |_, _, _| ()
+        )));
+        assert!(rejected(
+            get_neighbors_within_ratio(&non_ascii, 0.5).map(drop)
+        ));
+        assert!(rejected(
+            pairwise_distances(&non_ascii, &ascii, None).map(drop)
+        ));
+        assert!(rejected(
+            get_candidate_pairs_within(&non_ascii, 1).map(drop)
+        ));
+        for (query, reference) in [(&non_ascii, &ascii), (&ascii, &non_ascii)] {
+            assert!(rejected(
+                get_candidate_pairs_across(query, reference, 1).map(drop)
+            ));
+            assert!(rejected(
+                compute_distances(&[(1, 1)], query, reference, 1).map(drop)
+            ));
+            assert!(rejected(
+                get_neighbors_across(query, reference, 1).map(drop)
+            ));
+            assert!(rejected(
+                get_neighbors_across_top_k(query, reference, 1, 1).map(drop)
+            ));
+            assert!(rejected(
+                get_neighbor_counts_across(query, reference, 1).map(drop)
+            ));
+            assert!(rejected(for_each_neighbor_across(
+                query,
+                reference,
+                1,
+                |_, _, _| ()
+            )));
+            assert!(rejected(has_neighbor_across(query, reference, 1).map(drop)));
+            assert!(rejected(
+                get_nearest_neighbors_across(query, reference, 1).map(drop)
+            ));
         }
+
+        assert!(rejected(CachedRef::new(&non_ascii, 1).map(drop)));
+        assert!(rejected(
+            CachedRef::from_offsets(&buffer, &offsets, 1).map(drop)
+        ));
+        assert!(rejected(extended.extend(&non_ascii)));
+        assert_eq!(extended.len(), cached.len());
+        assert!(rejected(
+            cached.get_neighbors_across(&non_ascii, 1).map(drop)
+        ));
+        assert!(rejected(
+            cached
+                .get_neighbors_across_top_k(&non_ascii, 1, 1)
+                .map(drop)
+        ));
+        assert!(rejected(
+            cached.get_neighbor_counts_across(&non_ascii, 1).map(drop)
+        ));
+        assert!(rejected(cached.for_each_neighbor_across(
+            &non_ascii,
+            1,
+            |_, _, _| ()
+        )));
+        assert!(rejected(
+            cached.has_neighbor_across(&non_ascii, 1).map(drop)
+        ));
+        assert!(rejected(
+            cached.get_nearest_neighbors_across(&non_ascii, 1).map(drop)
+        ));
+        assert!(rejected(
+            cached
+                .get_neighbors_across_with_scratch(&non_ascii, 1, &mut cached.query_scratch())
+                .map(drop)
+        ));
+        assert!(cached.query_one("füzz", 1).is_err());
     }
 
     #[test]
-    fn test_within() {
-        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+    fn test_empty_strings() {
+        let strings = ["", "a", "ab", "", "abc", "ba", "abcd"];
+        let others = ["", "b", "xyz"];
+        assert_eq!(
+            get_num_del_vars_per_string(
+                &strings[..2],
+                MaxDistance::try_from(2).unwrap(),
+                Anchoring::default()
+            ),
+            vec![1, 2]
+        );
 
-        let hits = get_neighbors_within(&query, 1).expect("short input");
-        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_1));
+        for max_distance in 1..=2 {
+            let distance = |a: &str, b: &str| {
+                let dist = levenshtein::distance(a.bytes(), b.bytes());
+                (dist <= max_distance as usize).then_some(dist as u8)
+            };
+            let expected_within = NeighborPairs::from_triplets(
+                (0..strings.len())
+                    .flat_map(|i| (i + 1..strings.len()).map(move |j| (i, j)))
+                    .filter_map(|(i, j)| {
+                        distance(strings[i], strings[j]).map(|d| (i as u32, j as u32, d))
+                    })
+                    .collect(),
+            );
+            let expected_across = NeighborPairs::from_triplets(
+                (0..others.len())
+                    .flat_map(|i| (0..strings.len()).map(move |j| (i, j)))
+                    .filter_map(|(i, j)| {
+                        distance(others[i], strings[j]).map(|d| (i as u32, j as u32, d))
+                    })
+                    .collect(),
+            );
 
-        let hits = get_neighbors_within(&query, 2).expect("short input");
-        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_WITHIN_2));
+            assert_eq!(
+                get_neighbors_within(&strings, max_distance).unwrap(),
+                expected_within
+            );
+            assert_eq!(
+                get_neighbors_across(&others, &strings, max_distance).unwrap(),
+                expected_across
+            );
+
+            let cached = CachedRef::new(&strings, max_distance).unwrap();
+            assert_eq!(
+                cached.get_neighbors_within(max_distance).unwrap(),
+                expected_within
+            );
+            assert_eq!(
+                cached.get_neighbors_across(&others, max_distance).unwrap(),
+                expected_across
+            );
+            assert_eq!(
+                cached.query_one("", max_distance).unwrap(),
+                expected_across
+                    .col
+                    .iter()
+                    .zip(&expected_across.dists)
+                    .zip(&expected_across.row)
+                    .filter(|(_, &row)| row == 0)
+                    .map(|((&col, &dist), _)| (col, dist))
+                    .collect_vec()
+            );
+        }
     }
 
     #[test]
-    fn test_cross() {
-        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
-        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+    fn test_input_too_long() {
+        let long = "ACGT".repeat(2500);
+        let strings = ["ACGT", long.as_str()];
+        let short = ["ACGT", "AGT"];
+        assert_eq!(
+            get_num_del_vars(long.len(), MaxDistance::try_from(2).unwrap()),
+            Some(1 + 10_000 + 10_000 * 9_999 / 2)
+        );
+        assert_eq!(
+            get_num_del_vars(long.len(), MaxDistance::try_from(3).unwrap()),
+            None
+        );
+        assert_eq!(
+            get_num_del_vars(long.len(), MaxDistance::try_from(254).unwrap()),
+            None
+        );
 
-        let hits = get_neighbors_across(&query, &reference, 1).expect("valid inputs");
-        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_1));
+        let too_long = |result: Result<(), Error>, expected_type: InputType| {
+            matches!(
+                result,
+                Err(Error::InputTooLong {
+                    input_type,
+                    offending_idx: 1,
+                    len: 10_000,
+                    max_distance: 3,
+                }) if input_type == expected_type
+            )
+        };
 
-        let hits = get_neighbors_across(&query, &reference, 2).expect("valid inputs");
-        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2));
+        assert!(too_long(
+            get_neighbors_within(&strings, 3).map(drop),
+            InputType::Query
+        ));
+        let options = SearchOptions {
+            case_insensitive: true,
+            deduplicate: true,
+            ..Default::default()
+        };
+        assert!(too_long(
+            get_neighbors_within_with_options(&strings, 3, &options).map(drop),
+            InputType::Query
+        ));
+        assert!(too_long(
+            get_neighbors_across(&strings, &short, 3).map(drop),
+            InputType::Query
+        ));
+        assert!(too_long(
+            get_neighbors_across(&short, &strings, 3).map(drop),
+            InputType::Reference
+        ));
+
+        assert!(too_long(
+            CachedRef::new(&strings, 3).map(drop),
+            InputType::Reference
+        ));
+        let mut cached = CachedRef::new(&short, 3).unwrap();
+        assert!(too_long(
+            cached.get_neighbors_across(&strings, 3).map(drop),
+            InputType::Query
+        ));
+        assert!(matches!(
+            cached.query_one(&long, 3),
+            Err(Error::InputTooLong {
+                offending_idx: 0,
+                ..
+            })
+        ));
+        assert!(too_long(cached.extend(&strings), InputType::Reference));
+        assert_eq!(cached.len(), short.len());
     }
 
     #[test]
@@ -1610,6 +17557,128 @@ mod tests {
         assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2));
     }
 
+    #[test]
+    fn test_cross_partially_cached_with_scratch() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached = CachedRef::new(&reference, 2).expect("short input");
+        let mut scratch = cached.query_scratch();
+
+        for (max_distance, expected) in [
+            (2, EXPECTED_BYTES_CROSS_2),
+            (1, EXPECTED_BYTES_CROSS_1),
+            (2, EXPECTED_BYTES_CROSS_2),
+        ] {
+            let hits = cached
+                .get_neighbors_across_with_scratch(&query, max_distance, &mut scratch)
+                .expect("legal max distance");
+            assert_eq!(hits, bytes_as_neighbour_pairs(expected));
+        }
+
+        // Smaller queries after larger ones must not see stale intermediate results.
+        for len in [5000, 10, 0] {
+            let hits = cached
+                .get_neighbors_across_with_scratch(&query[..len], 2, &mut scratch)
+                .expect("legal max distance");
+            assert_eq!(hits, cached.get_neighbors_across(&query[..len], 2).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_cross_partially_cached_chunks() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached = CachedRef::new(&reference, 2).expect("short input");
+        let queries = || query.iter().map(|s| s.to_string());
+
+        for chunk_size in [1, 3333, query.len(), 2 * query.len()] {
+            let hits = cached
+                .get_neighbors_across_chunks(queries().take(5000), chunk_size, 2)
+                .collect::<Result<NeighborPairs, _>>()
+                .expect("legal max distance");
+            assert_eq!(
+                hits,
+                cached.get_neighbors_across(&query[..5000], 2).unwrap()
+            );
+        }
+
+        let options = SearchOptions {
+            order: OutputOrder::ByDistance,
+            ..Default::default()
+        };
+        let chunks = cached
+            .get_neighbors_across_chunks_with_options(queries(), 3000, 1, &options)
+            .collect::<Result<Vec<_>, _>>()
+            .expect("legal max distance");
+        assert_eq!(chunks.len(), query.len().div_ceil(3000));
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let batch = &query[i * 3000..query.len().min((i + 1) * 3000)];
+            let mut expected = cached
+                .get_neighbors_across_with_options(batch, 1, &options)
+                .unwrap();
+            for row in &mut expected.row {
+                *row += (i * 3000) as u32;
+            }
+            assert_eq!(chunk, expected);
+        }
+
+        assert_eq!(
+            cached
+                .get_neighbors_across_chunks(iter::empty(), 10, 1)
+                .count(),
+            0
+        );
+
+        let results = cached
+            .get_neighbors_across_chunks(
+                ["fizz", "fuzz", "fïzz", "buzz", "bïzz"].map(String::from),
+                2,
+                1,
+            )
+            .collect_vec();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(Error::NonAsciiInput {
+                offending_idx: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_cross_partially_cached_into() {
+        let query = bytes_as_ascii_lines(CDR3_Q_BYTES);
+        let reference = bytes_as_ascii_lines(CDR3_R_BYTES);
+        let cached = CachedRef::new(&reference, 2).expect("short input");
+        let mut scratch = cached.query_scratch();
+        let mut hits = NeighborPairs::default();
+
+        cached
+            .get_neighbors_across_into(&query, 2, &mut scratch, &mut hits)
+            .expect("legal max distance");
+        assert_eq!(hits, bytes_as_neighbour_pairs(EXPECTED_BYTES_CROSS_2));
+        let capacity = hits.row.capacity();
+
+        // Smaller results reuse the buffers of larger ones, without any stale hits.
+        for (batch, max_distance) in query.chunks(1000).zip([1, 2].into_iter().cycle()) {
+            cached
+                .get_neighbors_across_into(batch, max_distance, &mut scratch, &mut hits)
+                .expect("legal max distance");
+            assert_eq!(
+                hits,
+                cached.get_neighbors_across(batch, max_distance).unwrap()
+            );
+            assert_eq!(hits.row.capacity(), capacity);
+        }
+
+        assert!(cached
+            .get_neighbors_across_into(&query, 3, &mut scratch, &mut hits)
+            .is_err());
+        assert_eq!(hits, NeighborPairs::default());
+    }
+
     #[test]
     fn test_cross_fully_cached() {
         let query = bytes_as_ascii_lines(CDR3_Q_BYTES);